@@ -1,9 +1,19 @@
-use golem_llm::error::{error_code_from_status, from_event_source_error, from_reqwest_error};
-use golem_llm::event_source::EventSource;
-use golem_llm::golem::llm::llm::Error;
+use golem_llm::body::read_body;
+use golem_llm::error::{
+    context_length_exceeded, decode_success_body, error_code_from_status, from_event_source_error,
+    from_reqwest_error, number_after,
+};
+use golem_llm::event_source::{EventSource, StreamFormat, StreamFormatHint};
+use golem_llm::extra_body::ExtraBody;
+use golem_llm::golem::llm::llm::{Error, ErrorCode, ProviderHealth};
+use golem_llm::health::probe;
+use golem_llm::proxy::http_client;
+use golem_llm::rate_limit::{rate_limit_info, RateLimited};
+use golem_llm::raw::send_raw;
+use golem_llm::retry::send_with_connection_retry;
 use log::trace;
-use reqwest::header::HeaderValue;
-use reqwest::{Client, Method, Response};
+use reqwest::header::{HeaderMap, HeaderValue};
+use reqwest::{Client, Method, Response, StatusCode};
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
@@ -14,35 +24,57 @@ const BASE_URL: &str = "https://api.anthropic.com";
 /// The Anthropic API client for creating model responses.
 pub struct MessagesApi {
     api_key: String,
+    extra_headers: HeaderMap,
+    /// Fields added to the chat request bodies
+    extra_body: ExtraBody,
     client: Client,
 }
 
 impl MessagesApi {
-    pub fn new(api_key: String) -> Self {
-        let client = Client::builder()
-            .build()
-            .expect("Failed to initialize HTTP client");
-        Self { api_key, client }
+    pub fn new(api_key: String, extra_headers: HeaderMap) -> Result<Self, Error> {
+        let client = http_client(BASE_URL)?;
+        Ok(Self {
+            api_key,
+            extra_headers,
+            extra_body: ExtraBody::default(),
+            client,
+        })
+    }
+
+    /// Adds the `extra_body` of the config to the chat requests
+    pub fn with_extra_body(mut self, extra_body: ExtraBody) -> Self {
+        self.extra_body = extra_body;
+        self
     }
 
-    pub fn send_messages(&self, request: MessagesRequest) -> Result<MessagesResponse, Error> {
+    pub fn send_messages(
+        &self,
+        request: MessagesRequest,
+    ) -> Result<RateLimited<MessagesResponse>, Error> {
         trace!("Sending request to Anthropic API: {request:?}");
 
-        let response: Response = self
-            .client
-            .request(Method::POST, format!("{BASE_URL}/v1/messages"))
-            .header("anthropic-version", "2023-06-01")
-            .header("x-api-key", &self.api_key)
-            .json(&request)
-            .send()
-            .map_err(|err| from_reqwest_error("Request failed", err))?;
+        let body = self.extra_body.merge(&request)?;
+
+        let response: Response = send_with_connection_retry(|| {
+            self.client
+                .request(Method::POST, format!("{BASE_URL}/v1/messages"))
+                .header("anthropic-version", "2023-06-01")
+                .header("x-api-key", &self.api_key)
+                .json(&body)
+                .headers(self.extra_headers.clone())
+                .send()
+        })
+        .map_err(|err| from_reqwest_error("Request failed", err))?;
 
-        parse_response(response)
+        let rate_limit = rate_limit_info(response.headers());
+        parse_response(response).map(|body| RateLimited { body, rate_limit })
     }
 
     pub fn stream_send_messages(&self, request: MessagesRequest) -> Result<EventSource, Error> {
         trace!("Sending request to Anthropic API: {request:?}");
 
+        let body = self.extra_body.merge(&request)?;
+
         let response: Response = self
             .client
             .request(Method::POST, format!("{BASE_URL}/v1/messages"))
@@ -52,14 +84,44 @@ impl MessagesApi {
                 reqwest::header::ACCEPT,
                 HeaderValue::from_static("text/event-stream"),
             )
-            .json(&request)
+            .json(&body)
+            .headers(self.extra_headers.clone())
             .send()
             .map_err(|err| from_reqwest_error("Request failed", err))?;
 
         trace!("Initializing SSE stream");
 
-        EventSource::new(response)
-            .map_err(|err| from_event_source_error("Failed to create SSE stream", err))
+        EventSource::new(
+            response,
+            StreamFormatHint::Detect(StreamFormat::EventStream),
+        )
+        .map_err(|err| from_event_source_error("Failed to create SSE stream", err))
+    }
+
+    /// Sends the JSON body untouched to the Messages API, returning the raw response body
+    pub fn send_raw(&self, body_json: String) -> Result<String, Error> {
+        send_raw(
+            self.client
+                .request(Method::POST, format!("{BASE_URL}/v1/messages"))
+                .header("anthropic-version", "2023-06-01")
+                .header("x-api-key", &self.api_key),
+            self.extra_headers.clone(),
+            body_json,
+        )
+    }
+
+    /// Checks the connectivity by listing the available models
+    pub fn ping(&self) -> Result<ProviderHealth, Error> {
+        trace!("Checking the connectivity of the Anthropic API");
+
+        probe(|| {
+            self.client
+                .request(Method::GET, format!("{BASE_URL}/v1/models"))
+                .header("anthropic-version", "2023-06-01")
+                .header("x-api-key", &self.api_key)
+                .headers(self.extra_headers.clone())
+                .send()
+        })
     }
 }
 
@@ -104,7 +166,10 @@ pub enum Content {
     Text {
         text: String,
         #[serde(skip_serializing_if = "Option::is_none")]
-        cache_control: Option<CacheControl>, // citations
+        cache_control: Option<CacheControl>,
+        /// Sources cited by a text block of a response
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        citations: Option<Vec<Citation>>,
     },
     #[serde(rename = "image")]
     Image {
@@ -129,8 +194,30 @@ pub enum Content {
         is_error: bool,
     },
     // Document
-    // Thinking
-    // RedactedThinking
+    /// Reasoning of the model preceding its answer, when extended thinking is enabled
+    #[serde(rename = "thinking")]
+    Thinking {
+        thinking: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        signature: Option<String>,
+    },
+    /// Reasoning flagged by the safety systems, which is only returned encrypted
+    #[serde(rename = "redacted_thinking")]
+    RedactedThinking { data: String },
+}
+
+/// Citation of a text block. Web search results are identified by their URL and title, while
+/// document citations (character, page or content block locations) only have the document title
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Citation {
+    #[serde(rename = "type")]
+    pub typ: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub document_title: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -204,6 +291,8 @@ pub struct MessagesResponse {
     pub model: String,
     pub role: Role,
     pub stop_reason: Option<StopReason>,
+    /// The stop sequence matched when the stop reason is `stop_sequence`
+    pub stop_sequence: Option<String>,
     pub usage: Usage,
 }
 
@@ -229,6 +318,24 @@ pub struct Usage {
     pub output_tokens: u32,
 }
 
+/// The message of a `message_start` stream event, before any content was generated
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageStart {
+    pub id: String,
+    pub model: String,
+    pub usage: Option<StreamUsage>,
+}
+
+/// Usage reported by the `message_start` and `message_delta` stream events, each of them
+/// containing only some of the token counts
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StreamUsage {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub input_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output_tokens: Option<u32>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Role {
     #[serde(rename = "user")]
@@ -256,29 +363,138 @@ pub enum ContentBlockDelta {
     TextDelta { text: String },
     #[serde(rename = "input_json_delta")]
     InputJsonDelta { partial_json: String },
+    #[serde(rename = "citations_delta")]
+    CitationsDelta { citation: Citation },
+    #[serde(rename = "thinking_delta")]
+    ThinkingDelta { thinking: String },
+    /// Signature of a thinking block, sent once at its end
+    #[serde(rename = "signature_delta")]
+    SignatureDelta { signature: String },
 }
 
 fn parse_response<T: DeserializeOwned + Debug>(response: Response) -> Result<T, Error> {
     let status = response.status();
     if status.is_success() {
-        let body = response
-            .json::<T>()
-            .map_err(|err| from_reqwest_error("Failed to decode response body", err))?;
+        let raw_body = read_body(response, "Failed to receive response body")?;
+        let body = decode_success_body::<T>(raw_body)?;
 
         trace!("Received response from Anthropic API: {body:?}");
 
         Ok(body)
     } else {
-        let error_body = response
-            .json::<ErrorResponse>()
-            .map_err(|err| from_reqwest_error("Failed to receive error response body", err))?;
+        let raw_error_body = read_body(response, "Failed to receive error response body")?;
+        let error_body =
+            serde_json::from_str::<ErrorResponse>(&raw_error_body).map_err(|err| Error {
+                code: ErrorCode::InternalError,
+                message: format!("Failed to decode error response body: {err}"),
+                provider_error_json: Some(raw_error_body),
+            })?;
 
         trace!("Received {status} response from Anthropic API: {error_body:?}");
 
-        Err(Error {
-            code: error_code_from_status(status),
-            message: format!("Request failed with {status}: {}", error_body.error.message),
-            provider_error_json: Some(serde_json::to_string(&error_body).unwrap()),
-        })
+        Err(error_from_body(status, error_body))
+    }
+}
+
+/// Phrases of the `invalid_request_error` messages reporting a prompt exceeding the context
+/// window, or a `max_tokens` not fitting in it next to the prompt
+const CONTEXT_LENGTH_PHRASES: [&str; 2] = ["prompt is too long", "exceed context limit"];
+
+/// Types of the errors reporting that Anthropic is temporarily unable to serve the request, which
+/// are retried like rate limits whatever the status they are returned with
+const RATE_LIMIT_ERROR_TYPES: [&str; 2] = ["rate_limit_error", "overloaded_error"];
+
+/// Maps the type of an error, returned in the body of a failed response or by an error event of a
+/// stream, to an error code, or `None` for the types mapped by the status of the response
+pub fn error_code_from_type(typ: &str) -> Option<ErrorCode> {
+    RATE_LIMIT_ERROR_TYPES
+        .contains(&typ)
+        .then_some(ErrorCode::RateLimitExceeded)
+}
+
+/// Converts the body of a failed response, reporting requests exceeding the context window as
+/// `context-length-exceeded` with the context window parsed from the message, and overloaded
+/// errors as `rate-limit-exceeded`
+fn error_from_body(status: StatusCode, error_body: ErrorResponse) -> Error {
+    let provider_error_json = Some(serde_json::to_string(&error_body).unwrap());
+    let details = error_body.error;
+    if details.typ == "invalid_request_error"
+        && CONTEXT_LENGTH_PHRASES
+            .iter()
+            .any(|phrase| details.message.contains(phrase))
+    {
+        let limit = number_after(&details.message, ">");
+        context_length_exceeded(&details.message, limit, provider_error_json)
+    } else {
+        Error {
+            code: error_code_from_type(&details.typ).unwrap_or(error_code_from_status(status)),
+            message: format!("Request failed with {status}: {}", details.message),
+            provider_error_json,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::{error_from_body, ErrorResponse};
+    use golem_llm::golem::llm::llm::ErrorCode;
+    use reqwest::StatusCode;
+
+    fn error_body(json: &str) -> ErrorResponse {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn prompt_too_long_reports_the_context_window() {
+        let error = error_from_body(
+            StatusCode::BAD_REQUEST,
+            error_body(
+                r#"{"type": "error", "error": {"type": "invalid_request_error", "message": "prompt is too long: 208310 tokens > 200000 maximum"}}"#,
+            ),
+        );
+
+        assert_eq!(error.code, ErrorCode::ContextLengthExceeded);
+        assert!(
+            error.message.contains("context window of 200000 tokens"),
+            "{}",
+            error.message
+        );
+    }
+
+    #[test]
+    fn max_tokens_exceeding_the_context_window_is_a_context_length_error() {
+        let error = error_from_body(
+            StatusCode::BAD_REQUEST,
+            error_body(
+                r#"{"type": "error", "error": {"type": "invalid_request_error", "message": "input length and `max_tokens` exceed context limit: 188240 + 21333 > 200000, decrease input length or `max_tokens` and try again"}}"#,
+            ),
+        );
+
+        assert_eq!(error.code, ErrorCode::ContextLengthExceeded);
+        assert!(error.message.contains("context window of 200000 tokens"));
+    }
+
+    #[test]
+    fn other_invalid_requests_are_not_context_length_errors() {
+        let error = error_from_body(
+            StatusCode::BAD_REQUEST,
+            error_body(
+                r#"{"type": "error", "error": {"type": "invalid_request_error", "message": "max_tokens: 100000 > 64000, which is the maximum allowed number of output tokens for claude-sonnet-4"}}"#,
+            ),
+        );
+
+        assert_eq!(error.code, ErrorCode::InvalidRequest);
+    }
+
+    #[test]
+    fn overloaded_errors_are_rate_limits() {
+        let body =
+            r#"{"type": "error", "error": {"type": "overloaded_error", "message": "Overloaded"}}"#;
+
+        let error = error_from_body(StatusCode::from_u16(529).unwrap(), error_body(body));
+        assert_eq!(error.code, ErrorCode::RateLimitExceeded);
+
+        let error = error_from_body(StatusCode::INTERNAL_SERVER_ERROR, error_body(body));
+        assert_eq!(error.code, ErrorCode::RateLimitExceeded);
     }
 }
@@ -1,44 +1,102 @@
 use crate::client::{
-    Content, ImageSource as ClientImageSource, MediaType, MessagesRequest, MessagesRequestMetadata,
-    MessagesResponse, StopReason, Tool, ToolChoice,
+    Citation, Content, ImageSource as ClientImageSource, MediaType, MessagesRequest,
+    MessagesRequestMetadata, MessagesResponse, StopReason, Tool, ToolChoice,
 };
 use base64::{engine::general_purpose, Engine as _};
+use golem_llm::capabilities::{
+    check_model_capabilities, check_streaming_support, ModelCapabilities,
+};
+use golem_llm::config::model_or_default;
+use golem_llm::conversions::{check_provider_options, split_tool_results, validate_temperature};
 use golem_llm::golem::llm::llm::{
-    ChatEvent, CompleteResponse, Config, ContentPart, Error, ErrorCode, FinishReason,
+    Annotation, ChatEvent, CompleteResponse, Config, ContentPart, Error, ErrorCode, FinishReason,
     ImageReference, ImageSource, ImageUrl, Message, ResponseMetadata, Role, ToolCall,
-    ToolDefinition, ToolResult, Usage,
+    ToolDefinition, ToolResult, ToolResultContent, Usage,
 };
+use golem_llm::usage::with_total_tokens;
 use std::collections::HashMap;
+use std::ops::RangeInclusive;
+
+/// Range of temperatures accepted by Anthropic
+const TEMPERATURE_RANGE: RangeInclusive<f32> = 0.0..=1.0;
+
+/// Provider options read by the Anthropic conversions, any other key is reported as unknown
+const PROVIDER_OPTIONS: [&str; 3] = ["user_id", "top_k", "top_p"];
+
+/// Capabilities of the known Anthropic models, requests to other models are not validated
+const MODEL_CAPABILITIES: [(&str, ModelCapabilities); 9] = [
+    ("claude-opus-4-1-20250805", ModelCapabilities::ALL),
+    ("claude-opus-4-20250514", ModelCapabilities::ALL),
+    ("claude-sonnet-4-20250514", ModelCapabilities::ALL),
+    ("claude-3-7-sonnet-20250219", ModelCapabilities::ALL),
+    ("claude-3-5-sonnet-20241022", ModelCapabilities::ALL),
+    ("claude-3-5-haiku-20241022", ModelCapabilities::ALL),
+    ("claude-3-haiku-20240307", ModelCapabilities::ALL),
+    ("claude-2.1", LEGACY_MODEL_CAPABILITIES),
+    ("claude-2.0", LEGACY_MODEL_CAPABILITIES),
+];
+
+/// The Claude 2 models take neither images nor tools
+const LEGACY_MODEL_CAPABILITIES: ModelCapabilities = ModelCapabilities {
+    vision: false,
+    tools: false,
+    streaming: true,
+};
 
 pub fn messages_to_request(
     messages: Vec<Message>,
     config: Config,
 ) -> Result<MessagesRequest, Error> {
+    let max_tokens = match config.max_tokens {
+        Some(max_tokens) if max_tokens > 0 => max_tokens,
+        _ => {
+            return Err(Error {
+                code: ErrorCode::InvalidRequest,
+                message: "Anthropic requires a positive max_tokens".to_string(),
+                provider_error_json: None,
+            })
+        }
+    };
+
     let options = config
         .provider_options
         .into_iter()
         .map(|kv| (kv.key, kv.value))
         .collect::<HashMap<_, _>>();
+    check_provider_options(&options, &PROVIDER_OPTIONS)?;
+
+    let model = model_or_default(config.model)?;
+    check_model_capabilities(&MODEL_CAPABILITIES, &model, &messages, &config.tools)?;
 
     let mut anthropic_messages = Vec::new();
     for message in &messages {
         if message.role != Role::System {
-            anthropic_messages.push(crate::client::Message {
-                role: match &message.role {
-                    Role::User => crate::client::Role::User,
-                    Role::Assistant => crate::client::Role::Assistant,
-                    Role::Tool => crate::client::Role::User,
-                    Role::System => unreachable!(),
-                },
-                content: message_to_content(message),
-            })
+            // Tool results included in the messages are sent as a tool use and its result
+            let (content, tool_results) =
+                split_tool_results(message.content.clone(), normalize_tool_call_id)?;
+            if !content.is_empty() || tool_results.is_empty() {
+                anthropic_messages.push(crate::client::Message {
+                    role: match &message.role {
+                        Role::User => crate::client::Role::User,
+                        Role::Assistant => crate::client::Role::Assistant,
+                        Role::Tool => crate::client::Role::User,
+                        Role::System => unreachable!(),
+                    },
+                    content: message_to_content(&content),
+                })
+            }
+            anthropic_messages.extend(tool_results_to_messages(tool_results));
         }
     }
 
+    prepare_prefill(&mut anthropic_messages)?;
+
+    // Anthropic takes a single system prompt, so all the system messages are merged into it
+    // in their original order, wherever they appear in the conversation
     let mut system_messages = Vec::new();
     for message in &messages {
         if message.role == Role::System {
-            system_messages.extend(message_to_content(message))
+            system_messages.extend(message_to_content(&message.content))
         }
     }
 
@@ -54,18 +112,27 @@ pub fn messages_to_request(
     };
 
     Ok(MessagesRequest {
-        max_tokens: config.max_tokens.unwrap_or(4096),
+        max_tokens,
         messages: anthropic_messages,
-        model: config.model,
+        model,
+        // Anthropic's request metadata only supports a user id, the rest of the metadata is
+        // only sent as headers
         metadata: options
             .get("user_id")
+            .or_else(|| {
+                config
+                    .metadata
+                    .iter()
+                    .find(|(key, _)| key == "user_id")
+                    .map(|(_, value)| value)
+            })
             .map(|user_id| MessagesRequestMetadata {
                 user_id: Some(user_id.to_string()),
             }),
         stop_sequences: config.stop_sequences,
         stream: false,
         system: system_messages,
-        temperature: config.temperature,
+        temperature: validate_temperature(config.temperature, TEMPERATURE_RANGE)?,
         tool_choice,
         tools,
         top_k: options
@@ -77,6 +144,41 @@ pub fn messages_to_request(
     })
 }
 
+/// Fails if the model is known not to support streaming
+pub fn check_streaming(model: &str) -> Result<(), Error> {
+    check_streaming_support(&MODEL_CAPABILITIES, model)
+}
+
+/// A trailing assistant message is a prefill, the beginning of the response which the model
+/// continues. Only a single trailing assistant message is allowed, and as Anthropic rejects
+/// prefills ending with whitespace, its trailing whitespace is removed.
+fn prepare_prefill(messages: &mut [crate::client::Message]) -> Result<(), Error> {
+    let trailing_assistant_messages = messages
+        .iter()
+        .rev()
+        .take_while(|message| matches!(message.role, crate::client::Role::Assistant))
+        .count();
+
+    if trailing_assistant_messages > 1 {
+        return Err(Error {
+            code: ErrorCode::InvalidRequest,
+            message: "Only the last message can be an assistant prefill".to_string(),
+            provider_error_json: None,
+        });
+    }
+
+    if trailing_assistant_messages == 1 {
+        if let Some(Content::Text { text, .. }) = messages
+            .last_mut()
+            .and_then(|message| message.content.last_mut())
+        {
+            text.truncate(text.trim_end().len());
+        }
+    }
+
+    Ok(())
+}
+
 fn convert_tool_choice(tool_name: String) -> ToolChoice {
     if &tool_name == "auto" {
         ToolChoice::Auto {
@@ -99,10 +201,24 @@ fn convert_tool_choice(tool_name: String) -> ToolChoice {
 pub fn process_response(response: MessagesResponse) -> ChatEvent {
     let mut contents = Vec::new();
     let mut tool_calls = Vec::new();
+    let mut annotations = Vec::new();
+    let mut text_length = 0;
 
     for content in response.content {
         match content {
-            Content::Text { text, .. } => contents.push(ContentPart::Text(text)),
+            Content::Text {
+                text, citations, ..
+            } => {
+                let start_index = text_length;
+                text_length += text.chars().count() as u32;
+                annotations.extend(
+                    citations
+                        .unwrap_or_default()
+                        .into_iter()
+                        .map(|citation| citation_to_annotation(citation, start_index, text_length)),
+                );
+                contents.push(ContentPart::Text(text));
+            }
             Content::Image { source, .. } => match source {
                 ClientImageSource::Url { url } => {
                     contents.push(ContentPart::Image(ImageReference::Url(ImageUrl {
@@ -145,18 +261,24 @@ pub fn process_response(response: MessagesResponse) -> ChatEvent {
                 arguments_json: serde_json::to_string(&input).unwrap(),
             }),
             Content::ToolResult { .. } => {}
+            Content::Thinking { thinking, .. } => contents.push(ContentPart::Reasoning(thinking)),
+            // The redacted reasoning is encrypted, so there is nothing to show
+            Content::RedactedThinking { .. } => {}
         }
     }
 
-    if contents.is_empty() {
+    if contents.is_empty() && !tool_calls.is_empty() {
         ChatEvent::ToolRequest(tool_calls)
     } else {
         let metadata = ResponseMetadata {
             finish_reason: response.stop_reason.map(stop_reason_to_finish_reason),
             usage: Some(convert_usage(response.usage)),
             provider_id: None,
+            model: Some(response.model.clone()),
             timestamp: None,
             provider_metadata_json: None,
+            tokens_per_second: None,
+            stop_sequence: response.stop_sequence,
         };
 
         ChatEvent::Message(CompleteResponse {
@@ -164,10 +286,37 @@ pub fn process_response(response: MessagesResponse) -> ChatEvent {
             content: contents,
             tool_calls,
             metadata,
+            annotations,
         })
     }
 }
 
+/// Converts a citation of a text block to an annotation of the block's span in the response text
+pub fn citation_to_annotation(citation: Citation, start_index: u32, end_index: u32) -> Annotation {
+    Annotation {
+        url: citation.url,
+        title: citation.title.or(citation.document_title),
+        start_index,
+        end_index,
+    }
+}
+
+/// Anthropic only accepts tool use ids made of letters, digits, `_` and `-`, so the other
+/// characters of an id coming from another provider are replaced by `_`
+pub fn normalize_tool_call_id(id: &str) -> Option<String> {
+    (!id.is_empty()).then(|| {
+        id.chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '_' || c == '-' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect()
+    })
+}
+
 pub fn tool_results_to_messages(
     tool_results: Vec<(ToolCall, ToolResult)>,
 ) -> Vec<crate::client::Message> {
@@ -184,12 +333,23 @@ pub fn tool_results_to_messages(
             role: crate::client::Role::Assistant,
         });
         let content = match tool_result {
+            ToolResult::Success(success) if !success.content.is_empty() => Content::ToolResult {
+                tool_use_id: tool_call.id,
+                cache_control: None,
+                content: success
+                    .content
+                    .iter()
+                    .map(tool_result_content_to_content)
+                    .collect(),
+                is_error: false,
+            },
             ToolResult::Success(success) => Content::ToolResult {
                 tool_use_id: tool_call.id,
                 cache_control: None,
                 content: vec![Content::Text {
                     text: success.result_json,
                     cache_control: None,
+                    citations: None,
                 }],
                 is_error: false,
             },
@@ -199,6 +359,7 @@ pub fn tool_results_to_messages(
                 content: vec![Content::Text {
                     text: error.error_message,
                     cache_control: None,
+                    citations: None,
                 }],
                 is_error: true,
             },
@@ -222,54 +383,93 @@ pub fn stop_reason_to_finish_reason(stop_reason: StopReason) -> FinishReason {
 }
 
 pub fn convert_usage(usage: crate::client::Usage) -> Usage {
-    Usage {
+    with_total_tokens(Usage {
         input_tokens: Some(usage.input_tokens),
         output_tokens: Some(usage.output_tokens),
         total_tokens: None,
-    }
+    })
 }
 
-fn message_to_content(message: &Message) -> Vec<Content> {
+fn message_to_content(content: &[ContentPart]) -> Vec<Content> {
     let mut result = Vec::new();
 
-    for content_part in &message.content {
+    for content_part in content {
         match content_part {
             ContentPart::Text(text) => result.push(Content::Text {
                 text: text.clone(),
                 cache_control: None,
+                citations: None,
             }),
-            ContentPart::Image(image_reference) => match image_reference {
-                ImageReference::Url(image_url) => result.push(Content::Image {
-                    source: ClientImageSource::Url {
-                        url: image_url.url.clone(),
-                    },
+            ContentPart::Image(image_reference) => {
+                result.push(image_reference_to_content(image_reference))
+            }
+            // Tool results are sent in messages of their own by `messages_to_request`
+            ContentPart::ToolResult(_) => {}
+            // The reasoning of earlier responses is not sent back to the model
+            ContentPart::Reasoning(_) => {}
+            // Audio responses are sent back to the model as their transcript
+            ContentPart::Audio(audio) => {
+                result.extend(audio.transcript.clone().map(|text| Content::Text {
+                    text,
                     cache_control: None,
-                }),
-                ImageReference::Inline(image_source) => {
-                    let base64_data = general_purpose::STANDARD.encode(&image_source.data);
-                    let media_type = match image_source.mime_type.as_str() {
-                        "image/jpeg" => MediaType::Jpeg,
-                        "image/png" => MediaType::Png,
-                        "image/gif" => MediaType::Gif,
-                        "image/webp" => MediaType::Webp,
-                        _ => MediaType::Jpeg,
-                    };
-
-                    result.push(Content::Image {
-                        source: ClientImageSource::Base64 {
-                            data: base64_data,
-                            media_type,
-                        },
-                        cache_control: None,
-                    });
-                }
-            },
+                    citations: None,
+                }))
+            }
+            // Refusals of earlier responses are sent back to the model as text
+            ContentPart::Refusal(text) => result.push(Content::Text {
+                text: text.clone(),
+                cache_control: None,
+                citations: None,
+            }),
         }
     }
 
     result
 }
 
+/// Converts an image. Anthropic has no image detail setting, so the detail of the image is dropped
+fn image_reference_to_content(image_reference: &ImageReference) -> Content {
+    match image_reference {
+        ImageReference::Url(image_url) => Content::Image {
+            source: ClientImageSource::Url {
+                url: image_url.url.clone(),
+            },
+            cache_control: None,
+        },
+        ImageReference::Inline(image_source) => {
+            let base64_data = general_purpose::STANDARD.encode(&image_source.data);
+            let media_type = match image_source.mime_type.as_str() {
+                "image/jpeg" => MediaType::Jpeg,
+                "image/png" => MediaType::Png,
+                "image/gif" => MediaType::Gif,
+                "image/webp" => MediaType::Webp,
+                _ => MediaType::Jpeg,
+            };
+
+            Content::Image {
+                source: ClientImageSource::Base64 {
+                    data: base64_data,
+                    media_type,
+                },
+                cache_control: None,
+            }
+        }
+    }
+}
+
+/// Converts the text and images returned by a tool, which are sent instead of its result JSON
+/// when there are any
+fn tool_result_content_to_content(content: &ToolResultContent) -> Content {
+    match content {
+        ToolResultContent::Text(text) => Content::Text {
+            text: text.clone(),
+            cache_control: None,
+            citations: None,
+        },
+        ToolResultContent::Image(image_reference) => image_reference_to_content(image_reference),
+    }
+}
+
 fn tool_definition_to_tool(tool: &ToolDefinition) -> Result<Tool, Error> {
     match serde_json::from_str(&tool.parameters_schema) {
         Ok(value) => Ok(Tool::CustomTool {
@@ -285,3 +485,378 @@ fn tool_definition_to_tool(tool: &ToolDefinition) -> Result<Tool, Error> {
         }),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::client::{Content, MessagesResponse, Role as ClientRole};
+    use crate::conversions::{
+        messages_to_request, normalize_tool_call_id, process_response, tool_results_to_messages,
+    };
+    use golem_llm::golem::llm::llm::{
+        Annotation, ChatEvent, Config, ContentPart, ErrorCode, FinishReason, ImageReference,
+        ImageSource, Message, Role, ToolCall, ToolResult, ToolResultContent, ToolSuccess,
+    };
+
+    fn config() -> Config {
+        Config {
+            model: "claude-3-7-sonnet-20250219".to_string(),
+            temperature: None,
+            max_tokens: Some(1024),
+            stop_sequences: None,
+            tools: vec![],
+            tool_choice: None,
+            provider_options: vec![],
+            extra_headers: vec![],
+            service_tier: None,
+            metadata: vec![],
+            reasoning_effort: None,
+            store: None,
+            prompt_cache_key: None,
+            stream_options: None,
+            deadline_ms: None,
+            extra_body: None,
+        }
+    }
+
+    fn message(role: Role, text: &str) -> Message {
+        Message {
+            role,
+            name: None,
+            content: vec![ContentPart::Text(text.to_string())],
+        }
+    }
+
+    #[test]
+    fn trailing_assistant_message_is_sent_as_prefill() {
+        let request = messages_to_request(
+            vec![
+                message(Role::System, "Answer in JSON"),
+                message(Role::User, "What is the capital of France?"),
+                message(Role::Assistant, "{\"capital\": \n"),
+            ],
+            config(),
+        )
+        .unwrap();
+
+        assert_eq!(request.messages.len(), 2);
+        let prefill = request.messages.last().unwrap();
+        assert!(matches!(prefill.role, ClientRole::Assistant));
+        assert!(matches!(
+            prefill.content.as_slice(),
+            [Content::Text { text, .. }] if text == "{\"capital\":"
+        ));
+    }
+
+    #[test]
+    fn earlier_assistant_messages_are_not_prefills() {
+        let request = messages_to_request(
+            vec![
+                message(Role::User, "Hi"),
+                message(Role::Assistant, "Hello! "),
+                message(Role::User, "How are you?"),
+            ],
+            config(),
+        )
+        .unwrap();
+
+        assert!(matches!(
+            request.messages[1].content.as_slice(),
+            [Content::Text { text, .. }] if text == "Hello! "
+        ));
+    }
+
+    #[test]
+    fn only_one_trailing_prefill_is_allowed() {
+        let error = messages_to_request(
+            vec![
+                message(Role::User, "Hi"),
+                message(Role::Assistant, "Hello"),
+                message(Role::Assistant, "!"),
+            ],
+            config(),
+        )
+        .unwrap_err();
+
+        assert_eq!(error.code, ErrorCode::InvalidRequest);
+    }
+
+    #[test]
+    fn interleaved_system_messages_are_merged_into_system_prompt() {
+        let request = messages_to_request(
+            vec![
+                message(Role::System, "Be concise"),
+                message(Role::User, "Hi"),
+                message(Role::System, "Answer in French"),
+                message(Role::User, "How are you?"),
+            ],
+            config(),
+        )
+        .unwrap();
+
+        let system = request
+            .system
+            .iter()
+            .map(|content| match content {
+                Content::Text { text, .. } => text.as_str(),
+                other => panic!("Unexpected system content: {other:?}"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(system, vec!["Be concise", "Answer in French"]);
+        assert_eq!(request.messages.len(), 2);
+        assert!(request
+            .messages
+            .iter()
+            .all(|message| matches!(message.role, ClientRole::User)));
+    }
+
+    #[test]
+    fn max_tokens_must_be_positive() {
+        let messages = vec![message(Role::User, "Hi")];
+
+        for max_tokens in [None, Some(0)] {
+            let error = messages_to_request(
+                messages.clone(),
+                Config {
+                    max_tokens,
+                    ..config()
+                },
+            )
+            .unwrap_err();
+
+            assert_eq!(error.code, ErrorCode::InvalidRequest);
+            assert_eq!(error.message, "Anthropic requires a positive max_tokens");
+        }
+
+        let request = messages_to_request(messages, config()).unwrap();
+        assert_eq!(request.max_tokens, 1024);
+    }
+
+    #[test]
+    fn user_id_metadata_is_sent_in_request_metadata() {
+        let request = messages_to_request(
+            vec![message(Role::User, "Hi")],
+            Config {
+                metadata: vec![
+                    ("tenant".to_string(), "acme".to_string()),
+                    ("user_id".to_string(), "user-1".to_string()),
+                ],
+                ..config()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            request.metadata.and_then(|metadata| metadata.user_id),
+            Some("user-1".to_string())
+        );
+    }
+
+    #[test]
+    fn citations_annotate_their_text_block() {
+        let response: MessagesResponse = serde_json::from_value(serde_json::json!({
+            "id": "msg_01",
+            "model": "claude-3-7-sonnet-20250219",
+            "role": "assistant",
+            "stop_reason": "end_turn",
+            "usage": { "input_tokens": 10, "output_tokens": 20 },
+            "content": [
+                { "type": "text", "text": "According to the docs, " },
+                {
+                    "type": "text",
+                    "text": "Golem is durable.",
+                    "citations": [
+                        {
+                            "type": "web_search_result_location",
+                            "url": "https://golem.cloud",
+                            "title": "Golem Cloud",
+                            "cited_text": "Durable execution",
+                            "encrypted_index": "abc"
+                        },
+                        {
+                            "type": "char_location",
+                            "document_title": "Golem manual",
+                            "document_index": 0,
+                            "cited_text": "Workers are durable",
+                            "start_char_index": 0,
+                            "end_char_index": 19
+                        }
+                    ]
+                }
+            ]
+        }))
+        .unwrap();
+
+        let ChatEvent::Message(message) = process_response(response) else {
+            panic!("Expected a message");
+        };
+        assert_eq!(
+            message.annotations,
+            vec![
+                Annotation {
+                    url: Some("https://golem.cloud".to_string()),
+                    title: Some("Golem Cloud".to_string()),
+                    start_index: 23,
+                    end_index: 40,
+                },
+                Annotation {
+                    url: None,
+                    title: Some("Golem manual".to_string()),
+                    start_index: 23,
+                    end_index: 40,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn matched_stop_sequence_is_reported_in_the_metadata() {
+        let response: MessagesResponse = serde_json::from_value(serde_json::json!({
+            "id": "msg_01",
+            "model": "claude-3-7-sonnet-20250219",
+            "role": "assistant",
+            "stop_reason": "stop_sequence",
+            "stop_sequence": "END",
+            "usage": { "input_tokens": 10, "output_tokens": 5 },
+            "content": [{ "type": "text", "text": "Golem is durable." }]
+        }))
+        .unwrap();
+
+        let ChatEvent::Message(message) = process_response(response) else {
+            panic!("Expected a message");
+        };
+        assert_eq!(message.metadata.finish_reason, Some(FinishReason::Stop));
+        assert_eq!(message.metadata.stop_sequence, Some("END".to_string()));
+    }
+
+    #[test]
+    fn thinking_blocks_are_returned_as_reasoning() {
+        let response: MessagesResponse = serde_json::from_value(serde_json::json!({
+            "id": "msg_01",
+            "model": "claude-sonnet-4-20250514",
+            "role": "assistant",
+            "stop_reason": "end_turn",
+            "stop_sequence": null,
+            "usage": { "input_tokens": 42, "output_tokens": 88 },
+            "content": [
+                { "type": "thinking", "thinking": "17 * 3 = 51.", "signature": "EqQBCgIYAhIM" },
+                { "type": "text", "text": "17 * 3 is 51." },
+                { "type": "redacted_thinking", "data": "EmwKAhgBEgy3" },
+                { "type": "text", "text": " Confirmed." }
+            ]
+        }))
+        .unwrap();
+
+        let ChatEvent::Message(message) = process_response(response) else {
+            panic!("Expected a message");
+        };
+        assert_eq!(
+            message.content,
+            vec![
+                ContentPart::Reasoning("17 * 3 = 51.".to_string()),
+                ContentPart::Text("17 * 3 is 51.".to_string()),
+                ContentPart::Text(" Confirmed.".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn tool_results_in_the_history_become_tool_use_and_result_blocks() {
+        let request = messages_to_request(
+            vec![
+                message(Role::User, "What's the weather in Paris?"),
+                Message {
+                    role: Role::Tool,
+                    name: None,
+                    content: vec![ContentPart::ToolResult((
+                        ToolCall {
+                            id: "call:1".to_string(),
+                            name: "get_weather".to_string(),
+                            arguments_json: r#"{"city":"Paris"}"#.to_string(),
+                        },
+                        ToolResult::Success(ToolSuccess {
+                            id: "call:1".to_string(),
+                            name: "get_weather".to_string(),
+                            result_json: r#"{"temperature":22}"#.to_string(),
+                            execution_time_ms: None,
+                            content: vec![],
+                        }),
+                    ))],
+                },
+                message(Role::User, "And in Rome?"),
+            ],
+            config(),
+        )
+        .unwrap();
+
+        assert_eq!(request.messages.len(), 4);
+        assert!(matches!(request.messages[1].role, ClientRole::Assistant));
+        assert!(matches!(
+            request.messages[1].content.as_slice(),
+            [Content::ToolUse { id, name, .. }] if id == "call_1" && name == "get_weather"
+        ));
+        assert!(matches!(request.messages[2].role, ClientRole::User));
+        assert!(matches!(
+            request.messages[2].content.as_slice(),
+            [Content::ToolResult { tool_use_id, is_error: false, .. }] if tool_use_id == "call_1"
+        ));
+        assert!(matches!(request.messages[3].role, ClientRole::User));
+    }
+
+    #[test]
+    fn tool_results_with_content_are_sent_as_text_and_image_blocks() {
+        let tool_call = ToolCall {
+            id: "toolu_1".to_string(),
+            name: "render_chart".to_string(),
+            arguments_json: r#"{"city":"Paris"}"#.to_string(),
+        };
+        let tool_result = ToolResult::Success(ToolSuccess {
+            id: "toolu_1".to_string(),
+            name: "render_chart".to_string(),
+            result_json: r#"{"rendered":true}"#.to_string(),
+            execution_time_ms: None,
+            content: vec![
+                ToolResultContent::Text("Temperatures of the week".to_string()),
+                ToolResultContent::Image(ImageReference::Inline(ImageSource {
+                    data: vec![137, 80, 78, 71],
+                    mime_type: "image/png".to_string(),
+                    detail: None,
+                })),
+            ],
+        });
+
+        let messages = tool_results_to_messages(vec![(tool_call, tool_result)]);
+
+        assert_eq!(
+            serde_json::to_value(&messages[1].content).unwrap(),
+            serde_json::json!([{
+                "type": "tool_result",
+                "tool_use_id": "toolu_1",
+                "content": [
+                    { "type": "text", "text": "Temperatures of the week" },
+                    {
+                        "type": "image",
+                        "source": { "type": "base64", "data": "iVBORw==", "media_type": "image/png" }
+                    }
+                ],
+                "is_error": false
+            }])
+        );
+    }
+
+    #[test]
+    fn anthropic_tool_call_ids_are_kept() {
+        assert_eq!(
+            normalize_tool_call_id("toolu_01A09q90qw90lq917835lq9"),
+            Some("toolu_01A09q90qw90lq917835lq9".to_string())
+        );
+    }
+
+    #[test]
+    fn other_tool_call_ids_are_corrected() {
+        assert_eq!(
+            normalize_tool_call_id("ollama-2025-01-01T00:00:00Z-0"),
+            Some("ollama-2025-01-01T00_00_00Z-0".to_string())
+        );
+        assert_eq!(normalize_tool_call_id(""), None);
+    }
+}
@@ -2,23 +2,38 @@ mod client;
 mod conversions;
 
 use crate::client::{
-    Content, ContentBlockDelta, ErrorResponse, MessagesApi, MessagesRequest, StopReason, Usage,
+    error_code_from_type, Citation, Content, ContentBlockDelta, ErrorResponse, MessageStart,
+    MessagesApi, MessagesRequest, StopReason, StreamUsage,
 };
 use crate::conversions::{
-    convert_usage, messages_to_request, process_response, stop_reason_to_finish_reason,
-    tool_results_to_messages,
+    check_streaming, citation_to_annotation, messages_to_request, normalize_tool_call_id,
+    process_response, stop_reason_to_finish_reason, tool_results_to_messages,
 };
-use golem_llm::chat_stream::{LlmChatStream, LlmChatStreamState};
+use golem_llm::batch::send_batch;
+use golem_llm::chat_stream::{request_deadline, LlmChatStream, LlmChatStreamState};
 use golem_llm::config::with_config_key;
+use golem_llm::conversions::{check_messages_not_empty, normalize_tool_call_ids};
+use golem_llm::dry_run::{send_or_dry_run, stream_or_dry_run};
 use golem_llm::durability::{DurableLLM, ExtendedGuest};
+use golem_llm::error::unsupported;
 use golem_llm::event_source::EventSource;
+use golem_llm::extra_body::extra_body;
 use golem_llm::golem::llm::llm::{
-    ChatEvent, ChatStream, Config, ContentPart, Error, ErrorCode, Guest, Message, ResponseMetadata,
-    Role, StreamDelta, StreamEvent, ToolCall, ToolResult,
+    ChatEvent, ChatStream, Config, ContentPart, EmbeddingConfig, EmbeddingResult, Error, ErrorCode,
+    Guest, ImageGenConfig, ImageGenResult, Message, ProviderCapabilities, ProviderHealth,
+    RerankConfig, RerankResult, ResponseMetadata, Role, StreamDelta, StreamEvent, StreamStart,
+    ToolCall, ToolResult, Usage,
 };
+use golem_llm::headers::extra_headers;
+use golem_llm::rate_limit::with_rate_limit;
+use golem_llm::tool_call_fragments::{
+    complete_tool_call_delta, is_streaming_tool_call_fragments, tool_call_fragment_delta,
+};
+use golem_llm::usage::with_total_tokens;
 use golem_llm::LOGGING_STATE;
 use golem_rust::wasm_rpc::Pollable;
 use log::trace;
+use reqwest::header::HeaderMap;
 use std::cell::{Ref, RefCell, RefMut};
 use std::collections::HashMap;
 
@@ -29,12 +44,24 @@ struct JsonFragment {
     json: String,
 }
 
+/// Text block being streamed, whose citations annotate it once it is complete
+#[derive(Default)]
+struct TextBlock {
+    start_index: u32,
+    citations: Vec<Citation>,
+}
+
 struct AnthropicChatStream {
     stream: RefCell<Option<EventSource>>,
     failure: Option<Error>,
     finished: RefCell<bool>,
     json_fragments: RefCell<HashMap<u64, JsonFragment>>,
+    text_blocks: RefCell<HashMap<u64, TextBlock>>,
+    /// Number of characters of text streamed so far
+    streamed_chars: RefCell<u32>,
     response_metadata: RefCell<ResponseMetadata>,
+    /// Whether the fragments of the tool calls' arguments are sent as they are received
+    stream_tool_call_fragments: bool,
 }
 
 impl AnthropicChatStream {
@@ -44,13 +71,19 @@ impl AnthropicChatStream {
             failure: None,
             finished: RefCell::new(false),
             json_fragments: RefCell::new(HashMap::new()),
+            text_blocks: RefCell::new(HashMap::new()),
+            streamed_chars: RefCell::new(0),
             response_metadata: RefCell::new(ResponseMetadata {
                 finish_reason: None,
                 usage: None,
                 provider_id: None,
+                model: None,
                 timestamp: None,
                 provider_metadata_json: None,
+                tokens_per_second: None,
+                stop_sequence: None,
             }),
+            stream_tool_call_fragments: is_streaming_tool_call_fragments(),
         })
     }
 
@@ -60,15 +93,63 @@ impl AnthropicChatStream {
             failure: Some(error),
             finished: RefCell::new(false),
             json_fragments: RefCell::new(HashMap::new()),
+            text_blocks: RefCell::new(HashMap::new()),
+            streamed_chars: RefCell::new(0),
             response_metadata: RefCell::new(ResponseMetadata {
                 finish_reason: None,
                 usage: None,
                 provider_id: None,
+                model: None,
                 timestamp: None,
                 provider_metadata_json: None,
+                tokens_per_second: None,
+                stop_sequence: None,
             }),
+            stream_tool_call_fragments: is_streaming_tool_call_fragments(),
         })
     }
+
+    pub fn dry_run(events: Vec<StreamEvent>) -> LlmChatStream<Self> {
+        LlmChatStream::from_events(
+            AnthropicChatStream {
+                stream: RefCell::new(None),
+                failure: None,
+                finished: RefCell::new(false),
+                json_fragments: RefCell::new(HashMap::new()),
+                text_blocks: RefCell::new(HashMap::new()),
+                streamed_chars: RefCell::new(0),
+                response_metadata: RefCell::new(ResponseMetadata {
+                    finish_reason: None,
+                    usage: None,
+                    provider_id: None,
+                    model: None,
+                    timestamp: None,
+                    provider_metadata_json: None,
+                    tokens_per_second: None,
+                    stop_sequence: None,
+                }),
+                stream_tool_call_fragments: is_streaming_tool_call_fragments(),
+            },
+            events,
+        )
+    }
+
+    /// Merges the partial usage of a `message_start` or `message_delta` event into the response
+    /// metadata reported by the final `message_stop` event
+    fn update_usage(&self, usage: StreamUsage) {
+        let mut response_metadata = self.response_metadata.borrow_mut();
+        let current = response_metadata.usage.get_or_insert(Usage {
+            input_tokens: None,
+            output_tokens: None,
+            total_tokens: None,
+        });
+        if let Some(input_tokens) = usage.input_tokens {
+            current.input_tokens = Some(input_tokens);
+        }
+        if let Some(output_tokens) = usage.output_tokens {
+            current.output_tokens = Some(output_tokens);
+        }
+    }
 }
 
 impl LlmChatStreamState for AnthropicChatStream {
@@ -92,25 +173,57 @@ impl LlmChatStreamState for AnthropicChatStream {
         self.stream.borrow_mut()
     }
 
-    fn decode_message(&self, raw: &str) -> Result<Option<StreamEvent>, String> {
-        trace!("Received raw stream event: {raw}");
+    /// The usage of the `message_start` and `message_delta` events received so far, whose input
+    /// tokens are known from the start of the response
+    fn known_usage(&self) -> Option<Usage> {
+        self.response_metadata.borrow().usage.clone()
+    }
+
+    fn decode_message(&self, event: &str, raw: &str) -> Result<Option<StreamEvent>, String> {
+        trace!("Received raw stream event {event}: {raw}");
         let json: serde_json::Value = serde_json::from_str(raw)
             .map_err(|err| format!("Failed to deserialize stream event: {err}"))?;
 
-        let typ = json
-            .as_object()
-            .and_then(|obj| obj.get("type"))
-            .and_then(|v| v.as_str());
+        // Anthropic names every SSE event after its type, the type in the data is only used
+        // for unnamed events
+        let typ = if event == "message" {
+            json.as_object()
+                .and_then(|obj| obj.get("type"))
+                .and_then(|v| v.as_str())
+        } else {
+            Some(event)
+        };
         match typ {
             Some("error") => {
                 let error = serde_json::from_value::<ErrorResponse>(json)
                     .map_err(|err| format!("Failed to deserialize stream event: {err}"))?;
                 Ok(Some(StreamEvent::Error(Error {
-                    code: ErrorCode::InternalError,
+                    code: error_code_from_type(&error.error.typ)
+                        .unwrap_or(ErrorCode::InternalError),
                     message: error.error.message,
                     provider_error_json: None,
                 })))
             }
+            Some("message_start") => {
+                let raw_message = json
+                    .as_object()
+                    .and_then(|obj| obj.get("message"))
+                    .ok_or_else(|| {
+                        "Unexpected stream event format, does not have 'message' field".to_string()
+                    })?;
+                let message = serde_json::from_value::<MessageStart>(raw_message.clone())
+                    .map_err(|err| format!("Failed to deserialize stream event: {err}"))?;
+
+                if let Some(usage) = message.usage {
+                    self.update_usage(usage);
+                }
+                self.response_metadata.borrow_mut().model = Some(message.model.clone());
+                Ok(Some(StreamEvent::Start(StreamStart {
+                    id: message.id,
+                    model: Some(message.model),
+                    created: None,
+                })))
+            }
             Some("content_block_start") => {
                 let index = json
                     .as_object()
@@ -131,15 +244,27 @@ impl LlmChatStreamState for AnthropicChatStream {
                 let content_block = serde_json::from_value::<Content>(raw_content_block.clone())
                     .map_err(|err| format!("Failed to deserialize stream event: {err}"))?;
 
-                if let Content::ToolUse { id, name, .. } = content_block {
-                    self.json_fragments.borrow_mut().insert(
-                        index,
-                        JsonFragment {
-                            id,
-                            name,
-                            json: String::new(),
-                        },
-                    );
+                match content_block {
+                    Content::ToolUse { id, name, .. } => {
+                        self.json_fragments.borrow_mut().insert(
+                            index,
+                            JsonFragment {
+                                id,
+                                name,
+                                json: String::new(),
+                            },
+                        );
+                    }
+                    Content::Text { .. } => {
+                        self.text_blocks.borrow_mut().insert(
+                            index,
+                            TextBlock {
+                                start_index: *self.streamed_chars.borrow(),
+                                citations: Vec::new(),
+                            },
+                        );
+                    }
+                    _ => {}
                 }
 
                 Ok(None)
@@ -156,11 +281,27 @@ impl LlmChatStreamState for AnthropicChatStream {
 
                 match delta {
                     ContentBlockDelta::TextDelta { text } => {
+                        *self.streamed_chars.borrow_mut() += text.chars().count() as u32;
                         Ok(Some(StreamEvent::Delta(StreamDelta {
                             content: Some(vec![ContentPart::Text(text)]),
                             tool_calls: None,
+                            annotations: None,
+                            tool_call_fragments: None,
+                            running_usage: None,
                         })))
                     }
+                    ContentBlockDelta::ThinkingDelta { thinking } => {
+                        // Kept apart from the text, which the citations' indices are counted in
+                        Ok(Some(StreamEvent::Delta(StreamDelta {
+                            content: Some(vec![ContentPart::Reasoning(thinking)]),
+                            tool_calls: None,
+                            annotations: None,
+                            tool_call_fragments: None,
+                            running_usage: None,
+                        })))
+                    }
+                    // The signature is only needed to send the thinking back, which is not done
+                    ContentBlockDelta::SignatureDelta { .. } => Ok(None),
                     ContentBlockDelta::InputJsonDelta { partial_json } => {
                         let index = json
                             .as_object()
@@ -175,6 +316,33 @@ impl LlmChatStreamState for AnthropicChatStream {
                         let fragment = json_fragments.entry(index).or_default();
                         fragment.json.push_str(&partial_json);
 
+                        if self.stream_tool_call_fragments && !partial_json.is_empty() {
+                            Ok(Some(StreamEvent::Delta(tool_call_fragment_delta(
+                                &fragment.id,
+                                &fragment.name,
+                                partial_json,
+                            ))))
+                        } else {
+                            Ok(None)
+                        }
+                    }
+                    ContentBlockDelta::CitationsDelta { citation } => {
+                        let index = json
+                            .as_object()
+                            .and_then(|obj| obj.get("index"))
+                            .and_then(|v| v.as_u64())
+                            .ok_or_else(|| {
+                                "Unexpected stream event format, does not have 'index' field"
+                                    .to_string()
+                            })?;
+
+                        self.text_blocks
+                            .borrow_mut()
+                            .entry(index)
+                            .or_default()
+                            .citations
+                            .push(citation);
+
                         Ok(None)
                     }
                 }
@@ -188,42 +356,75 @@ impl LlmChatStreamState for AnthropicChatStream {
                         "Unexpected stream event format, does not have 'index' field".to_string()
                     })?;
 
-                if let Some(tool_use) = self.json_fragments.borrow_mut().remove(&index) {
+                if let Some(text_block) = self.text_blocks.borrow_mut().remove(&index) {
+                    if text_block.citations.is_empty() {
+                        return Ok(None);
+                    }
+                    let end_index = *self.streamed_chars.borrow();
                     Ok(Some(StreamEvent::Delta(StreamDelta {
                         content: None,
-                        tool_calls: Some(vec![ToolCall {
+                        tool_calls: None,
+                        annotations: Some(
+                            text_block
+                                .citations
+                                .into_iter()
+                                .map(|citation| {
+                                    citation_to_annotation(
+                                        citation,
+                                        text_block.start_index,
+                                        end_index,
+                                    )
+                                })
+                                .collect(),
+                        ),
+                        tool_call_fragments: None,
+                        running_usage: None,
+                    })))
+                } else if let Some(tool_use) = self.json_fragments.borrow_mut().remove(&index) {
+                    Ok(Some(StreamEvent::Delta(complete_tool_call_delta(
+                        ToolCall {
                             id: tool_use.id,
                             name: tool_use.name,
                             arguments_json: tool_use.json,
-                        }]),
-                    })))
+                        },
+                        self.stream_tool_call_fragments,
+                    ))))
                 } else {
                     Ok(None)
                 }
             }
             Some("message_delta") => {
-                let stop_reason = json
+                let delta = json
                     .as_object()
                     .and_then(|obj| obj.get("delta"))
-                    .and_then(|v| v.as_object())
+                    .and_then(|v| v.as_object());
+                let stop_reason = delta
                     .and_then(|obj| obj.get("stop_reason"))
                     .and_then(|v| serde_json::from_value::<StopReason>(v.clone()).ok());
+                let stop_sequence = delta
+                    .and_then(|obj| obj.get("stop_sequence"))
+                    .and_then(|v| v.as_str());
                 let usage = json
                     .as_object()
                     .and_then(|obj| obj.get("usage"))
-                    .and_then(|v| serde_json::from_value::<Usage>(v.clone()).ok());
+                    .and_then(|v| serde_json::from_value::<StreamUsage>(v.clone()).ok());
 
                 if let Some(stop_reason) = stop_reason {
                     self.response_metadata.borrow_mut().finish_reason =
                         Some(stop_reason_to_finish_reason(stop_reason));
                 }
+                if let Some(stop_sequence) = stop_sequence {
+                    self.response_metadata.borrow_mut().stop_sequence =
+                        Some(stop_sequence.to_string());
+                }
                 if let Some(usage) = usage {
-                    self.response_metadata.borrow_mut().usage = Some(convert_usage(usage));
+                    self.update_usage(usage);
                 }
                 Ok(None)
             }
             Some("message_stop") => {
-                let response_metadata = self.response_metadata.borrow().clone();
+                let mut response_metadata = self.response_metadata.borrow().clone();
+                response_metadata.usage = response_metadata.usage.map(with_total_tokens);
                 Ok(Some(StreamEvent::Finish(response_metadata)))
             }
             Some(_) => Ok(None),
@@ -238,21 +439,28 @@ impl AnthropicComponent {
     const ENV_VAR_NAME: &'static str = "ANTHROPIC_API_KEY";
 
     fn request(client: MessagesApi, request: MessagesRequest) -> ChatEvent {
-        match client.send_messages(request) {
-            Ok(response) => process_response(response),
+        send_or_dry_run(request, |request| match client.send_messages(request) {
+            Ok(response) => with_rate_limit(process_response(response.body), response.rate_limit),
             Err(err) => ChatEvent::Error(err),
-        }
+        })
     }
 
     fn streaming_request(
         client: MessagesApi,
         mut request: MessagesRequest,
     ) -> LlmChatStream<AnthropicChatStream> {
-        request.stream = true;
-        match client.stream_send_messages(request) {
-            Ok(stream) => AnthropicChatStream::new(stream),
-            Err(err) => AnthropicChatStream::failed(err),
+        if let Err(err) = check_streaming(&request.model) {
+            return AnthropicChatStream::failed(err);
         }
+        request.stream = true;
+        stream_or_dry_run(
+            request,
+            AnthropicChatStream::dry_run,
+            |request| match client.stream_send_messages(request) {
+                Ok(stream) => AnthropicChatStream::new(stream),
+                Err(err) => AnthropicChatStream::failed(err),
+            },
+        )
     }
 }
 
@@ -261,8 +469,26 @@ impl Guest for AnthropicComponent {
 
     fn send(messages: Vec<Message>, config: Config) -> ChatEvent {
         LOGGING_STATE.with_borrow_mut(|state| state.init());
+
+        if let Err(err) = check_messages_not_empty(&messages, &[]) {
+            return ChatEvent::Error(err);
+        }
+
+        let extra_headers = match extra_headers(&config) {
+            Ok(extra_headers) => extra_headers,
+            Err(err) => return ChatEvent::Error(err),
+        };
+        let extra_body = match extra_body(&config) {
+            Ok(extra_body) => extra_body,
+            Err(err) => return ChatEvent::Error(err),
+        };
         with_config_key(Self::ENV_VAR_NAME, ChatEvent::Error, |anthropic_api_key| {
-            let client = MessagesApi::new(anthropic_api_key);
+            let client = match MessagesApi::new(anthropic_api_key, extra_headers)
+                .map(|client| client.with_extra_body(extra_body))
+            {
+                Ok(client) => client,
+                Err(err) => return ChatEvent::Error(err),
+            };
 
             match messages_to_request(messages, config) {
                 Ok(request) => Self::request(client, request),
@@ -278,8 +504,31 @@ impl Guest for AnthropicComponent {
     ) -> ChatEvent {
         LOGGING_STATE.with_borrow_mut(|state| state.init());
 
+        if let Err(err) = check_messages_not_empty(&messages, &tool_results) {
+            return ChatEvent::Error(err);
+        }
+
+        let extra_headers = match extra_headers(&config) {
+            Ok(extra_headers) => extra_headers,
+            Err(err) => return ChatEvent::Error(err),
+        };
+        let extra_body = match extra_body(&config) {
+            Ok(extra_body) => extra_body,
+            Err(err) => return ChatEvent::Error(err),
+        };
+
+        let tool_results = match normalize_tool_call_ids(tool_results, normalize_tool_call_id) {
+            Ok(tool_results) => tool_results,
+            Err(err) => return ChatEvent::Error(err),
+        };
+
         with_config_key(Self::ENV_VAR_NAME, ChatEvent::Error, |anthropic_api_key| {
-            let client = MessagesApi::new(anthropic_api_key);
+            let client = match MessagesApi::new(anthropic_api_key, extra_headers)
+                .map(|client| client.with_extra_body(extra_body))
+            {
+                Ok(client) => client,
+                Err(err) => return ChatEvent::Error(err),
+            };
 
             match messages_to_request(messages, config) {
                 Ok(mut request) => {
@@ -293,26 +542,130 @@ impl Guest for AnthropicComponent {
         })
     }
 
+    fn send_batch(requests: Vec<(Vec<Message>, Config)>, abort_on_error: bool) -> Vec<ChatEvent> {
+        send_batch(requests, abort_on_error, Self::send)
+    }
+
     fn stream(messages: Vec<Message>, config: Config) -> ChatStream {
         ChatStream::new(Self::unwrapped_stream(messages, config))
     }
+
+    fn continue_stream(
+        messages: Vec<Message>,
+        tool_results: Vec<(ToolCall, ToolResult)>,
+        config: Config,
+    ) -> ChatStream {
+        ChatStream::new(Self::unwrapped_continue_stream(
+            messages,
+            tool_results,
+            config,
+        ))
+    }
+
+    fn send_raw(body_json: String, config: Config) -> Result<String, Error> {
+        LOGGING_STATE.with_borrow_mut(|state| state.init());
+
+        let extra_headers = extra_headers(&config)?;
+        with_config_key(Self::ENV_VAR_NAME, Err, |anthropic_api_key| {
+            MessagesApi::new(anthropic_api_key, extra_headers)?.send_raw(body_json)
+        })
+    }
+
+    fn rerank(
+        _query: String,
+        _documents: Vec<String>,
+        _config: RerankConfig,
+    ) -> Result<RerankResult, Error> {
+        Err(unsupported("rerank"))
+    }
+
+    fn embed(
+        _inputs: Vec<ContentPart>,
+        _config: EmbeddingConfig,
+    ) -> Result<EmbeddingResult, Error> {
+        Err(unsupported("embed"))
+    }
+
+    fn ping() -> Result<ProviderHealth, Error> {
+        LOGGING_STATE.with_borrow_mut(|state| state.init());
+
+        with_config_key(Self::ENV_VAR_NAME, Err, |anthropic_api_key| {
+            MessagesApi::new(anthropic_api_key, HeaderMap::new())?.ping()
+        })
+    }
+
+    fn capabilities() -> ProviderCapabilities {
+        ProviderCapabilities {
+            provider: "anthropic".to_string(),
+            streaming: true,
+            tools: true,
+            vision: true,
+            embeddings: false,
+            json_schema: false,
+        }
+    }
+
+    fn warm(_config: Config) -> Result<(), Error> {
+        Err(unsupported("warm"))
+    }
+
+    fn generate_image(_prompt: String, _config: ImageGenConfig) -> Result<ImageGenResult, Error> {
+        Err(unsupported("generate-image"))
+    }
 }
 
 impl ExtendedGuest for AnthropicComponent {
     fn unwrapped_stream(
         messages: Vec<Message>,
         config: Config,
+    ) -> LlmChatStream<AnthropicChatStream> {
+        Self::unwrapped_continue_stream(messages, vec![], config)
+    }
+
+    fn unwrapped_continue_stream(
+        messages: Vec<Message>,
+        tool_results: Vec<(ToolCall, ToolResult)>,
+        config: Config,
     ) -> LlmChatStream<AnthropicChatStream> {
         LOGGING_STATE.with_borrow_mut(|state| state.init());
 
+        if let Err(err) = check_messages_not_empty(&messages, &tool_results) {
+            return AnthropicChatStream::failed(err);
+        }
+
+        let extra_headers = match extra_headers(&config) {
+            Ok(extra_headers) => extra_headers,
+            Err(err) => return AnthropicChatStream::failed(err),
+        };
+        let extra_body = match extra_body(&config) {
+            Ok(extra_body) => extra_body,
+            Err(err) => return AnthropicChatStream::failed(err),
+        };
+        let deadline = request_deadline(&config);
+
+        let tool_results = match normalize_tool_call_ids(tool_results, normalize_tool_call_id) {
+            Ok(tool_results) => tool_results,
+            Err(err) => return AnthropicChatStream::failed(err),
+        };
+
         with_config_key(
             Self::ENV_VAR_NAME,
             AnthropicChatStream::failed,
             |anthropic_api_key| {
-                let client = MessagesApi::new(anthropic_api_key);
+                let client = match MessagesApi::new(anthropic_api_key, extra_headers)
+                    .map(|client| client.with_extra_body(extra_body))
+                {
+                    Ok(client) => client,
+                    Err(err) => return AnthropicChatStream::failed(err),
+                };
 
                 match messages_to_request(messages, config) {
-                    Ok(request) => Self::streaming_request(client, request),
+                    Ok(mut request) => {
+                        request
+                            .messages
+                            .extend(tool_results_to_messages(tool_results));
+                        Self::streaming_request(client, request).with_deadline(deadline)
+                    }
                     Err(err) => AnthropicChatStream::failed(err),
                 }
             },
@@ -371,8 +724,391 @@ impl ExtendedGuest for AnthropicComponent {
     fn subscribe(stream: &Self::ChatStream) -> Pollable {
         stream.subscribe()
     }
+
+    fn subscribe_deadline(stream: &Self::ChatStream) -> Option<Pollable> {
+        stream.subscribe_deadline()
+    }
+
+    fn usage_so_far(stream: &Self::ChatStream) -> Option<Usage> {
+        stream.usage_so_far()
+    }
 }
 
 type DurableAnthropicComponent = DurableLLM<AnthropicComponent>;
 
 golem_llm::export_llm!(DurableAnthropicComponent with_types_in golem_llm);
+
+#[cfg(test)]
+mod tests {
+    use crate::{AnthropicChatStream, AnthropicComponent};
+    use golem_llm::chat_stream::{LlmChatStream, LlmChatStreamState};
+    use golem_llm::event_source::StreamFormat;
+    use golem_llm::golem::llm::llm::{
+        Annotation, ContentPart, FinishReason, Guest, ProviderCapabilities, ResponseMetadata,
+        StreamDelta, StreamEvent, StreamStart, ToolCall, ToolCallFragment, Usage,
+    };
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+
+    fn stream() -> AnthropicChatStream {
+        AnthropicChatStream {
+            stream: RefCell::new(None),
+            failure: None,
+            finished: RefCell::new(false),
+            json_fragments: RefCell::new(HashMap::new()),
+            text_blocks: RefCell::new(HashMap::new()),
+            streamed_chars: RefCell::new(0),
+            response_metadata: RefCell::new(ResponseMetadata {
+                finish_reason: None,
+                usage: None,
+                provider_id: None,
+                model: None,
+                timestamp: None,
+                provider_metadata_json: None,
+                tokens_per_second: None,
+                stop_sequence: None,
+            }),
+            stream_tool_call_fragments: false,
+        }
+    }
+
+    #[test]
+    fn interleaved_thinking_and_text_are_streamed_apart_in_order() {
+        let events = LlmChatStream::new(stream()).decode_transcript(
+            include_str!("../fixtures/interleaved_thinking.sse"),
+            StreamFormat::EventStream,
+        );
+
+        let parts = events
+            .iter()
+            .filter_map(|event| match event {
+                StreamEvent::Delta(delta) => delta.content.clone(),
+                _ => None,
+            })
+            .flatten()
+            .collect::<Vec<_>>();
+        assert_eq!(
+            parts,
+            vec![
+                ContentPart::Reasoning("The user asks for 17 * 3.".to_string()),
+                ContentPart::Reasoning(" 17 * 3 = 51.".to_string()),
+                ContentPart::Text("17 * 3 is 51.".to_string()),
+                ContentPart::Reasoning("Checking: 50 + 1 = 51.".to_string()),
+                ContentPart::Text(" The result is".to_string()),
+                ContentPart::Text(" confirmed.".to_string()),
+            ]
+        );
+        assert!(matches!(
+            events.last(),
+            Some(StreamEvent::Finish(ResponseMetadata {
+                finish_reason: Some(FinishReason::Stop),
+                ..
+            }))
+        ));
+    }
+
+    #[test]
+    fn input_tokens_are_known_from_the_start_of_the_response() {
+        let stream = LlmChatStream::new(stream());
+        stream.decode_transcript(
+            concat!(
+                "event: message_start\n",
+                r#"data: {"type":"message_start","message":{"id":"msg_01","type":"message","role":"assistant","content":[],"model":"claude-3-7-sonnet-20250219","stop_reason":null,"stop_sequence":null,"usage":{"input_tokens":25,"output_tokens":1}}}"#,
+                "\n\n",
+                "event: content_block_start\n",
+                r#"data: {"type":"content_block_start","index":0,"content_block":{"type":"text","text":""}}"#,
+                "\n\n",
+                "event: content_block_delta\n",
+                r#"data: {"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"Hello"}}"#,
+                "\n\n",
+            ),
+            StreamFormat::EventStream,
+        );
+
+        assert_eq!(
+            stream.usage_so_far(),
+            Some(Usage {
+                input_tokens: Some(25),
+                output_tokens: Some(2),
+                total_tokens: Some(27),
+            })
+        );
+    }
+
+    #[test]
+    fn replays_captured_stream_transcript() {
+        let transcript = [
+            (
+                "message_start",
+                r#"{"type":"message_start","message":{"id":"msg_01","type":"message","role":"assistant","content":[],"model":"claude-3-7-sonnet-20250219","stop_reason":null,"stop_sequence":null,"usage":{"input_tokens":25,"output_tokens":1}}}"#,
+            ),
+            (
+                "content_block_start",
+                r#"{"type":"content_block_start","index":0,"content_block":{"type":"text","text":""}}"#,
+            ),
+            ("ping", r#"{"type":"ping"}"#),
+            (
+                "content_block_delta",
+                r#"{"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"Hello"}}"#,
+            ),
+            (
+                "content_block_delta",
+                r#"{"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"!"}}"#,
+            ),
+            (
+                "content_block_stop",
+                r#"{"type":"content_block_stop","index":0}"#,
+            ),
+            (
+                "message_delta",
+                r#"{"type":"message_delta","delta":{"stop_reason":"max_tokens","stop_sequence":null},"usage":{"output_tokens":15}}"#,
+            ),
+            ("message_stop", r#"{"type":"message_stop"}"#),
+        ];
+
+        let stream = stream();
+
+        let events = transcript
+            .iter()
+            .filter_map(|(event, raw)| stream.decode_message(event, raw).unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            events,
+            vec![
+                StreamEvent::Start(StreamStart {
+                    id: "msg_01".to_string(),
+                    model: Some("claude-3-7-sonnet-20250219".to_string()),
+                    created: None,
+                }),
+                StreamEvent::Delta(StreamDelta {
+                    content: Some(vec![ContentPart::Text("Hello".to_string())]),
+                    tool_calls: None,
+                    annotations: None,
+                    tool_call_fragments: None,
+                    running_usage: None,
+                }),
+                StreamEvent::Delta(StreamDelta {
+                    content: Some(vec![ContentPart::Text("!".to_string())]),
+                    tool_calls: None,
+                    annotations: None,
+                    tool_call_fragments: None,
+                    running_usage: None,
+                }),
+                StreamEvent::Finish(ResponseMetadata {
+                    finish_reason: Some(FinishReason::Length),
+                    usage: Some(Usage {
+                        input_tokens: Some(25),
+                        output_tokens: Some(15),
+                        total_tokens: Some(40),
+                    }),
+                    provider_id: None,
+                    model: Some("claude-3-7-sonnet-20250219".to_string()),
+                    timestamp: None,
+                    provider_metadata_json: None,
+                    tokens_per_second: None,
+                    stop_sequence: None,
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn matched_stop_sequence_is_reported_in_the_finish_event() {
+        let stream = stream();
+        stream
+            .decode_message(
+                "message_delta",
+                r#"{"type":"message_delta","delta":{"stop_reason":"stop_sequence","stop_sequence":"END"},"usage":{"output_tokens":5}}"#,
+            )
+            .unwrap();
+
+        match stream
+            .decode_message("message_stop", r#"{"type":"message_stop"}"#)
+            .unwrap()
+        {
+            Some(StreamEvent::Finish(metadata)) => {
+                assert_eq!(metadata.finish_reason, Some(FinishReason::Stop));
+                assert_eq!(metadata.stop_sequence, Some("END".to_string()));
+            }
+            event => panic!("Expected a finish event, got {event:?}"),
+        }
+    }
+
+    #[test]
+    fn citations_annotate_their_streamed_text_block() {
+        let transcript = [
+            (
+                "content_block_start",
+                r#"{"type":"content_block_start","index":0,"content_block":{"type":"text","text":""}}"#,
+            ),
+            (
+                "content_block_delta",
+                r#"{"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"Hi. "}}"#,
+            ),
+            (
+                "content_block_stop",
+                r#"{"type":"content_block_stop","index":0}"#,
+            ),
+            (
+                "content_block_start",
+                r#"{"type":"content_block_start","index":1,"content_block":{"type":"text","text":"","citations":[]}}"#,
+            ),
+            (
+                "content_block_delta",
+                r#"{"type":"content_block_delta","index":1,"delta":{"type":"citations_delta","citation":{"type":"web_search_result_location","url":"https://golem.cloud","title":"Golem Cloud","cited_text":"Durable execution","encrypted_index":"abc"}}}"#,
+            ),
+            (
+                "content_block_delta",
+                r#"{"type":"content_block_delta","index":1,"delta":{"type":"text_delta","text":"Golem is durable."}}"#,
+            ),
+            (
+                "content_block_stop",
+                r#"{"type":"content_block_stop","index":1}"#,
+            ),
+        ];
+
+        let stream = stream();
+        let events = transcript
+            .iter()
+            .filter_map(|(event, raw)| stream.decode_message(event, raw).unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            events.last(),
+            Some(&StreamEvent::Delta(StreamDelta {
+                content: None,
+                tool_calls: None,
+                annotations: Some(vec![Annotation {
+                    url: Some("https://golem.cloud".to_string()),
+                    title: Some("Golem Cloud".to_string()),
+                    start_index: 4,
+                    end_index: 21,
+                }]),
+                tool_call_fragments: None,
+                running_usage: None,
+            }))
+        );
+    }
+
+    #[test]
+    fn streams_tool_call_fragments_when_enabled() {
+        let transcript = [
+            (
+                "content_block_start",
+                r#"{"type":"content_block_start","index":0,"content_block":{"type":"tool_use","id":"toolu_01","name":"get_weather","input":{}}}"#,
+            ),
+            (
+                "content_block_delta",
+                r#"{"type":"content_block_delta","index":0,"delta":{"type":"input_json_delta","partial_json":""}}"#,
+            ),
+            (
+                "content_block_delta",
+                r#"{"type":"content_block_delta","index":0,"delta":{"type":"input_json_delta","partial_json":"{\"city\": \"Bud"}}"#,
+            ),
+            (
+                "content_block_delta",
+                r#"{"type":"content_block_delta","index":0,"delta":{"type":"input_json_delta","partial_json":"apest\"}"}}"#,
+            ),
+            (
+                "content_block_stop",
+                r#"{"type":"content_block_stop","index":0}"#,
+            ),
+        ];
+        let fragment = |arguments_json: &str, is_complete: bool| ToolCallFragment {
+            id: "toolu_01".to_string(),
+            name: "get_weather".to_string(),
+            arguments_json: arguments_json.to_string(),
+            is_complete,
+        };
+
+        let stream = AnthropicChatStream {
+            stream_tool_call_fragments: true,
+            ..stream()
+        };
+        let deltas = transcript
+            .iter()
+            .filter_map(|(event, raw)| stream.decode_message(event, raw).unwrap())
+            .map(|event| match event {
+                StreamEvent::Delta(delta) => delta,
+                event => panic!("Unexpected event: {event:?}"),
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            deltas
+                .iter()
+                .map(|delta| delta.tool_call_fragments.clone())
+                .collect::<Vec<_>>(),
+            vec![
+                Some(vec![fragment(r#"{"city": "Bud"#, false)]),
+                Some(vec![fragment(r#"apest"}"#, false)]),
+                Some(vec![fragment("", true)]),
+            ]
+        );
+        assert_eq!(deltas[0].tool_calls, None);
+        assert_eq!(deltas[1].tool_calls, None);
+        assert_eq!(
+            deltas[2].tool_calls,
+            Some(vec![ToolCall {
+                id: "toolu_01".to_string(),
+                name: "get_weather".to_string(),
+                arguments_json: r#"{"city": "Budapest"}"#.to_string(),
+            }])
+        );
+    }
+
+    #[test]
+    fn sends_only_complete_tool_calls_by_default() {
+        let events = [
+            (
+                "content_block_start",
+                r#"{"type":"content_block_start","index":0,"content_block":{"type":"tool_use","id":"toolu_01","name":"get_weather","input":{}}}"#,
+            ),
+            (
+                "content_block_delta",
+                r#"{"type":"content_block_delta","index":0,"delta":{"type":"input_json_delta","partial_json":"{}"}}"#,
+            ),
+            (
+                "content_block_stop",
+                r#"{"type":"content_block_stop","index":0}"#,
+            ),
+        ];
+
+        let stream = stream();
+        let events = events
+            .iter()
+            .filter_map(|(event, raw)| stream.decode_message(event, raw).unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            events,
+            vec![StreamEvent::Delta(StreamDelta {
+                content: None,
+                tool_calls: Some(vec![ToolCall {
+                    id: "toolu_01".to_string(),
+                    name: "get_weather".to_string(),
+                    arguments_json: "{}".to_string(),
+                }]),
+                annotations: None,
+                tool_call_fragments: None,
+                running_usage: None,
+            })]
+        );
+    }
+
+    #[test]
+    fn capabilities_describe_the_provider() {
+        assert_eq!(
+            AnthropicComponent::capabilities(),
+            ProviderCapabilities {
+                provider: "anthropic".to_string(),
+                streaming: true,
+                tools: true,
+                vision: true,
+                embeddings: false,
+                json_schema: false,
+            }
+        );
+    }
+}
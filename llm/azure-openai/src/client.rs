@@ -0,0 +1,130 @@
+use golem_llm::chat_completions::client::ChatCompletionsEndpoint;
+use reqwest::RequestBuilder;
+
+/// Header carrying the API key, used by Azure instead of `Authorization: Bearer`
+const API_KEY_HEADER: &str = "api-key";
+
+/// The Azure OpenAI Chat Completions API of a resource, where the model of a request is the name
+/// of a deployment.
+///
+/// Based on https://learn.microsoft.com/en-us/azure/ai-services/openai/reference
+pub struct AzureOpenAIApi {
+    endpoint: String,
+    api_key: String,
+    api_version: String,
+}
+
+impl AzureOpenAIApi {
+    pub fn new(endpoint: String, api_key: String, api_version: String) -> Self {
+        Self {
+            endpoint,
+            api_key,
+            api_version,
+        }
+    }
+}
+
+impl ChatCompletionsEndpoint for AzureOpenAIApi {
+    const API_NAME: &'static str = "Azure OpenAI API";
+
+    fn base_url(&self) -> &str {
+        &self.endpoint
+    }
+
+    fn completions_url(&self, deployment: &str) -> String {
+        format!(
+            "{}/openai/deployments/{deployment}/chat/completions?api-version={}",
+            self.endpoint.trim_end_matches('/'),
+            self.api_version
+        )
+    }
+
+    fn models_url(&self) -> String {
+        format!(
+            "{}/openai/models?api-version={}",
+            self.endpoint.trim_end_matches('/'),
+            self.api_version
+        )
+    }
+
+    fn authenticate(&self, request: RequestBuilder) -> RequestBuilder {
+        request.header(API_KEY_HEADER, &self.api_key)
+    }
+}
+
+/// The Azure OpenAI Chat Completions API client for creating model responses.
+pub type CompletionsApi = golem_llm::chat_completions::client::CompletionsApi<AzureOpenAIApi>;
+
+#[cfg(test)]
+mod tests {
+    use crate::client::{AzureOpenAIApi, CompletionsApi};
+    use golem_llm::chat_completions::client::ChatCompletionsEndpoint;
+    use reqwest::header::{HeaderMap, HeaderValue};
+
+    fn endpoint(endpoint: &str) -> AzureOpenAIApi {
+        AzureOpenAIApi::new(
+            endpoint.to_string(),
+            "test-key".to_string(),
+            "2024-10-21".to_string(),
+        )
+    }
+
+    fn api(endpoint_url: &str) -> CompletionsApi {
+        CompletionsApi::new(endpoint(endpoint_url), HeaderMap::new()).unwrap()
+    }
+
+    #[test]
+    fn completions_url_targets_deployment() {
+        assert_eq!(
+            endpoint("https://test-resource.openai.azure.com").completions_url("gpt-4o-mini"),
+            "https://test-resource.openai.azure.com/openai/deployments/gpt-4o-mini/chat/completions?api-version=2024-10-21"
+        );
+    }
+
+    #[test]
+    fn completions_url_ignores_trailing_slash_of_endpoint() {
+        assert_eq!(
+            endpoint("https://test-resource.openai.azure.com/").completions_url("gpt-4o-mini"),
+            "https://test-resource.openai.azure.com/openai/deployments/gpt-4o-mini/chat/completions?api-version=2024-10-21"
+        );
+    }
+
+    #[test]
+    fn completions_request_authenticates_with_api_key_header() {
+        let request = api("https://test-resource.openai.azure.com")
+            .completions_request("gpt-4o-mini")
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            request.headers().get("api-key").unwrap().to_str().unwrap(),
+            "test-key"
+        );
+        assert!(request
+            .headers()
+            .get(reqwest::header::AUTHORIZATION)
+            .is_none());
+    }
+
+    #[test]
+    fn completions_request_attaches_extra_headers() {
+        let mut extra_headers = HeaderMap::new();
+        extra_headers.insert("x-title", HeaderValue::from_static("golem"));
+        let api = CompletionsApi::new(
+            endpoint("https://test-resource.openai.azure.com"),
+            extra_headers,
+        )
+        .unwrap();
+
+        let request = api.completions_request("gpt-4o-mini").build().unwrap();
+
+        assert_eq!(
+            request.headers().get("x-title").unwrap().to_str().unwrap(),
+            "golem"
+        );
+        assert_eq!(
+            request.headers().get("api-key").unwrap().to_str().unwrap(),
+            "test-key"
+        );
+    }
+}
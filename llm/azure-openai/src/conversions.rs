@@ -0,0 +1,24 @@
+use golem_llm::chat_completions::conversions::{ChatCompletionsProvider, MaxTokensField};
+
+/// The settings of the Chat Completions API of Azure OpenAI. The models are deployments named by
+/// their users, so their capabilities are not validated
+pub const PROVIDER: ChatCompletionsProvider = ChatCompletionsProvider {
+    provider_options: &PROVIDER_OPTIONS,
+    temperature_range: 0.0..=2.0,
+    model_capabilities: &[],
+    max_tokens_field: MaxTokensField::MaxCompletionTokens,
+    text_only: false,
+    tool_messages_answer_tool_calls: false,
+};
+
+/// Provider options read by the Azure OpenAI conversions, any other key is reported as unknown
+const PROVIDER_OPTIONS: [&str; 8] = [
+    "frequency_penalty",
+    "n",
+    "presence_penalty",
+    "reasoning_effort",
+    "seed",
+    "top_logprobs",
+    "top_p",
+    "user_id",
+];
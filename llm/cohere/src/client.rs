@@ -0,0 +1,388 @@
+use golem_llm::body::read_body;
+use golem_llm::error::{
+    decode_success_body, error_code_from_response, from_event_source_error, from_reqwest_error,
+};
+use golem_llm::event_source::{EventSource, StreamFormat, StreamFormatHint};
+use golem_llm::extra_body::ExtraBody;
+use golem_llm::golem::llm::llm::{Error, ProviderHealth};
+use golem_llm::health::probe;
+use golem_llm::proxy::http_client;
+use golem_llm::rate_limit::{rate_limit_info, RateLimited};
+use golem_llm::raw::send_raw;
+use golem_llm::retry::send_with_connection_retry;
+use log::trace;
+use reqwest::header::{HeaderMap, HeaderValue};
+use reqwest::{Client, Method, Response};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fmt::Debug;
+
+const BASE_URL: &str = "https://api.cohere.com";
+
+/// The Cohere API client for creating model responses and reranking documents.
+pub struct CohereApi {
+    api_key: String,
+    extra_headers: HeaderMap,
+    /// Fields added to the chat request bodies
+    extra_body: ExtraBody,
+    client: Client,
+}
+
+impl CohereApi {
+    pub fn new(api_key: String, extra_headers: HeaderMap) -> Result<Self, Error> {
+        let client = http_client(BASE_URL)?;
+        Ok(Self {
+            api_key,
+            extra_headers,
+            extra_body: ExtraBody::default(),
+            client,
+        })
+    }
+
+    /// Adds the `extra_body` of the config to the chat requests
+    pub fn with_extra_body(mut self, extra_body: ExtraBody) -> Self {
+        self.extra_body = extra_body;
+        self
+    }
+
+    pub fn send_messages(&self, request: ChatRequest) -> Result<RateLimited<ChatResponse>, Error> {
+        trace!("Sending request to Cohere API: {request:?}");
+
+        let body = self.extra_body.merge(&request)?;
+
+        let response: Response = send_with_connection_retry(|| {
+            self.client
+                .request(Method::POST, format!("{BASE_URL}/v2/chat"))
+                .bearer_auth(self.api_key.clone())
+                .json(&body)
+                .headers(self.extra_headers.clone())
+                .send()
+        })
+        .map_err(|err| from_reqwest_error("Request failed", err))?;
+
+        let rate_limit = rate_limit_info(response.headers());
+        parse_response(response).map(|body| RateLimited { body, rate_limit })
+    }
+
+    pub fn stream_send_messages(&self, request: ChatRequest) -> Result<EventSource, Error> {
+        trace!("Sending request to Cohere API: {request:?}");
+
+        let body = self.extra_body.merge(&request)?;
+
+        let response: Response = self
+            .client
+            .request(Method::POST, format!("{BASE_URL}/v2/chat"))
+            .bearer_auth(self.api_key.clone())
+            .header(
+                reqwest::header::ACCEPT,
+                HeaderValue::from_static("text/event-stream"),
+            )
+            .json(&body)
+            .headers(self.extra_headers.clone())
+            .send()
+            .map_err(|err| from_reqwest_error("Request failed", err))?;
+
+        trace!("Initializing SSE stream");
+
+        EventSource::new(
+            response,
+            StreamFormatHint::Detect(StreamFormat::EventStream),
+        )
+        .map_err(|err| from_event_source_error("Failed to create SSE stream", err))
+    }
+
+    pub fn rerank(&self, request: RerankRequest) -> Result<RerankResponse, Error> {
+        trace!("Sending rerank request to Cohere API: {request:?}");
+
+        let response: Response = self
+            .client
+            .request(Method::POST, format!("{BASE_URL}/v2/rerank"))
+            .bearer_auth(self.api_key.clone())
+            .json(&request)
+            .headers(self.extra_headers.clone())
+            .send()
+            .map_err(|err| from_reqwest_error("Request failed", err))?;
+
+        parse_response(response)
+    }
+
+    /// Sends the JSON body untouched to the Chat API, returning the raw response body
+    pub fn send_raw(&self, body_json: String) -> Result<String, Error> {
+        send_raw(
+            self.client
+                .request(Method::POST, format!("{BASE_URL}/v2/chat"))
+                .bearer_auth(self.api_key.clone()),
+            self.extra_headers.clone(),
+            body_json,
+        )
+    }
+
+    /// Checks the connectivity by listing the available models
+    pub fn ping(&self) -> Result<ProviderHealth, Error> {
+        trace!("Checking the connectivity of the Cohere API");
+
+        probe(|| {
+            self.client
+                .request(Method::GET, format!("{BASE_URL}/v1/models"))
+                .bearer_auth(self.api_key.clone())
+                .headers(self.extra_headers.clone())
+                .send()
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatRequest {
+    pub model: String,
+    pub messages: Vec<Message>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tools: Vec<Tool>,
+    pub stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop_sequences: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub k: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_choice: Option<ToolChoice>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "role")]
+pub enum Message {
+    #[serde(rename = "system")]
+    System { content: Vec<ContentPart> },
+    #[serde(rename = "user")]
+    User { content: Vec<ContentPart> },
+    #[serde(rename = "assistant")]
+    Assistant {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        content: Option<Vec<ContentPart>>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        tool_calls: Option<Vec<ToolCall>>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        tool_plan: Option<String>,
+    },
+    #[serde(rename = "tool")]
+    Tool {
+        tool_call_id: String,
+        content: Vec<ToolContent>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum ContentPart {
+    #[serde(rename = "text")]
+    Text { text: String },
+    #[serde(rename = "image_url")]
+    ImageUrl { image_url: ImageUrl },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageUrl {
+    pub url: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum ToolContent {
+    #[serde(rename = "document")]
+    Document { document: ToolDocument },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolDocument {
+    pub data: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum Tool {
+    #[serde(rename = "function")]
+    Function { function: Function },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Function {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub parameters: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ToolChoice {
+    #[serde(rename = "REQUIRED")]
+    Required,
+    #[serde(rename = "NONE")]
+    None,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolCall {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub typ: String,
+    pub function: FunctionCall,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FunctionCall {
+    pub name: String,
+    pub arguments: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatResponse {
+    pub id: String,
+    pub finish_reason: FinishReason,
+    pub message: ResponseMessage,
+    pub usage: Option<Usage>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum FinishReason {
+    #[serde(rename = "COMPLETE")]
+    Complete,
+    #[serde(rename = "STOP_SEQUENCE")]
+    StopSequence,
+    #[serde(rename = "MAX_TOKENS")]
+    MaxTokens,
+    #[serde(rename = "TOOL_CALL")]
+    ToolCall,
+    #[serde(rename = "ERROR")]
+    Error,
+    #[serde(rename = "TIMEOUT")]
+    Timeout,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResponseMessage {
+    #[serde(default)]
+    pub content: Vec<ResponseContent>,
+    #[serde(default)]
+    pub tool_calls: Vec<ToolCall>,
+    pub tool_plan: Option<String>,
+    #[serde(default)]
+    pub citations: Vec<Citation>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum ResponseContent {
+    #[serde(rename = "text")]
+    Text { text: String },
+    #[serde(other)]
+    Other,
+}
+
+/// Span of the response text grounded in the documents or tool results it cites
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Citation {
+    pub start: Option<u32>,
+    pub end: Option<u32>,
+    pub text: Option<String>,
+    #[serde(default)]
+    pub sources: Vec<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Usage {
+    pub billed_units: Option<UsageTokens>,
+    pub tokens: Option<UsageTokens>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageTokens {
+    pub input_tokens: Option<f64>,
+    pub output_tokens: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StreamMessageDelta {
+    pub message: Option<StreamMessage>,
+    pub finish_reason: Option<FinishReason>,
+    pub usage: Option<Usage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StreamMessage {
+    pub content: Option<StreamContent>,
+    pub tool_calls: Option<StreamToolCall>,
+    pub citations: Option<Citation>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StreamContent {
+    pub text: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StreamToolCall {
+    pub id: Option<String>,
+    pub function: Option<StreamFunctionCall>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StreamFunctionCall {
+    pub name: Option<String>,
+    pub arguments: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RerankRequest {
+    pub model: String,
+    pub query: String,
+    pub documents: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_n: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens_per_doc: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RerankResponse {
+    pub id: Option<String>,
+    pub results: Vec<RerankResponseResult>,
+    pub meta: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RerankResponseResult {
+    pub index: u32,
+    pub relevance_score: f32,
+}
+
+fn parse_response<T: DeserializeOwned + Debug>(response: Response) -> Result<T, Error> {
+    let status = response.status();
+    if status.is_success() {
+        let raw_body = read_body(response, "Failed to receive response body")?;
+        let body = decode_success_body::<T>(raw_body)?;
+
+        trace!("Received response from Cohere API: {body:?}");
+
+        Ok(body)
+    } else {
+        let error_body = read_body(response, "Failed to receive error response body")?;
+
+        trace!("Received {status} response from Cohere API: {error_body:?}");
+
+        Err(Error {
+            code: error_code_from_response(status, &error_body),
+            message: format!("Request failed with {status}"),
+            provider_error_json: Some(serde_json::to_string(&error_body).unwrap()),
+        })
+    }
+}
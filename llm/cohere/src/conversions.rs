@@ -0,0 +1,523 @@
+use crate::client::{
+    ChatRequest, ChatResponse, Citation, RerankRequest, RerankResponse, ResponseContent, ToolChoice,
+};
+use base64::{engine::general_purpose, Engine as _};
+use golem_llm::capabilities::{
+    check_model_capabilities, check_streaming_support, ModelCapabilities,
+};
+use golem_llm::config::model_or_default;
+use golem_llm::conversions::{
+    check_provider_options, non_empty_tool_call_id, split_tool_results, validate_temperature,
+};
+use golem_llm::golem::llm::llm::{
+    ChatEvent, CompleteResponse, Config, ContentPart, Error, ErrorCode, FinishReason,
+    ImageReference, Message, RerankConfig, RerankResult, RerankScore, ResponseMetadata, Role,
+    ToolCall, ToolDefinition, ToolResult, Usage,
+};
+use golem_llm::usage::with_total_tokens;
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+
+/// Range of temperatures accepted by Cohere
+const TEMPERATURE_RANGE: RangeInclusive<f32> = 0.0..=1.0;
+
+/// Provider options read by the Cohere conversions, any other key is reported as unknown
+const PROVIDER_OPTIONS: [&str; 5] = [
+    "seed",
+    "frequency_penalty",
+    "presence_penalty",
+    "top_k",
+    "top_p",
+];
+
+/// Capabilities of the known Cohere models, requests to other models are not validated
+const MODEL_CAPABILITIES: [(&str, ModelCapabilities); 7] = [
+    ("command-a-03-2025", ModelCapabilities::TEXT_ONLY),
+    ("command-a-vision-07-2025", VISION_MODEL_CAPABILITIES),
+    ("command-r-plus-08-2024", ModelCapabilities::TEXT_ONLY),
+    ("command-r-08-2024", ModelCapabilities::TEXT_ONLY),
+    ("command-r7b-12-2024", ModelCapabilities::TEXT_ONLY),
+    ("c4ai-aya-vision-32b", VISION_MODEL_CAPABILITIES),
+    ("c4ai-aya-vision-8b", VISION_MODEL_CAPABILITIES),
+];
+
+/// The vision models of Cohere do not support tools
+const VISION_MODEL_CAPABILITIES: ModelCapabilities = ModelCapabilities {
+    vision: true,
+    tools: false,
+    streaming: true,
+};
+
+/// Provider options read by the Cohere rerank conversion
+const RERANK_PROVIDER_OPTIONS: [&str; 1] = ["max_tokens_per_doc"];
+
+pub fn messages_to_request(messages: Vec<Message>, config: Config) -> Result<ChatRequest, Error> {
+    let options = config
+        .provider_options
+        .into_iter()
+        .map(|kv| (kv.key, kv.value))
+        .collect::<HashMap<_, _>>();
+    check_provider_options(&options, &PROVIDER_OPTIONS)?;
+
+    let model = model_or_default(config.model)?;
+    check_model_capabilities(&MODEL_CAPABILITIES, &model, &messages, &config.tools)?;
+
+    let mut cohere_messages = Vec::new();
+    for message in messages {
+        // Tool results included in the messages are sent as a tool call and its result
+        let (content, tool_results) = split_tool_results(message.content, non_empty_tool_call_id)?;
+        if !content.is_empty() || tool_results.is_empty() {
+            match message.role {
+                Role::System => cohere_messages.push(crate::client::Message::System {
+                    content: convert_content_parts(content),
+                }),
+                // Cohere only accepts tool messages answering a tool call, other tool messages are
+                // sent as user input
+                Role::User | Role::Tool => cohere_messages.push(crate::client::Message::User {
+                    content: convert_content_parts(content),
+                }),
+                Role::Assistant => cohere_messages.push(crate::client::Message::Assistant {
+                    content: Some(convert_content_parts(content)),
+                    tool_calls: None,
+                    tool_plan: None,
+                }),
+            }
+        }
+        cohere_messages.extend(tool_results_to_messages(tool_results));
+    }
+
+    let mut tools = Vec::new();
+    for tool in config.tools {
+        tools.push(tool_definition_to_tool(tool)?)
+    }
+
+    Ok(ChatRequest {
+        model,
+        messages: cohere_messages,
+        tools,
+        stream: false,
+        max_tokens: config.max_tokens,
+        stop_sequences: config.stop_sequences,
+        temperature: validate_temperature(config.temperature, TEMPERATURE_RANGE)?,
+        seed: options
+            .get("seed")
+            .and_then(|seed_s| seed_s.parse::<u32>().ok()),
+        frequency_penalty: options
+            .get("frequency_penalty")
+            .and_then(|fp_s| fp_s.parse::<f32>().ok()),
+        presence_penalty: options
+            .get("presence_penalty")
+            .and_then(|pp_s| pp_s.parse::<f32>().ok()),
+        k: options.get("top_k").and_then(|k_s| k_s.parse::<u32>().ok()),
+        p: options
+            .get("top_p")
+            .and_then(|top_p_s| top_p_s.parse::<f32>().ok()),
+        tool_choice: config.tool_choice.and_then(convert_tool_choice),
+    })
+}
+
+/// Fails if the model is known not to support streaming
+pub fn check_streaming(model: &str) -> Result<(), Error> {
+    check_streaming_support(&MODEL_CAPABILITIES, model)
+}
+
+pub fn process_response(response: ChatResponse) -> ChatEvent {
+    let mut contents = Vec::new();
+    for content in response.message.content {
+        if let ResponseContent::Text { text } = content {
+            contents.push(ContentPart::Text(text));
+        }
+    }
+
+    let tool_calls: Vec<ToolCall> = response
+        .message
+        .tool_calls
+        .iter()
+        .map(convert_tool_call)
+        .collect();
+
+    if contents.is_empty() && !tool_calls.is_empty() {
+        ChatEvent::ToolRequest(tool_calls)
+    } else {
+        let metadata = ResponseMetadata {
+            finish_reason: Some(convert_finish_reason(response.finish_reason)),
+            usage: response.usage.as_ref().map(convert_usage),
+            provider_id: None,
+            model: None,
+            timestamp: None,
+            provider_metadata_json: citations_to_provider_metadata(&response.message.citations),
+            tokens_per_second: None,
+            stop_sequence: None,
+        };
+
+        ChatEvent::Message(CompleteResponse {
+            id: response.id,
+            content: contents,
+            tool_calls,
+            metadata,
+            annotations: vec![],
+        })
+    }
+}
+
+pub fn rerank_request(
+    query: String,
+    documents: Vec<String>,
+    config: RerankConfig,
+) -> Result<RerankRequest, Error> {
+    let options = config
+        .provider_options
+        .into_iter()
+        .map(|kv| (kv.key, kv.value))
+        .collect::<HashMap<_, _>>();
+    check_provider_options(&options, &RERANK_PROVIDER_OPTIONS)?;
+
+    Ok(RerankRequest {
+        model: model_or_default(config.model)?,
+        query,
+        documents,
+        top_n: config.top_n,
+        max_tokens_per_doc: options
+            .get("max_tokens_per_doc")
+            .and_then(|max_s| max_s.parse::<u32>().ok()),
+    })
+}
+
+/// Converts the rerank response, making sure the results are sorted by descending relevance.
+/// The `meta` object of the response, containing the billed search units, is reported as
+/// provider metadata.
+pub fn process_rerank_response(response: RerankResponse, model: String) -> RerankResult {
+    let mut results: Vec<RerankScore> = response
+        .results
+        .into_iter()
+        .map(|result| RerankScore {
+            index: result.index,
+            relevance_score: result.relevance_score,
+        })
+        .collect();
+    results.sort_by(|a, b| b.relevance_score.total_cmp(&a.relevance_score));
+
+    RerankResult {
+        results,
+        model: Some(model),
+        provider_metadata_json: response.meta.map(|meta| meta.to_string()),
+    }
+}
+
+pub fn tool_results_to_messages(
+    tool_results: Vec<(ToolCall, ToolResult)>,
+) -> Vec<crate::client::Message> {
+    let mut messages = Vec::new();
+    for (tool_call, tool_result) in tool_results {
+        messages.push(crate::client::Message::Assistant {
+            content: None,
+            tool_calls: Some(vec![crate::client::ToolCall {
+                id: tool_call.id.clone(),
+                typ: "function".to_string(),
+                function: crate::client::FunctionCall {
+                    name: tool_call.name,
+                    arguments: tool_call.arguments_json,
+                },
+            }]),
+            tool_plan: None,
+        });
+        let data = match tool_result {
+            ToolResult::Success(success) => success.result_json,
+            ToolResult::Error(failure) => failure.error_message,
+        };
+        messages.push(crate::client::Message::Tool {
+            tool_call_id: tool_call.id,
+            content: vec![crate::client::ToolContent::Document {
+                document: crate::client::ToolDocument { data },
+            }],
+        });
+    }
+    messages
+}
+
+pub fn convert_tool_call(tool_call: &crate::client::ToolCall) -> ToolCall {
+    ToolCall {
+        id: tool_call.id.clone(),
+        name: tool_call.function.name.clone(),
+        arguments_json: tool_call.function.arguments.clone(),
+    }
+}
+
+pub fn convert_finish_reason(value: crate::client::FinishReason) -> FinishReason {
+    match value {
+        crate::client::FinishReason::Complete => FinishReason::Stop,
+        crate::client::FinishReason::StopSequence => FinishReason::Stop,
+        crate::client::FinishReason::MaxTokens => FinishReason::Length,
+        crate::client::FinishReason::ToolCall => FinishReason::ToolCalls,
+        crate::client::FinishReason::Error => FinishReason::Error,
+        crate::client::FinishReason::Timeout => FinishReason::Error,
+    }
+}
+
+/// Converts the token counts of the response, falling back to the billed units if the actual
+/// token counts are not reported
+pub fn convert_usage(value: &crate::client::Usage) -> Usage {
+    let tokens = value.tokens.as_ref().or(value.billed_units.as_ref());
+    let input_tokens = tokens
+        .and_then(|tokens| tokens.input_tokens)
+        .map(|t| t as u32);
+    let output_tokens = tokens
+        .and_then(|tokens| tokens.output_tokens)
+        .map(|t| t as u32);
+    with_total_tokens(Usage {
+        input_tokens,
+        output_tokens,
+        total_tokens: None,
+    })
+}
+
+/// Reports the citations of the response in its provider metadata, as
+/// `{"citations": [...]}`. Returns `None` if the response has no citations.
+pub fn citations_to_provider_metadata(citations: &[Citation]) -> Option<String> {
+    if citations.is_empty() {
+        None
+    } else {
+        Some(serde_json::json!({ "citations": citations }).to_string())
+    }
+}
+
+/// Cohere only supports forcing or disabling tool use, other tool choices such as `auto` or the
+/// name of a tool leave the decision to the model
+fn convert_tool_choice(tool_choice: String) -> Option<ToolChoice> {
+    match tool_choice.as_str() {
+        "required" | "any" => Some(ToolChoice::Required),
+        "none" => Some(ToolChoice::None),
+        _ => None,
+    }
+}
+
+fn convert_content_parts(contents: Vec<ContentPart>) -> Vec<crate::client::ContentPart> {
+    let mut result = Vec::new();
+    for content in contents {
+        match content {
+            ContentPart::Text(text) => result.push(crate::client::ContentPart::Text { text }),
+            // Cohere does not take an image detail, so it is dropped
+            ContentPart::Image(image_reference) => match image_reference {
+                ImageReference::Url(image_url) => {
+                    result.push(crate::client::ContentPart::ImageUrl {
+                        image_url: crate::client::ImageUrl { url: image_url.url },
+                    })
+                }
+                ImageReference::Inline(image_source) => {
+                    let base64_data = general_purpose::STANDARD.encode(&image_source.data);
+                    result.push(crate::client::ContentPart::ImageUrl {
+                        image_url: crate::client::ImageUrl {
+                            url: format!("data:{};base64,{}", image_source.mime_type, base64_data),
+                        },
+                    });
+                }
+            },
+            // Tool results are sent in messages of their own by `messages_to_request`
+            ContentPart::ToolResult(_) => {}
+            // The reasoning of earlier responses is not sent back to the model
+            ContentPart::Reasoning(_) => {}
+            // Audio responses are sent back to the model as their transcript
+            ContentPart::Audio(audio) => result.extend(
+                audio
+                    .transcript
+                    .map(|text| crate::client::ContentPart::Text { text }),
+            ),
+            // Refusals of earlier responses are sent back to the model as text
+            ContentPart::Refusal(text) => result.push(crate::client::ContentPart::Text { text }),
+        }
+    }
+    result
+}
+
+fn tool_definition_to_tool(tool: ToolDefinition) -> Result<crate::client::Tool, Error> {
+    match serde_json::from_str(&tool.parameters_schema) {
+        Ok(value) => Ok(crate::client::Tool::Function {
+            function: crate::client::Function {
+                name: tool.name,
+                description: tool.description,
+                parameters: value,
+            },
+        }),
+        Err(error) => Err(Error {
+            code: ErrorCode::InternalError,
+            message: format!("Failed to parse tool parameters for {}: {error}", tool.name),
+            provider_error_json: None,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::{ChatResponse, RerankResponse};
+    use crate::conversions::{messages_to_request, process_rerank_response, process_response};
+    use golem_llm::golem::llm::llm::{
+        ChatEvent, Config, ContentPart, FinishReason, Message, RerankScore, Role, ToolCall,
+    };
+
+    fn config(tool_choice: Option<&str>) -> Config {
+        Config {
+            model: "command-a-03-2025".to_string(),
+            temperature: None,
+            max_tokens: None,
+            stop_sequences: None,
+            tools: vec![],
+            tool_choice: tool_choice.map(|tool_choice| tool_choice.to_string()),
+            provider_options: vec![],
+            extra_headers: vec![],
+            service_tier: None,
+            metadata: vec![],
+            reasoning_effort: None,
+            store: None,
+            prompt_cache_key: None,
+            stream_options: None,
+            deadline_ms: None,
+            extra_body: None,
+        }
+    }
+
+    #[test]
+    fn messages_are_converted_to_v2_chat_request() {
+        let request = messages_to_request(
+            vec![
+                Message {
+                    role: Role::System,
+                    name: None,
+                    content: vec![ContentPart::Text("Be brief".to_string())],
+                },
+                Message {
+                    role: Role::User,
+                    name: None,
+                    content: vec![ContentPart::Text("Hi".to_string())],
+                },
+            ],
+            config(Some("required")),
+        )
+        .unwrap();
+
+        assert_eq!(
+            serde_json::to_value(&request).unwrap(),
+            serde_json::json!({
+                "model": "command-a-03-2025",
+                "messages": [
+                    {"role": "system", "content": [{"type": "text", "text": "Be brief"}]},
+                    {"role": "user", "content": [{"type": "text", "text": "Hi"}]}
+                ],
+                "stream": false,
+                "tool_choice": "REQUIRED"
+            })
+        );
+    }
+
+    #[test]
+    fn citations_are_reported_in_provider_metadata() {
+        let response: ChatResponse = serde_json::from_str(
+            r#"{
+                "id": "c14c80c3-18eb-4519-9460-6c92edd8cfb4",
+                "finish_reason": "COMPLETE",
+                "message": {
+                    "role": "assistant",
+                    "content": [{"type": "text", "text": "Golem is durable."}],
+                    "citations": [{
+                        "start": 9,
+                        "end": 16,
+                        "text": "durable",
+                        "sources": [{"type": "document", "id": "doc-0", "document": {"title": "Golem"}}]
+                    }]
+                },
+                "usage": {
+                    "billed_units": {"input_tokens": 5, "output_tokens": 4},
+                    "tokens": {"input_tokens": 71, "output_tokens": 4}
+                }
+            }"#,
+        )
+        .unwrap();
+
+        let ChatEvent::Message(message) = process_response(response) else {
+            panic!("Expected a message");
+        };
+        assert_eq!(
+            message.content,
+            vec![ContentPart::Text("Golem is durable.".to_string())]
+        );
+        assert_eq!(message.metadata.finish_reason, Some(FinishReason::Stop));
+        let usage = message.metadata.usage.unwrap();
+        assert_eq!(usage.input_tokens, Some(71));
+        assert_eq!(usage.total_tokens, Some(75));
+
+        let provider_metadata: serde_json::Value =
+            serde_json::from_str(&message.metadata.provider_metadata_json.unwrap()).unwrap();
+        assert_eq!(provider_metadata["citations"][0]["text"], "durable");
+        assert_eq!(
+            provider_metadata["citations"][0]["sources"][0]["id"],
+            "doc-0"
+        );
+    }
+
+    #[test]
+    fn tool_calls_are_returned_as_tool_request() {
+        let response: ChatResponse = serde_json::from_str(
+            r#"{
+                "id": "5d2e3b5a",
+                "finish_reason": "TOOL_CALL",
+                "message": {
+                    "role": "assistant",
+                    "tool_plan": "I will look up the weather",
+                    "tool_calls": [{
+                        "id": "get_weather_0",
+                        "type": "function",
+                        "function": {"name": "get_weather", "arguments": "{\"city\":\"Paris\"}"}
+                    }]
+                }
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            process_response(response),
+            ChatEvent::ToolRequest(vec![ToolCall {
+                id: "get_weather_0".to_string(),
+                name: "get_weather".to_string(),
+                arguments_json: "{\"city\":\"Paris\"}".to_string(),
+            }])
+        );
+    }
+
+    #[test]
+    fn rerank_results_are_sorted_by_descending_relevance() {
+        let response: RerankResponse = serde_json::from_str(
+            r#"{
+                "id": "07734bd2-2473-4f07-94e1-0d9f0e6843cf",
+                "results": [
+                    {"index": 0, "relevance_score": 0.12},
+                    {"index": 3, "relevance_score": 0.99},
+                    {"index": 1, "relevance_score": 0.54}
+                ],
+                "meta": {"api_version": {"version": "2"}, "billed_units": {"search_units": 1}}
+            }"#,
+        )
+        .unwrap();
+
+        let result = process_rerank_response(response, "rerank-v3.5".to_string());
+
+        assert_eq!(
+            result.results,
+            vec![
+                RerankScore {
+                    index: 3,
+                    relevance_score: 0.99,
+                },
+                RerankScore {
+                    index: 1,
+                    relevance_score: 0.54,
+                },
+                RerankScore {
+                    index: 0,
+                    relevance_score: 0.12,
+                },
+            ]
+        );
+        let provider_metadata: serde_json::Value =
+            serde_json::from_str(&result.provider_metadata_json.unwrap()).unwrap();
+        assert_eq!(provider_metadata["billed_units"]["search_units"], 1);
+    }
+}
@@ -0,0 +1,530 @@
+mod client;
+mod conversions;
+
+use crate::client::{ChatRequest, Citation, CohereApi, StreamFunctionCall, StreamMessageDelta};
+use crate::conversions::{
+    check_streaming, citations_to_provider_metadata, convert_finish_reason, convert_usage,
+    messages_to_request, process_rerank_response, process_response, rerank_request,
+    tool_results_to_messages,
+};
+use golem_llm::batch::send_batch;
+use golem_llm::chat_stream::{request_deadline, LlmChatStream, LlmChatStreamState};
+use golem_llm::config::with_config_key;
+use golem_llm::conversions::{
+    check_messages_not_empty, non_empty_tool_call_id, normalize_tool_call_ids,
+};
+use golem_llm::dry_run::{send_or_dry_run, stream_or_dry_run};
+use golem_llm::durability::{DurableLLM, ExtendedGuest};
+use golem_llm::error::unsupported;
+use golem_llm::event_source::EventSource;
+use golem_llm::extra_body::extra_body;
+use golem_llm::golem::llm::llm::{
+    ChatEvent, ChatStream, Config, ContentPart, EmbeddingConfig, EmbeddingResult, Error, ErrorCode,
+    Guest, ImageGenConfig, ImageGenResult, Message, ProviderCapabilities, ProviderHealth,
+    RerankConfig, RerankResult, ResponseMetadata, StreamDelta, StreamEvent, StreamStart, ToolCall,
+    ToolResult, Usage,
+};
+use golem_llm::headers::extra_headers;
+use golem_llm::rate_limit::with_rate_limit;
+use golem_llm::tool_call_fragments::{
+    complete_tool_call_delta, is_streaming_tool_call_fragments, tool_call_fragment_delta,
+};
+use golem_llm::LOGGING_STATE;
+use golem_rust::wasm_rpc::Pollable;
+use log::trace;
+use reqwest::header::HeaderMap;
+use std::cell::{Ref, RefCell, RefMut};
+
+struct CohereChatStream {
+    stream: RefCell<Option<EventSource>>,
+    failure: Option<Error>,
+    finished: RefCell<bool>,
+    tool_call: RefCell<Option<ToolCall>>,
+    citations: RefCell<Vec<Citation>>,
+    /// Whether the fragments of the tool calls' arguments are sent as they are received
+    stream_tool_call_fragments: bool,
+}
+
+impl CohereChatStream {
+    pub fn new(stream: EventSource) -> LlmChatStream<Self> {
+        LlmChatStream::new(CohereChatStream {
+            stream: RefCell::new(Some(stream)),
+            failure: None,
+            finished: RefCell::new(false),
+            tool_call: RefCell::new(None),
+            citations: RefCell::new(Vec::new()),
+            stream_tool_call_fragments: is_streaming_tool_call_fragments(),
+        })
+    }
+
+    pub fn failed(error: Error) -> LlmChatStream<Self> {
+        LlmChatStream::new(CohereChatStream {
+            stream: RefCell::new(None),
+            failure: Some(error),
+            finished: RefCell::new(false),
+            tool_call: RefCell::new(None),
+            citations: RefCell::new(Vec::new()),
+            stream_tool_call_fragments: is_streaming_tool_call_fragments(),
+        })
+    }
+
+    pub fn dry_run(events: Vec<StreamEvent>) -> LlmChatStream<Self> {
+        LlmChatStream::from_events(
+            CohereChatStream {
+                stream: RefCell::new(None),
+                failure: None,
+                finished: RefCell::new(false),
+                tool_call: RefCell::new(None),
+                citations: RefCell::new(Vec::new()),
+                stream_tool_call_fragments: is_streaming_tool_call_fragments(),
+            },
+            events,
+        )
+    }
+}
+
+impl LlmChatStreamState for CohereChatStream {
+    fn failure(&self) -> &Option<Error> {
+        &self.failure
+    }
+
+    fn is_finished(&self) -> bool {
+        *self.finished.borrow()
+    }
+
+    fn set_finished(&self) {
+        *self.finished.borrow_mut() = true;
+    }
+
+    fn stream(&self) -> Ref<Option<EventSource>> {
+        self.stream.borrow()
+    }
+
+    fn stream_mut(&self) -> RefMut<Option<EventSource>> {
+        self.stream.borrow_mut()
+    }
+
+    fn decode_message(&self, _event: &str, raw: &str) -> Result<Option<StreamEvent>, String> {
+        trace!("Received raw stream event: {raw}");
+        let json: serde_json::Value = serde_json::from_str(raw)
+            .map_err(|err| format!("Failed to deserialize stream event: {err}"))?;
+
+        let typ = json
+            .as_object()
+            .and_then(|obj| obj.get("type"))
+            .and_then(|v| v.as_str());
+        let delta = || {
+            json.as_object()
+                .and_then(|obj| obj.get("delta"))
+                .ok_or_else(|| {
+                    "Unexpected stream event format, does not have 'delta' field".to_string()
+                })
+                .and_then(|delta| {
+                    serde_json::from_value::<StreamMessageDelta>(delta.clone())
+                        .map_err(|err| format!("Failed to parse stream event: {err}"))
+                })
+        };
+
+        match typ {
+            Some("message-start") => {
+                let id = json
+                    .as_object()
+                    .and_then(|obj| obj.get("id"))
+                    .and_then(|v| v.as_str())
+                    .unwrap_or_default()
+                    .to_string();
+                Ok(Some(StreamEvent::Start(StreamStart {
+                    id,
+                    model: None,
+                    created: None,
+                })))
+            }
+            Some("content-delta") => {
+                let text = delta()?
+                    .message
+                    .and_then(|message| message.content)
+                    .and_then(|content| content.text);
+                Ok(text.map(|text| {
+                    StreamEvent::Delta(StreamDelta {
+                        content: Some(vec![ContentPart::Text(text)]),
+                        tool_calls: None,
+                        annotations: None,
+                        tool_call_fragments: None,
+                        running_usage: None,
+                    })
+                }))
+            }
+            Some("tool-call-start") => {
+                let tool_call = delta()?
+                    .message
+                    .and_then(|message| message.tool_calls)
+                    .ok_or_else(|| {
+                        "Unexpected stream event format, does not have 'tool_calls' field"
+                            .to_string()
+                    })?;
+                let function = tool_call.function.unwrap_or(StreamFunctionCall {
+                    name: None,
+                    arguments: None,
+                });
+                let tool_call = ToolCall {
+                    id: tool_call.id.unwrap_or_default(),
+                    name: function.name.unwrap_or_default(),
+                    arguments_json: function.arguments.unwrap_or_default(),
+                };
+                let fragment = (self.stream_tool_call_fragments
+                    && !tool_call.arguments_json.is_empty())
+                .then(|| {
+                    StreamEvent::Delta(tool_call_fragment_delta(
+                        &tool_call.id,
+                        &tool_call.name,
+                        tool_call.arguments_json.clone(),
+                    ))
+                });
+                *self.tool_call.borrow_mut() = Some(tool_call);
+                Ok(fragment)
+            }
+            Some("tool-call-delta") => {
+                let arguments = delta()?
+                    .message
+                    .and_then(|message| message.tool_calls)
+                    .and_then(|tool_call| tool_call.function)
+                    .and_then(|function| function.arguments);
+                match (self.tool_call.borrow_mut().as_mut(), arguments) {
+                    (Some(tool_call), Some(arguments)) => {
+                        tool_call.arguments_json.push_str(&arguments);
+                        if self.stream_tool_call_fragments && !arguments.is_empty() {
+                            Ok(Some(StreamEvent::Delta(tool_call_fragment_delta(
+                                &tool_call.id,
+                                &tool_call.name,
+                                arguments,
+                            ))))
+                        } else {
+                            Ok(None)
+                        }
+                    }
+                    _ => Ok(None),
+                }
+            }
+            Some("tool-call-end") => Ok(self.tool_call.borrow_mut().take().map(|tool_call| {
+                StreamEvent::Delta(complete_tool_call_delta(
+                    tool_call,
+                    self.stream_tool_call_fragments,
+                ))
+            })),
+            Some("citation-start") => {
+                if let Some(citation) = delta()?.message.and_then(|message| message.citations) {
+                    self.citations.borrow_mut().push(citation);
+                }
+                Ok(None)
+            }
+            Some("message-end") => {
+                let delta = delta()?;
+                Ok(Some(StreamEvent::Finish(ResponseMetadata {
+                    finish_reason: delta.finish_reason.map(convert_finish_reason),
+                    usage: delta.usage.as_ref().map(convert_usage),
+                    provider_id: None,
+                    model: None,
+                    timestamp: None,
+                    provider_metadata_json: citations_to_provider_metadata(
+                        &self.citations.borrow(),
+                    ),
+                    tokens_per_second: None,
+                    stop_sequence: None,
+                })))
+            }
+            Some("error") => Ok(Some(StreamEvent::Error(Error {
+                code: ErrorCode::InternalError,
+                message: json
+                    .as_object()
+                    .and_then(|obj| obj.get("message"))
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("Stream failed")
+                    .to_string(),
+                provider_error_json: Some(raw.to_string()),
+            }))),
+            Some(_) => Ok(None),
+            None => Err("Unexpected stream event format, does not have 'type' field".to_string()),
+        }
+    }
+}
+
+struct CohereComponent;
+
+impl CohereComponent {
+    const ENV_VAR_NAME: &'static str = "COHERE_API_KEY";
+
+    fn request(client: CohereApi, request: ChatRequest) -> ChatEvent {
+        send_or_dry_run(request, |request| match client.send_messages(request) {
+            Ok(response) => with_rate_limit(process_response(response.body), response.rate_limit),
+            Err(err) => ChatEvent::Error(err),
+        })
+    }
+
+    fn streaming_request(
+        client: CohereApi,
+        mut request: ChatRequest,
+    ) -> LlmChatStream<CohereChatStream> {
+        if let Err(err) = check_streaming(&request.model) {
+            return CohereChatStream::failed(err);
+        }
+        request.stream = true;
+        stream_or_dry_run(request, CohereChatStream::dry_run, |request| {
+            match client.stream_send_messages(request) {
+                Ok(stream) => CohereChatStream::new(stream),
+                Err(err) => CohereChatStream::failed(err),
+            }
+        })
+    }
+}
+
+impl Guest for CohereComponent {
+    type ChatStream = LlmChatStream<CohereChatStream>;
+
+    fn send(messages: Vec<Message>, config: Config) -> ChatEvent {
+        LOGGING_STATE.with_borrow_mut(|state| state.init());
+
+        if let Err(err) = check_messages_not_empty(&messages, &[]) {
+            return ChatEvent::Error(err);
+        }
+
+        let extra_headers = match extra_headers(&config) {
+            Ok(extra_headers) => extra_headers,
+            Err(err) => return ChatEvent::Error(err),
+        };
+        let extra_body = match extra_body(&config) {
+            Ok(extra_body) => extra_body,
+            Err(err) => return ChatEvent::Error(err),
+        };
+
+        with_config_key(Self::ENV_VAR_NAME, ChatEvent::Error, |cohere_api_key| {
+            let client = match CohereApi::new(cohere_api_key, extra_headers)
+                .map(|client| client.with_extra_body(extra_body))
+            {
+                Ok(client) => client,
+                Err(err) => return ChatEvent::Error(err),
+            };
+
+            match messages_to_request(messages, config) {
+                Ok(request) => Self::request(client, request),
+                Err(err) => ChatEvent::Error(err),
+            }
+        })
+    }
+
+    fn continue_(
+        messages: Vec<Message>,
+        tool_results: Vec<(ToolCall, ToolResult)>,
+        config: Config,
+    ) -> ChatEvent {
+        LOGGING_STATE.with_borrow_mut(|state| state.init());
+
+        if let Err(err) = check_messages_not_empty(&messages, &tool_results) {
+            return ChatEvent::Error(err);
+        }
+
+        let extra_headers = match extra_headers(&config) {
+            Ok(extra_headers) => extra_headers,
+            Err(err) => return ChatEvent::Error(err),
+        };
+        let extra_body = match extra_body(&config) {
+            Ok(extra_body) => extra_body,
+            Err(err) => return ChatEvent::Error(err),
+        };
+
+        let tool_results = match normalize_tool_call_ids(tool_results, non_empty_tool_call_id) {
+            Ok(tool_results) => tool_results,
+            Err(err) => return ChatEvent::Error(err),
+        };
+
+        with_config_key(Self::ENV_VAR_NAME, ChatEvent::Error, |cohere_api_key| {
+            let client = match CohereApi::new(cohere_api_key, extra_headers)
+                .map(|client| client.with_extra_body(extra_body))
+            {
+                Ok(client) => client,
+                Err(err) => return ChatEvent::Error(err),
+            };
+
+            match messages_to_request(messages, config) {
+                Ok(mut request) => {
+                    request
+                        .messages
+                        .extend(tool_results_to_messages(tool_results));
+                    Self::request(client, request)
+                }
+                Err(err) => ChatEvent::Error(err),
+            }
+        })
+    }
+
+    fn send_batch(requests: Vec<(Vec<Message>, Config)>, abort_on_error: bool) -> Vec<ChatEvent> {
+        send_batch(requests, abort_on_error, Self::send)
+    }
+
+    fn stream(messages: Vec<Message>, config: Config) -> ChatStream {
+        ChatStream::new(Self::unwrapped_stream(messages, config))
+    }
+
+    fn continue_stream(
+        messages: Vec<Message>,
+        tool_results: Vec<(ToolCall, ToolResult)>,
+        config: Config,
+    ) -> ChatStream {
+        ChatStream::new(Self::unwrapped_continue_stream(
+            messages,
+            tool_results,
+            config,
+        ))
+    }
+
+    fn send_raw(body_json: String, config: Config) -> Result<String, Error> {
+        LOGGING_STATE.with_borrow_mut(|state| state.init());
+
+        let extra_headers = extra_headers(&config)?;
+        with_config_key(Self::ENV_VAR_NAME, Err, |cohere_api_key| {
+            CohereApi::new(cohere_api_key, extra_headers)?.send_raw(body_json)
+        })
+    }
+
+    fn rerank(
+        query: String,
+        documents: Vec<String>,
+        config: RerankConfig,
+    ) -> Result<RerankResult, Error> {
+        LOGGING_STATE.with_borrow_mut(|state| state.init());
+
+        with_config_key(Self::ENV_VAR_NAME, Err, |cohere_api_key| {
+            let request = rerank_request(query, documents, config)?;
+            let model = request.model.clone();
+            CohereApi::new(cohere_api_key, HeaderMap::new())?
+                .rerank(request)
+                .map(|response| process_rerank_response(response, model))
+        })
+    }
+
+    fn embed(
+        _inputs: Vec<ContentPart>,
+        _config: EmbeddingConfig,
+    ) -> Result<EmbeddingResult, Error> {
+        Err(unsupported("embed"))
+    }
+
+    fn ping() -> Result<ProviderHealth, Error> {
+        LOGGING_STATE.with_borrow_mut(|state| state.init());
+
+        with_config_key(Self::ENV_VAR_NAME, Err, |cohere_api_key| {
+            CohereApi::new(cohere_api_key, HeaderMap::new())?.ping()
+        })
+    }
+
+    fn capabilities() -> ProviderCapabilities {
+        ProviderCapabilities {
+            provider: "cohere".to_string(),
+            streaming: true,
+            tools: true,
+            vision: true,
+            embeddings: false,
+            json_schema: false,
+        }
+    }
+
+    fn warm(_config: Config) -> Result<(), Error> {
+        Err(unsupported("warm"))
+    }
+
+    fn generate_image(_prompt: String, _config: ImageGenConfig) -> Result<ImageGenResult, Error> {
+        Err(unsupported("generate-image"))
+    }
+}
+
+impl ExtendedGuest for CohereComponent {
+    fn unwrapped_stream(messages: Vec<Message>, config: Config) -> LlmChatStream<CohereChatStream> {
+        Self::unwrapped_continue_stream(messages, vec![], config)
+    }
+
+    fn unwrapped_continue_stream(
+        messages: Vec<Message>,
+        tool_results: Vec<(ToolCall, ToolResult)>,
+        config: Config,
+    ) -> LlmChatStream<CohereChatStream> {
+        LOGGING_STATE.with_borrow_mut(|state| state.init());
+
+        if let Err(err) = check_messages_not_empty(&messages, &tool_results) {
+            return CohereChatStream::failed(err);
+        }
+
+        let extra_headers = match extra_headers(&config) {
+            Ok(extra_headers) => extra_headers,
+            Err(err) => return CohereChatStream::failed(err),
+        };
+        let extra_body = match extra_body(&config) {
+            Ok(extra_body) => extra_body,
+            Err(err) => return CohereChatStream::failed(err),
+        };
+        let deadline = request_deadline(&config);
+
+        let tool_results = match normalize_tool_call_ids(tool_results, non_empty_tool_call_id) {
+            Ok(tool_results) => tool_results,
+            Err(err) => return CohereChatStream::failed(err),
+        };
+
+        with_config_key(
+            Self::ENV_VAR_NAME,
+            CohereChatStream::failed,
+            |cohere_api_key| {
+                let client = match CohereApi::new(cohere_api_key, extra_headers)
+                    .map(|client| client.with_extra_body(extra_body))
+                {
+                    Ok(client) => client,
+                    Err(err) => return CohereChatStream::failed(err),
+                };
+
+                match messages_to_request(messages, config) {
+                    Ok(mut request) => {
+                        request
+                            .messages
+                            .extend(tool_results_to_messages(tool_results));
+                        Self::streaming_request(client, request).with_deadline(deadline)
+                    }
+                    Err(err) => CohereChatStream::failed(err),
+                }
+            },
+        )
+    }
+
+    fn subscribe(stream: &Self::ChatStream) -> Pollable {
+        stream.subscribe()
+    }
+
+    fn subscribe_deadline(stream: &Self::ChatStream) -> Option<Pollable> {
+        stream.subscribe_deadline()
+    }
+
+    fn usage_so_far(stream: &Self::ChatStream) -> Option<Usage> {
+        stream.usage_so_far()
+    }
+}
+
+type DurableCohereComponent = DurableLLM<CohereComponent>;
+
+golem_llm::export_llm!(DurableCohereComponent with_types_in golem_llm);
+
+#[cfg(test)]
+mod tests {
+    use crate::CohereComponent;
+    use golem_llm::golem::llm::llm::{Guest, ProviderCapabilities};
+
+    #[test]
+    fn capabilities_describe_the_provider() {
+        assert_eq!(
+            CohereComponent::capabilities(),
+            ProviderCapabilities {
+                provider: "cohere".to_string(),
+                streaming: true,
+                tools: true,
+                vision: true,
+                embeddings: false,
+                json_schema: false,
+            }
+        );
+    }
+}
@@ -0,0 +1,44 @@
+use golem_llm::chat_completions::client::ChatCompletionsEndpoint;
+use golem_llm::config::config_value;
+use reqwest::RequestBuilder;
+
+/// Environment variable overriding the base URL of the DeepSeek API
+const BASE_URL_ENV_VAR_NAME: &str = "DEEPSEEK_BASE_URL";
+
+const DEFAULT_BASE_URL: &str = "https://api.deepseek.com";
+
+/// DeepSeek's OpenAI-compatible Chat Completions API, authenticated with a bearer API key
+pub struct DeepSeekApi {
+    api_key: String,
+    base_url: String,
+}
+
+impl DeepSeekApi {
+    pub fn new(api_key: String) -> Self {
+        let base_url = config_value(BASE_URL_ENV_VAR_NAME).unwrap_or(DEFAULT_BASE_URL.to_string());
+        Self { api_key, base_url }
+    }
+}
+
+impl ChatCompletionsEndpoint for DeepSeekApi {
+    const API_NAME: &'static str = "DeepSeek API";
+
+    fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    fn completions_url(&self, _model: &str) -> String {
+        format!("{}/chat/completions", self.base_url)
+    }
+
+    fn models_url(&self) -> String {
+        format!("{}/models", self.base_url)
+    }
+
+    fn authenticate(&self, request: RequestBuilder) -> RequestBuilder {
+        request.bearer_auth(&self.api_key)
+    }
+}
+
+/// The Chat Completions API client for creating model responses.
+pub type CompletionsApi = golem_llm::chat_completions::client::CompletionsApi<DeepSeekApi>;
@@ -0,0 +1,217 @@
+use golem_llm::capabilities::ModelCapabilities;
+use golem_llm::chat_completions::conversions::{ChatCompletionsProvider, MaxTokensField};
+use golem_llm::conversions::TEXT_JOIN_OPTION;
+
+/// The settings of DeepSeek's Chat Completions API, which only accepts text content and the older
+/// `max_tokens` field
+pub const PROVIDER: ChatCompletionsProvider = ChatCompletionsProvider {
+    provider_options: &PROVIDER_OPTIONS,
+    temperature_range: 0.0..=2.0,
+    model_capabilities: &MODEL_CAPABILITIES,
+    max_tokens_field: MaxTokensField::MaxTokens,
+    text_only: true,
+    tool_messages_answer_tool_calls: true,
+};
+
+/// Provider options read by the DeepSeek conversions, any other key is reported as unknown
+const PROVIDER_OPTIONS: [&str; 4] = [
+    TEXT_JOIN_OPTION,
+    "frequency_penalty",
+    "presence_penalty",
+    "top_p",
+];
+
+/// Capabilities of the known DeepSeek models, requests to other models are not validated
+const MODEL_CAPABILITIES: [(&str, ModelCapabilities); 2] = [
+    ("deepseek-chat", ModelCapabilities::TEXT_ONLY),
+    ("deepseek-reasoner", ModelCapabilities::TEXT_ONLY),
+];
+
+#[cfg(test)]
+mod tests {
+    use crate::conversions::PROVIDER;
+    use golem_llm::chat_completions::client::CompletionsResponse;
+    use golem_llm::chat_completions::conversions::{
+        messages_to_request, process_response, to_streaming_request,
+    };
+    use golem_llm::golem::llm::llm::{
+        ChatEvent, Config, ContentPart, FinishReason, Message, Role, ToolCall,
+    };
+
+    fn config() -> Config {
+        Config {
+            model: "deepseek-reasoner".to_string(),
+            temperature: None,
+            max_tokens: None,
+            stop_sequences: None,
+            tools: vec![],
+            tool_choice: None,
+            provider_options: vec![],
+            extra_headers: vec![],
+            service_tier: None,
+            metadata: vec![],
+            reasoning_effort: None,
+            store: None,
+            prompt_cache_key: None,
+            stream_options: None,
+            deadline_ms: None,
+            extra_body: None,
+        }
+    }
+
+    fn response(message: serde_json::Value, finish_reason: &str) -> CompletionsResponse {
+        serde_json::from_value(serde_json::json!({
+            "id": "930c60df-bf64-41c9-a88e-3ec75f81e00e",
+            "object": "chat.completion",
+            "created": 1740000000,
+            "model": "deepseek-reasoner",
+            "choices": [{
+                "index": 0,
+                "message": message,
+                "logprobs": null,
+                "finish_reason": finish_reason
+            }],
+            "usage": {
+                "prompt_tokens": 12,
+                "completion_tokens": 40,
+                "total_tokens": 52,
+                "prompt_cache_hit_tokens": 0,
+                "prompt_cache_miss_tokens": 12,
+                "completion_tokens_details": {"reasoning_tokens": 32}
+            },
+            "system_fingerprint": "fp_7e73fd9a08"
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn reasoning_content_precedes_the_text() {
+        let event = process_response(response(
+            serde_json::json!({
+                "role": "assistant",
+                "content": "9.11 is smaller than 9.8",
+                "reasoning_content": "Comparing the decimals, 0.11 is less than 0.8"
+            }),
+            "stop",
+        ));
+
+        let ChatEvent::Message(response) = event else {
+            panic!("Expected a message, got {event:?}");
+        };
+        assert_eq!(
+            response.content,
+            vec![
+                ContentPart::Reasoning("Comparing the decimals, 0.11 is less than 0.8".to_string()),
+                ContentPart::Text("9.11 is smaller than 9.8".to_string()),
+            ]
+        );
+        assert_eq!(response.metadata.finish_reason, Some(FinishReason::Stop));
+        assert_eq!(
+            response
+                .metadata
+                .usage
+                .and_then(|usage| usage.output_tokens),
+            Some(40)
+        );
+    }
+
+    #[test]
+    fn tool_calls_without_content_are_a_tool_request() {
+        let event = process_response(response(
+            serde_json::json!({
+                "role": "assistant",
+                "content": "",
+                "tool_calls": [{
+                    "index": 0,
+                    "id": "call_0_a4a2f1d3",
+                    "type": "function",
+                    "function": {"name": "get_weather", "arguments": "{\"city\":\"Hangzhou\"}"}
+                }]
+            }),
+            "tool_calls",
+        ));
+
+        assert_eq!(
+            event,
+            ChatEvent::ToolRequest(vec![ToolCall {
+                id: "call_0_a4a2f1d3".to_string(),
+                name: "get_weather".to_string(),
+                arguments_json: "{\"city\":\"Hangzhou\"}".to_string(),
+            }])
+        );
+    }
+
+    #[test]
+    fn reasoning_is_not_sent_back() {
+        let request = messages_to_request(
+            &PROVIDER,
+            vec![
+                Message {
+                    role: Role::User,
+                    name: None,
+                    content: vec![ContentPart::Text("Which is larger?".to_string())],
+                },
+                Message {
+                    role: Role::Assistant,
+                    name: None,
+                    content: vec![
+                        ContentPart::Reasoning("Comparing the decimals".to_string()),
+                        ContentPart::Text("9.8 is larger".to_string()),
+                    ],
+                },
+            ],
+            config(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            serde_json::to_value(to_streaming_request(request)).unwrap(),
+            serde_json::json!({
+                "messages": [
+                    {"role": "user", "content": "Which is larger?"},
+                    {"role": "assistant", "content": "9.8 is larger"}
+                ],
+                "model": "deepseek-reasoner",
+                "stream": true,
+                "stream_options": {"include_usage": true}
+            })
+        );
+    }
+
+    #[test]
+    fn tool_messages_and_max_tokens_follow_the_deepseek_api() {
+        let request = messages_to_request(
+            &PROVIDER,
+            vec![
+                Message {
+                    role: Role::User,
+                    name: None,
+                    content: vec![ContentPart::Text("What is the weather?".to_string())],
+                },
+                Message {
+                    role: Role::Tool,
+                    name: None,
+                    content: vec![ContentPart::Text("Sunny".to_string())],
+                },
+            ],
+            Config {
+                max_tokens: Some(100),
+                ..config()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            serde_json::to_value(request).unwrap(),
+            serde_json::json!({
+                "messages": [
+                    {"role": "user", "content": "What is the weather?"},
+                    {"role": "user", "content": "Sunny"}
+                ],
+                "model": "deepseek-reasoner",
+                "max_tokens": 100,
+                "stream": false
+            })
+        );
+    }
+}
@@ -0,0 +1,309 @@
+mod client;
+mod conversions;
+
+use crate::client::{CompletionsApi, DeepSeekApi};
+use crate::conversions::PROVIDER;
+use golem_llm::batch::send_batch;
+use golem_llm::chat_completions::client::CompletionsRequest;
+use golem_llm::chat_completions::conversions::{
+    check_streaming, messages_to_request, process_response, to_streaming_request,
+    tool_results_to_messages,
+};
+use golem_llm::chat_completions::stream::ChatCompletionsStream;
+use golem_llm::chat_stream::{request_deadline, LlmChatStream};
+use golem_llm::config::with_config_key;
+use golem_llm::conversions::{
+    check_messages_not_empty, non_empty_tool_call_id, normalize_tool_call_ids,
+};
+use golem_llm::dry_run::{send_or_dry_run, stream_or_dry_run};
+use golem_llm::durability::{DurableLLM, ExtendedGuest};
+use golem_llm::error::unsupported;
+use golem_llm::extra_body::extra_body;
+use golem_llm::golem::llm::llm::{
+    ChatEvent, ChatStream, Config, ContentPart, EmbeddingConfig, EmbeddingResult, Error, Guest,
+    ImageGenConfig, ImageGenResult, Message, ProviderCapabilities, ProviderHealth, RerankConfig,
+    RerankResult, ToolCall, ToolResult, Usage,
+};
+use golem_llm::headers::extra_headers;
+use golem_llm::rate_limit::with_rate_limit;
+use golem_llm::LOGGING_STATE;
+use golem_rust::wasm_rpc::Pollable;
+use reqwest::header::HeaderMap;
+
+struct DeepSeekComponent;
+
+impl DeepSeekComponent {
+    const ENV_VAR_NAME: &'static str = "DEEPSEEK_API_KEY";
+
+    fn request(client: CompletionsApi, request: CompletionsRequest) -> ChatEvent {
+        send_or_dry_run(request, |request| match client.send_messages(request) {
+            Ok(response) => with_rate_limit(process_response(response.body), response.rate_limit),
+            Err(err) => ChatEvent::Error(err),
+        })
+    }
+
+    fn streaming_request(
+        client: CompletionsApi,
+        request: CompletionsRequest,
+    ) -> LlmChatStream<ChatCompletionsStream> {
+        if let Err(err) = check_streaming(&PROVIDER, &request.model) {
+            return ChatCompletionsStream::failed(err);
+        }
+        stream_or_dry_run(
+            to_streaming_request(request),
+            ChatCompletionsStream::dry_run,
+            |request| match client.stream_send_messages(request) {
+                Ok(stream) => ChatCompletionsStream::new(stream),
+                Err(err) => ChatCompletionsStream::failed(err),
+            },
+        )
+    }
+}
+
+impl Guest for DeepSeekComponent {
+    type ChatStream = LlmChatStream<ChatCompletionsStream>;
+
+    fn send(messages: Vec<Message>, config: Config) -> ChatEvent {
+        LOGGING_STATE.with_borrow_mut(|state| state.init());
+
+        if let Err(err) = check_messages_not_empty(&messages, &[]) {
+            return ChatEvent::Error(err);
+        }
+
+        let extra_headers = match extra_headers(&config) {
+            Ok(extra_headers) => extra_headers,
+            Err(err) => return ChatEvent::Error(err),
+        };
+        let extra_body = match extra_body(&config) {
+            Ok(extra_body) => extra_body,
+            Err(err) => return ChatEvent::Error(err),
+        };
+
+        with_config_key(Self::ENV_VAR_NAME, ChatEvent::Error, |api_key| {
+            let client = match CompletionsApi::new(DeepSeekApi::new(api_key), extra_headers)
+                .map(|client| client.with_extra_body(extra_body))
+            {
+                Ok(client) => client,
+                Err(err) => return ChatEvent::Error(err),
+            };
+
+            match messages_to_request(&PROVIDER, messages, config) {
+                Ok(request) => Self::request(client, request),
+                Err(err) => ChatEvent::Error(err),
+            }
+        })
+    }
+
+    fn continue_(
+        messages: Vec<Message>,
+        tool_results: Vec<(ToolCall, ToolResult)>,
+        config: Config,
+    ) -> ChatEvent {
+        LOGGING_STATE.with_borrow_mut(|state| state.init());
+
+        if let Err(err) = check_messages_not_empty(&messages, &tool_results) {
+            return ChatEvent::Error(err);
+        }
+
+        let extra_headers = match extra_headers(&config) {
+            Ok(extra_headers) => extra_headers,
+            Err(err) => return ChatEvent::Error(err),
+        };
+        let extra_body = match extra_body(&config) {
+            Ok(extra_body) => extra_body,
+            Err(err) => return ChatEvent::Error(err),
+        };
+
+        let tool_results = match normalize_tool_call_ids(tool_results, non_empty_tool_call_id) {
+            Ok(tool_results) => tool_results,
+            Err(err) => return ChatEvent::Error(err),
+        };
+
+        with_config_key(Self::ENV_VAR_NAME, ChatEvent::Error, |api_key| {
+            let client = match CompletionsApi::new(DeepSeekApi::new(api_key), extra_headers)
+                .map(|client| client.with_extra_body(extra_body))
+            {
+                Ok(client) => client,
+                Err(err) => return ChatEvent::Error(err),
+            };
+
+            match messages_to_request(&PROVIDER, messages, config) {
+                Ok(mut request) => {
+                    request
+                        .messages
+                        .extend(tool_results_to_messages(tool_results));
+                    Self::request(client, request)
+                }
+                Err(err) => ChatEvent::Error(err),
+            }
+        })
+    }
+
+    fn send_batch(requests: Vec<(Vec<Message>, Config)>, abort_on_error: bool) -> Vec<ChatEvent> {
+        send_batch(requests, abort_on_error, Self::send)
+    }
+
+    fn stream(messages: Vec<Message>, config: Config) -> ChatStream {
+        ChatStream::new(Self::unwrapped_stream(messages, config))
+    }
+
+    fn continue_stream(
+        messages: Vec<Message>,
+        tool_results: Vec<(ToolCall, ToolResult)>,
+        config: Config,
+    ) -> ChatStream {
+        ChatStream::new(Self::unwrapped_continue_stream(
+            messages,
+            tool_results,
+            config,
+        ))
+    }
+
+    fn send_raw(body_json: String, config: Config) -> Result<String, Error> {
+        LOGGING_STATE.with_borrow_mut(|state| state.init());
+
+        let extra_headers = extra_headers(&config)?;
+        with_config_key(Self::ENV_VAR_NAME, Err, |api_key| {
+            CompletionsApi::new(DeepSeekApi::new(api_key), extra_headers)?
+                .send_raw(&config.model, body_json)
+        })
+    }
+
+    fn rerank(
+        _query: String,
+        _documents: Vec<String>,
+        _config: RerankConfig,
+    ) -> Result<RerankResult, Error> {
+        Err(unsupported("rerank"))
+    }
+
+    fn embed(
+        _inputs: Vec<ContentPart>,
+        _config: EmbeddingConfig,
+    ) -> Result<EmbeddingResult, Error> {
+        Err(unsupported("embed"))
+    }
+
+    fn ping() -> Result<ProviderHealth, Error> {
+        LOGGING_STATE.with_borrow_mut(|state| state.init());
+
+        with_config_key(Self::ENV_VAR_NAME, Err, |api_key| {
+            CompletionsApi::new(DeepSeekApi::new(api_key), HeaderMap::new())?.ping()
+        })
+    }
+
+    fn capabilities() -> ProviderCapabilities {
+        ProviderCapabilities {
+            provider: "deepseek".to_string(),
+            streaming: true,
+            tools: true,
+            vision: false,
+            embeddings: false,
+            json_schema: false,
+        }
+    }
+
+    fn warm(_config: Config) -> Result<(), Error> {
+        Err(unsupported("warm"))
+    }
+
+    fn generate_image(_prompt: String, _config: ImageGenConfig) -> Result<ImageGenResult, Error> {
+        Err(unsupported("generate-image"))
+    }
+}
+
+impl ExtendedGuest for DeepSeekComponent {
+    fn unwrapped_stream(
+        messages: Vec<Message>,
+        config: Config,
+    ) -> LlmChatStream<ChatCompletionsStream> {
+        Self::unwrapped_continue_stream(messages, vec![], config)
+    }
+
+    fn unwrapped_continue_stream(
+        messages: Vec<Message>,
+        tool_results: Vec<(ToolCall, ToolResult)>,
+        config: Config,
+    ) -> LlmChatStream<ChatCompletionsStream> {
+        LOGGING_STATE.with_borrow_mut(|state| state.init());
+
+        if let Err(err) = check_messages_not_empty(&messages, &tool_results) {
+            return ChatCompletionsStream::failed(err);
+        }
+
+        let extra_headers = match extra_headers(&config) {
+            Ok(extra_headers) => extra_headers,
+            Err(err) => return ChatCompletionsStream::failed(err),
+        };
+        let extra_body = match extra_body(&config) {
+            Ok(extra_body) => extra_body,
+            Err(err) => return ChatCompletionsStream::failed(err),
+        };
+        let deadline = request_deadline(&config);
+
+        let tool_results = match normalize_tool_call_ids(tool_results, non_empty_tool_call_id) {
+            Ok(tool_results) => tool_results,
+            Err(err) => return ChatCompletionsStream::failed(err),
+        };
+
+        with_config_key(
+            Self::ENV_VAR_NAME,
+            ChatCompletionsStream::failed,
+            |api_key| {
+                let client = match CompletionsApi::new(DeepSeekApi::new(api_key), extra_headers)
+                    .map(|client| client.with_extra_body(extra_body))
+                {
+                    Ok(client) => client,
+                    Err(err) => return ChatCompletionsStream::failed(err),
+                };
+
+                match messages_to_request(&PROVIDER, messages, config) {
+                    Ok(mut request) => {
+                        request
+                            .messages
+                            .extend(tool_results_to_messages(tool_results));
+                        Self::streaming_request(client, request).with_deadline(deadline)
+                    }
+                    Err(err) => ChatCompletionsStream::failed(err),
+                }
+            },
+        )
+    }
+
+    fn subscribe(stream: &Self::ChatStream) -> Pollable {
+        stream.subscribe()
+    }
+
+    fn subscribe_deadline(stream: &Self::ChatStream) -> Option<Pollable> {
+        stream.subscribe_deadline()
+    }
+
+    fn usage_so_far(stream: &Self::ChatStream) -> Option<Usage> {
+        stream.usage_so_far()
+    }
+}
+
+type DurableDeepSeekComponent = DurableLLM<DeepSeekComponent>;
+
+golem_llm::export_llm!(DurableDeepSeekComponent with_types_in golem_llm);
+
+#[cfg(test)]
+mod tests {
+    use crate::DeepSeekComponent;
+    use golem_llm::golem::llm::llm::{Guest, ProviderCapabilities};
+
+    #[test]
+    fn capabilities_describe_the_provider() {
+        assert_eq!(
+            DeepSeekComponent::capabilities(),
+            ProviderCapabilities {
+                provider: "deepseek".to_string(),
+                streaming: true,
+                tools: true,
+                vision: false,
+                embeddings: false,
+                json_schema: false,
+            }
+        );
+    }
+}
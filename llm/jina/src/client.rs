@@ -0,0 +1,137 @@
+use golem_llm::body::read_body;
+use golem_llm::error::{decode_success_body, error_code_from_response, from_reqwest_error};
+use golem_llm::golem::llm::llm::Error;
+use golem_llm::proxy::http_client;
+use log::trace;
+use reqwest::{Client, Method, Response};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fmt::Debug;
+
+const BASE_URL: &str = "https://api.jina.ai";
+
+/// The Jina AI API client for computing embeddings and reranking documents.
+pub struct JinaApi {
+    api_key: String,
+    client: Client,
+}
+
+impl JinaApi {
+    pub fn new(api_key: String) -> Result<Self, Error> {
+        let client = http_client(BASE_URL)?;
+        Ok(Self { api_key, client })
+    }
+
+    pub fn embed(&self, request: EmbeddingsRequest) -> Result<EmbeddingsResponse, Error> {
+        trace!("Sending embeddings request to Jina AI API: {request:?}");
+
+        let response: Response = self
+            .client
+            .request(Method::POST, format!("{BASE_URL}/v1/embeddings"))
+            .bearer_auth(self.api_key.clone())
+            .json(&request)
+            .send()
+            .map_err(|err| from_reqwest_error("Request failed", err))?;
+
+        parse_response(response)
+    }
+
+    pub fn rerank(&self, request: RerankRequest) -> Result<RerankResponse, Error> {
+        trace!("Sending rerank request to Jina AI API: {request:?}");
+
+        let response: Response = self
+            .client
+            .request(Method::POST, format!("{BASE_URL}/v1/rerank"))
+            .bearer_auth(self.api_key.clone())
+            .json(&request)
+            .send()
+            .map_err(|err| from_reqwest_error("Request failed", err))?;
+
+        parse_response(response)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmbeddingsRequest {
+    pub model: String,
+    pub input: Vec<EmbeddingInput>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub task: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dimensions: Option<u32>,
+}
+
+/// An input of the multimodal embedding models, which is either a text or an image given by its
+/// URL or its base64 encoded data
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum EmbeddingInput {
+    Text { text: String },
+    Image { image: String },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmbeddingsResponse {
+    pub model: Option<String>,
+    pub data: Vec<EmbeddingData>,
+    pub usage: Option<Usage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmbeddingData {
+    pub index: u32,
+    pub embedding: Vec<f32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Usage {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prompt_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total_tokens: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RerankRequest {
+    pub model: String,
+    pub query: String,
+    pub documents: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_n: Option<u32>,
+    pub return_documents: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RerankResponse {
+    pub model: Option<String>,
+    pub results: Vec<RerankResponseResult>,
+    pub usage: Option<Usage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RerankResponseResult {
+    pub index: u32,
+    pub relevance_score: f32,
+}
+
+fn parse_response<T: DeserializeOwned + Debug>(response: Response) -> Result<T, Error> {
+    let status = response.status();
+    if status.is_success() {
+        let raw_body = read_body(response, "Failed to receive response body")?;
+        let body = decode_success_body::<T>(raw_body)?;
+
+        trace!("Received response from Jina AI API: {body:?}");
+
+        Ok(body)
+    } else {
+        let error_body = read_body(response, "Failed to receive error response body")?;
+
+        trace!("Received {status} response from Jina AI API: {error_body:?}");
+
+        Err(Error {
+            code: error_code_from_response(status, &error_body),
+            message: format!("Request failed with {status}"),
+            provider_error_json: Some(serde_json::to_string(&error_body).unwrap()),
+        })
+    }
+}
@@ -0,0 +1,296 @@
+use crate::client::{
+    EmbeddingInput, EmbeddingsRequest, EmbeddingsResponse, RerankRequest, RerankResponse,
+};
+use base64::{engine::general_purpose, Engine as _};
+use golem_llm::config::model_or_default;
+use golem_llm::conversions::check_provider_options;
+use golem_llm::golem::llm::llm::{
+    ContentPart, Embedding, EmbeddingConfig, EmbeddingResult, Error, ErrorCode, ImageReference, Kv,
+    RerankConfig, RerankResult, RerankScore, Usage,
+};
+use std::collections::HashMap;
+
+/// Provider options read by the Jina embeddings conversion
+const EMBEDDING_PROVIDER_OPTIONS: [&str; 2] = ["task", "dimensions"];
+
+/// Provider options read by the Jina rerank conversion
+const RERANK_PROVIDER_OPTIONS: [&str; 0] = [];
+
+pub fn embeddings_request(
+    inputs: Vec<ContentPart>,
+    config: EmbeddingConfig,
+) -> Result<EmbeddingsRequest, Error> {
+    let options = provider_options(config.provider_options);
+    check_provider_options(&options, &EMBEDDING_PROVIDER_OPTIONS)?;
+
+    Ok(EmbeddingsRequest {
+        model: model_or_default(config.model)?,
+        input: inputs
+            .into_iter()
+            .map(content_part_to_input)
+            .collect::<Result<_, _>>()?,
+        task: options.get("task").cloned(),
+        dimensions: options
+            .get("dimensions")
+            .and_then(|dimensions_s| dimensions_s.parse::<u32>().ok()),
+    })
+}
+
+/// Images are sent by their URL, or as base64 encoded data when inline. Tool results, reasoning
+/// and audio cannot be embedded.
+fn content_part_to_input(part: ContentPart) -> Result<EmbeddingInput, Error> {
+    match part {
+        ContentPart::Text(text) => Ok(EmbeddingInput::Text { text }),
+        ContentPart::Image(ImageReference::Url(image_url)) => Ok(EmbeddingInput::Image {
+            image: image_url.url,
+        }),
+        ContentPart::Image(ImageReference::Inline(image_source)) => Ok(EmbeddingInput::Image {
+            image: general_purpose::STANDARD.encode(&image_source.data),
+        }),
+        ContentPart::ToolResult(_) => Err(Error {
+            code: ErrorCode::InvalidRequest,
+            message: "Tool results cannot be embedded".to_string(),
+            provider_error_json: None,
+        }),
+        ContentPart::Reasoning(_) => Err(Error {
+            code: ErrorCode::InvalidRequest,
+            message: "Reasoning cannot be embedded".to_string(),
+            provider_error_json: None,
+        }),
+        ContentPart::Audio(_) => Err(Error {
+            code: ErrorCode::InvalidRequest,
+            message: "Audio cannot be embedded".to_string(),
+            provider_error_json: None,
+        }),
+        ContentPart::Refusal(_) => Err(Error {
+            code: ErrorCode::InvalidRequest,
+            message: "Refusals cannot be embedded".to_string(),
+            provider_error_json: None,
+        }),
+    }
+}
+
+/// Converts the embeddings response, making sure the embeddings are in the order of the inputs
+pub fn process_embeddings_response(response: EmbeddingsResponse, model: String) -> EmbeddingResult {
+    let mut embeddings: Vec<Embedding> = response
+        .data
+        .into_iter()
+        .map(|data| Embedding {
+            index: data.index,
+            vector: data.embedding,
+        })
+        .collect();
+    embeddings.sort_by_key(|embedding| embedding.index);
+
+    EmbeddingResult {
+        embeddings,
+        usage: response.usage.map(|usage| Usage {
+            input_tokens: usage.prompt_tokens,
+            output_tokens: None,
+            total_tokens: usage.total_tokens,
+        }),
+        model: Some(response.model.unwrap_or(model)),
+        provider_metadata_json: None,
+    }
+}
+
+pub fn rerank_request(
+    query: String,
+    documents: Vec<String>,
+    config: RerankConfig,
+) -> Result<RerankRequest, Error> {
+    let options = provider_options(config.provider_options);
+    check_provider_options(&options, &RERANK_PROVIDER_OPTIONS)?;
+
+    Ok(RerankRequest {
+        model: model_or_default(config.model)?,
+        query,
+        documents,
+        top_n: config.top_n,
+        return_documents: false,
+    })
+}
+
+/// Converts the rerank response, making sure the results are sorted by descending relevance.
+/// The `usage` object of the response is reported as provider metadata.
+pub fn process_rerank_response(response: RerankResponse, model: String) -> RerankResult {
+    let mut results: Vec<RerankScore> = response
+        .results
+        .into_iter()
+        .map(|result| RerankScore {
+            index: result.index,
+            relevance_score: result.relevance_score,
+        })
+        .collect();
+    results.sort_by(|a, b| b.relevance_score.total_cmp(&a.relevance_score));
+
+    RerankResult {
+        results,
+        model: Some(response.model.unwrap_or(model)),
+        provider_metadata_json: response
+            .usage
+            .map(|usage| serde_json::json!({ "usage": usage }).to_string()),
+    }
+}
+
+fn provider_options(provider_options: Vec<Kv>) -> HashMap<String, String> {
+    provider_options
+        .into_iter()
+        .map(|kv| (kv.key, kv.value))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::{EmbeddingInput, EmbeddingsResponse, RerankResponse};
+    use crate::conversions::{
+        embeddings_request, process_embeddings_response, process_rerank_response,
+    };
+    use golem_llm::golem::llm::llm::{
+        ContentPart, EmbeddingConfig, ImageReference, ImageSource, ImageUrl, Kv, RerankScore, Usage,
+    };
+
+    #[test]
+    fn multimodal_inputs_are_converted() {
+        let request = embeddings_request(
+            vec![
+                ContentPart::Text("A red bicycle".to_string()),
+                ContentPart::Image(ImageReference::Url(ImageUrl {
+                    url: "https://example.com/bicycle.jpg".to_string(),
+                    detail: None,
+                })),
+                ContentPart::Image(ImageReference::Inline(ImageSource {
+                    data: vec![1, 2, 3],
+                    mime_type: "image/png".to_string(),
+                    detail: None,
+                })),
+            ],
+            EmbeddingConfig {
+                model: "jina-embeddings-v4".to_string(),
+                provider_options: vec![],
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            request.input,
+            vec![
+                EmbeddingInput::Text {
+                    text: "A red bicycle".to_string()
+                },
+                EmbeddingInput::Image {
+                    image: "https://example.com/bicycle.jpg".to_string()
+                },
+                EmbeddingInput::Image {
+                    image: "AQID".to_string()
+                },
+            ]
+        );
+        assert_eq!(
+            serde_json::to_value(&request).unwrap(),
+            serde_json::json!({
+                "model": "jina-embeddings-v4",
+                "input": [
+                    {"text": "A red bicycle"},
+                    {"image": "https://example.com/bicycle.jpg"},
+                    {"image": "AQID"}
+                ]
+            })
+        );
+    }
+
+    #[test]
+    fn task_and_dimensions_are_read_from_the_provider_options() {
+        let request = embeddings_request(
+            vec![ContentPart::Text("A red bicycle".to_string())],
+            EmbeddingConfig {
+                model: "jina-embeddings-v3".to_string(),
+                provider_options: vec![
+                    Kv {
+                        key: "task".to_string(),
+                        value: "retrieval.query".to_string(),
+                    },
+                    Kv {
+                        key: "dimensions".to_string(),
+                        value: "256".to_string(),
+                    },
+                ],
+            },
+        )
+        .unwrap();
+
+        assert_eq!(request.task, Some("retrieval.query".to_string()));
+        assert_eq!(request.dimensions, Some(256));
+    }
+
+    #[test]
+    fn embeddings_are_in_the_order_of_the_inputs() {
+        let response: EmbeddingsResponse = serde_json::from_str(
+            r#"{
+                "model": "jina-embeddings-v3",
+                "object": "list",
+                "usage": {"total_tokens": 9, "prompt_tokens": 9},
+                "data": [
+                    {"object": "embedding", "index": 1, "embedding": [0.5, -0.25]},
+                    {"object": "embedding", "index": 0, "embedding": [0.125, 1.0]}
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let result = process_embeddings_response(response, "jina-embeddings-v3".to_string());
+
+        assert_eq!(
+            result
+                .embeddings
+                .iter()
+                .map(|embedding| (embedding.index, embedding.vector.clone()))
+                .collect::<Vec<_>>(),
+            vec![(0, vec![0.125, 1.0]), (1, vec![0.5, -0.25])]
+        );
+        assert_eq!(
+            result.usage,
+            Some(Usage {
+                input_tokens: Some(9),
+                output_tokens: None,
+                total_tokens: Some(9),
+            })
+        );
+    }
+
+    #[test]
+    fn rerank_results_are_sorted_by_descending_relevance() {
+        let response: RerankResponse = serde_json::from_str(
+            r#"{
+                "model": "jina-reranker-v2-base-multilingual",
+                "usage": {"total_tokens": 38},
+                "results": [
+                    {"index": 0, "relevance_score": 0.12},
+                    {"index": 2, "relevance_score": 0.87}
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let result =
+            process_rerank_response(response, "jina-reranker-v2-base-multilingual".to_string());
+
+        assert_eq!(
+            result.results,
+            vec![
+                RerankScore {
+                    index: 2,
+                    relevance_score: 0.87,
+                },
+                RerankScore {
+                    index: 0,
+                    relevance_score: 0.12,
+                },
+            ]
+        );
+        assert_eq!(
+            result.provider_metadata_json,
+            Some(r#"{"usage":{"total_tokens":38}}"#.to_string())
+        );
+    }
+}
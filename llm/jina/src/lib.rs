@@ -0,0 +1,207 @@
+mod client;
+mod conversions;
+
+use crate::client::JinaApi;
+use crate::conversions::{
+    embeddings_request, process_embeddings_response, process_rerank_response, rerank_request,
+};
+use golem_llm::batch::send_batch;
+use golem_llm::chat_stream::{LlmChatStream, LlmChatStreamState};
+use golem_llm::config::with_config_key;
+use golem_llm::durability::{DurableLLM, ExtendedGuest};
+use golem_llm::error::unsupported;
+use golem_llm::event_source::EventSource;
+use golem_llm::golem::llm::llm::{
+    ChatEvent, ChatStream, Config, ContentPart, EmbeddingConfig, EmbeddingResult, Error, Guest,
+    ImageGenConfig, ImageGenResult, Message, ProviderCapabilities, ProviderHealth, RerankConfig,
+    RerankResult, StreamEvent, ToolCall, ToolResult,
+};
+use golem_llm::LOGGING_STATE;
+use golem_rust::wasm_rpc::Pollable;
+use std::cell::{Ref, RefCell, RefMut};
+
+/// Jina AI has no chat models, so its streams only report the `unsupported` error
+struct JinaChatStream {
+    stream: RefCell<Option<EventSource>>,
+    failure: Option<Error>,
+    finished: RefCell<bool>,
+}
+
+impl JinaChatStream {
+    pub fn failed(error: Error) -> LlmChatStream<Self> {
+        LlmChatStream::new(JinaChatStream {
+            stream: RefCell::new(None),
+            failure: Some(error),
+            finished: RefCell::new(false),
+        })
+    }
+}
+
+impl LlmChatStreamState for JinaChatStream {
+    fn failure(&self) -> &Option<Error> {
+        &self.failure
+    }
+
+    fn is_finished(&self) -> bool {
+        *self.finished.borrow()
+    }
+
+    fn set_finished(&self) {
+        *self.finished.borrow_mut() = true;
+    }
+
+    fn stream(&self) -> Ref<Option<EventSource>> {
+        self.stream.borrow()
+    }
+
+    fn stream_mut(&self) -> RefMut<Option<EventSource>> {
+        self.stream.borrow_mut()
+    }
+
+    fn decode_message(&self, _event: &str, _raw: &str) -> Result<Option<StreamEvent>, String> {
+        Ok(None)
+    }
+}
+
+/// Embeddings and reranking provider. The chat functions are not supported by Jina AI and fail
+/// with an `unsupported` error.
+struct JinaComponent;
+
+impl JinaComponent {
+    const ENV_VAR_NAME: &'static str = "JINA_API_KEY";
+}
+
+impl Guest for JinaComponent {
+    type ChatStream = LlmChatStream<JinaChatStream>;
+
+    fn send(_messages: Vec<Message>, _config: Config) -> ChatEvent {
+        ChatEvent::Error(unsupported("chat"))
+    }
+
+    fn continue_(
+        _messages: Vec<Message>,
+        _tool_results: Vec<(ToolCall, ToolResult)>,
+        _config: Config,
+    ) -> ChatEvent {
+        ChatEvent::Error(unsupported("chat"))
+    }
+
+    fn send_batch(requests: Vec<(Vec<Message>, Config)>, abort_on_error: bool) -> Vec<ChatEvent> {
+        send_batch(requests, abort_on_error, Self::send)
+    }
+
+    fn stream(messages: Vec<Message>, config: Config) -> ChatStream {
+        ChatStream::new(Self::unwrapped_stream(messages, config))
+    }
+
+    fn continue_stream(
+        messages: Vec<Message>,
+        tool_results: Vec<(ToolCall, ToolResult)>,
+        config: Config,
+    ) -> ChatStream {
+        ChatStream::new(Self::unwrapped_continue_stream(
+            messages,
+            tool_results,
+            config,
+        ))
+    }
+
+    fn send_raw(_body_json: String, _config: Config) -> Result<String, Error> {
+        Err(unsupported("chat"))
+    }
+
+    fn rerank(
+        query: String,
+        documents: Vec<String>,
+        config: RerankConfig,
+    ) -> Result<RerankResult, Error> {
+        LOGGING_STATE.with_borrow_mut(|state| state.init());
+
+        with_config_key(Self::ENV_VAR_NAME, Err, |jina_api_key| {
+            let request = rerank_request(query, documents, config)?;
+            let model = request.model.clone();
+            JinaApi::new(jina_api_key)?
+                .rerank(request)
+                .map(|response| process_rerank_response(response, model))
+        })
+    }
+
+    fn embed(inputs: Vec<ContentPart>, config: EmbeddingConfig) -> Result<EmbeddingResult, Error> {
+        LOGGING_STATE.with_borrow_mut(|state| state.init());
+
+        with_config_key(Self::ENV_VAR_NAME, Err, |jina_api_key| {
+            let request = embeddings_request(inputs, config)?;
+            let model = request.model.clone();
+            JinaApi::new(jina_api_key)?
+                .embed(request)
+                .map(|response| process_embeddings_response(response, model))
+        })
+    }
+
+    /// Jina AI has no endpoint to check the credentials without consuming tokens
+    fn ping() -> Result<ProviderHealth, Error> {
+        Err(unsupported("ping"))
+    }
+
+    fn capabilities() -> ProviderCapabilities {
+        ProviderCapabilities {
+            provider: "jina".to_string(),
+            streaming: false,
+            tools: false,
+            vision: false,
+            embeddings: true,
+            json_schema: false,
+        }
+    }
+
+    fn warm(_config: Config) -> Result<(), Error> {
+        Err(unsupported("warm"))
+    }
+
+    fn generate_image(_prompt: String, _config: ImageGenConfig) -> Result<ImageGenResult, Error> {
+        Err(unsupported("generate-image"))
+    }
+}
+
+impl ExtendedGuest for JinaComponent {
+    fn unwrapped_stream(_messages: Vec<Message>, _config: Config) -> LlmChatStream<JinaChatStream> {
+        JinaChatStream::failed(unsupported("chat"))
+    }
+
+    fn unwrapped_continue_stream(
+        _messages: Vec<Message>,
+        _tool_results: Vec<(ToolCall, ToolResult)>,
+        _config: Config,
+    ) -> LlmChatStream<JinaChatStream> {
+        JinaChatStream::failed(unsupported("chat"))
+    }
+
+    fn subscribe(stream: &Self::ChatStream) -> Pollable {
+        stream.subscribe()
+    }
+}
+
+type DurableJinaComponent = DurableLLM<JinaComponent>;
+
+golem_llm::export_llm!(DurableJinaComponent with_types_in golem_llm);
+
+#[cfg(test)]
+mod tests {
+    use crate::JinaComponent;
+    use golem_llm::golem::llm::llm::{Guest, ProviderCapabilities};
+
+    #[test]
+    fn capabilities_describe_the_provider() {
+        assert_eq!(
+            JinaComponent::capabilities(),
+            ProviderCapabilities {
+                provider: "jina".to_string(),
+                streaming: false,
+                tools: false,
+                vision: false,
+                embeddings: true,
+                json_schema: false,
+            }
+        );
+    }
+}
@@ -0,0 +1,364 @@
+use crate::collect::StreamCollector;
+use crate::golem::llm::llm::{ChatEvent, Config, GuestChatStream, Message};
+
+/// Environment variable limiting how many requests of a batch are in flight at the same time
+const MAX_CONCURRENCY_ENV_VAR_NAME: &str = "GOLEM_LLM_MAX_CONCURRENCY";
+
+/// Returns the maximum number of concurrently in-flight batch requests set by the
+/// `GOLEM_LLM_MAX_CONCURRENCY` environment variable, `None` if it is not set to a positive number.
+///
+/// The limit is applied to each `send-batch` call by `stream_batch`, not by a semaphore in the
+/// provider clients: a worker is single-threaded and its other calls block until they are
+/// answered, so a batch is the only place where its requests to the provider overlap.
+pub fn max_concurrency() -> Option<usize> {
+    std::env::var(MAX_CONCURRENCY_ENV_VAR_NAME)
+        .ok()
+        .and_then(|value| value.trim().parse::<usize>().ok())
+        .filter(|max| *max > 0)
+}
+
+/// Sends each request of a batch sequentially with the given `send` function, collecting
+/// one `ChatEvent` per request sent.
+///
+/// When `abort_on_error` is set, the batch stops after the first `ChatEvent::Error` and the
+/// remaining requests are not sent at all.
+pub fn send_batch(
+    requests: Vec<(Vec<Message>, Config)>,
+    abort_on_error: bool,
+    send: impl Fn(Vec<Message>, Config) -> ChatEvent,
+) -> Vec<ChatEvent> {
+    let mut events = Vec::with_capacity(requests.len());
+    for (messages, config) in requests {
+        let event = send(messages, config);
+        let failed = matches!(event, ChatEvent::Error(_));
+        events.push(event);
+
+        if failed && abort_on_error {
+            break;
+        }
+    }
+    events
+}
+
+/// Sends the requests of a batch as streams created by `start`, keeping at most `max_in_flight`
+/// of them open at the same time, and collects each stream into one `ChatEvent`.
+///
+/// Golem workers are single-threaded, so this is the only place where requests of a component
+/// overlap: each open stream is an HTTP request whose response the provider keeps generating
+/// while the others are read. Requests beyond the limit wait in a queue and are started in order
+/// as earlier streams complete, instead of failing. `wait` blocks until the given in-flight stream
+/// has something to read, which lets the other ones make progress in the meantime as well.
+///
+/// The result is the same as of `send_batch`: one event per request in the order of the
+/// requests, ending with the first `ChatEvent::Error` when `abort_on_error` is set. Streams of
+/// requests after a failed one are dropped, and no more requests are started.
+pub fn stream_batch<S: GuestChatStream>(
+    requests: Vec<(Vec<Message>, Config)>,
+    abort_on_error: bool,
+    max_in_flight: usize,
+    start: impl Fn(Vec<Message>, Config) -> S,
+    wait: impl Fn(&S),
+) -> Vec<ChatEvent> {
+    let mut results: Vec<Option<ChatEvent>> = requests.iter().map(|_| None).collect();
+    let mut queue = requests.into_iter().enumerate();
+    let mut in_flight: Vec<(usize, S, StreamCollector)> = Vec::new();
+    let mut failed_at: Option<usize> = None;
+
+    loop {
+        while failed_at.is_none() && in_flight.len() < max_in_flight.max(1) {
+            match queue.next() {
+                Some((index, (messages, config))) => {
+                    in_flight.push((index, start(messages, config), StreamCollector::new()))
+                }
+                None => break,
+            }
+        }
+
+        match in_flight.first() {
+            Some((_, stream, _)) => wait(stream),
+            None => break,
+        }
+
+        let mut position = 0;
+        while position < in_flight.len() {
+            let (index, stream, collector) = &mut in_flight[position];
+            match stream.get_next().and_then(|events| collector.add(events)) {
+                Some(event) => {
+                    let index = *index;
+                    if abort_on_error && matches!(event, ChatEvent::Error(_)) {
+                        failed_at = Some(failed_at.map_or(index, |failed_at| failed_at.min(index)));
+                    }
+                    results[index] = Some(event);
+                    in_flight.remove(position);
+                }
+                None => position += 1,
+            }
+        }
+
+        if let Some(failed_at) = failed_at {
+            in_flight.retain(|(index, _, _)| *index < failed_at);
+        }
+    }
+
+    let mut events = Vec::with_capacity(results.len());
+    for event in results.into_iter().take_while(Option::is_some).flatten() {
+        let failed = matches!(event, ChatEvent::Error(_));
+        events.push(event);
+
+        if failed && abort_on_error {
+            break;
+        }
+    }
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::batch::{send_batch, stream_batch};
+    use crate::golem::llm::llm::{
+        ChatEvent, CompleteResponse, Config, ContentPart, Error, ErrorCode, FinishReason,
+        GuestChatStream, Message, ResponseMetadata, Role, StreamDelta, StreamEvent, ToolCall,
+    };
+    use std::cell::{Cell, RefCell};
+    use std::rc::Rc;
+
+    fn request(text: &str) -> (Vec<Message>, Config) {
+        (
+            vec![Message {
+                role: Role::User,
+                name: None,
+                content: vec![ContentPart::Text(text.to_string())],
+            }],
+            Config {
+                model: "test-model".to_string(),
+                temperature: None,
+                max_tokens: None,
+                stop_sequences: None,
+                tools: vec![],
+                tool_choice: None,
+                provider_options: vec![],
+                extra_headers: vec![],
+                service_tier: None,
+                metadata: vec![],
+                reasoning_effort: None,
+                store: None,
+                prompt_cache_key: None,
+                stream_options: None,
+                deadline_ms: None,
+                extra_body: None,
+            },
+        )
+    }
+
+    fn fake_send(sent: &RefCell<Vec<String>>) -> impl Fn(Vec<Message>, Config) -> ChatEvent + '_ {
+        move |messages, _config| {
+            let text = match &messages[0].content[0] {
+                ContentPart::Text(text) => text.clone(),
+                _ => unreachable!(),
+            };
+            sent.borrow_mut().push(text.clone());
+            if text == "fail" {
+                ChatEvent::Error(Error {
+                    code: ErrorCode::InternalError,
+                    message: "failed".to_string(),
+                    provider_error_json: None,
+                })
+            } else {
+                ChatEvent::ToolRequest(vec![ToolCall {
+                    id: text.clone(),
+                    name: text,
+                    arguments_json: "{}".to_string(),
+                }])
+            }
+        }
+    }
+
+    #[test]
+    fn mid_batch_error_halts_when_aborting() {
+        let sent = RefCell::new(Vec::new());
+        let events = send_batch(
+            vec![request("first"), request("fail"), request("third")],
+            true,
+            fake_send(&sent),
+        );
+
+        assert_eq!(events.len(), 2);
+        assert!(matches!(events[1], ChatEvent::Error(_)));
+        assert_eq!(*sent.borrow(), vec!["first", "fail"]);
+    }
+
+    #[test]
+    fn mid_batch_error_continues_when_not_aborting() {
+        let sent = RefCell::new(Vec::new());
+        let events = send_batch(
+            vec![request("first"), request("fail"), request("third")],
+            false,
+            fake_send(&sent),
+        );
+
+        assert_eq!(events.len(), 3);
+        assert!(matches!(events[1], ChatEvent::Error(_)));
+        assert!(matches!(events[2], ChatEvent::ToolRequest(_)));
+        assert_eq!(*sent.borrow(), vec!["first", "fail", "third"]);
+    }
+
+    /// Stream of a fake request, which is ready with its text after being polled as many times
+    /// as the number in its text, and keeps track of the number of open streams
+    struct DelayedStream {
+        text: String,
+        remaining_polls: Cell<usize>,
+        open: Rc<Cell<usize>>,
+    }
+
+    impl GuestChatStream for DelayedStream {
+        fn get_next(&self) -> Option<Vec<StreamEvent>> {
+            if self.remaining_polls.get() > 0 {
+                self.remaining_polls.set(self.remaining_polls.get() - 1);
+                None
+            } else if self.text.starts_with("fail") {
+                Some(vec![StreamEvent::Error(Error {
+                    code: ErrorCode::InternalError,
+                    message: "failed".to_string(),
+                    provider_error_json: None,
+                })])
+            } else {
+                Some(vec![
+                    StreamEvent::Delta(StreamDelta {
+                        content: Some(vec![ContentPart::Text(self.text.clone())]),
+                        tool_calls: None,
+                        annotations: None,
+                        tool_call_fragments: None,
+                        running_usage: None,
+                    }),
+                    StreamEvent::Finish(ResponseMetadata {
+                        finish_reason: Some(FinishReason::Stop),
+                        usage: None,
+                        provider_id: None,
+                        model: None,
+                        timestamp: None,
+                        provider_metadata_json: None,
+                        tokens_per_second: None,
+                        stop_sequence: None,
+                    }),
+                ])
+            }
+        }
+
+        fn blocking_get_next(&self) -> Vec<StreamEvent> {
+            unreachable!()
+        }
+
+        fn partial_text(&self) -> String {
+            unreachable!()
+        }
+
+        fn raw_transcript(&self) -> String {
+            unreachable!()
+        }
+    }
+
+    impl Drop for DelayedStream {
+        fn drop(&mut self) {
+            self.open.set(self.open.get() - 1);
+        }
+    }
+
+    struct FakeStreams {
+        started: RefCell<Vec<String>>,
+        open: Rc<Cell<usize>>,
+        max_open: Cell<usize>,
+    }
+
+    impl FakeStreams {
+        fn new() -> Self {
+            Self {
+                started: RefCell::new(Vec::new()),
+                open: Rc::new(Cell::new(0)),
+                max_open: Cell::new(0),
+            }
+        }
+
+        fn start(&self, messages: Vec<Message>, _config: Config) -> DelayedStream {
+            let text = match &messages[0].content[0] {
+                ContentPart::Text(text) => text.clone(),
+                _ => unreachable!(),
+            };
+            self.started.borrow_mut().push(text.clone());
+            self.open.set(self.open.get() + 1);
+            self.max_open.set(self.max_open.get().max(self.open.get()));
+            DelayedStream {
+                remaining_polls: Cell::new(
+                    text.trim_start_matches(char::is_alphabetic)
+                        .parse()
+                        .unwrap(),
+                ),
+                text,
+                open: self.open.clone(),
+            }
+        }
+    }
+
+    fn text_of(event: &ChatEvent) -> &str {
+        match event {
+            ChatEvent::Message(CompleteResponse { content, .. }) => match content.as_slice() {
+                [ContentPart::Text(text)] => text,
+                _ => unreachable!(),
+            },
+            ChatEvent::Error(_) => "error",
+            ChatEvent::ToolRequest(_) => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn requests_beyond_the_limit_are_queued() {
+        let streams = FakeStreams::new();
+        let events = stream_batch(
+            vec![
+                request("a3"),
+                request("b0"),
+                request("c1"),
+                request("d0"),
+                request("e2"),
+            ],
+            false,
+            2,
+            |messages, config| streams.start(messages, config),
+            |_| {},
+        );
+
+        assert_eq!(
+            events.iter().map(text_of).collect::<Vec<_>>(),
+            vec!["a3", "b0", "c1", "d0", "e2"]
+        );
+        assert_eq!(streams.max_open.get(), 2);
+        assert_eq!(streams.open.get(), 0);
+        assert_eq!(
+            *streams.started.borrow(),
+            vec!["a3", "b0", "c1", "d0", "e2"]
+        );
+    }
+
+    #[test]
+    fn queued_requests_are_not_started_after_error_when_aborting() {
+        let streams = FakeStreams::new();
+        let events = stream_batch(
+            vec![
+                request("a2"),
+                request("fail0"),
+                request("c0"),
+                request("d0"),
+            ],
+            true,
+            2,
+            |messages, config| streams.start(messages, config),
+            |_| {},
+        );
+
+        assert_eq!(
+            events.iter().map(text_of).collect::<Vec<_>>(),
+            vec!["a2", "error"]
+        );
+        assert_eq!(*streams.started.borrow(), vec!["a2", "fail0"]);
+        assert_eq!(streams.open.get(), 0);
+    }
+}
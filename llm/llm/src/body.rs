@@ -0,0 +1,134 @@
+use crate::golem::llm::llm::{Error, ErrorCode};
+use golem_rust::bindings::wasi::io::streams::StreamError;
+use reqwest::header::CONTENT_LENGTH;
+use reqwest::Response;
+
+/// Environment variable limiting the size of the response bodies read by the non-streaming
+/// requests, in bytes
+const MAX_RESPONSE_BYTES_ENV_VAR_NAME: &str = "GOLEM_LLM_MAX_RESPONSE_BYTES";
+
+/// Size limit of the response bodies when `GOLEM_LLM_MAX_RESPONSE_BYTES` is not set
+const DEFAULT_MAX_RESPONSE_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Size of the chunks the response body is read in
+const CHUNK_SIZE: u64 = 64 * 1024;
+
+/// Returns the maximum size of a response body set by the `GOLEM_LLM_MAX_RESPONSE_BYTES`
+/// environment variable, or 64 MiB if it is not set to a positive number
+pub fn max_response_bytes() -> u64 {
+    std::env::var(MAX_RESPONSE_BYTES_ENV_VAR_NAME)
+        .ok()
+        .and_then(|value| value.trim().parse::<u64>().ok())
+        .filter(|max| *max > 0)
+        .unwrap_or(DEFAULT_MAX_RESPONSE_BYTES)
+}
+
+/// Reads the whole body of a response as text, like `Response::text`, but failing with an
+/// `internal-error` as soon as the body turns out to be larger than `max_response_bytes`, without
+/// reading the rest of it. A body announcing a larger `Content-Length` is not read at all.
+pub fn read_body(mut response: Response, details: impl AsRef<str>) -> Result<String, Error> {
+    let max_bytes = max_response_bytes();
+
+    let content_length = response
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok());
+    if content_length.is_some_and(|content_length| content_length > max_bytes) {
+        return Err(too_large(details, max_bytes));
+    }
+
+    let stream = unsafe {
+        std::mem::transmute::<
+            reqwest::InputStream,
+            golem_rust::bindings::wasi::io::streams::InputStream,
+        >(response.get_raw_input_stream())
+    };
+    let body = read_capped(
+        || match stream.blocking_read(CHUNK_SIZE) {
+            Ok(chunk) => Ok(Some(chunk)),
+            Err(StreamError::Closed) => Ok(None),
+            Err(StreamError::LastOperationFailed(err)) => Err(err.to_debug_string()),
+        },
+        max_bytes,
+        &details,
+    );
+    // The stream has to be dropped before the response it belongs to
+    drop(stream);
+
+    body.map(|body| String::from_utf8_lossy(&body).into_owned())
+}
+
+/// Reads the chunks returned by `next_chunk` until it returns `None`, failing as soon as their
+/// total size exceeds `max_bytes`
+fn read_capped(
+    mut next_chunk: impl FnMut() -> Result<Option<Vec<u8>>, String>,
+    max_bytes: u64,
+    details: impl AsRef<str>,
+) -> Result<Vec<u8>, Error> {
+    let mut body = Vec::new();
+    loop {
+        match next_chunk() {
+            Ok(Some(chunk)) => {
+                if (body.len() + chunk.len()) as u64 > max_bytes {
+                    return Err(too_large(details, max_bytes));
+                }
+                body.extend_from_slice(&chunk);
+            }
+            Ok(None) => return Ok(body),
+            Err(err) => {
+                return Err(Error {
+                    code: ErrorCode::InternalError,
+                    message: format!("{}: {err}", details.as_ref()),
+                    provider_error_json: None,
+                })
+            }
+        }
+    }
+}
+
+fn too_large(details: impl AsRef<str>, max_bytes: u64) -> Error {
+    Error {
+        code: ErrorCode::InternalError,
+        message: format!(
+            "{}: Response too large, exceeding the limit of {max_bytes} bytes set by {MAX_RESPONSE_BYTES_ENV_VAR_NAME}",
+            details.as_ref()
+        ),
+        provider_error_json: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::body::read_capped;
+    use crate::golem::llm::llm::ErrorCode;
+
+    #[test]
+    fn body_within_the_limit_is_read() {
+        let mut chunks = vec![b"{\"id\":".to_vec(), b"1}".to_vec()].into_iter();
+
+        let body = read_capped(|| Ok(chunks.next()), 8, "Failed to receive response body");
+
+        assert_eq!(body, Ok(b"{\"id\":1}".to_vec()));
+    }
+
+    #[test]
+    fn body_over_the_limit_fails_early() {
+        let mut chunks_read = 0;
+
+        let error = read_capped(
+            || {
+                chunks_read += 1;
+                Ok(Some(vec![b'x'; 10]))
+            },
+            25,
+            "Failed to receive response body",
+        )
+        .unwrap_err();
+
+        assert_eq!(chunks_read, 3);
+        assert_eq!(error.code, ErrorCode::InternalError);
+        assert!(error.message.contains("Response too large"));
+        assert!(error.message.contains("GOLEM_LLM_MAX_RESPONSE_BYTES"));
+    }
+}
@@ -0,0 +1,195 @@
+use crate::golem::llm::llm::{ContentPart, Error, ErrorCode, Message, ToolDefinition};
+
+/// Features of the requests supported by a model
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ModelCapabilities {
+    /// Images can be part of the messages
+    pub vision: bool,
+    /// Tools can be given to the model
+    pub tools: bool,
+    /// Responses can be streamed
+    pub streaming: bool,
+}
+
+impl ModelCapabilities {
+    pub const ALL: Self = Self {
+        vision: true,
+        tools: true,
+        streaming: true,
+    };
+
+    pub const TEXT_ONLY: Self = Self {
+        vision: false,
+        tools: true,
+        streaming: true,
+    };
+}
+
+/// Looks up the capabilities of a model in a provider's registry. Models missing from the registry
+/// are not validated.
+pub fn model_capabilities(
+    registry: &[(&str, ModelCapabilities)],
+    model: &str,
+) -> Option<ModelCapabilities> {
+    registry
+        .iter()
+        .find(|(id, _)| *id == model)
+        .map(|(_, capabilities)| *capabilities)
+}
+
+/// Checks that a known model supports the images of the messages and the tools of the request,
+/// so the request fails with a clear `invalid-request` error instead of a provider error
+pub fn check_model_capabilities(
+    registry: &[(&str, ModelCapabilities)],
+    model: &str,
+    messages: &[Message],
+    tools: &[ToolDefinition],
+) -> Result<(), Error> {
+    check_request_capabilities(registry, model, has_images(messages), !tools.is_empty())
+}
+
+/// Same as `check_model_capabilities`, for providers which have already converted the messages
+/// and tools of the request
+pub fn check_request_capabilities(
+    registry: &[(&str, ModelCapabilities)],
+    model: &str,
+    has_images: bool,
+    has_tools: bool,
+) -> Result<(), Error> {
+    let Some(capabilities) = model_capabilities(registry, model) else {
+        return Ok(());
+    };
+
+    if has_images && !capabilities.vision {
+        return Err(unsupported_by_model(model, "image input"));
+    }
+    if has_tools && !capabilities.tools {
+        return Err(unsupported_by_model(model, "tools"));
+    }
+
+    Ok(())
+}
+
+fn has_images(messages: &[Message]) -> bool {
+    messages.iter().any(|message| {
+        message
+            .content
+            .iter()
+            .any(|part| matches!(part, ContentPart::Image(_)))
+    })
+}
+
+/// Checks that a known model can stream its responses
+pub fn check_streaming_support(
+    registry: &[(&str, ModelCapabilities)],
+    model: &str,
+) -> Result<(), Error> {
+    match model_capabilities(registry, model) {
+        Some(capabilities) if !capabilities.streaming => {
+            Err(unsupported_by_model(model, "streaming"))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn unsupported_by_model(model: &str, feature: &str) -> Error {
+    Error {
+        code: ErrorCode::InvalidRequest,
+        message: format!("Model {model} does not support {feature}"),
+        provider_error_json: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::capabilities::{
+        check_model_capabilities, check_streaming_support, ModelCapabilities,
+    };
+    use crate::golem::llm::llm::{
+        ContentPart, ErrorCode, ImageReference, ImageUrl, Message, Role, ToolDefinition,
+    };
+
+    const REGISTRY: [(&str, ModelCapabilities); 3] = [
+        ("vision-model", ModelCapabilities::ALL),
+        ("text-model", ModelCapabilities::TEXT_ONLY),
+        (
+            "completion-model",
+            ModelCapabilities {
+                vision: false,
+                tools: false,
+                streaming: false,
+            },
+        ),
+    ];
+
+    fn image_message() -> Message {
+        Message {
+            role: Role::User,
+            name: None,
+            content: vec![
+                ContentPart::Text("What is in this image?".to_string()),
+                ContentPart::Image(ImageReference::Url(ImageUrl {
+                    url: "https://example.com/image.png".to_string(),
+                    detail: None,
+                })),
+            ],
+        }
+    }
+
+    fn tool() -> ToolDefinition {
+        ToolDefinition {
+            name: "get_weather".to_string(),
+            description: None,
+            parameters_schema: "{}".to_string(),
+        }
+    }
+
+    #[test]
+    fn images_are_rejected_for_text_models() {
+        assert!(
+            check_model_capabilities(&REGISTRY, "vision-model", &[image_message()], &[]).is_ok()
+        );
+
+        let error =
+            check_model_capabilities(&REGISTRY, "text-model", &[image_message()], &[]).unwrap_err();
+
+        assert_eq!(error.code, ErrorCode::InvalidRequest);
+        assert_eq!(
+            error.message,
+            "Model text-model does not support image input"
+        );
+    }
+
+    #[test]
+    fn tools_are_rejected_for_models_without_tool_support() {
+        assert!(check_model_capabilities(&REGISTRY, "text-model", &[], &[tool()]).is_ok());
+
+        let error =
+            check_model_capabilities(&REGISTRY, "completion-model", &[], &[tool()]).unwrap_err();
+
+        assert_eq!(error.code, ErrorCode::InvalidRequest);
+        assert_eq!(
+            error.message,
+            "Model completion-model does not support tools"
+        );
+    }
+
+    #[test]
+    fn unknown_models_are_not_validated() {
+        assert!(
+            check_model_capabilities(&REGISTRY, "new-model", &[image_message()], &[tool()]).is_ok()
+        );
+        assert!(check_streaming_support(&REGISTRY, "new-model").is_ok());
+    }
+
+    #[test]
+    fn streaming_is_rejected_for_models_without_streaming() {
+        assert!(check_streaming_support(&REGISTRY, "text-model").is_ok());
+        assert_eq!(
+            check_streaming_support(&REGISTRY, "completion-model")
+                .unwrap_err()
+                .message,
+            "Model completion-model does not support streaming"
+        );
+    }
+}
@@ -0,0 +1,446 @@
+use crate::body::read_body;
+use crate::error::{
+    decode_success_body, error_code_from_response, from_event_source_error, from_reqwest_error,
+};
+use crate::event_source::{EventSource, StreamFormat, StreamFormatHint};
+use crate::extra_body::ExtraBody;
+use crate::golem::llm::llm::{Error, ProviderHealth};
+use crate::health::probe;
+use crate::proxy::http_client;
+use crate::rate_limit::{rate_limit_info, RateLimited};
+use crate::raw::send_raw;
+use crate::retry::send_with_connection_retry;
+use log::trace;
+use reqwest::header::{HeaderMap, HeaderValue};
+use reqwest::{Client, Method, RequestBuilder, Response};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fmt::Debug;
+use std::str::FromStr;
+
+/// Where and how a provider serves its Chat Completions API
+pub trait ChatCompletionsEndpoint {
+    /// Name of the API in the logs
+    const API_NAME: &'static str;
+
+    /// URL the HTTP client is created for, see `http_client`
+    fn base_url(&self) -> &str;
+
+    /// URL of the chat completions of the model
+    fn completions_url(&self, model: &str) -> String;
+
+    /// URL listing the available models, used to check the connectivity
+    fn models_url(&self) -> String;
+
+    /// Adds the credentials of the provider to a request
+    fn authenticate(&self, request: RequestBuilder) -> RequestBuilder;
+}
+
+/// A chat request body, sent to the completions URL of its model
+pub trait CompletionsRequestBody: Serialize + Debug {
+    fn model(&self) -> &str;
+}
+
+/// The Chat Completions API client for creating model responses.
+pub struct CompletionsApi<E> {
+    endpoint: E,
+    extra_headers: HeaderMap,
+    /// Fields added to the chat request bodies
+    extra_body: ExtraBody,
+    client: Client,
+}
+
+impl<E: ChatCompletionsEndpoint> CompletionsApi<E> {
+    pub fn new(endpoint: E, extra_headers: HeaderMap) -> Result<Self, Error> {
+        let client = http_client(endpoint.base_url())?;
+        Ok(Self {
+            endpoint,
+            extra_headers,
+            extra_body: ExtraBody::default(),
+            client,
+        })
+    }
+
+    /// Adds the `extra_body` of the config to the chat requests
+    pub fn with_extra_body(mut self, extra_body: ExtraBody) -> Self {
+        self.extra_body = extra_body;
+        self
+    }
+
+    pub fn send_messages<Request: CompletionsRequestBody, Body: DeserializeOwned + Debug>(
+        &self,
+        request: Request,
+    ) -> Result<RateLimited<Body>, Error> {
+        trace!("Sending request to {}: {request:?}", E::API_NAME);
+
+        let body = self.extra_body.merge(&request)?;
+
+        let response: Response = send_with_connection_retry(|| {
+            self.completions_request(request.model()).json(&body).send()
+        })
+        .map_err(|err| from_reqwest_error("Request failed", err))?;
+
+        let rate_limit = rate_limit_info(response.headers());
+        parse_response::<E, Body>(response).map(|body| RateLimited { body, rate_limit })
+    }
+
+    pub fn stream_send_messages<Request: CompletionsRequestBody>(
+        &self,
+        request: Request,
+    ) -> Result<EventSource, Error> {
+        trace!("Sending request to {}: {request:?}", E::API_NAME);
+
+        let body = self.extra_body.merge(&request)?;
+
+        let response: Response = self
+            .completions_request(request.model())
+            .header(
+                reqwest::header::ACCEPT,
+                HeaderValue::from_static("text/event-stream"),
+            )
+            .json(&body)
+            .send()
+            .map_err(|err| from_reqwest_error("Request failed", err))?;
+
+        trace!("Initializing SSE stream");
+
+        EventSource::new(
+            response,
+            StreamFormatHint::Detect(StreamFormat::EventStream),
+        )
+        .map_err(|err| from_event_source_error("Failed to create SSE stream", err))
+    }
+
+    /// Sends the JSON body untouched to the chat completions of the model, returning the raw
+    /// response body
+    pub fn send_raw(&self, model: &str, body_json: String) -> Result<String, Error> {
+        send_raw(
+            self.endpoint.authenticate(
+                self.client
+                    .request(Method::POST, self.endpoint.completions_url(model)),
+            ),
+            self.extra_headers.clone(),
+            body_json,
+        )
+    }
+
+    /// Checks the connectivity by listing the available models
+    pub fn ping(&self) -> Result<ProviderHealth, Error> {
+        trace!("Checking the connectivity of the {}", E::API_NAME);
+
+        probe(|| {
+            self.endpoint
+                .authenticate(self.client.request(Method::GET, self.endpoint.models_url()))
+                .headers(self.extra_headers.clone())
+                .send()
+        })
+    }
+
+    /// Starts a chat completions request to the model, authenticated and with the extra headers
+    pub fn completions_request(&self, model: &str) -> RequestBuilder {
+        self.endpoint
+            .authenticate(
+                self.client
+                    .request(Method::POST, self.endpoint.completions_url(model)),
+            )
+            .headers(self.extra_headers.clone())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompletionsRequest {
+    pub messages: Vec<Message>,
+    pub model: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_completion_tokens: Option<u32>,
+    /// The older name of `max_completion_tokens`, the only one some providers accept
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub n: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reasoning_effort: Option<Effort>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream_options: Option<StreamOptions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_choice: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tools: Vec<Tool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_logprobs: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+
+    /// Whether the usage of the response is requested when the request is streamed, see
+    /// `to_streaming_request`
+    #[serde(skip)]
+    pub include_stream_usage: bool,
+}
+
+impl CompletionsRequestBody for CompletionsRequest {
+    fn model(&self) -> &str {
+        &self.model
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StreamOptions {
+    pub include_usage: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum Tool {
+    #[serde(rename = "function")]
+    Function { function: Function },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Function {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parameters: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "role")]
+pub enum Message {
+    #[serde(rename = "system")]
+    System {
+        content: Content,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        name: Option<String>,
+    },
+    #[serde(rename = "user")]
+    User {
+        content: Content,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        name: Option<String>,
+    },
+    #[serde(rename = "assistant")]
+    Assistant {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        content: Option<Content>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        name: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        tool_calls: Option<Vec<ToolCall>>,
+    },
+    #[serde(rename = "tool")]
+    Tool {
+        content: Content,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        name: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        tool_call_id: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Content {
+    TextInput(String),
+    List(Vec<ContentPart>),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum ContentPart {
+    #[serde(rename = "text")]
+    TextInput { text: String },
+    #[serde(rename = "image_url")]
+    ImageInput { image_url: ImageUrl },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub enum Detail {
+    #[serde(rename = "auto")]
+    #[default]
+    Auto,
+    #[serde(rename = "low")]
+    Low,
+    #[serde(rename = "high")]
+    High,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Effort {
+    #[serde(rename = "low")]
+    Low,
+    #[serde(rename = "medium")]
+    Medium,
+    #[serde(rename = "high")]
+    High,
+}
+
+impl FromStr for Effort {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "low" => Ok(Effort::Low),
+            "medium" => Ok(Effort::Medium),
+            "high" => Ok(Effort::High),
+            _ => Err(format!("Invalid effort value: {s}")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageUrl {
+    pub url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<Detail>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum ToolCall {
+    #[serde(rename = "function")]
+    Function {
+        function: FunctionCall,
+        id: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        index: Option<u32>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FunctionCall {
+    pub arguments: String,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompletionsResponse {
+    pub choices: Vec<Choice>,
+    pub created: u64,
+    pub id: String,
+    pub model: String,
+    pub system_fingerprint: Option<String>,
+    pub usage: Option<Usage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Choice {
+    pub finish_reason: Option<FinishReason>,
+    pub index: u32,
+    pub message: ResponseMessage,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum FinishReason {
+    #[serde(rename = "stop")]
+    Stop,
+    #[serde(rename = "length")]
+    Length,
+    #[serde(rename = "end_turn")]
+    EndTurn,
+    #[serde(rename = "tool_calls")]
+    ToolCalls,
+    #[serde(rename = "content_filter")]
+    ContentFilter,
+    /// DeepSeek ran out of capacity while generating the response
+    #[serde(rename = "insufficient_system_resource")]
+    InsufficientSystemResource,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResponseMessage {
+    pub content: Option<String>,
+    /// The reasoning preceding the answer, returned by the reasoning models of some providers
+    /// like DeepSeek
+    pub reasoning_content: Option<String>,
+    pub refusal: Option<String>,
+    pub tool_calls: Option<Vec<ToolCall>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Usage {
+    pub completion_tokens: u32,
+    pub prompt_tokens: u32,
+    pub total_tokens: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatCompletionChunk {
+    pub id: String,
+    pub created: u64,
+    pub model: String,
+    pub choices: Vec<ChoiceChunk>,
+    pub usage: Option<Usage>,
+    pub system_fingerprint: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChoiceChunk {
+    pub index: u32,
+    pub delta: ChoiceDelta,
+    pub finish_reason: Option<FinishReason>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChoiceDelta {
+    pub content: Option<String>,
+    pub reasoning_content: Option<String>,
+    pub tool_calls: Option<Vec<ToolCallChunk>>,
+    pub role: Option<String>,
+}
+
+/// A fragment of a streamed tool call. The first fragment of a call has its id and name, the
+/// following ones only the next part of its arguments.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolCallChunk {
+    pub index: u32,
+    pub id: Option<String>,
+    pub function: Option<FunctionCallChunk>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FunctionCallChunk {
+    pub name: Option<String>,
+    pub arguments: Option<String>,
+}
+
+fn parse_response<E: ChatCompletionsEndpoint, T: DeserializeOwned + Debug>(
+    response: Response,
+) -> Result<T, Error> {
+    let status = response.status();
+    if status.is_success() {
+        let raw_body = read_body(response, "Failed to receive response body")?;
+        let body = decode_success_body::<T>(raw_body)?;
+
+        trace!("Received response from {}: {body:?}", E::API_NAME);
+
+        Ok(body)
+    } else {
+        let error_body = read_body(response, "Failed to receive error response body")?;
+
+        trace!(
+            "Received {status} response from {}: {error_body:?}",
+            E::API_NAME
+        );
+
+        Err(Error {
+            code: error_code_from_response(status, &error_body),
+            message: format!("Request failed with {status}"),
+            provider_error_json: Some(serde_json::to_string(&error_body).unwrap()),
+        })
+    }
+}
@@ -0,0 +1,367 @@
+use crate::capabilities::{check_model_capabilities, check_streaming_support, ModelCapabilities};
+use crate::chat_completions::client::{
+    self, CompletionsRequest, CompletionsResponse, Detail, Effort, StreamOptions,
+};
+use crate::config::model_or_default;
+use crate::conversions::{
+    check_provider_options, non_empty_tool_call_id, split_tool_results, validate_temperature,
+    TextJoin,
+};
+use crate::error::unsupported;
+use crate::golem::llm::llm::{
+    ChatEvent, CompleteResponse, Config, ContentPart, Error, ErrorCode, FinishReason, ImageDetail,
+    ImageReference, Message, ResponseMetadata, Role, ToolCall, ToolDefinition, ToolResult, Usage,
+};
+use crate::usage::with_total_tokens;
+use base64::{engine::general_purpose, Engine as _};
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+
+/// The settings in which the Chat Completions APIs of the providers differ
+pub struct ChatCompletionsProvider {
+    /// Provider options read by the conversions, any other key is reported as unknown
+    pub provider_options: &'static [&'static str],
+    /// Range of temperatures accepted by the provider
+    pub temperature_range: RangeInclusive<f32>,
+    /// Capabilities of the known models, requests to other models are not validated
+    pub model_capabilities: &'static [(&'static str, ModelCapabilities)],
+    /// Request field the maximum number of output tokens is sent in
+    pub max_tokens_field: MaxTokensField,
+    /// Whether the provider only accepts text content, sent as a string joined according to the
+    /// `text_join` provider option
+    pub text_only: bool,
+    /// Whether the provider requires the tool messages to answer a tool call, in which case the
+    /// tool messages without one are sent as user messages
+    pub tool_messages_answer_tool_calls: bool,
+}
+
+/// The request fields the maximum number of output tokens can be sent in
+pub enum MaxTokensField {
+    MaxCompletionTokens,
+    MaxTokens,
+}
+
+pub fn messages_to_request(
+    provider: &ChatCompletionsProvider,
+    messages: Vec<Message>,
+    config: Config,
+) -> Result<CompletionsRequest, Error> {
+    let options = config
+        .provider_options
+        .into_iter()
+        .map(|kv| (kv.key, kv.value))
+        .collect::<HashMap<_, _>>();
+    check_provider_options(&options, provider.provider_options)?;
+
+    let model = model_or_default(config.model)?;
+    check_model_capabilities(
+        provider.model_capabilities,
+        &model,
+        &messages,
+        &config.tools,
+    )?;
+
+    let text_join = TextJoin::from_options(&options);
+    let mut completion_messages = Vec::new();
+    for message in messages {
+        // Tool results included in the messages are sent as a tool call and its result
+        let (content, tool_results) = split_tool_results(message.content, non_empty_tool_call_id)?;
+        if !content.is_empty() || tool_results.is_empty() {
+            let content = convert_content(provider, content, &text_join)?;
+            match message.role {
+                Role::User => completion_messages.push(client::Message::User {
+                    name: message.name,
+                    content,
+                }),
+                Role::Assistant => completion_messages.push(client::Message::Assistant {
+                    name: message.name,
+                    content: Some(content),
+                    tool_calls: None,
+                }),
+                Role::System => completion_messages.push(client::Message::System {
+                    name: message.name,
+                    content,
+                }),
+                Role::Tool if provider.tool_messages_answer_tool_calls => {
+                    completion_messages.push(client::Message::User {
+                        name: message.name,
+                        content,
+                    })
+                }
+                Role::Tool => completion_messages.push(client::Message::Tool {
+                    name: message.name,
+                    content,
+                    tool_call_id: None,
+                }),
+            }
+        }
+        completion_messages.extend(tool_results_to_messages(tool_results));
+    }
+
+    let mut tools = Vec::new();
+    for tool in config.tools {
+        tools.push(tool_definition_to_tool(tool)?)
+    }
+
+    let (max_completion_tokens, max_tokens) = match provider.max_tokens_field {
+        MaxTokensField::MaxCompletionTokens => (config.max_tokens, None),
+        MaxTokensField::MaxTokens => (None, config.max_tokens),
+    };
+
+    Ok(CompletionsRequest {
+        messages: completion_messages,
+        model,
+        frequency_penalty: options
+            .get("frequency_penalty")
+            .and_then(|fp_s| fp_s.parse::<f32>().ok()),
+        max_completion_tokens,
+        max_tokens,
+        n: options.get("n").and_then(|n_s| n_s.parse::<u32>().ok()),
+        presence_penalty: options
+            .get("presence_penalty")
+            .and_then(|pp_s| pp_s.parse::<f32>().ok()),
+        reasoning_effort: options
+            .get("reasoning_effort")
+            .and_then(|effort_s| effort_s.parse::<Effort>().ok()),
+        seed: options
+            .get("seed")
+            .and_then(|seed_s| seed_s.parse::<u32>().ok()),
+        stop: config.stop_sequences,
+        stream: Some(false),
+        stream_options: None,
+        temperature: validate_temperature(config.temperature, provider.temperature_range.clone())?,
+        tool_choice: config.tool_choice,
+        tools,
+        top_logprobs: options
+            .get("top_logprobs")
+            .and_then(|top_logprobs_s| top_logprobs_s.parse::<u8>().ok()),
+        top_p: options
+            .get("top_p")
+            .and_then(|top_p_s| top_p_s.parse::<f32>().ok()),
+        user: options.get("user_id").cloned(),
+        include_stream_usage: config
+            .stream_options
+            .map(|stream_options| stream_options.include_usage)
+            .unwrap_or(true),
+    })
+}
+
+/// Turns a request into a streaming request, asking for the usage of the response in its last
+/// chunk unless the `stream-options` of the config turned it off
+pub fn to_streaming_request(mut request: CompletionsRequest) -> CompletionsRequest {
+    request.stream = Some(true);
+    request.stream_options = Some(StreamOptions {
+        include_usage: request.include_stream_usage,
+    });
+    request
+}
+
+/// Fails if the model is known not to support streaming
+pub fn check_streaming(provider: &ChatCompletionsProvider, model: &str) -> Result<(), Error> {
+    check_streaming_support(provider.model_capabilities, model)
+}
+
+pub fn process_response(response: CompletionsResponse) -> ChatEvent {
+    let choice = response.choices.first();
+    if let Some(choice) = choice {
+        let contents = convert_response_content(
+            choice.message.reasoning_content.clone(),
+            choice.message.content.clone(),
+        );
+
+        let mut tool_calls = Vec::new();
+        let empty = Vec::new();
+        for tool_call in choice.message.tool_calls.as_ref().unwrap_or(&empty) {
+            tool_calls.push(convert_tool_call(tool_call));
+        }
+
+        if contents.is_empty() && !tool_calls.is_empty() {
+            ChatEvent::ToolRequest(tool_calls)
+        } else {
+            let metadata = ResponseMetadata {
+                finish_reason: choice.finish_reason.as_ref().map(convert_finish_reason),
+                usage: response.usage.as_ref().map(convert_usage),
+                provider_id: None,
+                model: Some(response.model.clone()),
+                timestamp: Some(response.created.to_string()),
+                provider_metadata_json: None,
+                tokens_per_second: None,
+                stop_sequence: None,
+            };
+
+            ChatEvent::Message(CompleteResponse {
+                id: response.id,
+                content: contents,
+                tool_calls,
+                metadata,
+                annotations: vec![],
+            })
+        }
+    } else {
+        ChatEvent::Error(Error {
+            code: ErrorCode::InternalError,
+            message: "No choices in response".to_string(),
+            provider_error_json: None,
+        })
+    }
+}
+
+/// Converts the `reasoning_content` and `content` of a response or a streamed delta to a
+/// reasoning and a text content part, in this order, leaving out the empty ones
+pub fn convert_response_content(
+    reasoning_content: Option<String>,
+    content: Option<String>,
+) -> Vec<ContentPart> {
+    let reasoning = reasoning_content
+        .filter(|reasoning| !reasoning.is_empty())
+        .map(ContentPart::Reasoning);
+    let text = content
+        .filter(|text| !text.is_empty())
+        .map(ContentPart::Text);
+    reasoning.into_iter().chain(text).collect()
+}
+
+pub fn tool_results_to_messages(tool_results: Vec<(ToolCall, ToolResult)>) -> Vec<client::Message> {
+    let mut messages = Vec::new();
+    for (tool_call, tool_result) in tool_results {
+        messages.push(client::Message::Assistant {
+            content: None,
+            name: None,
+            tool_calls: Some(vec![client::ToolCall::Function {
+                function: client::FunctionCall {
+                    arguments: tool_call.arguments_json,
+                    name: tool_call.name,
+                },
+                id: tool_call.id.clone(),
+                index: None,
+            }]),
+        });
+        let content = match tool_result {
+            ToolResult::Success(success) => success.result_json,
+            ToolResult::Error(failure) => failure.error_message,
+        };
+        messages.push(client::Message::Tool {
+            name: None,
+            content: client::Content::TextInput(content),
+            tool_call_id: Some(tool_call.id),
+        });
+    }
+    messages
+}
+
+pub fn convert_tool_call(tool_call: &client::ToolCall) -> ToolCall {
+    match tool_call {
+        client::ToolCall::Function { function, id, .. } => ToolCall {
+            id: id.clone(),
+            name: function.name.clone(),
+            arguments_json: function.arguments.clone(),
+        },
+    }
+}
+
+/// Converts the content of a message, joining its text parts into a string for the providers
+/// accepting only text
+fn convert_content(
+    provider: &ChatCompletionsProvider,
+    contents: Vec<ContentPart>,
+    text_join: &TextJoin,
+) -> Result<client::Content, Error> {
+    let parts = convert_content_parts(contents);
+    if !provider.text_only {
+        return Ok(client::Content::List(parts));
+    }
+    let mut texts = Vec::new();
+    for part in parts {
+        match part {
+            client::ContentPart::TextInput { text } => texts.push(text),
+            client::ContentPart::ImageInput { .. } => return Err(unsupported("image input")),
+        }
+    }
+    Ok(client::Content::TextInput(text_join.join(&texts)))
+}
+
+fn convert_content_parts(contents: Vec<ContentPart>) -> Vec<client::ContentPart> {
+    let mut result = Vec::new();
+    for content in contents {
+        match content {
+            ContentPart::Text(text) => result.push(client::ContentPart::TextInput { text }),
+            ContentPart::Image(image_reference) => match image_reference {
+                ImageReference::Url(image_url) => result.push(client::ContentPart::ImageInput {
+                    image_url: client::ImageUrl {
+                        url: image_url.url,
+                        detail: image_url.detail.map(|d| d.into()),
+                    },
+                }),
+                ImageReference::Inline(image_source) => {
+                    let base64_data = general_purpose::STANDARD.encode(&image_source.data);
+                    let media_type = &image_source.mime_type; // This is already a string
+                    result.push(client::ContentPart::ImageInput {
+                        image_url: client::ImageUrl {
+                            url: format!("data:{};base64,{}", media_type, base64_data),
+                            detail: image_source.detail.map(|d| d.into()),
+                        },
+                    });
+                }
+            },
+            // Tool results are sent in messages of their own by `messages_to_request`
+            ContentPart::ToolResult(_) => {}
+            // The reasoning of earlier responses is not sent back to the model
+            ContentPart::Reasoning(_) => {}
+            // Audio responses are sent back to the model as their transcript
+            ContentPart::Audio(audio) => result.extend(
+                audio
+                    .transcript
+                    .map(|text| client::ContentPart::TextInput { text }),
+            ),
+            // Refusals of earlier responses are sent back to the model as text
+            ContentPart::Refusal(text) => result.push(client::ContentPart::TextInput { text }),
+        }
+    }
+    result
+}
+
+impl From<ImageDetail> for Detail {
+    fn from(value: ImageDetail) -> Self {
+        match value {
+            ImageDetail::Auto => Self::Auto,
+            ImageDetail::Low => Self::Low,
+            ImageDetail::High => Self::High,
+        }
+    }
+}
+
+pub fn convert_finish_reason(value: &client::FinishReason) -> FinishReason {
+    match value {
+        client::FinishReason::Stop => FinishReason::Stop,
+        client::FinishReason::Length => FinishReason::Length,
+        client::FinishReason::EndTurn => FinishReason::Other,
+        client::FinishReason::ToolCalls => FinishReason::ToolCalls,
+        client::FinishReason::ContentFilter => FinishReason::ContentFilter,
+        client::FinishReason::InsufficientSystemResource => FinishReason::Error,
+    }
+}
+
+pub fn convert_usage(value: &client::Usage) -> Usage {
+    with_total_tokens(Usage {
+        input_tokens: Some(value.prompt_tokens),
+        output_tokens: Some(value.completion_tokens),
+        total_tokens: Some(value.total_tokens),
+    })
+}
+
+pub fn tool_definition_to_tool(tool: ToolDefinition) -> Result<client::Tool, Error> {
+    match serde_json::from_str(&tool.parameters_schema) {
+        Ok(value) => Ok(client::Tool::Function {
+            function: client::Function {
+                name: tool.name,
+                description: tool.description,
+                parameters: Some(value),
+            },
+        }),
+        Err(error) => Err(Error {
+            code: ErrorCode::InternalError,
+            message: format!("Failed to parse tool parameters for {}: {error}", tool.name),
+            provider_error_json: None,
+        }),
+    }
+}
@@ -0,0 +1,6 @@
+// Shared implementation of the providers exposing an OpenAI-compatible Chat Completions API,
+// which only differ in their endpoint, authentication and a few settings
+
+pub mod client;
+pub mod conversions;
+pub mod stream;
@@ -0,0 +1,342 @@
+use crate::chat_completions::client::{ChatCompletionChunk, ToolCallChunk};
+use crate::chat_completions::conversions::{
+    convert_finish_reason, convert_response_content, convert_usage,
+};
+use crate::chat_stream::{LlmChatStream, LlmChatStreamState};
+use crate::event_source::EventSource;
+use crate::golem::llm::llm::{Error, ResponseMetadata, StreamDelta, StreamEvent, ToolCall};
+use log::trace;
+use std::cell::{Ref, RefCell, RefMut};
+use std::collections::BTreeMap;
+
+/// The state of a streamed Chat Completions response, finished by the chunk carrying the usage,
+/// or by the end of the stream when the usage was not requested
+pub struct ChatCompletionsStream {
+    stream: RefCell<Option<EventSource>>,
+    failure: Option<Error>,
+    finished: RefCell<bool>,
+    /// The finish reason of the choice, with the model and creation time of its chunk, sent with
+    /// the usage of the following chunk, or at the end of the stream without one
+    finish: RefCell<Option<ResponseMetadata>>,
+    /// The tool calls being streamed by their index, emitted once the choice is finished
+    tool_calls: RefCell<BTreeMap<u32, ToolCall>>,
+}
+
+impl ChatCompletionsStream {
+    fn state(stream: Option<EventSource>, failure: Option<Error>) -> Self {
+        ChatCompletionsStream {
+            stream: RefCell::new(stream),
+            failure,
+            finished: RefCell::new(false),
+            finish: RefCell::new(None),
+            tool_calls: RefCell::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn new(stream: EventSource) -> LlmChatStream<Self> {
+        LlmChatStream::new(Self::state(Some(stream), None))
+    }
+
+    pub fn failed(error: Error) -> LlmChatStream<Self> {
+        LlmChatStream::new(Self::state(None, Some(error)))
+    }
+
+    pub fn dry_run(events: Vec<StreamEvent>) -> LlmChatStream<Self> {
+        LlmChatStream::from_events(Self::state(None, None), events)
+    }
+
+    /// Appends the streamed fragments to the tool calls, which get their id and name from their
+    /// first fragment
+    fn add_tool_call_chunks(&self, chunks: Vec<ToolCallChunk>) {
+        let mut tool_calls = self.tool_calls.borrow_mut();
+        for chunk in chunks {
+            let tool_call = tool_calls.entry(chunk.index).or_insert_with(|| ToolCall {
+                id: String::new(),
+                name: String::new(),
+                arguments_json: String::new(),
+            });
+            if let Some(id) = chunk.id {
+                tool_call.id = id;
+            }
+            if let Some(function) = chunk.function {
+                if let Some(name) = function.name {
+                    tool_call.name = name;
+                }
+                if let Some(arguments) = function.arguments {
+                    tool_call.arguments_json.push_str(&arguments);
+                }
+            }
+        }
+    }
+}
+
+impl LlmChatStreamState for ChatCompletionsStream {
+    fn failure(&self) -> &Option<Error> {
+        &self.failure
+    }
+
+    fn is_finished(&self) -> bool {
+        *self.finished.borrow()
+    }
+
+    fn set_finished(&self) {
+        *self.finished.borrow_mut() = true;
+    }
+
+    fn stream(&self) -> Ref<Option<EventSource>> {
+        self.stream.borrow()
+    }
+
+    fn stream_mut(&self) -> RefMut<Option<EventSource>> {
+        self.stream.borrow_mut()
+    }
+
+    fn pending_finish(&self) -> Option<ResponseMetadata> {
+        self.finish.borrow().clone()
+    }
+
+    fn decode_message(&self, _event: &str, raw: &str) -> Result<Option<StreamEvent>, String> {
+        trace!("Received raw stream event: {raw}");
+        let json: serde_json::Value = serde_json::from_str(raw)
+            .map_err(|err| format!("Failed to deserialize stream event: {err}"))?;
+
+        let typ = json
+            .as_object()
+            .and_then(|obj| obj.get("object"))
+            .and_then(|v| v.as_str());
+        match typ {
+            Some("chat.completion.chunk") => {
+                let message: ChatCompletionChunk = serde_json::from_value(json)
+                    .map_err(|err| format!("Failed to parse stream event: {err}"))?;
+                if let Some(choice) = message.choices.into_iter().next() {
+                    self.add_tool_call_chunks(choice.delta.tool_calls.unwrap_or_default());
+
+                    let tool_calls = if let Some(finish_reason) = choice.finish_reason {
+                        *self.finish.borrow_mut() = Some(ResponseMetadata {
+                            finish_reason: Some(convert_finish_reason(&finish_reason)),
+                            usage: None,
+                            provider_id: None,
+                            model: Some(message.model),
+                            timestamp: Some(message.created.to_string()),
+                            provider_metadata_json: None,
+                            tokens_per_second: None,
+                            stop_sequence: None,
+                        });
+                        self.tool_calls.take().into_values().collect()
+                    } else {
+                        Vec::new()
+                    };
+                    let content = convert_response_content(
+                        choice.delta.reasoning_content,
+                        choice.delta.content,
+                    );
+
+                    Ok(Some(StreamEvent::Delta(StreamDelta {
+                        content: (!content.is_empty()).then_some(content),
+                        tool_calls: (!tool_calls.is_empty()).then_some(tool_calls),
+                        annotations: None,
+                        tool_call_fragments: None,
+                        running_usage: None,
+                    })))
+                } else if let Some(usage) = message.usage {
+                    let finish_reason = self
+                        .finish
+                        .borrow()
+                        .as_ref()
+                        .and_then(|finish| finish.finish_reason);
+                    Ok(Some(StreamEvent::Finish(ResponseMetadata {
+                        finish_reason,
+                        usage: Some(convert_usage(&usage)),
+                        provider_id: None,
+                        model: Some(message.model),
+                        timestamp: Some(message.created.to_string()),
+                        provider_metadata_json: None,
+                        tokens_per_second: None,
+                        stop_sequence: None,
+                    })))
+                } else {
+                    Ok(None)
+                }
+            }
+            Some(_) => Ok(None),
+            None => Err("Unexpected stream event format, does not have 'object' field".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chat_completions::stream::ChatCompletionsStream;
+    use crate::chat_stream::{append_delta_text, LlmChatStream};
+    use crate::event_source::StreamFormat;
+    use crate::golem::llm::llm::{
+        ContentPart, FinishReason, ResponseMetadata, StreamDelta, StreamEvent, ToolCall, Usage,
+    };
+
+    fn decode_transcript(transcript: &str) -> Vec<StreamEvent> {
+        LlmChatStream::new(ChatCompletionsStream::state(None, None))
+            .decode_transcript(transcript, StreamFormat::EventStream)
+    }
+
+    fn delta(content: Option<Vec<ContentPart>>, tool_calls: Option<Vec<ToolCall>>) -> StreamEvent {
+        StreamEvent::Delta(StreamDelta {
+            content,
+            tool_calls,
+            annotations: None,
+            tool_call_fragments: None,
+            running_usage: None,
+        })
+    }
+
+    #[test]
+    fn chunks_decode_to_deltas_finished_by_the_usage_chunk() {
+        let events = decode_transcript(concat!(
+            r#"data: {"id":"1","object":"chat.completion.chunk","created":1,"model":"m","choices":[{"index":0,"delta":{"role":"assistant","content":"Hello"},"finish_reason":null}]}"#,
+            "\n\n",
+            r#"data: {"id":"1","object":"chat.completion.chunk","created":1,"model":"m","choices":[{"index":0,"delta":{"content":" world"},"finish_reason":"stop"}]}"#,
+            "\n\n",
+            r#"data: {"id":"1","object":"chat.completion.chunk","created":1,"model":"m","choices":[],"usage":{"prompt_tokens":5,"completion_tokens":2,"total_tokens":7}}"#,
+            "\n\n",
+            "data: [DONE]\n\n",
+        ));
+
+        let (finish, deltas) = events.split_last().unwrap();
+        let mut text = String::new();
+        for event in deltas {
+            match event {
+                StreamEvent::Delta(delta) => append_delta_text(&mut text, delta),
+                event => panic!("Expected a delta event, got {event:?}"),
+            }
+        }
+        assert_eq!(text, "Hello world");
+
+        match finish {
+            StreamEvent::Finish(metadata) => {
+                assert_eq!(metadata.finish_reason, Some(FinishReason::Stop));
+                assert_eq!(
+                    metadata.usage,
+                    Some(Usage {
+                        input_tokens: Some(5),
+                        output_tokens: Some(2),
+                        total_tokens: Some(7),
+                    })
+                );
+                assert_eq!(metadata.model, Some("m".to_string()));
+            }
+            event => panic!("Expected a finish event, got {event:?}"),
+        }
+    }
+
+    #[test]
+    fn stream_without_usage_is_finished_by_the_done_sentinel() {
+        // Sent with `stream_options.include_usage` set to false, so no chunk carries the usage
+        let events = decode_transcript(concat!(
+            r#"data: {"id":"1","object":"chat.completion.chunk","created":1,"model":"m","choices":[{"index":0,"delta":{"role":"assistant","content":"Hello"},"finish_reason":null}]}"#,
+            "\n\n",
+            r#"data: {"id":"1","object":"chat.completion.chunk","created":1,"model":"m","choices":[{"index":0,"delta":{"content":" world"},"finish_reason":"length"}]}"#,
+            "\n\n",
+            "data: [DONE]\n\n",
+        ));
+
+        assert_eq!(
+            events,
+            vec![
+                delta(Some(vec![ContentPart::Text("Hello".to_string())]), None),
+                delta(Some(vec![ContentPart::Text(" world".to_string())]), None),
+                StreamEvent::Finish(ResponseMetadata {
+                    finish_reason: Some(FinishReason::Length),
+                    usage: None,
+                    provider_id: None,
+                    model: Some("m".to_string()),
+                    timestamp: Some("1".to_string()),
+                    provider_metadata_json: None,
+                    tokens_per_second: None,
+                    stop_sequence: None,
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn reasoning_content_is_streamed_as_reasoning() {
+        let events = decode_transcript(concat!(
+            r#"data: {"id":"1","object":"chat.completion.chunk","created":1740000000,"model":"deepseek-reasoner","choices":[{"index":0,"delta":{"role":"assistant","content":null,"reasoning_content":""},"finish_reason":null}]}"#,
+            "\n\n",
+            r#"data: {"id":"1","object":"chat.completion.chunk","created":1740000000,"model":"deepseek-reasoner","choices":[{"index":0,"delta":{"content":null,"reasoning_content":"Comparing the decimals"},"finish_reason":null}]}"#,
+            "\n\n",
+            r#"data: {"id":"1","object":"chat.completion.chunk","created":1740000000,"model":"deepseek-reasoner","choices":[{"index":0,"delta":{"content":"9.8 is larger","reasoning_content":null},"finish_reason":null}]}"#,
+            "\n\n",
+            r#"data: {"id":"1","object":"chat.completion.chunk","created":1740000000,"model":"deepseek-reasoner","choices":[{"index":0,"delta":{"content":"","reasoning_content":null},"finish_reason":"stop"}]}"#,
+            "\n\n",
+        ));
+
+        assert_eq!(
+            events,
+            vec![
+                delta(None, None),
+                delta(
+                    Some(vec![ContentPart::Reasoning(
+                        "Comparing the decimals".to_string()
+                    )]),
+                    None
+                ),
+                delta(
+                    Some(vec![ContentPart::Text("9.8 is larger".to_string())]),
+                    None
+                ),
+                delta(None, None),
+                StreamEvent::Finish(ResponseMetadata {
+                    finish_reason: Some(FinishReason::Stop),
+                    usage: None,
+                    provider_id: None,
+                    model: Some("deepseek-reasoner".to_string()),
+                    timestamp: Some("1740000000".to_string()),
+                    provider_metadata_json: None,
+                    tokens_per_second: None,
+                    stop_sequence: None,
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn streamed_tool_calls_are_emitted_when_finished() {
+        let events = decode_transcript(concat!(
+            r#"data: {"id":"2","object":"chat.completion.chunk","created":1740000000,"model":"deepseek-chat","choices":[{"index":0,"delta":{"tool_calls":[{"index":0,"id":"call_0_a4a2f1d3","type":"function","function":{"name":"get_weather","arguments":""}}]},"finish_reason":null}]}"#,
+            "\n\n",
+            r#"data: {"id":"2","object":"chat.completion.chunk","created":1740000000,"model":"deepseek-chat","choices":[{"index":0,"delta":{"tool_calls":[{"index":0,"function":{"arguments":"{\"city\":"}}]},"finish_reason":null}]}"#,
+            "\n\n",
+            r#"data: {"id":"2","object":"chat.completion.chunk","created":1740000000,"model":"deepseek-chat","choices":[{"index":0,"delta":{"tool_calls":[{"index":0,"function":{"arguments":"\"Hangzhou\"}"}}]},"finish_reason":null}]}"#,
+            "\n\n",
+            r#"data: {"id":"2","object":"chat.completion.chunk","created":1740000000,"model":"deepseek-chat","choices":[{"index":0,"delta":{"content":""},"finish_reason":"tool_calls"}]}"#,
+            "\n\n",
+        ));
+
+        assert_eq!(
+            events,
+            vec![
+                delta(None, None),
+                delta(None, None),
+                delta(None, None),
+                delta(
+                    None,
+                    Some(vec![ToolCall {
+                        id: "call_0_a4a2f1d3".to_string(),
+                        name: "get_weather".to_string(),
+                        arguments_json: r#"{"city":"Hangzhou"}"#.to_string(),
+                    }])
+                ),
+                StreamEvent::Finish(ResponseMetadata {
+                    finish_reason: Some(FinishReason::ToolCalls),
+                    usage: None,
+                    provider_id: None,
+                    model: Some("deepseek-chat".to_string()),
+                    timestamp: Some("1740000000".to_string()),
+                    provider_metadata_json: None,
+                    tokens_per_second: None,
+                    stop_sequence: None,
+                }),
+            ]
+        );
+    }
+}
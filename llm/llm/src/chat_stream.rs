@@ -1,8 +1,19 @@
-use crate::event_source::{Event, EventSource, MessageEvent};
-use crate::golem::llm::llm::{Error, ErrorCode, GuestChatStream, StreamEvent};
+use crate::clock::{Clock, MonotonicClock};
+use crate::event_source::{parse_transcript, Event, EventSource, MessageEvent, StreamFormat};
+use crate::golem::llm::llm::{
+    Config, ContentPart, Error, ErrorCode, FinishReason, GuestChatStream, ResponseMetadata,
+    StreamDelta, StreamEvent, Usage,
+};
+use crate::usage::{is_streaming_running_usage, with_total_tokens, RunningUsage};
 use golem_rust::wasm_rpc::Pollable;
-use std::cell::{Ref, RefMut};
+use log::{trace, warn};
+use std::cell::{Cell, Ref, RefCell, RefMut};
+use std::rc::Rc;
 use std::task::Poll;
+use std::time::{Duration, Instant};
+
+/// Literal data sent by OpenAI-style SSE streams as their last message
+const DONE_SENTINEL: &str = "[DONE]";
 
 pub trait LlmChatStreamState: 'static {
     fn failure(&self) -> &Option<Error>;
@@ -10,16 +21,100 @@ pub trait LlmChatStreamState: 'static {
     fn set_finished(&self);
     fn stream(&self) -> Ref<Option<EventSource>>;
     fn stream_mut(&self) -> RefMut<Option<EventSource>>;
-    fn decode_message(&self, raw: &str) -> Result<Option<StreamEvent>, String>;
+    /// Decodes a single message of the stream. `event` is the name of the SSE event, which is
+    /// `message` when the provider does not name its events.
+    fn decode_message(&self, event: &str, raw: &str) -> Result<Option<StreamEvent>, String>;
+
+    /// The usage reported by the provider before its finish event, like the input tokens of
+    /// Anthropic's `message_start` event. `None` by default, for providers only reporting the
+    /// usage when finishing.
+    fn known_usage(&self) -> Option<Usage> {
+        None
+    }
+
+    /// The metadata of the finish event sent at the end of the stream, for providers reporting it
+    /// along with the last content, which is sent as a delta first. `None` by default, for
+    /// providers sending their own finish event.
+    fn pending_finish(&self) -> Option<ResponseMetadata> {
+        None
+    }
 }
 
 pub struct LlmChatStream<T> {
     implementation: T,
+    predefined_events: RefCell<Vec<StreamEvent>>,
+    tool_calls_received: Cell<bool>,
+    first_delta_received_at: Cell<Option<Instant>>,
+    partial_text: RefCell<String>,
+    /// The output tokens received so far, tracked even when they are not reported on the deltas
+    running_usage: RefCell<RunningUsage>,
+    /// Whether the running usage is reported on the deltas, enabled by
+    /// `GOLEM_LLM_STREAM_RUNNING_USAGE`
+    streaming_running_usage: bool,
+    /// Whether a finish or error event was emitted, after which the end of the stream is expected
+    terminal_event_emitted: Cell<bool>,
+    /// The last running usage reported on the deltas
+    last_running_usage: RefCell<Option<Usage>>,
+    clock: Rc<dyn Clock>,
+    /// The instant by which the stream has to be over, set by the `deadline-ms` of the config
+    deadline: Option<Instant>,
+}
+
+/// Returns the instant by which a stream of a request sent now has to be over, according to the
+/// `deadline-ms` of its config
+pub fn request_deadline(config: &Config) -> Option<Instant> {
+    config
+        .deadline_ms
+        .map(|deadline_ms| MonotonicClock.now() + Duration::from_millis(deadline_ms as u64))
 }
 
 impl<T: LlmChatStreamState> LlmChatStream<T> {
     pub fn new(implementation: T) -> Self {
-        Self { implementation }
+        Self {
+            implementation,
+            predefined_events: RefCell::new(Vec::new()),
+            tool_calls_received: Cell::new(false),
+            first_delta_received_at: Cell::new(None),
+            partial_text: RefCell::new(String::new()),
+            running_usage: RefCell::new(RunningUsage::default()),
+            streaming_running_usage: is_streaming_running_usage(),
+            terminal_event_emitted: Cell::new(false),
+            last_running_usage: RefCell::new(None),
+            clock: Rc::new(MonotonicClock),
+            deadline: None,
+        }
+    }
+
+    /// Creates a stream emitting the given events at once and then finishing, without reading
+    /// anything from the underlying event source
+    pub fn from_events(implementation: T, events: Vec<StreamEvent>) -> Self {
+        Self {
+            implementation,
+            predefined_events: RefCell::new(events),
+            tool_calls_received: Cell::new(false),
+            first_delta_received_at: Cell::new(None),
+            partial_text: RefCell::new(String::new()),
+            running_usage: RefCell::new(RunningUsage::default()),
+            streaming_running_usage: is_streaming_running_usage(),
+            terminal_event_emitted: Cell::new(false),
+            last_running_usage: RefCell::new(None),
+            clock: Rc::new(MonotonicClock),
+            deadline: None,
+        }
+    }
+
+    /// Replaces the clock measuring the timing of the stream, which is the monotonic clock by
+    /// default
+    pub fn with_clock(mut self, clock: Rc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Ends the stream with a `timeout` error if it is still going on at the deadline, measured
+    /// by the clock of the stream
+    pub fn with_deadline(mut self, deadline: Option<Instant>) -> Self {
+        self.deadline = deadline;
+        self
     }
 
     pub fn subscribe(&self) -> Pollable {
@@ -29,6 +124,259 @@ impl<T: LlmChatStreamState> LlmChatStream<T> {
             golem_rust::bindings::wasi::clocks::monotonic_clock::subscribe_duration(0)
         }
     }
+
+    /// Decodes the data of a single received message. The `[DONE]` sentinel terminates the stream
+    /// without being passed to the provider specific decoder, with a finish event if none was
+    /// received before it.
+    fn decode_message_data(&self, event: &str, data: &str) -> Vec<StreamEvent> {
+        // Some providers, and proxies merging streams, send more than one finish event or done
+        // marker. Only the first one ends the stream, the following messages are ignored
+        if self.implementation.is_finished() {
+            trace!("Ignoring a message received after the end of the stream: {data}");
+            return vec![];
+        }
+
+        if data == DONE_SENTINEL {
+            return self.end_of_stream();
+        }
+
+        match self.implementation.decode_message(event, data) {
+            Ok(Some(stream_event)) => {
+                let stream_event = self.with_running_usage(
+                    self.with_tokens_per_second(self.with_tool_calls_finish_reason(stream_event)),
+                );
+                self.record_partial_text(&stream_event);
+                self.record_terminal_event(&stream_event);
+                if matches!(stream_event, StreamEvent::Finish(_)) {
+                    self.implementation.set_finished();
+                }
+                vec![stream_event]
+            }
+            Ok(None) => {
+                // Ignored event
+                vec![]
+            }
+            Err(error) => {
+                self.terminal_event_emitted.set(true);
+                vec![StreamEvent::Error(Error {
+                    code: ErrorCode::InternalError,
+                    message: error,
+                    provider_error_json: None,
+                })]
+            }
+        }
+    }
+
+    /// Decodes the complete raw SSE or NDJSON text of a stream the way a live stream decodes the
+    /// messages it receives, so the provider decoders can be tested against recorded transcripts.
+    /// The messages after the end of the stream or a decoding error are ignored, and a transcript
+    /// ending without a finish event is finished like a stream closed by the provider.
+    pub fn decode_transcript(&self, transcript: &str, format: StreamFormat) -> Vec<StreamEvent> {
+        let mut events = Vec::new();
+        for message in parse_transcript(transcript, format) {
+            if self.implementation.is_finished() || self.terminal_event_emitted.get() {
+                break;
+            }
+            events.extend(self.decode_message_data(&message.event, &message.data));
+        }
+        if !self.implementation.is_finished() {
+            events.extend(self.end_of_stream());
+        }
+        events
+    }
+
+    /// Ends the stream with a `timeout` error, closing its connection, once its deadline has
+    /// passed. Being returned by `get_next`, the error is persisted by the durable streams, so a
+    /// replay ends at the same point instead of streaming past the deadline again.
+    fn deadline_exceeded(&self) -> Option<Vec<StreamEvent>> {
+        let deadline = self.deadline?;
+        if self.terminal_event_emitted.get() || self.clock.now() < deadline {
+            return None;
+        }
+
+        warn!("The stream did not finish before its deadline, closing it");
+        self.terminal_event_emitted.set(true);
+        self.implementation.set_finished();
+        if let Some(stream) = self.implementation.stream_mut().as_mut() {
+            stream.close();
+        }
+        Some(vec![StreamEvent::Error(Error {
+            code: ErrorCode::Timeout,
+            message: "The stream did not finish before its deadline".to_string(),
+            provider_error_json: None,
+        })])
+    }
+
+    /// The usage of the response received so far, whether or not the running usage is reported on
+    /// the deltas: the usage reported by the provider, with the output tokens estimated from the
+    /// received text when it did not report more. `None` until anything is known.
+    pub fn usage_so_far(&self) -> Option<Usage> {
+        let known_usage = self.implementation.known_usage();
+        let received_tokens =
+            Some(self.running_usage.borrow().output_tokens()).filter(|tokens| *tokens > 0);
+        let input_tokens = known_usage.as_ref().and_then(|usage| usage.input_tokens);
+        let output_tokens = match (
+            known_usage.as_ref().and_then(|usage| usage.output_tokens),
+            received_tokens,
+        ) {
+            (Some(known), Some(received)) => Some(known.max(received)),
+            (known, received) => known.or(received),
+        };
+        (input_tokens.is_some() || output_tokens.is_some()).then(|| {
+            with_total_tokens(Usage {
+                input_tokens,
+                output_tokens,
+                total_tokens: None,
+            })
+        })
+    }
+
+    /// Pollable ready at the deadline of the stream, if it has one
+    pub fn subscribe_deadline(&self) -> Option<Pollable> {
+        self.deadline.map(|deadline| {
+            let remaining = deadline.saturating_duration_since(self.clock.now());
+            golem_rust::bindings::wasi::clocks::monotonic_clock::subscribe_duration(
+                remaining.as_nanos() as u64,
+            )
+        })
+    }
+
+    /// Finishes the stream when the provider closed it, with the pending finish event of the
+    /// provider if it has one. Some OpenAI-compatible servers close the connection without sending
+    /// a finish event, in which case one is synthesized with the `Stop` finish reason and the last
+    /// running usage, so the consumers still get the end of the response. Being returned by
+    /// `get_next`, it is persisted by the durable streams like any other event.
+    fn end_of_stream(&self) -> Vec<StreamEvent> {
+        self.implementation.set_finished();
+        if self.terminal_event_emitted.replace(true) {
+            return vec![];
+        }
+
+        let metadata = self.implementation.pending_finish().unwrap_or_else(|| {
+            warn!(
+                "The stream was closed without a finish event, finishing it with the stop reason"
+            );
+            ResponseMetadata {
+                finish_reason: Some(FinishReason::Stop),
+                usage: self.last_running_usage.borrow().clone(),
+                provider_id: None,
+                model: None,
+                timestamp: None,
+                provider_metadata_json: None,
+                tokens_per_second: None,
+                stop_sequence: None,
+            }
+        });
+        let finish = self.with_tokens_per_second(
+            self.with_tool_calls_finish_reason(StreamEvent::Finish(metadata)),
+        );
+        vec![finish]
+    }
+}
+
+impl<T> LlmChatStream<T> {
+    fn record_partial_text(&self, event: &StreamEvent) {
+        if let StreamEvent::Delta(delta) = event {
+            append_delta_text(&mut self.partial_text.borrow_mut(), delta);
+        }
+    }
+
+    /// Records whether the stream reached a finish or error event, and the running usage of the
+    /// deltas
+    fn record_terminal_event(&self, event: &StreamEvent) {
+        match event {
+            StreamEvent::Delta(StreamDelta {
+                running_usage: Some(running_usage),
+                ..
+            }) => {
+                *self.last_running_usage.borrow_mut() = Some(running_usage.clone());
+            }
+            StreamEvent::Finish(_) | StreamEvent::Error(_) => self.terminal_event_emitted.set(true),
+            _ => {}
+        }
+    }
+
+    /// Records whether any tool call was received, and reports `ToolCalls` as the finish reason
+    /// of a stream containing tool calls, unless the provider reported a reason other than `Stop`
+    fn with_tool_calls_finish_reason(&self, event: StreamEvent) -> StreamEvent {
+        if let StreamEvent::Delta(delta) = &event {
+            if delta
+                .tool_calls
+                .as_ref()
+                .is_some_and(|tool_calls| !tool_calls.is_empty())
+            {
+                self.tool_calls_received.set(true);
+            }
+        }
+
+        match event {
+            StreamEvent::Finish(mut metadata)
+                if self.tool_calls_received.get()
+                    && matches!(metadata.finish_reason, None | Some(FinishReason::Stop)) =>
+            {
+                metadata.finish_reason = Some(FinishReason::ToolCalls);
+                StreamEvent::Finish(metadata)
+            }
+            event => event,
+        }
+    }
+
+    /// Tracks the output tokens received so far, and reports them on the deltas when enabled
+    fn with_running_usage(&self, event: StreamEvent) -> StreamEvent {
+        match event {
+            StreamEvent::Delta(delta) if self.streaming_running_usage => {
+                StreamEvent::Delta(self.running_usage.borrow_mut().with_running_usage(delta))
+            }
+            StreamEvent::Delta(delta) => {
+                self.running_usage.borrow_mut().record(&delta);
+                StreamEvent::Delta(delta)
+            }
+            event => event,
+        }
+    }
+
+    /// Records when the first delta was received, and reports the output tokens per second
+    /// measured since then on the finish event, if the provider reported the output tokens
+    fn with_tokens_per_second(&self, event: StreamEvent) -> StreamEvent {
+        match event {
+            StreamEvent::Delta(_) => {
+                if self.first_delta_received_at.get().is_none() {
+                    self.first_delta_received_at.set(Some(self.clock.now()));
+                }
+                event
+            }
+            StreamEvent::Finish(mut metadata) => {
+                let output_tokens = metadata
+                    .usage
+                    .as_ref()
+                    .and_then(|usage| usage.output_tokens);
+                metadata.tokens_per_second =
+                    self.first_delta_received_at.get().and_then(|first_delta| {
+                        tokens_per_second(output_tokens, self.clock.now() - first_delta)
+                    });
+                StreamEvent::Finish(metadata)
+            }
+            event => event,
+        }
+    }
+}
+
+/// Appends the text content of the delta to `text`
+pub fn append_delta_text(text: &mut String, delta: &StreamDelta) {
+    for part in delta.content.iter().flatten() {
+        if let ContentPart::Text(part) = part {
+            text.push_str(part);
+        }
+    }
+}
+
+/// Output tokens per second, `None` if the token count is unknown or no time has been measured
+fn tokens_per_second(output_tokens: Option<u32>, elapsed: Duration) -> Option<f32> {
+    let seconds = elapsed.as_secs_f32();
+    match output_tokens {
+        Some(output_tokens) if seconds > 0.0 => Some(output_tokens as f32 / seconds),
+        _ => None,
+    }
 }
 
 impl<T: LlmChatStreamState> GuestChatStream for LlmChatStream<T> {
@@ -37,59 +385,57 @@ impl<T: LlmChatStreamState> GuestChatStream for LlmChatStream<T> {
             return Some(vec![]);
         }
 
+        if let Some(events) = self.deadline_exceeded() {
+            return Some(events);
+        }
+
+        let predefined_events = self.predefined_events.take();
+        if !predefined_events.is_empty() {
+            self.implementation.set_finished();
+            for event in &predefined_events {
+                self.record_partial_text(event);
+            }
+            return Some(predefined_events);
+        }
+
         let mut stream = self.implementation.stream_mut();
         if let Some(stream) = stream.as_mut() {
-            match stream.poll_next() {
-                Poll::Ready(None) => {
-                    self.implementation.set_finished();
-                    Some(vec![])
-                }
+            let events = match stream.poll_next() {
+                Poll::Ready(None) => Some(self.end_of_stream()),
                 Poll::Ready(Some(Err(crate::event_source::error::Error::StreamEnded))) => {
-                    self.implementation.set_finished();
-                    Some(vec![])
+                    Some(self.end_of_stream())
+                }
+                Poll::Ready(Some(Err(error))) => {
+                    self.terminal_event_emitted.set(true);
+                    Some(vec![StreamEvent::Error(Error {
+                        code: ErrorCode::InternalError,
+                        message: error.to_string(),
+                        provider_error_json: None,
+                    })])
                 }
-                Poll::Ready(Some(Err(error))) => Some(vec![StreamEvent::Error(Error {
-                    code: ErrorCode::InternalError,
-                    message: error.to_string(),
-                    provider_error_json: None,
-                })]),
                 Poll::Ready(Some(Ok(event))) => {
-                    let mut events = vec![];
-
-                    match event {
-                        Event::Open => {}
-                        Event::Message(MessageEvent { data, .. }) => {
-                            if data != "[DONE]" {
-                                match self.implementation.decode_message(&data) {
-                                    Ok(Some(stream_event)) => {
-                                        if matches!(stream_event, StreamEvent::Finish(_)) {
-                                            self.implementation.set_finished();
-                                        }
-                                        events.push(stream_event);
-                                    }
-                                    Ok(None) => {
-                                        // Ignored event
-                                    }
-                                    Err(error) => {
-                                        events.push(StreamEvent::Error(Error {
-                                            code: ErrorCode::InternalError,
-                                            message: error,
-                                            provider_error_json: None,
-                                        }));
-                                    }
-                                }
-                            }
+                    let events = match event {
+                        Event::Open => vec![],
+                        Event::Message(MessageEvent { event, data, .. }) => {
+                            self.decode_message_data(&event, &data)
                         }
-                    }
+                    };
 
-                    if events.is_empty() {
+                    if events.is_empty() && !self.implementation.is_finished() {
                         None
                     } else {
                         Some(events)
                     }
                 }
                 Poll::Pending => None,
+            };
+
+            // Releases the connection as soon as the response is over, instead of when the stream
+            // gets dropped
+            if self.implementation.is_finished() || self.terminal_event_emitted.get() {
+                stream.close();
             }
+            events
         } else if let Some(error) = self.implementation.failure().clone() {
             self.implementation.set_finished();
             Some(vec![StreamEvent::Error(error)])
@@ -100,9 +446,16 @@ impl<T: LlmChatStreamState> GuestChatStream for LlmChatStream<T> {
 
     fn blocking_get_next(&self) -> Vec<StreamEvent> {
         let pollable = self.subscribe();
+        // Wakes up at the deadline even if the provider stops sending anything
+        let deadline = self.subscribe_deadline();
         let mut result = Vec::new();
         loop {
-            pollable.block();
+            match &deadline {
+                Some(deadline) => {
+                    golem_rust::bindings::wasi::io::poll::poll(&[&pollable, deadline]);
+                }
+                None => pollable.block(),
+            }
             match self.get_next() {
                 Some(events) => {
                     result.extend(events);
@@ -112,4 +465,548 @@ impl<T: LlmChatStreamState> GuestChatStream for LlmChatStream<T> {
             }
         }
     }
+
+    fn partial_text(&self) -> String {
+        self.partial_text.borrow().clone()
+    }
+
+    fn raw_transcript(&self) -> String {
+        self.implementation
+            .stream()
+            .as_ref()
+            .and_then(|stream| stream.raw_transcript())
+            .unwrap_or_default()
+            .to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chat_stream::{tokens_per_second, LlmChatStream, LlmChatStreamState};
+    use crate::clock::{Clock, FakeClock};
+    use crate::event_source::{EventSource, ReadyState, StreamFormat};
+    use crate::golem::llm::llm::{
+        ContentPart, Error, ErrorCode, FinishReason, GuestChatStream, ResponseMetadata,
+        StreamDelta, StreamEvent, ToolCall, Usage,
+    };
+    use std::cell::{Ref, RefCell, RefMut};
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    /// Stream state without an underlying event source, decoding unnamed and `delta` events as
+    /// text deltas, `tool` events as tool calls, `usage` events as deltas reporting their data as
+    /// the running output token count, `finish` events as the end of the stream, with their data
+    /// as the output token count if it is a number, and `invalid` events as decoding errors
+    struct TextStreamState {
+        stream: RefCell<Option<EventSource>>,
+        failure: Option<Error>,
+        finished: RefCell<bool>,
+    }
+
+    impl TextStreamState {
+        fn new() -> LlmChatStream<Self> {
+            LlmChatStream::new(TextStreamState {
+                stream: RefCell::new(None),
+                failure: None,
+                finished: RefCell::new(false),
+            })
+        }
+
+        fn with_source(source: EventSource) -> LlmChatStream<Self> {
+            LlmChatStream::new(TextStreamState {
+                stream: RefCell::new(Some(source)),
+                failure: None,
+                finished: RefCell::new(false),
+            })
+        }
+    }
+
+    impl LlmChatStreamState for TextStreamState {
+        fn failure(&self) -> &Option<Error> {
+            &self.failure
+        }
+
+        fn is_finished(&self) -> bool {
+            *self.finished.borrow()
+        }
+
+        fn set_finished(&self) {
+            *self.finished.borrow_mut() = true;
+        }
+
+        fn stream(&self) -> Ref<Option<EventSource>> {
+            self.stream.borrow()
+        }
+
+        fn stream_mut(&self) -> RefMut<Option<EventSource>> {
+            self.stream.borrow_mut()
+        }
+
+        fn decode_message(&self, event: &str, raw: &str) -> Result<Option<StreamEvent>, String> {
+            match event {
+                "message" | "delta" => Ok(Some(StreamEvent::Delta(StreamDelta {
+                    content: Some(vec![ContentPart::Text(raw.to_string())]),
+                    tool_calls: None,
+                    annotations: None,
+                    tool_call_fragments: None,
+                    running_usage: None,
+                }))),
+                "tool" => Ok(Some(StreamEvent::Delta(StreamDelta {
+                    content: None,
+                    tool_calls: Some(vec![ToolCall {
+                        id: "call-1".to_string(),
+                        name: raw.to_string(),
+                        arguments_json: "{}".to_string(),
+                    }]),
+                    annotations: None,
+                    tool_call_fragments: None,
+                    running_usage: None,
+                }))),
+                "usage" => Ok(Some(StreamEvent::Delta(StreamDelta {
+                    content: None,
+                    tool_calls: None,
+                    annotations: None,
+                    tool_call_fragments: None,
+                    running_usage: Some(Usage {
+                        input_tokens: None,
+                        output_tokens: raw.parse::<u32>().ok(),
+                        total_tokens: None,
+                    }),
+                }))),
+                "finish" => Ok(Some(StreamEvent::Finish(ResponseMetadata {
+                    finish_reason: Some(FinishReason::Stop),
+                    usage: raw.parse::<u32>().ok().map(|output_tokens| Usage {
+                        input_tokens: None,
+                        output_tokens: Some(output_tokens),
+                        total_tokens: None,
+                    }),
+                    provider_id: None,
+                    model: None,
+                    timestamp: None,
+                    provider_metadata_json: None,
+                    tokens_per_second: None,
+                    stop_sequence: None,
+                }))),
+                "invalid" => Err(format!("Invalid event: {raw}")),
+                _ => Ok(None),
+            }
+        }
+    }
+
+    #[test]
+    fn usage_so_far_is_tracked_without_running_usage_on_the_deltas() {
+        let stream = TextStreamState::new();
+        assert_eq!(stream.usage_so_far(), None);
+
+        let mut events = Vec::new();
+        for data in ["Hello", ", world"] {
+            events.extend(stream.decode_message_data("message", data));
+        }
+
+        assert!(events.iter().all(|event| matches!(
+            event,
+            StreamEvent::Delta(StreamDelta {
+                running_usage: None,
+                ..
+            })
+        )));
+        assert_eq!(
+            stream.usage_so_far(),
+            Some(Usage {
+                input_tokens: None,
+                output_tokens: Some(3),
+                total_tokens: None,
+            })
+        );
+    }
+
+    #[test]
+    fn done_sentinel_without_a_finish_event_finishes_the_stream() {
+        let stream = TextStreamState::new();
+
+        let mut events = Vec::new();
+        for data in ["Hello", " world", "[DONE]"] {
+            events.extend(stream.decode_message_data("message", data));
+        }
+
+        assert_eq!(
+            events,
+            vec![
+                StreamEvent::Delta(StreamDelta {
+                    content: Some(vec![ContentPart::Text("Hello".to_string())]),
+                    tool_calls: None,
+                    annotations: None,
+                    tool_call_fragments: None,
+                    running_usage: None,
+                }),
+                StreamEvent::Delta(StreamDelta {
+                    content: Some(vec![ContentPart::Text(" world".to_string())]),
+                    tool_calls: None,
+                    annotations: None,
+                    tool_call_fragments: None,
+                    running_usage: None,
+                }),
+                StreamEvent::Finish(ResponseMetadata {
+                    finish_reason: Some(FinishReason::Stop),
+                    usage: None,
+                    provider_id: None,
+                    model: None,
+                    timestamp: None,
+                    provider_metadata_json: None,
+                    tokens_per_second: None,
+                    stop_sequence: None,
+                }),
+            ]
+        );
+        assert!(stream.implementation.is_finished());
+        assert_eq!(stream.get_next(), Some(vec![]));
+    }
+
+    #[test]
+    fn messages_are_dispatched_by_event_name() {
+        let stream = TextStreamState::new();
+
+        let mut events = Vec::new();
+        for (event, data) in [
+            ("delta", "Hello"),
+            ("ping", "{}"),
+            ("delta", "!"),
+            ("finish", "{}"),
+        ] {
+            events.extend(stream.decode_message_data(event, data));
+        }
+
+        assert_eq!(
+            events,
+            vec![
+                StreamEvent::Delta(StreamDelta {
+                    content: Some(vec![ContentPart::Text("Hello".to_string())]),
+                    tool_calls: None,
+                    annotations: None,
+                    tool_call_fragments: None,
+                    running_usage: None,
+                }),
+                StreamEvent::Delta(StreamDelta {
+                    content: Some(vec![ContentPart::Text("!".to_string())]),
+                    tool_calls: None,
+                    annotations: None,
+                    tool_call_fragments: None,
+                    running_usage: None,
+                }),
+                StreamEvent::Finish(ResponseMetadata {
+                    finish_reason: Some(FinishReason::Stop),
+                    usage: None,
+                    provider_id: None,
+                    model: None,
+                    timestamp: None,
+                    provider_metadata_json: None,
+                    tokens_per_second: None,
+                    stop_sequence: None,
+                }),
+            ]
+        );
+        assert!(stream.implementation.is_finished());
+    }
+
+    #[test]
+    fn messages_after_the_first_finish_are_ignored() {
+        let stream = TextStreamState::new();
+
+        let mut events = Vec::new();
+        for (event, data) in [
+            ("delta", "Hello"),
+            ("finish", "5"),
+            ("finish", "5"),
+            ("delta", " again"),
+            ("message", "[DONE]"),
+        ] {
+            events.extend(stream.decode_message_data(event, data));
+        }
+
+        assert_eq!(
+            events
+                .iter()
+                .filter(|event| matches!(event, StreamEvent::Finish(_)))
+                .count(),
+            1
+        );
+        assert_eq!(events.len(), 2);
+        assert_eq!(stream.partial_text(), "Hello");
+        assert_eq!(stream.get_next(), Some(vec![]));
+    }
+
+    #[test]
+    fn stream_ending_with_tool_call_finishes_with_tool_calls() {
+        let stream = TextStreamState::new();
+
+        let mut events = Vec::new();
+        for (event, data) in [
+            ("delta", "Let me check the weather"),
+            ("tool", "get_weather"),
+            ("finish", "{}"),
+        ] {
+            events.extend(stream.decode_message_data(event, data));
+        }
+
+        assert_eq!(
+            events.last(),
+            Some(&StreamEvent::Finish(ResponseMetadata {
+                finish_reason: Some(FinishReason::ToolCalls),
+                usage: None,
+                provider_id: None,
+                model: None,
+                timestamp: None,
+                provider_metadata_json: None,
+                tokens_per_second: None,
+                stop_sequence: None,
+            }))
+        );
+    }
+
+    #[test]
+    fn stream_closed_without_finish_is_finished_with_stop() {
+        let stream = TextStreamState::new();
+
+        for (event, data) in [("delta", "Hello"), ("usage", "3"), ("delta", " world")] {
+            stream.decode_message_data(event, data);
+        }
+
+        match stream.end_of_stream().as_slice() {
+            [StreamEvent::Finish(metadata)] => {
+                assert_eq!(metadata.finish_reason, Some(FinishReason::Stop));
+                assert_eq!(
+                    metadata.usage,
+                    Some(Usage {
+                        input_tokens: None,
+                        output_tokens: Some(3),
+                        total_tokens: None,
+                    })
+                );
+            }
+            other => panic!("Unexpected events: {other:?}"),
+        }
+        assert_eq!(stream.partial_text(), "Hello world");
+        assert!(stream.implementation.is_finished());
+        assert_eq!(stream.get_next(), Some(vec![]));
+    }
+
+    #[test]
+    fn stream_closed_after_tool_call_without_finish_is_finished_with_tool_calls() {
+        let stream = TextStreamState::new();
+
+        stream.decode_message_data("tool", "get_weather");
+
+        assert!(matches!(
+            stream.end_of_stream().as_slice(),
+            [StreamEvent::Finish(ResponseMetadata {
+                finish_reason: Some(FinishReason::ToolCalls),
+                usage: None,
+                ..
+            })]
+        ));
+    }
+
+    #[test]
+    fn transcript_is_decoded_like_a_live_stream() {
+        let stream = TextStreamState::new();
+
+        let events = stream.decode_transcript(
+            "event: delta\ndata: Hello\n\nevent: tool\ndata: get_weather\n\nevent: finish\ndata: 2\n\nevent: delta\ndata: ignored\n\n",
+            StreamFormat::EventStream,
+        );
+
+        match events.as_slice() {
+            [StreamEvent::Delta(text), StreamEvent::Delta(tool_call), StreamEvent::Finish(metadata)] =>
+            {
+                assert_eq!(
+                    text.content,
+                    Some(vec![ContentPart::Text("Hello".to_string())])
+                );
+                assert!(tool_call.tool_calls.is_some());
+                assert_eq!(metadata.finish_reason, Some(FinishReason::ToolCalls));
+            }
+            other => panic!("Unexpected events: {other:?}"),
+        }
+        assert_eq!(stream.partial_text(), "Hello");
+    }
+
+    #[test]
+    fn transcript_without_finish_is_finished_with_stop() {
+        let stream = TextStreamState::new();
+
+        let events = stream.decode_transcript("Hello\n", StreamFormat::NdJson);
+
+        assert!(matches!(
+            events.as_slice(),
+            [
+                StreamEvent::Delta(_),
+                StreamEvent::Finish(ResponseMetadata {
+                    finish_reason: Some(FinishReason::Stop),
+                    ..
+                })
+            ]
+        ));
+    }
+
+    #[test]
+    fn stream_closed_after_finish_or_error_is_not_finished_again() {
+        let stream = TextStreamState::new();
+        stream.decode_message_data("delta", "Hello");
+        stream.decode_message_data("finish", "{}");
+        assert_eq!(stream.end_of_stream(), vec![]);
+
+        let stream = TextStreamState::new();
+        stream.decode_message_data("delta", "Hello");
+        let events = stream.decode_message_data("invalid", "{");
+        assert!(matches!(events.as_slice(), [StreamEvent::Error(_)]));
+        assert_eq!(stream.end_of_stream(), vec![]);
+    }
+
+    #[test]
+    fn event_source_is_closed_once_the_stream_is_finished() {
+        let stream = TextStreamState::with_source(EventSource::ended());
+        let ready_state = || {
+            stream
+                .implementation
+                .stream()
+                .as_ref()
+                .map(|source| source.ready_state())
+        };
+        assert_eq!(ready_state(), Some(ReadyState::Open));
+
+        let events = stream.get_next().unwrap();
+
+        assert!(matches!(events.as_slice(), [StreamEvent::Finish(_)]));
+        assert_eq!(ready_state(), Some(ReadyState::Closed));
+        assert_eq!(stream.get_next(), Some(vec![]));
+    }
+
+    #[test]
+    fn partial_text_is_the_text_received_so_far() {
+        let stream = TextStreamState::new();
+
+        stream.decode_message_data("delta", "Hello");
+        stream.decode_message_data("tool", "get_weather");
+        assert_eq!(stream.partial_text(), "Hello");
+
+        stream.decode_message_data("delta", " world");
+        assert_eq!(stream.partial_text(), "Hello world");
+        assert!(!stream.implementation.is_finished());
+    }
+
+    #[test]
+    fn tokens_per_second_is_output_tokens_over_elapsed_time() {
+        assert_eq!(
+            tokens_per_second(Some(50), Duration::from_millis(2500)),
+            Some(20.0)
+        );
+        assert_eq!(tokens_per_second(None, Duration::from_secs(1)), None);
+        assert_eq!(tokens_per_second(Some(50), Duration::ZERO), None);
+    }
+
+    #[test]
+    fn finish_reports_tokens_per_second_since_first_delta() {
+        let clock = Rc::new(FakeClock::new());
+        let stream = TextStreamState::new().with_clock(clock.clone());
+
+        clock.advance(Duration::from_secs(3));
+        stream.decode_message_data("delta", "Hello");
+        clock.advance(Duration::from_millis(1500));
+        stream.decode_message_data("delta", " world");
+        clock.advance(Duration::from_millis(500));
+        let events = stream.decode_message_data("finish", "12");
+
+        match events.as_slice() {
+            [StreamEvent::Finish(metadata)] => {
+                assert_eq!(metadata.tokens_per_second, Some(6.0));
+            }
+            other => panic!("Unexpected events: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn finish_without_elapsed_time_has_no_tokens_per_second() {
+        let clock = Rc::new(FakeClock::new());
+        let stream = TextStreamState::new().with_clock(clock);
+
+        stream.decode_message_data("delta", "Hello");
+        let events = stream.decode_message_data("finish", "12");
+
+        assert!(matches!(
+            events.as_slice(),
+            [StreamEvent::Finish(ResponseMetadata {
+                tokens_per_second: None,
+                ..
+            })]
+        ));
+    }
+
+    #[test]
+    fn finish_without_usage_has_no_tokens_per_second() {
+        let stream = TextStreamState::new();
+
+        stream.decode_message_data("delta", "Hello");
+        let events = stream.decode_message_data("finish", "{}");
+
+        assert!(matches!(
+            events.as_slice(),
+            [StreamEvent::Finish(ResponseMetadata {
+                tokens_per_second: None,
+                ..
+            })]
+        ));
+    }
+
+    #[test]
+    fn stream_past_its_deadline_ends_with_a_timeout() {
+        let clock = Rc::new(FakeClock::new());
+        let stream = TextStreamState::new()
+            .with_clock(clock.clone())
+            .with_deadline(Some(clock.now() + Duration::from_millis(100)));
+
+        stream.decode_message_data("delta", "Hello");
+        clock.advance(Duration::from_millis(99));
+        assert_eq!(stream.get_next(), None);
+
+        clock.advance(Duration::from_millis(1));
+        match stream.get_next().unwrap().as_slice() {
+            [StreamEvent::Error(error)] => assert_eq!(error.code, ErrorCode::Timeout),
+            other => panic!("Unexpected events: {other:?}"),
+        }
+        assert_eq!(stream.get_next(), Some(vec![]));
+        assert_eq!(stream.partial_text(), "Hello");
+    }
+
+    #[test]
+    fn deadline_closes_the_event_source() {
+        let clock = Rc::new(FakeClock::new());
+        let stream = TextStreamState::with_source(EventSource::ended())
+            .with_clock(clock.clone())
+            .with_deadline(Some(clock.now()));
+
+        let events = stream.get_next().unwrap();
+
+        assert!(matches!(events.as_slice(), [StreamEvent::Error(_)]));
+        assert_eq!(
+            stream
+                .implementation
+                .stream()
+                .as_ref()
+                .map(|source| source.ready_state()),
+            Some(ReadyState::Closed)
+        );
+    }
+
+    #[test]
+    fn stream_finished_before_its_deadline_is_not_timed_out() {
+        let clock = Rc::new(FakeClock::new());
+        let stream = TextStreamState::new()
+            .with_clock(clock.clone())
+            .with_deadline(Some(clock.now() + Duration::from_millis(100)));
+
+        stream.decode_message_data("finish", "{}");
+        clock.advance(Duration::from_secs(1));
+
+        assert_eq!(stream.get_next(), Some(vec![]));
+    }
 }
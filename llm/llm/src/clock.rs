@@ -0,0 +1,73 @@
+#[cfg(test)]
+use std::cell::Cell;
+#[cfg(test)]
+use std::time::Duration;
+use std::time::Instant;
+
+/// Source of the time measured by the timing dependent features, such as the tokens per second
+/// of the streams and the latency of the health checks
+pub trait Clock {
+    fn now(&self) -> Instant;
+}
+
+/// The monotonic clock, which is WASI's monotonic clock in a component
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MonotonicClock;
+
+impl Clock for MonotonicClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A clock which only moves when advanced, to test the timing dependent features
+/// deterministically
+#[cfg(test)]
+#[derive(Debug)]
+pub struct FakeClock {
+    now: Cell<Instant>,
+}
+
+#[cfg(test)]
+impl FakeClock {
+    pub fn new() -> Self {
+        Self {
+            now: Cell::new(Instant::now()),
+        }
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        self.now.set(self.now.get() + duration);
+    }
+}
+
+#[cfg(test)]
+impl Default for FakeClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+impl Clock for FakeClock {
+    fn now(&self) -> Instant {
+        self.now.get()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::clock::{Clock, FakeClock};
+    use std::time::Duration;
+
+    #[test]
+    fn fake_clock_only_moves_when_advanced() {
+        let clock = FakeClock::new();
+        let start = clock.now();
+        assert_eq!(clock.now() - start, Duration::ZERO);
+
+        clock.advance(Duration::from_millis(1500));
+
+        assert_eq!(clock.now() - start, Duration::from_millis(1500));
+    }
+}
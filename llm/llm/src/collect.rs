@@ -0,0 +1,356 @@
+use crate::golem::llm::llm::{
+    Annotation, ChatEvent, CompleteResponse, ContentPart, GuestChatStream, ResponseMetadata,
+    StreamEvent, ToolCall,
+};
+
+/// Drains a chat stream and assembles its events into a single response.
+///
+/// Consecutive text parts are concatenated, as are consecutive reasoning parts, so reasoning
+/// interleaved with the answer stays in separate parts in the order it was received. Tool calls
+/// and annotations are collected in the order they were received, and the metadata of the finish
+/// event becomes the metadata of the response. An error event is returned as `ChatEvent::Error`,
+/// dropping everything received before it.
+///
+/// Like the responses of `send`, a response made only of tool calls is returned as
+/// `ChatEvent::ToolRequest`, so a streamed batch request ends the same way as a sent one.
+pub fn collect_stream<S: GuestChatStream>(stream: &S) -> ChatEvent {
+    let mut collector = StreamCollector::new();
+    loop {
+        if let Some(event) = collector.add(stream.blocking_get_next()) {
+            break event;
+        }
+    }
+}
+
+/// Assembles the events of a chat stream into a single response, as described for
+/// `collect_stream`, one batch of events at a time.
+pub struct StreamCollector {
+    id: String,
+    content: Vec<ContentPart>,
+    tool_calls: Vec<ToolCall>,
+    annotations: Vec<Annotation>,
+    metadata: ResponseMetadata,
+}
+
+impl StreamCollector {
+    pub fn new() -> Self {
+        Self {
+            id: String::new(),
+            content: Vec::new(),
+            tool_calls: Vec::new(),
+            annotations: Vec::new(),
+            metadata: empty_metadata(),
+        }
+    }
+
+    /// Adds a batch of events received from the stream, an empty batch meaning the end of the
+    /// stream. Returns the assembled response once the stream finished or failed.
+    pub fn add(&mut self, events: Vec<StreamEvent>) -> Option<ChatEvent> {
+        if events.is_empty() {
+            return Some(self.response());
+        }
+
+        for event in events {
+            match event {
+                // A start event following deltas is the one of a durable stream restarted by the
+                // `restart` retry strategy, which sends the response again from its beginning
+                StreamEvent::Start(start) => {
+                    self.content.clear();
+                    self.tool_calls.clear();
+                    self.annotations.clear();
+                    self.id = start.id;
+                    if self.metadata.model.is_none() {
+                        self.metadata.model = start.model;
+                    }
+                }
+                StreamEvent::Delta(delta) => {
+                    for part in delta.content.unwrap_or_default() {
+                        push_content_part(&mut self.content, part);
+                    }
+                    self.tool_calls.extend(delta.tool_calls.unwrap_or_default());
+                    self.annotations
+                        .extend(delta.annotations.unwrap_or_default());
+                }
+                StreamEvent::Finish(finish) => {
+                    self.metadata = ResponseMetadata {
+                        model: finish.model.or(self.metadata.model.take()),
+                        ..finish
+                    };
+                    return Some(self.response());
+                }
+                StreamEvent::Error(error) => return Some(ChatEvent::Error(error)),
+            }
+        }
+
+        None
+    }
+
+    fn response(&mut self) -> ChatEvent {
+        let response = CompleteResponse {
+            id: std::mem::take(&mut self.id),
+            content: std::mem::take(&mut self.content),
+            tool_calls: std::mem::take(&mut self.tool_calls),
+            metadata: std::mem::replace(&mut self.metadata, empty_metadata()),
+            annotations: std::mem::take(&mut self.annotations),
+        };
+        if response.content.is_empty() && !response.tool_calls.is_empty() {
+            ChatEvent::ToolRequest(response.tool_calls)
+        } else {
+            ChatEvent::Message(response)
+        }
+    }
+}
+
+impl Default for StreamCollector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Appends a content part, concatenating it to the last one if both are text or both are
+/// reasoning, so the reasoning and the text received in turns are kept apart and in order
+pub fn push_content_part(content: &mut Vec<ContentPart>, part: ContentPart) {
+    match (content.last_mut(), part) {
+        (Some(ContentPart::Text(text)), ContentPart::Text(next)) => text.push_str(&next),
+        (Some(ContentPart::Reasoning(reasoning)), ContentPart::Reasoning(next)) => {
+            reasoning.push_str(&next)
+        }
+        (_, part) => content.push(part),
+    }
+}
+
+fn empty_metadata() -> ResponseMetadata {
+    ResponseMetadata {
+        finish_reason: None,
+        usage: None,
+        provider_id: None,
+        model: None,
+        timestamp: None,
+        provider_metadata_json: None,
+        tokens_per_second: None,
+        stop_sequence: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::collect::collect_stream;
+    use crate::golem::llm::llm::{
+        Annotation, ChatEvent, CompleteResponse, ContentPart, Error, ErrorCode, FinishReason,
+        GuestChatStream, ResponseMetadata, StreamDelta, StreamEvent, StreamStart, ToolCall, Usage,
+    };
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+
+    /// Chat stream returning a predefined sequence of event batches
+    struct SyntheticChatStream {
+        batches: RefCell<VecDeque<Vec<StreamEvent>>>,
+    }
+
+    impl GuestChatStream for SyntheticChatStream {
+        fn get_next(&self) -> Option<Vec<StreamEvent>> {
+            Some(self.blocking_get_next())
+        }
+
+        fn blocking_get_next(&self) -> Vec<StreamEvent> {
+            self.batches.borrow_mut().pop_front().unwrap_or_default()
+        }
+
+        fn partial_text(&self) -> String {
+            unreachable!()
+        }
+
+        fn raw_transcript(&self) -> String {
+            unreachable!()
+        }
+    }
+
+    fn stream(batches: Vec<Vec<StreamEvent>>) -> SyntheticChatStream {
+        SyntheticChatStream {
+            batches: RefCell::new(batches.into()),
+        }
+    }
+
+    fn text(text: &str) -> StreamEvent {
+        StreamEvent::Delta(StreamDelta {
+            content: Some(vec![ContentPart::Text(text.to_string())]),
+            tool_calls: None,
+            annotations: None,
+            tool_call_fragments: None,
+            running_usage: None,
+        })
+    }
+
+    fn tool_call(id: &str) -> ToolCall {
+        ToolCall {
+            id: id.to_string(),
+            name: "get_weather".to_string(),
+            arguments_json: r#"{"city":"Paris"}"#.to_string(),
+        }
+    }
+
+    #[test]
+    fn deltas_are_assembled_into_a_complete_response() {
+        let finish = ResponseMetadata {
+            finish_reason: Some(FinishReason::ToolCalls),
+            usage: Some(Usage {
+                input_tokens: Some(10),
+                output_tokens: Some(20),
+                total_tokens: Some(30),
+            }),
+            provider_id: None,
+            model: None,
+            timestamp: Some("1746000000".to_string()),
+            provider_metadata_json: None,
+            tokens_per_second: None,
+            stop_sequence: None,
+        };
+        let stream = stream(vec![
+            vec![
+                StreamEvent::Start(StreamStart {
+                    id: "response-1".to_string(),
+                    model: Some("model-1".to_string()),
+                    created: None,
+                }),
+                text("Let me "),
+            ],
+            vec![text("check"), text(" that.")],
+            vec![StreamEvent::Delta(StreamDelta {
+                content: None,
+                tool_calls: Some(vec![tool_call("call-1"), tool_call("call-2")]),
+                annotations: None,
+                tool_call_fragments: None,
+                running_usage: None,
+            })],
+            vec![StreamEvent::Finish(finish.clone())],
+        ]);
+
+        assert_eq!(
+            collect_stream(&stream),
+            ChatEvent::Message(CompleteResponse {
+                id: "response-1".to_string(),
+                content: vec![ContentPart::Text("Let me check that.".to_string())],
+                tool_calls: vec![tool_call("call-1"), tool_call("call-2")],
+                metadata: ResponseMetadata {
+                    model: Some("model-1".to_string()),
+                    ..finish
+                },
+                annotations: vec![],
+            })
+        );
+    }
+
+    #[test]
+    fn tool_calls_without_content_are_collected_into_a_tool_request() {
+        let stream = stream(vec![
+            vec![StreamEvent::Delta(StreamDelta {
+                content: None,
+                tool_calls: Some(vec![tool_call("call-1"), tool_call("call-2")]),
+                annotations: None,
+                tool_call_fragments: None,
+                running_usage: None,
+            })],
+            vec![StreamEvent::Finish(ResponseMetadata {
+                finish_reason: Some(FinishReason::ToolCalls),
+                usage: None,
+                provider_id: None,
+                model: None,
+                timestamp: None,
+                provider_metadata_json: None,
+                tokens_per_second: None,
+                stop_sequence: None,
+            })],
+        ]);
+
+        assert_eq!(
+            collect_stream(&stream),
+            ChatEvent::ToolRequest(vec![tool_call("call-1"), tool_call("call-2")])
+        );
+    }
+
+    #[test]
+    fn interleaved_reasoning_and_text_are_collected_apart_in_order() {
+        let reasoning = |reasoning: &str| {
+            StreamEvent::Delta(StreamDelta {
+                content: Some(vec![ContentPart::Reasoning(reasoning.to_string())]),
+                tool_calls: None,
+                annotations: None,
+                tool_call_fragments: None,
+                running_usage: None,
+            })
+        };
+        let stream = stream(vec![
+            vec![reasoning("The user asks"), reasoning(" for 17 * 3.")],
+            vec![text("17 * 3"), text(" is 51.")],
+            vec![reasoning("Checking: 50 + 1 = 51."), text(" Confirmed.")],
+            vec![],
+        ]);
+
+        let ChatEvent::Message(response) = collect_stream(&stream) else {
+            panic!("Expected a message");
+        };
+        assert_eq!(
+            response.content,
+            vec![
+                ContentPart::Reasoning("The user asks for 17 * 3.".to_string()),
+                ContentPart::Text("17 * 3 is 51.".to_string()),
+                ContentPart::Reasoning("Checking: 50 + 1 = 51.".to_string()),
+                ContentPart::Text(" Confirmed.".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn annotations_are_collected_in_order() {
+        let annotation = |url: &str, start_index: u32, end_index: u32| Annotation {
+            url: Some(url.to_string()),
+            title: None,
+            start_index,
+            end_index,
+        };
+        let annotated = |annotations: Vec<Annotation>| {
+            StreamEvent::Delta(StreamDelta {
+                content: None,
+                tool_calls: None,
+                annotations: Some(annotations),
+                tool_call_fragments: None,
+                running_usage: None,
+            })
+        };
+        let stream = stream(vec![
+            vec![text("Golem is durable."), text(" It is open source.")],
+            vec![annotated(vec![annotation("https://golem.cloud", 0, 17)])],
+            vec![annotated(vec![annotation(
+                "https://github.com/golemcloud",
+                18,
+                36,
+            )])],
+        ]);
+
+        let ChatEvent::Message(response) = collect_stream(&stream) else {
+            panic!("Expected a message");
+        };
+        assert_eq!(
+            response.annotations,
+            vec![
+                annotation("https://golem.cloud", 0, 17),
+                annotation("https://github.com/golemcloud", 18, 36),
+            ]
+        );
+    }
+
+    #[test]
+    fn error_event_becomes_error_response() {
+        let error = Error {
+            code: ErrorCode::RateLimitExceeded,
+            message: "Slow down".to_string(),
+            provider_error_json: None,
+        };
+        let stream = stream(vec![
+            vec![text("Hello")],
+            vec![StreamEvent::Error(error.clone())],
+        ]);
+
+        assert_eq!(collect_stream(&stream), ChatEvent::Error(error));
+    }
+}
@@ -1,17 +1,53 @@
-use crate::golem::llm::llm::{Error, ErrorCode};
+use crate::golem::llm::llm::{Config, Error, ErrorCode};
+use log::{trace, warn};
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::ffi::OsStr;
 
-/// Gets an expected configuration value from the environment, and fails if its is not found
-/// using the `fail` function. Otherwise, it runs `succeed` with the configuration value.
+/// A source of the providers' credentials and endpoints, like their API keys and base URLs,
+/// looked up by the name of their environment variable
+pub trait CredentialSource {
+    /// Returns the value of the given key, or `None` if it is not set
+    fn get(&self, key: &str) -> Option<String>;
+}
+
+/// The default credential source, reading the keys from the environment variables
+pub struct EnvCredentialSource;
+
+impl CredentialSource for EnvCredentialSource {
+    fn get(&self, key: &str) -> Option<String> {
+        std::env::var(key).ok()
+    }
+}
+
+thread_local! {
+    static CREDENTIAL_SOURCE: RefCell<Box<dyn CredentialSource>> =
+        RefCell::new(Box::new(EnvCredentialSource));
+}
+
+/// Replaces the source the credentials are resolved from, like a secret manager, for the
+/// requests sent afterwards. The environment variables are used by default.
+pub fn set_credential_source(source: impl CredentialSource + 'static) {
+    CREDENTIAL_SOURCE.with_borrow_mut(|current| *current = Box::new(source));
+}
+
+/// Gets an optional configuration value from the current credential source
+pub fn config_value(key: impl AsRef<OsStr>) -> Option<String> {
+    let key_str = key.as_ref().to_string_lossy().to_string();
+    CREDENTIAL_SOURCE.with_borrow(|source| source.get(&key_str))
+}
+
+/// Gets an expected configuration value from the current credential source, and fails if its is
+/// not found using the `fail` function. Otherwise, it runs `succeed` with the configuration value.
 pub fn with_config_key<R>(
     key: impl AsRef<OsStr>,
     fail: impl FnOnce(Error) -> R,
     succeed: impl FnOnce(String) -> R,
 ) -> R {
     let key_str = key.as_ref().to_string_lossy().to_string();
-    match std::env::var(key) {
-        Ok(value) => succeed(value),
-        Err(_) => {
+    match config_value(&key_str) {
+        Some(value) => succeed(value),
+        None => {
             let error = Error {
                 code: ErrorCode::InternalError,
                 message: format!("Missing config key: {key_str}"),
@@ -21,3 +57,218 @@ pub fn with_config_key<R>(
         }
     }
 }
+
+/// Environment variable setting the model of requests whose config has an empty `model`
+pub const DEFAULT_MODEL_ENV_VAR_NAME: &str = "GOLEM_LLM_DEFAULT_MODEL";
+
+/// Returns the model of a request, which is the default model set by the
+/// `GOLEM_LLM_DEFAULT_MODEL` environment variable if the config's `model` is empty. Fails with an
+/// `invalid-request` error if neither is set.
+pub fn model_or_default(model: String) -> Result<String, Error> {
+    model_or(model, std::env::var(DEFAULT_MODEL_ENV_VAR_NAME).ok())
+}
+
+fn model_or(model: String, default_model: Option<String>) -> Result<String, Error> {
+    if !model.trim().is_empty() {
+        return Ok(model);
+    }
+
+    match default_model.filter(|default_model| !default_model.trim().is_empty()) {
+        Some(default_model) => Ok(default_model),
+        None => Err(Error {
+            code: ErrorCode::InvalidRequest,
+            message: format!(
+                "No model is set in the config, and no default model is set by {DEFAULT_MODEL_ENV_VAR_NAME}"
+            ),
+            provider_error_json: None,
+        }),
+    }
+}
+
+/// Provider option holding a JSON object mapping model aliases to the models they stand for
+pub const MODEL_ALIASES_OPTION: &str = "model_aliases";
+
+/// Environment variable holding a JSON object mapping model aliases to the models they stand for,
+/// used when the config has no `model_aliases` provider option
+const MODEL_ALIASES_ENV_VAR_NAME: &str = "GOLEM_LLM_MODEL_ALIASES";
+
+/// Replaces the config's `model` by the model it is an alias of, looked up in the
+/// `model_aliases` provider option, or else in the `GOLEM_LLM_MODEL_ALIASES` environment
+/// variable. A model which is not an alias is kept as is.
+pub fn with_resolved_model(config: Config) -> Config {
+    resolve_model(config, std::env::var(MODEL_ALIASES_ENV_VAR_NAME).ok())
+}
+
+fn resolve_model(mut config: Config, env_aliases: Option<String>) -> Config {
+    let aliases = match config
+        .provider_options
+        .iter()
+        .find(|kv| kv.key == MODEL_ALIASES_OPTION)
+    {
+        Some(kv) => parse_model_aliases(&kv.value, MODEL_ALIASES_OPTION),
+        None => env_aliases
+            .map(|json| parse_model_aliases(&json, MODEL_ALIASES_ENV_VAR_NAME))
+            .unwrap_or_default(),
+    };
+
+    if let Some(model) = aliases.get(&config.model) {
+        trace!("Resolved model alias {} to {model}", config.model);
+        config.model = model.clone();
+    }
+    config
+}
+
+/// Parses a table of model aliases, ignoring it with a warning if it is not a JSON object of
+/// strings
+fn parse_model_aliases(json: &str, source: &str) -> HashMap<String, String> {
+    match serde_json::from_str(json) {
+        Ok(aliases) => aliases,
+        Err(err) => {
+            warn!("Ignoring the model aliases of {source}, which are not a JSON object of strings: {err}");
+            HashMap::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{
+        config_value, model_or, resolve_model, set_credential_source, with_config_key,
+        CredentialSource, EnvCredentialSource, MODEL_ALIASES_OPTION,
+    };
+    use crate::golem::llm::llm::{Config, ErrorCode, Kv};
+    use std::collections::HashMap;
+
+    struct MockCredentialSource(HashMap<String, String>);
+
+    impl CredentialSource for MockCredentialSource {
+        fn get(&self, key: &str) -> Option<String> {
+            self.0.get(key).cloned()
+        }
+    }
+
+    #[test]
+    fn configured_model_is_kept() {
+        assert_eq!(
+            model_or("gpt-4o".to_string(), Some("gpt-4o-mini".to_string())),
+            Ok("gpt-4o".to_string())
+        );
+    }
+
+    #[test]
+    fn empty_model_is_replaced_by_the_default() {
+        assert_eq!(
+            model_or(String::new(), Some("gpt-4o-mini".to_string())),
+            Ok("gpt-4o-mini".to_string())
+        );
+    }
+
+    #[test]
+    fn empty_model_without_default_is_rejected() {
+        for default_model in [None, Some(String::new())] {
+            let error = model_or(String::new(), default_model).unwrap_err();
+
+            assert_eq!(error.code, ErrorCode::InvalidRequest);
+            assert!(error.message.contains("GOLEM_LLM_DEFAULT_MODEL"));
+        }
+    }
+
+    #[test]
+    fn keys_are_resolved_through_the_credential_source() {
+        set_credential_source(MockCredentialSource(HashMap::from([(
+            "GOLEM_LLM_TEST_API_KEY".to_string(),
+            "secret".to_string(),
+        )])));
+
+        assert_eq!(
+            with_config_key("GOLEM_LLM_TEST_API_KEY", Err, Ok),
+            Ok("secret".to_string())
+        );
+        assert_eq!(config_value("GOLEM_LLM_TEST_BASE_URL"), None);
+
+        let error = with_config_key("GOLEM_LLM_TEST_BASE_URL", Err, Ok).unwrap_err();
+        assert_eq!(error.code, ErrorCode::InternalError);
+        assert_eq!(error.message, "Missing config key: GOLEM_LLM_TEST_BASE_URL");
+
+        set_credential_source(EnvCredentialSource);
+    }
+
+    fn config(model: &str, model_aliases: Option<&str>) -> Config {
+        Config {
+            model: model.to_string(),
+            temperature: None,
+            max_tokens: None,
+            stop_sequences: None,
+            tools: vec![],
+            tool_choice: None,
+            provider_options: model_aliases
+                .map(|model_aliases| Kv {
+                    key: MODEL_ALIASES_OPTION.to_string(),
+                    value: model_aliases.to_string(),
+                })
+                .into_iter()
+                .collect(),
+            extra_headers: vec![],
+            service_tier: None,
+            metadata: vec![],
+            reasoning_effort: None,
+            store: None,
+            prompt_cache_key: None,
+            stream_options: None,
+            deadline_ms: None,
+            extra_body: None,
+        }
+    }
+
+    #[test]
+    fn model_alias_is_resolved() {
+        let aliases = r#"{"fast": "gpt-4o-mini", "smart": "gpt-4o"}"#;
+
+        assert_eq!(
+            resolve_model(config("fast", Some(aliases)), None).model,
+            "gpt-4o-mini"
+        );
+        assert_eq!(
+            resolve_model(config("smart", None), Some(aliases.to_string())).model,
+            "gpt-4o"
+        );
+    }
+
+    #[test]
+    fn provider_option_aliases_take_precedence() {
+        let resolved = resolve_model(
+            config("fast", Some(r#"{"fast": "claude-3-5-haiku-latest"}"#)),
+            Some(r#"{"fast": "gpt-4o-mini"}"#.to_string()),
+        );
+
+        assert_eq!(resolved.model, "claude-3-5-haiku-latest");
+    }
+
+    #[test]
+    fn unknown_model_is_kept() {
+        assert_eq!(
+            resolve_model(
+                config("gpt-4.1", None),
+                Some(r#"{"fast": "gpt-4o-mini"}"#.to_string())
+            )
+            .model,
+            "gpt-4.1"
+        );
+        assert_eq!(
+            resolve_model(config("fast", Some("not json")), None).model,
+            "fast"
+        );
+    }
+
+    #[test]
+    fn resolved_model_is_stable_when_the_aliases_change() {
+        let resolved = resolve_model(
+            config("fast", None),
+            Some(r#"{"fast": "gpt-4o-mini"}"#.to_string()),
+        );
+
+        let replayed = resolve_model(resolved, Some(r#"{"fast": "gpt-4.1-nano"}"#.to_string()));
+
+        assert_eq!(replayed.model, "gpt-4o-mini");
+    }
+}
@@ -0,0 +1,410 @@
+use crate::config::MODEL_ALIASES_OPTION;
+use crate::extra_body::ALLOW_BODY_OVERRIDE_OPTION;
+use crate::golem::llm::llm::{ContentPart, Error, ErrorCode, Message, ToolCall, ToolResult};
+use crate::headers::ALLOW_HEADER_OVERRIDE_OPTION;
+use crate::request_id::REQUEST_ID_OPTION;
+use log::warn;
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+
+/// Provider option selecting how the text parts of a message are joined by providers which take
+/// the content of a message as a single string
+pub const TEXT_JOIN_OPTION: &str = "text_join";
+
+/// Environment variable making requests with unknown provider options fail when set to `true` or
+/// `1`, instead of only logging a warning about them
+const STRICT_OPTIONS_ENV_VAR_NAME: &str = "GOLEM_LLM_STRICT_OPTIONS";
+
+/// Provider options understood by every provider, in addition to their own
+const COMMON_PROVIDER_OPTIONS: [&str; 4] = [
+    ALLOW_BODY_OVERRIDE_OPTION,
+    ALLOW_HEADER_OVERRIDE_OPTION,
+    MODEL_ALIASES_OPTION,
+    REQUEST_ID_OPTION,
+];
+
+/// Separator put between the text parts of a message when they are joined into a single string
+#[derive(Debug, Clone, PartialEq)]
+pub enum TextJoin {
+    Newline,
+    Space,
+    None,
+    Custom(String),
+}
+
+impl TextJoin {
+    /// Gets the join mode from the `text_join` provider option, which can be `newline` (the
+    /// default), `space`, `none`, or any other string to be used as the separator
+    pub fn from_options(options: &HashMap<String, String>) -> Self {
+        match options.get(TEXT_JOIN_OPTION).map(|value| value.as_str()) {
+            None | Some("newline") => Self::Newline,
+            Some("space") => Self::Space,
+            Some("none") => Self::None,
+            Some(separator) => Self::Custom(separator.to_string()),
+        }
+    }
+
+    pub fn separator(&self) -> &str {
+        match self {
+            Self::Newline => "\n",
+            Self::Space => " ",
+            Self::None => "",
+            Self::Custom(separator) => separator,
+        }
+    }
+
+    pub fn join(&self, texts: &[String]) -> String {
+        texts.join(self.separator())
+    }
+}
+
+/// Validates the temperature of a request. Non-finite values are rejected, and values outside of
+/// the range accepted by the provider are clamped to it.
+pub fn validate_temperature(
+    temperature: Option<f32>,
+    range: RangeInclusive<f32>,
+) -> Result<Option<f32>, Error> {
+    match temperature {
+        Some(temperature) if !temperature.is_finite() => Err(Error {
+            code: ErrorCode::InvalidRequest,
+            message: format!("Temperature must be a finite number, got {temperature}"),
+            provider_error_json: None,
+        }),
+        Some(temperature) if !range.contains(&temperature) => {
+            let clamped = temperature.clamp(*range.start(), *range.end());
+            warn!(
+                "Temperature {temperature} is outside of the supported range {}..={}, using {clamped}",
+                range.start(),
+                range.end()
+            );
+            Ok(Some(clamped))
+        }
+        temperature => Ok(temperature),
+    }
+}
+
+/// Checks the provider options against the keys known by the provider, so typos do not go
+/// unnoticed. Unknown keys are logged as warnings, unless strict mode is enabled by the
+/// `GOLEM_LLM_STRICT_OPTIONS` environment variable, in which case the request is rejected with
+/// an `InvalidRequest` error listing all of them.
+pub fn check_provider_options(
+    options: &HashMap<String, String>,
+    known_options: &[&str],
+) -> Result<(), Error> {
+    check_provider_options_in_mode(options, known_options, is_strict_options())
+}
+
+fn is_strict_options() -> bool {
+    std::env::var(STRICT_OPTIONS_ENV_VAR_NAME)
+        .map(|value| matches!(value.to_lowercase().as_str(), "true" | "1"))
+        .unwrap_or(false)
+}
+
+fn check_provider_options_in_mode(
+    options: &HashMap<String, String>,
+    known_options: &[&str],
+    strict: bool,
+) -> Result<(), Error> {
+    let unknown_options = unknown_provider_options(options, known_options);
+    if unknown_options.is_empty() {
+        Ok(())
+    } else if strict {
+        Err(Error {
+            code: ErrorCode::InvalidRequest,
+            message: format!("Unknown provider options: {}", unknown_options.join(", ")),
+            provider_error_json: None,
+        })
+    } else {
+        for key in unknown_options {
+            warn!("Ignoring unknown provider option {key}");
+        }
+        Ok(())
+    }
+}
+
+/// The keys of the options which are neither known by the provider nor common ones, sorted
+fn unknown_provider_options<'a>(
+    options: &'a HashMap<String, String>,
+    known_options: &[&str],
+) -> Vec<&'a str> {
+    let mut unknown_options = options
+        .keys()
+        .map(|key| key.as_str())
+        .filter(|key| !known_options.contains(key) && !COMMON_PROVIDER_OPTIONS.contains(key))
+        .collect::<Vec<_>>();
+    unknown_options.sort();
+    unknown_options
+}
+
+/// Rejects a request with nothing to send, having no messages and no tool results, with an
+/// `InvalidRequest` error before it reaches the provider, which would reject it with an error
+/// specific to each provider
+pub fn check_messages_not_empty(
+    messages: &[Message],
+    tool_results: &[(ToolCall, ToolResult)],
+) -> Result<(), Error> {
+    if messages.is_empty() && tool_results.is_empty() {
+        Err(Error {
+            code: ErrorCode::InvalidRequest,
+            message: "at least one message is required".to_string(),
+            provider_error_json: None,
+        })
+    } else {
+        Ok(())
+    }
+}
+
+/// Checks the ids of the tool results sent by `continue_` before they are converted to the
+/// provider's format. Each result has to have the id of its tool call, an empty result id being
+/// corrected to it. The ids are then converted by `normalize_id`, which corrects an id to the
+/// format expected by the provider, or returns `None` if it cannot, rejecting the request with an
+/// `InvalidRequest` error.
+pub fn normalize_tool_call_ids(
+    tool_results: Vec<(ToolCall, ToolResult)>,
+    normalize_id: impl Fn(&str) -> Option<String>,
+) -> Result<Vec<(ToolCall, ToolResult)>, Error> {
+    tool_results
+        .into_iter()
+        .map(|(mut tool_call, mut tool_result)| {
+            let result_id = match &mut tool_result {
+                ToolResult::Success(success) => &mut success.id,
+                ToolResult::Error(error) => &mut error.id,
+            };
+            if !result_id.is_empty() && *result_id != tool_call.id {
+                return Err(Error {
+                    code: ErrorCode::InvalidRequest,
+                    message: format!(
+                        "The result of tool call {} has a different id: {result_id}",
+                        tool_call.id
+                    ),
+                    provider_error_json: None,
+                });
+            }
+
+            let id = normalize_id(&tool_call.id).ok_or_else(|| Error {
+                code: ErrorCode::InvalidRequest,
+                message: format!("Invalid tool call id: {:?}", tool_call.id),
+                provider_error_json: None,
+            })?;
+            result_id.clone_from(&id);
+            tool_call.id = id;
+            Ok((tool_call, tool_result))
+        })
+        .collect()
+}
+
+/// Splits the content of a message into its other parts and the tool results included in it
+/// inline, each with the tool call it answers. The ids of the tool results are checked and
+/// converted by `normalize_id` like the ones sent by `continue_`.
+pub fn split_tool_results(
+    content: Vec<ContentPart>,
+    normalize_id: impl Fn(&str) -> Option<String>,
+) -> Result<(Vec<ContentPart>, Vec<(ToolCall, ToolResult)>), Error> {
+    let mut other_parts = Vec::new();
+    let mut tool_results = Vec::new();
+    for part in content {
+        match part {
+            ContentPart::ToolResult(tool_result) => tool_results.push(tool_result),
+            part => other_parts.push(part),
+        }
+    }
+    Ok((
+        other_parts,
+        normalize_tool_call_ids(tool_results, normalize_id)?,
+    ))
+}
+
+/// Accepts any non-empty tool call id as is, for providers which do not restrict their format
+pub fn non_empty_tool_call_id(id: &str) -> Option<String> {
+    (!id.is_empty()).then(|| id.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::conversions::{
+        check_messages_not_empty, check_provider_options_in_mode, non_empty_tool_call_id,
+        normalize_tool_call_ids, split_tool_results, unknown_provider_options,
+        validate_temperature,
+    };
+    use crate::golem::llm::llm::{
+        ContentPart, ErrorCode, Message, Role, ToolCall, ToolResult, ToolSuccess,
+    };
+    use std::collections::HashMap;
+
+    #[test]
+    fn non_finite_temperature_is_rejected() {
+        for temperature in [f32::NAN, f32::INFINITY, f32::NEG_INFINITY] {
+            let error = validate_temperature(Some(temperature), 0.0..=2.0).unwrap_err();
+            assert_eq!(error.code, ErrorCode::InvalidRequest);
+        }
+    }
+
+    #[test]
+    fn out_of_range_temperature_is_clamped() {
+        assert_eq!(validate_temperature(Some(1.5), 0.0..=1.0), Ok(Some(1.0)));
+        assert_eq!(validate_temperature(Some(-0.5), 0.0..=2.0), Ok(Some(0.0)));
+    }
+
+    #[test]
+    fn valid_temperature_is_kept() {
+        assert_eq!(validate_temperature(Some(0.7), 0.0..=2.0), Ok(Some(0.7)));
+        assert_eq!(validate_temperature(None, 0.0..=2.0), Ok(None));
+    }
+
+    fn options(keys: &[&str]) -> HashMap<String, String> {
+        keys.iter()
+            .map(|key| (key.to_string(), "1".to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn strict_mode_rejects_unknown_provider_options() {
+        let error = check_provider_options_in_mode(
+            &options(&["top_p", "temperatur", "request_id", "seeed"]),
+            &["top_p", "seed"],
+            true,
+        )
+        .unwrap_err();
+
+        assert_eq!(error.code, ErrorCode::InvalidRequest);
+        assert_eq!(error.message, "Unknown provider options: seeed, temperatur");
+    }
+
+    #[test]
+    fn lenient_mode_only_warns_about_unknown_provider_options() {
+        let options = options(&["top_p", "temperatur"]);
+
+        assert_eq!(
+            unknown_provider_options(&options, &["top_p"]),
+            vec!["temperatur"]
+        );
+        assert_eq!(
+            check_provider_options_in_mode(&options, &["top_p"], false),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn known_and_common_provider_options_are_accepted() {
+        let options = options(&[
+            "top_p",
+            "request_id",
+            "allow_header_override",
+            "allow_body_override",
+            "model_aliases",
+        ]);
+
+        assert!(unknown_provider_options(&options, &["top_p"]).is_empty());
+        assert_eq!(
+            check_provider_options_in_mode(&options, &["top_p"], true),
+            Ok(())
+        );
+    }
+
+    fn tool_result(call_id: &str, result_id: &str) -> (ToolCall, ToolResult) {
+        (
+            ToolCall {
+                id: call_id.to_string(),
+                name: "get_weather".to_string(),
+                arguments_json: "{}".to_string(),
+            },
+            ToolResult::Success(ToolSuccess {
+                id: result_id.to_string(),
+                name: "get_weather".to_string(),
+                result_json: "{}".to_string(),
+                execution_time_ms: None,
+                content: vec![],
+            }),
+        )
+    }
+
+    #[test]
+    fn empty_messages_are_rejected() {
+        let error = check_messages_not_empty(&[], &[]).unwrap_err();
+
+        assert_eq!(error.code, ErrorCode::InvalidRequest);
+        assert_eq!(error.message, "at least one message is required");
+    }
+
+    #[test]
+    fn a_single_message_or_tool_result_is_accepted() {
+        let message = Message {
+            role: Role::User,
+            name: None,
+            content: vec![ContentPart::Text("Hi".to_string())],
+        };
+
+        assert!(check_messages_not_empty(&[message], &[]).is_ok());
+        assert!(check_messages_not_empty(&[], &[tool_result("call_1", "call_1")]).is_ok());
+    }
+
+    fn ids(tool_results: &[(ToolCall, ToolResult)]) -> Vec<(String, String)> {
+        tool_results
+            .iter()
+            .map(|(tool_call, tool_result)| {
+                let result_id = match tool_result {
+                    ToolResult::Success(success) => success.id.clone(),
+                    ToolResult::Error(error) => error.id.clone(),
+                };
+                (tool_call.id.clone(), result_id)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn tool_call_ids_are_normalized() {
+        let tool_results = normalize_tool_call_ids(
+            vec![tool_result("call_1", "call_1"), tool_result("call.2", "")],
+            |id| Some(id.replace('.', "_")),
+        )
+        .unwrap();
+
+        assert_eq!(
+            ids(&tool_results),
+            vec![
+                ("call_1".to_string(), "call_1".to_string()),
+                ("call_2".to_string(), "call_2".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn mismatched_tool_result_id_is_rejected() {
+        let error = normalize_tool_call_ids(vec![tool_result("call_1", "call_2")], |id| {
+            Some(id.to_string())
+        })
+        .unwrap_err();
+
+        assert_eq!(error.code, ErrorCode::InvalidRequest);
+        assert!(error.message.contains("call_2"));
+    }
+
+    #[test]
+    fn invalid_tool_call_id_is_rejected() {
+        let error =
+            normalize_tool_call_ids(vec![tool_result("", "")], non_empty_tool_call_id).unwrap_err();
+
+        assert_eq!(error.code, ErrorCode::InvalidRequest);
+    }
+
+    #[test]
+    fn inline_tool_results_are_split_from_the_content() {
+        let (content, tool_results) = split_tool_results(
+            vec![
+                ContentPart::Text("Here is the weather".to_string()),
+                ContentPart::ToolResult(tool_result("call_1", "")),
+            ],
+            non_empty_tool_call_id,
+        )
+        .unwrap();
+
+        assert_eq!(
+            content,
+            vec![ContentPart::Text("Here is the weather".to_string())]
+        );
+        assert_eq!(
+            ids(&tool_results),
+            vec![("call_1".to_string(), "call_1".to_string())]
+        );
+    }
+}
@@ -0,0 +1,183 @@
+use crate::golem::llm::llm::{
+    ChatEvent, CompleteResponse, ContentPart, ResponseMetadata, StreamDelta, StreamEvent,
+};
+use serde::Serialize;
+
+/// Environment variable enabling dry-run mode when set to `true` or `1`
+const DRY_RUN_ENV_VAR_NAME: &str = "GOLEM_LLM_DRY_RUN";
+
+/// The `provider_id` reported by responses created in dry-run mode
+pub const DRY_RUN_PROVIDER_ID: &str = "dry-run";
+
+/// Returns whether dry-run mode is enabled by the `GOLEM_LLM_DRY_RUN` environment variable
+pub fn is_dry_run() -> bool {
+    is_dry_run_in(|name| std::env::var(name).ok())
+}
+
+fn is_dry_run_in(env_var: impl Fn(&str) -> Option<String>) -> bool {
+    env_var(DRY_RUN_ENV_VAR_NAME)
+        .is_some_and(|value| matches!(value.to_lowercase().as_str(), "true" | "1"))
+}
+
+/// Sends the request with the given `send` function, unless dry-run mode is enabled, in which
+/// case `send` is never called and the serialized request is returned as the response content.
+pub fn send_or_dry_run<T: Serialize>(request: T, send: impl FnOnce(T) -> ChatEvent) -> ChatEvent {
+    send_or_dry_run_in(request, send, |name| std::env::var(name).ok())
+}
+
+fn send_or_dry_run_in<T: Serialize>(
+    request: T,
+    send: impl FnOnce(T) -> ChatEvent,
+    env_var: impl Fn(&str) -> Option<String>,
+) -> ChatEvent {
+    if is_dry_run_in(env_var) {
+        let request_json = serialize_request(&request);
+        ChatEvent::Message(CompleteResponse {
+            id: DRY_RUN_PROVIDER_ID.to_string(),
+            content: vec![ContentPart::Text(request_json)],
+            tool_calls: vec![],
+            metadata: dry_run_metadata(),
+            annotations: vec![],
+        })
+    } else {
+        send(request)
+    }
+}
+
+/// Starts streaming the request with the given `stream` function, unless dry-run mode is enabled,
+/// in which case `stream` is never called and the stream created by `dry_run` emits the
+/// serialized request as a single delta followed by a finish event.
+pub fn stream_or_dry_run<T: Serialize, S>(
+    request: T,
+    dry_run: impl FnOnce(Vec<StreamEvent>) -> S,
+    stream: impl FnOnce(T) -> S,
+) -> S {
+    stream_or_dry_run_in(request, dry_run, stream, |name| std::env::var(name).ok())
+}
+
+fn stream_or_dry_run_in<T: Serialize, S>(
+    request: T,
+    dry_run: impl FnOnce(Vec<StreamEvent>) -> S,
+    stream: impl FnOnce(T) -> S,
+    env_var: impl Fn(&str) -> Option<String>,
+) -> S {
+    if is_dry_run_in(env_var) {
+        let request_json = serialize_request(&request);
+        dry_run(vec![
+            StreamEvent::Delta(StreamDelta {
+                content: Some(vec![ContentPart::Text(request_json)]),
+                tool_calls: None,
+                annotations: None,
+                tool_call_fragments: None,
+                running_usage: None,
+            }),
+            StreamEvent::Finish(dry_run_metadata()),
+        ])
+    } else {
+        stream(request)
+    }
+}
+
+fn serialize_request<T: Serialize>(request: &T) -> String {
+    serde_json::to_string(request)
+        .unwrap_or_else(|err| format!("Failed to serialize request: {err}"))
+}
+
+fn dry_run_metadata() -> ResponseMetadata {
+    ResponseMetadata {
+        finish_reason: None,
+        usage: None,
+        provider_id: Some(DRY_RUN_PROVIDER_ID.to_string()),
+        model: None,
+        timestamp: None,
+        provider_metadata_json: None,
+        tokens_per_second: None,
+        stop_sequence: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dry_run::{send_or_dry_run_in, stream_or_dry_run_in, DRY_RUN_ENV_VAR_NAME};
+    use crate::golem::llm::llm::{ChatEvent, ContentPart, StreamDelta, StreamEvent};
+    use serde::Serialize;
+
+    #[derive(Serialize)]
+    struct TestRequest {
+        model: String,
+        max_tokens: Option<u32>,
+    }
+
+    fn request() -> TestRequest {
+        TestRequest {
+            model: "test-model".to_string(),
+            max_tokens: Some(100),
+        }
+    }
+
+    fn dry_run(value: Option<&str>) -> impl Fn(&str) -> Option<String> {
+        let value = value.map(str::to_string);
+        move |name| {
+            assert_eq!(name, DRY_RUN_ENV_VAR_NAME);
+            value.clone()
+        }
+    }
+
+    #[test]
+    fn dry_run_returns_serialized_request_without_sending() {
+        let event = send_or_dry_run_in(
+            request(),
+            |_| panic!("HTTP client must not be invoked"),
+            dry_run(Some("true")),
+        );
+        match event {
+            ChatEvent::Message(response) => {
+                assert_eq!(
+                    response.content,
+                    vec![ContentPart::Text(
+                        r#"{"model":"test-model","max_tokens":100}"#.to_string()
+                    )]
+                );
+                assert_eq!(response.metadata.provider_id, Some("dry-run".to_string()));
+            }
+            other => panic!("Unexpected event: {other:?}"),
+        }
+
+        let events = stream_or_dry_run_in(
+            request(),
+            |events| events,
+            |_| panic!("HTTP client must not be invoked"),
+            dry_run(Some("1")),
+        );
+        assert_eq!(
+            events[0],
+            StreamEvent::Delta(StreamDelta {
+                content: Some(vec![ContentPart::Text(
+                    r#"{"model":"test-model","max_tokens":100}"#.to_string()
+                )]),
+                tool_calls: None,
+                annotations: None,
+                tool_call_fragments: None,
+                running_usage: None,
+            })
+        );
+        assert!(matches!(&events[1], StreamEvent::Finish(metadata)
+            if metadata.provider_id == Some("dry-run".to_string())));
+    }
+
+    #[test]
+    fn requests_are_sent_unless_dry_run_is_enabled() {
+        for value in [None, Some("false"), Some("0")] {
+            let event = send_or_dry_run_in(
+                request(),
+                |_| ChatEvent::ToolRequest(vec![]),
+                dry_run(value),
+            );
+            assert_eq!(event, ChatEvent::ToolRequest(vec![]), "{value:?}");
+
+            let events =
+                stream_or_dry_run_in(request(), |events| events, |_| vec![], dry_run(value));
+            assert!(events.is_empty(), "{value:?}");
+        }
+    }
+}
@@ -1,7 +1,89 @@
-use crate::golem::llm::llm::{Config, ContentPart, Guest, Message, Role, StreamDelta};
+use crate::golem::llm::llm::{
+    Config, ContentPart, Guest, Message, Role, StreamDelta, ToolCall, ToolResult, Usage,
+};
 use golem_rust::wasm_rpc::Pollable;
+use log::warn;
 use std::marker::PhantomData;
 
+/// Environment variable selecting how a durable stream interrupted mid-response is resumed
+const RETRY_STRATEGY_ENV_VAR_NAME: &str = "GOLEM_LLM_RETRY_STRATEGY";
+
+/// Environment variable overriding the system message asking the model to continue an
+/// interrupted response
+const RETRY_SYSTEM_PROMPT_ENV_VAR_NAME: &str = "GOLEM_LLM_RETRY_SYSTEM_PROMPT";
+
+/// Environment variable overriding the text introducing the partially received response
+const RETRY_CONTINUATION_PROMPT_ENV_VAR_NAME: &str = "GOLEM_LLM_RETRY_CONTINUATION_PROMPT";
+
+const DEFAULT_RETRY_SYSTEM_PROMPT: &str =
+    "You were asked the same question previously, but the response was interrupted before completion. \
+     Please continue your response from where you left off. \
+     Do not include the part of the response that was already seen.";
+
+const DEFAULT_RETRY_CONTINUATION_PROMPT: &str =
+    "Here is the partial response that was successfully received:";
+
+/// The instructions of the retry prompt asking the model to continue an interrupted response,
+/// which can be overridden by the `GOLEM_LLM_RETRY_SYSTEM_PROMPT` environment variable
+pub fn retry_system_prompt() -> String {
+    prompt_from_env(
+        RETRY_SYSTEM_PROMPT_ENV_VAR_NAME,
+        DEFAULT_RETRY_SYSTEM_PROMPT,
+        |name| std::env::var(name).ok(),
+    )
+}
+
+/// The text preceding the partially received response in the retry prompt, which can be
+/// overridden by the `GOLEM_LLM_RETRY_CONTINUATION_PROMPT` environment variable
+pub fn retry_continuation_prompt() -> String {
+    prompt_from_env(
+        RETRY_CONTINUATION_PROMPT_ENV_VAR_NAME,
+        DEFAULT_RETRY_CONTINUATION_PROMPT,
+        |name| std::env::var(name).ok(),
+    )
+}
+
+fn prompt_from_env(
+    env_var_name: &str,
+    default: &str,
+    env_var: impl Fn(&str) -> Option<String>,
+) -> String {
+    env_var(env_var_name)
+        .filter(|prompt| !prompt.trim().is_empty())
+        .unwrap_or_else(|| default.to_string())
+}
+
+/// How a durable stream interrupted mid-response is resumed after a replay
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetryStrategy {
+    /// Asks the model to continue the partial response with `ExtendedGuest::retry_prompt`
+    Continue,
+    /// Sends the conversation again with `ExtendedGuest::restart_prompt`, discarding the partial
+    /// response, for tool calls and structured outputs which cannot be continued reliably
+    Restart,
+}
+
+impl RetryStrategy {
+    /// Gets the strategy from the `GOLEM_LLM_RETRY_STRATEGY` environment variable, which can be
+    /// `continue` (the default) or `restart`
+    pub fn from_env() -> Self {
+        Self::parse(std::env::var(RETRY_STRATEGY_ENV_VAR_NAME).ok().as_deref())
+    }
+
+    fn parse(value: Option<&str>) -> Self {
+        match value.map(|value| value.trim().to_lowercase()).as_deref() {
+            None | Some("") | Some("continue") => Self::Continue,
+            Some("restart") => Self::Restart,
+            Some(other) => {
+                warn!(
+                    "Ignoring unknown {RETRY_STRATEGY_ENV_VAR_NAME} {other}, continuing the partial response"
+                );
+                Self::Continue
+            }
+        }
+    }
+}
+
 /// Wraps an LLM implementation with custom durability
 pub struct DurableLLM<Impl> {
     phantom: PhantomData<Impl>,
@@ -12,19 +94,25 @@ pub trait ExtendedGuest: Guest + 'static {
     /// Creates an instance of the LLM specific `ChatStream` without wrapping it in a `Resource`
     fn unwrapped_stream(messages: Vec<Message>, config: Config) -> Self::ChatStream;
 
+    /// Creates an instance of the LLM specific `ChatStream` continuing the conversation with the
+    /// results of the requested tool calls, without wrapping it in a `Resource`
+    fn unwrapped_continue_stream(
+        messages: Vec<Message>,
+        tool_results: Vec<(ToolCall, ToolResult)>,
+        config: Config,
+    ) -> Self::ChatStream;
+
     /// Creates the retry prompt with a combination of the original messages, and the partially received
     /// streaming responses. There is a default implementation here, but it can be overridden with provider-specific
-    /// prompts if needed.
+    /// prompts if needed. Its texts can be overridden with the `GOLEM_LLM_RETRY_SYSTEM_PROMPT` and
+    /// `GOLEM_LLM_RETRY_CONTINUATION_PROMPT` environment variables.
     fn retry_prompt(original_messages: &[Message], partial_result: &[StreamDelta]) -> Vec<Message> {
         let mut extended_messages = Vec::new();
         extended_messages.push(Message {
             role: Role::System,
             name: None,
             content: vec![
-                ContentPart::Text(
-                    "You were asked the same question previously, but the response was interrupted before completion. \
-                                        Please continue your response from where you left off. \
-                                        Do not include the part of the response that was already seen.".to_string()),
+                ContentPart::Text(retry_system_prompt()),
                 ContentPart::Text("Here is the original question:".to_string()),
             ],
         });
@@ -48,32 +136,53 @@ pub trait ExtendedGuest: Guest + 'static {
         extended_messages.push(Message {
             role: Role::System,
             name: None,
-            content: vec![ContentPart::Text(
-                "Here is the partial response that was successfully received:".to_string(),
-            )]
-            .into_iter()
-            .chain(partial_result_as_content)
-            .collect(),
+            content: vec![ContentPart::Text(retry_continuation_prompt())]
+                .into_iter()
+                .chain(partial_result_as_content)
+                .collect(),
         });
         extended_messages
     }
 
+    /// Creates the prompt of the `restart` retry strategy, which sends the conversation again
+    /// without the partially received response. By default it is the original messages unchanged.
+    fn restart_prompt(original_messages: &[Message]) -> Vec<Message> {
+        original_messages.to_vec()
+    }
+
     fn subscribe(stream: &Self::ChatStream) -> Pollable;
+
+    /// Pollable ready at the deadline of the stream, if it has one, waking up the blocking reads
+    /// of the durable stream even if the provider stops sending anything
+    fn subscribe_deadline(_stream: &Self::ChatStream) -> Option<Pollable> {
+        None
+    }
+
+    /// The usage of the response received so far by the stream, persisted by the durable stream
+    /// so a stream interrupted mid-response adds the usage of its interrupted segment to the usage
+    /// of the new live stream, whether or not the running usage is reported on the deltas
+    fn usage_so_far(_stream: &Self::ChatStream) -> Option<Usage> {
+        None
+    }
 }
 
 /// When the durability feature flag is off, wrapping with `DurableLLM` is just a passthrough
 #[cfg(not(feature = "durability"))]
 mod passthrough_impl {
+    use crate::batch::{max_concurrency, stream_batch};
+    use crate::config::with_resolved_model;
     use crate::durability::{DurableLLM, ExtendedGuest};
     use crate::golem::llm::llm::{
-        ChatEvent, ChatStream, Config, Guest, Message, ToolCall, ToolResult,
+        ChatEvent, ChatStream, Config, ContentPart, EmbeddingConfig, EmbeddingResult, Error, Guest,
+        ImageGenConfig, ImageGenResult, Message, ProviderCapabilities, ProviderHealth,
+        RerankConfig, RerankResult, ToolCall, ToolResult,
     };
 
     impl<Impl: ExtendedGuest> Guest for DurableLLM<Impl> {
         type ChatStream = Impl::ChatStream;
 
         fn send(messages: Vec<Message>, config: Config) -> ChatEvent {
-            Impl::send(messages, config)
+            Impl::send(messages, with_resolved_model(config))
         }
 
         fn continue_(
@@ -81,11 +190,74 @@ mod passthrough_impl {
             tool_results: Vec<(ToolCall, ToolResult)>,
             config: Config,
         ) -> ChatEvent {
-            Impl::continue_(messages, tool_results, config)
+            Impl::continue_(messages, tool_results, with_resolved_model(config))
+        }
+
+        fn send_batch(
+            requests: Vec<(Vec<Message>, Config)>,
+            abort_on_error: bool,
+        ) -> Vec<ChatEvent> {
+            let requests = requests
+                .into_iter()
+                .map(|(messages, config)| (messages, with_resolved_model(config)))
+                .collect();
+            match max_concurrency() {
+                Some(max_in_flight) => stream_batch(
+                    requests,
+                    abort_on_error,
+                    max_in_flight,
+                    Impl::unwrapped_stream,
+                    |stream| Impl::subscribe(stream).block(),
+                ),
+                None => Impl::send_batch(requests, abort_on_error),
+            }
         }
 
         fn stream(messages: Vec<Message>, config: Config) -> ChatStream {
-            Impl::stream(messages, config)
+            Impl::stream(messages, with_resolved_model(config))
+        }
+
+        fn continue_stream(
+            messages: Vec<Message>,
+            tool_results: Vec<(ToolCall, ToolResult)>,
+            config: Config,
+        ) -> ChatStream {
+            Impl::continue_stream(messages, tool_results, with_resolved_model(config))
+        }
+
+        fn send_raw(body_json: String, config: Config) -> Result<String, Error> {
+            Impl::send_raw(body_json, with_resolved_model(config))
+        }
+
+        fn rerank(
+            query: String,
+            documents: Vec<String>,
+            config: RerankConfig,
+        ) -> Result<RerankResult, Error> {
+            Impl::rerank(query, documents, config)
+        }
+
+        fn embed(
+            inputs: Vec<ContentPart>,
+            config: EmbeddingConfig,
+        ) -> Result<EmbeddingResult, Error> {
+            Impl::embed(inputs, config)
+        }
+
+        fn ping() -> Result<ProviderHealth, Error> {
+            Impl::ping()
+        }
+
+        fn capabilities() -> ProviderCapabilities {
+            Impl::capabilities()
+        }
+
+        fn warm(config: Config) -> Result<(), Error> {
+            Impl::warm(with_resolved_model(config))
+        }
+
+        fn generate_image(prompt: String, config: ImageGenConfig) -> Result<ImageGenResult, Error> {
+            Impl::generate_image(prompt, config)
         }
     }
 }
@@ -100,19 +272,32 @@ mod passthrough_impl {
 /// which is implemented using the type classes and builder in the `golem-rust` library.
 #[cfg(feature = "durability")]
 mod durable_impl {
-    use crate::durability::{DurableLLM, ExtendedGuest};
+    use crate::batch::{max_concurrency, send_batch, stream_batch};
+    use crate::chat_stream::append_delta_text;
+    use crate::config::with_resolved_model;
+    use crate::durability::{DurableLLM, ExtendedGuest, RetryStrategy};
     use crate::golem::llm::llm::{
-        ChatEvent, ChatStream, Config, Guest, GuestChatStream, Message, StreamDelta, StreamEvent,
-        ToolCall, ToolResult,
+        ChatEvent, ChatStream, Config, ContentPart, EmbeddingConfig, EmbeddingResult, Error, Guest,
+        GuestChatStream, ImageGenConfig, ImageGenResult, Message, ProviderCapabilities,
+        ProviderHealth, RerankConfig, RerankResult, StreamDelta, StreamEvent, StreamStart,
+        ToolCall, ToolResult, Usage,
+    };
+    use crate::request_hash::{
+        request_hash, with_request_hash_event, with_request_hash_stream_events,
+    };
+    use crate::request_id::{
+        with_request_id, with_request_id_event, with_request_id_stream_events,
     };
     use golem_rust::bindings::golem::durability::durability::{
         DurableFunctionType, LazyInitializedPollable,
     };
     use golem_rust::durability::Durability;
-    use golem_rust::wasm_rpc::Pollable;
+    use golem_rust::value_and_type;
+    use golem_rust::wasm_rpc::{Pollable, WitValueExtractor};
     use golem_rust::{with_persistence_level, FromValueAndType, IntoValue, PersistenceLevel};
     use std::cell::RefCell;
     use std::fmt::{Display, Formatter};
+    use std::time::{SystemTime, UNIX_EPOCH};
 
     impl<Impl: ExtendedGuest> Guest for DurableLLM<Impl> {
         type ChatStream = DurableChatStream<Impl>;
@@ -124,10 +309,24 @@ mod durable_impl {
                 DurableFunctionType::WriteRemote,
             );
             if durability.is_live() {
+                let config = with_resolved_model(config);
+                let request_hash = request_hash(&messages, &[], &config);
+                let (request_id, config) = with_request_id(config, new_request_id);
                 let result = with_persistence_level(PersistenceLevel::PersistNothing, || {
                     Impl::send(messages.clone(), config.clone())
                 });
-                durability.persist_infallible(SendInput { messages, config }, result)
+                durability.persist_infallible(
+                    SendInput {
+                        messages,
+                        config,
+                        request_id: request_id.clone(),
+                        request_hash: request_hash.clone(),
+                    },
+                    with_request_hash_event(
+                        with_request_id_event(result, &request_id),
+                        &request_hash,
+                    ),
+                )
             } else {
                 durability.replay_infallible()
             }
@@ -144,6 +343,9 @@ mod durable_impl {
                 DurableFunctionType::WriteRemote,
             );
             if durability.is_live() {
+                let config = with_resolved_model(config);
+                let request_hash = request_hash(&messages, &tool_results, &config);
+                let (request_id, config) = with_request_id(config, new_request_id);
                 let result = with_persistence_level(PersistenceLevel::PersistNothing, || {
                     Impl::continue_(messages.clone(), tool_results.clone(), config.clone())
                 });
@@ -152,6 +354,79 @@ mod durable_impl {
                         messages,
                         tool_results,
                         config,
+                        request_id: request_id.clone(),
+                        request_hash: request_hash.clone(),
+                    },
+                    with_request_hash_event(
+                        with_request_id_event(result, &request_id),
+                        &request_hash,
+                    ),
+                )
+            } else {
+                durability.replay_infallible()
+            }
+        }
+
+        /// Each request of the batch goes through the durable `send`, so every sub-result is
+        /// persisted separately and a batch interrupted mid-way resumes with the remaining requests.
+        ///
+        /// With `GOLEM_LLM_MAX_CONCURRENCY` set, the requests are in flight at the same time, so
+        /// they cannot be persisted one by one in order. The whole batch is persisted as a single
+        /// entry instead, which means a batch interrupted mid-way is sent again in full.
+        fn send_batch(
+            requests: Vec<(Vec<Message>, Config)>,
+            abort_on_error: bool,
+        ) -> Vec<ChatEvent> {
+            let Some(max_in_flight) = max_concurrency() else {
+                return send_batch(requests, abort_on_error, Self::send);
+            };
+
+            let durability = Durability::<Vec<ChatEvent>, UnusedError>::new(
+                "golem_llm",
+                "send_batch",
+                DurableFunctionType::WriteRemote,
+            );
+            if durability.is_live() {
+                let requests = requests
+                    .into_iter()
+                    .map(|(messages, config)| {
+                        let config = with_resolved_model(config);
+                        let request_hash = request_hash(&messages, &[], &config);
+                        let (request_id, config) = with_request_id(config, new_request_id);
+                        SendInput {
+                            messages,
+                            config,
+                            request_id,
+                            request_hash,
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                let result = with_persistence_level(PersistenceLevel::PersistNothing, || {
+                    stream_batch(
+                        requests
+                            .iter()
+                            .map(|input| (input.messages.clone(), input.config.clone()))
+                            .collect(),
+                        abort_on_error,
+                        max_in_flight,
+                        Impl::unwrapped_stream,
+                        |stream| Impl::subscribe(stream).block(),
+                    )
+                });
+                let result = result
+                    .into_iter()
+                    .zip(&requests)
+                    .map(|(event, input)| {
+                        with_request_hash_event(
+                            with_request_id_event(event, &input.request_id),
+                            &input.request_hash,
+                        )
+                    })
+                    .collect();
+                durability.persist_infallible(
+                    SendBatchInput {
+                        requests,
+                        abort_on_error,
                     },
                     result,
                 )
@@ -161,23 +436,224 @@ mod durable_impl {
         }
 
         fn stream(messages: Vec<Message>, config: Config) -> ChatStream {
-            let durability = Durability::<NoOutput, UnusedError>::new(
+            let durability = Durability::<StreamOutput, UnusedError>::new(
                 "golem_llm",
                 "stream",
                 DurableFunctionType::WriteRemote,
             );
             if durability.is_live() {
+                let config = with_resolved_model(config);
+                let request_hash = request_hash(&messages, &[], &config);
+                let (request_id, config) = with_request_id(config, new_request_id);
+                let resolved_model = config.model.clone();
+                let started_at_ms = wall_clock_ms();
+                let result = with_persistence_level(PersistenceLevel::PersistNothing, || {
+                    ChatStream::new(DurableChatStream::<Impl>::live(
+                        Impl::unwrapped_stream(messages.clone(), config.clone()),
+                        request_id.clone(),
+                        request_hash.clone(),
+                    ))
+                });
+                let _ = durability.persist_infallible(
+                    SendInput {
+                        messages,
+                        config,
+                        request_id: request_id.clone(),
+                        request_hash,
+                    },
+                    StreamOutput {
+                        request_id: Some(request_id),
+                        model: Some(resolved_model),
+                        started_at_ms: Some(started_at_ms),
+                    },
+                );
+                result
+            } else {
+                let output: StreamOutput = durability.replay_infallible();
+                let started_at_ms = output.started_at_ms;
+                let (request_id, config) = replayed_stream_config(output, config);
+                let request_hash = request_hash(&messages, &[], &config);
+                ChatStream::new(DurableChatStream::<Impl>::replay(
+                    messages,
+                    vec![],
+                    config,
+                    started_at_ms,
+                    request_id,
+                    request_hash,
+                ))
+            }
+        }
+
+        fn continue_stream(
+            messages: Vec<Message>,
+            tool_results: Vec<(ToolCall, ToolResult)>,
+            config: Config,
+        ) -> ChatStream {
+            let durability = Durability::<StreamOutput, UnusedError>::new(
+                "golem_llm",
+                "continue_stream",
+                DurableFunctionType::WriteRemote,
+            );
+            if durability.is_live() {
+                let config = with_resolved_model(config);
+                let request_hash = request_hash(&messages, &tool_results, &config);
+                let (request_id, config) = with_request_id(config, new_request_id);
+                let resolved_model = config.model.clone();
+                let started_at_ms = wall_clock_ms();
                 let result = with_persistence_level(PersistenceLevel::PersistNothing, || {
-                    ChatStream::new(DurableChatStream::<Impl>::live(Impl::unwrapped_stream(
-                        messages.clone(),
-                        config.clone(),
-                    )))
+                    ChatStream::new(DurableChatStream::<Impl>::live(
+                        Impl::unwrapped_continue_stream(
+                            messages.clone(),
+                            tool_results.clone(),
+                            config.clone(),
+                        ),
+                        request_id.clone(),
+                        request_hash.clone(),
+                    ))
                 });
-                let _ = durability.persist_infallible(SendInput { messages, config }, NoOutput);
+                let _ = durability.persist_infallible(
+                    ContinueInput {
+                        messages,
+                        tool_results,
+                        config,
+                        request_id: request_id.clone(),
+                        request_hash,
+                    },
+                    StreamOutput {
+                        request_id: Some(request_id),
+                        model: Some(resolved_model),
+                        started_at_ms: Some(started_at_ms),
+                    },
+                );
                 result
             } else {
-                let _: NoOutput = durability.replay_infallible();
-                ChatStream::new(DurableChatStream::<Impl>::replay(messages, config))
+                let output: StreamOutput = durability.replay_infallible();
+                let started_at_ms = output.started_at_ms;
+                let (request_id, config) = replayed_stream_config(output, config);
+                let request_hash = request_hash(&messages, &tool_results, &config);
+                ChatStream::new(DurableChatStream::<Impl>::replay(
+                    messages,
+                    tool_results,
+                    config,
+                    started_at_ms,
+                    request_id,
+                    request_hash,
+                ))
+            }
+        }
+
+        fn send_raw(body_json: String, config: Config) -> Result<String, Error> {
+            let durability = Durability::<Result<String, Error>, UnusedError>::new(
+                "golem_llm",
+                "send_raw",
+                DurableFunctionType::WriteRemote,
+            );
+            if durability.is_live() {
+                let config = with_resolved_model(config);
+                let result = with_persistence_level(PersistenceLevel::PersistNothing, || {
+                    Impl::send_raw(body_json.clone(), config.clone())
+                });
+                durability.persist_infallible(SendRawInput { body_json, config }, result)
+            } else {
+                durability.replay_infallible()
+            }
+        }
+
+        fn rerank(
+            query: String,
+            documents: Vec<String>,
+            config: RerankConfig,
+        ) -> Result<RerankResult, Error> {
+            let durability = Durability::<Result<RerankResult, Error>, UnusedError>::new(
+                "golem_llm",
+                "rerank",
+                DurableFunctionType::ReadRemote,
+            );
+            if durability.is_live() {
+                let result = with_persistence_level(PersistenceLevel::PersistNothing, || {
+                    Impl::rerank(query.clone(), documents.clone(), config.clone())
+                });
+                durability.persist_infallible(
+                    RerankInput {
+                        query,
+                        documents,
+                        config,
+                    },
+                    result,
+                )
+            } else {
+                durability.replay_infallible()
+            }
+        }
+
+        fn embed(
+            inputs: Vec<ContentPart>,
+            config: EmbeddingConfig,
+        ) -> Result<EmbeddingResult, Error> {
+            let durability = Durability::<Result<EmbeddingResult, Error>, UnusedError>::new(
+                "golem_llm",
+                "embed",
+                DurableFunctionType::ReadRemote,
+            );
+            if durability.is_live() {
+                let result = with_persistence_level(PersistenceLevel::PersistNothing, || {
+                    Impl::embed(inputs.clone(), config.clone())
+                });
+                durability.persist_infallible(EmbedInput { inputs, config }, result)
+            } else {
+                durability.replay_infallible()
+            }
+        }
+
+        fn ping() -> Result<ProviderHealth, Error> {
+            let durability = Durability::<Result<ProviderHealth, Error>, UnusedError>::new(
+                "golem_llm",
+                "ping",
+                DurableFunctionType::ReadRemote,
+            );
+            if durability.is_live() {
+                let result = with_persistence_level(PersistenceLevel::PersistNothing, Impl::ping);
+                durability.persist_infallible(NoInput, result)
+            } else {
+                durability.replay_infallible()
+            }
+        }
+
+        /// The capabilities are a static description of the provider, so they are not persisted
+        fn capabilities() -> ProviderCapabilities {
+            Impl::capabilities()
+        }
+
+        fn warm(config: Config) -> Result<(), Error> {
+            let durability = Durability::<Result<(), Error>, UnusedError>::new(
+                "golem_llm",
+                "warm",
+                DurableFunctionType::WriteRemote,
+            );
+            if durability.is_live() {
+                let config = with_resolved_model(config);
+                let result = with_persistence_level(PersistenceLevel::PersistNothing, || {
+                    Impl::warm(config.clone())
+                });
+                durability.persist_infallible(WarmInput { config }, result)
+            } else {
+                durability.replay_infallible()
+            }
+        }
+
+        fn generate_image(prompt: String, config: ImageGenConfig) -> Result<ImageGenResult, Error> {
+            let durability = Durability::<Result<ImageGenResult, Error>, UnusedError>::new(
+                "golem_llm",
+                "generate_image",
+                DurableFunctionType::WriteRemote,
+            );
+            if durability.is_live() {
+                let result = with_persistence_level(PersistenceLevel::PersistNothing, || {
+                    Impl::generate_image(prompt.clone(), config.clone())
+                });
+                durability.persist_infallible(GenerateImageInput { prompt, config }, result)
+            } else {
+                durability.replay_infallible()
             }
         }
     }
@@ -194,17 +670,57 @@ mod durable_impl {
     /// When reaching the end of the replay mode, if the replayed stream was not finished yet,
     /// the replay prompt implemented in `ExtendedGuest` is used to create a new LLM response
     /// stream and continue the response seamlessly.
+    ///
+    /// The usage of the interrupted segments, persisted with each `get_next` output whether or not
+    /// the deltas report their running usage, is kept in `partial_usage`, and carried over to the
+    /// new live stream as `previous_usage` so its deltas and final finish event report the token
+    /// counts of all the segments together.
+    ///
+    /// A stream continuing a conversation with tool results keeps them in `tool_results`, and the
+    /// new live stream is created with them sent after the retry prompt.
+    ///
+    /// Similarly, `started` records whether a start event was already emitted by an interrupted
+    /// segment, in which case the start event of the new live stream is dropped.
+    ///
+    /// The `started_at_ms` persisted when the stream was created is the wall-clock time its
+    /// request was sent at, so the new live stream only gets what is left of the `deadline-ms`
+    /// of the config instead of a new budget.
+    ///
+    /// The `request_id` is the one persisted when the stream was created, added to the finish
+    /// event's provider metadata and sent again by the new live stream after a replay. The
+    /// `request_hash` is added to the finish event's provider metadata too, being computed from
+    /// the same messages and config on replay.
+    ///
+    /// The text of `partial_result` is kept as `replayed_text` by the new live stream, so
+    /// `partial_text` reports the text of all the segments together.
+    ///
+    /// With the `restart` retry strategy, the new live stream is created with the original
+    /// messages instead of the retry prompt, and sends the whole response again, preceded by a
+    /// start event telling the consumer to discard the deltas it received before. The partial
+    /// result and its usage are discarded, so `replayed_text` is empty, and `partial_text` and the
+    /// usage only report the new response.
     enum DurableChatStreamState<Impl: ExtendedGuest> {
         Live {
             stream: Impl::ChatStream,
             pollables: Vec<LazyInitializedPollable>,
+            previous_usage: Option<Usage>,
+            started: bool,
+            request_id: String,
+            request_hash: String,
+            replayed_text: String,
         },
         Replay {
             original_messages: Vec<Message>,
+            tool_results: Vec<(ToolCall, ToolResult)>,
             config: Config,
+            started_at_ms: Option<u64>,
             pollables: Vec<LazyInitializedPollable>,
             partial_result: Vec<StreamDelta>,
+            partial_usage: Option<Usage>,
+            started: bool,
             finished: bool,
+            request_id: String,
+            request_hash: String,
         },
     }
 
@@ -214,24 +730,42 @@ mod durable_impl {
     }
 
     impl<Impl: ExtendedGuest> DurableChatStream<Impl> {
-        fn live(stream: Impl::ChatStream) -> Self {
+        fn live(stream: Impl::ChatStream, request_id: String, request_hash: String) -> Self {
             Self {
                 state: RefCell::new(Some(DurableChatStreamState::Live {
                     stream,
                     pollables: Vec::new(),
+                    previous_usage: None,
+                    started: false,
+                    request_id,
+                    request_hash,
+                    replayed_text: String::new(),
                 })),
                 subscription: RefCell::new(None),
             }
         }
 
-        fn replay(original_messages: Vec<Message>, config: Config) -> Self {
+        fn replay(
+            original_messages: Vec<Message>,
+            tool_results: Vec<(ToolCall, ToolResult)>,
+            config: Config,
+            started_at_ms: Option<u64>,
+            request_id: String,
+            request_hash: String,
+        ) -> Self {
             Self {
                 state: RefCell::new(Some(DurableChatStreamState::Replay {
                     original_messages,
+                    tool_results,
                     config,
+                    started_at_ms,
                     pollables: Vec::new(),
                     partial_result: Vec::new(),
+                    partial_usage: None,
+                    started: false,
                     finished: false,
+                    request_id,
+                    request_hash,
                 })),
                 subscription: RefCell::new(None),
             }
@@ -261,6 +795,7 @@ mod durable_impl {
                 Some(DurableChatStreamState::Live {
                     mut pollables,
                     stream,
+                    ..
                 }) => {
                     with_persistence_level(PersistenceLevel::PersistNothing, move || {
                         pollables.clear();
@@ -277,7 +812,7 @@ mod durable_impl {
 
     impl<Impl: ExtendedGuest> GuestChatStream for DurableChatStream<Impl> {
         fn get_next(&self) -> Option<Vec<StreamEvent>> {
-            let durability = Durability::<Option<Vec<StreamEvent>>, UnusedError>::new(
+            let durability = Durability::<GetNextOutput, UnusedError>::new(
                 "golem_llm",
                 "get_next",
                 DurableFunctionType::ReadRemote,
@@ -285,43 +820,103 @@ mod durable_impl {
             if durability.is_live() {
                 let mut state = self.state.borrow_mut();
                 let (result, new_live_stream) = match &*state {
-                    Some(DurableChatStreamState::Live { stream, .. }) => {
-                        let result =
+                    Some(DurableChatStreamState::Live {
+                        stream,
+                        previous_usage,
+                        started,
+                        request_id,
+                        request_hash,
+                        ..
+                    }) => {
+                        let (result, usage) =
                             with_persistence_level(PersistenceLevel::PersistNothing, || {
-                                stream.get_next()
+                                (stream.get_next(), Impl::usage_so_far(stream))
                             });
-                        (durability.persist_infallible(NoInput, result.clone()), None)
+                        let result = result.map(|events| {
+                            with_request_hash_stream_events(
+                                with_request_id_stream_events(
+                                    with_previous_usage(
+                                        without_repeated_start(events, *started),
+                                        previous_usage,
+                                    ),
+                                    request_id,
+                                ),
+                                request_hash,
+                            )
+                        });
+                        let output = durability.persist_infallible(
+                            NoInput,
+                            GetNextOutput {
+                                events: result,
+                                usage: with_previous(previous_usage, usage),
+                            },
+                        );
+                        (output.events, None)
                     }
                     Some(DurableChatStreamState::Replay {
                         original_messages,
+                        tool_results,
                         config,
+                        started_at_ms,
                         pollables,
                         partial_result,
+                        partial_usage,
+                        started,
                         finished,
+                        request_id,
+                        request_hash,
                     }) => {
                         if *finished {
                             (None, None)
                         } else {
-                            let extended_messages =
-                                Impl::retry_prompt(original_messages, partial_result);
+                            let retry_strategy = RetryStrategy::from_env();
+                            let extended_messages = match retry_strategy {
+                                RetryStrategy::Continue => {
+                                    Impl::retry_prompt(original_messages, partial_result)
+                                }
+                                RetryStrategy::Restart => Impl::restart_prompt(original_messages),
+                            };
+                            let previous_usage = resumed_usage(retry_strategy, partial_usage);
 
-                            let (stream, first_live_result) =
+                            let (stream, first_live_result, usage) =
                                 with_persistence_level(PersistenceLevel::PersistNothing, || {
-                                    let stream = <Impl as ExtendedGuest>::unwrapped_stream(
+                                    let stream = <Impl as ExtendedGuest>::unwrapped_continue_stream(
                                         extended_messages,
-                                        config.clone(),
+                                        tool_results.clone(),
+                                        with_remaining_deadline(
+                                            config.clone(),
+                                            *started_at_ms,
+                                            wall_clock_ms(),
+                                        ),
                                     );
 
                                     for lazy_initialized_pollable in pollables {
                                         lazy_initialized_pollable.set(Impl::subscribe(&stream));
                                     }
 
-                                    let next = stream.get_next();
-                                    (stream, next)
+                                    let next = resumed_events(
+                                        retry_strategy,
+                                        stream.get_next(),
+                                        *started,
+                                        &previous_usage,
+                                        request_id,
+                                        request_hash,
+                                    );
+                                    let usage = Impl::usage_so_far(&stream);
+                                    (stream, next, usage)
                                 });
-                            durability.persist_infallible(NoInput, first_live_result.clone());
+                            durability.persist_infallible(
+                                NoInput,
+                                GetNextOutput {
+                                    events: first_live_result.clone(),
+                                    usage: with_previous(&previous_usage, usage),
+                                },
+                            );
 
-                            (first_live_result, Some(stream))
+                            (
+                                first_live_result,
+                                Some((stream, retry_strategy, previous_usage)),
+                            )
                         }
                     }
                     None => {
@@ -329,20 +924,65 @@ mod durable_impl {
                     }
                 };
 
-                if let Some(stream) = new_live_stream {
-                    let pollables = match state.take() {
-                        Some(DurableChatStreamState::Live { pollables, .. }) => pollables,
-                        Some(DurableChatStreamState::Replay { pollables, .. }) => pollables,
+                if let Some((stream, retry_strategy, resumed_usage)) = new_live_stream {
+                    let (
+                        pollables,
+                        previous_usage,
+                        started,
+                        request_id,
+                        request_hash,
+                        replayed_text,
+                    ) = match state.take() {
+                        Some(DurableChatStreamState::Live {
+                            pollables,
+                            previous_usage,
+                            started,
+                            request_id,
+                            request_hash,
+                            replayed_text,
+                            ..
+                        }) => (
+                            pollables,
+                            previous_usage,
+                            started,
+                            request_id,
+                            request_hash,
+                            replayed_text,
+                        ),
+                        Some(DurableChatStreamState::Replay {
+                            pollables,
+                            partial_result,
+                            started,
+                            request_id,
+                            request_hash,
+                            ..
+                        }) => (
+                            pollables,
+                            resumed_usage,
+                            // A restarted stream always sends a start event first
+                            started || retry_strategy == RetryStrategy::Restart,
+                            request_id,
+                            request_hash,
+                            resumed_text(retry_strategy, &partial_result),
+                        ),
                         None => {
                             unreachable!()
                         }
                     };
-                    *state = Some(DurableChatStreamState::Live { stream, pollables });
+                    *state = Some(DurableChatStreamState::Live {
+                        stream,
+                        pollables,
+                        previous_usage,
+                        started,
+                        request_id,
+                        request_hash,
+                        replayed_text,
+                    });
                 }
 
                 result
             } else {
-                let result: Option<Vec<StreamEvent>> = durability.replay_infallible();
+                let output: GetNextOutput = durability.replay_infallible();
                 let mut state = self.state.borrow_mut();
                 match &mut *state {
                     Some(DurableChatStreamState::Live { .. }) => {
@@ -350,30 +990,24 @@ mod durable_impl {
                     }
                     Some(DurableChatStreamState::Replay {
                         partial_result,
+                        partial_usage,
+                        started,
                         finished,
                         ..
                     }) => {
-                        if let Some(result) = &result {
-                            for event in result {
-                                match event {
-                                    StreamEvent::Delta(delta) => {
-                                        partial_result.push(delta.clone());
-                                    }
-                                    StreamEvent::Finish(_) => {
-                                        *finished = true;
-                                    }
-                                    StreamEvent::Error(_) => {
-                                        *finished = true;
-                                    }
-                                }
-                            }
-                        }
+                        record_replayed_output(
+                            &output,
+                            partial_result,
+                            partial_usage,
+                            started,
+                            finished,
+                        );
                     }
                     None => {
                         unreachable!()
                     }
                 }
-                result
+                output.events
             }
         }
 
@@ -385,7 +1019,18 @@ mod durable_impl {
             let subscription = subscription.as_mut().unwrap();
             let mut result = Vec::new();
             loop {
-                subscription.block();
+                let deadline = match &*self.state.borrow() {
+                    Some(DurableChatStreamState::Live { stream, .. }) => {
+                        Impl::subscribe_deadline(stream)
+                    }
+                    _ => None,
+                };
+                match deadline {
+                    Some(deadline) => {
+                        golem_rust::bindings::wasi::io::poll::poll(&[&*subscription, &deadline]);
+                    }
+                    None => subscription.block(),
+                }
                 match self.get_next() {
                     Some(events) => {
                         result.extend(events);
@@ -395,46 +1040,470 @@ mod durable_impl {
                 }
             }
         }
-    }
 
-    #[derive(Debug, Clone, PartialEq, IntoValue)]
-    struct SendInput {
-        messages: Vec<Message>,
-        config: Config,
-    }
+        /// Reading the accumulated text has no side effect, so nothing is persisted for it
+        fn partial_text(&self) -> String {
+            match &*self.state.borrow() {
+                Some(DurableChatStreamState::Live {
+                    stream,
+                    replayed_text,
+                    ..
+                }) => format!("{replayed_text}{}", stream.partial_text()),
+                Some(DurableChatStreamState::Replay { partial_result, .. }) => {
+                    partial_text(partial_result)
+                }
+                None => {
+                    unreachable!()
+                }
+            }
+        }
 
-    #[derive(Debug, IntoValue)]
-    struct ContinueInput {
-        messages: Vec<Message>,
-        tool_results: Vec<(ToolCall, ToolResult)>,
-        config: Config,
+        /// The transcript is persisted, so a replayed worker returns the same text even though
+        /// the replayed stream received nothing from the provider. A live stream continuing an
+        /// interrupted one only has the text received since it was created.
+        fn raw_transcript(&self) -> String {
+            let durability = Durability::<String, UnusedError>::new(
+                "golem_llm",
+                "raw_transcript",
+                DurableFunctionType::ReadLocal,
+            );
+            if durability.is_live() {
+                let transcript = match &*self.state.borrow() {
+                    Some(DurableChatStreamState::Live { stream, .. }) => {
+                        with_persistence_level(PersistenceLevel::PersistNothing, || {
+                            stream.raw_transcript()
+                        })
+                    }
+                    Some(DurableChatStreamState::Replay { .. }) => String::new(),
+                    None => {
+                        unreachable!()
+                    }
+                };
+                durability.persist_infallible(NoInput, transcript)
+            } else {
+                durability.replay_infallible()
+            }
+        }
     }
 
-    #[derive(Debug, IntoValue)]
-    struct NoInput;
-
-    #[derive(Debug, Clone, FromValueAndType, IntoValue)]
-    struct NoOutput;
+    /// The text of the interrupted segments kept by the new live stream, which is none when the
+    /// partial result is discarded by the `restart` retry strategy
+    fn resumed_text(retry_strategy: RetryStrategy, partial_result: &[StreamDelta]) -> String {
+        match retry_strategy {
+            RetryStrategy::Continue => partial_text(partial_result),
+            RetryStrategy::Restart => String::new(),
+        }
+    }
 
-    #[derive(Debug, FromValueAndType, IntoValue)]
-    struct UnusedError;
+    /// The config of the new live stream resuming an interrupted one, whose `deadline-ms` is
+    /// reduced by the time elapsed since the request was sent, so the stream does not get a new
+    /// budget. Streams persisted without the time of their request keep the whole budget.
+    fn with_remaining_deadline(config: Config, started_at_ms: Option<u64>, now_ms: u64) -> Config {
+        match (config.deadline_ms, started_at_ms) {
+            (Some(deadline_ms), Some(started_at_ms)) => {
+                let elapsed_ms = now_ms.saturating_sub(started_at_ms);
+                let remaining_ms = (deadline_ms as u64).saturating_sub(elapsed_ms);
+                Config {
+                    deadline_ms: Some(remaining_ms as u32),
+                    ..config
+                }
+            }
+            _ => config,
+        }
+    }
 
-    impl Display for UnusedError {
-        fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-            write!(f, "UnusedError")
+    /// The usage of the interrupted segments carried over to the new live stream, which is none
+    /// when the partial result is discarded by the `restart` retry strategy, as the stream then
+    /// only reports the response sent again
+    fn resumed_usage(
+        retry_strategy: RetryStrategy,
+        partial_usage: &Option<Usage>,
+    ) -> Option<Usage> {
+        match retry_strategy {
+            RetryStrategy::Continue => partial_usage.clone(),
+            RetryStrategy::Restart => None,
         }
     }
 
-    #[cfg(test)]
-    mod tests {
-        use crate::durability::durable_impl::SendInput;
-        use crate::golem::llm::llm::{
-            ChatEvent, CompleteResponse, Config, ContentPart, Error, ErrorCode, FinishReason,
-            ImageDetail, ImageReference, ImageSource, ImageUrl, Message, ResponseMetadata, Role,
-            ToolCall, Usage,
+    /// The first events of the new live stream resuming an interrupted one, `None` if it has not
+    /// received anything yet.
+    ///
+    /// With the `restart` retry strategy, the events always begin with a start event, even before
+    /// anything is received, which tells the consumer that the response is sent again from its
+    /// beginning, and that the deltas it received before are to be discarded.
+    fn resumed_events(
+        retry_strategy: RetryStrategy,
+        events: Option<Vec<StreamEvent>>,
+        started: bool,
+        previous_usage: &Option<Usage>,
+        request_id: &str,
+        request_hash: &str,
+    ) -> Option<Vec<StreamEvent>> {
+        let events = match retry_strategy {
+            RetryStrategy::Continue => without_repeated_start(events?, started),
+            RetryStrategy::Restart => with_leading_start(events.unwrap_or_default(), request_id),
+        };
+        Some(with_request_hash_stream_events(
+            with_request_id_stream_events(with_previous_usage(events, previous_usage), request_id),
+            request_hash,
+        ))
+    }
+
+    /// Moves the start event of the events first, or adds one identified by the request id if
+    /// the provider does not send any
+    fn with_leading_start(events: Vec<StreamEvent>, request_id: &str) -> Vec<StreamEvent> {
+        let (starts, others): (Vec<_>, Vec<_>) = events
+            .into_iter()
+            .partition(|event| matches!(event, StreamEvent::Start(_)));
+        let start = starts.into_iter().next().unwrap_or_else(|| {
+            StreamEvent::Start(StreamStart {
+                id: request_id.to_string(),
+                model: None,
+                created: None,
+            })
+        });
+        std::iter::once(start).chain(others).collect()
+    }
+
+    /// Concatenates the text content of the deltas received so far
+    fn partial_text(partial_result: &[StreamDelta]) -> String {
+        let mut text = String::new();
+        for delta in partial_result {
+            append_delta_text(&mut text, delta);
+        }
+        text
+    }
+
+    /// Adds the token counts of `usage` to `accumulated`, keeping a count `None` only if
+    /// neither side reported it
+    fn add_usage(accumulated: &mut Option<Usage>, usage: &Usage) {
+        fn add(a: Option<u32>, b: Option<u32>) -> Option<u32> {
+            match (a, b) {
+                (Some(a), Some(b)) => Some(a.saturating_add(b)),
+                (a, b) => a.or(b),
+            }
+        }
+
+        *accumulated = Some(match accumulated.take() {
+            Some(acc) => Usage {
+                input_tokens: add(acc.input_tokens, usage.input_tokens),
+                output_tokens: add(acc.output_tokens, usage.output_tokens),
+                total_tokens: add(acc.total_tokens, usage.total_tokens),
+            },
+            None => usage.clone(),
+        });
+    }
+
+    /// The usage of the previous, interrupted stream segments added to the current segment's own
+    /// usage
+    fn with_previous(
+        previous_usage: &Option<Usage>,
+        current_usage: Option<Usage>,
+    ) -> Option<Usage> {
+        let mut usage = previous_usage.clone();
+        if let Some(current_usage) = &current_usage {
+            add_usage(&mut usage, current_usage);
+        }
+        usage
+    }
+
+    /// Makes the running usage of the deltas and the finish events report the usage of the
+    /// previous, interrupted stream segments in addition to the current segment's own usage
+    fn with_previous_usage(
+        events: Vec<StreamEvent>,
+        previous_usage: &Option<Usage>,
+    ) -> Vec<StreamEvent> {
+        if previous_usage.is_none() {
+            return events;
+        }
+        events
+            .into_iter()
+            .map(|event| match event {
+                StreamEvent::Delta(mut delta) if delta.running_usage.is_some() => {
+                    delta.running_usage = with_previous(previous_usage, delta.running_usage);
+                    StreamEvent::Delta(delta)
+                }
+                StreamEvent::Finish(mut metadata) => {
+                    metadata.usage = with_previous(previous_usage, metadata.usage);
+                    StreamEvent::Finish(metadata)
+                }
+                other => other,
+            })
+            .collect()
+    }
+
+    /// Accumulates the deltas of the replayed events, and keeps their last usage, up to the first
+    /// finish or error event. The running usage of the deltas already includes the usage of the
+    /// segments preceding theirs, so the last one is the usage of an interrupted stream. The
+    /// events following the finish, such as a second finish event sent by a provider or a proxy
+    /// merging streams, are ignored, so they cannot replace its usage or add deltas past the end
+    /// of the response. A second start event is the one of a stream restarted by the `restart`
+    /// retry strategy, so the deltas and the usage received before it are discarded.
+    fn record_replayed_events(
+        events: &[StreamEvent],
+        partial_result: &mut Vec<StreamDelta>,
+        partial_usage: &mut Option<Usage>,
+        started: &mut bool,
+        finished: &mut bool,
+    ) {
+        for event in events {
+            if *finished {
+                return;
+            }
+            match event {
+                StreamEvent::Start(_) => {
+                    if *started {
+                        partial_result.clear();
+                        *partial_usage = None;
+                    }
+                    *started = true;
+                }
+                StreamEvent::Delta(delta) => {
+                    if let Some(running_usage) = &delta.running_usage {
+                        *partial_usage = Some(running_usage.clone());
+                    }
+                    partial_result.push(delta.clone());
+                }
+                StreamEvent::Finish(metadata) => {
+                    if metadata.usage.is_some() {
+                        *partial_usage = metadata.usage.clone();
+                    }
+                    *finished = true;
+                }
+                StreamEvent::Error(_) => {
+                    *finished = true;
+                }
+            }
+        }
+    }
+
+    /// Records a replayed `get_next` output like its events. The usage persisted with them, which
+    /// already includes the usage of the segments preceding theirs, replaces the usage of the
+    /// events until the stream is finished, as it is known even when the deltas do not report
+    /// their running usage. Outputs persisted without it keep the usage of their events.
+    fn record_replayed_output(
+        output: &GetNextOutput,
+        partial_result: &mut Vec<StreamDelta>,
+        partial_usage: &mut Option<Usage>,
+        started: &mut bool,
+        finished: &mut bool,
+    ) {
+        if let Some(events) = &output.events {
+            record_replayed_events(events, partial_result, partial_usage, started, finished);
+        }
+        if let (Some(usage), false) = (&output.usage, *finished) {
+            *partial_usage = Some(usage.clone());
+        }
+    }
+
+    /// Drops the start event of a new stream segment when an interrupted segment has already
+    /// emitted one
+    fn without_repeated_start(events: Vec<StreamEvent>, started: bool) -> Vec<StreamEvent> {
+        if started {
+            events
+                .into_iter()
+                .filter(|event| !matches!(event, StreamEvent::Start(_)))
+                .collect()
+        } else {
+            events
+        }
+    }
+
+    /// Creates a new request id using Golem's idempotency key generation. Request ids are only
+    /// created by live calls, so the generation is not persisted on its own, which would make the
+    /// replayed calls skip a host call of the oplog. The id is persisted with the input and the
+    /// output of the call instead, and replayed from them.
+    fn new_request_id() -> String {
+        with_persistence_level(PersistenceLevel::PersistNothing, || {
+            golem_rust::generate_idempotency_key().to_string()
+        })
+    }
+
+    /// The current wall-clock time in milliseconds since the Unix epoch. Like the request ids, it
+    /// is only read by live calls and persisted with their outputs, so the read itself is not
+    /// persisted, and a resumed stream reads the actual time instead of a replayed one.
+    fn wall_clock_ms() -> u64 {
+        with_persistence_level(PersistenceLevel::PersistNothing, || {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_millis() as u64)
+                .unwrap_or_default()
+        })
+    }
+
+    /// Returns the request id and the config of a replayed stream, with the model it was created
+    /// with. Streams persisted without them keep the request id of the config or get a new one,
+    /// and resolve the model again.
+    fn replayed_stream_config(output: StreamOutput, config: Config) -> (String, Config) {
+        let config = match output.model {
+            Some(model) => Config { model, ..config },
+            None => with_resolved_model(config),
+        };
+        with_request_id(config, || output.request_id.unwrap_or_else(new_request_id))
+    }
+
+    #[derive(Debug, Clone, PartialEq, IntoValue)]
+    struct SendInput {
+        messages: Vec<Message>,
+        config: Config,
+        request_id: String,
+        request_hash: String,
+    }
+
+    #[derive(Debug, IntoValue)]
+    struct SendBatchInput {
+        requests: Vec<SendInput>,
+        abort_on_error: bool,
+    }
+
+    #[derive(Debug, IntoValue)]
+    struct ContinueInput {
+        messages: Vec<Message>,
+        tool_results: Vec<(ToolCall, ToolResult)>,
+        config: Config,
+        request_id: String,
+        request_hash: String,
+    }
+
+    #[derive(Debug, IntoValue)]
+    struct SendRawInput {
+        body_json: String,
+        config: Config,
+    }
+
+    #[derive(Debug, IntoValue)]
+    struct RerankInput {
+        query: String,
+        documents: Vec<String>,
+        config: RerankConfig,
+    }
+
+    #[derive(Debug, IntoValue)]
+    struct EmbedInput {
+        inputs: Vec<ContentPart>,
+        config: EmbeddingConfig,
+    }
+
+    #[derive(Debug, IntoValue)]
+    struct WarmInput {
+        config: Config,
+    }
+
+    #[derive(Debug, IntoValue)]
+    struct GenerateImageInput {
+        prompt: String,
+        config: ImageGenConfig,
+    }
+
+    #[derive(Debug, IntoValue)]
+    struct NoInput;
+
+    /// The output of the durable streams. It was empty before the request id and the model were
+    /// added, and has string fields in the first versions having them, so it is decoded by hand
+    /// to replay the oplogs of every version.
+    #[derive(Debug, Clone, PartialEq, IntoValue)]
+    struct StreamOutput {
+        request_id: Option<String>,
+        /// The model the stream was created with, after resolving its alias, so a replayed
+        /// stream resumes with the same model even if the aliases changed since
+        model: Option<String>,
+        /// The wall-clock time the request was sent at, in milliseconds since the Unix epoch
+        started_at_ms: Option<u64>,
+    }
+
+    impl value_and_type::FromValueAndType for StreamOutput {
+        fn from_extractor<'a, 'b>(
+            extractor: &'a impl WitValueExtractor<'a, 'b>,
+        ) -> Result<Self, String> {
+            Ok(Self {
+                request_id: optional_string_field(extractor, 0),
+                model: optional_string_field(extractor, 1),
+                started_at_ms: extractor.field(2).and_then(|field| {
+                    <Option<u64> as value_and_type::FromValueAndType>::from_extractor(&field)
+                        .ok()
+                        .flatten()
+                }),
+            })
+        }
+    }
+
+    /// Reads a record field which is either a string or an optional string, `None` if the
+    /// record does not have it
+    fn optional_string_field<'a, 'b>(
+        extractor: &'a impl WitValueExtractor<'a, 'b>,
+        index: usize,
+    ) -> Option<String> {
+        let field = extractor.field(index)?;
+        <Option<String> as value_and_type::FromValueAndType>::from_extractor(&field)
+            .or_else(|_| {
+                <String as value_and_type::FromValueAndType>::from_extractor(&field).map(Some)
+            })
+            .ok()
+            .flatten()
+    }
+
+    /// The output of the durable `get_next`. It was the events alone before their usage was added,
+    /// so it is decoded by hand to replay the oplogs of every version.
+    #[derive(Debug, Clone, PartialEq, IntoValue)]
+    struct GetNextOutput {
+        events: Option<Vec<StreamEvent>>,
+        /// The usage of the response received so far, including the usage of the interrupted
+        /// segments preceding the current one
+        usage: Option<Usage>,
+    }
+
+    impl value_and_type::FromValueAndType for GetNextOutput {
+        fn from_extractor<'a, 'b>(
+            extractor: &'a impl WitValueExtractor<'a, 'b>,
+        ) -> Result<Self, String> {
+            let Some(events) = extractor.field(0) else {
+                return Ok(Self {
+                    events: value_and_type::FromValueAndType::from_extractor(extractor)?,
+                    usage: None,
+                });
+            };
+            Ok(Self {
+                events: value_and_type::FromValueAndType::from_extractor(&events)?,
+                usage: extractor.field(1).and_then(|field| {
+                    <Option<Usage> as value_and_type::FromValueAndType>::from_extractor(&field)
+                        .ok()
+                        .flatten()
+                }),
+            })
+        }
+    }
+
+    #[derive(Debug, FromValueAndType, IntoValue)]
+    struct UnusedError;
+
+    impl Display for UnusedError {
+        fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+            write!(f, "UnusedError")
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use crate::collect::StreamCollector;
+        use crate::durability::durable_impl::{
+            add_usage, partial_text, record_replayed_events, record_replayed_output,
+            replayed_stream_config, resumed_events, resumed_text, resumed_usage,
+            with_previous_usage, with_remaining_deadline, without_repeated_start, GetNextOutput,
+            SendInput, StreamOutput,
+        };
+        use crate::durability::RetryStrategy;
+        use crate::golem::llm::llm::{
+            Annotation, AudioSource, ChatEvent, CompleteResponse, Config, ContentPart, Embedding,
+            EmbeddingConfig, EmbeddingResult, Error, ErrorCode, FinishReason, ImageDetail,
+            ImageGenConfig, ImageGenResult, ImageReference, ImageResponseFormat, ImageSource,
+            ImageUrl, Kv, Message, ProviderHealth, ReasoningEffort, RerankConfig, RerankResult,
+            RerankScore, ResponseMetadata, Role, StreamDelta, StreamEvent, StreamOptions,
+            StreamStart, ToolCall, ToolCallFragment, ToolResult, ToolResultContent, ToolSuccess,
+            Usage,
         };
         use golem_rust::value_and_type::{FromValueAndType, IntoValueAndType};
-        use golem_rust::wasm_rpc::WitTypeNode;
+        use golem_rust::wasm_rpc::{Value, WitTypeNode};
+        use golem_rust::IntoValue;
         use std::fmt::Debug;
 
         fn roundtrip_test<T: Debug + Clone + PartialEq + IntoValueAndType + FromValueAndType>(
@@ -464,6 +1533,26 @@ mod durable_impl {
                 message: "Authentication failed".to_string(),
                 provider_error_json: None,
             });
+            roundtrip_test(Error {
+                code: ErrorCode::RateLimitExceeded,
+                message: "Request failed with 429 Too Many Requests".to_string(),
+                provider_error_json: Some("{\"rate_limit\": {\"remaining\": \"0\"}}".to_string()),
+            });
+            roundtrip_test(Error {
+                code: ErrorCode::Timeout,
+                message: "Request failed: operation timed out".to_string(),
+                provider_error_json: None,
+            });
+            roundtrip_test(Error {
+                code: ErrorCode::ContextLengthExceeded,
+                message: "Request failed with 400 Bad Request".to_string(),
+                provider_error_json: None,
+            });
+            roundtrip_test(Error {
+                code: ErrorCode::ContentFilter,
+                message: "The model refused to answer: I can't help with that.".to_string(),
+                provider_error_json: None,
+            });
         }
 
         #[test]
@@ -504,6 +1593,50 @@ mod durable_impl {
                 mime_type: "image/jpeg".to_string(),
                 detail: Some(ImageDetail::Auto),
             })));
+            roundtrip_test(ContentPart::ToolResult((
+                ToolCall {
+                    id: "call_1".to_string(),
+                    name: "get_weather".to_string(),
+                    arguments_json: "{\"city\":\"Paris\"}".to_string(),
+                },
+                ToolResult::Success(ToolSuccess {
+                    id: "call_1".to_string(),
+                    name: "get_weather".to_string(),
+                    result_json: "{\"temperature\":21}".to_string(),
+                    execution_time_ms: Some(12),
+                    content: vec![],
+                }),
+            )));
+            roundtrip_test(ContentPart::ToolResult((
+                ToolCall {
+                    id: "call_2".to_string(),
+                    name: "render_chart".to_string(),
+                    arguments_json: "{\"city\":\"Paris\"}".to_string(),
+                },
+                ToolResult::Success(ToolSuccess {
+                    id: "call_2".to_string(),
+                    name: "render_chart".to_string(),
+                    result_json: "{\"rendered\":true}".to_string(),
+                    execution_time_ms: None,
+                    content: vec![
+                        ToolResultContent::Text("Temperatures of the week".to_string()),
+                        ToolResultContent::Image(ImageReference::Inline(ImageSource {
+                            data: vec![137, 80, 78, 71],
+                            mime_type: "image/png".to_string(),
+                            detail: None,
+                        })),
+                    ],
+                }),
+            )));
+            roundtrip_test(ContentPart::Reasoning(
+                "The user is asking about the weather".to_string(),
+            ));
+            roundtrip_test(ContentPart::Audio(AudioSource {
+                data: vec![82, 73, 70, 70],
+                mime_type: "audio/wav".to_string(),
+                transcript: Some("It is sunny in Paris".to_string()),
+            }));
+            roundtrip_test(ContentPart::Refusal("I can't help with that.".to_string()));
         }
 
         #[test]
@@ -530,15 +1663,21 @@ mod durable_impl {
                     total_tokens: Some(100),
                 }),
                 provider_id: Some("provider_id".to_string()),
+                model: Some("gpt-4o-2024-08-06".to_string()),
                 timestamp: Some("2023-10-01T00:00:00Z".to_string()),
                 provider_metadata_json: Some("{\"key\": \"value\"}".to_string()),
+                tokens_per_second: Some(42.5),
+                stop_sequence: Some("END".to_string()),
             });
             roundtrip_test(ResponseMetadata {
                 finish_reason: None,
                 usage: None,
                 provider_id: None,
+                model: None,
                 timestamp: None,
                 provider_metadata_json: None,
+                tokens_per_second: None,
+                stop_sequence: None,
             });
         }
 
@@ -562,9 +1701,26 @@ mod durable_impl {
                     finish_reason: Some(FinishReason::Stop),
                     usage: None,
                     provider_id: None,
+                    model: None,
                     timestamp: None,
                     provider_metadata_json: None,
+                    tokens_per_second: None,
+                    stop_sequence: None,
                 },
+                annotations: vec![
+                    Annotation {
+                        url: Some("https://example.com/source".to_string()),
+                        title: Some("Source".to_string()),
+                        start_index: 0,
+                        end_index: 5,
+                    },
+                    Annotation {
+                        url: None,
+                        title: Some("Document".to_string()),
+                        start_index: 0,
+                        end_index: 5,
+                    },
+                ],
             });
         }
 
@@ -588,9 +1744,13 @@ mod durable_impl {
                     finish_reason: Some(FinishReason::Stop),
                     usage: None,
                     provider_id: None,
+                    model: None,
                     timestamp: None,
                     provider_metadata_json: None,
+                    tokens_per_second: None,
+                    stop_sequence: None,
                 },
+                annotations: vec![],
             }));
             roundtrip_test(ChatEvent::ToolRequest(vec![ToolCall {
                 id: "x".to_string(),
@@ -604,6 +1764,891 @@ mod durable_impl {
             }));
         }
 
+        #[test]
+        fn config_roundtrip() {
+            roundtrip_test(Config {
+                model: "gpt-3.5-turbo".to_string(),
+                temperature: Some(0.7),
+                max_tokens: Some(100),
+                stop_sequences: None,
+                tools: vec![],
+                tool_choice: None,
+                provider_options: vec![],
+                extra_headers: vec![
+                    (
+                        "HTTP-Referer".to_string(),
+                        "https://golem.cloud".to_string(),
+                    ),
+                    ("X-Title".to_string(), "golem".to_string()),
+                ],
+                service_tier: Some("flex".to_string()),
+                metadata: vec![("tenant".to_string(), "acme".to_string())],
+                reasoning_effort: Some(ReasoningEffort::Medium),
+                store: Some(true),
+                prompt_cache_key: Some("tenant-acme".to_string()),
+                stream_options: Some(StreamOptions {
+                    include_usage: false,
+                    include_obfuscation: Some(false),
+                }),
+                deadline_ms: Some(30000),
+                extra_body: None,
+            });
+        }
+
+        #[test]
+        fn provider_health_roundtrip() {
+            roundtrip_test(ProviderHealth {
+                reachable: true,
+                latency_ms: 120,
+            });
+            roundtrip_test(ProviderHealth {
+                reachable: false,
+                latency_ms: 0,
+            });
+        }
+
+        #[test]
+        fn rerank_config_roundtrip() {
+            roundtrip_test(RerankConfig {
+                model: "rerank-v3.5".to_string(),
+                top_n: Some(3),
+                provider_options: vec![Kv {
+                    key: "max_tokens_per_doc".to_string(),
+                    value: "512".to_string(),
+                }],
+            });
+        }
+
+        #[test]
+        fn rerank_result_roundtrip() {
+            roundtrip_test(RerankResult {
+                results: vec![
+                    RerankScore {
+                        index: 2,
+                        relevance_score: 0.98,
+                    },
+                    RerankScore {
+                        index: 0,
+                        relevance_score: 0.12,
+                    },
+                ],
+                model: Some("rerank-v3.5".to_string()),
+                provider_metadata_json: None,
+            });
+        }
+
+        #[test]
+        fn embedding_config_roundtrip() {
+            roundtrip_test(EmbeddingConfig {
+                model: "jina-embeddings-v4".to_string(),
+                provider_options: vec![Kv {
+                    key: "task".to_string(),
+                    value: "retrieval.query".to_string(),
+                }],
+            });
+        }
+
+        #[test]
+        fn embedding_result_roundtrip() {
+            roundtrip_test(EmbeddingResult {
+                embeddings: vec![
+                    Embedding {
+                        index: 0,
+                        vector: vec![0.25, -0.5, 0.125],
+                    },
+                    Embedding {
+                        index: 1,
+                        vector: vec![-0.75, 0.0, 1.0],
+                    },
+                ],
+                usage: Some(Usage {
+                    input_tokens: Some(12),
+                    output_tokens: None,
+                    total_tokens: Some(12),
+                }),
+                model: Some("jina-embeddings-v4".to_string()),
+                provider_metadata_json: None,
+            });
+        }
+
+        #[test]
+        fn image_gen_config_roundtrip() {
+            roundtrip_test(ImageGenConfig {
+                model: "dall-e-3".to_string(),
+                n: Some(1),
+                size: Some("1024x1024".to_string()),
+                response_format: Some(ImageResponseFormat::Inline),
+                provider_options: vec![Kv {
+                    key: "quality".to_string(),
+                    value: "hd".to_string(),
+                }],
+            });
+        }
+
+        #[test]
+        fn image_gen_result_roundtrip() {
+            roundtrip_test(ImageGenResult {
+                images: vec![
+                    ImageReference::Url(ImageUrl {
+                        url: "https://example.com/generated.png".to_string(),
+                        detail: None,
+                    }),
+                    ImageReference::Inline(ImageSource {
+                        data: vec![137, 80, 78, 71],
+                        mime_type: "image/png".to_string(),
+                        detail: None,
+                    }),
+                ],
+                usage: Some(Usage {
+                    input_tokens: Some(20),
+                    output_tokens: Some(4160),
+                    total_tokens: Some(4180),
+                }),
+                model: Some("dall-e-3".to_string()),
+                provider_metadata_json: Some(
+                    r#"{"revised_prompts":["A red bicycle"]}"#.to_string(),
+                ),
+            });
+        }
+
+        #[test]
+        fn stream_event_roundtrip() {
+            roundtrip_test(StreamEvent::Start(StreamStart {
+                id: "response_id".to_string(),
+                model: Some("model".to_string()),
+                created: Some("1746000000".to_string()),
+            }));
+            roundtrip_test(StreamEvent::Delta(StreamDelta {
+                content: Some(vec![ContentPart::Text("Hello".to_string())]),
+                tool_calls: None,
+                annotations: Some(vec![Annotation {
+                    url: Some("https://example.com/source".to_string()),
+                    title: None,
+                    start_index: 0,
+                    end_index: 5,
+                }]),
+                tool_call_fragments: None,
+                running_usage: None,
+            }));
+            roundtrip_test(StreamEvent::Delta(StreamDelta {
+                content: None,
+                tool_calls: None,
+                annotations: None,
+                tool_call_fragments: Some(vec![ToolCallFragment {
+                    id: "call_1".to_string(),
+                    name: "get_weather".to_string(),
+                    arguments_json: r#"{"city":"#.to_string(),
+                    is_complete: false,
+                }]),
+                running_usage: None,
+            }));
+        }
+
+        #[test]
+        fn stream_output_roundtrip() {
+            roundtrip_test(StreamOutput {
+                request_id: Some("request-1".to_string()),
+                model: Some("gpt-4o".to_string()),
+                started_at_ms: Some(1_750_000_000_000),
+            });
+        }
+
+        #[test]
+        fn stream_outputs_of_earlier_versions_are_decoded() {
+            #[derive(IntoValue)]
+            struct NoOutput;
+
+            #[derive(IntoValue)]
+            struct StringStreamOutput {
+                request_id: String,
+                model: String,
+            }
+
+            assert_eq!(
+                StreamOutput::from_value_and_type(NoOutput.into_value_and_type()).unwrap(),
+                StreamOutput {
+                    request_id: None,
+                    model: None,
+                    started_at_ms: None,
+                }
+            );
+            assert_eq!(
+                StreamOutput::from_value_and_type(
+                    StringStreamOutput {
+                        request_id: "request-1".to_string(),
+                        model: "gpt-4o".to_string(),
+                    }
+                    .into_value_and_type()
+                )
+                .unwrap(),
+                StreamOutput {
+                    request_id: Some("request-1".to_string()),
+                    model: Some("gpt-4o".to_string()),
+                    started_at_ms: None,
+                }
+            );
+        }
+
+        #[test]
+        fn stream_events_keep_the_encoding_of_earlier_versions() {
+            let string =
+                |value: &str| Value::Option(Some(Box::new(Value::String(value.to_string()))));
+
+            // The cases and fields added since the first version come after the earlier ones, so
+            // the events persisted by earlier versions decode as the same cases and fields
+            let finish = StreamEvent::Finish(ResponseMetadata {
+                finish_reason: Some(FinishReason::Stop),
+                usage: None,
+                provider_id: None,
+                model: Some("gpt-4o".to_string()),
+                timestamp: Some("1750000000".to_string()),
+                provider_metadata_json: None,
+                tokens_per_second: None,
+                stop_sequence: None,
+            });
+            match finish.into_value() {
+                Value::Variant {
+                    case_idx: 1,
+                    case_value: Some(metadata),
+                } => match *metadata {
+                    Value::Record(fields) => {
+                        assert_eq!(fields[3], string("1750000000"));
+                        assert_eq!(fields.last(), Some(&string("gpt-4o")));
+                    }
+                    other => panic!("Unexpected response metadata: {other:?}"),
+                },
+                other => panic!("Unexpected finish event: {other:?}"),
+            }
+
+            let start = StreamEvent::Start(StreamStart {
+                id: "response-1".to_string(),
+                model: None,
+                created: None,
+            });
+            assert!(matches!(
+                start.into_value(),
+                Value::Variant { case_idx: 3, .. }
+            ));
+        }
+
+        #[test]
+        fn replayed_stream_uses_the_persisted_request_id_and_model() {
+            let (request_id, config) = replayed_stream_config(
+                StreamOutput {
+                    request_id: Some("request-1".to_string()),
+                    model: Some("gpt-4o".to_string()),
+                    started_at_ms: None,
+                },
+                aliased_model_config(vec![]),
+            );
+
+            assert_eq!(request_id, "request-1");
+            assert_eq!(config.model, "gpt-4o");
+        }
+
+        #[test]
+        fn replayed_stream_persisted_without_request_id_and_model_resolves_them_again() {
+            let (request_id, config) = replayed_stream_config(
+                StreamOutput {
+                    request_id: None,
+                    model: None,
+                    started_at_ms: None,
+                },
+                aliased_model_config(vec![Kv {
+                    key: "request_id".to_string(),
+                    value: "request-2".to_string(),
+                }]),
+            );
+
+            assert_eq!(request_id, "request-2");
+            assert_eq!(config.model, "gpt-4o-mini");
+        }
+
+        /// A config whose model is the `fast` alias of `gpt-4o-mini`
+        fn aliased_model_config(mut provider_options: Vec<Kv>) -> Config {
+            provider_options.push(Kv {
+                key: "model_aliases".to_string(),
+                value: r#"{"fast": "gpt-4o-mini"}"#.to_string(),
+            });
+            Config {
+                model: "fast".to_string(),
+                temperature: None,
+                max_tokens: None,
+                stop_sequences: None,
+                tools: vec![],
+                tool_choice: None,
+                provider_options,
+                extra_headers: vec![],
+                service_tier: None,
+                metadata: vec![],
+                reasoning_effort: None,
+                store: None,
+                prompt_cache_key: None,
+                stream_options: None,
+                deadline_ms: None,
+                extra_body: None,
+            }
+        }
+
+        #[test]
+        fn resumed_stream_gets_the_remaining_deadline() {
+            let config = Config {
+                deadline_ms: Some(30_000),
+                ..aliased_model_config(vec![])
+            };
+            let started_at_ms = 1_750_000_000_000;
+
+            let resumed = with_remaining_deadline(
+                config.clone(),
+                Some(started_at_ms),
+                started_at_ms + 12_000,
+            );
+            assert_eq!(resumed.deadline_ms, Some(18_000));
+
+            let expired = with_remaining_deadline(
+                config.clone(),
+                Some(started_at_ms),
+                started_at_ms + 45_000,
+            );
+            assert_eq!(expired.deadline_ms, Some(0));
+
+            // Streams persisted before the start time was recorded keep their whole budget
+            let unrecorded = with_remaining_deadline(config, None, started_at_ms + 12_000);
+            assert_eq!(unrecorded.deadline_ms, Some(30_000));
+        }
+
+        #[test]
+        fn start_is_not_repeated_by_resumed_segment() {
+            let start = StreamEvent::Start(StreamStart {
+                id: "resumed_response_id".to_string(),
+                model: None,
+                created: None,
+            });
+            let delta = StreamEvent::Delta(StreamDelta {
+                content: Some(vec![ContentPart::Text("rest".to_string())]),
+                tool_calls: None,
+                annotations: None,
+                tool_call_fragments: None,
+                running_usage: None,
+            });
+
+            assert_eq!(
+                without_repeated_start(vec![start.clone(), delta.clone()], false),
+                vec![start.clone(), delta.clone()]
+            );
+            assert_eq!(
+                without_repeated_start(vec![start, delta.clone()], true),
+                vec![delta]
+            );
+        }
+
+        #[test]
+        fn partial_text_concatenates_the_received_deltas() {
+            let partial_result = vec![
+                StreamDelta {
+                    content: Some(vec![ContentPart::Text("Hello".to_string())]),
+                    tool_calls: None,
+                    annotations: None,
+                    tool_call_fragments: None,
+                    running_usage: None,
+                },
+                StreamDelta {
+                    content: None,
+                    tool_calls: Some(vec![ToolCall {
+                        id: "call-1".to_string(),
+                        name: "get_weather".to_string(),
+                        arguments_json: "{}".to_string(),
+                    }]),
+                    annotations: None,
+                    tool_call_fragments: None,
+                    running_usage: None,
+                },
+                StreamDelta {
+                    content: Some(vec![ContentPart::Text(", world".to_string())]),
+                    tool_calls: None,
+                    annotations: None,
+                    tool_call_fragments: None,
+                    running_usage: None,
+                },
+            ];
+
+            assert_eq!(partial_text(&partial_result), "Hello, world");
+            assert_eq!(partial_text(&[]), "");
+        }
+
+        #[test]
+        fn restart_discards_the_partial_result() {
+            let partial_result = vec![StreamDelta {
+                content: Some(vec![ContentPart::Text("{\"city\": \"Bud".to_string())]),
+                tool_calls: None,
+                annotations: None,
+                tool_call_fragments: None,
+                running_usage: None,
+            }];
+
+            assert_eq!(
+                resumed_text(RetryStrategy::Continue, &partial_result),
+                "{\"city\": \"Bud"
+            );
+            assert_eq!(resumed_text(RetryStrategy::Restart, &partial_result), "");
+        }
+
+        #[test]
+        fn restarted_stream_is_not_duplicated() {
+            let delta = |text: &str, output_tokens| {
+                StreamEvent::Delta(StreamDelta {
+                    content: Some(vec![ContentPart::Text(text.to_string())]),
+                    tool_calls: None,
+                    annotations: None,
+                    tool_call_fragments: None,
+                    running_usage: Some(Usage {
+                        input_tokens: None,
+                        output_tokens: Some(output_tokens),
+                        total_tokens: None,
+                    }),
+                })
+            };
+            let finish = |output_tokens| {
+                StreamEvent::Finish(ResponseMetadata {
+                    finish_reason: Some(FinishReason::Stop),
+                    usage: Some(Usage {
+                        input_tokens: Some(10),
+                        output_tokens: Some(output_tokens),
+                        total_tokens: None,
+                    }),
+                    provider_id: None,
+                    model: None,
+                    timestamp: None,
+                    provider_metadata_json: None,
+                    tokens_per_second: None,
+                    stop_sequence: None,
+                })
+            };
+
+            // The interrupted segment, delivered to the consumer before the worker was restarted
+            let interrupted = vec![
+                vec![StreamEvent::Start(StreamStart {
+                    id: "response-1".to_string(),
+                    model: None,
+                    created: None,
+                })],
+                vec![delta("{\"city\": \"Bud", 4)],
+            ];
+            let mut partial_result = Vec::new();
+            let mut partial_usage = None;
+            let mut started = false;
+            let mut finished = false;
+            for events in &interrupted {
+                record_replayed_events(
+                    events,
+                    &mut partial_result,
+                    &mut partial_usage,
+                    &mut started,
+                    &mut finished,
+                );
+            }
+
+            // The new live stream has not received anything yet when it is first read, and then
+            // sends the whole response again
+            let previous_usage = resumed_usage(RetryStrategy::Restart, &partial_usage);
+            let restart = resumed_events(
+                RetryStrategy::Restart,
+                None,
+                started,
+                &previous_usage,
+                "request-1",
+                "hash-1",
+            )
+            .unwrap();
+            assert!(matches!(restart.as_slice(), [StreamEvent::Start(_)]));
+            let resumed = with_previous_usage(
+                without_repeated_start(vec![delta("{\"city\": \"Budapest\"}", 6), finish(6)], true),
+                &previous_usage,
+            );
+
+            let consumed = interrupted
+                .into_iter()
+                .chain([restart, resumed])
+                .collect::<Vec<_>>();
+            let mut collector = StreamCollector::new();
+            let response = consumed
+                .iter()
+                .find_map(|events| collector.add(events.clone()));
+            match response {
+                Some(ChatEvent::Message(response)) => {
+                    assert_eq!(
+                        response.content,
+                        vec![ContentPart::Text("{\"city\": \"Budapest\"}".to_string())]
+                    );
+                    assert_eq!(
+                        response
+                            .metadata
+                            .usage
+                            .and_then(|usage| usage.output_tokens),
+                        Some(6)
+                    );
+                }
+                other => panic!("Unexpected response: {other:?}"),
+            }
+
+            // Replaying the restarted stream keeps only the response sent again
+            let mut partial_result = Vec::new();
+            let mut partial_usage = None;
+            let mut started = false;
+            let mut finished = false;
+            for events in &consumed[..3] {
+                record_replayed_events(
+                    events,
+                    &mut partial_result,
+                    &mut partial_usage,
+                    &mut started,
+                    &mut finished,
+                );
+            }
+            assert!(partial_result.is_empty());
+            assert_eq!(partial_usage, None);
+        }
+
+        #[test]
+        fn usage_is_accumulated_across_segments() {
+            let mut partial_usage = None;
+            add_usage(
+                &mut partial_usage,
+                &Usage {
+                    input_tokens: Some(10),
+                    output_tokens: Some(5),
+                    total_tokens: Some(15),
+                },
+            );
+
+            let events = with_previous_usage(
+                vec![
+                    StreamEvent::Delta(StreamDelta {
+                        content: Some(vec![ContentPart::Text("rest".to_string())]),
+                        tool_calls: None,
+                        annotations: None,
+                        tool_call_fragments: None,
+                        running_usage: None,
+                    }),
+                    StreamEvent::Finish(ResponseMetadata {
+                        finish_reason: Some(FinishReason::Stop),
+                        usage: Some(Usage {
+                            input_tokens: Some(20),
+                            output_tokens: Some(7),
+                            total_tokens: Some(27),
+                        }),
+                        provider_id: None,
+                        model: None,
+                        timestamp: None,
+                        provider_metadata_json: None,
+                        tokens_per_second: None,
+                        stop_sequence: None,
+                    }),
+                ],
+                &partial_usage,
+            );
+
+            match &events[1] {
+                StreamEvent::Finish(metadata) => assert_eq!(
+                    metadata.usage,
+                    Some(Usage {
+                        input_tokens: Some(30),
+                        output_tokens: Some(12),
+                        total_tokens: Some(42),
+                    })
+                ),
+                other => panic!("Unexpected event: {other:?}"),
+            }
+        }
+
+        #[test]
+        fn running_usage_of_an_interrupted_segment_is_carried_forward() {
+            let delta = |text: &str, output_tokens| {
+                StreamEvent::Delta(StreamDelta {
+                    content: Some(vec![ContentPart::Text(text.to_string())]),
+                    tool_calls: None,
+                    annotations: None,
+                    tool_call_fragments: None,
+                    running_usage: Some(Usage {
+                        input_tokens: None,
+                        output_tokens: Some(output_tokens),
+                        total_tokens: None,
+                    }),
+                })
+            };
+
+            let mut partial_result = Vec::new();
+            let mut partial_usage = None;
+            let mut started = false;
+            let mut finished = false;
+            for events in [vec![delta("Hello", 1)], vec![delta(", world", 3)]] {
+                record_replayed_events(
+                    &events,
+                    &mut partial_result,
+                    &mut partial_usage,
+                    &mut started,
+                    &mut finished,
+                );
+            }
+            assert!(!finished);
+            assert_eq!(
+                partial_usage,
+                Some(Usage {
+                    input_tokens: None,
+                    output_tokens: Some(3),
+                    total_tokens: None,
+                })
+            );
+
+            // The new live segment reports its usage on top of the interrupted one
+            let events = with_previous_usage(vec![delta("!", 1)], &partial_usage);
+            match &events[0] {
+                StreamEvent::Delta(delta) => assert_eq!(
+                    delta
+                        .running_usage
+                        .as_ref()
+                        .and_then(|usage| usage.output_tokens),
+                    Some(4)
+                ),
+                other => panic!("Unexpected event: {other:?}"),
+            }
+        }
+
+        #[test]
+        fn usage_of_an_interrupted_segment_is_carried_forward_without_running_usage() {
+            let usage = |input_tokens, output_tokens, total_tokens| Usage {
+                input_tokens,
+                output_tokens,
+                total_tokens,
+            };
+            let delta = |text: &str| {
+                StreamEvent::Delta(StreamDelta {
+                    content: Some(vec![ContentPart::Text(text.to_string())]),
+                    tool_calls: None,
+                    annotations: None,
+                    tool_call_fragments: None,
+                    running_usage: None,
+                })
+            };
+
+            // The input tokens are known from the start of the response, and the output tokens
+            // are estimated from the received text
+            let interrupted = [
+                GetNextOutput {
+                    events: Some(vec![StreamEvent::Start(StreamStart {
+                        id: "response-1".to_string(),
+                        model: None,
+                        created: None,
+                    })]),
+                    usage: Some(usage(Some(25), None, None)),
+                },
+                GetNextOutput {
+                    events: Some(vec![delta("Hello, world")]),
+                    usage: Some(usage(Some(25), Some(3), Some(28))),
+                },
+            ];
+            let mut partial_result = Vec::new();
+            let mut partial_usage = None;
+            let mut started = false;
+            let mut finished = false;
+            for output in &interrupted {
+                record_replayed_output(
+                    output,
+                    &mut partial_result,
+                    &mut partial_usage,
+                    &mut started,
+                    &mut finished,
+                );
+            }
+            assert_eq!(partial_usage, Some(usage(Some(25), Some(3), Some(28))));
+
+            let events = with_previous_usage(
+                vec![
+                    delta("!"),
+                    StreamEvent::Finish(ResponseMetadata {
+                        finish_reason: Some(FinishReason::Stop),
+                        usage: Some(usage(Some(40), Some(1), Some(41))),
+                        provider_id: None,
+                        model: None,
+                        timestamp: None,
+                        provider_metadata_json: None,
+                        tokens_per_second: None,
+                        stop_sequence: None,
+                    }),
+                ],
+                &resumed_usage(RetryStrategy::Continue, &partial_usage),
+            );
+
+            match &events[..] {
+                [StreamEvent::Delta(delta), StreamEvent::Finish(metadata)] => {
+                    assert_eq!(delta.running_usage, None);
+                    assert_eq!(metadata.usage, Some(usage(Some(65), Some(4), Some(69))));
+                }
+                other => panic!("Unexpected events: {other:?}"),
+            }
+        }
+
+        #[test]
+        fn get_next_output_roundtrip() {
+            roundtrip_test(GetNextOutput {
+                events: Some(vec![StreamEvent::Delta(StreamDelta {
+                    content: Some(vec![ContentPart::Text("Hello".to_string())]),
+                    tool_calls: None,
+                    annotations: None,
+                    tool_call_fragments: None,
+                    running_usage: None,
+                })]),
+                usage: Some(Usage {
+                    input_tokens: Some(25),
+                    output_tokens: Some(2),
+                    total_tokens: Some(27),
+                }),
+            });
+        }
+
+        #[test]
+        fn get_next_outputs_of_earlier_versions_are_decoded() {
+            let events = Some(vec![StreamEvent::Delta(StreamDelta {
+                content: Some(vec![ContentPart::Text("Hello".to_string())]),
+                tool_calls: None,
+                annotations: None,
+                tool_call_fragments: None,
+                running_usage: None,
+            })]);
+
+            assert_eq!(
+                GetNextOutput::from_value_and_type(events.clone().into_value_and_type()).unwrap(),
+                GetNextOutput {
+                    events,
+                    usage: None,
+                }
+            );
+            assert_eq!(
+                GetNextOutput::from_value_and_type(None::<Vec<StreamEvent>>.into_value_and_type())
+                    .unwrap(),
+                GetNextOutput {
+                    events: None,
+                    usage: None,
+                }
+            );
+        }
+
+        #[test]
+        fn usage_counts_saturate_instead_of_overflowing() {
+            let mut usage = Some(Usage {
+                input_tokens: Some(u32::MAX - 1),
+                output_tokens: Some(1),
+                total_tokens: None,
+            });
+            add_usage(
+                &mut usage,
+                &Usage {
+                    input_tokens: Some(10),
+                    output_tokens: Some(2),
+                    total_tokens: Some(12),
+                },
+            );
+
+            assert_eq!(
+                usage,
+                Some(Usage {
+                    input_tokens: Some(u32::MAX),
+                    output_tokens: Some(3),
+                    total_tokens: Some(12),
+                })
+            );
+        }
+
+        #[test]
+        fn replay_ignores_events_after_the_first_finish() {
+            let finish = |output_tokens| {
+                StreamEvent::Finish(ResponseMetadata {
+                    finish_reason: Some(FinishReason::Stop),
+                    usage: Some(Usage {
+                        input_tokens: Some(10),
+                        output_tokens: Some(output_tokens),
+                        total_tokens: Some(10 + output_tokens),
+                    }),
+                    provider_id: None,
+                    model: None,
+                    timestamp: None,
+                    provider_metadata_json: None,
+                    tokens_per_second: None,
+                    stop_sequence: None,
+                })
+            };
+            let delta = |text: &str| {
+                StreamEvent::Delta(StreamDelta {
+                    content: Some(vec![ContentPart::Text(text.to_string())]),
+                    tool_calls: None,
+                    annotations: None,
+                    tool_call_fragments: None,
+                    running_usage: None,
+                })
+            };
+
+            let mut partial_result = Vec::new();
+            let mut partial_usage = None;
+            let mut started = false;
+            let mut finished = false;
+            for events in [
+                vec![delta("Hello")],
+                vec![finish(5), finish(5)],
+                vec![delta(" again"), finish(7)],
+            ] {
+                record_replayed_events(
+                    &events,
+                    &mut partial_result,
+                    &mut partial_usage,
+                    &mut started,
+                    &mut finished,
+                );
+            }
+
+            assert!(finished);
+            assert_eq!(partial_text(&partial_result), "Hello");
+            assert_eq!(
+                partial_usage,
+                Some(Usage {
+                    input_tokens: Some(10),
+                    output_tokens: Some(5),
+                    total_tokens: Some(15),
+                })
+            );
+        }
+
+        #[test]
+        fn replay_ends_at_a_persisted_deadline_error() {
+            let mut partial_result = Vec::new();
+            let mut partial_usage = None;
+            let mut started = false;
+            let mut finished = false;
+            record_replayed_events(
+                &[
+                    StreamEvent::Delta(StreamDelta {
+                        content: Some(vec![ContentPart::Text("Hello".to_string())]),
+                        tool_calls: None,
+                        annotations: None,
+                        tool_call_fragments: None,
+                        running_usage: None,
+                    }),
+                    StreamEvent::Error(Error {
+                        code: ErrorCode::Timeout,
+                        message: "The stream did not finish before its deadline".to_string(),
+                        provider_error_json: None,
+                    }),
+                ],
+                &mut partial_result,
+                &mut partial_usage,
+                &mut started,
+                &mut finished,
+            );
+
+            // A finished replay does not resume the stream with the provider
+            assert!(finished);
+            assert_eq!(partial_text(&partial_result), "Hello");
+        }
+
         #[test]
         fn send_input_encoding() {
             let input = SendInput {
@@ -642,7 +2687,24 @@ mod durable_impl {
                     tools: vec![],
                     tool_choice: None,
                     provider_options: vec![],
+                    extra_headers: vec![],
+                    service_tier: None,
+                    metadata: vec![
+                        ("tenant".to_string(), "acme".to_string()),
+                        ("feature".to_string(), "summary".to_string()),
+                    ],
+                    reasoning_effort: Some(ReasoningEffort::High),
+                    store: Some(true),
+                    prompt_cache_key: Some("tenant-acme".to_string()),
+                    stream_options: Some(StreamOptions {
+                        include_usage: true,
+                        include_obfuscation: None,
+                    }),
+                    deadline_ms: None,
+                    extra_body: None,
                 },
+                request_id: "b1b8ef06-42f1-4d32-9c4d-1b8a8b5c2d10".to_string(),
+                request_hash: "9b1e0c2a7f4d3e65".to_string(),
             };
 
             let encoded = input.into_value_and_type();
@@ -656,3 +2718,79 @@ mod durable_impl {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::durability::{
+        prompt_from_env, RetryStrategy, DEFAULT_RETRY_CONTINUATION_PROMPT,
+        DEFAULT_RETRY_SYSTEM_PROMPT, RETRY_CONTINUATION_PROMPT_ENV_VAR_NAME,
+        RETRY_SYSTEM_PROMPT_ENV_VAR_NAME,
+    };
+    use std::collections::HashMap;
+
+    fn env(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect::<HashMap<_, _>>();
+        move |name| vars.get(name).cloned()
+    }
+
+    #[test]
+    fn retry_strategy_is_parsed() {
+        assert_eq!(RetryStrategy::parse(None), RetryStrategy::Continue);
+        assert_eq!(
+            RetryStrategy::parse(Some("continue")),
+            RetryStrategy::Continue
+        );
+        assert_eq!(
+            RetryStrategy::parse(Some(" Restart ")),
+            RetryStrategy::Restart
+        );
+        assert_eq!(
+            RetryStrategy::parse(Some("rewind")),
+            RetryStrategy::Continue
+        );
+    }
+
+    #[test]
+    fn retry_prompts_can_be_overridden() {
+        let overridden = env(&[
+            (
+                RETRY_SYSTEM_PROMPT_ENV_VAR_NAME,
+                "Ta réponse a été interrompue, continue-la là où elle s'est arrêtée.",
+            ),
+            (
+                RETRY_CONTINUATION_PROMPT_ENV_VAR_NAME,
+                "Voici la réponse partielle :",
+            ),
+        ]);
+        assert_eq!(
+            prompt_from_env(
+                RETRY_SYSTEM_PROMPT_ENV_VAR_NAME,
+                DEFAULT_RETRY_SYSTEM_PROMPT,
+                &overridden
+            ),
+            "Ta réponse a été interrompue, continue-la là où elle s'est arrêtée."
+        );
+        assert_eq!(
+            prompt_from_env(
+                RETRY_CONTINUATION_PROMPT_ENV_VAR_NAME,
+                DEFAULT_RETRY_CONTINUATION_PROMPT,
+                &overridden
+            ),
+            "Voici la réponse partielle :"
+        );
+
+        for unset in [env(&[]), env(&[(RETRY_SYSTEM_PROMPT_ENV_VAR_NAME, "  ")])] {
+            assert_eq!(
+                prompt_from_env(
+                    RETRY_SYSTEM_PROMPT_ENV_VAR_NAME,
+                    DEFAULT_RETRY_SYSTEM_PROMPT,
+                    &unset
+                ),
+                DEFAULT_RETRY_SYSTEM_PROMPT
+            );
+        }
+    }
+}
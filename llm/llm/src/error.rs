@@ -1,6 +1,8 @@
 use crate::event_source;
 use crate::golem::llm::llm::{Error, ErrorCode};
 use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
+use std::io;
 
 /// Creates an `Error` value representing that something is unsuported
 pub fn unsupported(what: impl AsRef<str>) -> Error {
@@ -11,33 +13,428 @@ pub fn unsupported(what: impl AsRef<str>) -> Error {
     }
 }
 
+/// Converts an error of sending a request or receiving its response, reporting the client's
+/// connect and read timeouts as `timeout`, and naming connection errors as such in the message
 pub fn from_reqwest_error(details: impl AsRef<str>, err: reqwest::Error) -> Error {
     Error {
-        code: ErrorCode::InternalError,
-        message: format!("{}: {err}", details.as_ref()),
+        code: if err.is_timeout() {
+            ErrorCode::Timeout
+        } else {
+            ErrorCode::InternalError
+        },
+        message: if is_connection_error(&err) {
+            format!(
+                "{}: Connection lost before receiving a response: {err}",
+                details.as_ref()
+            )
+        } else {
+            format!("{}: {err}", details.as_ref())
+        },
         provider_error_json: None,
     }
 }
 
+/// Kinds of the I/O errors of a connection reset, refused or closed before any response was
+/// received
+const CONNECTION_ERROR_KINDS: [io::ErrorKind; 6] = [
+    io::ErrorKind::ConnectionReset,
+    io::ErrorKind::ConnectionRefused,
+    io::ErrorKind::ConnectionAborted,
+    io::ErrorKind::NotConnected,
+    io::ErrorKind::BrokenPipe,
+    io::ErrorKind::UnexpectedEof,
+];
+
+/// Names of the wasi-http error codes of a connection refused or closed before any response was
+/// received. The errors of the executor reach the HTTP client as the debug representation of
+/// their error code, without a type of their own, so they are recognized by these names.
+const WASI_CONNECTION_ERROR_CODES: [&str; 2] = ["ConnectionRefused", "ConnectionTerminated"];
+
+/// Returns whether the error, or any of its sources, is a connection reset, refused or closed
+/// before any response was received, as opposed to an invalid request or a timeout.
+///
+/// The errors are recognized by their type: a `reqwest::Error` failing to connect, an I/O error
+/// of one of the connection error kinds, or an error carrying a wasi-http connection error code.
+pub fn is_connection_error(err: &(dyn std::error::Error + 'static)) -> bool {
+    let mut source = Some(err);
+    while let Some(err) = source {
+        if err
+            .downcast_ref::<reqwest::Error>()
+            .is_some_and(|err| err.is_connect())
+            || is_wasi_connection_error(err)
+        {
+            return true;
+        }
+        source = match err.downcast_ref::<io::Error>() {
+            Some(err) if CONNECTION_ERROR_KINDS.contains(&err.kind()) => return true,
+            // The source of an I/O error is the source of the error it wraps, so the wrapped
+            // error itself is checked next
+            Some(err) => err
+                .get_ref()
+                .map(|inner| inner as &(dyn std::error::Error + 'static)),
+            None => err.source(),
+        };
+    }
+    false
+}
+
+/// Returns whether the error is described by a wasi-http connection error code, matched as a
+/// whole identifier so that codes like `ConnectionReadTimeout` are not mistaken for one
+fn is_wasi_connection_error(err: &dyn std::error::Error) -> bool {
+    format!("{err:?}")
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .any(|identifier| WASI_CONNECTION_ERROR_CODES.contains(&identifier))
+}
+
+/// Converts an error of creating or reading a stream. A rejected streaming request is reported
+/// with the error code of its status and its body as the provider error JSON, like the
+/// non-streaming requests.
 pub fn from_event_source_error(details: impl AsRef<str>, err: event_source::error::Error) -> Error {
-    Error {
+    match err {
+        event_source::error::Error::InvalidStatusCode(status, body) => Error {
+            code: error_code_from_response(status, &body),
+            message: format!("{}: Request failed with {status}: {body}", details.as_ref()),
+            provider_error_json: Some(body),
+        },
+        err => Error {
+            code: ErrorCode::InternalError,
+            message: format!("{}: {err}", details.as_ref()),
+            provider_error_json: None,
+        },
+    }
+}
+
+/// Decodes the body of a successful response.
+///
+/// Some providers and proxies report errors with a `200 OK` status and an `error` field in the
+/// body, like `{"error": {"message": "..."}}` or Ollama's `{"error": "..."}`. Such a body is
+/// converted to an `internal-error` keeping the raw body as its provider error JSON, instead of
+/// being decoded as a response.
+pub fn decode_success_body<T: DeserializeOwned>(raw_body: String) -> Result<T, Error> {
+    let decoding_error = |err: serde_json::Error, raw_body: String| Error {
         code: ErrorCode::InternalError,
-        message: format!("{}: {err}", details.as_ref()),
-        provider_error_json: None,
+        message: format!("Failed to decode response body: {err}"),
+        provider_error_json: Some(raw_body),
+    };
+
+    let value = match serde_json::from_str::<serde_json::Value>(&raw_body) {
+        Ok(value) => value,
+        Err(err) => return Err(decoding_error(err, raw_body)),
+    };
+
+    if let Some(error) = value.get("error").filter(|error| !error.is_null()) {
+        let message = match error {
+            serde_json::Value::String(message) => Some(message.as_str()),
+            error => error.get("message").and_then(|message| message.as_str()),
+        };
+        return Err(Error {
+            code: ErrorCode::InternalError,
+            message: format!(
+                "Request failed with an error in a successful response: {}",
+                message.unwrap_or("unknown error")
+            ),
+            provider_error_json: Some(raw_body),
+        });
     }
+
+    serde_json::from_value(value).map_err(|err| decoding_error(err, raw_body))
 }
 
+/// Non-standard status with which Anthropic reports being temporarily overloaded, which is
+/// retried like a rate limit
+const OVERLOADED_STATUS: u16 = 529;
+
+/// Maps the HTTP status of a failed provider response to an error code
 pub fn error_code_from_status(status: StatusCode) -> ErrorCode {
-    if status == StatusCode::TOO_MANY_REQUESTS {
-        ErrorCode::RateLimitExceeded
-    } else if status == StatusCode::UNAUTHORIZED
-        || status == StatusCode::FORBIDDEN
-        || status == StatusCode::PAYMENT_REQUIRED
-    {
-        ErrorCode::AuthenticationFailed
-    } else if status.is_client_error() {
-        ErrorCode::InvalidRequest
-    } else {
-        ErrorCode::InternalError
+    match status {
+        StatusCode::TOO_MANY_REQUESTS => ErrorCode::RateLimitExceeded,
+        status if status.as_u16() == OVERLOADED_STATUS => ErrorCode::RateLimitExceeded,
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN | StatusCode::PAYMENT_REQUIRED => {
+            ErrorCode::AuthenticationFailed
+        }
+        StatusCode::REQUEST_TIMEOUT | StatusCode::GATEWAY_TIMEOUT => ErrorCode::Timeout,
+        StatusCode::CONFLICT => ErrorCode::Conflict,
+        StatusCode::PAYLOAD_TOO_LARGE | StatusCode::UNPROCESSABLE_ENTITY => {
+            ErrorCode::InvalidRequest
+        }
+        status if status.is_client_error() => ErrorCode::InvalidRequest,
+        _ => ErrorCode::InternalError,
+    }
+}
+
+/// Lowercase phrases with which providers describe a request exceeding the model's context window
+const CONTEXT_LENGTH_PHRASES: [&str; 6] = [
+    "context length",
+    "context_length",
+    "context window",
+    "maximum context",
+    "prompt is too long",
+    "too many tokens",
+];
+
+/// Maps a failed provider response to an error code like `error_code_from_status`, additionally
+/// reporting an invalid request whose error body describes an exceeded context window as
+/// `context-length-exceeded`
+pub fn error_code_from_response(status: StatusCode, error_body: &str) -> ErrorCode {
+    match error_code_from_status(status) {
+        ErrorCode::InvalidRequest if is_context_length_exceeded(error_body) => {
+            ErrorCode::ContextLengthExceeded
+        }
+        code => code,
+    }
+}
+
+/// Returns whether the error message describes a request exceeding the model's context window
+pub fn is_context_length_exceeded(message: &str) -> bool {
+    let message = message.to_lowercase();
+    CONTEXT_LENGTH_PHRASES
+        .iter()
+        .any(|phrase| message.contains(phrase))
+}
+
+/// Creates a `context-length-exceeded` error with the provider's message, naming the model's
+/// context window if the provider reported it
+pub fn context_length_exceeded(
+    message: &str,
+    limit: Option<u32>,
+    provider_error_json: Option<String>,
+) -> Error {
+    Error {
+        code: ErrorCode::ContextLengthExceeded,
+        message: match limit {
+            Some(limit) => {
+                format!(
+                    "The request exceeds the model's context window of {limit} tokens: {message}"
+                )
+            }
+            None => format!("The request exceeds the model's context window: {message}"),
+        },
+        provider_error_json,
+    }
+}
+
+/// Returns the first number following the phrase in the message, like the `128000` of
+/// `maximum context length is 128000 tokens` after `maximum context length`
+pub fn number_after(message: &str, phrase: &str) -> Option<u32> {
+    let rest = &message[message.find(phrase)? + phrase.len()..];
+    let digits = rest
+        .trim_start_matches(|c: char| !c.is_ascii_digit())
+        .split(|c: char| !c.is_ascii_digit())
+        .next()?;
+    digits.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::{
+        context_length_exceeded, decode_success_body, error_code_from_response,
+        error_code_from_status, from_event_source_error, is_connection_error, number_after,
+    };
+    use crate::event_source;
+    use crate::golem::llm::llm::ErrorCode;
+    use reqwest::StatusCode;
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Completion {
+        id: String,
+        #[serde(default)]
+        error: Option<String>,
+    }
+
+    #[test]
+    fn statuses_are_mapped_to_error_codes() {
+        let cases = [
+            (StatusCode::BAD_REQUEST, ErrorCode::InvalidRequest),
+            (StatusCode::UNAUTHORIZED, ErrorCode::AuthenticationFailed),
+            (
+                StatusCode::PAYMENT_REQUIRED,
+                ErrorCode::AuthenticationFailed,
+            ),
+            (StatusCode::FORBIDDEN, ErrorCode::AuthenticationFailed),
+            (StatusCode::NOT_FOUND, ErrorCode::InvalidRequest),
+            (StatusCode::REQUEST_TIMEOUT, ErrorCode::Timeout),
+            (StatusCode::CONFLICT, ErrorCode::Conflict),
+            (StatusCode::PAYLOAD_TOO_LARGE, ErrorCode::InvalidRequest),
+            (StatusCode::UNPROCESSABLE_ENTITY, ErrorCode::InvalidRequest),
+            (StatusCode::TOO_MANY_REQUESTS, ErrorCode::RateLimitExceeded),
+            (StatusCode::GATEWAY_TIMEOUT, ErrorCode::Timeout),
+            (
+                StatusCode::from_u16(529).unwrap(),
+                ErrorCode::RateLimitExceeded,
+            ),
+        ];
+
+        for (status, code) in cases {
+            assert_eq!(error_code_from_status(status), code, "{status}");
+        }
+    }
+
+    #[test]
+    fn other_statuses_are_internal_errors() {
+        assert_eq!(
+            error_code_from_status(StatusCode::INTERNAL_SERVER_ERROR),
+            ErrorCode::InternalError
+        );
+        assert_eq!(
+            error_code_from_status(StatusCode::BAD_GATEWAY),
+            ErrorCode::InternalError
+        );
+        assert_eq!(
+            error_code_from_status(StatusCode::SERVICE_UNAVAILABLE),
+            ErrorCode::InternalError
+        );
+    }
+
+    #[test]
+    fn context_length_errors_are_detected_in_the_error_body() {
+        let cases = [
+            r#"{"error": {"message": "This model's maximum context length is 128000 tokens.", "code": "context_length_exceeded"}}"#,
+            r#"{"type": "error", "error": {"type": "invalid_request_error", "message": "prompt is too long: 208310 tokens > 200000 maximum"}}"#,
+            r#"{"message": "Prompt contains 40000 tokens, too many tokens for model context window"}"#,
+        ];
+
+        for error_body in cases {
+            assert_eq!(
+                error_code_from_response(StatusCode::BAD_REQUEST, error_body),
+                ErrorCode::ContextLengthExceeded,
+                "{error_body}"
+            );
+        }
+    }
+
+    #[test]
+    fn other_errors_are_mapped_by_status() {
+        assert_eq!(
+            error_code_from_response(
+                StatusCode::BAD_REQUEST,
+                r#"{"error": {"message": "Invalid value for 'temperature'"}}"#
+            ),
+            ErrorCode::InvalidRequest
+        );
+        assert_eq!(
+            error_code_from_response(
+                StatusCode::TOO_MANY_REQUESTS,
+                r#"{"error": {"message": "Rate limit reached: too many tokens per minute"}}"#
+            ),
+            ErrorCode::RateLimitExceeded
+        );
+    }
+
+    #[test]
+    fn successful_response_is_decoded() {
+        let body =
+            decode_success_body::<Completion>(r#"{"id": "chatcmpl-1", "error": null}"#.to_string());
+
+        assert_eq!(
+            body.unwrap(),
+            Completion {
+                id: "chatcmpl-1".to_string(),
+                error: None,
+            }
+        );
+    }
+
+    #[test]
+    fn error_in_successful_response_is_an_error() {
+        let raw_body =
+            r#"{"error": {"message": "The upstream provider is overloaded", "code": 502}}"#;
+
+        let error = decode_success_body::<Completion>(raw_body.to_string()).unwrap_err();
+
+        assert_eq!(error.code, ErrorCode::InternalError);
+        assert!(
+            error
+                .message
+                .contains("The upstream provider is overloaded"),
+            "{}",
+            error.message
+        );
+        assert_eq!(error.provider_error_json, Some(raw_body.to_string()));
+    }
+
+    #[test]
+    fn ollama_error_in_successful_response_is_an_error() {
+        let raw_body = r#"{"error": "model 'llama9' not found"}"#;
+
+        let error = decode_success_body::<Completion>(raw_body.to_string()).unwrap_err();
+
+        assert_eq!(error.code, ErrorCode::InternalError);
+        assert!(error.message.contains("model 'llama9' not found"));
+        assert_eq!(error.provider_error_json, Some(raw_body.to_string()));
+    }
+
+    #[test]
+    fn context_length_error_names_the_limit() {
+        let error = context_length_exceeded("prompt is too long", Some(200000), None);
+
+        assert_eq!(error.code, ErrorCode::ContextLengthExceeded);
+        assert_eq!(
+            error.message,
+            "The request exceeds the model's context window of 200000 tokens: prompt is too long"
+        );
+    }
+
+    #[test]
+    fn number_is_parsed_after_the_phrase() {
+        assert_eq!(
+            number_after(
+                "This model's maximum context length is 128000 tokens. However, your messages resulted in 130412 tokens.",
+                "maximum context length"
+            ),
+            Some(128000)
+        );
+        assert_eq!(
+            number_after("prompt is too long: 208310 tokens > 200000 maximum", ">"),
+            Some(200000)
+        );
+        assert_eq!(number_after("prompt is too long", ">"), None);
+        assert_eq!(
+            number_after("context length is unknown", "context length"),
+            None
+        );
+    }
+
+    #[test]
+    fn rejected_stream_reports_the_response_body() {
+        let body = r#"{"error":{"message":"Incorrect API key provided: sk-xxxx","type":"invalid_request_error"}}"#;
+
+        let error = from_event_source_error(
+            "Failed to create SSE stream",
+            event_source::error::Error::InvalidStatusCode(
+                StatusCode::UNAUTHORIZED,
+                body.to_string(),
+            ),
+        );
+
+        assert_eq!(error.code, ErrorCode::AuthenticationFailed);
+        assert!(error.message.contains("401 Unauthorized"));
+        assert!(error.message.contains("Incorrect API key provided"));
+        assert_eq!(error.provider_error_json, Some(body.to_string()));
+    }
+
+    #[test]
+    fn connection_errors_are_distinguished() {
+        let reset = std::io::Error::new(
+            std::io::ErrorKind::ConnectionReset,
+            "Connection reset by peer",
+        );
+        let terminated = std::io::Error::other("ErrorCode::ConnectionTerminated");
+        let wrapped = std::io::Error::other(reset);
+        let broken_pipe = std::io::Error::from(std::io::ErrorKind::BrokenPipe);
+        let invalid_url = std::io::Error::other("builder error: relative URL without a base");
+        let timeout = std::io::Error::other("ConnectionReadTimeout");
+        // Only the kind of an I/O error tells whether it is a connection error, not its message
+        let described = std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "invalid chunk after connection reset by the proxy",
+        );
+
+        assert!(is_connection_error(&terminated));
+        assert!(is_connection_error(&wrapped));
+        assert!(is_connection_error(&broken_pipe));
+        assert!(!is_connection_error(&invalid_url));
+        assert!(!is_connection_error(&timeout));
+        assert!(!is_connection_error(&described));
     }
 }
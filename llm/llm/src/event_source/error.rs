@@ -41,15 +41,19 @@ pub enum Error {
     /// The `Content-Type` returned by the server is invalid
     #[error("Invalid header value: {0:?}")]
     InvalidContentType(HeaderValue, Response),
-    /// The status code returned by the server is invalid
-    #[error("Invalid status code: {0}")]
-    InvalidStatusCode(StatusCode, Response),
+    /// The status code returned by the server is invalid, with the body of the response, which
+    /// explains the failure with most providers
+    #[error("Invalid status code: {0}: {1}")]
+    InvalidStatusCode(StatusCode, String),
     /// The `Last-Event-ID` cannot be formed into a Header to be submitted to the server
     #[error("Invalid `Last-Event-ID`: {0}")]
     InvalidLastEventId(String),
     /// The stream ended
     #[error("Stream ended")]
     StreamEnded,
+    /// A line of the stream exceeded the maximum line length without being terminated
+    #[error("Stream line exceeds the maximum length of {0} bytes")]
+    LineTooLong(usize),
 }
 
 impl From<StreamError<ReqwestError>> for Error {
@@ -58,6 +62,7 @@ impl From<StreamError<ReqwestError>> for Error {
             StreamError::Utf8(err) => Self::Utf8(err),
             StreamError::Parser(err) => Self::Parser(err),
             StreamError::Transport(err) => Self::Transport(err),
+            StreamError::LineTooLong(max_length) => Self::LineTooLong(max_length),
         }
     }
 }
@@ -73,6 +78,7 @@ impl From<StreamError<WasiStreamError>> for Error {
                     Self::TransportStream(err.to_debug_string())
                 }
             },
+            StreamError::LineTooLong(max_length) => Self::LineTooLong(max_length),
         }
     }
 }
@@ -7,7 +7,9 @@ use golem_rust::wasm_rpc::Pollable;
 use log::trace;
 use std::task::Poll;
 
-use super::stream::{LlmStream, StreamError as EventStreamError};
+use super::stream::{
+    check_line_length, max_stream_line_bytes, LlmStream, StreamError as EventStreamError,
+};
 
 #[derive(Default, Debug)]
 struct EventBuilder {
@@ -130,6 +132,7 @@ pub struct EventStream {
     builder: EventBuilder,
     state: EventStreamState,
     last_event_id: String,
+    max_line_length: usize,
 }
 
 impl LlmStream for EventStream {
@@ -141,6 +144,7 @@ impl LlmStream for EventStream {
             builder: EventBuilder::default(),
             state: EventStreamState::NotStarted,
             last_event_id: String::new(),
+            max_line_length: max_stream_line_bytes(),
         }
     }
 
@@ -159,15 +163,19 @@ impl LlmStream for EventStream {
         self.stream.subscribe()
     }
 
+    fn raw_transcript(&self) -> Option<&str> {
+        self.stream.raw_transcript()
+    }
+
     fn poll_next(&mut self) -> Poll<Option<Result<MessageEvent, EventStreamError<StreamError>>>> {
         trace!("Polling for next event");
 
-        match parse_event(&mut self.buffer, &mut self.builder) {
+        match parse_event(&mut self.buffer, &mut self.builder, self.max_line_length) {
             Ok(Some(event)) => {
                 self.last_event_id = event.id.clone();
                 return Poll::Ready(Some(Ok(event)));
             }
-            Err(err) => return Poll::Ready(Some(Err(err))),
+            Err(err) => return Poll::Ready(Some(Err(self.fail(err)))),
             _ => {}
         }
 
@@ -194,12 +202,12 @@ impl LlmStream for EventStream {
                     };
                     self.buffer.push_str(slice);
 
-                    match parse_event(&mut self.buffer, &mut self.builder) {
+                    match parse_event(&mut self.buffer, &mut self.builder, self.max_line_length) {
                         Ok(Some(event)) => {
                             self.last_event_id = event.id.clone();
                             return Poll::Ready(Some(Ok(event)));
                         }
-                        Err(err) => return Poll::Ready(Some(Err(err))),
+                        Err(err) => return Poll::Ready(Some(Err(self.fail(err)))),
                         _ => {}
                     }
                 }
@@ -214,9 +222,35 @@ impl LlmStream for EventStream {
     }
 }
 
+impl EventStream {
+    /// Terminates the stream after a line exceeding the maximum line length, as the rest of it
+    /// cannot be told apart from the following lines
+    fn fail(&mut self, err: EventStreamError<StreamError>) -> EventStreamError<StreamError> {
+        if matches!(err, EventStreamError::LineTooLong(_)) {
+            self.state = EventStreamState::Terminated;
+        }
+        err
+    }
+}
+
+/// Parses the events of a complete transcript, the way a live stream receiving it would. An event
+/// not terminated by a blank line is dropped, like at the end of a live stream
+pub(crate) fn parse_transcript(transcript: &str) -> Vec<MessageEvent> {
+    let mut buffer = transcript.trim_start_matches(is_bom).to_string();
+    let mut builder = EventBuilder::default();
+    let mut events = Vec::new();
+    while let Ok(Some(event)) = parse_event::<()>(&mut buffer, &mut builder, usize::MAX) {
+        events.push(event);
+    }
+    events
+}
+
+/// Parses the lines of the buffer until an event is complete. Fails when the line still being
+/// received exceeds `max_line_length`
 fn parse_event<E>(
     buffer: &mut String,
     builder: &mut EventBuilder,
+    max_line_length: usize,
 ) -> Result<Option<MessageEvent>, EventStreamError<E>> {
     if buffer.is_empty() {
         return Ok(None);
@@ -234,8 +268,34 @@ fn parse_event<E>(
                     }
                 }
             }
-            Err(nom::Err::Incomplete(_)) => return Ok(None),
+            Err(nom::Err::Incomplete(_)) => {
+                check_line_length(buffer, max_line_length)?;
+                return Ok(None);
+            }
             Err(nom::Err::Error(err)) | Err(nom::Err::Failure(err)) => return Err(err.into()),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::event_source::event_stream::{parse_event, EventBuilder};
+    use crate::event_source::stream::StreamError;
+
+    #[test]
+    fn line_exceeding_the_maximum_length_without_newline_fails() {
+        let mut buffer = "data: {\"done\":false}\n\ndata: {\"text\":\"".to_string();
+        let mut builder = EventBuilder::default();
+
+        let event = parse_event::<()>(&mut buffer, &mut builder, 32).unwrap();
+        assert_eq!(event.unwrap().data, "{\"done\":false}");
+        assert_eq!(parse_event::<()>(&mut buffer, &mut builder, 32), Ok(None));
+
+        buffer.push_str(&"a".repeat(32));
+        assert_eq!(
+            parse_event::<()>(&mut buffer, &mut builder, 32),
+            Err(StreamError::LineTooLong(32))
+        );
+        assert!(buffer.is_empty());
+    }
+}
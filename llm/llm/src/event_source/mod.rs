@@ -31,18 +31,40 @@ pub enum ReadyState {
     Closed = 2,
 }
 
+/// Framing of the messages of a stream
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum StreamFormat {
+    /// Server-sent events
+    EventStream,
+    /// Newline delimited JSON
+    NdJson,
+}
+
+/// How an [`EventSource`] chooses the [`StreamFormat`] of a response
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum StreamFormatHint {
+    /// Detected from the response's `Content-Type`, using the given format when the header is
+    /// missing
+    Detect(StreamFormat),
+    /// Used regardless of the response's `Content-Type`, for providers whose framing is known
+    Force(StreamFormat),
+}
+
 pub struct EventSource {
-    /// stream is the type which implements Stream trait
-    stream: StreamType,
-    response: Response,
+    /// stream is the type which implements Stream trait, dropped when the source gets closed
+    stream: Option<StreamType>,
+    /// The response owning the body the stream reads from, dropped after the stream
+    response: Option<Response>,
     is_closed: bool,
+    /// The raw transcript of the stream, kept when the stream gets closed
+    closed_transcript: Option<String>,
 }
 
 impl EventSource {
     #[allow(clippy::result_large_err)]
-    pub fn new(response: Response) -> Result<Self, Error> {
-        match check_response(response) {
-            Ok(mut response) => {
+    pub fn new(response: Response, hint: StreamFormatHint) -> Result<Self, Error> {
+        match check_response(response, hint) {
+            Ok((mut response, format)) => {
                 let handle = unsafe {
                     std::mem::transmute::<
                         reqwest::InputStream,
@@ -50,31 +72,30 @@ impl EventSource {
                     >(response.get_raw_input_stream())
                 };
 
-                let stream = if response
-                    .headers()
-                    .get(&reqwest::header::CONTENT_TYPE)
-                    .unwrap()
-                    .to_str()
-                    .unwrap()
-                    .contains("ndjson")
-                {
-                    StreamType::NdJsonStream(NdJsonStream::new(handle))
-                } else {
-                    StreamType::EventStream(EventStream::new(handle))
+                let stream = match format {
+                    StreamFormat::NdJson => StreamType::NdJsonStream(NdJsonStream::new(handle)),
+                    StreamFormat::EventStream => StreamType::EventStream(EventStream::new(handle)),
                 };
                 Ok(Self {
-                    response,
-                    stream,
+                    response: Some(response),
+                    stream: Some(stream),
                     is_closed: false,
+                    closed_transcript: None,
                 })
             }
             Err(err) => Err(err),
         }
     }
 
-    /// Close the EventSource stream and stop trying to reconnect
+    /// Close the EventSource stream and stop trying to reconnect.
+    ///
+    /// Drops the WASI input stream and then the response it belongs to, releasing the underlying
+    /// connection immediately. The raw transcript of the stream is kept.
     pub fn close(&mut self) {
         self.is_closed = true;
+        self.closed_transcript = self.raw_transcript().map(str::to_string);
+        drop(self.stream.take());
+        drop(self.response.take());
     }
 
     /// Get the current ready state
@@ -88,17 +109,39 @@ impl EventSource {
 
     pub fn subscribe(&self) -> Pollable {
         match &self.stream {
-            StreamType::EventStream(stream) => stream.subscribe(),
-            StreamType::NdJsonStream(stream) => stream.subscribe(),
+            Some(StreamType::EventStream(stream)) => stream.subscribe(),
+            Some(StreamType::NdJsonStream(stream)) => stream.subscribe(),
+            None => golem_rust::bindings::wasi::clocks::monotonic_clock::subscribe_duration(0),
+        }
+    }
+
+    /// The raw SSE or NDJSON text received so far, if capturing the raw stream is enabled by the
+    /// `GOLEM_LLM_CAPTURE_RAW_STREAM` environment variable
+    pub fn raw_transcript(&self) -> Option<&str> {
+        match &self.stream {
+            Some(StreamType::EventStream(stream)) => stream.raw_transcript(),
+            Some(StreamType::NdJsonStream(stream)) => stream.raw_transcript(),
+            None => self.closed_transcript.as_deref(),
+        }
+    }
+
+    /// A source without a stream, which ends as soon as it is polled
+    #[cfg(test)]
+    pub(crate) fn ended() -> Self {
+        Self {
+            stream: None,
+            response: None,
+            is_closed: false,
+            closed_transcript: None,
         }
     }
 
     pub fn poll_next(&mut self) -> Poll<Option<Result<Event, Error>>> {
-        if self.is_closed {
+        let Some(stream) = self.stream.as_mut() else {
             return Poll::Ready(None);
-        }
+        };
 
-        match &mut self.stream {
+        match stream {
             StreamType::EventStream(stream) => match stream.poll_next() {
                 Poll::Ready(Some(Ok(event))) => Poll::Ready(Some(Ok(Event::Message(event)))),
                 Poll::Ready(Some(Err(err))) => Poll::Ready(Some(Err(err.into()))),
@@ -115,38 +158,69 @@ impl EventSource {
     }
 }
 
+impl Drop for EventSource {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
 #[allow(clippy::result_large_err)]
-fn check_response(response: Response) -> Result<Response, Error> {
+fn check_response(
+    response: Response,
+    hint: StreamFormatHint,
+) -> Result<(Response, StreamFormat), Error> {
     match response.status() {
         StatusCode::OK => {}
         status => {
-            return Err(Error::InvalidStatusCode(status, response));
+            // The body is read before the response is discarded, as it is the only place where
+            // most providers explain why the request was rejected
+            let body = response.text().unwrap_or_default();
+            return Err(Error::InvalidStatusCode(status, body));
         }
     }
-    let content_type =
-        if let Some(content_type) = response.headers().get(&reqwest::header::CONTENT_TYPE) {
-            content_type
-        } else {
-            return Err(Error::InvalidContentType(
-                HeaderValue::from_static(""),
-                response,
-            ));
-        };
-    if content_type
-        .to_str()
-        .map_err(|_| ())
-        .and_then(|s| s.parse::<mime::Mime>().map_err(|_| ()))
-        .map(|mime_type| {
-            matches!(
+    let content_type = response.headers().get(&reqwest::header::CONTENT_TYPE);
+    match stream_format(content_type, hint) {
+        Some(format) => Ok((response, format)),
+        None => {
+            let content_type = content_type
+                .cloned()
+                .unwrap_or(HeaderValue::from_static(""));
+            Err(Error::InvalidContentType(content_type, response))
+        }
+    }
+}
+
+/// Chooses the format of a stream by the provider's hint and the response's `Content-Type`, or
+/// returns `None` if the content type is neither server-sent events nor NDJSON
+fn stream_format(
+    content_type: Option<&HeaderValue>,
+    hint: StreamFormatHint,
+) -> Option<StreamFormat> {
+    match (hint, content_type) {
+        (StreamFormatHint::Force(format), _) => Some(format),
+        (StreamFormatHint::Detect(default), None) => Some(default),
+        (StreamFormatHint::Detect(_), Some(content_type)) => {
+            let mime_type = content_type.to_str().ok()?.parse::<mime::Mime>().ok()?;
+            if matches!(
                 (mime_type.type_(), mime_type.subtype()),
                 (mime::TEXT, mime::EVENT_STREAM)
-            ) || mime_type.subtype().as_str().contains("ndjson")
-        })
-        .unwrap_or(false)
-    {
-        Ok(response)
-    } else {
-        Err(Error::InvalidContentType(content_type.clone(), response))
+            ) {
+                Some(StreamFormat::EventStream)
+            } else if mime_type.subtype().as_str().contains("ndjson") {
+                Some(StreamFormat::NdJson)
+            } else {
+                None
+            }
+        }
+    }
+}
+
+/// Splits the complete raw text of a stream into its messages, framed the same way as by a live
+/// [`EventSource`], so recorded transcripts can be decoded without a connection
+pub fn parse_transcript(transcript: &str, format: StreamFormat) -> Vec<MessageEvent> {
+    match format {
+        StreamFormat::EventStream => event_stream::parse_transcript(transcript),
+        StreamFormat::NdJson => ndjson_stream::parse_transcript(transcript),
     }
 }
 
@@ -164,3 +238,108 @@ impl From<MessageEvent> for Event {
         Event::Message(event)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::event_source::{
+        parse_transcript, stream_format, EventSource, ReadyState, StreamFormat, StreamFormatHint,
+    };
+    use reqwest::header::HeaderValue;
+    use std::task::Poll;
+
+    #[test]
+    fn closed_source_releases_its_stream() {
+        let mut source = EventSource::ended();
+        assert_eq!(source.ready_state(), ReadyState::Open);
+
+        source.close();
+
+        assert_eq!(source.ready_state(), ReadyState::Closed);
+        assert!(source.stream.is_none());
+        assert!(source.response.is_none());
+        assert!(matches!(source.poll_next(), Poll::Ready(None)));
+    }
+
+    #[test]
+    fn format_is_detected_from_the_content_type() {
+        let hint = StreamFormatHint::Detect(StreamFormat::EventStream);
+
+        assert_eq!(
+            stream_format(
+                Some(&HeaderValue::from_static(
+                    "text/event-stream; charset=utf-8"
+                )),
+                hint
+            ),
+            Some(StreamFormat::EventStream)
+        );
+        assert_eq!(
+            stream_format(
+                Some(&HeaderValue::from_static("application/x-ndjson")),
+                hint
+            ),
+            Some(StreamFormat::NdJson)
+        );
+        assert_eq!(
+            stream_format(Some(&HeaderValue::from_static("application/json")), hint),
+            None
+        );
+    }
+
+    #[test]
+    fn missing_content_type_falls_back_to_the_default_format() {
+        assert_eq!(
+            stream_format(None, StreamFormatHint::Detect(StreamFormat::NdJson)),
+            Some(StreamFormat::NdJson)
+        );
+        assert_eq!(
+            stream_format(None, StreamFormatHint::Detect(StreamFormat::EventStream)),
+            Some(StreamFormat::EventStream)
+        );
+    }
+
+    #[test]
+    fn forced_format_overrides_a_mismatched_content_type() {
+        let hint = StreamFormatHint::Force(StreamFormat::NdJson);
+
+        assert_eq!(
+            stream_format(Some(&HeaderValue::from_static("text/event-stream")), hint),
+            Some(StreamFormat::NdJson)
+        );
+        assert_eq!(
+            stream_format(Some(&HeaderValue::from_static("application/json")), hint),
+            Some(StreamFormat::NdJson)
+        );
+        assert_eq!(stream_format(None, hint), Some(StreamFormat::NdJson));
+    }
+
+    #[test]
+    fn transcripts_are_framed_like_live_streams() {
+        let events = parse_transcript(
+            "\u{feff}event: delta\ndata: Hel\ndata: lo\n\n: keep-alive\n\ndata: [DONE]\n\ndata: cut",
+            StreamFormat::EventStream,
+        );
+        assert_eq!(
+            events
+                .iter()
+                .map(|event| (event.event.as_str(), event.data.as_str()))
+                .collect::<Vec<_>>(),
+            vec![("delta", "Hel\nlo"), ("message", "[DONE]")]
+        );
+
+        let events = parse_transcript(
+            "\u{feff}\n{\"done\":false}\n\n{\"done\":true}",
+            StreamFormat::NdJson,
+        );
+        assert_eq!(
+            events
+                .iter()
+                .map(|event| (event.event.as_str(), event.data.as_str()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("message", "{\"done\":false}"),
+                ("message", "{\"done\":true}")
+            ]
+        );
+    }
+}
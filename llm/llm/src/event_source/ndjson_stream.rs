@@ -1,4 +1,6 @@
-use super::stream::{LlmStream, StreamError as NdJsonStreamError};
+use super::stream::{
+    check_line_length, max_stream_line_bytes, LlmStream, StreamError as NdJsonStreamError,
+};
 use crate::event_source::utf8_stream::Utf8Stream;
 use crate::event_source::MessageEvent;
 use golem_rust::bindings::wasi::io::streams::{InputStream, StreamError};
@@ -25,6 +27,7 @@ pub struct NdJsonStream {
     buffer: String,
     state: NdJsonStreamState,
     last_event_id: String,
+    max_line_length: usize,
 }
 
 impl LlmStream for NdJsonStream {
@@ -35,6 +38,7 @@ impl LlmStream for NdJsonStream {
             buffer: String::new(),
             state: NdJsonStreamState::NotStarted,
             last_event_id: String::new(),
+            max_line_length: max_stream_line_bytes(),
         }
     }
 
@@ -52,6 +56,10 @@ impl LlmStream for NdJsonStream {
         self.stream.subscribe()
     }
 
+    fn raw_transcript(&self) -> Option<&str> {
+        self.stream.raw_transcript()
+    }
+
     fn poll_next(&mut self) -> Poll<Option<Result<MessageEvent, NdJsonStreamError<StreamError>>>> {
         trace!("Polling for next NDJSON event");
 
@@ -67,16 +75,10 @@ impl LlmStream for NdJsonStream {
         loop {
             match self.stream.poll_next() {
                 Poll::Ready(Some(Ok(string))) => {
-                    if string.is_empty() {
+                    if !push_chunk(&mut self.buffer, &mut self.state, &string) {
                         continue;
                     }
 
-                    if !self.state.is_terminated() {
-                        self.state = NdJsonStreamState::Started;
-                    }
-
-                    self.buffer.push_str(&string);
-
                     // Try to parse complete lines from the updated buffer
                     if let Some(event) = try_parse_line(self)? {
                         return Poll::Ready(Some(Ok(event)));
@@ -106,38 +108,223 @@ impl LlmStream for NdJsonStream {
     }
 }
 
+/// Byte order mark which some servers send before the first line
+const BOM: char = '\u{feff}';
+
+/// Appends a received chunk to the buffer, returning whether anything was added.
+///
+/// Until the first line starts, a byte order mark and whitespace are dropped, as some servers
+/// prefix the stream with them, which would make the first line fail to parse. Later lines are
+/// buffered untouched.
+fn push_chunk(buffer: &mut String, state: &mut NdJsonStreamState, chunk: &str) -> bool {
+    let chunk = match state {
+        NdJsonStreamState::NotStarted => {
+            chunk.trim_start_matches(|c: char| c == BOM || c.is_whitespace())
+        }
+        _ => chunk,
+    };
+    if chunk.is_empty() {
+        return false;
+    }
+
+    if !state.is_terminated() {
+        *state = NdJsonStreamState::Started;
+    }
+
+    buffer.push_str(chunk);
+    true
+}
+
+/// Parses the lines of a complete transcript, the way a live stream receiving it would, including a
+/// last line without a trailing newline
+pub(crate) fn parse_transcript(transcript: &str) -> Vec<MessageEvent> {
+    let mut buffer = String::new();
+    let mut state = NdJsonStreamState::NotStarted;
+    push_chunk(&mut buffer, &mut state, transcript);
+
+    let mut lines = Vec::new();
+    while let Some(line) = take_line(&mut buffer) {
+        lines.push(line);
+    }
+    if !buffer.trim().is_empty() {
+        lines.push(buffer.trim().to_string());
+    }
+
+    lines
+        .into_iter()
+        .map(|data| MessageEvent {
+            event: "message".to_string(),
+            data,
+            id: String::new(),
+            retry: None,
+        })
+        .collect()
+}
+
+/// Upper bound of the size of an unterminated line which is checked for being a complete JSON
+/// value, so a large object still being received is not parsed again on every chunk
+const MAX_UNTERMINATED_LINE_LENGTH: usize = 64 * 1024;
+
 /// Try to parse a complete line from the buffer
 /// Returns Ok(Some(event)) if a complete line was found and parsed
 /// Returns Ok(None) if no complete line is available
-/// Returns Err if there was a parsing error
+/// Returns Err if the line being received is too long, which terminates the stream
 fn try_parse_line(
     stream: &mut NdJsonStream,
 ) -> Result<Option<MessageEvent>, NdJsonStreamError<StreamError>> {
-    // Look for a complete line (ending with \n)
-    if let Some(newline_pos) = stream.buffer.find('\n') {
+    let line = match next_line(&mut stream.buffer, stream.max_line_length) {
+        Ok(line) => line,
+        Err(err) => {
+            stream.state = NdJsonStreamState::Terminated;
+            return Err(err);
+        }
+    };
+    match line {
+        Some(line) => {
+            trace!("Parsed NDJSON line: {}", line);
+
+            // Create a MessageEvent with the JSON line as data
+            Ok(Some(MessageEvent {
+                event: "message".to_string(),
+                data: line,
+                id: stream.last_event_id.clone(),
+                retry: None,
+            }))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Removes the next non-empty line from the buffer like [`take_line`], failing when the line still
+/// being received exceeds the maximum line length
+fn next_line<E>(
+    buffer: &mut String,
+    max_line_length: usize,
+) -> Result<Option<String>, NdJsonStreamError<E>> {
+    match take_line(buffer) {
+        Some(line) => Ok(Some(line)),
+        None => check_line_length(buffer, max_line_length).map(|()| None),
+    }
+}
+
+/// Removes the next non-empty line from the buffer, if a complete one was received.
+///
+/// A line is complete when it ends with a newline, or when it is a JSON object or array which
+/// parses on its own. The latter handles providers sending the last line without a newline while
+/// keeping the connection open, which would otherwise leave it stuck in the buffer. A partially
+/// received object or array never parses, so it stays buffered until the rest arrives.
+fn take_line(buffer: &mut String) -> Option<String> {
+    // Look for a complete line (ending with \n), skipping empty lines
+    while let Some(newline_pos) = buffer.find('\n') {
         // Extract the line (without the newline)
-        let line = stream.buffer[..newline_pos].trim().to_string();
+        let line = buffer[..newline_pos].trim().to_string();
 
         // Remove the processed line from the buffer (including the newline)
-        stream.buffer.drain(..=newline_pos);
+        buffer.drain(..=newline_pos);
 
-        // Skip empty lines
-        if line.is_empty() {
-            return Ok(None);
+        if !line.is_empty() {
+            return Some(line);
         }
+    }
+
+    let unterminated = buffer.trim();
+    let looks_balanced = (unterminated.starts_with('{') && unterminated.ends_with('}'))
+        || (unterminated.starts_with('[') && unterminated.ends_with(']'));
+    if looks_balanced
+        && unterminated.len() <= MAX_UNTERMINATED_LINE_LENGTH
+        && serde_json::from_str::<serde_json::de::IgnoredAny>(unterminated).is_ok()
+    {
+        trace!("Parsed NDJSON line without a trailing newline");
+        let line = unterminated.to_string();
+        buffer.clear();
+        Some(line)
+    } else {
+        None
+    }
+}
 
-        trace!("Parsed NDJSON line: {}", line);
+#[cfg(test)]
+mod tests {
+    use crate::event_source::ndjson_stream::{next_line, push_chunk, take_line, NdJsonStreamState};
+    use crate::event_source::stream::StreamError;
 
-        // Create a MessageEvent with the JSON line as data
-        let event = MessageEvent {
-            event: "message".to_string(),
-            data: line,
-            id: stream.last_event_id.clone(),
-            retry: None,
-        };
+    #[test]
+    fn lines_are_split_on_newlines() {
+        let mut buffer = "{\"a\":1}\n\n{\"b\":2}\n{\"c\":".to_string();
 
-        return Ok(Some(event));
+        assert_eq!(take_line(&mut buffer), Some("{\"a\":1}".to_string()));
+        assert_eq!(take_line(&mut buffer), Some("{\"b\":2}".to_string()));
+        assert_eq!(take_line(&mut buffer), None);
+        assert_eq!(buffer, "{\"c\":");
     }
 
-    Ok(None)
+    #[test]
+    fn final_line_without_newline_is_taken_once_complete() {
+        // The stream stays open after the last line, so it is never terminated by a newline
+        let mut buffer = String::new();
+        let mut lines = Vec::new();
+        for chunk in [
+            "{\"message\":\"Hel",
+            "lo}\"",
+            ",\"done\":false}\n{\"done\":",
+            "true,\"nested\":{\"x\":[1]}",
+            "}",
+        ] {
+            buffer.push_str(chunk);
+            while let Some(line) = take_line(&mut buffer) {
+                lines.push(line);
+            }
+        }
+
+        assert_eq!(
+            lines,
+            vec![
+                "{\"message\":\"Hello}\",\"done\":false}".to_string(),
+                "{\"done\":true,\"nested\":{\"x\":[1]}}".to_string(),
+            ]
+        );
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn byte_order_mark_before_the_first_line_is_dropped() {
+        let mut buffer = String::new();
+        let mut state = NdJsonStreamState::NotStarted;
+        for chunk in [
+            "\u{feff}",
+            "  \n{\"done\":",
+            "false}\n",
+            "{\"text\":\"\u{feff}\"}\n",
+        ] {
+            push_chunk(&mut buffer, &mut state, chunk);
+        }
+
+        let first = take_line(&mut buffer).unwrap();
+        let first_event: serde_json::Value = serde_json::from_str(&first).unwrap();
+        assert_eq!(first_event["done"], false);
+        assert_eq!(
+            take_line(&mut buffer),
+            Some("{\"text\":\"\u{feff}\"}".to_string())
+        );
+    }
+
+    #[test]
+    fn line_exceeding_the_maximum_length_without_newline_fails() {
+        let mut buffer = String::new();
+        let mut state = NdJsonStreamState::NotStarted;
+
+        push_chunk(&mut buffer, &mut state, "{\"done\":false}\n{\"message\":\"");
+        assert_eq!(
+            next_line::<()>(&mut buffer, 32),
+            Ok(Some("{\"done\":false}".to_string()))
+        );
+        assert_eq!(next_line::<()>(&mut buffer, 32), Ok(None));
+
+        push_chunk(&mut buffer, &mut state, &"a".repeat(32));
+        assert_eq!(
+            next_line::<()>(&mut buffer, 32),
+            Err(StreamError::LineTooLong(32))
+        );
+        assert!(buffer.is_empty());
+    }
 }
@@ -11,6 +11,36 @@ use golem_rust::{
 };
 use nom::error::Error as NomError;
 
+/// Environment variable overriding the maximum length in bytes of a line being received, see
+/// [`max_stream_line_bytes`]
+const MAX_STREAM_LINE_BYTES_ENV_VAR_NAME: &str = "GOLEM_LLM_MAX_STREAM_LINE_BYTES";
+
+/// Default maximum length in bytes of a line being received
+pub const DEFAULT_MAX_STREAM_LINE_BYTES: usize = 4 * 1024 * 1024;
+
+/// Returns the maximum length of a line being received, set by the
+/// `GOLEM_LLM_MAX_STREAM_LINE_BYTES` environment variable, or the default if it is not set to a
+/// positive number. A provider which never sends a newline would otherwise make the streams buffer
+/// its response without bound.
+pub fn max_stream_line_bytes() -> usize {
+    std::env::var(MAX_STREAM_LINE_BYTES_ENV_VAR_NAME)
+        .ok()
+        .and_then(|value| value.trim().parse::<usize>().ok())
+        .filter(|max| *max > 0)
+        .unwrap_or(DEFAULT_MAX_STREAM_LINE_BYTES)
+}
+
+/// Fails once the buffered text of a line not terminated yet exceeds the maximum line length,
+/// dropping it
+pub fn check_line_length<E>(buffer: &mut String, max_length: usize) -> Result<(), StreamError<E>> {
+    if buffer.len() > max_length {
+        buffer.clear();
+        Err(StreamError::LineTooLong(max_length))
+    } else {
+        Ok(())
+    }
+}
+
 pub enum StreamType {
     EventStream(EventStream),
     NdJsonStream(NdJsonStream),
@@ -22,6 +52,8 @@ pub trait LlmStream {
     fn last_event_id(&self) -> &str;
     fn subscribe(&self) -> Pollable;
     fn poll_next(&mut self) -> Poll<Option<Result<MessageEvent, StreamError<WasiStreamError>>>>;
+    /// The raw text received so far, if capturing the raw stream is enabled
+    fn raw_transcript(&self) -> Option<&str>;
 }
 
 /// Error thrown while parsing an event line
@@ -33,6 +65,8 @@ pub enum StreamError<E> {
     Parser(NomError<String>),
     /// Underlying source stream error
     Transport(E),
+    /// A line exceeded the maximum line length without being terminated
+    LineTooLong(usize),
 }
 
 impl<E> From<Utf8StreamError<E>> for StreamError<E> {
@@ -59,6 +93,10 @@ where
             Self::Utf8(err) => f.write_fmt(format_args!("UTF8 error: {}", err)),
             Self::Parser(err) => f.write_fmt(format_args!("Parse error: {}", err)),
             Self::Transport(err) => f.write_fmt(format_args!("Transport error: {}", err)),
+            Self::LineTooLong(max_length) => f.write_fmt(format_args!(
+                "Line exceeds the maximum length of {} bytes",
+                max_length
+            )),
         }
     }
 }
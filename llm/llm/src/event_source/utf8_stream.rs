@@ -1,3 +1,4 @@
+use crate::raw_transcript::{is_capturing_raw_stream, RawTranscript};
 use golem_rust::bindings::wasi::io::streams::{InputStream, StreamError};
 use golem_rust::wasm_rpc::Pollable;
 use log::trace;
@@ -9,6 +10,8 @@ pub struct Utf8Stream {
     stream: InputStream,
     buffer: Vec<u8>,
     terminated: bool,
+    /// The text received so far, when capturing the raw stream is enabled
+    transcript: Option<RawTranscript>,
 }
 
 impl Utf8Stream {
@@ -21,6 +24,7 @@ impl Utf8Stream {
             subscription,
             buffer: Vec::new(),
             terminated: false,
+            transcript: is_capturing_raw_stream().then(RawTranscript::default),
         }
     }
 
@@ -28,7 +32,20 @@ impl Utf8Stream {
         self.stream.subscribe()
     }
 
+    /// The text received so far, if capturing the raw stream is enabled
+    pub fn raw_transcript(&self) -> Option<&str> {
+        self.transcript.as_ref().map(|transcript| transcript.text())
+    }
+
     pub fn poll_next(&mut self) -> Poll<Option<Result<String, Utf8StreamError<StreamError>>>> {
+        let next = self.read_next();
+        if let (Some(transcript), Poll::Ready(Some(Ok(chunk)))) = (&mut self.transcript, &next) {
+            transcript.append(chunk);
+        }
+        next
+    }
+
+    fn read_next(&mut self) -> Poll<Option<Result<String, Utf8StreamError<StreamError>>>> {
         if !self.terminated && self.subscription.ready() {
             match self.stream.read(Self::CHUNK_SIZE) {
                 Ok(bytes) => {
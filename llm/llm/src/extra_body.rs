@@ -0,0 +1,202 @@
+use crate::golem::llm::llm::{Config, Error, ErrorCode};
+use log::trace;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+/// Provider option which has to be set to `true` to let the `extra_body` of the config replace
+/// the request fields set by the provider
+pub const ALLOW_BODY_OVERRIDE_OPTION: &str = "allow_body_override";
+
+/// Fields added to the top level of the request bodies, from the `extra_body` of the config
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ExtraBody {
+    fields: Map<String, Value>,
+    allow_override: bool,
+}
+
+impl ExtraBody {
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// Serializes the request and adds the extra fields to its top level object, as the final
+    /// step before sending it.
+    ///
+    /// Fails if a field is already set by the request, unless the `allow_body_override` provider
+    /// option was set to `true`, in which case the extra field replaces it.
+    pub fn merge<T: Serialize>(&self, request: &T) -> Result<Value, Error> {
+        let body = serde_json::to_value(request).map_err(|err| Error {
+            code: ErrorCode::InternalError,
+            message: format!("Failed to serialize request body: {err}"),
+            provider_error_json: None,
+        })?;
+        if self.is_empty() {
+            return Ok(body);
+        }
+
+        let Value::Object(mut body) = body else {
+            return Err(invalid_extra_body(
+                "The request body is not a JSON object".to_string(),
+            ));
+        };
+        for (key, value) in &self.fields {
+            if !self.allow_override && body.contains_key(key) {
+                return Err(invalid_extra_body(format!(
+                    "Overriding the {key} field of the request requires the {ALLOW_BODY_OVERRIDE_OPTION} provider option"
+                )));
+            }
+            body.insert(key.clone(), value.clone());
+        }
+        trace!(
+            "Added extra body fields: {}",
+            self.fields.keys().cloned().collect::<Vec<_>>().join(", ")
+        );
+
+        Ok(Value::Object(body))
+    }
+}
+
+/// Parses the `extra_body` of the config, to be merged into the request bodies by
+/// [`ExtraBody::merge`]. Fails if it is not a JSON object.
+pub fn extra_body(config: &Config) -> Result<ExtraBody, Error> {
+    let allow_override = config
+        .provider_options
+        .iter()
+        .any(|kv| kv.key == ALLOW_BODY_OVERRIDE_OPTION && kv.value == "true");
+
+    let fields = match &config.extra_body {
+        None => Map::new(),
+        Some(extra_body) => match serde_json::from_str::<Value>(extra_body) {
+            Ok(Value::Object(fields)) => fields,
+            Ok(_) => {
+                return Err(invalid_extra_body(
+                    "The extra body is not a JSON object".to_string(),
+                ))
+            }
+            Err(err) => {
+                return Err(invalid_extra_body(format!(
+                    "The extra body is not valid JSON: {err}"
+                )))
+            }
+        },
+    };
+
+    Ok(ExtraBody {
+        fields,
+        allow_override,
+    })
+}
+
+fn invalid_extra_body(message: String) -> Error {
+    Error {
+        code: ErrorCode::InvalidRequest,
+        message,
+        provider_error_json: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::extra_body::{extra_body, ALLOW_BODY_OVERRIDE_OPTION};
+    use crate::golem::llm::llm::{Config, ErrorCode, Kv};
+    use serde::Serialize;
+
+    #[derive(Serialize)]
+    struct Request {
+        model: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        temperature: Option<f32>,
+    }
+
+    fn request() -> Request {
+        Request {
+            model: "test-model".to_string(),
+            temperature: None,
+        }
+    }
+
+    fn config(extra_body: Option<&str>, provider_options: Vec<Kv>) -> Config {
+        Config {
+            model: "test-model".to_string(),
+            temperature: None,
+            max_tokens: None,
+            stop_sequences: None,
+            tools: vec![],
+            tool_choice: None,
+            provider_options,
+            extra_headers: vec![],
+            service_tier: None,
+            metadata: vec![],
+            reasoning_effort: None,
+            store: None,
+            prompt_cache_key: None,
+            stream_options: None,
+            deadline_ms: None,
+            extra_body: extra_body.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn extra_fields_are_added_to_the_request_body() {
+        let extra_body = extra_body(&config(
+            Some(r#"{"top_k":40,"safe_prompt":true,"temperature":0.2}"#),
+            vec![],
+        ))
+        .unwrap();
+
+        let body = extra_body.merge(&request()).unwrap();
+
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "model": "test-model",
+                "top_k": 40,
+                "safe_prompt": true,
+                "temperature": 0.2,
+            })
+        );
+    }
+
+    #[test]
+    fn request_without_extra_body_is_unchanged() {
+        let extra_body = extra_body(&config(None, vec![])).unwrap();
+
+        assert!(extra_body.is_empty());
+        assert_eq!(
+            extra_body.merge(&request()).unwrap(),
+            serde_json::json!({"model": "test-model"})
+        );
+    }
+
+    #[test]
+    fn conflicting_fields_are_rejected_unless_overriding_is_allowed() {
+        let extra_body_json = Some(r#"{"model":"other-model"}"#);
+
+        let error = extra_body(&config(extra_body_json, vec![]))
+            .unwrap()
+            .merge(&request())
+            .unwrap_err();
+        assert_eq!(error.code, ErrorCode::InvalidRequest);
+        assert!(error.message.contains("model"));
+
+        let body = extra_body(&config(
+            extra_body_json,
+            vec![Kv {
+                key: ALLOW_BODY_OVERRIDE_OPTION.to_string(),
+                value: "true".to_string(),
+            }],
+        ))
+        .unwrap()
+        .merge(&request())
+        .unwrap();
+        assert_eq!(body, serde_json::json!({"model": "other-model"}));
+    }
+
+    #[test]
+    fn extra_body_must_be_a_json_object() {
+        for extra_body_json in [r#"["top_k"]"#, "40", "{top_k: 40}"] {
+            let error = extra_body(&config(Some(extra_body_json), vec![])).unwrap_err();
+            assert_eq!(error.code, ErrorCode::InvalidRequest);
+        }
+    }
+}
@@ -0,0 +1,569 @@
+use crate::batch::send_batch;
+use crate::golem::llm::llm::{
+    ChatEvent, ChatStream, Config, ContentPart, EmbeddingConfig, EmbeddingResult, Error, ErrorCode,
+    Guest, ImageGenConfig, ImageGenResult, Message, ProviderCapabilities, ProviderHealth,
+    RerankConfig, RerankResult, ToolCall, ToolResult,
+};
+use log::trace;
+use std::marker::PhantomData;
+
+/// Combines two LLM implementations into a fallback chain, sending every request to `Primary`
+/// first and falling back to `Secondary` if it fails with an error worth retrying elsewhere.
+/// Longer chains are built by nesting, for example `FallbackLLM<A, FallbackLLM<B, C>>`.
+///
+/// When the providers are wrapped with `DurableLLM`, the outcome of every attempt is persisted,
+/// so a replay follows the same path and ends with the same provider as the original run.
+///
+/// Streams are always served by `Primary`, as their failure is only known after the stream has
+/// been returned, and so are raw requests, as their body is specific to the provider. Reranking,
+/// embedding and image generation also fall back to `Secondary` when `Primary` does not support
+/// them.
+pub struct FallbackLLM<Primary, Secondary> {
+    phantom: PhantomData<(Primary, Secondary)>,
+}
+
+/// Returns whether the next provider of a fallback chain should be tried after this error.
+/// Errors caused by the request itself would fail with every provider, so they stop the chain.
+pub fn is_fallback_error(error: &Error) -> bool {
+    matches!(
+        error.code,
+        ErrorCode::AuthenticationFailed
+            | ErrorCode::RateLimitExceeded
+            | ErrorCode::InternalError
+            | ErrorCode::Timeout
+    )
+}
+
+/// Sends the request with each provider in order, returning the first result which is not an
+/// error to fall back on. If every provider fails, the last error is returned.
+pub fn send_with_fallback<R: Clone>(
+    request: R,
+    providers: &[&dyn Fn(R) -> ChatEvent],
+) -> ChatEvent {
+    let mut result = None;
+    for (index, send) in providers.iter().enumerate() {
+        match send(request.clone()) {
+            ChatEvent::Error(error) if is_fallback_error(&error) => {
+                trace!(
+                    "Provider #{index} of the fallback chain failed: {}",
+                    error.message
+                );
+                result = Some(ChatEvent::Error(error));
+            }
+            event => return event,
+        }
+    }
+    result.unwrap_or_else(|| {
+        ChatEvent::Error(Error {
+            code: ErrorCode::InvalidRequest,
+            message: "No providers in the fallback chain".to_string(),
+            provider_error_json: None,
+        })
+    })
+}
+
+impl<Primary: Guest, Secondary: Guest> Guest for FallbackLLM<Primary, Secondary> {
+    type ChatStream = Primary::ChatStream;
+
+    fn send(messages: Vec<Message>, config: Config) -> ChatEvent {
+        send_with_fallback(
+            (messages, config),
+            &[
+                &|(messages, config)| Primary::send(messages, config),
+                &|(messages, config)| Secondary::send(messages, config),
+            ],
+        )
+    }
+
+    fn continue_(
+        messages: Vec<Message>,
+        tool_results: Vec<(ToolCall, ToolResult)>,
+        config: Config,
+    ) -> ChatEvent {
+        send_with_fallback(
+            (messages, tool_results, config),
+            &[
+                &|(messages, tool_results, config)| {
+                    Primary::continue_(messages, tool_results, config)
+                },
+                &|(messages, tool_results, config)| {
+                    Secondary::continue_(messages, tool_results, config)
+                },
+            ],
+        )
+    }
+
+    fn send_batch(requests: Vec<(Vec<Message>, Config)>, abort_on_error: bool) -> Vec<ChatEvent> {
+        send_batch(requests, abort_on_error, Self::send)
+    }
+
+    fn stream(messages: Vec<Message>, config: Config) -> ChatStream {
+        Primary::stream(messages, config)
+    }
+
+    fn continue_stream(
+        messages: Vec<Message>,
+        tool_results: Vec<(ToolCall, ToolResult)>,
+        config: Config,
+    ) -> ChatStream {
+        Primary::continue_stream(messages, tool_results, config)
+    }
+
+    fn send_raw(body_json: String, config: Config) -> Result<String, Error> {
+        Primary::send_raw(body_json, config)
+    }
+
+    fn rerank(
+        query: String,
+        documents: Vec<String>,
+        config: RerankConfig,
+    ) -> Result<RerankResult, Error> {
+        match Primary::rerank(query.clone(), documents.clone(), config.clone()) {
+            Err(error) if is_fallback_error(&error) || error.code == ErrorCode::Unsupported => {
+                Secondary::rerank(query, documents, config)
+            }
+            result => result,
+        }
+    }
+
+    fn embed(inputs: Vec<ContentPart>, config: EmbeddingConfig) -> Result<EmbeddingResult, Error> {
+        match Primary::embed(inputs.clone(), config.clone()) {
+            Err(error) if is_fallback_error(&error) || error.code == ErrorCode::Unsupported => {
+                Secondary::embed(inputs, config)
+            }
+            result => result,
+        }
+    }
+
+    fn ping() -> Result<ProviderHealth, Error> {
+        match Primary::ping() {
+            Ok(health) if health.reachable => Ok(health),
+            Err(error) if !is_fallback_error(&error) => Err(error),
+            _ => Secondary::ping(),
+        }
+    }
+
+    /// The capabilities of `Primary`, which serves the streams and every request failing with an
+    /// error not worth retrying elsewhere, except for the embeddings `Secondary` can serve
+    fn capabilities() -> ProviderCapabilities {
+        let primary = Primary::capabilities();
+        ProviderCapabilities {
+            embeddings: primary.embeddings || Secondary::capabilities().embeddings,
+            ..primary
+        }
+    }
+
+    fn warm(config: Config) -> Result<(), Error> {
+        match Primary::warm(config.clone()) {
+            Err(error) if is_fallback_error(&error) || error.code == ErrorCode::Unsupported => {
+                Secondary::warm(config)
+            }
+            result => result,
+        }
+    }
+
+    fn generate_image(prompt: String, config: ImageGenConfig) -> Result<ImageGenResult, Error> {
+        match Primary::generate_image(prompt.clone(), config.clone()) {
+            Err(error) if is_fallback_error(&error) || error.code == ErrorCode::Unsupported => {
+                Secondary::generate_image(prompt, config)
+            }
+            result => result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fallback::{send_with_fallback, FallbackLLM};
+    use crate::golem::llm::llm::{
+        ChatEvent, ChatStream, Config, ContentPart, EmbeddingConfig, EmbeddingResult, Error,
+        ErrorCode, Guest, GuestChatStream, ImageGenConfig, ImageGenResult, Message,
+        ProviderCapabilities, ProviderHealth, RerankConfig, RerankResult, Role, StreamEvent,
+        ToolCall, ToolResult,
+    };
+    use std::cell::RefCell;
+    use std::marker::PhantomData;
+
+    fn error(code: ErrorCode) -> ChatEvent {
+        ChatEvent::Error(Error {
+            code,
+            message: "failed".to_string(),
+            provider_error_json: None,
+        })
+    }
+
+    fn success(provider: &str) -> ChatEvent {
+        ChatEvent::ToolRequest(vec![ToolCall {
+            id: provider.to_string(),
+            name: provider.to_string(),
+            arguments_json: "{}".to_string(),
+        }])
+    }
+
+    #[test]
+    fn next_provider_is_used_when_first_fails() {
+        for code in [
+            ErrorCode::AuthenticationFailed,
+            ErrorCode::RateLimitExceeded,
+            ErrorCode::InternalError,
+        ] {
+            let called = RefCell::new(Vec::new());
+            let event = send_with_fallback(
+                "request",
+                &[
+                    &|request| {
+                        called.borrow_mut().push(format!("first {request}"));
+                        error(code)
+                    },
+                    &|request| {
+                        called.borrow_mut().push(format!("second {request}"));
+                        success("second")
+                    },
+                ],
+            );
+
+            assert_eq!(event, success("second"));
+            assert_eq!(*called.borrow(), vec!["first request", "second request"]);
+        }
+    }
+
+    #[test]
+    fn non_retryable_error_stops_the_chain() {
+        let event = send_with_fallback(
+            (),
+            &[&|_| error(ErrorCode::InvalidRequest), &|_| {
+                panic!("Fallback used after a non-retryable error")
+            }],
+        );
+
+        assert_eq!(event, error(ErrorCode::InvalidRequest));
+    }
+
+    #[test]
+    fn last_error_is_returned_when_every_provider_fails() {
+        let event = send_with_fallback(
+            (),
+            &[&|_| error(ErrorCode::RateLimitExceeded), &|_| {
+                error(ErrorCode::AuthenticationFailed)
+            }],
+        );
+
+        assert_eq!(event, error(ErrorCode::AuthenticationFailed));
+    }
+
+    #[test]
+    fn first_success_is_returned() {
+        let event = send_with_fallback((), &[&|_| success("first"), &|_| success("second")]);
+
+        assert_eq!(event, success("first"));
+    }
+
+    /// How every request sent to a `FakeProvider` ends
+    trait Outcome {
+        const NAME: &'static str;
+        const ERROR: Option<ErrorCode>;
+    }
+
+    struct Working;
+
+    impl Outcome for Working {
+        const NAME: &'static str = "working";
+        const ERROR: Option<ErrorCode> = None;
+    }
+
+    struct RateLimited;
+
+    impl Outcome for RateLimited {
+        const NAME: &'static str = "rate-limited";
+        const ERROR: Option<ErrorCode> = Some(ErrorCode::RateLimitExceeded);
+    }
+
+    struct Rejecting;
+
+    impl Outcome for Rejecting {
+        const NAME: &'static str = "rejecting";
+        const ERROR: Option<ErrorCode> = Some(ErrorCode::InvalidRequest);
+    }
+
+    struct Unsupported;
+
+    impl Outcome for Unsupported {
+        const NAME: &'static str = "unsupported";
+        const ERROR: Option<ErrorCode> = Some(ErrorCode::Unsupported);
+    }
+
+    thread_local! {
+        static CALLS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+    }
+
+    /// Returns the calls received by the fake providers of the current test so far
+    fn take_calls() -> Vec<String> {
+        CALLS.with(|calls| calls.take())
+    }
+
+    /// A provider answering every request with the outcome `O`, and recording the calls it gets
+    struct FakeProvider<O>(PhantomData<O>);
+
+    impl<O: Outcome> FakeProvider<O> {
+        fn respond<T>(call: &str, value: T) -> Result<T, Error> {
+            CALLS.with(|calls| calls.borrow_mut().push(format!("{} {call}", O::NAME)));
+            match O::ERROR {
+                Some(code) => Err(Error {
+                    code,
+                    message: "failed".to_string(),
+                    provider_error_json: None,
+                }),
+                None => Ok(value),
+            }
+        }
+
+        fn chat(call: &str) -> ChatEvent {
+            Self::respond(call, success(O::NAME)).unwrap_or_else(ChatEvent::Error)
+        }
+    }
+
+    struct NoStream;
+
+    impl GuestChatStream for NoStream {
+        fn get_next(&self) -> Option<Vec<StreamEvent>> {
+            unreachable!()
+        }
+
+        fn blocking_get_next(&self) -> Vec<StreamEvent> {
+            unreachable!()
+        }
+
+        fn partial_text(&self) -> String {
+            unreachable!()
+        }
+
+        fn raw_transcript(&self) -> String {
+            unreachable!()
+        }
+    }
+
+    impl<O: Outcome> Guest for FakeProvider<O> {
+        type ChatStream = NoStream;
+
+        fn send(_messages: Vec<Message>, _config: Config) -> ChatEvent {
+            Self::chat("send")
+        }
+
+        fn continue_(
+            _messages: Vec<Message>,
+            _tool_results: Vec<(ToolCall, ToolResult)>,
+            _config: Config,
+        ) -> ChatEvent {
+            Self::chat("continue")
+        }
+
+        fn send_batch(
+            _requests: Vec<(Vec<Message>, Config)>,
+            _abort_on_error: bool,
+        ) -> Vec<ChatEvent> {
+            unreachable!()
+        }
+
+        fn stream(_messages: Vec<Message>, _config: Config) -> ChatStream {
+            unreachable!()
+        }
+
+        fn continue_stream(
+            _messages: Vec<Message>,
+            _tool_results: Vec<(ToolCall, ToolResult)>,
+            _config: Config,
+        ) -> ChatStream {
+            unreachable!()
+        }
+
+        fn send_raw(_body_json: String, _config: Config) -> Result<String, Error> {
+            unreachable!()
+        }
+
+        fn rerank(
+            _query: String,
+            _documents: Vec<String>,
+            _config: RerankConfig,
+        ) -> Result<RerankResult, Error> {
+            Self::respond(
+                "rerank",
+                RerankResult {
+                    results: vec![],
+                    model: Some(O::NAME.to_string()),
+                    provider_metadata_json: None,
+                },
+            )
+        }
+
+        fn embed(
+            _inputs: Vec<ContentPart>,
+            _config: EmbeddingConfig,
+        ) -> Result<EmbeddingResult, Error> {
+            Self::respond(
+                "embed",
+                EmbeddingResult {
+                    embeddings: vec![],
+                    usage: None,
+                    model: Some(O::NAME.to_string()),
+                    provider_metadata_json: None,
+                },
+            )
+        }
+
+        fn ping() -> Result<ProviderHealth, Error> {
+            CALLS.with(|calls| calls.borrow_mut().push(format!("{} ping", O::NAME)));
+            Ok(ProviderHealth {
+                reachable: O::ERROR.is_none(),
+                latency_ms: 0,
+            })
+        }
+
+        fn capabilities() -> ProviderCapabilities {
+            ProviderCapabilities {
+                provider: O::NAME.to_string(),
+                streaming: true,
+                tools: true,
+                vision: false,
+                embeddings: O::ERROR.is_none(),
+                json_schema: false,
+            }
+        }
+
+        fn warm(_config: Config) -> Result<(), Error> {
+            Self::respond("warm", ())
+        }
+
+        fn generate_image(
+            _prompt: String,
+            _config: ImageGenConfig,
+        ) -> Result<ImageGenResult, Error> {
+            Self::respond(
+                "generate-image",
+                ImageGenResult {
+                    images: vec![],
+                    usage: None,
+                    model: Some(O::NAME.to_string()),
+                    provider_metadata_json: None,
+                },
+            )
+        }
+    }
+
+    fn messages() -> Vec<Message> {
+        vec![Message {
+            role: Role::User,
+            name: None,
+            content: vec![ContentPart::Text("Hello".to_string())],
+        }]
+    }
+
+    fn config() -> Config {
+        Config {
+            model: "test-model".to_string(),
+            temperature: None,
+            max_tokens: None,
+            stop_sequences: None,
+            tools: vec![],
+            tool_choice: None,
+            provider_options: vec![],
+            extra_headers: vec![],
+            service_tier: None,
+            metadata: vec![],
+            reasoning_effort: None,
+            store: None,
+            prompt_cache_key: None,
+            stream_options: None,
+            deadline_ms: None,
+            extra_body: None,
+        }
+    }
+
+    #[test]
+    fn chat_requests_fall_back_to_the_secondary_provider() {
+        type Chain = FallbackLLM<FakeProvider<RateLimited>, FakeProvider<Working>>;
+
+        assert_eq!(Chain::send(messages(), config()), success("working"));
+        assert_eq!(
+            Chain::continue_(messages(), vec![], config()),
+            success("working")
+        );
+        assert_eq!(
+            Chain::send_batch(vec![(messages(), config())], true),
+            vec![success("working")]
+        );
+        assert_eq!(
+            take_calls(),
+            vec![
+                "rate-limited send",
+                "working send",
+                "rate-limited continue",
+                "working continue",
+                "rate-limited send",
+                "working send",
+            ]
+        );
+    }
+
+    #[test]
+    fn chat_requests_rejected_by_the_primary_provider_do_not_fall_back() {
+        type Chain = FallbackLLM<FakeProvider<Rejecting>, FakeProvider<Working>>;
+
+        assert_eq!(
+            Chain::send(messages(), config()),
+            error(ErrorCode::InvalidRequest)
+        );
+        assert_eq!(take_calls(), vec!["rejecting send"]);
+    }
+
+    #[test]
+    fn unsupported_requests_fall_back_to_the_secondary_provider() {
+        type Chain = FallbackLLM<FakeProvider<Unsupported>, FakeProvider<Working>>;
+
+        let embeddings = Chain::embed(
+            vec![ContentPart::Text("Hello".to_string())],
+            EmbeddingConfig {
+                model: "test-model".to_string(),
+                provider_options: vec![],
+            },
+        )
+        .unwrap();
+        assert_eq!(embeddings.model, Some("working".to_string()));
+
+        let ranking = Chain::rerank(
+            "query".to_string(),
+            vec!["document".to_string()],
+            RerankConfig {
+                model: "test-model".to_string(),
+                top_n: None,
+                provider_options: vec![],
+            },
+        )
+        .unwrap();
+        assert_eq!(ranking.model, Some("working".to_string()));
+
+        assert!(Chain::warm(config()).is_ok());
+        assert_eq!(
+            take_calls(),
+            vec![
+                "unsupported embed",
+                "working embed",
+                "unsupported rerank",
+                "working rerank",
+                "unsupported warm",
+                "working warm",
+            ]
+        );
+    }
+
+    #[test]
+    fn unreachable_primary_provider_falls_back_on_ping() {
+        type Chain = FallbackLLM<FakeProvider<RateLimited>, FakeProvider<Working>>;
+
+        assert!(Chain::ping().unwrap().reachable);
+        assert_eq!(take_calls(), vec!["rate-limited ping", "working ping"]);
+
+        let capabilities = Chain::capabilities();
+        assert_eq!(capabilities.provider, "rate-limited");
+        assert!(capabilities.embeddings);
+    }
+}
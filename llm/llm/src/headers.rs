@@ -0,0 +1,240 @@
+use crate::golem::llm::llm::{Config, Error, ErrorCode};
+use crate::request_id::{request_id, REQUEST_ID_HEADER};
+use log::trace;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, CONTENT_TYPE};
+
+/// Provider option which has to be set to `true` to let extra headers replace the
+/// `Authorization`, `Content-Type` and API key headers set by the provider clients
+pub const ALLOW_HEADER_OVERRIDE_OPTION: &str = "allow_header_override";
+
+/// Headers carrying the API key instead of `Authorization`, like those of Azure OpenAI and
+/// Anthropic
+const API_KEY_HEADER_NAMES: [&str; 2] = ["api-key", "x-api-key"];
+
+/// Prefix of the headers carrying the `metadata` of the config, read by proxy based
+/// observability platforms
+pub const METADATA_HEADER_PREFIX: &str = "X-Metadata-";
+
+/// Parts of header names whose values must not be logged
+const SENSITIVE_HEADER_NAME_PARTS: [&str; 5] =
+    ["authorization", "key", "token", "secret", "cookie"];
+
+/// Converts the `extra_headers` of the config to a header map to be applied on every request
+/// after the headers set by the provider client, replacing them if they have the same name.
+///
+/// Each entry of the config's `metadata` is added as an `X-Metadata-<key>` header, and the
+/// `request_id` provider option as the `X-Request-Id` header, unless an extra header with the
+/// same name is set.
+///
+/// Fails if a header is invalid, or if it would replace `Authorization`, `Content-Type`, `api-key`
+/// or `x-api-key` without the `allow_header_override` provider option being set to `true`.
+pub fn extra_headers(config: &Config) -> Result<HeaderMap, Error> {
+    let allow_override = config
+        .provider_options
+        .iter()
+        .any(|kv| kv.key == ALLOW_HEADER_OVERRIDE_OPTION && kv.value == "true");
+
+    let mut headers = HeaderMap::new();
+    for (name, value) in &config.extra_headers {
+        let header_name = HeaderName::from_bytes(name.as_bytes())
+            .map_err(|err| invalid_header(format!("Invalid header name {name}: {err}")))?;
+        if !allow_override && is_protected(&header_name) {
+            return Err(invalid_header(format!(
+                "Overriding the {header_name} header requires the {ALLOW_HEADER_OVERRIDE_OPTION} provider option"
+            )));
+        }
+        let header_value = HeaderValue::from_str(value)
+            .map_err(|err| invalid_header(format!("Invalid value for header {name}: {err}")))?;
+        headers.append(header_name, header_value);
+    }
+
+    for (key, value) in &config.metadata {
+        let name = format!("{METADATA_HEADER_PREFIX}{key}");
+        let header_name = HeaderName::from_bytes(name.as_bytes())
+            .map_err(|err| invalid_header(format!("Invalid metadata key {key}: {err}")))?;
+        let header_value = HeaderValue::from_str(value)
+            .map_err(|err| invalid_header(format!("Invalid value for metadata {key}: {err}")))?;
+        if !headers.contains_key(&header_name) {
+            headers.insert(header_name, header_value);
+        }
+    }
+
+    if let Some(request_id) = request_id(config) {
+        let header_value = HeaderValue::from_str(request_id)
+            .map_err(|err| invalid_header(format!("Invalid request id {request_id}: {err}")))?;
+        if !headers.contains_key(REQUEST_ID_HEADER) {
+            headers.insert(REQUEST_ID_HEADER, header_value);
+        }
+    }
+
+    if !headers.is_empty() {
+        trace!("Using extra headers: {}", redacted(&headers));
+    }
+
+    Ok(headers)
+}
+
+/// Returns whether the header is set by the provider clients, and can only be replaced when
+/// explicitly allowed
+fn is_protected(name: &HeaderName) -> bool {
+    name == AUTHORIZATION || name == CONTENT_TYPE || API_KEY_HEADER_NAMES.contains(&name.as_str())
+}
+
+/// Describes the headers for logging, hiding the values of the sensitive ones
+fn redacted(headers: &HeaderMap) -> String {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let is_sensitive = SENSITIVE_HEADER_NAME_PARTS
+                .iter()
+                .any(|part| name.as_str().contains(part));
+            if is_sensitive {
+                format!("{name}: <redacted>")
+            } else {
+                format!("{name}: {}", value.to_str().unwrap_or("<non-ascii>"))
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn invalid_header(message: String) -> Error {
+    Error {
+        code: ErrorCode::InvalidRequest,
+        message,
+        provider_error_json: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::golem::llm::llm::{Config, ErrorCode, Kv};
+    use crate::headers::{extra_headers, redacted};
+
+    fn config(extra_headers: Vec<(&str, &str)>, provider_options: Vec<Kv>) -> Config {
+        Config {
+            model: "test-model".to_string(),
+            temperature: None,
+            max_tokens: None,
+            stop_sequences: None,
+            tools: vec![],
+            tool_choice: None,
+            provider_options,
+            extra_headers: extra_headers
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            service_tier: None,
+            metadata: vec![],
+            reasoning_effort: None,
+            store: None,
+            prompt_cache_key: None,
+            stream_options: None,
+            deadline_ms: None,
+            extra_body: None,
+        }
+    }
+
+    #[test]
+    fn metadata_is_sent_as_headers() {
+        let headers = extra_headers(&Config {
+            metadata: vec![
+                ("tenant".to_string(), "acme".to_string()),
+                ("feature".to_string(), "summary".to_string()),
+            ],
+            ..config(vec![("X-Metadata-Feature", "chat")], vec![])
+        })
+        .unwrap();
+
+        assert_eq!(headers.len(), 2);
+        assert_eq!(headers.get("x-metadata-tenant").unwrap(), "acme");
+        assert_eq!(headers.get("x-metadata-feature").unwrap(), "chat");
+    }
+
+    #[test]
+    fn request_id_is_sent_as_header() {
+        let headers = extra_headers(&config(
+            vec![],
+            vec![Kv {
+                key: "request_id".to_string(),
+                value: "id-1".to_string(),
+            }],
+        ))
+        .unwrap();
+
+        assert_eq!(headers.get("x-request-id").unwrap(), "id-1");
+    }
+
+    #[test]
+    fn invalid_metadata_keys_are_rejected() {
+        let error = extra_headers(&Config {
+            metadata: vec![("tenant id".to_string(), "acme".to_string())],
+            ..config(vec![], vec![])
+        })
+        .unwrap_err();
+
+        assert_eq!(error.code, ErrorCode::InvalidRequest);
+    }
+
+    #[test]
+    fn extra_headers_are_converted() {
+        let headers = extra_headers(&config(
+            vec![
+                ("HTTP-Referer", "https://golem.cloud"),
+                ("X-Title", "golem"),
+            ],
+            vec![],
+        ))
+        .unwrap();
+
+        assert_eq!(headers.len(), 2);
+        assert_eq!(headers.get("http-referer").unwrap(), "https://golem.cloud");
+        assert_eq!(headers.get("x-title").unwrap(), "golem");
+    }
+
+    #[test]
+    fn protected_headers_are_rejected_unless_allowed() {
+        let error =
+            extra_headers(&config(vec![("Authorization", "Bearer other")], vec![])).unwrap_err();
+        assert_eq!(error.code, ErrorCode::InvalidRequest);
+
+        let headers = extra_headers(&config(
+            vec![("Authorization", "Bearer other")],
+            vec![Kv {
+                key: "allow_header_override".to_string(),
+                value: "true".to_string(),
+            }],
+        ))
+        .unwrap();
+        assert_eq!(headers.get("authorization").unwrap(), "Bearer other");
+    }
+
+    #[test]
+    fn api_key_headers_are_protected() {
+        for name in ["api-key", "X-Api-Key"] {
+            let error = extra_headers(&config(vec![(name, "other-key")], vec![])).unwrap_err();
+            assert_eq!(error.code, ErrorCode::InvalidRequest, "{name}");
+
+            let headers = extra_headers(&config(
+                vec![(name, "other-key")],
+                vec![Kv {
+                    key: "allow_header_override".to_string(),
+                    value: "true".to_string(),
+                }],
+            ))
+            .unwrap();
+            assert_eq!(headers.get(name).unwrap(), "other-key");
+        }
+    }
+
+    #[test]
+    fn sensitive_header_values_are_redacted() {
+        let headers = extra_headers(&config(
+            vec![("X-Api-Key", "secret-value"), ("X-Title", "golem")],
+            vec![],
+        ))
+        .unwrap();
+
+        assert_eq!(redacted(&headers), "x-api-key: <redacted>, x-title: golem");
+    }
+}
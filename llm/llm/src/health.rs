@@ -0,0 +1,98 @@
+use crate::clock::{Clock, MonotonicClock};
+use crate::error::error_code_from_status;
+use crate::golem::llm::llm::{Error, ProviderHealth};
+use log::trace;
+use reqwest::Response;
+use std::time::Duration;
+
+/// Time after which a connectivity probe is considered to have failed
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Sends a connectivity probe and measures its latency.
+///
+/// A request failing without a response, or taking longer than 10 seconds, means the endpoint is
+/// not reachable, while a response with an unsuccessful status (for example because of invalid
+/// credentials) is an error.
+pub fn probe(
+    send: impl FnOnce() -> Result<Response, reqwest::Error>,
+) -> Result<ProviderHealth, Error> {
+    let (result, latency_ms) = timed(&MonotonicClock, PROBE_TIMEOUT, send);
+
+    match result {
+        Some(Ok(response)) => {
+            let status = response.status();
+            if status.is_success() {
+                Ok(ProviderHealth {
+                    reachable: true,
+                    latency_ms,
+                })
+            } else {
+                let body = response.text().ok();
+                Err(Error {
+                    code: error_code_from_status(status),
+                    message: format!("Health check failed with status {status}"),
+                    provider_error_json: body,
+                })
+            }
+        }
+        Some(Err(err)) => {
+            trace!("Health check request failed: {err}");
+            Ok(ProviderHealth {
+                reachable: false,
+                latency_ms,
+            })
+        }
+        None => {
+            trace!("Health check request timed out after {latency_ms} ms");
+            Ok(ProviderHealth {
+                reachable: false,
+                latency_ms,
+            })
+        }
+    }
+}
+
+/// Runs the probe, measuring its latency in milliseconds with the clock. The result of a probe
+/// taking longer than the timeout is discarded.
+fn timed<T>(clock: &dyn Clock, timeout: Duration, send: impl FnOnce() -> T) -> (Option<T>, u64) {
+    let start = clock.now();
+    let result = send();
+    let elapsed = clock.now() - start;
+    (
+        (elapsed <= timeout).then_some(result),
+        elapsed.as_millis() as u64,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::clock::FakeClock;
+    use crate::health::{timed, PROBE_TIMEOUT};
+    use std::time::Duration;
+
+    #[test]
+    fn probe_latency_is_measured_with_the_clock() {
+        let clock = FakeClock::new();
+
+        let (result, latency_ms) = timed(&clock, PROBE_TIMEOUT, || {
+            clock.advance(Duration::from_millis(250));
+            "pong"
+        });
+
+        assert_eq!(result, Some("pong"));
+        assert_eq!(latency_ms, 250);
+    }
+
+    #[test]
+    fn probe_slower_than_the_timeout_has_no_result() {
+        let clock = FakeClock::new();
+
+        let (result, latency_ms) = timed(&clock, PROBE_TIMEOUT, || {
+            clock.advance(PROBE_TIMEOUT + Duration::from_millis(1));
+            "pong"
+        });
+
+        assert_eq!(result, None);
+        assert_eq!(latency_ms, 10_001);
+    }
+}
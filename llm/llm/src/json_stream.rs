@@ -0,0 +1,162 @@
+use crate::golem::llm::llm::{Error, GuestChatStream};
+use crate::text_stream::TextStream;
+
+/// Chunk of text received on a `JsonStream`
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonChunk {
+    pub text: String,
+    /// Set on the single chunk completing the JSON document
+    pub complete: bool,
+}
+
+/// Wrapper around a chat stream of a JSON response, signalling when the accumulated text
+/// becomes a complete JSON document, so consumers know when it can be parsed.
+pub struct JsonStream<S> {
+    stream: TextStream<S>,
+    tracker: JsonTracker,
+}
+
+impl<S: GuestChatStream> JsonStream<S> {
+    pub fn new(stream: S) -> Self {
+        Self {
+            stream: TextStream::new(stream),
+            tracker: JsonTracker::default(),
+        }
+    }
+
+    /// Blocks until the next text chunk is available. Returns `Ok(None)` when the underlying
+    /// stream is finished, and the stream's error if it failed.
+    pub fn next_chunk(&mut self) -> Result<Option<JsonChunk>, Error> {
+        Ok(self.stream.next_text()?.map(|text| {
+            let complete = self.tracker.push(&text);
+            JsonChunk { text, complete }
+        }))
+    }
+
+    /// The JSON text accumulated so far
+    pub fn json(&self) -> &str {
+        &self.tracker.json
+    }
+
+    /// Whether the accumulated text is already a complete JSON document
+    pub fn is_complete(&self) -> bool {
+        self.tracker.complete
+    }
+}
+
+/// Tracks the nesting depth of objects and arrays across text chunks, ignoring braces and
+/// brackets within strings
+#[derive(Debug, Default)]
+struct JsonTracker {
+    json: String,
+    depth: usize,
+    started: bool,
+    in_string: bool,
+    escaped: bool,
+    complete: bool,
+}
+
+impl JsonTracker {
+    /// Adds a chunk of text, returning `true` if it completed the JSON document. This happens
+    /// only once, text received after completion is accumulated but not tracked.
+    fn push(&mut self, text: &str) -> bool {
+        self.json.push_str(text);
+        if self.complete {
+            return false;
+        }
+
+        let mut balanced = false;
+        for c in text.chars() {
+            if self.in_string {
+                match c {
+                    _ if self.escaped => self.escaped = false,
+                    '\\' => self.escaped = true,
+                    '"' => self.in_string = false,
+                    _ => {}
+                }
+            } else {
+                match c {
+                    '"' => self.in_string = true,
+                    '{' | '[' => {
+                        self.depth += 1;
+                        self.started = true;
+                    }
+                    '}' | ']' => {
+                        self.depth = self.depth.saturating_sub(1);
+                        balanced = self.started && self.depth == 0;
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        if balanced && serde_json::from_str::<serde_json::Value>(self.json.trim()).is_ok() {
+            self.complete = true;
+        }
+        self.complete
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::json_stream::JsonTracker;
+
+    /// Feeds the chunks to a new tracker, returning the indices of the chunks completing it
+    fn completions(chunks: &[&str]) -> Vec<usize> {
+        let mut tracker = JsonTracker::default();
+        chunks
+            .iter()
+            .enumerate()
+            .filter(|(_, chunk)| tracker.push(chunk))
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    #[test]
+    fn completion_is_signalled_once_on_the_last_brace() {
+        assert_eq!(
+            completions(&[
+                "{\"na",
+                "me\": \"Golem\", ",
+                "\"tags\": [1, ",
+                "2]",
+                "}",
+                "\n"
+            ]),
+            vec![4]
+        );
+    }
+
+    #[test]
+    fn braces_within_strings_are_ignored() {
+        assert_eq!(
+            completions(&[
+                "{\"text\": \"a } b ",
+                "] c \\\" } d\\\\\"",
+                ", \"more\": \"{\"",
+                "}"
+            ]),
+            vec![3]
+        );
+    }
+
+    #[test]
+    fn escape_split_across_chunks_is_handled() {
+        assert_eq!(completions(&["{\"a\": \"\\", "\"}\"", "}"]), vec![2]);
+    }
+
+    #[test]
+    fn top_level_arrays_are_tracked() {
+        assert_eq!(completions(&["[{\"a\": 1}", ", {\"b\": 2}", "]"]), vec![2]);
+    }
+
+    #[test]
+    fn text_after_completion_does_not_signal_again() {
+        assert_eq!(completions(&["{}", " {}", "[]"]), vec![0]);
+    }
+
+    #[test]
+    fn incomplete_document_is_never_signalled() {
+        assert_eq!(completions(&["{\"a\": [1, 2", "}"]), Vec::<usize>::new());
+    }
+}
@@ -1,7 +1,33 @@
+pub mod batch;
+pub mod body;
+pub mod capabilities;
+pub mod chat_completions;
 pub mod chat_stream;
+pub mod clock;
+pub mod collect;
 pub mod config;
+pub mod conversions;
+pub mod dry_run;
 pub mod durability;
 pub mod error;
+pub mod extra_body;
+pub mod fallback;
+pub mod headers;
+pub mod health;
+pub mod json_stream;
+pub mod proxy;
+pub mod rate_limit;
+pub mod raw;
+pub mod raw_transcript;
+pub mod refusal;
+pub mod request_hash;
+pub mod request_id;
+pub mod retry;
+pub mod text_stream;
+pub mod tool_call_fragments;
+pub mod truncate;
+pub mod url_guard;
+pub mod usage;
 
 #[allow(dead_code)]
 pub mod event_source;
@@ -0,0 +1,307 @@
+use crate::error::from_reqwest_error;
+use crate::golem::llm::llm::{Error, ErrorCode};
+use log::{trace, warn};
+use reqwest::{Client, Url};
+use std::cell::{Cell, OnceCell};
+
+/// Environment variable setting the proxy of every provider, taking precedence over the standard
+/// `HTTPS_PROXY` and `HTTP_PROXY` variables
+pub const PROXY_ENV_VAR_NAME: &str = "GOLEM_LLM_PROXY";
+
+/// Standard environment variables setting the proxy of `https` URLs, in order of precedence
+const HTTPS_PROXY_ENV_VAR_NAMES: [&str; 2] = ["HTTPS_PROXY", "https_proxy"];
+
+/// Standard environment variables setting the proxy of `http` URLs, in order of precedence
+const HTTP_PROXY_ENV_VAR_NAMES: [&str; 2] = ["HTTP_PROXY", "http_proxy"];
+
+/// Standard environment variables listing the hosts which are not proxied
+const NO_PROXY_ENV_VAR_NAMES: [&str; 2] = ["NO_PROXY", "no_proxy"];
+
+/// Environment variable asking to skip the verification of TLS certificates, for local and
+/// self-signed development endpoints only
+pub const INSECURE_SKIP_VERIFY_ENV_VAR_NAME: &str = "GOLEM_LLM_INSECURE_SKIP_VERIFY";
+
+/// Environment variable turning the reuse of the HTTP client off when set to `0`. Sizing the pool
+/// of idle connections with other values is not supported, see [`shared_client`]
+pub const POOL_MAX_IDLE_ENV_VAR_NAME: &str = "GOLEM_LLM_POOL_MAX_IDLE";
+
+thread_local! {
+    /// The HTTP client shared by the requests of the worker, built on first use
+    static SHARED_CLIENT: OnceCell<Client> = const { OnceCell::new() };
+
+    /// Number of HTTP clients built by the worker
+    static BUILT_CLIENTS: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Creates the HTTP client of a provider whose API is at `base_url`.
+///
+/// Requests are sent through the host's wasi-http implementation, which has no proxy support, so
+/// instead of silently bypassing a proxy configured for `base_url` by the environment, this fails
+/// with an `internal-error`.
+///
+/// The TLS connections are also made by the host, which verifies the certificates itself, so
+/// `GOLEM_LLM_INSECURE_SKIP_VERIFY` cannot be honored by the client either, and there is no
+/// per-provider option to skip the verification for the same reason. Instead of connecting with
+/// verification anyway, which would fail with a confusing certificate error, an `https`
+/// `base_url` fails with an `internal-error` explaining that the executor has to trust the
+/// endpoint's certificate. Plain `http` URLs have no certificate to verify, so they are not
+/// affected.
+pub fn http_client(base_url: &str) -> Result<Client, Error> {
+    build_client(base_url, |name| std::env::var(name).ok())
+}
+
+fn build_client(base_url: &str, env_var: impl Fn(&str) -> Option<String>) -> Result<Client, Error> {
+    if is_insecure_skip_verify(&env_var) && is_https(base_url) {
+        warn!(
+            "{INSECURE_SKIP_VERIFY_ENV_VAR_NAME} is set, asking to skip the TLS certificate verification of {base_url}. This is insecure and only meant for local development"
+        );
+        return Err(Error {
+            code: ErrorCode::InternalError,
+            message: format!(
+                "{INSECURE_SKIP_VERIFY_ENV_VAR_NAME} cannot be used for {base_url}, because the certificates are verified by the wasi-http implementation of the executor, which has to trust the endpoint's certificate instead"
+            ),
+            provider_error_json: None,
+        });
+    }
+
+    if let Some((env_var_name, proxy)) = proxy_for(base_url, env_var) {
+        return Err(Error {
+            code: ErrorCode::InternalError,
+            message: format!(
+                "The proxy {proxy} set by {env_var_name} cannot be used for {base_url}, because the wasi-http client does not support proxies"
+            ),
+            provider_error_json: None,
+        });
+    }
+
+    shared_client()
+}
+
+/// Returns the HTTP client shared by all the requests of the worker, such as the requests of the
+/// providers and the downloads of the images they are sent, building it on first use.
+///
+/// Reusing the client lets the executor's wasi-http implementation reuse its connections instead
+/// of opening new ones for every provider instance. Setting `GOLEM_LLM_POOL_MAX_IDLE` to `0`
+/// turns this off, building a new client every time.
+///
+/// Sizing the pool is deliberately not supported: the idle connections are kept by the
+/// executor's wasi-http implementation, which the client has no setting for, so any value other
+/// than `0` is ignored with a warning.
+pub fn shared_client() -> Result<Client, Error> {
+    match pool_max_idle(|name| std::env::var(name).ok()) {
+        Some(0) => return build_new_client(),
+        Some(max_idle) => warn!(
+            "{POOL_MAX_IDLE_ENV_VAR_NAME} is set to {max_idle}, but the idle connections are kept by the wasi-http implementation of the executor, so it is ignored"
+        ),
+        None => {}
+    }
+
+    if let Some(client) = SHARED_CLIENT.with(|client| client.get().cloned()) {
+        return Ok(client);
+    }
+    let client = build_new_client()?;
+    Ok(SHARED_CLIENT.with(|shared| shared.get_or_init(|| client).clone()))
+}
+
+/// Number of HTTP clients built by the worker so far, which stays at one while they are shared
+pub fn built_clients() -> usize {
+    BUILT_CLIENTS.get()
+}
+
+fn build_new_client() -> Result<Client, Error> {
+    let client = Client::builder()
+        .build()
+        .map_err(|err| from_reqwest_error("Failed to initialize HTTP client", err))?;
+    BUILT_CLIENTS.set(BUILT_CLIENTS.get() + 1);
+    Ok(client)
+}
+
+/// Reads `GOLEM_LLM_POOL_MAX_IDLE`, `None` if it is not set to a number
+fn pool_max_idle(env_var: impl Fn(&str) -> Option<String>) -> Option<usize> {
+    env_var(POOL_MAX_IDLE_ENV_VAR_NAME).and_then(|value| value.trim().parse::<usize>().ok())
+}
+
+fn is_https(url: &str) -> bool {
+    Url::parse(url).is_ok_and(|url| url.scheme() == "https")
+}
+
+fn is_insecure_skip_verify(env_var: impl Fn(&str) -> Option<String>) -> bool {
+    env_var(INSECURE_SKIP_VERIFY_ENV_VAR_NAME)
+        .is_some_and(|value| matches!(value.trim().to_lowercase().as_str(), "true" | "1"))
+}
+
+/// Returns the proxy configured for the URL, with the name of the environment variable setting it
+fn proxy_for(
+    url: &str,
+    env_var: impl Fn(&str) -> Option<String>,
+) -> Option<(&'static str, String)> {
+    let first_set = |names: &[&'static str]| {
+        names.iter().find_map(|name| {
+            env_var(name)
+                .filter(|value| !value.trim().is_empty())
+                .map(|value| (*name, value))
+        })
+    };
+
+    let url = Url::parse(url).ok()?;
+    let proxy = first_set(&[PROXY_ENV_VAR_NAME]).or_else(|| match url.scheme() {
+        "https" => first_set(&HTTPS_PROXY_ENV_VAR_NAMES),
+        "http" => first_set(&HTTP_PROXY_ENV_VAR_NAMES),
+        _ => None,
+    })?;
+
+    let host = url.host_str()?;
+    if let Some((env_var_name, no_proxy)) = first_set(&NO_PROXY_ENV_VAR_NAMES) {
+        if is_excluded(host, &no_proxy) {
+            trace!("Not proxying {host}, which is excluded by {env_var_name}");
+            return None;
+        }
+    }
+
+    Some(proxy)
+}
+
+/// Returns whether the host matches the comma separated list of hosts, like `NO_PROXY`, where `*`
+/// matches every host and an entry matches itself and its subdomains
+pub(crate) fn is_excluded(host: &str, no_proxy: &str) -> bool {
+    no_proxy
+        .split(',')
+        .map(|entry| entry.trim().trim_start_matches('.'))
+        .filter(|entry| !entry.is_empty())
+        .any(|entry| {
+            entry == "*"
+                || host.eq_ignore_ascii_case(entry)
+                || host
+                    .to_ascii_lowercase()
+                    .ends_with(&format!(".{}", entry.to_ascii_lowercase()))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::golem::llm::llm::ErrorCode;
+    use crate::proxy::{build_client, built_clients, pool_max_idle, proxy_for, shared_client};
+    use std::collections::HashMap;
+
+    fn env(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect::<HashMap<_, _>>();
+        move |name| vars.get(name).cloned()
+    }
+
+    #[test]
+    fn client_builder_fails_when_a_proxy_is_configured() {
+        let error = build_client(
+            "https://api.openai.com",
+            env(&[("HTTPS_PROXY", "http://proxy.corp:3128")]),
+        )
+        .unwrap_err();
+
+        assert_eq!(error.code, ErrorCode::InternalError);
+        assert!(error.message.contains("HTTPS_PROXY"), "{}", error.message);
+    }
+
+    #[test]
+    fn client_builder_refuses_to_skip_certificate_verification() {
+        let error = build_client(
+            "https://ollama.internal.corp",
+            env(&[("GOLEM_LLM_INSECURE_SKIP_VERIFY", "true")]),
+        )
+        .unwrap_err();
+
+        assert_eq!(error.code, ErrorCode::InternalError);
+        assert!(
+            error.message.contains("GOLEM_LLM_INSECURE_SKIP_VERIFY"),
+            "{}",
+            error.message
+        );
+        assert!(build_client(
+            "https://ollama.internal.corp",
+            env(&[("GOLEM_LLM_INSECURE_SKIP_VERIFY", "false")])
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn skipping_certificate_verification_does_not_affect_plain_http() {
+        assert!(build_client(
+            "http://localhost:11434",
+            env(&[("GOLEM_LLM_INSECURE_SKIP_VERIFY", "true")])
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn client_builder_succeeds_without_proxy() {
+        assert!(build_client("https://api.openai.com", env(&[])).is_ok());
+    }
+
+    #[test]
+    fn clients_are_shared() {
+        shared_client().unwrap();
+        let built = built_clients();
+
+        shared_client().unwrap();
+        build_client("https://api.mistral.ai", env(&[])).unwrap();
+
+        assert_eq!(built_clients(), built);
+    }
+
+    #[test]
+    fn pool_max_idle_is_read_as_a_number() {
+        assert_eq!(
+            pool_max_idle(env(&[("GOLEM_LLM_POOL_MAX_IDLE", " 0 ")])),
+            Some(0)
+        );
+        assert_eq!(
+            pool_max_idle(env(&[("GOLEM_LLM_POOL_MAX_IDLE", "many")])),
+            None
+        );
+        assert_eq!(pool_max_idle(env(&[])), None);
+    }
+
+    #[test]
+    fn proxy_is_selected_by_scheme() {
+        let env = env(&[
+            ("HTTPS_PROXY", "http://secure.corp:3128"),
+            ("http_proxy", "http://plain.corp:3128"),
+        ]);
+
+        assert_eq!(
+            proxy_for("https://api.openai.com", &env),
+            Some(("HTTPS_PROXY", "http://secure.corp:3128".to_string()))
+        );
+        assert_eq!(
+            proxy_for("http://ollama.corp:11434", &env),
+            Some(("http_proxy", "http://plain.corp:3128".to_string()))
+        );
+    }
+
+    #[test]
+    fn golem_llm_proxy_overrides_the_standard_variables() {
+        let env = env(&[
+            ("GOLEM_LLM_PROXY", "http://llm.corp:8080"),
+            ("HTTPS_PROXY", "http://secure.corp:3128"),
+        ]);
+
+        assert_eq!(
+            proxy_for("https://api.anthropic.com", &env),
+            Some(("GOLEM_LLM_PROXY", "http://llm.corp:8080".to_string()))
+        );
+    }
+
+    #[test]
+    fn no_proxy_hosts_are_not_proxied() {
+        let env = env(&[
+            ("HTTPS_PROXY", "http://secure.corp:3128"),
+            ("HTTP_PROXY", "http://plain.corp:3128"),
+            ("NO_PROXY", "localhost, .internal.corp"),
+        ]);
+
+        assert_eq!(proxy_for("http://localhost:11434", &env), None);
+        assert_eq!(proxy_for("https://llm.internal.corp", &env), None);
+        assert!(proxy_for("https://api.mistral.ai", &env).is_some());
+    }
+}
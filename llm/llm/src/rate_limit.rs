@@ -0,0 +1,175 @@
+use crate::golem::llm::llm::ChatEvent;
+use reqwest::header::HeaderMap;
+
+/// Prefixes of the rate limit headers: the common `X-RateLimit-*` ones, and Anthropic's
+/// `anthropic-ratelimit-*` ones
+const RATE_LIMIT_HEADER_PREFIXES: [&str; 2] = ["x-ratelimit-", "anthropic-ratelimit-"];
+
+/// A successful response body, with the rate limit info of the response's headers
+pub struct RateLimited<T> {
+    pub body: T,
+    pub rate_limit: Option<serde_json::Value>,
+}
+
+/// Collects the `X-RateLimit-*` and `anthropic-ratelimit-*` headers of a response, keyed by the
+/// rest of their name, like `limit`, `remaining` and `reset`, OpenAI's `remaining-requests` and
+/// `remaining-tokens`, or Anthropic's `requests-remaining` and `tokens-reset`
+pub fn rate_limit_info(headers: &HeaderMap) -> Option<serde_json::Value> {
+    let info = headers
+        .iter()
+        .filter_map(|(name, value)| {
+            let key = RATE_LIMIT_HEADER_PREFIXES
+                .iter()
+                .find_map(|prefix| name.as_str().strip_prefix(prefix))?;
+            let value = value.to_str().ok()?;
+            Some((
+                key.to_string(),
+                serde_json::Value::String(value.to_string()),
+            ))
+        })
+        .collect::<serde_json::Map<_, _>>();
+
+    if info.is_empty() {
+        None
+    } else {
+        Some(serde_json::Value::Object(info))
+    }
+}
+
+/// Adds the rate limit info as `rate_limit` to the provider metadata JSON of a message
+pub fn with_rate_limit(event: ChatEvent, rate_limit: Option<serde_json::Value>) -> ChatEvent {
+    match (event, rate_limit) {
+        (ChatEvent::Message(mut response), Some(rate_limit)) => {
+            response.metadata.provider_metadata_json = Some(add_rate_limit(
+                response.metadata.provider_metadata_json,
+                rate_limit,
+            ));
+            ChatEvent::Message(response)
+        }
+        (event, _) => event,
+    }
+}
+
+/// Metadata which is not a JSON object is reported as `metadata` next to the rate limit info
+fn add_rate_limit(provider_metadata_json: Option<String>, rate_limit: serde_json::Value) -> String {
+    let metadata = provider_metadata_json.map(|json| {
+        serde_json::from_str::<serde_json::Value>(&json).unwrap_or(serde_json::Value::String(json))
+    });
+
+    match metadata {
+        Some(serde_json::Value::Object(mut metadata)) => {
+            metadata.insert("rate_limit".to_string(), rate_limit);
+            serde_json::Value::Object(metadata).to_string()
+        }
+        Some(metadata) => {
+            serde_json::json!({ "metadata": metadata, "rate_limit": rate_limit }).to_string()
+        }
+        None => serde_json::json!({ "rate_limit": rate_limit }).to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::golem::llm::llm::{ChatEvent, CompleteResponse, ResponseMetadata};
+    use crate::rate_limit::{rate_limit_info, with_rate_limit};
+    use reqwest::header::{HeaderMap, HeaderValue, CONTENT_TYPE};
+
+    fn message(provider_metadata_json: Option<String>) -> ChatEvent {
+        ChatEvent::Message(CompleteResponse {
+            id: "resp-1".to_string(),
+            content: vec![],
+            tool_calls: vec![],
+            metadata: ResponseMetadata {
+                finish_reason: None,
+                usage: None,
+                provider_id: None,
+                model: None,
+                timestamp: None,
+                provider_metadata_json,
+                tokens_per_second: None,
+                stop_sequence: None,
+            },
+            annotations: vec![],
+        })
+    }
+
+    fn provider_metadata(event: ChatEvent) -> serde_json::Value {
+        match event {
+            ChatEvent::Message(response) => {
+                serde_json::from_str(&response.metadata.provider_metadata_json.unwrap()).unwrap()
+            }
+            event => panic!("Unexpected event: {event:?}"),
+        }
+    }
+
+    #[test]
+    fn rate_limit_headers_are_added_to_the_metadata() {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        headers.insert(
+            "X-RateLimit-Remaining-Requests",
+            HeaderValue::from_static("59"),
+        );
+        headers.insert("X-RateLimit-Reset-Tokens", HeaderValue::from_static("6m0s"));
+
+        let event = with_rate_limit(
+            message(Some(r#"{"system_fingerprint":"fp_1"}"#.to_string())),
+            rate_limit_info(&headers),
+        );
+
+        assert_eq!(
+            provider_metadata(event),
+            serde_json::json!({
+                "system_fingerprint": "fp_1",
+                "rate_limit": {"remaining-requests": "59", "reset-tokens": "6m0s"}
+            })
+        );
+    }
+
+    #[test]
+    fn rate_limit_is_added_without_metadata() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-ratelimit-limit", HeaderValue::from_static("20"));
+
+        let event = with_rate_limit(message(None), rate_limit_info(&headers));
+
+        assert_eq!(
+            provider_metadata(event),
+            serde_json::json!({"rate_limit": {"limit": "20"}})
+        );
+    }
+
+    #[test]
+    fn anthropic_rate_limit_headers_are_added_to_the_metadata() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "anthropic-ratelimit-requests-remaining",
+            HeaderValue::from_static("49"),
+        );
+        headers.insert(
+            "anthropic-ratelimit-tokens-reset",
+            HeaderValue::from_static("2025-05-01T12:00:30Z"),
+        );
+
+        let event = with_rate_limit(message(None), rate_limit_info(&headers));
+
+        assert_eq!(
+            provider_metadata(event),
+            serde_json::json!({
+                "rate_limit": {
+                    "requests-remaining": "49",
+                    "tokens-reset": "2025-05-01T12:00:30Z"
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn metadata_is_kept_without_rate_limit_headers() {
+        assert_eq!(rate_limit_info(&HeaderMap::new()), None);
+        assert_eq!(
+            with_rate_limit(message(Some("{}".to_string())), None),
+            message(Some("{}".to_string()))
+        );
+    }
+}
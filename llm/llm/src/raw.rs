@@ -0,0 +1,141 @@
+use crate::body::read_body;
+use crate::dry_run::is_dry_run;
+use crate::error::{error_code_from_status, from_reqwest_error};
+use crate::golem::llm::llm::{Error, ErrorCode};
+use log::trace;
+use reqwest::header::{HeaderMap, HeaderValue, CONTENT_TYPE};
+use reqwest::{RequestBuilder, StatusCode};
+
+/// Sends the JSON body untouched with the given request, which has the URL and the credentials of
+/// the provider's chat endpoint set, and returns the response body untouched.
+///
+/// The extra headers are applied last, like for every other request, and a failed response is
+/// mapped to an error with the response body as its provider error JSON. In dry-run mode, the
+/// body is returned as is without sending anything.
+pub fn send_raw(
+    request: RequestBuilder,
+    extra_headers: HeaderMap,
+    body_json: String,
+) -> Result<String, Error> {
+    if is_dry_run() {
+        return Ok(body_json);
+    }
+
+    let response = raw_request(request, extra_headers, body_json)?
+        .send()
+        .map_err(|err| from_reqwest_error("Request failed", err))?;
+
+    let status = response.status();
+    let body = read_body(response, "Failed to receive response body")?;
+
+    trace!("Received {status} raw response: {body}");
+
+    if status.is_success() {
+        Ok(body)
+    } else {
+        Err(error_response(status, body))
+    }
+}
+
+/// Maps a failed response to an error, keeping the response body, which is the provider's own
+/// error JSON, untouched
+fn error_response(status: StatusCode, body: String) -> Error {
+    Error {
+        code: error_code_from_status(status),
+        message: format!("Request failed with {status}"),
+        provider_error_json: Some(body),
+    }
+}
+
+/// Sets the body of the request, after making sure it is valid JSON
+fn raw_request(
+    request: RequestBuilder,
+    extra_headers: HeaderMap,
+    body_json: String,
+) -> Result<RequestBuilder, Error> {
+    serde_json::from_str::<serde_json::de::IgnoredAny>(&body_json).map_err(|err| Error {
+        code: ErrorCode::InvalidRequest,
+        message: format!("The raw request body is not valid JSON: {err}"),
+        provider_error_json: None,
+    })?;
+
+    trace!("Sending raw request: {body_json}");
+
+    Ok(request
+        .header(CONTENT_TYPE, HeaderValue::from_static("application/json"))
+        .headers(extra_headers)
+        .body(body_json))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::golem::llm::llm::ErrorCode;
+    use crate::raw::{error_response, raw_request};
+    use reqwest::header::{HeaderMap, HeaderValue, CONTENT_TYPE};
+    use reqwest::{Client, Method, StatusCode};
+
+    fn request() -> reqwest::RequestBuilder {
+        Client::builder()
+            .build()
+            .unwrap()
+            .request(Method::POST, "https://api.example.com/v1/chat")
+    }
+
+    #[test]
+    fn body_is_forwarded_unchanged() {
+        let body_json = r#"{"model": "m", "messages": [], "unmodeled_feature": {"x": [1, 2]}}"#;
+
+        let request = raw_request(request(), HeaderMap::new(), body_json.to_string())
+            .unwrap()
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            request.body().and_then(|body| body.as_bytes()),
+            Some(body_json.as_bytes())
+        );
+        assert_eq!(
+            request.headers().get(CONTENT_TYPE).unwrap(),
+            "application/json"
+        );
+    }
+
+    #[test]
+    fn extra_headers_are_applied_last() {
+        let mut extra_headers = HeaderMap::new();
+        extra_headers.insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static("application/json; charset=utf-8"),
+        );
+
+        let request = raw_request(request(), extra_headers, "{}".to_string())
+            .unwrap()
+            .build()
+            .unwrap();
+
+        assert_eq!(request.headers().get_all(CONTENT_TYPE).iter().count(), 1);
+        assert_eq!(
+            request.headers().get(CONTENT_TYPE).unwrap(),
+            "application/json; charset=utf-8"
+        );
+    }
+
+    #[test]
+    fn invalid_json_body_is_rejected() {
+        let error = raw_request(request(), HeaderMap::new(), "{\"model\":".to_string())
+            .map(|_| ())
+            .unwrap_err();
+
+        assert_eq!(error.code, ErrorCode::InvalidRequest);
+    }
+
+    #[test]
+    fn error_body_is_returned_unchanged() {
+        let body = r#"{"error": {"message": "Invalid model", "type": "invalid_request_error"}}"#;
+
+        let error = error_response(StatusCode::BAD_REQUEST, body.to_string());
+
+        assert_eq!(error.code, ErrorCode::InvalidRequest);
+        assert_eq!(error.provider_error_json, Some(body.to_string()));
+    }
+}
@@ -0,0 +1,104 @@
+use log::warn;
+
+/// Environment variable making streams keep the raw text received from the provider, returned
+/// by `raw-transcript`, when set to `true` or `1`
+const CAPTURE_RAW_STREAM_ENV_VAR_NAME: &str = "GOLEM_LLM_CAPTURE_RAW_STREAM";
+
+/// Upper bound of the size of a captured transcript in bytes. The lines received after reaching
+/// it are not captured.
+pub const MAX_RAW_TRANSCRIPT_LENGTH: usize = 4 * 1024 * 1024;
+
+/// Returns whether the streams capture the raw text received from the provider, as enabled by
+/// the `GOLEM_LLM_CAPTURE_RAW_STREAM` environment variable
+pub fn is_capturing_raw_stream() -> bool {
+    std::env::var(CAPTURE_RAW_STREAM_ENV_VAR_NAME)
+        .map(|value| matches!(value.to_lowercase().as_str(), "true" | "1"))
+        .unwrap_or(false)
+}
+
+/// The raw SSE or NDJSON text of a stream, in the chunks it was received in, bounded by
+/// `max_length`
+#[derive(Debug)]
+pub struct RawTranscript {
+    text: String,
+    max_length: usize,
+    truncated: bool,
+}
+
+impl RawTranscript {
+    pub fn new(max_length: usize) -> Self {
+        Self {
+            text: String::new(),
+            max_length,
+            truncated: false,
+        }
+    }
+
+    /// Appends the next received chunk, unless it would make the transcript exceed its maximum
+    /// length, in which case it and all the following chunks are dropped
+    pub fn append(&mut self, chunk: &str) {
+        if self.truncated {
+            return;
+        }
+
+        if self.text.len() + chunk.len() > self.max_length {
+            warn!(
+                "The raw stream transcript reached its maximum length of {} bytes, the rest of the stream is not captured",
+                self.max_length
+            );
+            self.truncated = true;
+        } else {
+            self.text.push_str(chunk);
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Whether chunks were dropped because of the maximum length
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+}
+
+impl Default for RawTranscript {
+    fn default() -> Self {
+        Self::new(MAX_RAW_TRANSCRIPT_LENGTH)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::raw_transcript::RawTranscript;
+
+    #[test]
+    fn transcript_is_the_received_lines() {
+        let lines = [
+            "data: {\"object\":\"chat.completion.chunk\",\"choices\":[{\"delta\":{\"content\":\"Hel\"}}]}\n\n",
+            ": keep-alive\n\n",
+            "data: {\"object\":\"chat.completion.chunk\",\"choices\":[{\"delta\":{\"content\":\"lo\"}}]}\n",
+            "\n",
+            "data: [DONE]\n\n",
+        ];
+
+        let mut transcript = RawTranscript::default();
+        for line in lines {
+            transcript.append(line);
+        }
+
+        assert_eq!(transcript.text(), lines.concat());
+        assert!(!transcript.is_truncated());
+    }
+
+    #[test]
+    fn lines_exceeding_the_maximum_length_are_dropped() {
+        let mut transcript = RawTranscript::new(16);
+        transcript.append("{\"done\":false}\n");
+        transcript.append("{\"done\":true}\n");
+        transcript.append("\n");
+
+        assert_eq!(transcript.text(), "{\"done\":false}\n");
+        assert!(transcript.is_truncated());
+    }
+}
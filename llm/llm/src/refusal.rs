@@ -0,0 +1,96 @@
+use crate::golem::llm::llm::{ChatEvent, ContentPart, Error, ErrorCode};
+
+/// Environment variable making the refusals of the models be reported as `content-filter` errors
+/// when set to `true` or `1`, instead of as `refusal` content parts
+const REFUSALS_AS_ERRORS_ENV_VAR_NAME: &str = "GOLEM_LLM_REFUSALS_AS_ERRORS";
+
+/// Returns whether the refusals are reported as errors, as enabled by the
+/// `GOLEM_LLM_REFUSALS_AS_ERRORS` environment variable
+pub fn is_reporting_refusals_as_errors() -> bool {
+    std::env::var(REFUSALS_AS_ERRORS_ENV_VAR_NAME)
+        .map(|value| matches!(value.to_lowercase().as_str(), "true" | "1"))
+        .unwrap_or(false)
+}
+
+/// The `content-filter` error reporting the refusal of the model
+pub fn refusal_error(refusal: &str) -> Error {
+    Error {
+        code: ErrorCode::ContentFilter,
+        message: format!("The model refused to answer: {refusal}"),
+        provider_error_json: None,
+    }
+}
+
+/// Replaces a response containing refusals with a `content-filter` error when `refusals_as_errors`
+/// is set, otherwise the refusals are kept as content parts
+pub fn with_refusal_as_error(event: ChatEvent, refusals_as_errors: bool) -> ChatEvent {
+    match event {
+        ChatEvent::Message(response) if refusals_as_errors => {
+            let refusals = response
+                .content
+                .iter()
+                .filter_map(|part| match part {
+                    ContentPart::Refusal(refusal) => Some(refusal.as_str()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            if refusals.is_empty() {
+                ChatEvent::Message(response)
+            } else {
+                ChatEvent::Error(refusal_error(&refusals.join("\n")))
+            }
+        }
+        event => event,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::golem::llm::llm::{
+        ChatEvent, CompleteResponse, ContentPart, ErrorCode, ResponseMetadata,
+    };
+    use crate::refusal::with_refusal_as_error;
+
+    fn response(content: Vec<ContentPart>) -> ChatEvent {
+        ChatEvent::Message(CompleteResponse {
+            id: "resp_1".to_string(),
+            content,
+            tool_calls: vec![],
+            metadata: ResponseMetadata {
+                finish_reason: None,
+                usage: None,
+                provider_id: None,
+                model: None,
+                timestamp: None,
+                provider_metadata_json: None,
+                tokens_per_second: None,
+                stop_sequence: None,
+            },
+            annotations: vec![],
+        })
+    }
+
+    #[test]
+    fn refusals_are_reported_as_errors_when_enabled() {
+        let refused = || {
+            response(vec![ContentPart::Refusal(
+                "I can't help with that.".to_string(),
+            )])
+        };
+
+        assert_eq!(with_refusal_as_error(refused(), false), refused());
+        match with_refusal_as_error(refused(), true) {
+            ChatEvent::Error(error) => {
+                assert_eq!(error.code, ErrorCode::ContentFilter);
+                assert_eq!(
+                    error.message,
+                    "The model refused to answer: I can't help with that."
+                );
+            }
+            other => panic!("Unexpected event: {other:?}"),
+        }
+
+        let answered = || response(vec![ContentPart::Text("Hello".to_string())]);
+        assert_eq!(with_refusal_as_error(answered(), true), answered());
+    }
+}
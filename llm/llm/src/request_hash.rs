@@ -0,0 +1,447 @@
+use crate::config::MODEL_ALIASES_OPTION;
+use crate::golem::llm::llm::{
+    AudioSource, ChatEvent, Config, ContentPart, ImageDetail, ImageReference, Message,
+    ReasoningEffort, ResponseMetadata, Role, StreamEvent, ToolCall, ToolDefinition, ToolResult,
+    ToolResultContent,
+};
+use crate::request_id::{with_provider_metadata_entry, REQUEST_ID_OPTION};
+use base64::{engine::general_purpose, Engine as _};
+use serde_json::{json, Value};
+use std::fmt::Write;
+
+/// Key of the request hash in the provider metadata JSON of the responses
+pub const REQUEST_HASH_KEY: &str = "request_hash";
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+/// 64-bit FNV-1a hash of the text written to it, which unlike the standard library's hasher is
+/// the same in every build and every run
+struct Fnv1a(u64);
+
+impl Write for Fnv1a {
+    fn write_str(&mut self, text: &str) -> std::fmt::Result {
+        for byte in text.bytes() {
+            self.0 = (self.0 ^ byte as u64).wrapping_mul(FNV_PRIME);
+        }
+        Ok(())
+    }
+}
+
+/// Returns a hash identifying the content of a request, the same for structurally identical
+/// requests whichever worker sends them, to recognize them in the provider metadata of their
+/// responses. The config is expected to have its model alias already resolved, so a request
+/// naming an alias gets the same hash as one naming its model.
+///
+/// The request is hashed as a canonical JSON encoding of its fields, named explicitly and left
+/// out when `None`, so the hash does not change when optional fields are added to the types. The
+/// request id is left out, as it differs for every request, and so are the model aliases, which
+/// were already applied. The provider options, metadata and extra headers are sorted, as their
+/// order does not change the request.
+pub fn request_hash(
+    messages: &[Message],
+    tool_results: &[(ToolCall, ToolResult)],
+    config: &Config,
+) -> String {
+    let request = json!({
+        "messages": messages.iter().map(message_json).collect::<Vec<_>>(),
+        "tool_results": tool_results
+            .iter()
+            .map(|(tool_call, tool_result)| tool_call_result_json(tool_call, tool_result))
+            .collect::<Vec<_>>(),
+        "config": config_json(config),
+    });
+
+    let mut hasher = Fnv1a(FNV_OFFSET_BASIS);
+    let _ = write!(hasher, "{request}");
+    format!("{:016x}", hasher.0)
+}
+
+/// A JSON object of the named fields, leaving out the `None` ones
+fn object<const N: usize>(fields: [(&str, Option<Value>); N]) -> Value {
+    Value::Object(
+        fields
+            .into_iter()
+            .filter_map(|(name, value)| Some((name.to_string(), value?)))
+            .collect(),
+    )
+}
+
+fn message_json(message: &Message) -> Value {
+    let role = match message.role {
+        Role::User => "user",
+        Role::Assistant => "assistant",
+        Role::System => "system",
+        Role::Tool => "tool",
+    };
+    object([
+        ("role", Some(json!(role))),
+        ("name", message.name.as_ref().map(|name| json!(name))),
+        (
+            "content",
+            Some(Value::Array(
+                message.content.iter().map(content_part_json).collect(),
+            )),
+        ),
+    ])
+}
+
+fn content_part_json(content_part: &ContentPart) -> Value {
+    match content_part {
+        ContentPart::Text(text) => json!({ "text": text }),
+        ContentPart::Image(image) => json!({ "image": image_json(image) }),
+        ContentPart::ToolResult((tool_call, tool_result)) => {
+            json!({ "tool_result": tool_call_result_json(tool_call, tool_result) })
+        }
+        ContentPart::Reasoning(reasoning) => json!({ "reasoning": reasoning }),
+        ContentPart::Audio(audio) => json!({ "audio": audio_json(audio) }),
+        ContentPart::Refusal(refusal) => json!({ "refusal": refusal }),
+    }
+}
+
+fn image_json(image: &ImageReference) -> Value {
+    match image {
+        ImageReference::Url(image_url) => json!({
+            "url": object([
+                ("url", Some(json!(image_url.url))),
+                ("detail", image_url.detail.map(image_detail_json)),
+            ])
+        }),
+        ImageReference::Inline(image_source) => json!({
+            "inline": object([
+                ("data", Some(json!(general_purpose::STANDARD.encode(&image_source.data)))),
+                ("mime_type", Some(json!(image_source.mime_type))),
+                ("detail", image_source.detail.map(image_detail_json)),
+            ])
+        }),
+    }
+}
+
+fn image_detail_json(detail: ImageDetail) -> Value {
+    json!(match detail {
+        ImageDetail::Low => "low",
+        ImageDetail::High => "high",
+        ImageDetail::Auto => "auto",
+    })
+}
+
+fn audio_json(audio: &AudioSource) -> Value {
+    object([
+        (
+            "data",
+            Some(json!(general_purpose::STANDARD.encode(&audio.data))),
+        ),
+        ("mime_type", Some(json!(audio.mime_type))),
+        (
+            "transcript",
+            audio.transcript.as_ref().map(|text| json!(text)),
+        ),
+    ])
+}
+
+fn tool_call_result_json(tool_call: &ToolCall, tool_result: &ToolResult) -> Value {
+    json!({
+        "tool_call": {
+            "id": tool_call.id,
+            "name": tool_call.name,
+            "arguments_json": tool_call.arguments_json,
+        },
+        "tool_result": tool_result_json(tool_result),
+    })
+}
+
+fn tool_result_json(tool_result: &ToolResult) -> Value {
+    match tool_result {
+        ToolResult::Success(success) => json!({
+            "success": object([
+                ("id", Some(json!(success.id))),
+                ("name", Some(json!(success.name))),
+                ("result_json", Some(json!(success.result_json))),
+                ("execution_time_ms", success.execution_time_ms.map(|ms| json!(ms))),
+                (
+                    "content",
+                    Some(Value::Array(
+                        success
+                            .content
+                            .iter()
+                            .map(|content| match content {
+                                ToolResultContent::Text(text) => json!({ "text": text }),
+                                ToolResultContent::Image(image) => {
+                                    json!({ "image": image_json(image) })
+                                }
+                            })
+                            .collect(),
+                    )),
+                ),
+            ])
+        }),
+        ToolResult::Error(failure) => json!({
+            "error": object([
+                ("id", Some(json!(failure.id))),
+                ("name", Some(json!(failure.name))),
+                ("error_message", Some(json!(failure.error_message))),
+                ("error_code", failure.error_code.as_ref().map(|code| json!(code))),
+            ])
+        }),
+    }
+}
+
+fn tool_definition_json(tool: &ToolDefinition) -> Value {
+    object([
+        ("name", Some(json!(tool.name))),
+        (
+            "description",
+            tool.description.as_ref().map(|text| json!(text)),
+        ),
+        ("parameters_schema", Some(json!(tool.parameters_schema))),
+    ])
+}
+
+fn config_json(config: &Config) -> Value {
+    let mut provider_options = config
+        .provider_options
+        .iter()
+        .filter(|kv| kv.key != REQUEST_ID_OPTION && kv.key != MODEL_ALIASES_OPTION)
+        .map(|kv| (&kv.key, &kv.value))
+        .collect::<Vec<_>>();
+    provider_options.sort();
+    let mut extra_headers = config.extra_headers.clone();
+    extra_headers.sort();
+    let mut metadata = config.metadata.clone();
+    metadata.sort();
+
+    object([
+        ("model", Some(json!(config.model))),
+        ("temperature", config.temperature.map(|value| json!(value))),
+        ("max_tokens", config.max_tokens.map(|value| json!(value))),
+        (
+            "stop_sequences",
+            config.stop_sequences.as_ref().map(|value| json!(value)),
+        ),
+        (
+            "tools",
+            Some(Value::Array(
+                config.tools.iter().map(tool_definition_json).collect(),
+            )),
+        ),
+        (
+            "tool_choice",
+            config.tool_choice.as_ref().map(|value| json!(value)),
+        ),
+        ("provider_options", Some(json!(provider_options))),
+        ("extra_headers", Some(json!(extra_headers))),
+        (
+            "service_tier",
+            config.service_tier.as_ref().map(|value| json!(value)),
+        ),
+        ("metadata", Some(json!(metadata))),
+        (
+            "reasoning_effort",
+            config.reasoning_effort.map(|effort| {
+                json!(match effort {
+                    ReasoningEffort::Low => "low",
+                    ReasoningEffort::Medium => "medium",
+                    ReasoningEffort::High => "high",
+                })
+            }),
+        ),
+        ("store", config.store.map(|value| json!(value))),
+        (
+            "prompt_cache_key",
+            config.prompt_cache_key.as_ref().map(|value| json!(value)),
+        ),
+        (
+            "stream_options",
+            config.stream_options.as_ref().map(|options| {
+                object([
+                    ("include_usage", Some(json!(options.include_usage))),
+                    (
+                        "include_obfuscation",
+                        options.include_obfuscation.map(|value| json!(value)),
+                    ),
+                ])
+            }),
+        ),
+        ("deadline_ms", config.deadline_ms.map(|value| json!(value))),
+        (
+            "extra_body",
+            config.extra_body.as_ref().map(|value| json!(value)),
+        ),
+    ])
+}
+
+/// Adds the request hash to the provider metadata JSON of the response
+pub fn with_request_hash_metadata(
+    metadata: ResponseMetadata,
+    request_hash: &str,
+) -> ResponseMetadata {
+    with_provider_metadata_entry(metadata, REQUEST_HASH_KEY, request_hash)
+}
+
+/// Adds the request hash to the metadata of a complete response
+pub fn with_request_hash_event(event: ChatEvent, request_hash: &str) -> ChatEvent {
+    match event {
+        ChatEvent::Message(mut response) => {
+            response.metadata = with_request_hash_metadata(response.metadata, request_hash);
+            ChatEvent::Message(response)
+        }
+        other => other,
+    }
+}
+
+/// Adds the request hash to the metadata of the finish events of a stream
+pub fn with_request_hash_stream_events(
+    events: Vec<StreamEvent>,
+    request_hash: &str,
+) -> Vec<StreamEvent> {
+    events
+        .into_iter()
+        .map(|event| match event {
+            StreamEvent::Finish(metadata) => {
+                StreamEvent::Finish(with_request_hash_metadata(metadata, request_hash))
+            }
+            other => other,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::with_resolved_model;
+    use crate::golem::llm::llm::{
+        Config, ContentPart, ImageReference, ImageSource, Kv, Message, ResponseMetadata, Role,
+    };
+    use crate::request_hash::{request_hash, with_request_hash_metadata};
+
+    fn config(provider_options: Vec<Kv>) -> Config {
+        Config {
+            model: "test-model".to_string(),
+            temperature: Some(0.2),
+            max_tokens: None,
+            stop_sequences: None,
+            tools: vec![],
+            tool_choice: None,
+            provider_options,
+            extra_headers: vec![],
+            service_tier: None,
+            metadata: vec![],
+            reasoning_effort: None,
+            store: None,
+            prompt_cache_key: None,
+            stream_options: None,
+            deadline_ms: None,
+            extra_body: None,
+        }
+    }
+
+    fn kv(key: &str, value: &str) -> Kv {
+        Kv {
+            key: key.to_string(),
+            value: value.to_string(),
+        }
+    }
+
+    fn messages(text: &str) -> Vec<Message> {
+        vec![
+            Message {
+                role: Role::System,
+                name: None,
+                content: vec![ContentPart::Text("You are a helpful assistant".to_string())],
+            },
+            Message {
+                role: Role::User,
+                name: None,
+                content: vec![ContentPart::Text(text.to_string())],
+            },
+        ]
+    }
+
+    #[test]
+    fn identical_requests_have_the_same_hash() {
+        let hash = request_hash(
+            &messages("Hi"),
+            &[],
+            &config(vec![kv("top_p", "0.9"), kv("request_id", "id-1")]),
+        );
+
+        assert_eq!(hash.len(), 16);
+        assert_eq!(
+            request_hash(
+                &messages("Hi"),
+                &[],
+                &config(vec![kv("request_id", "id-2"), kv("top_p", "0.9")]),
+            ),
+            hash
+        );
+    }
+
+    #[test]
+    fn different_requests_have_different_hashes() {
+        let hash = request_hash(&messages("Hi"), &[], &config(vec![]));
+
+        assert_ne!(request_hash(&messages("Hello"), &[], &config(vec![])), hash);
+        assert_ne!(
+            request_hash(&messages("Hi"), &[], &config(vec![kv("top_p", "0.9")])),
+            hash
+        );
+    }
+
+    #[test]
+    fn aliases_have_the_hash_of_their_model() {
+        let aliased = with_resolved_model(Config {
+            model: "fast".to_string(),
+            ..config(vec![kv("model_aliases", r#"{"fast": "test-model"}"#)])
+        });
+
+        assert_eq!(
+            request_hash(&messages("Hi"), &[], &aliased),
+            request_hash(&messages("Hi"), &[], &config(vec![]))
+        );
+    }
+
+    #[test]
+    fn inline_images_are_hashed_by_their_data() {
+        let image = |data: Vec<u8>| {
+            vec![Message {
+                role: Role::User,
+                name: None,
+                content: vec![ContentPart::Image(ImageReference::Inline(ImageSource {
+                    data,
+                    mime_type: "image/png".to_string(),
+                    detail: None,
+                }))],
+            }]
+        };
+
+        assert_ne!(
+            request_hash(&image(vec![1, 2, 3]), &[], &config(vec![])),
+            request_hash(&image(vec![1, 2, 4]), &[], &config(vec![]))
+        );
+    }
+
+    #[test]
+    fn request_hash_is_added_to_provider_metadata() {
+        let metadata = ResponseMetadata {
+            finish_reason: None,
+            usage: None,
+            provider_id: None,
+            model: None,
+            timestamp: None,
+            provider_metadata_json: Some(r#"{"request_id":"id-1"}"#.to_string()),
+            tokens_per_second: None,
+            stop_sequence: None,
+        };
+
+        let provider_metadata: serde_json::Value = serde_json::from_str(
+            &with_request_hash_metadata(metadata, "0123456789abcdef")
+                .provider_metadata_json
+                .unwrap(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            provider_metadata,
+            serde_json::json!({"request_id": "id-1", "request_hash": "0123456789abcdef"})
+        );
+    }
+}
@@ -0,0 +1,184 @@
+use crate::golem::llm::llm::{ChatEvent, Config, Kv, ResponseMetadata, StreamEvent};
+use log::trace;
+
+/// Provider option carrying the client generated id of the request, sent to the provider in the
+/// `X-Request-Id` header and echoed in the provider metadata of the response
+pub const REQUEST_ID_OPTION: &str = "request_id";
+
+/// Header sent with every request having a request id, `X-Request-Id`
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Returns the request id set in the provider options of the config
+pub fn request_id(config: &Config) -> Option<&str> {
+    config
+        .provider_options
+        .iter()
+        .find(|kv| kv.key == REQUEST_ID_OPTION)
+        .map(|kv| kv.value.as_str())
+}
+
+/// Makes sure the config has a request id, keeping the one set in its provider options or adding
+/// one created by `generate`. Returns the request id along with the config.
+pub fn with_request_id(mut config: Config, generate: impl FnOnce() -> String) -> (String, Config) {
+    match request_id(&config) {
+        Some(request_id) => (request_id.to_string(), config),
+        None => {
+            let request_id = generate();
+            trace!("Generated request id {request_id}");
+            config.provider_options.push(Kv {
+                key: REQUEST_ID_OPTION.to_string(),
+                value: request_id.clone(),
+            });
+            (request_id, config)
+        }
+    }
+}
+
+/// Adds the request id to the provider metadata JSON of the response. Provider metadata which is
+/// not a JSON object is left untouched.
+pub fn with_request_id_metadata(metadata: ResponseMetadata, request_id: &str) -> ResponseMetadata {
+    with_provider_metadata_entry(metadata, REQUEST_ID_OPTION, request_id)
+}
+
+/// Sets a string entry of the provider metadata JSON of the response. Provider metadata which is
+/// not a JSON object is left untouched.
+pub fn with_provider_metadata_entry(
+    mut metadata: ResponseMetadata,
+    key: &str,
+    value: &str,
+) -> ResponseMetadata {
+    let provider_metadata = match &metadata.provider_metadata_json {
+        Some(json) => serde_json::from_str::<serde_json::Value>(json).ok(),
+        None => Some(serde_json::Value::Object(serde_json::Map::new())),
+    };
+
+    match provider_metadata {
+        Some(serde_json::Value::Object(mut provider_metadata)) => {
+            provider_metadata.insert(
+                key.to_string(),
+                serde_json::Value::String(value.to_string()),
+            );
+            metadata.provider_metadata_json =
+                Some(serde_json::Value::Object(provider_metadata).to_string());
+        }
+        _ => trace!("Provider metadata is not a JSON object, not adding {key} {value}"),
+    }
+
+    metadata
+}
+
+/// Adds the request id to the metadata of a complete response
+pub fn with_request_id_event(event: ChatEvent, request_id: &str) -> ChatEvent {
+    match event {
+        ChatEvent::Message(mut response) => {
+            response.metadata = with_request_id_metadata(response.metadata, request_id);
+            ChatEvent::Message(response)
+        }
+        other => other,
+    }
+}
+
+/// Adds the request id to the metadata of the finish events of a stream
+pub fn with_request_id_stream_events(
+    events: Vec<StreamEvent>,
+    request_id: &str,
+) -> Vec<StreamEvent> {
+    events
+        .into_iter()
+        .map(|event| match event {
+            StreamEvent::Finish(metadata) => {
+                StreamEvent::Finish(with_request_id_metadata(metadata, request_id))
+            }
+            other => other,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::golem::llm::llm::{Config, Kv, ResponseMetadata};
+    use crate::request_id::{request_id, with_request_id, with_request_id_metadata};
+
+    fn config(provider_options: Vec<Kv>) -> Config {
+        Config {
+            model: "test-model".to_string(),
+            temperature: None,
+            max_tokens: None,
+            stop_sequences: None,
+            tools: vec![],
+            tool_choice: None,
+            provider_options,
+            extra_headers: vec![],
+            service_tier: None,
+            metadata: vec![],
+            reasoning_effort: None,
+            store: None,
+            prompt_cache_key: None,
+            stream_options: None,
+            deadline_ms: None,
+            extra_body: None,
+        }
+    }
+
+    fn metadata(provider_metadata_json: Option<&str>) -> ResponseMetadata {
+        ResponseMetadata {
+            finish_reason: None,
+            usage: None,
+            provider_id: None,
+            model: None,
+            timestamp: None,
+            provider_metadata_json: provider_metadata_json.map(|json| json.to_string()),
+            tokens_per_second: None,
+            stop_sequence: None,
+        }
+    }
+
+    #[test]
+    fn request_id_is_generated_once_and_reused_on_replay() {
+        let (generated, live_config) = with_request_id(config(vec![]), || "id-1".to_string());
+        assert_eq!(generated, "id-1");
+        assert_eq!(request_id(&live_config), Some("id-1"));
+
+        // Replaying with the persisted config must not generate a new id
+        let (replayed, replayed_config) = with_request_id(live_config.clone(), || {
+            panic!("Request id regenerated on replay")
+        });
+        assert_eq!(replayed, generated);
+        assert_eq!(replayed_config, live_config);
+    }
+
+    #[test]
+    fn provided_request_id_is_used() {
+        let (request_id, config) = with_request_id(
+            config(vec![Kv {
+                key: "request_id".to_string(),
+                value: "trace-42".to_string(),
+            }]),
+            || panic!("Request id generated despite being provided"),
+        );
+
+        assert_eq!(request_id, "trace-42");
+        assert_eq!(config.provider_options.len(), 1);
+    }
+
+    #[test]
+    fn request_id_is_added_to_provider_metadata() {
+        assert_eq!(
+            with_request_id_metadata(metadata(None), "id-1").provider_metadata_json,
+            Some(r#"{"request_id":"id-1"}"#.to_string())
+        );
+
+        let extended = with_request_id_metadata(metadata(Some(r#"{"key":"value"}"#)), "id-1");
+        let provider_metadata: serde_json::Value =
+            serde_json::from_str(&extended.provider_metadata_json.unwrap()).unwrap();
+        assert_eq!(
+            provider_metadata,
+            serde_json::json!({"key": "value", "request_id": "id-1"})
+        );
+
+        assert_eq!(
+            with_request_id_metadata(metadata(Some("[1,2]")), "id-1").provider_metadata_json,
+            Some("[1,2]".to_string())
+        );
+    }
+}
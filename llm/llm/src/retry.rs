@@ -0,0 +1,73 @@
+use crate::error::is_connection_error;
+use log::warn;
+
+/// Sends a non-streaming request with `send`, sending it once more right away if the connection
+/// was reset, refused or closed before any response was received.
+///
+/// `send` returns as soon as the status and headers of the response are received, so a request is
+/// never sent again once the provider started responding: errors of reading the response body are
+/// not retried. Until then the provider has not produced any response, which makes sending the
+/// request again safe.
+pub fn send_with_connection_retry<T, E: std::error::Error + 'static>(
+    mut send: impl FnMut() -> Result<T, E>,
+) -> Result<T, E> {
+    match send() {
+        Err(err) if is_connection_error(&err) => {
+            warn!("Sending the request again after a connection error: {err}");
+            send()
+        }
+        result => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::retry::send_with_connection_retry;
+    use std::io;
+
+    fn attempts(
+        results: Vec<Result<&'static str, io::Error>>,
+    ) -> (Result<&'static str, io::Error>, usize) {
+        let mut results = results.into_iter();
+        let mut sent = 0;
+        let result = send_with_connection_retry(|| {
+            sent += 1;
+            results.next().unwrap()
+        });
+        (result, sent)
+    }
+
+    #[test]
+    fn connection_reset_is_retried_once() {
+        let (result, sent) = attempts(vec![
+            Err(io::Error::new(
+                io::ErrorKind::ConnectionReset,
+                "Connection reset by peer",
+            )),
+            Ok("response"),
+        ]);
+
+        assert_eq!(result.unwrap(), "response");
+        assert_eq!(sent, 2);
+    }
+
+    #[test]
+    fn repeated_connection_reset_fails() {
+        let (result, sent) = attempts(vec![
+            Err(io::Error::other("ConnectionTerminated")),
+            Err(io::Error::other("ConnectionTerminated")),
+            Ok("response"),
+        ]);
+
+        assert!(result.is_err());
+        assert_eq!(sent, 2);
+    }
+
+    #[test]
+    fn other_errors_are_not_retried() {
+        let (result, sent) = attempts(vec![Err(io::Error::other("invalid URL")), Ok("response")]);
+
+        assert!(result.is_err());
+        assert_eq!(sent, 1);
+    }
+}
@@ -0,0 +1,188 @@
+use crate::golem::llm::llm::{ContentPart, Error, GuestChatStream, StreamEvent};
+use std::collections::VecDeque;
+
+/// Thin wrapper around a chat stream, yielding only the text content of its deltas.
+///
+/// Images, tool calls and the start and finish events are dropped. An error event terminates
+/// the text stream after the text received before it has been yielded.
+pub struct TextStream<S> {
+    stream: S,
+    pending: VecDeque<String>,
+    error: Option<Error>,
+    finished: bool,
+}
+
+impl<S: GuestChatStream> TextStream<S> {
+    pub fn new(stream: S) -> Self {
+        Self {
+            stream,
+            pending: VecDeque::new(),
+            error: None,
+            finished: false,
+        }
+    }
+
+    /// Blocks until the next text chunk is available. Returns `Ok(None)` when the underlying
+    /// stream is finished, and the stream's error if it failed.
+    pub fn next_text(&mut self) -> Result<Option<String>, Error> {
+        loop {
+            if let Some(text) = self.pending.pop_front() {
+                return Ok(Some(text));
+            }
+            if let Some(error) = self.error.take() {
+                return Err(error);
+            }
+            if self.finished {
+                return Ok(None);
+            }
+
+            let events = self.stream.blocking_get_next();
+            if events.is_empty() {
+                self.finished = true;
+            }
+            for event in events {
+                match event {
+                    StreamEvent::Delta(delta) => {
+                        for content in delta.content.unwrap_or_default() {
+                            if let ContentPart::Text(text) = content {
+                                self.pending.push_back(text);
+                            }
+                        }
+                    }
+                    StreamEvent::Finish(_) => {
+                        self.finished = true;
+                    }
+                    StreamEvent::Error(error) => {
+                        self.error = Some(error);
+                        self.finished = true;
+                        break;
+                    }
+                    StreamEvent::Start(_) => {}
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::golem::llm::llm::{
+        ContentPart, Error, ErrorCode, GuestChatStream, ImageReference, ImageUrl, ResponseMetadata,
+        StreamDelta, StreamEvent, ToolCall,
+    };
+    use crate::text_stream::TextStream;
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+
+    /// Chat stream returning a predefined sequence of event batches
+    struct SyntheticChatStream {
+        batches: RefCell<VecDeque<Vec<StreamEvent>>>,
+    }
+
+    impl SyntheticChatStream {
+        fn new(batches: Vec<Vec<StreamEvent>>) -> Self {
+            Self {
+                batches: RefCell::new(batches.into()),
+            }
+        }
+    }
+
+    impl GuestChatStream for SyntheticChatStream {
+        fn get_next(&self) -> Option<Vec<StreamEvent>> {
+            Some(self.blocking_get_next())
+        }
+
+        fn blocking_get_next(&self) -> Vec<StreamEvent> {
+            self.batches.borrow_mut().pop_front().unwrap_or_default()
+        }
+
+        fn partial_text(&self) -> String {
+            unreachable!()
+        }
+
+        fn raw_transcript(&self) -> String {
+            unreachable!()
+        }
+    }
+
+    fn text(text: &str) -> StreamEvent {
+        StreamEvent::Delta(StreamDelta {
+            content: Some(vec![ContentPart::Text(text.to_string())]),
+            tool_calls: None,
+            annotations: None,
+            tool_call_fragments: None,
+            running_usage: None,
+        })
+    }
+
+    fn collect(stream: &mut TextStream<SyntheticChatStream>) -> Vec<Result<String, Error>> {
+        let mut result = Vec::new();
+        loop {
+            match stream.next_text() {
+                Ok(Some(text)) => result.push(Ok(text)),
+                Ok(None) => break result,
+                Err(error) => result.push(Err(error)),
+            }
+        }
+    }
+
+    #[test]
+    fn yields_only_text_chunks() {
+        let mut stream = TextStream::new(SyntheticChatStream::new(vec![
+            vec![
+                text("Hello"),
+                StreamEvent::Delta(StreamDelta {
+                    content: Some(vec![ContentPart::Image(ImageReference::Url(ImageUrl {
+                        url: "https://example.com/image.png".to_string(),
+                        detail: None,
+                    }))]),
+                    tool_calls: Some(vec![ToolCall {
+                        id: "x".to_string(),
+                        name: "y".to_string(),
+                        arguments_json: "{}".to_string(),
+                    }]),
+                    annotations: None,
+                    tool_call_fragments: None,
+                    running_usage: None,
+                }),
+            ],
+            vec![
+                text(" world"),
+                StreamEvent::Finish(ResponseMetadata {
+                    finish_reason: None,
+                    usage: None,
+                    provider_id: None,
+                    model: None,
+                    timestamp: None,
+                    provider_metadata_json: None,
+                    tokens_per_second: None,
+                    stop_sequence: None,
+                }),
+            ],
+        ]));
+
+        assert_eq!(
+            collect(&mut stream),
+            vec![Ok("Hello".to_string()), Ok(" world".to_string())]
+        );
+    }
+
+    #[test]
+    fn error_terminates_the_stream() {
+        let error = Error {
+            code: ErrorCode::InternalError,
+            message: "failed".to_string(),
+            provider_error_json: None,
+        };
+        let mut stream = TextStream::new(SyntheticChatStream::new(vec![
+            vec![text("Hello"), StreamEvent::Error(error.clone())],
+            vec![text("never seen")],
+        ]));
+
+        assert_eq!(
+            collect(&mut stream),
+            vec![Ok("Hello".to_string()), Err(error)]
+        );
+        assert_eq!(stream.next_text(), Ok(None));
+    }
+}
@@ -0,0 +1,104 @@
+use crate::golem::llm::llm::{StreamDelta, ToolCall, ToolCallFragment};
+
+/// Environment variable making streams send the fragments of the tool calls' arguments as they
+/// are received when set to `true` or `1`, in addition to the complete tool calls
+const STREAM_TOOL_CALL_FRAGMENTS_ENV_VAR_NAME: &str = "GOLEM_LLM_STREAM_TOOL_CALL_FRAGMENTS";
+
+/// Returns whether the streams send the fragments of the tool calls' arguments, as enabled by the
+/// `GOLEM_LLM_STREAM_TOOL_CALL_FRAGMENTS` environment variable
+pub fn is_streaming_tool_call_fragments() -> bool {
+    std::env::var(STREAM_TOOL_CALL_FRAGMENTS_ENV_VAR_NAME)
+        .map(|value| matches!(value.to_lowercase().as_str(), "true" | "1"))
+        .unwrap_or(false)
+}
+
+/// A delta carrying the next fragment of the arguments of a tool call being streamed
+pub fn tool_call_fragment_delta(id: &str, name: &str, arguments_json: String) -> StreamDelta {
+    StreamDelta {
+        content: None,
+        tool_calls: None,
+        annotations: None,
+        tool_call_fragments: Some(vec![ToolCallFragment {
+            id: id.to_string(),
+            name: name.to_string(),
+            arguments_json,
+            is_complete: false,
+        }]),
+        running_usage: None,
+    }
+}
+
+/// A delta carrying a complete tool call. When the fragments are streamed, it also carries an
+/// empty last fragment of the call, marking it complete.
+pub fn complete_tool_call_delta(tool_call: ToolCall, stream_fragments: bool) -> StreamDelta {
+    let tool_call_fragments = stream_fragments.then(|| {
+        vec![ToolCallFragment {
+            id: tool_call.id.clone(),
+            name: tool_call.name.clone(),
+            arguments_json: String::new(),
+            is_complete: true,
+        }]
+    });
+
+    StreamDelta {
+        content: None,
+        tool_calls: Some(vec![tool_call]),
+        annotations: None,
+        tool_call_fragments,
+        running_usage: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::golem::llm::llm::{ToolCall, ToolCallFragment};
+    use crate::tool_call_fragments::{complete_tool_call_delta, tool_call_fragment_delta};
+
+    fn tool_call() -> ToolCall {
+        ToolCall {
+            id: "call_1".to_string(),
+            name: "get_weather".to_string(),
+            arguments_json: r#"{"city":"Budapest"}"#.to_string(),
+        }
+    }
+
+    #[test]
+    fn complete_call_marks_the_last_fragment() {
+        let delta = complete_tool_call_delta(tool_call(), true);
+
+        assert_eq!(delta.tool_calls, Some(vec![tool_call()]));
+        assert_eq!(
+            delta.tool_call_fragments,
+            Some(vec![ToolCallFragment {
+                id: "call_1".to_string(),
+                name: "get_weather".to_string(),
+                arguments_json: String::new(),
+                is_complete: true,
+            }])
+        );
+    }
+
+    #[test]
+    fn complete_call_has_no_fragment_by_default() {
+        let delta = complete_tool_call_delta(tool_call(), false);
+
+        assert_eq!(delta.tool_calls, Some(vec![tool_call()]));
+        assert_eq!(delta.tool_call_fragments, None);
+    }
+
+    #[test]
+    fn fragment_is_not_complete() {
+        let delta = tool_call_fragment_delta("call_1", "get_weather", r#"{"ci"#.to_string());
+
+        assert_eq!(delta.tool_calls, None);
+        assert_eq!(
+            delta.tool_call_fragments,
+            Some(vec![ToolCallFragment {
+                id: "call_1".to_string(),
+                name: "get_weather".to_string(),
+                arguments_json: r#"{"ci"#.to_string(),
+                is_complete: false,
+            }])
+        );
+    }
+}
@@ -0,0 +1,163 @@
+use crate::golem::llm::llm::{ContentPart, Message, Role, ToolResult};
+use log::trace;
+
+/// Approximate number of characters per token of English text, used to estimate the token count
+/// of a message without the provider's tokenizer
+const CHARS_PER_TOKEN: usize = 4;
+
+/// Tokens added by every message for its role and delimiters
+const MESSAGE_OVERHEAD_TOKENS: u32 = 4;
+
+/// Tokens counted for an image, which is the cost of a low detail image with OpenAI
+const IMAGE_TOKENS: u32 = 85;
+
+/// How `truncate_to_budget` makes a conversation fit the token budget
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TruncationStrategy {
+    /// Keeps the leading system message and the most recent messages fitting the budget
+    DropOldest,
+    /// Replaces the dropped messages with a summary. Summarizing is not implemented yet, so for
+    /// now it drops them like `DropOldest`
+    Summarize,
+}
+
+/// Estimates the number of tokens of a message from the length of its text
+pub fn estimate_tokens(message: &Message) -> u32 {
+    message
+        .content
+        .iter()
+        .map(|part| match part {
+            ContentPart::Text(text) => estimate_text_tokens(text),
+            ContentPart::Image(_) => IMAGE_TOKENS,
+            ContentPart::ToolResult((tool_call, tool_result)) => {
+                estimate_text_tokens(&tool_call.arguments_json)
+                    + estimate_text_tokens(match tool_result {
+                        ToolResult::Success(success) => &success.result_json,
+                        ToolResult::Error(error) => &error.error_message,
+                    })
+            }
+            // The reasoning of earlier responses is not sent to the providers
+            ContentPart::Reasoning(_) => 0,
+            // Audio is sent to the providers as its transcript
+            ContentPart::Audio(audio) => audio
+                .transcript
+                .as_deref()
+                .map(estimate_text_tokens)
+                .unwrap_or(0),
+            // Refusals are sent to the providers as text
+            ContentPart::Refusal(refusal) => estimate_text_tokens(refusal),
+        })
+        .sum::<u32>()
+        + MESSAGE_OVERHEAD_TOKENS
+}
+
+/// Estimates the number of tokens of a text from its length
+pub fn estimate_text_tokens(text: &str) -> u32 {
+    text.chars().count().div_ceil(CHARS_PER_TOKEN) as u32
+}
+
+/// Drops messages from a conversation until its estimated token count fits in `max_tokens`,
+/// oldest first. The leading system message is always kept, even if it alone exceeds the budget,
+/// and the kept messages are always the most recent ones, so a message is never skipped to keep
+/// an older one.
+pub fn truncate_to_budget(
+    messages: Vec<Message>,
+    max_tokens: u32,
+    strategy: TruncationStrategy,
+) -> Vec<Message> {
+    if strategy == TruncationStrategy::Summarize {
+        trace!("Summarizing is not supported yet, dropping the oldest messages instead");
+    }
+
+    let mut messages = messages.into_iter().peekable();
+    let system = messages.next_if(|message| message.role == Role::System);
+    let mut remaining = max_tokens.saturating_sub(system.as_ref().map_or(0, estimate_tokens));
+
+    let mut kept = Vec::new();
+    for message in messages.rev() {
+        let tokens = estimate_tokens(&message);
+        if tokens > remaining {
+            break;
+        }
+        remaining -= tokens;
+        kept.push(message);
+    }
+
+    system.into_iter().chain(kept.into_iter().rev()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::golem::llm::llm::{ContentPart, Message, Role};
+    use crate::truncate::{estimate_tokens, truncate_to_budget, TruncationStrategy};
+
+    fn message(role: Role, text: &str) -> Message {
+        Message {
+            role,
+            name: None,
+            content: vec![ContentPart::Text(text.to_string())],
+        }
+    }
+
+    /// A conversation of a system message and four turns, each message estimated as 6 tokens
+    fn conversation() -> Vec<Message> {
+        vec![
+            message(Role::System, "Be brief"),
+            message(Role::User, "Query #1"),
+            message(Role::Assistant, "Reply #1"),
+            message(Role::User, "Query #2"),
+            message(Role::Assistant, "Reply #2"),
+        ]
+    }
+
+    fn texts(messages: &[Message]) -> Vec<&str> {
+        messages
+            .iter()
+            .flat_map(|message| &message.content)
+            .map(|part| match part {
+                ContentPart::Text(text) => text.as_str(),
+                ContentPart::Image(_) => "<image>",
+                ContentPart::ToolResult(_) => "<tool result>",
+                ContentPart::Reasoning(_) => "<reasoning>",
+                ContentPart::Audio(_) => "<audio>",
+                ContentPart::Refusal(_) => "<refusal>",
+            })
+            .collect()
+    }
+
+    #[test]
+    fn tokens_are_estimated_from_the_text_length() {
+        assert_eq!(estimate_tokens(&message(Role::User, "Question1")), 7);
+        assert_eq!(estimate_tokens(&message(Role::User, "")), 4);
+    }
+
+    #[test]
+    fn oldest_messages_are_dropped_first() {
+        let messages = truncate_to_budget(conversation(), 20, TruncationStrategy::DropOldest);
+
+        assert_eq!(texts(&messages), vec!["Be brief", "Query #2", "Reply #2"]);
+    }
+
+    #[test]
+    fn system_message_is_retained() {
+        let messages = truncate_to_budget(conversation(), 5, TruncationStrategy::DropOldest);
+
+        assert_eq!(texts(&messages), vec!["Be brief"]);
+        assert_eq!(messages[0].role, Role::System);
+    }
+
+    #[test]
+    fn conversation_within_the_budget_is_kept() {
+        let messages = truncate_to_budget(conversation(), 100, TruncationStrategy::DropOldest);
+
+        assert_eq!(messages, conversation());
+    }
+
+    #[test]
+    fn summarize_drops_the_oldest_messages_for_now() {
+        assert_eq!(
+            truncate_to_budget(conversation(), 20, TruncationStrategy::Summarize),
+            truncate_to_budget(conversation(), 20, TruncationStrategy::DropOldest)
+        );
+    }
+}
@@ -0,0 +1,300 @@
+use crate::golem::llm::llm::{Error, ErrorCode};
+use crate::proxy::is_excluded;
+use log::trace;
+use reqwest::Url;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// Environment variable listing the only hosts images may be downloaded from, separated by commas,
+/// where `*` allows every host and an entry allows itself and its subdomains
+pub const IMAGE_URL_ALLOWLIST_ENV_VAR_NAME: &str = "GOLEM_LLM_IMAGE_URL_ALLOWLIST";
+
+/// Checks that an image may be downloaded from the URL, before fetching it, failing with an
+/// `invalid-request` error otherwise.
+///
+/// Without the `GOLEM_LLM_IMAGE_URL_ALLOWLIST` environment variable, only `http` and `https`
+/// URLs of public hosts are allowed, so that the URLs of the messages cannot reach the loopback,
+/// private and link-local addresses of the worker's network, like cloud metadata endpoints. With
+/// it, only the listed hosts are allowed, private or not. Host names are not resolved: local names,
+/// like `metadata.google.internal`, and names embedding an internal address, like
+/// `169.254.169.254.nip.io`, are blocked, but other public names pointing to a private address are
+/// not. The URLs an image is redirected to must be checked again before following them.
+pub fn check_image_url(url: &str) -> Result<(), Error> {
+    check_url(url, |name| std::env::var(name).ok())
+}
+
+fn check_url(url: &str, env_var: impl Fn(&str) -> Option<String>) -> Result<(), Error> {
+    let parsed = Url::parse(url).map_err(|err| blocked_url(url, &format!("invalid URL: {err}")))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(blocked_url(
+            url,
+            &format!("the {} scheme is not allowed", parsed.scheme()),
+        ));
+    }
+    let host = parsed
+        .host_str()
+        .map(|host| host.trim_start_matches('[').trim_end_matches(']'))
+        .ok_or_else(|| blocked_url(url, "it has no host"))?;
+
+    let allowlist =
+        env_var(IMAGE_URL_ALLOWLIST_ENV_VAR_NAME).filter(|allowlist| !allowlist.trim().is_empty());
+    match allowlist {
+        Some(allowlist) if is_excluded(host, &allowlist) => {
+            trace!("Allowing image URL of {host}, listed in {IMAGE_URL_ALLOWLIST_ENV_VAR_NAME}");
+            Ok(())
+        }
+        Some(_) => Err(blocked_url(
+            url,
+            &format!("{host} is not listed in {IMAGE_URL_ALLOWLIST_ENV_VAR_NAME}"),
+        )),
+        None if is_internal_host(host) => Err(blocked_url(
+            url,
+            &format!("{host} is a local, private or link-local address"),
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Returns whether the host is a local name, a name embedding an internal IP address, or a
+/// loopback, private, link-local, shared, multicast, reserved or unspecified IP address
+fn is_internal_host(host: &str) -> bool {
+    let host = host.to_ascii_lowercase();
+    let host = host.trim_end_matches('.');
+    match host.parse::<IpAddr>() {
+        Ok(ip) => is_internal_ip(ip),
+        Err(_) => is_local_name(host) || embeds_internal_ip(host),
+    }
+}
+
+/// Returns whether the name can only be resolved inside the worker's network, like `localhost`,
+/// the `metadata.google.internal` metadata endpoint or a single-label name
+fn is_local_name(host: &str) -> bool {
+    !host.contains('.')
+        || [".localhost", ".internal", ".local"]
+            .iter()
+            .any(|suffix| host.ends_with(suffix))
+}
+
+/// Returns whether a label of the name embeds an internal IP address, as the wildcard DNS services
+/// like `nip.io` and `sslip.io` resolve `169.254.169.254.nip.io`, `app-10-0-0-1.nip.io`,
+/// `0a000001.nip.io` or `--1.sslip.io` to the address they contain
+fn embeds_internal_ip(host: &str) -> bool {
+    let labels = host.split('.').collect::<Vec<_>>();
+    let dotted = labels
+        .windows(4)
+        .filter_map(|labels| labels.join(".").parse::<Ipv4Addr>().ok())
+        .map(IpAddr::V4);
+    let dashed = labels.iter().flat_map(|label| label_ips(label));
+    dotted.chain(dashed).any(is_internal_ip)
+}
+
+/// The IP addresses a label may stand for: its last four dash-separated parts as an IPv4 address,
+/// its last part as eight hexadecimal digits, or the label as an IPv6 address with dashes for colons
+fn label_ips(label: &str) -> Vec<IpAddr> {
+    let mut ips = Vec::new();
+    let mut parts = label.rsplitn(5, '-').take(4).collect::<Vec<_>>();
+    parts.reverse();
+    if let Ok(ip) = parts.join(".").parse::<Ipv4Addr>() {
+        ips.push(IpAddr::V4(ip));
+    }
+    let last = label.rsplit('-').next().unwrap_or(label);
+    if last.len() == 8 {
+        if let Ok(bits) = u32::from_str_radix(last, 16) {
+            ips.push(IpAddr::V4(Ipv4Addr::from(bits)));
+        }
+    }
+    if let Ok(ip) = label.replace('-', ":").parse::<Ipv6Addr>() {
+        ips.push(IpAddr::V6(ip));
+    }
+    ips
+}
+
+fn is_internal_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_internal_ipv4(ip),
+        IpAddr::V6(ip) => is_internal_ipv6(ip),
+    }
+}
+
+fn is_internal_ipv4(ip: Ipv4Addr) -> bool {
+    let [first, second, ..] = ip.octets();
+    ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        // The reserved block, 240.0.0.0/4, including the broadcast address
+        || first >= 240
+        // The shared address space of carrier-grade NATs, 100.64.0.0/10
+        || (first == 100 && (second & 0b1100_0000) == 64)
+        // The "this network" block, 0.0.0.0/8
+        || first == 0
+}
+
+fn is_internal_ipv6(ip: Ipv6Addr) -> bool {
+    // IPv4-mapped and IPv4-compatible addresses, ::ffff:0:0/96 and ::/96, including :: and ::1
+    if let Some(ip) = ip.to_ipv4() {
+        return is_internal_ipv4(ip);
+    }
+    let segments = ip.segments();
+    // NAT64 addresses, 64:ff9b::/96, reaching the IPv4 address of their last 32 bits
+    if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+        let bits = (u32::from(segments[6]) << 16) | u32::from(segments[7]);
+        return is_internal_ipv4(Ipv4Addr::from(bits));
+    }
+    let first_segment = segments[0];
+    ip.is_multicast()
+        // Unique local addresses, fc00::/7
+        || (first_segment & 0xfe00) == 0xfc00
+        // Link-local addresses, fe80::/10
+        || (first_segment & 0xffc0) == 0xfe80
+}
+
+fn blocked_url(url: &str, reason: &str) -> Error {
+    Error {
+        code: ErrorCode::InvalidRequest,
+        message: format!("Image URL {url} is blocked: {reason}"),
+        provider_error_json: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::golem::llm::llm::ErrorCode;
+    use crate::url_guard::{check_url, IMAGE_URL_ALLOWLIST_ENV_VAR_NAME};
+
+    fn allowlist(allowlist: Option<&str>) -> impl Fn(&str) -> Option<String> {
+        let allowlist = allowlist.map(str::to_string);
+        move |name| {
+            assert_eq!(name, IMAGE_URL_ALLOWLIST_ENV_VAR_NAME);
+            allowlist.clone()
+        }
+    }
+
+    #[test]
+    fn private_addresses_are_blocked() {
+        for url in [
+            "http://10.0.0.12/image.png",
+            "http://192.168.1.1/image.png",
+            "https://172.16.0.1/image.png",
+            "http://127.0.0.1:8080/image.png",
+            "http://localhost/image.png",
+            "http://[::1]/image.png",
+            "http://[fd00::1]/image.png",
+            "http://[::ffff:10.0.0.1]/image.png",
+            "http://[::10.0.0.1]/image.png",
+            "http://[64:ff9b::10.0.0.1]/image.png",
+            // Decimal form of 127.0.0.1, normalized by the URL parser
+            "http://2130706433/image.png",
+        ] {
+            let error = check_url(url, allowlist(None)).unwrap_err();
+            assert_eq!(error.code, ErrorCode::InvalidRequest, "{url}");
+        }
+    }
+
+    #[test]
+    fn link_local_addresses_are_blocked() {
+        for url in [
+            "http://169.254.169.254/latest/meta-data/",
+            "http://[fe80::1]/image.png",
+            "http://[::a9fe:a9fe]/latest/meta-data/",
+            "http://[::ffff:169.254.169.254]/latest/meta-data/",
+            "http://[64:ff9b::169.254.169.254]/latest/meta-data/",
+        ] {
+            let error = check_url(url, allowlist(None)).unwrap_err();
+            assert_eq!(error.code, ErrorCode::InvalidRequest, "{url}");
+            assert!(error.message.contains("link-local"), "{}", error.message);
+        }
+    }
+
+    #[test]
+    fn multicast_and_reserved_addresses_are_blocked() {
+        for url in [
+            "http://224.0.0.1/image.png",
+            "http://239.255.255.250/image.png",
+            "http://240.0.0.1/image.png",
+            "http://255.255.255.255/image.png",
+            "http://[ff02::1]/image.png",
+        ] {
+            let error = check_url(url, allowlist(None)).unwrap_err();
+            assert_eq!(error.code, ErrorCode::InvalidRequest, "{url}");
+        }
+    }
+
+    #[test]
+    fn local_names_are_blocked() {
+        for url in [
+            "http://metadata.google.internal/computeMetadata/v1/",
+            "http://metadata.google.internal./computeMetadata/v1/",
+            "http://metadata/computeMetadata/v1/",
+            "http://printer.local/image.png",
+            "http://api.localhost/image.png",
+        ] {
+            let error = check_url(url, allowlist(None)).unwrap_err();
+            assert_eq!(error.code, ErrorCode::InvalidRequest, "{url}");
+        }
+    }
+
+    #[test]
+    fn names_embedding_internal_addresses_are_blocked() {
+        for url in [
+            "http://169.254.169.254.nip.io/latest/meta-data/",
+            "http://app.10.0.0.1.nip.io/image.png",
+            "http://app-127-0-0-1.nip.io/image.png",
+            "http://10-0-0-1.sslip.io/image.png",
+            "http://a9fea9fe.nip.io/latest/meta-data/",
+            "http://--1.sslip.io/image.png",
+        ] {
+            let error = check_url(url, allowlist(None)).unwrap_err();
+            assert_eq!(error.code, ErrorCode::InvalidRequest, "{url}");
+        }
+    }
+
+    #[test]
+    fn names_embedding_public_addresses_are_allowed() {
+        for url in [
+            "http://93.184.216.34.nip.io/image.png",
+            "http://app-93-184-216-34.sslip.io/image.png",
+        ] {
+            assert!(check_url(url, allowlist(None)).is_ok(), "{url}");
+        }
+    }
+
+    #[test]
+    fn public_urls_are_allowed() {
+        for url in [
+            "https://example.com/image.png",
+            "http://93.184.216.34/image.png",
+            "https://[2606:2800:220:1::1]/image.png",
+        ] {
+            assert!(check_url(url, allowlist(None)).is_ok(), "{url}");
+        }
+    }
+
+    #[test]
+    fn urls_other_than_http_are_blocked() {
+        for url in ["ftp://example.com/image.png", "file:///etc/passwd"] {
+            let error = check_url(url, allowlist(None)).unwrap_err();
+            assert_eq!(error.code, ErrorCode::InvalidRequest, "{url}");
+        }
+    }
+
+    #[test]
+    fn allowlist_allows_only_the_listed_hosts() {
+        let listed = || allowlist(Some("images.internal.corp, example.com"));
+
+        assert!(check_url("http://images.internal.corp/image.png", listed()).is_ok());
+        assert!(check_url("https://cdn.example.com/image.png", listed()).is_ok());
+        assert_eq!(
+            check_url("https://example.org/image.png", listed())
+                .unwrap_err()
+                .code,
+            ErrorCode::InvalidRequest
+        );
+        assert!(check_url("http://169.254.169.254/", listed()).is_err());
+    }
+
+    #[test]
+    fn wildcard_allowlist_allows_every_host() {
+        assert!(check_url("http://10.0.0.12/image.png", allowlist(Some("*"))).is_ok());
+    }
+}
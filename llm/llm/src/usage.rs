@@ -0,0 +1,192 @@
+use crate::golem::llm::llm::{ContentPart, StreamDelta, Usage};
+use crate::truncate::estimate_text_tokens;
+use log::warn;
+
+/// Environment variable making streams report the output tokens received so far on each delta
+/// when set to `true` or `1`
+const STREAM_RUNNING_USAGE_ENV_VAR_NAME: &str = "GOLEM_LLM_STREAM_RUNNING_USAGE";
+
+/// Returns whether the streams report the running usage on their deltas, as enabled by the
+/// `GOLEM_LLM_STREAM_RUNNING_USAGE` environment variable
+pub fn is_streaming_running_usage() -> bool {
+    std::env::var(STREAM_RUNNING_USAGE_ENV_VAR_NAME)
+        .map(|value| matches!(value.to_lowercase().as_str(), "true" | "1"))
+        .unwrap_or(false)
+}
+
+/// Completes the token usage reported by a provider. The total is computed from the input and
+/// output token counts when the provider only reports those, and a reported total which is not
+/// their sum is kept as is, but logged as a warning.
+pub fn with_total_tokens(usage: Usage) -> Usage {
+    match (usage.input_tokens, usage.output_tokens, usage.total_tokens) {
+        (Some(input_tokens), Some(output_tokens), None) => Usage {
+            total_tokens: Some(input_tokens.saturating_add(output_tokens)),
+            ..usage
+        },
+        (Some(input_tokens), Some(output_tokens), Some(total_tokens)) => {
+            if is_inconsistent(input_tokens, output_tokens, total_tokens) {
+                warn!(
+                    "Inconsistent token usage reported by the provider: {input_tokens} input and {output_tokens} output tokens, but {total_tokens} tokens in total"
+                );
+            }
+            usage
+        }
+        _ => usage,
+    }
+}
+
+/// Tracks the output tokens of a response being streamed. The last count reported by the provider
+/// on a delta's `running_usage` is used when there is one, adding the estimated tokens of the
+/// text received since then, and the tokens of the whole text are estimated otherwise. The count
+/// never decreases, even if the provider reports fewer tokens than estimated.
+#[derive(Default)]
+pub struct RunningUsage {
+    text: String,
+    reported: Option<(u32, usize)>,
+    output_tokens: u32,
+}
+
+impl RunningUsage {
+    /// Sets the output tokens received so far, including the delta, as its `running_usage`
+    pub fn with_running_usage(&mut self, mut delta: StreamDelta) -> StreamDelta {
+        let output_tokens = self.record(&delta);
+        delta.running_usage = Some(Usage {
+            input_tokens: None,
+            output_tokens: Some(output_tokens),
+            total_tokens: None,
+        });
+        delta
+    }
+
+    /// Records the delta, returning the output tokens received so far, including it
+    pub fn record(&mut self, delta: &StreamDelta) -> u32 {
+        for part in delta.content.iter().flatten() {
+            if let ContentPart::Text(text) = part {
+                self.text.push_str(text);
+            }
+        }
+        if let Some(output_tokens) = delta
+            .running_usage
+            .as_ref()
+            .and_then(|usage| usage.output_tokens)
+        {
+            self.reported = Some((output_tokens, self.text.len()));
+        }
+
+        let estimated_tokens = match self.reported {
+            Some((output_tokens, text_len)) => {
+                output_tokens.saturating_add(estimate_text_tokens(&self.text[text_len..]))
+            }
+            None => estimate_text_tokens(&self.text),
+        };
+        self.output_tokens = self.output_tokens.max(estimated_tokens);
+        self.output_tokens
+    }
+
+    /// The output tokens received so far
+    pub fn output_tokens(&self) -> u32 {
+        self.output_tokens
+    }
+}
+
+fn is_inconsistent(input_tokens: u32, output_tokens: u32, total_tokens: u32) -> bool {
+    input_tokens.saturating_add(output_tokens) != total_tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::golem::llm::llm::{ContentPart, StreamDelta, Usage};
+    use crate::usage::{is_inconsistent, with_total_tokens, RunningUsage};
+
+    fn usage(
+        input_tokens: Option<u32>,
+        output_tokens: Option<u32>,
+        total_tokens: Option<u32>,
+    ) -> Usage {
+        Usage {
+            input_tokens,
+            output_tokens,
+            total_tokens,
+        }
+    }
+
+    #[test]
+    fn missing_total_is_computed() {
+        assert_eq!(
+            with_total_tokens(usage(Some(25), Some(15), None)),
+            usage(Some(25), Some(15), Some(40))
+        );
+    }
+
+    #[test]
+    fn inconsistent_total_is_kept_and_detected() {
+        assert_eq!(
+            with_total_tokens(usage(Some(25), Some(15), Some(42))),
+            usage(Some(25), Some(15), Some(42))
+        );
+        assert!(is_inconsistent(25, 15, 42));
+        assert!(!is_inconsistent(25, 15, 40));
+    }
+
+    #[test]
+    fn total_is_not_computed_from_partial_counts() {
+        assert_eq!(
+            with_total_tokens(usage(Some(25), None, None)),
+            usage(Some(25), None, None)
+        );
+        assert_eq!(
+            with_total_tokens(usage(None, None, Some(40))),
+            usage(None, None, Some(40))
+        );
+    }
+
+    fn delta(text: &str, running_usage: Option<Usage>) -> StreamDelta {
+        StreamDelta {
+            content: Some(vec![ContentPart::Text(text.to_string())]),
+            tool_calls: None,
+            annotations: None,
+            tool_call_fragments: None,
+            running_usage,
+        }
+    }
+
+    fn running_output_tokens(running_usage: &mut RunningUsage, delta: StreamDelta) -> u32 {
+        running_usage
+            .with_running_usage(delta)
+            .running_usage
+            .and_then(|usage| usage.output_tokens)
+            .unwrap()
+    }
+
+    #[test]
+    fn running_usage_increases_with_the_received_text() {
+        let mut running_usage = RunningUsage::default();
+
+        let counts: Vec<u32> = ["Hello", ", world", "", "! How are you today?"]
+            .into_iter()
+            .map(|text| running_output_tokens(&mut running_usage, delta(text, None)))
+            .collect();
+
+        assert_eq!(counts, vec![2, 3, 3, 8]);
+    }
+
+    #[test]
+    fn running_usage_continues_from_the_provider_counts() {
+        let mut running_usage = RunningUsage::default();
+
+        let counts = vec![
+            running_output_tokens(&mut running_usage, delta("Hello", None)),
+            running_output_tokens(
+                &mut running_usage,
+                delta(", world", Some(usage(None, Some(4), None))),
+            ),
+            running_output_tokens(&mut running_usage, delta("! How", None)),
+            running_output_tokens(
+                &mut running_usage,
+                delta("", Some(usage(None, Some(3), None))),
+            ),
+        ];
+
+        assert_eq!(counts, vec![2, 4, 6, 6]);
+    }
+}
@@ -0,0 +1,239 @@
+use golem_llm::chat_completions::client::{
+    ChatCompletionsEndpoint, CompletionsRequestBody, Tool, Usage,
+};
+use reqwest::RequestBuilder;
+use serde::{Deserialize, Serialize};
+
+const BASE_URL: &str = "https://api.mistral.ai";
+
+/// The Chat Completions API of Mistral, authenticated with a bearer API key
+pub struct MistralApi {
+    api_key: String,
+}
+
+impl MistralApi {
+    pub fn new(api_key: String) -> Self {
+        Self { api_key }
+    }
+}
+
+impl ChatCompletionsEndpoint for MistralApi {
+    const API_NAME: &'static str = "Mistral API";
+
+    fn base_url(&self) -> &str {
+        BASE_URL
+    }
+
+    fn completions_url(&self, _model: &str) -> String {
+        format!("{BASE_URL}/v1/chat/completions")
+    }
+
+    fn models_url(&self) -> String {
+        format!("{BASE_URL}/v1/models")
+    }
+
+    fn authenticate(&self, request: RequestBuilder) -> RequestBuilder {
+        request.bearer_auth(&self.api_key)
+    }
+}
+
+/// The Chat Completions API client for creating model responses.
+pub type CompletionsApi = golem_llm::chat_completions::client::CompletionsApi<MistralApi>;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompletionsRequest {
+    pub messages: Vec<Message>,
+    pub model: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub n: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub random_seed: Option<u32>,
+    /// Injects Mistral's safety prompt before the conversation
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub safe_prompt: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop: Option<Vec<String>>,
+    pub stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_choice: Option<ToolChoice>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tools: Vec<Tool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+}
+
+impl CompletionsRequestBody for CompletionsRequest {
+    fn model(&self) -> &str {
+        &self.model
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ToolChoice {
+    /// `auto`, `none`, `any` or `required`
+    Mode(String),
+    Function(ToolChoiceFunction),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum ToolChoiceFunction {
+    #[serde(rename = "function")]
+    Function { function: FunctionName },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FunctionName {
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "role")]
+pub enum Message {
+    #[serde(rename = "system")]
+    System { content: Content },
+    #[serde(rename = "user")]
+    User { content: Content },
+    #[serde(rename = "assistant")]
+    Assistant {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        content: Option<Content>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        tool_calls: Option<Vec<ToolCall>>,
+        /// Marks the message as the prefix of the response, which the model continues
+        #[serde(skip_serializing_if = "std::ops::Not::not", default)]
+        prefix: bool,
+    },
+    #[serde(rename = "tool")]
+    Tool {
+        content: String,
+        tool_call_id: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        name: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Content {
+    Text(String),
+    List(Vec<ContentPart>),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum ContentPart {
+    #[serde(rename = "text")]
+    TextInput { text: String },
+    /// Mistral takes the image URL, or a base64 data URL, directly as a string
+    #[serde(rename = "image_url")]
+    ImageInput { image_url: String },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolCall {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub typ: Option<String>,
+    pub function: FunctionCall,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub index: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FunctionCall {
+    pub name: String,
+    /// Mistral usually returns the arguments as a JSON string, but can return them as an object
+    pub arguments: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompletionsResponse {
+    pub id: String,
+    pub created: u64,
+    pub model: String,
+    pub choices: Vec<Choice>,
+    pub usage: Option<Usage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Choice {
+    pub index: u32,
+    pub message: ResponseMessage,
+    pub finish_reason: Option<FinishReason>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum FinishReason {
+    #[serde(rename = "stop")]
+    Stop,
+    #[serde(rename = "length")]
+    Length,
+    #[serde(rename = "model_length")]
+    ModelLength,
+    #[serde(rename = "error")]
+    Error,
+    #[serde(rename = "tool_calls")]
+    ToolCalls,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResponseMessage {
+    pub content: Option<ResponseContent>,
+    pub tool_calls: Option<Vec<ToolCall>>,
+}
+
+/// Content of a response, which is a list of chunks for reasoning models
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ResponseContent {
+    Text(String),
+    Chunks(Vec<ResponseContentChunk>),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum ResponseContentChunk {
+    #[serde(rename = "text")]
+    Text { text: String },
+    /// Reasoning of the reasoning models preceding their answer, made of text chunks
+    #[serde(rename = "thinking")]
+    Thinking { thinking: Vec<ResponseContentChunk> },
+    #[serde(other)]
+    Other,
+}
+
+/// Streamed chunk of a response. Unlike OpenAI, Mistral sends each tool call whole in a single
+/// chunk, and the usage in the chunk carrying the finish reason.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatCompletionChunk {
+    pub id: String,
+    pub created: u64,
+    pub model: String,
+    pub choices: Vec<ChoiceChunk>,
+    pub usage: Option<Usage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChoiceChunk {
+    pub index: u32,
+    pub delta: ChoiceDelta,
+    pub finish_reason: Option<FinishReason>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChoiceDelta {
+    pub content: Option<ResponseContent>,
+    pub tool_calls: Option<Vec<ToolCall>>,
+    pub role: Option<String>,
+}
@@ -0,0 +1,556 @@
+use crate::client::{
+    CompletionsRequest, CompletionsResponse, FunctionName, ResponseContent, ResponseContentChunk,
+    ToolChoice, ToolChoiceFunction,
+};
+use base64::{engine::general_purpose, Engine as _};
+use golem_llm::capabilities::{
+    check_model_capabilities, check_streaming_support, ModelCapabilities,
+};
+use golem_llm::chat_completions::conversions::{convert_usage, tool_definition_to_tool};
+use golem_llm::collect::push_content_part;
+use golem_llm::config::model_or_default;
+use golem_llm::conversions::{check_provider_options, split_tool_results, validate_temperature};
+use golem_llm::golem::llm::llm::{
+    ChatEvent, CompleteResponse, Config, ContentPart, Error, ErrorCode, FinishReason,
+    ImageReference, Message, ResponseMetadata, Role, ToolCall, ToolResult,
+};
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+
+/// Range of temperatures accepted by Mistral
+const TEMPERATURE_RANGE: RangeInclusive<f32> = 0.0..=1.5;
+
+/// Provider options read by the Mistral conversions, any other key is reported as unknown
+const PROVIDER_OPTIONS: [&str; 6] = [
+    "frequency_penalty",
+    "n",
+    "presence_penalty",
+    "seed",
+    "safe_prompt",
+    "top_p",
+];
+
+/// Capabilities of the known Mistral models, requests to other models are not validated
+const MODEL_CAPABILITIES: [(&str, ModelCapabilities); 9] = [
+    ("pixtral-large-latest", ModelCapabilities::ALL),
+    ("pixtral-12b-2409", ModelCapabilities::ALL),
+    ("mistral-medium-latest", ModelCapabilities::ALL),
+    ("mistral-small-latest", ModelCapabilities::ALL),
+    ("mistral-large-latest", ModelCapabilities::TEXT_ONLY),
+    ("codestral-latest", ModelCapabilities::TEXT_ONLY),
+    ("open-mistral-nemo", ModelCapabilities::TEXT_ONLY),
+    ("ministral-8b-latest", ModelCapabilities::TEXT_ONLY),
+    ("ministral-3b-latest", ModelCapabilities::TEXT_ONLY),
+];
+
+pub fn messages_to_request(
+    messages: Vec<Message>,
+    config: Config,
+) -> Result<CompletionsRequest, Error> {
+    let options = config
+        .provider_options
+        .into_iter()
+        .map(|kv| (kv.key, kv.value))
+        .collect::<HashMap<_, _>>();
+    check_provider_options(&options, &PROVIDER_OPTIONS)?;
+
+    let model = model_or_default(config.model)?;
+    check_model_capabilities(&MODEL_CAPABILITIES, &model, &messages, &config.tools)?;
+
+    let mut completion_messages = Vec::new();
+    for message in messages {
+        // Tool results included in the messages are sent as a tool call and its result
+        let (content, tool_results) = split_tool_results(message.content, normalize_tool_call_id)?;
+        if !content.is_empty() || tool_results.is_empty() {
+            match message.role {
+                Role::System => completion_messages.push(crate::client::Message::System {
+                    content: convert_content_parts(content),
+                }),
+                // A Mistral tool message needs the id of the tool call it answers, which only tool
+                // results have, so the other tool messages are sent as user input
+                Role::User | Role::Tool => completion_messages.push(crate::client::Message::User {
+                    content: convert_content_parts(content),
+                }),
+                Role::Assistant => completion_messages.push(crate::client::Message::Assistant {
+                    content: Some(convert_content_parts(content)),
+                    tool_calls: None,
+                    prefix: false,
+                }),
+            }
+        }
+        completion_messages.extend(tool_results_to_messages(tool_results));
+    }
+
+    mark_prefix(&mut completion_messages)?;
+
+    let mut tools = Vec::new();
+    for tool in config.tools {
+        tools.push(tool_definition_to_tool(tool)?)
+    }
+
+    Ok(CompletionsRequest {
+        messages: completion_messages,
+        model,
+        frequency_penalty: options
+            .get("frequency_penalty")
+            .and_then(|fp_s| fp_s.parse::<f32>().ok()),
+        max_tokens: config.max_tokens,
+        n: options.get("n").and_then(|n_s| n_s.parse::<u32>().ok()),
+        presence_penalty: options
+            .get("presence_penalty")
+            .and_then(|pp_s| pp_s.parse::<f32>().ok()),
+        random_seed: options
+            .get("seed")
+            .and_then(|seed_s| seed_s.parse::<u32>().ok()),
+        safe_prompt: options
+            .get("safe_prompt")
+            .and_then(|safe_prompt_s| safe_prompt_s.parse::<bool>().ok()),
+        stop: config.stop_sequences,
+        stream: false,
+        temperature: validate_temperature(config.temperature, TEMPERATURE_RANGE)?,
+        tool_choice: config.tool_choice.map(convert_tool_choice),
+        tools,
+        top_p: options
+            .get("top_p")
+            .and_then(|top_p_s| top_p_s.parse::<f32>().ok()),
+    })
+}
+
+/// Fails if the model is known not to support streaming
+pub fn check_streaming(model: &str) -> Result<(), Error> {
+    check_streaming_support(&MODEL_CAPABILITIES, model)
+}
+
+/// A trailing assistant message is a prefill, sent with Mistral's `prefix` flag so the model
+/// continues it. Only a single trailing assistant message is allowed.
+fn mark_prefix(messages: &mut [crate::client::Message]) -> Result<(), Error> {
+    let trailing_assistant_messages = messages
+        .iter()
+        .rev()
+        .take_while(|message| matches!(message, crate::client::Message::Assistant { .. }))
+        .count();
+
+    if trailing_assistant_messages > 1 {
+        return Err(Error {
+            code: ErrorCode::InvalidRequest,
+            message: "Only the last message can be an assistant prefill".to_string(),
+            provider_error_json: None,
+        });
+    }
+
+    if let Some(crate::client::Message::Assistant { prefix, .. }) = messages.last_mut() {
+        *prefix = true;
+    }
+
+    Ok(())
+}
+
+pub fn process_response(response: CompletionsResponse) -> ChatEvent {
+    let choice = response.choices.first();
+    if let Some(choice) = choice {
+        let mut contents = Vec::new();
+        let mut tool_calls = Vec::new();
+
+        if let Some(content) = &choice.message.content {
+            contents.extend(convert_response_content(content.clone()));
+        }
+
+        let empty = Vec::new();
+        for tool_call in choice.message.tool_calls.as_ref().unwrap_or(&empty) {
+            tool_calls.push(convert_tool_call(tool_call));
+        }
+
+        if contents.is_empty() && !tool_calls.is_empty() {
+            ChatEvent::ToolRequest(tool_calls)
+        } else {
+            let metadata = ResponseMetadata {
+                finish_reason: choice.finish_reason.as_ref().map(convert_finish_reason),
+                usage: response.usage.as_ref().map(convert_usage),
+                provider_id: None,
+                model: Some(response.model.clone()),
+                timestamp: Some(response.created.to_string()),
+                provider_metadata_json: None,
+                tokens_per_second: None,
+                stop_sequence: None,
+            };
+
+            ChatEvent::Message(CompleteResponse {
+                id: response.id,
+                content: contents,
+                tool_calls,
+                metadata,
+                annotations: vec![],
+            })
+        }
+    } else {
+        ChatEvent::Error(Error {
+            code: ErrorCode::InternalError,
+            message: "No choices in response".to_string(),
+            provider_error_json: None,
+        })
+    }
+}
+
+/// Length of the tool call ids accepted by Mistral
+const TOOL_CALL_ID_LENGTH: usize = 9;
+
+/// Mistral only accepts tool call ids of 9 letters and digits. Other ids, like the ones of other
+/// providers, are replaced by 9 letters and digits derived from their FNV-1a hash, so an id is
+/// always replaced the same way, including when a durable worker is replayed.
+pub fn normalize_tool_call_id(id: &str) -> Option<String> {
+    if id.is_empty() {
+        None
+    } else if id.len() == TOOL_CALL_ID_LENGTH && id.chars().all(|c| c.is_ascii_alphanumeric()) {
+        Some(id.to_string())
+    } else {
+        const ALPHABET: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
+
+        let mut hash = id.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        });
+        let mut normalized = String::with_capacity(TOOL_CALL_ID_LENGTH);
+        for _ in 0..TOOL_CALL_ID_LENGTH {
+            normalized.push(ALPHABET[(hash % ALPHABET.len() as u64) as usize] as char);
+            hash /= ALPHABET.len() as u64;
+        }
+        Some(normalized)
+    }
+}
+
+pub fn tool_results_to_messages(
+    tool_results: Vec<(ToolCall, ToolResult)>,
+) -> Vec<crate::client::Message> {
+    let mut messages = Vec::new();
+    for (tool_call, tool_result) in tool_results {
+        messages.push(crate::client::Message::Assistant {
+            content: None,
+            tool_calls: Some(vec![crate::client::ToolCall {
+                id: Some(tool_call.id.clone()),
+                typ: Some("function".to_string()),
+                function: crate::client::FunctionCall {
+                    name: tool_call.name.clone(),
+                    arguments: serde_json::Value::String(tool_call.arguments_json),
+                },
+                index: None,
+            }]),
+            prefix: false,
+        });
+        let content = match tool_result {
+            ToolResult::Success(success) => success.result_json,
+            ToolResult::Error(failure) => failure.error_message,
+        };
+        messages.push(crate::client::Message::Tool {
+            content,
+            tool_call_id: tool_call.id,
+            name: Some(tool_call.name),
+        });
+    }
+    messages
+}
+
+pub fn convert_tool_call(tool_call: &crate::client::ToolCall) -> ToolCall {
+    ToolCall {
+        id: tool_call.id.clone().unwrap_or_default(),
+        name: tool_call.function.name.clone(),
+        arguments_json: match &tool_call.function.arguments {
+            serde_json::Value::String(arguments) => arguments.clone(),
+            arguments => arguments.to_string(),
+        },
+    }
+}
+
+/// Converts the content of a response or a streamed delta. The thinking chunks of reasoning
+/// models become reasoning parts, kept apart from the text and in the order they were received,
+/// while the other non-text chunks are dropped.
+pub fn convert_response_content(content: ResponseContent) -> Vec<ContentPart> {
+    match content {
+        ResponseContent::Text(text) if text.is_empty() => vec![],
+        ResponseContent::Text(text) => vec![ContentPart::Text(text)],
+        ResponseContent::Chunks(chunks) => {
+            let mut content = Vec::new();
+            for chunk in chunks {
+                match chunk {
+                    ResponseContentChunk::Text { text } => {
+                        push_content_part(&mut content, ContentPart::Text(text))
+                    }
+                    ResponseContentChunk::Thinking { thinking } => {
+                        let reasoning = chunks_text(thinking);
+                        if !reasoning.is_empty() {
+                            push_content_part(&mut content, ContentPart::Reasoning(reasoning));
+                        }
+                    }
+                    ResponseContentChunk::Other => {}
+                }
+            }
+            content
+        }
+    }
+}
+
+/// The text of the text chunks, such as the ones a thinking chunk is made of
+fn chunks_text(chunks: Vec<ResponseContentChunk>) -> String {
+    chunks
+        .into_iter()
+        .filter_map(|chunk| match chunk {
+            ResponseContentChunk::Text { text } => Some(text),
+            _ => None,
+        })
+        .collect()
+}
+
+fn convert_content_parts(contents: Vec<ContentPart>) -> crate::client::Content {
+    let mut result = Vec::new();
+    for content in contents {
+        match content {
+            ContentPart::Text(text) => result.push(crate::client::ContentPart::TextInput { text }),
+            // Mistral takes the image as a plain URL string, which has no room for the detail
+            ContentPart::Image(image_reference) => match image_reference {
+                ImageReference::Url(image_url) => {
+                    result.push(crate::client::ContentPart::ImageInput {
+                        image_url: image_url.url,
+                    })
+                }
+                ImageReference::Inline(image_source) => {
+                    let base64_data = general_purpose::STANDARD.encode(&image_source.data);
+                    result.push(crate::client::ContentPart::ImageInput {
+                        image_url: format!(
+                            "data:{};base64,{}",
+                            image_source.mime_type, base64_data
+                        ),
+                    });
+                }
+            },
+            // Tool results are sent in messages of their own by `messages_to_request`
+            ContentPart::ToolResult(_) => {}
+            // The reasoning of earlier responses is not sent back to the model
+            ContentPart::Reasoning(_) => {}
+            // Audio responses are sent back to the model as their transcript
+            ContentPart::Audio(audio) => result.extend(
+                audio
+                    .transcript
+                    .map(|text| crate::client::ContentPart::TextInput { text }),
+            ),
+            // Refusals of earlier responses are sent back to the model as text
+            ContentPart::Refusal(text) => {
+                result.push(crate::client::ContentPart::TextInput { text })
+            }
+        }
+    }
+    crate::client::Content::List(result)
+}
+
+fn convert_tool_choice(tool_choice: String) -> ToolChoice {
+    match tool_choice.as_str() {
+        "auto" | "none" | "any" | "required" => ToolChoice::Mode(tool_choice),
+        _ => ToolChoice::Function(ToolChoiceFunction::Function {
+            function: FunctionName { name: tool_choice },
+        }),
+    }
+}
+
+pub fn convert_finish_reason(value: &crate::client::FinishReason) -> FinishReason {
+    match value {
+        crate::client::FinishReason::Stop => FinishReason::Stop,
+        crate::client::FinishReason::Length => FinishReason::Length,
+        crate::client::FinishReason::ModelLength => FinishReason::Length,
+        crate::client::FinishReason::Error => FinishReason::Error,
+        crate::client::FinishReason::ToolCalls => FinishReason::ToolCalls,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::{CompletionsResponse, ResponseContent};
+    use crate::conversions::{
+        convert_response_content, messages_to_request, normalize_tool_call_id, process_response,
+    };
+    use golem_llm::golem::llm::llm::{
+        ChatEvent, Config, ContentPart, ErrorCode, ImageReference, ImageUrl, Kv, Message, Role,
+        ToolCall,
+    };
+
+    fn config(provider_options: Vec<Kv>) -> Config {
+        Config {
+            model: "mistral-large-latest".to_string(),
+            temperature: None,
+            max_tokens: None,
+            stop_sequences: None,
+            tools: vec![],
+            tool_choice: None,
+            provider_options,
+            extra_headers: vec![],
+            service_tier: None,
+            metadata: vec![],
+            reasoning_effort: None,
+            store: None,
+            prompt_cache_key: None,
+            stream_options: None,
+            deadline_ms: None,
+            extra_body: None,
+        }
+    }
+
+    fn message(role: Role, text: &str) -> Message {
+        Message {
+            role,
+            name: None,
+            content: vec![ContentPart::Text(text.to_string())],
+        }
+    }
+
+    #[test]
+    fn trailing_assistant_message_is_sent_as_prefix() {
+        let request = messages_to_request(
+            vec![
+                message(Role::User, "Hi"),
+                message(Role::Assistant, "Hello! "),
+                message(Role::User, "What is the capital of France?"),
+                message(Role::Assistant, "{\"capital\":"),
+            ],
+            config(vec![]),
+        )
+        .unwrap();
+
+        let body = serde_json::to_value(&request).unwrap();
+        assert!(body["messages"][1].get("prefix").is_none());
+        assert_eq!(body["messages"][3]["prefix"], true);
+    }
+
+    #[test]
+    fn only_one_trailing_prefix_is_allowed() {
+        let error = messages_to_request(
+            vec![
+                message(Role::User, "Hi"),
+                message(Role::Assistant, "Hello"),
+                message(Role::Assistant, "!"),
+            ],
+            config(vec![]),
+        )
+        .unwrap_err();
+
+        assert_eq!(error.code, ErrorCode::InvalidRequest);
+    }
+
+    #[test]
+    fn safe_prompt_is_mapped_from_provider_options() {
+        let request = messages_to_request(
+            vec![message(Role::User, "Hi")],
+            config(vec![Kv {
+                key: "safe_prompt".to_string(),
+                value: "true".to_string(),
+            }]),
+        )
+        .unwrap();
+        assert_eq!(request.safe_prompt, Some(true));
+
+        let request = messages_to_request(vec![message(Role::User, "Hi")], config(vec![])).unwrap();
+        let body = serde_json::to_value(&request).unwrap();
+        assert!(body.get("safe_prompt").is_none());
+    }
+
+    #[test]
+    fn images_are_sent_as_url_strings() {
+        let request = messages_to_request(
+            vec![Message {
+                role: Role::User,
+                name: None,
+                content: vec![ContentPart::Image(ImageReference::Url(ImageUrl {
+                    url: "https://example.com/cat.png".to_string(),
+                    detail: None,
+                }))],
+            }],
+            Config {
+                model: "pixtral-12b-2409".to_string(),
+                ..config(vec![])
+            },
+        )
+        .unwrap();
+
+        let body = serde_json::to_value(&request).unwrap();
+        assert_eq!(
+            body["messages"][0]["content"],
+            serde_json::json!([{"type": "image_url", "image_url": "https://example.com/cat.png"}])
+        );
+    }
+
+    #[test]
+    fn tool_call_arguments_are_returned_as_json_string() {
+        let response: CompletionsResponse = serde_json::from_str(
+            r#"{
+                "id": "cmpl-e5cc70bb28c444948073e77776eb30ef",
+                "object": "chat.completion",
+                "model": "mistral-large-latest",
+                "created": 1746000000,
+                "choices": [{
+                    "index": 0,
+                    "message": {
+                        "role": "assistant",
+                        "content": "",
+                        "tool_calls": [{
+                            "id": "D681PevKs",
+                            "function": {"name": "get_weather", "arguments": {"city": "Paris"}}
+                        }]
+                    },
+                    "finish_reason": "tool_calls"
+                }],
+                "usage": {"prompt_tokens": 80, "completion_tokens": 20, "total_tokens": 100}
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            process_response(response),
+            ChatEvent::ToolRequest(vec![ToolCall {
+                id: "D681PevKs".to_string(),
+                name: "get_weather".to_string(),
+                arguments_json: "{\"city\":\"Paris\"}".to_string(),
+            }])
+        );
+    }
+
+    #[test]
+    fn thinking_chunks_are_converted_to_reasoning_in_order() {
+        let content: ResponseContent = serde_json::from_str(
+            r#"[
+                {"type": "thinking", "thinking": [{"type": "text", "text": "The user asks for 17 * 3."}]},
+                {"type": "thinking", "thinking": [{"type": "text", "text": " That is 51."}]},
+                {"type": "text", "text": "17 * 3 is 51."},
+                {"type": "thinking", "thinking": [{"type": "text", "text": "Checking: 50 + 1 = 51."}]},
+                {"type": "text", "text": " Confirmed."}
+            ]"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            convert_response_content(content),
+            vec![
+                ContentPart::Reasoning("The user asks for 17 * 3. That is 51.".to_string()),
+                ContentPart::Text("17 * 3 is 51.".to_string()),
+                ContentPart::Reasoning("Checking: 50 + 1 = 51.".to_string()),
+                ContentPart::Text(" Confirmed.".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn mistral_tool_call_ids_are_kept() {
+        assert_eq!(
+            normalize_tool_call_id("D681PevKs"),
+            Some("D681PevKs".to_string())
+        );
+    }
+
+    #[test]
+    fn other_tool_call_ids_are_replaced_by_their_hash() {
+        let normalized = normalize_tool_call_id("call_abc123").unwrap();
+
+        assert_eq!(normalized.len(), 9);
+        assert!(normalized.chars().all(|c| c.is_ascii_alphanumeric()));
+        assert_eq!(
+            normalize_tool_call_id("call_abc123"),
+            Some(normalized.clone())
+        );
+        assert_ne!(normalize_tool_call_id("call_abc124"), Some(normalized));
+        assert_eq!(normalize_tool_call_id(""), None);
+    }
+}
@@ -0,0 +1,503 @@
+mod client;
+mod conversions;
+
+use crate::client::{ChatCompletionChunk, CompletionsApi, CompletionsRequest, MistralApi};
+use crate::conversions::{
+    check_streaming, convert_finish_reason, convert_response_content, convert_tool_call,
+    messages_to_request, normalize_tool_call_id, process_response, tool_results_to_messages,
+};
+use golem_llm::batch::send_batch;
+use golem_llm::chat_completions::conversions::convert_usage;
+use golem_llm::chat_stream::{request_deadline, LlmChatStream, LlmChatStreamState};
+use golem_llm::config::with_config_key;
+use golem_llm::conversions::{check_messages_not_empty, normalize_tool_call_ids};
+use golem_llm::dry_run::{send_or_dry_run, stream_or_dry_run};
+use golem_llm::durability::{DurableLLM, ExtendedGuest};
+use golem_llm::error::unsupported;
+use golem_llm::event_source::EventSource;
+use golem_llm::extra_body::extra_body;
+use golem_llm::golem::llm::llm::{
+    ChatEvent, ChatStream, Config, ContentPart, EmbeddingConfig, EmbeddingResult, Error, Guest,
+    ImageGenConfig, ImageGenResult, Message, ProviderCapabilities, ProviderHealth, RerankConfig,
+    RerankResult, ResponseMetadata, StreamDelta, StreamEvent, ToolCall, ToolResult, Usage,
+};
+use golem_llm::headers::extra_headers;
+use golem_llm::rate_limit::with_rate_limit;
+use golem_llm::LOGGING_STATE;
+use golem_rust::wasm_rpc::Pollable;
+use log::trace;
+use reqwest::header::HeaderMap;
+use std::cell::{Ref, RefCell, RefMut};
+
+struct MistralChatStream {
+    stream: RefCell<Option<EventSource>>,
+    failure: Option<Error>,
+    finished: RefCell<bool>,
+    /// The finish reason and usage of the last chunk, sent as a finish event at the end of the
+    /// stream after the content and tool calls of that chunk
+    finish: RefCell<Option<ResponseMetadata>>,
+}
+
+impl MistralChatStream {
+    pub fn new(stream: EventSource) -> LlmChatStream<Self> {
+        LlmChatStream::new(MistralChatStream {
+            stream: RefCell::new(Some(stream)),
+            failure: None,
+            finished: RefCell::new(false),
+            finish: RefCell::new(None),
+        })
+    }
+
+    pub fn failed(error: Error) -> LlmChatStream<Self> {
+        LlmChatStream::new(MistralChatStream {
+            stream: RefCell::new(None),
+            failure: Some(error),
+            finished: RefCell::new(false),
+            finish: RefCell::new(None),
+        })
+    }
+
+    pub fn dry_run(events: Vec<StreamEvent>) -> LlmChatStream<Self> {
+        LlmChatStream::from_events(
+            MistralChatStream {
+                stream: RefCell::new(None),
+                failure: None,
+                finished: RefCell::new(false),
+                finish: RefCell::new(None),
+            },
+            events,
+        )
+    }
+}
+
+impl LlmChatStreamState for MistralChatStream {
+    fn failure(&self) -> &Option<Error> {
+        &self.failure
+    }
+
+    fn is_finished(&self) -> bool {
+        *self.finished.borrow()
+    }
+
+    fn set_finished(&self) {
+        *self.finished.borrow_mut() = true;
+    }
+
+    fn stream(&self) -> Ref<Option<EventSource>> {
+        self.stream.borrow()
+    }
+
+    fn stream_mut(&self) -> RefMut<Option<EventSource>> {
+        self.stream.borrow_mut()
+    }
+
+    fn pending_finish(&self) -> Option<ResponseMetadata> {
+        self.finish.borrow().clone()
+    }
+
+    fn decode_message(&self, _event: &str, raw: &str) -> Result<Option<StreamEvent>, String> {
+        trace!("Received raw stream event: {raw}");
+        let json: serde_json::Value = serde_json::from_str(raw)
+            .map_err(|err| format!("Failed to deserialize stream event: {err}"))?;
+
+        let typ = json
+            .as_object()
+            .and_then(|obj| obj.get("object"))
+            .and_then(|v| v.as_str());
+        match typ {
+            Some("chat.completion.chunk") => {
+                let message: ChatCompletionChunk = serde_json::from_value(json)
+                    .map_err(|err| format!("Failed to parse stream event: {err}"))?;
+                let choice = message.choices.into_iter().next();
+                let finish_reason = choice
+                    .as_ref()
+                    .and_then(|choice| choice.finish_reason.as_ref())
+                    .map(convert_finish_reason);
+
+                // The finish reason and the usage come with the last chunk, which can also carry
+                // the last content or a whole tool call, so the finish event is only sent at the
+                // end of the stream, after the delta of the chunk
+                if finish_reason.is_some() || message.usage.is_some() {
+                    let mut finish = self.finish.borrow_mut();
+                    let finish = finish.get_or_insert_with(|| ResponseMetadata {
+                        finish_reason: None,
+                        usage: None,
+                        provider_id: None,
+                        model: None,
+                        timestamp: None,
+                        provider_metadata_json: None,
+                        tokens_per_second: None,
+                        stop_sequence: None,
+                    });
+                    if finish_reason.is_some() {
+                        finish.finish_reason = finish_reason;
+                    }
+                    if let Some(usage) = &message.usage {
+                        finish.usage = Some(convert_usage(usage));
+                    }
+                    finish.model = Some(message.model);
+                    finish.timestamp = Some(message.created.to_string());
+                }
+
+                if let Some(choice) = choice {
+                    Ok(Some(StreamEvent::Delta(StreamDelta {
+                        content: choice.delta.content.map(convert_response_content),
+                        tool_calls: choice
+                            .delta
+                            .tool_calls
+                            .map(|calls| calls.iter().map(convert_tool_call).collect()),
+                        annotations: None,
+                        tool_call_fragments: None,
+                        running_usage: None,
+                    })))
+                } else {
+                    Ok(None)
+                }
+            }
+            Some(_) => Ok(None),
+            None => Err("Unexpected stream event format, does not have 'object' field".to_string()),
+        }
+    }
+}
+
+struct MistralComponent;
+
+impl MistralComponent {
+    const ENV_VAR_NAME: &'static str = "MISTRAL_API_KEY";
+
+    fn request(client: CompletionsApi, request: CompletionsRequest) -> ChatEvent {
+        send_or_dry_run(request, |request| match client.send_messages(request) {
+            Ok(response) => with_rate_limit(process_response(response.body), response.rate_limit),
+            Err(err) => ChatEvent::Error(err),
+        })
+    }
+
+    fn streaming_request(
+        client: CompletionsApi,
+        mut request: CompletionsRequest,
+    ) -> LlmChatStream<MistralChatStream> {
+        if let Err(err) = check_streaming(&request.model) {
+            return MistralChatStream::failed(err);
+        }
+        request.stream = true;
+        stream_or_dry_run(request, MistralChatStream::dry_run, |request| match client
+            .stream_send_messages(request)
+        {
+            Ok(stream) => MistralChatStream::new(stream),
+            Err(err) => MistralChatStream::failed(err),
+        })
+    }
+}
+
+impl Guest for MistralComponent {
+    type ChatStream = LlmChatStream<MistralChatStream>;
+
+    fn send(messages: Vec<Message>, config: Config) -> ChatEvent {
+        LOGGING_STATE.with_borrow_mut(|state| state.init());
+
+        if let Err(err) = check_messages_not_empty(&messages, &[]) {
+            return ChatEvent::Error(err);
+        }
+
+        let extra_headers = match extra_headers(&config) {
+            Ok(extra_headers) => extra_headers,
+            Err(err) => return ChatEvent::Error(err),
+        };
+        let extra_body = match extra_body(&config) {
+            Ok(extra_body) => extra_body,
+            Err(err) => return ChatEvent::Error(err),
+        };
+
+        with_config_key(Self::ENV_VAR_NAME, ChatEvent::Error, |mistral_api_key| {
+            let client = match CompletionsApi::new(MistralApi::new(mistral_api_key), extra_headers)
+                .map(|client| client.with_extra_body(extra_body))
+            {
+                Ok(client) => client,
+                Err(err) => return ChatEvent::Error(err),
+            };
+
+            match messages_to_request(messages, config) {
+                Ok(request) => Self::request(client, request),
+                Err(err) => ChatEvent::Error(err),
+            }
+        })
+    }
+
+    fn continue_(
+        messages: Vec<Message>,
+        tool_results: Vec<(ToolCall, ToolResult)>,
+        config: Config,
+    ) -> ChatEvent {
+        LOGGING_STATE.with_borrow_mut(|state| state.init());
+
+        if let Err(err) = check_messages_not_empty(&messages, &tool_results) {
+            return ChatEvent::Error(err);
+        }
+
+        let extra_headers = match extra_headers(&config) {
+            Ok(extra_headers) => extra_headers,
+            Err(err) => return ChatEvent::Error(err),
+        };
+        let extra_body = match extra_body(&config) {
+            Ok(extra_body) => extra_body,
+            Err(err) => return ChatEvent::Error(err),
+        };
+
+        let tool_results = match normalize_tool_call_ids(tool_results, normalize_tool_call_id) {
+            Ok(tool_results) => tool_results,
+            Err(err) => return ChatEvent::Error(err),
+        };
+
+        with_config_key(Self::ENV_VAR_NAME, ChatEvent::Error, |mistral_api_key| {
+            let client = match CompletionsApi::new(MistralApi::new(mistral_api_key), extra_headers)
+                .map(|client| client.with_extra_body(extra_body))
+            {
+                Ok(client) => client,
+                Err(err) => return ChatEvent::Error(err),
+            };
+
+            match messages_to_request(messages, config) {
+                Ok(mut request) => {
+                    request
+                        .messages
+                        .extend(tool_results_to_messages(tool_results));
+                    Self::request(client, request)
+                }
+                Err(err) => ChatEvent::Error(err),
+            }
+        })
+    }
+
+    fn send_batch(requests: Vec<(Vec<Message>, Config)>, abort_on_error: bool) -> Vec<ChatEvent> {
+        send_batch(requests, abort_on_error, Self::send)
+    }
+
+    fn stream(messages: Vec<Message>, config: Config) -> ChatStream {
+        ChatStream::new(Self::unwrapped_stream(messages, config))
+    }
+
+    fn continue_stream(
+        messages: Vec<Message>,
+        tool_results: Vec<(ToolCall, ToolResult)>,
+        config: Config,
+    ) -> ChatStream {
+        ChatStream::new(Self::unwrapped_continue_stream(
+            messages,
+            tool_results,
+            config,
+        ))
+    }
+
+    fn send_raw(body_json: String, config: Config) -> Result<String, Error> {
+        LOGGING_STATE.with_borrow_mut(|state| state.init());
+
+        let extra_headers = extra_headers(&config)?;
+        with_config_key(Self::ENV_VAR_NAME, Err, |mistral_api_key| {
+            CompletionsApi::new(MistralApi::new(mistral_api_key), extra_headers)?
+                .send_raw(&config.model, body_json)
+        })
+    }
+
+    fn rerank(
+        _query: String,
+        _documents: Vec<String>,
+        _config: RerankConfig,
+    ) -> Result<RerankResult, Error> {
+        Err(unsupported("rerank"))
+    }
+
+    fn embed(
+        _inputs: Vec<ContentPart>,
+        _config: EmbeddingConfig,
+    ) -> Result<EmbeddingResult, Error> {
+        Err(unsupported("embed"))
+    }
+
+    fn ping() -> Result<ProviderHealth, Error> {
+        LOGGING_STATE.with_borrow_mut(|state| state.init());
+
+        with_config_key(Self::ENV_VAR_NAME, Err, |mistral_api_key| {
+            CompletionsApi::new(MistralApi::new(mistral_api_key), HeaderMap::new())?.ping()
+        })
+    }
+
+    fn capabilities() -> ProviderCapabilities {
+        ProviderCapabilities {
+            provider: "mistral".to_string(),
+            streaming: true,
+            tools: true,
+            vision: true,
+            embeddings: false,
+            json_schema: false,
+        }
+    }
+
+    fn warm(_config: Config) -> Result<(), Error> {
+        Err(unsupported("warm"))
+    }
+
+    fn generate_image(_prompt: String, _config: ImageGenConfig) -> Result<ImageGenResult, Error> {
+        Err(unsupported("generate-image"))
+    }
+}
+
+impl ExtendedGuest for MistralComponent {
+    fn unwrapped_stream(
+        messages: Vec<Message>,
+        config: Config,
+    ) -> LlmChatStream<MistralChatStream> {
+        Self::unwrapped_continue_stream(messages, vec![], config)
+    }
+
+    fn unwrapped_continue_stream(
+        messages: Vec<Message>,
+        tool_results: Vec<(ToolCall, ToolResult)>,
+        config: Config,
+    ) -> LlmChatStream<MistralChatStream> {
+        LOGGING_STATE.with_borrow_mut(|state| state.init());
+
+        if let Err(err) = check_messages_not_empty(&messages, &tool_results) {
+            return MistralChatStream::failed(err);
+        }
+
+        let extra_headers = match extra_headers(&config) {
+            Ok(extra_headers) => extra_headers,
+            Err(err) => return MistralChatStream::failed(err),
+        };
+        let extra_body = match extra_body(&config) {
+            Ok(extra_body) => extra_body,
+            Err(err) => return MistralChatStream::failed(err),
+        };
+        let deadline = request_deadline(&config);
+
+        let tool_results = match normalize_tool_call_ids(tool_results, normalize_tool_call_id) {
+            Ok(tool_results) => tool_results,
+            Err(err) => return MistralChatStream::failed(err),
+        };
+
+        with_config_key(
+            Self::ENV_VAR_NAME,
+            MistralChatStream::failed,
+            |mistral_api_key| {
+                let client =
+                    match CompletionsApi::new(MistralApi::new(mistral_api_key), extra_headers)
+                        .map(|client| client.with_extra_body(extra_body))
+                    {
+                        Ok(client) => client,
+                        Err(err) => return MistralChatStream::failed(err),
+                    };
+
+                match messages_to_request(messages, config) {
+                    Ok(mut request) => {
+                        request
+                            .messages
+                            .extend(tool_results_to_messages(tool_results));
+                        Self::streaming_request(client, request).with_deadline(deadline)
+                    }
+                    Err(err) => MistralChatStream::failed(err),
+                }
+            },
+        )
+    }
+
+    fn subscribe(stream: &Self::ChatStream) -> Pollable {
+        stream.subscribe()
+    }
+
+    fn subscribe_deadline(stream: &Self::ChatStream) -> Option<Pollable> {
+        stream.subscribe_deadline()
+    }
+
+    fn usage_so_far(stream: &Self::ChatStream) -> Option<Usage> {
+        stream.usage_so_far()
+    }
+}
+
+type DurableMistralComponent = DurableLLM<MistralComponent>;
+
+golem_llm::export_llm!(DurableMistralComponent with_types_in golem_llm);
+
+#[cfg(test)]
+mod tests {
+    use crate::{MistralChatStream, MistralComponent};
+    use golem_llm::chat_stream::LlmChatStream;
+    use golem_llm::event_source::StreamFormat;
+    use golem_llm::golem::llm::llm::{
+        ContentPart, FinishReason, Guest, ProviderCapabilities, StreamEvent, ToolCall, Usage,
+    };
+    use std::cell::RefCell;
+
+    fn decode_transcript(transcript: &str) -> Vec<StreamEvent> {
+        LlmChatStream::new(MistralChatStream {
+            stream: RefCell::new(None),
+            failure: None,
+            finished: RefCell::new(false),
+            finish: RefCell::new(None),
+        })
+        .decode_transcript(transcript, StreamFormat::EventStream)
+    }
+
+    #[test]
+    fn last_chunk_is_sent_before_the_finish() {
+        let events = decode_transcript(include_str!("../fixtures/tool_call.sse"));
+
+        let (finish, deltas) = events.split_last().unwrap();
+        let deltas = deltas
+            .iter()
+            .map(|event| match event {
+                StreamEvent::Delta(delta) => delta.clone(),
+                event => panic!("Expected a delta event, got {event:?}"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            deltas
+                .iter()
+                .flat_map(|delta| delta.content.clone().unwrap_or_default())
+                .collect::<Vec<_>>(),
+            vec![ContentPart::Text("Let me check the weather.".to_string())]
+        );
+        assert_eq!(
+            deltas
+                .iter()
+                .flat_map(|delta| delta.tool_calls.clone().unwrap_or_default())
+                .collect::<Vec<_>>(),
+            vec![ToolCall {
+                id: "D681PevKs".to_string(),
+                name: "get_weather".to_string(),
+                arguments_json: r#"{"city": "Paris"}"#.to_string(),
+            }]
+        );
+
+        match finish {
+            StreamEvent::Finish(metadata) => {
+                assert_eq!(metadata.finish_reason, Some(FinishReason::ToolCalls));
+                assert_eq!(
+                    metadata.usage,
+                    Some(Usage {
+                        input_tokens: Some(90),
+                        output_tokens: Some(22),
+                        total_tokens: Some(112),
+                    })
+                );
+                assert_eq!(metadata.model, Some("mistral-small-latest".to_string()));
+                assert_eq!(metadata.timestamp, Some("1750000000".to_string()));
+            }
+            event => panic!("Expected a finish event, got {event:?}"),
+        }
+    }
+
+    #[test]
+    fn capabilities_describe_the_provider() {
+        assert_eq!(
+            MistralComponent::capabilities(),
+            ProviderCapabilities {
+                provider: "mistral".to_string(),
+                streaming: true,
+                tools: true,
+                vision: true,
+                embeddings: false,
+                json_schema: false,
+            }
+        );
+    }
+}
@@ -2,40 +2,67 @@ use std::{fmt::Debug, fs, path::Path};
 
 use base64::{engine::general_purpose, Engine};
 use golem_llm::{
-    error::{error_code_from_status, from_event_source_error},
-    event_source::EventSource,
-    golem::llm::llm::{Error, ErrorCode},
+    body::read_body,
+    config::config_value,
+    error::{
+        context_length_exceeded, decode_success_body, error_code_from_status,
+        from_event_source_error, from_reqwest_error, is_context_length_exceeded,
+    },
+    event_source::{EventSource, StreamFormat, StreamFormatHint},
+    extra_body::ExtraBody,
+    golem::llm::llm::{Error, ErrorCode, ProviderHealth},
+    health::probe,
+    proxy::{http_client, shared_client},
+    rate_limit::{rate_limit_info, RateLimited},
+    raw::send_raw,
+    retry::send_with_connection_retry,
+    url_guard::check_image_url,
 };
 use log::trace;
 use reqwest::{
-    header::{HeaderMap, HeaderValue, CONTENT_TYPE},
-    Client, Method, Response, StatusCode,
+    header::{HeaderMap, HeaderValue, CONTENT_TYPE, LOCATION},
+    Client, Method, RequestBuilder, Response, StatusCode,
 };
 
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use url::Url;
 
+const BASE_URL_ENV_VAR_NAME: &str = "GOLEM_OLLAMA_BASE_URL";
+
+const DEFAULT_BASE_URL: &str = "http://localhost:11434";
+
 pub struct OllamaApi {
     default_model: String,
     base_url: String,
+    extra_headers: HeaderMap,
+    /// Fields added to the chat request bodies
+    extra_body: ExtraBody,
     client: Client,
 }
 
 impl OllamaApi {
-    pub fn new(default_model: String) -> Self {
-        let base_url =
-            std::env::var("GOLEM_OLLAMA_BASE_URL").unwrap_or("http://localhost:11434".to_string());
-        let client = Client::builder()
-            .build()
-            .expect("Failed to initialize HTTP client");
-        Self {
+    pub fn new(default_model: String, extra_headers: HeaderMap) -> Result<Self, Error> {
+        let base_url = config_value(BASE_URL_ENV_VAR_NAME).unwrap_or(DEFAULT_BASE_URL.to_string());
+        let client = http_client(&base_url)?;
+        Ok(Self {
             default_model,
             base_url,
+            extra_headers,
+            extra_body: ExtraBody::default(),
             client,
-        }
+        })
     }
 
-    pub fn send_chat(&self, params: CompletionsRequest) -> Result<CompletionsResponse, Error> {
+    /// Adds the `extra_body` of the config to the chat requests
+    pub fn with_extra_body(mut self, extra_body: ExtraBody) -> Self {
+        self.extra_body = extra_body;
+        self
+    }
+
+    pub fn send_chat(
+        &self,
+        params: CompletionsRequest,
+    ) -> Result<RateLimited<CompletionsResponse>, Error> {
         trace!("Sending request to Ollama API: {params:?}");
 
         let mut modified_params = params;
@@ -43,20 +70,25 @@ impl OllamaApi {
         if modified_params.model.is_none() {
             modified_params.model = Some(self.default_model.clone())
         };
+        let body = self.extra_body.merge(&modified_params)?;
 
         let mut headers = HeaderMap::new();
         headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
 
         let url = format!("{}/api/chat", self.base_url);
-        let response: Response = self
-            .client
-            .request(Method::POST, url)
-            .headers(headers)
-            .json(&modified_params)
-            .send()
-            .map_err(|err| from_reqwest_error("Request failed", err))?;
-
+        let response: Response = send_with_connection_retry(|| {
+            self.client
+                .request(Method::POST, &url)
+                .headers(headers.clone())
+                .headers(self.extra_headers.clone())
+                .json(&body)
+                .send()
+        })
+        .map_err(|err| from_reqwest_error("Request failed", err))?;
+
+        let rate_limit = rate_limit_info(response.headers());
         handle_response::<CompletionsResponse>(response)
+            .map(|body| RateLimited { body, rate_limit })
     }
 
     pub fn send_chat_stream(&self, params: CompletionsRequest) -> Result<EventSource, Error> {
@@ -68,11 +100,7 @@ impl OllamaApi {
             modified_params.model = Some(self.default_model.clone())
         };
 
-        let json_body = serde_json::to_string(&modified_params).map_err(|e| Error {
-            code: ErrorCode::InternalError,
-            message: format!("Failed to serialize request body: {e}"),
-            provider_error_json: None,
-        })?;
+        let json_body = self.extra_body.merge(&modified_params)?.to_string();
 
         let mut headers = HeaderMap::new();
         headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
@@ -83,12 +111,42 @@ impl OllamaApi {
             .client
             .request(Method::POST, url)
             .headers(headers)
+            .headers(self.extra_headers.clone())
             .body(json_body)
             .send()
             .map_err(|err| from_reqwest_error("Request failed", err))?;
-        EventSource::new(response)
+        EventSource::new(response, StreamFormatHint::Force(StreamFormat::NdJson))
             .map_err(|err| from_event_source_error("Failed to create EventSource stream", err))
     }
+
+    /// Loads the model of a request without messages, see `warm_request`
+    pub fn warm(&self, params: CompletionsRequest) -> Result<(), Error> {
+        trace!("Warming up the model of the Ollama API: {params:?}");
+
+        self.send_chat(params).map(|_| ())
+    }
+
+    /// Sends the JSON body untouched to the chat endpoint, returning the raw response body
+    pub fn send_raw(&self, body_json: String) -> Result<String, Error> {
+        send_raw(
+            self.client
+                .request(Method::POST, format!("{}/api/chat", self.base_url)),
+            self.extra_headers.clone(),
+            body_json,
+        )
+    }
+
+    /// Checks the connectivity by querying the version of the Ollama server
+    pub fn ping(&self) -> Result<ProviderHealth, Error> {
+        trace!("Checking the connectivity of the Ollama API");
+
+        probe(|| {
+            self.client
+                .request(Method::GET, format!("{}/api/version", self.base_url))
+                .headers(self.extra_headers.clone())
+                .send()
+        })
+    }
 }
 
 /// GenerateOptions is Options for generating completions
@@ -175,6 +233,11 @@ pub struct CompletionsRequest {
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub keep_alive: Option<String>,
+
+    /// Number of images left out of the request because they could not be loaded, reported in
+    /// the provider metadata of the response
+    #[serde(skip)]
+    pub dropped_images: u32,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -281,25 +344,18 @@ pub fn handle_response<T: DeserializeOwned + Debug>(response: Response) -> Resul
 
     match status {
         StatusCode::OK => {
-            let raw_body = response
-                .text()
-                .map_err(|err| from_reqwest_error("Failed to receive response body", err))?;
+            let raw_body = read_body(response, "Failed to receive response body")?;
 
-            match serde_json::from_str::<T>(&raw_body) {
-                Ok(body) => Ok(body),
-                Err(err) => Err(Error {
-                    code: ErrorCode::InternalError,
-                    message: format!("Failed to parse response body: {err}"),
-                    provider_error_json: Some(raw_body),
-                }),
-            }
+            decode_success_body(raw_body)
         }
         _ => {
-            let raw_error_body = response
-                .text()
-                .map_err(|err| from_reqwest_error("Failed to receive error response body", err))?;
+            let raw_error_body = read_body(response, "Failed to receive error response body")?;
             trace!("Received {status} response from OpenRouter API: {raw_error_body:?}");
 
+            if let Ok(OllamaError { error }) = serde_json::from_str(&raw_error_body) {
+                return Err(error_from_message(status, error, raw_error_body));
+            }
+
             let error_body: OllamaRequestError =
                 serde_json::from_str(&raw_error_body).map_err(|err| Error {
                     code: ErrorCode::InternalError,
@@ -316,12 +372,33 @@ pub fn handle_response<T: DeserializeOwned + Debug>(response: Response) -> Resul
     }
 }
 
-pub fn image_to_base64(source: &str) -> Result<String, Box<dyn std::error::Error>> {
-    let bytes = if Url::parse(source).is_ok() {
-        let client = Client::new();
-        let response = client.get(source).send()?;
+/// Error body of the Ollama API, which only has a message
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OllamaError {
+    pub error: String,
+}
+
+/// Converts the message of a failed response, reporting prompts exceeding the context window as
+/// `context-length-exceeded`. Ollama does not name the context window in its message.
+fn error_from_message(status: StatusCode, message: String, raw_error_body: String) -> Error {
+    if is_context_length_exceeded(&message) {
+        context_length_exceeded(&message, None, Some(raw_error_body))
+    } else {
+        Error {
+            code: error_code_from_status(status),
+            message,
+            provider_error_json: Some(raw_error_body),
+        }
+    }
+}
 
-        response.bytes()?.to_vec()
+/// Loads an image from a base64 `data:` URL, from a URL, downloaded with the shared HTTP client
+/// unless blocked by [`check_image_url`], or from a file
+pub fn image_to_base64(source: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let bytes = if let Some(data_url) = source.strip_prefix(DATA_URL_PREFIX) {
+        decode_data_url(data_url)?
+    } else if Url::parse(source).is_ok() {
+        download_image(source)?
     } else {
         let path = Path::new(source);
 
@@ -332,10 +409,219 @@ pub fn image_to_base64(source: &str) -> Result<String, Box<dyn std::error::Error
     Ok(base64_data)
 }
 
-pub fn from_reqwest_error(context: &str, err: reqwest::Error) -> Error {
-    Error {
-        code: ErrorCode::InternalError,
-        message: format!("{}: {}", context, err),
-        provider_error_json: None,
+/// Maximum number of redirects followed when downloading an image
+const MAX_IMAGE_REDIRECTS: usize = 5;
+
+/// Downloads an image, following its redirects only to the URLs allowed by [`check_image_url`]
+fn download_image(url: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut current_url = url.to_string();
+    for _ in 0..=MAX_IMAGE_REDIRECTS {
+        let response = image_request(&current_url)
+            .map_err(|err| err.message)?
+            .send()?;
+        if !response.status().is_redirection() {
+            return Ok(response.bytes()?.to_vec());
+        }
+        current_url = redirect_target(&current_url, response.headers().get(LOCATION))?;
+        trace!("Following the redirect of image {url} to {current_url}");
+    }
+    Err(format!("Image URL {url} is redirected more than {MAX_IMAGE_REDIRECTS} times").into())
+}
+
+/// Starts the download of an image from a URL allowed by [`check_image_url`], with the shared
+/// HTTP client
+fn image_request(url: &str) -> Result<RequestBuilder, Error> {
+    check_image_url(url)?;
+    Ok(shared_client()?.get(url))
+}
+
+/// Resolves the `Location` header of a redirect against the URL it redirects from
+fn redirect_target(url: &str, location: Option<&HeaderValue>) -> Result<String, String> {
+    let location = location
+        .and_then(|location| location.to_str().ok())
+        .ok_or_else(|| format!("Image URL {url} is redirected without a valid Location header"))?;
+    Url::parse(url)
+        .and_then(|url| url.join(location))
+        .map(String::from)
+        .map_err(|err| format!("Image URL {url} is redirected to an invalid URL {location}: {err}"))
+}
+
+const DATA_URL_PREFIX: &str = "data:";
+
+/// Decodes the image of a data URL given without its `data:` prefix, like
+/// `image/png;base64,iVBORw0KGgo...`. Only base64 encoded images are accepted.
+fn decode_data_url(data_url: &str) -> Result<Vec<u8>, String> {
+    let (media_type, data) = data_url
+        .split_once(',')
+        .ok_or("Data URL without a comma before its data")?;
+    let mime_type = media_type
+        .strip_suffix(";base64")
+        .ok_or("Data URL is not base64 encoded")?;
+    if !mime_type.starts_with("image/") {
+        return Err(format!("Data URL of type {mime_type:?} is not an image"));
+    }
+
+    general_purpose::STANDARD
+        .decode(data)
+        .map_err(|err| format!("Data URL has invalid base64 data: {err}"))
+}
+
+/// Names the source of an image in logs and errors, without the content of data URLs
+pub fn image_source_name(source: &str) -> &str {
+    if source.starts_with(DATA_URL_PREFIX) {
+        "data URL"
+    } else {
+        source
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::{
+        error_from_message, image_request, image_source_name, image_to_base64, redirect_target,
+        OllamaApi,
+    };
+    use golem_llm::golem::llm::llm::ErrorCode;
+    use golem_llm::proxy::{built_clients, shared_client};
+    use reqwest::header::{HeaderMap, HeaderValue};
+    use reqwest::StatusCode;
+
+    #[test]
+    fn images_are_downloaded_with_the_shared_client() {
+        OllamaApi::new("llama3.2".to_string(), HeaderMap::new()).unwrap();
+        let built = built_clients();
+
+        image_request("https://example.com/image.png").unwrap();
+
+        assert_eq!(built_clients(), built);
+    }
+
+    #[test]
+    fn images_of_internal_addresses_are_not_downloaded() {
+        shared_client().unwrap();
+        let built = built_clients();
+
+        let error = image_to_base64("http://169.254.169.254/latest/meta-data/").unwrap_err();
+
+        assert!(error.to_string().contains("is blocked"), "{error}");
+        assert_eq!(built_clients(), built);
+    }
+
+    #[test]
+    fn redirects_are_resolved_against_the_redirected_url() {
+        let url = "https://example.com/images/cat.png";
+
+        assert_eq!(
+            redirect_target(url, Some(&HeaderValue::from_static("../cats/1.png"))).unwrap(),
+            "https://example.com/cats/1.png"
+        );
+        assert_eq!(
+            redirect_target(
+                url,
+                Some(&HeaderValue::from_static("https://cdn.example.com/1.png"))
+            )
+            .unwrap(),
+            "https://cdn.example.com/1.png"
+        );
+        assert!(redirect_target(url, None).is_err());
+    }
+
+    #[test]
+    fn redirects_to_internal_addresses_are_not_followed() {
+        let location = HeaderValue::from_static("http://169.254.169.254/latest/meta-data/");
+        let target = redirect_target("https://example.com/image.png", Some(&location)).unwrap();
+
+        let error = image_request(&target).unwrap_err();
+
+        assert_eq!(error.code, ErrorCode::InvalidRequest);
+        assert!(error.message.contains("is blocked"), "{}", error.message);
+    }
+
+    #[test]
+    fn data_url_images_are_decoded_without_being_downloaded() {
+        shared_client().unwrap();
+        let built = built_clients();
+
+        let image = image_to_base64("data:image/png;base64,iVBORw0KGgo=").unwrap();
+
+        assert_eq!(image, "iVBORw0KGgo=");
+        assert_eq!(built_clients(), built);
+    }
+
+    #[test]
+    fn malformed_data_urls_are_rejected() {
+        for (url, message) in [
+            (
+                "data:image/png;base64",
+                "Data URL without a comma before its data",
+            ),
+            (
+                "data:image/png,iVBORw0KGgo=",
+                "Data URL is not base64 encoded",
+            ),
+            (
+                "data:text/plain;base64,SGVsbG8=",
+                "Data URL of type \"text/plain\" is not an image",
+            ),
+        ] {
+            assert_eq!(image_to_base64(url).unwrap_err().to_string(), message);
+        }
+
+        assert!(image_to_base64("data:image/png;base64,not base64!")
+            .unwrap_err()
+            .to_string()
+            .starts_with("Data URL has invalid base64 data"));
+    }
+
+    #[test]
+    fn images_are_read_from_files() {
+        let path = std::env::temp_dir().join("golem-llm-ollama-image.png");
+        std::fs::write(&path, [1, 2, 3]).unwrap();
+
+        let image = image_to_base64(path.to_str().unwrap());
+
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(image.unwrap(), "AQID");
+    }
+
+    #[test]
+    fn data_urls_are_not_named_by_their_content() {
+        assert_eq!(
+            image_source_name("data:image/png;base64,iVBORw0KGgo="),
+            "data URL"
+        );
+        assert_eq!(
+            image_source_name("https://example.com/image.png"),
+            "https://example.com/image.png"
+        );
+    }
+
+    #[test]
+    fn context_length_error_is_detected_in_the_message() {
+        let raw_error_body = r#"{"error":"the input length exceeds the context length"}"#;
+
+        let error = error_from_message(
+            StatusCode::BAD_REQUEST,
+            "the input length exceeds the context length".to_string(),
+            raw_error_body.to_string(),
+        );
+
+        assert_eq!(error.code, ErrorCode::ContextLengthExceeded);
+        assert_eq!(error.provider_error_json, Some(raw_error_body.to_string()));
+    }
+
+    #[test]
+    fn other_errors_keep_their_message() {
+        let error = error_from_message(
+            StatusCode::NOT_FOUND,
+            "model \"llama9\" not found, try pulling it first".to_string(),
+            r#"{"error":"model \"llama9\" not found, try pulling it first"}"#.to_string(),
+        );
+
+        assert_eq!(error.code, ErrorCode::InvalidRequest);
+        assert_eq!(
+            error.message,
+            "model \"llama9\" not found, try pulling it first"
+        );
     }
 }
@@ -1,16 +1,114 @@
 use std::collections::HashMap;
+use std::ops::RangeInclusive;
 
 use crate::client::{
-    image_to_base64, CompletionsRequest, CompletionsResponse, FunctionTool, MessageRequest,
-    MessageRole, OllamaModelOptions, Tool,
+    image_source_name, image_to_base64, CompletionsRequest, CompletionsResponse, FunctionTool,
+    MessageRequest, MessageRole, OllamaModelOptions, Tool,
 };
 use base64::{engine::general_purpose, Engine};
+use golem_llm::capabilities::{
+    check_model_capabilities, check_streaming_support, ModelCapabilities,
+};
+use golem_llm::config::model_or_default;
+use golem_llm::conversions::{
+    check_provider_options, non_empty_tool_call_id, split_tool_results, validate_temperature,
+    TextJoin, TEXT_JOIN_OPTION,
+};
 use golem_llm::golem::llm::llm::{
     ChatEvent, CompleteResponse, Config, ContentPart, Error, ErrorCode, FinishReason,
     ImageReference, Message, ResponseMetadata, Role, ToolCall as golem_llm_ToolCall, ToolResult,
     Usage,
 };
-use log::trace;
+use golem_llm::usage::with_total_tokens;
+use log::{trace, warn};
+
+/// Range of temperatures accepted by Ollama
+const TEMPERATURE_RANGE: RangeInclusive<f32> = 0.0..=f32::MAX;
+
+/// Provider option selecting what happens when an image of a message cannot be loaded, `fail`
+/// (the default) or `skip`
+const ON_IMAGE_ERROR_OPTION: &str = "on_image_error";
+
+/// Provider option overriding the Ollama role of the messages of each role, as a JSON object
+/// like `{"tool": "user"}`
+const ROLE_MAP_OPTION: &str = "role_map";
+
+/// Model name prefixes of the models whose chat template supports the `tool` role. The tool
+/// messages of the other models are sent as user messages.
+const TOOL_ROLE_MODEL_PREFIXES: [&str; 10] = [
+    "llama3.1",
+    "llama3.2",
+    "llama3.3",
+    "llama4",
+    "qwen2.5",
+    "qwen3",
+    "mistral-nemo",
+    "mistral-small",
+    "command-r",
+    "gpt-oss",
+];
+
+/// Provider options read by the Ollama conversions, any other key is reported as unknown
+const PROVIDER_OPTIONS: [&str; 27] = [
+    TEXT_JOIN_OPTION,
+    ON_IMAGE_ERROR_OPTION,
+    ROLE_MAP_OPTION,
+    "min_p",
+    "top_p",
+    "top_k",
+    "num_predict",
+    "repeat_penalty",
+    "num_ctx",
+    "seed",
+    "mirostat",
+    "mirostat_eta",
+    "mirostat_tau",
+    "num_gpu",
+    "num_thread",
+    "penalize_newline",
+    "num_keep",
+    "typical_p",
+    "repeat_last_n",
+    "presence_penalty",
+    "frequency_penalty",
+    "numa",
+    "num_batch",
+    "main_gpu",
+    "use_mmap",
+    "format",
+    "keep_alive",
+];
+
+/// Capabilities of the known Ollama models by name, without their tag. Requests to other models
+/// are not validated.
+const MODEL_CAPABILITIES: [(&str, ModelCapabilities); 12] = [
+    ("llama3.1", ModelCapabilities::TEXT_ONLY),
+    ("llama3.2", ModelCapabilities::TEXT_ONLY),
+    ("llama3.3", ModelCapabilities::TEXT_ONLY),
+    ("qwen2.5", ModelCapabilities::TEXT_ONLY),
+    ("qwen3", ModelCapabilities::TEXT_ONLY),
+    ("mistral-nemo", ModelCapabilities::TEXT_ONLY),
+    ("llava", VISION_MODEL_CAPABILITIES),
+    ("llama3.2-vision", VISION_MODEL_CAPABILITIES),
+    ("gemma3", VISION_MODEL_CAPABILITIES),
+    ("llama3", TEXT_MODEL_CAPABILITIES),
+    ("llama2", TEXT_MODEL_CAPABILITIES),
+    ("gemma2", TEXT_MODEL_CAPABILITIES),
+];
+
+/// Vision models whose chat template does not support tools
+const VISION_MODEL_CAPABILITIES: ModelCapabilities = ModelCapabilities {
+    vision: true,
+    tools: false,
+    streaming: true,
+};
+
+/// Models whose chat template supports neither images nor tools
+const TEXT_MODEL_CAPABILITIES: ModelCapabilities = ModelCapabilities {
+    vision: false,
+    tools: false,
+    streaming: true,
+};
 
 pub fn messages_to_request(
     messages: Vec<Message>,
@@ -22,56 +120,36 @@ pub fn messages_to_request(
         .into_iter()
         .map(|kv| (kv.key, kv.value))
         .collect::<HashMap<_, _>>();
+    check_provider_options(&options, &PROVIDER_OPTIONS)?;
 
+    let model = model_or_default(config.model)?;
+    check_model_capabilities(
+        &MODEL_CAPABILITIES,
+        model_name(&model),
+        &messages,
+        &config.tools,
+    )?;
+    let text_join = TextJoin::from_options(&options);
+    let on_image_error = ImageErrorPolicy::from_options(&options)?;
+    let role_map = RoleMap::from_options(&model, &options)?;
+    let mut dropped_images = 0;
+    // System messages are kept in their original position, as Ollama accepts them anywhere in
+    // the conversation
     let mut request_message = Vec::new();
 
     for message in messages {
-        let message_role = match message.role {
-            Role::Assistant => MessageRole::Assistant,
-            Role::System => MessageRole::System,
-            Role::User => MessageRole::User,
-            Role::Tool => MessageRole::User, // Ollama treats tool results as user input
-        };
-
-        let mut message_content = String::new();
-        let mut attached_image = Vec::new();
-
-        for content_part in message.content {
-            match content_part {
-                ContentPart::Text(text) => {
-                    if !message_content.is_empty() {
-                        message_content.push('\n');
-                    }
-                    message_content.push_str(&text);
-                }
-                ContentPart::Image(reference) => match reference {
-                    ImageReference::Url(image_url) => {
-                        let url = &image_url.url;
-                        match image_to_base64(url) {
-                            Ok(image) => attached_image.push(image),
-                            Err(err) => {
-                                trace!("Failed to encode image: {url}\nError: {err}\n");
-                            }
-                        }
-                    }
-                    ImageReference::Inline(image_source) => {
-                        let base64_data = general_purpose::STANDARD.encode(&image_source.data);
-                        attached_image.push(base64_data);
-                    }
-                },
-            }
+        // Tool results included in the messages are sent like the ones sent by `continue_`
+        let (content, tool_results) = split_tool_results(message.content, non_empty_tool_call_id)?;
+        if !content.is_empty() || tool_results.is_empty() {
+            request_message.extend(message_to_requests(
+                role_map.get(message.role),
+                content,
+                &text_join,
+                on_image_error,
+                &mut dropped_images,
+            )?);
         }
-
-        request_message.push(MessageRequest {
-            content: message_content,
-            role: message_role,
-            images: if attached_image.is_empty() {
-                None
-            } else {
-                Some(attached_image)
-            },
-            tools_calls: None,
-        });
+        request_message.extend(tool_results_to_messages(tool_results));
     }
 
     if let Some(tool_results) = tool_results {
@@ -97,7 +175,7 @@ pub fn messages_to_request(
 
     let ollama_options = OllamaModelOptions {
         min_p: parse_option(&options, "min_p"),
-        temperature: config.temperature,
+        temperature: validate_temperature(config.temperature, TEMPERATURE_RANGE)?,
         top_p: parse_option(&options, "top_p"),
         top_k: parse_option(&options, "top_k"),
         num_predict: parse_option(&options, "num_predict"),
@@ -123,16 +201,260 @@ pub fn messages_to_request(
     };
 
     Ok(CompletionsRequest {
-        model: Some(config.model),
+        model: Some(model),
         messages: Some(request_message),
         tools: Some(tools),
         format: options.get("format").cloned(),
         options: Some(ollama_options),
         keep_alive: options.get("keep_alive").cloned(),
         stream: Some(false),
+        dropped_images,
     })
 }
 
+/// Name of a model without its tag, like `llava` for `llava:13b`
+fn model_name(model: &str) -> &str {
+    model.split_once(':').map_or(model, |(name, _)| name)
+}
+
+/// Fails if the model is known not to support streaming
+pub fn check_streaming(model: &str) -> Result<(), Error> {
+    check_streaming_support(&MODEL_CAPABILITIES, model_name(model))
+}
+
+/// How long a warmed model stays loaded when the `keep_alive` provider option is not set
+const DEFAULT_WARM_KEEP_ALIVE: &str = "24h";
+
+/// A chat request without messages, which Ollama treats as a request to load the model and keep
+/// it loaded for `keep_alive`
+pub fn warm_request(config: Config) -> Result<CompletionsRequest, Error> {
+    let options = config
+        .provider_options
+        .into_iter()
+        .map(|kv| (kv.key, kv.value))
+        .collect::<HashMap<_, _>>();
+    check_provider_options(&options, &PROVIDER_OPTIONS)?;
+
+    Ok(CompletionsRequest {
+        model: Some(model_or_default(config.model)?),
+        messages: Some(vec![]),
+        tools: None,
+        format: None,
+        options: None,
+        keep_alive: Some(
+            options
+                .get("keep_alive")
+                .cloned()
+                .unwrap_or_else(|| DEFAULT_WARM_KEEP_ALIVE.to_string()),
+        ),
+        stream: Some(false),
+        dropped_images: 0,
+    })
+}
+
+/// A run of consecutive text or image parts of a message
+enum ContentRun {
+    Texts(Vec<String>),
+    Images(Vec<String>),
+}
+
+/// What to do with an image which cannot be loaded
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ImageErrorPolicy {
+    /// Fail the request with an `InvalidRequest` error naming the image
+    Fail,
+    /// Leave the image out of the request, reporting the number of images left out as
+    /// `dropped_images` in the provider metadata of the response
+    Skip,
+}
+
+impl ImageErrorPolicy {
+    fn from_options(options: &HashMap<String, String>) -> Result<Self, Error> {
+        match options
+            .get(ON_IMAGE_ERROR_OPTION)
+            .map(|value| value.as_str())
+        {
+            None | Some("fail") => Ok(Self::Fail),
+            Some("skip") => Ok(Self::Skip),
+            Some(other) => Err(Error {
+                code: ErrorCode::InvalidRequest,
+                message: format!(
+                    "Invalid {ON_IMAGE_ERROR_OPTION} provider option {other}, expected fail or skip"
+                ),
+                provider_error_json: None,
+            }),
+        }
+    }
+}
+
+/// Ollama role of the messages of each role
+#[derive(Debug, Clone)]
+struct RoleMap {
+    assistant: MessageRole,
+    system: MessageRole,
+    user: MessageRole,
+    tool: MessageRole,
+}
+
+impl RoleMap {
+    /// The default mapping for the given model, overridden by the roles of the `role_map` option
+    fn from_options(model: &str, options: &HashMap<String, String>) -> Result<Self, Error> {
+        let mut role_map = Self::default_for(model);
+
+        if let Some(value) = options.get(ROLE_MAP_OPTION) {
+            let invalid = |details: String| Error {
+                code: ErrorCode::InvalidRequest,
+                message: format!("Invalid {ROLE_MAP_OPTION} provider option {value}: {details}"),
+                provider_error_json: None,
+            };
+            let overrides = serde_json::from_str::<HashMap<String, MessageRole>>(value)
+                .map_err(|err| invalid(err.to_string()))?;
+            for (role, ollama_role) in overrides {
+                match role.as_str() {
+                    "assistant" => role_map.assistant = ollama_role,
+                    "system" => role_map.system = ollama_role,
+                    "user" => role_map.user = ollama_role,
+                    "tool" => role_map.tool = ollama_role,
+                    other => return Err(invalid(format!("unknown role {other}"))),
+                }
+            }
+        }
+
+        Ok(role_map)
+    }
+
+    /// Maps each role to the Ollama role of the same name, except for the tool messages of models
+    /// not known to support the `tool` role, which Ollama treats as user input
+    fn default_for(model: &str) -> Self {
+        let supports_tool_role = TOOL_ROLE_MODEL_PREFIXES
+            .iter()
+            .any(|prefix| model.starts_with(prefix));
+        Self {
+            assistant: MessageRole::Assistant,
+            system: MessageRole::System,
+            user: MessageRole::User,
+            tool: if supports_tool_role {
+                MessageRole::Tool
+            } else {
+                MessageRole::User
+            },
+        }
+    }
+
+    fn get(&self, role: Role) -> MessageRole {
+        match role {
+            Role::Assistant => self.assistant.clone(),
+            Role::System => self.system.clone(),
+            Role::User => self.user.clone(),
+            Role::Tool => self.tool.clone(),
+        }
+    }
+}
+
+/// Converts a message to Ollama messages. Ollama takes the images of a message separately from
+/// its text, so a message whose text and images interleave is split into consecutive messages of
+/// the same role, one for each run of texts or images, keeping their order. Any other message is
+/// converted to a single message with all its images attached.
+///
+/// Images which cannot be loaded fail the conversion, or are left out and counted in
+/// `dropped_images`, depending on the `on_image_error` policy.
+fn message_to_requests(
+    role: MessageRole,
+    content: Vec<ContentPart>,
+    text_join: &TextJoin,
+    on_image_error: ImageErrorPolicy,
+    dropped_images: &mut u32,
+) -> Result<Vec<MessageRequest>, Error> {
+    let mut runs = Vec::new();
+    for content_part in content {
+        match content_part {
+            ContentPart::Text(text) => match runs.last_mut() {
+                Some(ContentRun::Texts(texts)) => texts.push(text),
+                _ => runs.push(ContentRun::Texts(vec![text])),
+            },
+            // Ollama has no image detail setting, so the detail of the images is dropped
+            ContentPart::Image(reference) => {
+                let image = match reference {
+                    ImageReference::Url(image_url) => {
+                        let url = image_source_name(&image_url.url);
+                        match image_to_base64(&image_url.url) {
+                            Ok(image) => image,
+                            Err(err) if on_image_error == ImageErrorPolicy::Skip => {
+                                warn!("Dropping image {url} which could not be loaded: {err}");
+                                *dropped_images += 1;
+                                continue;
+                            }
+                            Err(err) => {
+                                return Err(Error {
+                                    code: ErrorCode::InvalidRequest,
+                                    message: format!("Failed to load image {url}: {err}"),
+                                    provider_error_json: None,
+                                })
+                            }
+                        }
+                    }
+                    ImageReference::Inline(image_source) => {
+                        general_purpose::STANDARD.encode(&image_source.data)
+                    }
+                };
+                match runs.last_mut() {
+                    Some(ContentRun::Images(images)) => images.push(image),
+                    _ => runs.push(ContentRun::Images(vec![image])),
+                }
+            }
+            // Tool results are sent in messages of their own by `messages_to_request`
+            ContentPart::ToolResult(_) => {}
+            // The reasoning of earlier responses is not sent back to the model
+            ContentPart::Reasoning(_) => {}
+            // Audio responses are sent back to the model as their transcript
+            ContentPart::Audio(audio) => {
+                if let Some(text) = audio.transcript {
+                    match runs.last_mut() {
+                        Some(ContentRun::Texts(texts)) => texts.push(text),
+                        _ => runs.push(ContentRun::Texts(vec![text])),
+                    }
+                }
+            }
+            // Refusals of earlier responses are sent back to the model as text
+            ContentPart::Refusal(text) => match runs.last_mut() {
+                Some(ContentRun::Texts(texts)) => texts.push(text),
+                _ => runs.push(ContentRun::Texts(vec![text])),
+            },
+        }
+    }
+
+    let message_request = |texts: Vec<String>, images: Vec<String>| MessageRequest {
+        role: role.clone(),
+        content: text_join.join(&texts),
+        images: if images.is_empty() {
+            None
+        } else {
+            Some(images)
+        },
+        tools_calls: None,
+    };
+
+    if runs.len() > 2 {
+        Ok(runs
+            .into_iter()
+            .map(|run| match run {
+                ContentRun::Texts(texts) => message_request(texts, vec![]),
+                ContentRun::Images(images) => message_request(vec![], images),
+            })
+            .collect())
+    } else {
+        let mut texts = Vec::new();
+        let mut images = Vec::new();
+        for run in runs {
+            match run {
+                ContentRun::Texts(run_texts) => texts.extend(run_texts),
+                ContentRun::Images(run_images) => images.extend(run_images),
+            }
+        }
+        Ok(vec![message_request(texts, images)])
+    }
+}
+
 fn tool_results_to_messages(
     tool_results: Vec<(golem_llm_ToolCall, ToolResult)>,
 ) -> Vec<MessageRequest> {
@@ -170,77 +492,683 @@ fn parse_option<T: std::str::FromStr>(options: &HashMap<String, String>, key: &s
     options.get(key).and_then(|v| v.parse::<T>().ok())
 }
 
+/// Converts the `done_reason` of a finished response. A finished response without a reason is
+/// considered stopped, and `load` or `unload` responses, which did not generate anything, end
+/// with `Other`.
+pub fn convert_finish_reason(done: bool, done_reason: Option<&str>) -> Option<FinishReason> {
+    if !done {
+        return None;
+    }
+
+    match done_reason {
+        None | Some("stop") => Some(FinishReason::Stop),
+        Some("length") => Some(FinishReason::Length),
+        Some("tool_calls") => Some(FinishReason::ToolCalls),
+        Some(_) => Some(FinishReason::Other),
+    }
+}
+
+/// Ollama does not identify its tool calls, so they get a synthetic id made of the creation
+/// time of the response and the index of the call in it, keeping the ids of the calls of a
+/// response distinct. The ids are never sent back to Ollama, which only gets the tool results
+/// as text.
+pub fn tool_call_id(created_at: &str, index: usize) -> String {
+    format!("ollama-{created_at}-{index}")
+}
+
+/// Converts a non-streaming response. A response with only tool calls becomes a tool request,
+/// and a successful response without any content becomes a message with empty content.
 pub fn process_response(response: CompletionsResponse) -> ChatEvent {
-    if let Some(ref message) = response.message {
-        let mut content = Vec::<ContentPart>::new();
-        let mut tool_calls = Vec::<golem_llm_ToolCall>::new();
+    let done = response.done.unwrap_or(false);
+    if response.message.is_none() && !done {
+        return ChatEvent::Error(Error {
+            code: ErrorCode::InternalError,
+            message: String::from("No messages in response"),
+            provider_error_json: None,
+        });
+    }
+
+    let mut content = Vec::<ContentPart>::new();
+    let mut tool_calls = Vec::<golem_llm_ToolCall>::new();
 
+    if let Some(ref message) = response.message {
         if let Some(ref message_content) = message.content {
-            content.push(ContentPart::Text(message_content.clone()));
+            if !message_content.is_empty() {
+                content.push(ContentPart::Text(message_content.clone()));
+            }
         }
 
         if let Some(ref message_tool_calls) = message.tool_calls {
-            for tool_call in message_tool_calls {
+            for (index, tool_call) in message_tool_calls.iter().enumerate() {
+                let Some(function) = &tool_call.function else {
+                    continue;
+                };
                 tool_calls.push(golem_llm_ToolCall {
-                    id: format!("ollama-{}", response.created_at.clone()),
-                    name: tool_call.name.clone().unwrap_or_default(),
-                    arguments_json: tool_call.function.as_ref().unwrap().arguments.to_string(),
+                    id: tool_call_id(&response.created_at, index),
+                    name: tool_call
+                        .name
+                        .clone()
+                        .unwrap_or_else(|| function.name.clone()),
+                    arguments_json: function.arguments.to_string(),
                 });
             }
         }
+    }
 
-        let finish_reason = if response.done.unwrap_or(false) {
-            Some(FinishReason::Stop)
-        } else {
-            None
+    if content.is_empty() && !tool_calls.is_empty() {
+        return ChatEvent::ToolRequest(tool_calls);
+    }
+
+    let finish_reason = convert_finish_reason(done, response.done_reason.as_deref());
+    let input_tokens = response.prompt_eval_count.map(|c| c as u32);
+    let output_tokens = response.eval_count.map(|c| c as u32);
+
+    let usage = with_total_tokens(Usage {
+        input_tokens,
+        output_tokens,
+        total_tokens: None,
+    });
+
+    let timestamp = response.created_at.clone();
+
+    let metadata = ResponseMetadata {
+        finish_reason,
+        usage: Some(usage),
+        provider_id: Some("ollama".to_string()),
+        model: Some(response.model.clone()),
+        timestamp: Some(timestamp.clone()),
+        provider_metadata_json: Some(get_provider_metadata(&response)),
+        tokens_per_second: None,
+        stop_sequence: None,
+    };
+
+    ChatEvent::Message(CompleteResponse {
+        id: format!("ollama-{}", timestamp),
+        content,
+        tool_calls,
+        metadata,
+        annotations: vec![],
+    })
+}
+
+pub fn get_provider_metadata(response: &CompletionsResponse) -> String {
+    serde_json::json!({
+        "total_duration": response.total_duration.unwrap_or(0),
+        "load_duration": response.load_duration.unwrap_or(0),
+        "prompt_eval_duration": response.prompt_eval_duration.unwrap_or(0),
+        "eval_duration": response.eval_duration.unwrap_or(0),
+        "context": response.eval_count.unwrap_or(0),
+    })
+    .to_string()
+}
+
+/// Adds the number of images left out of the request to the provider metadata JSON
+pub fn with_dropped_images(provider_metadata_json: String, dropped_images: u32) -> String {
+    if dropped_images == 0 {
+        return provider_metadata_json;
+    }
+
+    match serde_json::from_str::<serde_json::Value>(&provider_metadata_json) {
+        Ok(serde_json::Value::Object(mut provider_metadata)) => {
+            provider_metadata.insert("dropped_images".to_string(), dropped_images.into());
+            serde_json::Value::Object(provider_metadata).to_string()
+        }
+        _ => provider_metadata_json,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::CompletionsResponse;
+    use crate::conversions::{
+        convert_finish_reason, messages_to_request, process_response, warm_request,
+        with_dropped_images,
+    };
+    use golem_llm::conversions::{non_empty_tool_call_id, normalize_tool_call_ids};
+    use golem_llm::golem::llm::llm::{
+        ChatEvent, Config, ContentPart, Error, ErrorCode, FinishReason, ImageDetail,
+        ImageReference, ImageSource, ImageUrl, Kv, Message, Role, ToolCall, ToolResult,
+        ToolSuccess,
+    };
+
+    fn response(json: &str) -> CompletionsResponse {
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn config(provider_options: Vec<Kv>) -> Config {
+        Config {
+            model: "llama3.2".to_string(),
+            temperature: None,
+            max_tokens: None,
+            stop_sequences: None,
+            tools: vec![],
+            tool_choice: None,
+            provider_options,
+            extra_headers: vec![],
+            service_tier: None,
+            metadata: vec![],
+            reasoning_effort: None,
+            store: None,
+            prompt_cache_key: None,
+            stream_options: None,
+            deadline_ms: None,
+            extra_body: None,
+        }
+    }
+
+    fn vision_config(provider_options: Vec<Kv>) -> Config {
+        Config {
+            model: "llava".to_string(),
+            ..config(provider_options)
+        }
+    }
+
+    /// Converts a two-part user message with the given `text_join` option, returning its content
+    fn joined_content(text_join: Option<&str>) -> String {
+        let config = config(
+            text_join
+                .map(|value| Kv {
+                    key: "text_join".to_string(),
+                    value: value.to_string(),
+                })
+                .into_iter()
+                .collect(),
+        );
+        let message = Message {
+            role: Role::User,
+            name: None,
+            content: vec![
+                ContentPart::Text("first".to_string()),
+                ContentPart::Text("second".to_string()),
+            ],
         };
-        let input_tokens = response.prompt_eval_count.map(|c| c as u32);
-        let output_tokens = response.eval_count.map(|c| c as u32);
 
-        let usage = Usage {
-            input_tokens,
-            output_tokens,
-            total_tokens: Some(input_tokens.unwrap_or(0) + output_tokens.unwrap_or(0)),
+        let request = messages_to_request(vec![message], config, None).unwrap();
+        request.messages.unwrap().remove(0).content
+    }
+
+    #[test]
+    fn text_parts_are_joined_with_newline_by_default() {
+        assert_eq!(joined_content(None), "first\nsecond");
+        assert_eq!(joined_content(Some("newline")), "first\nsecond");
+    }
+
+    #[test]
+    fn text_parts_are_joined_with_space() {
+        assert_eq!(joined_content(Some("space")), "first second");
+    }
+
+    #[test]
+    fn text_parts_are_joined_without_separator() {
+        assert_eq!(joined_content(Some("none")), "firstsecond");
+    }
+
+    #[test]
+    fn text_parts_are_joined_with_custom_separator() {
+        assert_eq!(joined_content(Some(" | ")), "first | second");
+    }
+
+    #[test]
+    fn content_only_response_is_a_message() {
+        let event = process_response(response(
+            r#"{
+                "model": "llama3.2",
+                "created_at": "2025-01-01T00:00:00Z",
+                "message": { "role": "assistant", "content": "Hello!" },
+                "done": true,
+                "prompt_eval_count": 10,
+                "eval_count": 3
+            }"#,
+        ));
+
+        let ChatEvent::Message(message) = event else {
+            panic!("Expected a message, got {event:?}");
+        };
+        assert_eq!(
+            message.content,
+            vec![ContentPart::Text("Hello!".to_string())]
+        );
+        assert!(message.tool_calls.is_empty());
+        assert_eq!(message.metadata.finish_reason, Some(FinishReason::Stop));
+        assert_eq!(message.metadata.usage.unwrap().total_tokens, Some(13));
+    }
+
+    #[test]
+    fn tool_only_response_is_a_tool_request() {
+        let event = process_response(response(
+            r#"{
+                "model": "llama3.2",
+                "created_at": "2025-01-01T00:00:00Z",
+                "message": {
+                    "role": "assistant",
+                    "content": "",
+                    "tool_calls": [
+                        { "function": { "name": "get_weather", "arguments": { "city": "Paris" } } }
+                    ]
+                },
+                "done": true
+            }"#,
+        ));
+
+        let ChatEvent::ToolRequest(tool_calls) = event else {
+            panic!("Expected a tool request, got {event:?}");
         };
+        assert_eq!(tool_calls.len(), 1);
+        assert_eq!(tool_calls[0].name, "get_weather");
+        assert_eq!(tool_calls[0].arguments_json, r#"{"city":"Paris"}"#);
+    }
 
-        let timestamp = response.created_at.clone();
+    #[test]
+    fn empty_successful_response_is_an_empty_message() {
+        let event = process_response(response(
+            r#"{
+                "model": "llama3.2",
+                "created_at": "2025-01-01T00:00:00Z",
+                "done": true,
+                "done_reason": "stop",
+                "prompt_eval_count": 10,
+                "eval_count": 0
+            }"#,
+        ));
 
-        let metadata = ResponseMetadata {
-            finish_reason,
-            usage: Some(usage),
-            provider_id: Some("ollama".to_string()),
-            timestamp: Some(timestamp.clone()),
-            provider_metadata_json: Some(get_provider_metadata(&response)),
+        let ChatEvent::Message(message) = event else {
+            panic!("Expected a message, got {event:?}");
         };
+        assert!(message.content.is_empty());
+        assert!(message.tool_calls.is_empty());
+        assert_eq!(message.id, "ollama-2025-01-01T00:00:00Z");
+        assert_eq!(message.metadata.finish_reason, Some(FinishReason::Stop));
+        assert_eq!(message.metadata.provider_id, Some("ollama".to_string()));
+        assert_eq!(message.metadata.usage.unwrap().input_tokens, Some(10));
+    }
+
+    #[test]
+    fn done_reason_is_converted_to_finish_reason() {
+        for (done_reason, finish_reason) in [
+            (r#""stop""#, FinishReason::Stop),
+            (r#""length""#, FinishReason::Length),
+            (r#""tool_calls""#, FinishReason::ToolCalls),
+            (r#""load""#, FinishReason::Other),
+            ("null", FinishReason::Stop),
+        ] {
+            let event = process_response(response(&format!(
+                r#"{{
+                    "model": "llama3.2",
+                    "created_at": "2025-01-01T00:00:00Z",
+                    "message": {{ "role": "assistant", "content": "Hello" }},
+                    "done": true,
+                    "done_reason": {done_reason}
+                }}"#
+            )));
 
-        ChatEvent::Message(CompleteResponse {
-            id: format!("ollama-{}", timestamp),
+            let ChatEvent::Message(message) = event else {
+                panic!("Expected a message, got {event:?}");
+            };
+            assert_eq!(
+                message.metadata.finish_reason,
+                Some(finish_reason),
+                "{done_reason}"
+            );
+        }
+    }
+
+    #[test]
+    fn unfinished_response_has_no_finish_reason() {
+        assert_eq!(convert_finish_reason(false, Some("length")), None);
+    }
+
+    #[test]
+    fn image_detail_is_dropped() {
+        let message = Message {
+            role: Role::User,
+            name: None,
+            content: vec![
+                ContentPart::Text("What is on this image?".to_string()),
+                ContentPart::Image(ImageReference::Inline(ImageSource {
+                    data: vec![1, 2, 3],
+                    mime_type: "image/png".to_string(),
+                    detail: Some(ImageDetail::High),
+                })),
+            ],
+        };
+
+        let request = messages_to_request(vec![message], vision_config(vec![]), None).unwrap();
+        let message = request.messages.unwrap().remove(0);
+
+        assert_eq!(message.images, Some(vec!["AQID".to_string()]));
+        assert!(!serde_json::to_string(&message).unwrap().contains("detail"));
+    }
+
+    fn inline_image(data: Vec<u8>) -> ContentPart {
+        ContentPart::Image(ImageReference::Inline(ImageSource {
+            data,
+            mime_type: "image/png".to_string(),
+            detail: None,
+        }))
+    }
+
+    fn user_message(content: Vec<ContentPart>) -> Message {
+        Message {
+            role: Role::User,
+            name: None,
             content,
-            tool_calls,
-            metadata,
-        })
-    } else {
-        ChatEvent::Error(Error {
-            code: ErrorCode::InternalError,
-            message: String::from("No messages in response"),
-            provider_error_json: None,
-        })
+        }
     }
-}
 
-pub fn get_provider_metadata(response: &CompletionsResponse) -> String {
-    format!(
-        r#"{{
-    "total_duration":"{}",
-    "load_duration":"{}",
-    "prompt_eval_duration":{},
-    "eval_duration":{},
-    "context":{},
-    }}"#,
-        response.total_duration.unwrap_or(0),
-        response.load_duration.unwrap_or(0),
-        response.prompt_eval_duration.unwrap_or(0),
-        response.eval_duration.unwrap_or(0),
-        response.eval_count.unwrap_or(0)
-    )
+    #[test]
+    fn all_images_of_a_message_are_attached() {
+        let message = user_message(vec![
+            ContentPart::Text("Compare these images".to_string()),
+            inline_image(vec![1, 2, 3]),
+            inline_image(vec![4, 5, 6]),
+        ]);
+
+        let request = messages_to_request(vec![message], vision_config(vec![]), None).unwrap();
+        let messages = request.messages.unwrap();
+
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].content, "Compare these images");
+        assert_eq!(
+            messages[0].images,
+            Some(vec!["AQID".to_string(), "BAUG".to_string()])
+        );
+    }
+
+    #[test]
+    fn interleaved_text_and_images_are_split_in_order() {
+        let message = user_message(vec![
+            ContentPart::Text("This is the first image:".to_string()),
+            inline_image(vec![1, 2, 3]),
+            ContentPart::Text("And this is the second one:".to_string()),
+            inline_image(vec![4, 5, 6]),
+        ]);
+
+        let request = messages_to_request(vec![message], vision_config(vec![]), None).unwrap();
+        let messages = request
+            .messages
+            .unwrap()
+            .into_iter()
+            .map(|message| (message.content, message.images))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            messages,
+            vec![
+                ("This is the first image:".to_string(), None),
+                (String::new(), Some(vec!["AQID".to_string()])),
+                ("And this is the second one:".to_string(), None),
+                (String::new(), Some(vec!["BAUG".to_string()])),
+            ]
+        );
+    }
+
+    const UNREACHABLE_IMAGE_URL: &str = "/nonexistent/cat.png";
+
+    /// A message with an image which cannot be loaded, followed by one which can
+    fn message_with_unreachable_image() -> Message {
+        user_message(vec![
+            ContentPart::Text("What is on these images?".to_string()),
+            ContentPart::Image(ImageReference::Url(ImageUrl {
+                url: UNREACHABLE_IMAGE_URL.to_string(),
+                detail: None,
+            })),
+            inline_image(vec![1, 2, 3]),
+        ])
+    }
+
+    fn on_image_error(value: &str) -> Config {
+        vision_config(vec![Kv {
+            key: "on_image_error".to_string(),
+            value: value.to_string(),
+        }])
+    }
+
+    #[test]
+    fn image_failing_to_load_fails_the_request_by_default() {
+        for config in [vision_config(vec![]), on_image_error("fail")] {
+            let error = messages_to_request(vec![message_with_unreachable_image()], config, None)
+                .unwrap_err();
+
+            assert_eq!(error.code, ErrorCode::InvalidRequest);
+            assert!(
+                error.message.contains(UNREACHABLE_IMAGE_URL),
+                "{}",
+                error.message
+            );
+        }
+    }
+
+    #[test]
+    fn image_of_a_link_local_address_fails_the_request() {
+        let message = user_message(vec![ContentPart::Image(ImageReference::Url(ImageUrl {
+            url: "http://169.254.169.254/latest/meta-data/".to_string(),
+            detail: None,
+        }))]);
+
+        let error = messages_to_request(vec![message], vision_config(vec![]), None).unwrap_err();
+
+        assert_eq!(error.code, ErrorCode::InvalidRequest);
+        assert!(error.message.contains("is blocked"), "{}", error.message);
+    }
+
+    #[test]
+    fn image_failing_to_load_is_skipped_and_counted() {
+        let request = messages_to_request(
+            vec![message_with_unreachable_image()],
+            on_image_error("skip"),
+            None,
+        )
+        .unwrap();
+        assert_eq!(request.dropped_images, 1);
+
+        let messages = request.messages.unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].images, Some(vec!["AQID".to_string()]));
+
+        let provider_metadata: serde_json::Value = serde_json::from_str(&with_dropped_images(
+            r#"{"total_duration":1}"#.to_string(),
+            request.dropped_images,
+        ))
+        .unwrap();
+        assert_eq!(provider_metadata["dropped_images"], 1);
+        assert_eq!(provider_metadata["total_duration"], 1);
+    }
+
+    #[test]
+    fn images_are_rejected_for_text_only_models_whatever_their_tag() {
+        let image_request = |model: &str| {
+            messages_to_request(
+                vec![user_message(vec![inline_image(vec![1, 2, 3])])],
+                Config {
+                    model: model.to_string(),
+                    ..config(vec![])
+                },
+                None,
+            )
+        };
+
+        assert!(image_request("llava:13b").is_ok());
+        assert!(image_request("my-custom-model").is_ok());
+
+        let error = image_request("llama3.2:3b").unwrap_err();
+        assert_eq!(error.code, ErrorCode::InvalidRequest);
+        assert_eq!(error.message, "Model llama3.2 does not support image input");
+    }
+
+    #[test]
+    fn invalid_image_error_policy_is_rejected() {
+        let error = messages_to_request(
+            vec![message_with_unreachable_image()],
+            on_image_error("retry"),
+            None,
+        )
+        .unwrap_err();
+
+        assert_eq!(error.code, ErrorCode::InvalidRequest);
+    }
+
+    #[test]
+    fn warm_request_has_no_messages_and_keeps_the_model_alive() {
+        let request = warm_request(config(vec![Kv {
+            key: "keep_alive".to_string(),
+            value: "2h".to_string(),
+        }]))
+        .unwrap();
+
+        assert_eq!(
+            serde_json::to_value(&request).unwrap(),
+            serde_json::json!({
+                "model": "llama3.2",
+                "messages": [],
+                "stream": false,
+                "keep_alive": "2h"
+            })
+        );
+    }
+
+    #[test]
+    fn warm_request_keeps_the_model_alive_for_a_day_by_default() {
+        let request = warm_request(config(vec![])).unwrap();
+
+        assert_eq!(request.keep_alive, Some("24h".to_string()));
+    }
+
+    #[test]
+    fn synthetic_tool_call_ids_are_distinct_and_roundtrip() {
+        let event = process_response(response(
+            r#"{
+                "model": "llama3.2",
+                "created_at": "2025-01-01T00:00:00Z",
+                "message": {
+                    "role": "assistant",
+                    "content": "",
+                    "tool_calls": [
+                        { "function": { "name": "get_weather", "arguments": { "city": "Paris" } } },
+                        { "function": { "name": "get_weather", "arguments": { "city": "Rome" } } }
+                    ]
+                },
+                "done": true
+            }"#,
+        ));
+        let ChatEvent::ToolRequest(tool_calls) = event else {
+            panic!("Expected a tool request, got {event:?}");
+        };
+        assert_eq!(
+            tool_calls
+                .iter()
+                .map(|tool_call| tool_call.id.as_str())
+                .collect::<Vec<_>>(),
+            vec![
+                "ollama-2025-01-01T00:00:00Z-0",
+                "ollama-2025-01-01T00:00:00Z-1"
+            ]
+        );
+
+        let tool_results = tool_calls
+            .iter()
+            .map(|tool_call| {
+                (
+                    tool_call.clone(),
+                    ToolResult::Success(ToolSuccess {
+                        id: tool_call.id.clone(),
+                        name: tool_call.name.clone(),
+                        result_json: r#"{"temperature":21}"#.to_string(),
+                        execution_time_ms: None,
+                        content: vec![],
+                    }),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            normalize_tool_call_ids(tool_results.clone(), non_empty_tool_call_id),
+            Ok(tool_results)
+        );
+    }
+
+    #[test]
+    fn tool_result_of_another_call_is_rejected() {
+        let tool_call = ToolCall {
+            id: "ollama-2025-01-01T00:00:00Z-0".to_string(),
+            name: "get_weather".to_string(),
+            arguments_json: r#"{"city":"Paris"}"#.to_string(),
+        };
+        let tool_result = ToolResult::Success(ToolSuccess {
+            id: "ollama-2025-01-01T00:00:00Z-1".to_string(),
+            name: "get_weather".to_string(),
+            result_json: r#"{"temperature":21}"#.to_string(),
+            execution_time_ms: None,
+            content: vec![],
+        });
+
+        let error = normalize_tool_call_ids(vec![(tool_call, tool_result)], non_empty_tool_call_id)
+            .unwrap_err();
+
+        assert_eq!(error.code, ErrorCode::InvalidRequest);
+    }
+
+    fn tool_message_role(model: &str, role_map: Option<&str>) -> Result<serde_json::Value, Error> {
+        let config = Config {
+            model: model.to_string(),
+            ..config(
+                role_map
+                    .map(|value| Kv {
+                        key: "role_map".to_string(),
+                        value: value.to_string(),
+                    })
+                    .into_iter()
+                    .collect(),
+            )
+        };
+        let message = Message {
+            role: Role::Tool,
+            name: None,
+            content: vec![ContentPart::Text("22 degrees".to_string())],
+        };
+
+        let request = messages_to_request(vec![message], config, None)?;
+        Ok(serde_json::to_value(&request.messages.unwrap()[0]).unwrap()["role"].clone())
+    }
+
+    #[test]
+    fn tool_messages_use_the_tool_role_of_the_models_supporting_it() {
+        assert_eq!(
+            tool_message_role("llama3.2", None),
+            Ok(serde_json::json!("tool"))
+        );
+        assert_eq!(
+            tool_message_role("qwen3:8b", None),
+            Ok(serde_json::json!("tool"))
+        );
+        assert_eq!(
+            tool_message_role("gemma2", None),
+            Ok(serde_json::json!("user"))
+        );
+    }
+
+    #[test]
+    fn role_map_overrides_the_default_mapping() {
+        assert_eq!(
+            tool_message_role("llama3.2", Some(r#"{"tool": "user"}"#)),
+            Ok(serde_json::json!("user"))
+        );
+        assert_eq!(
+            tool_message_role("gemma2", Some(r#"{"tool": "tool"}"#)),
+            Ok(serde_json::json!("tool"))
+        );
+    }
+
+    #[test]
+    fn invalid_role_map_is_rejected() {
+        for role_map in [
+            r#"{"tool": "function"}"#,
+            r#"{"developer": "system"}"#,
+            "tool",
+        ] {
+            assert_eq!(
+                tool_message_role("llama3.2", Some(role_map)).map_err(|error| error.code),
+                Err(ErrorCode::InvalidRequest),
+                "{role_map}"
+            );
+        }
+    }
 }
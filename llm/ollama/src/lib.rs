@@ -1,19 +1,33 @@
 use std::cell::{Ref, RefCell, RefMut};
 
 use client::{CompletionsRequest, OllamaApi};
-use conversions::{messages_to_request, process_response};
+use conversions::{
+    check_streaming, convert_finish_reason, messages_to_request, process_response, warm_request,
+    with_dropped_images,
+};
 use golem_llm::{
-    chat_stream::{LlmChatStream, LlmChatStreamState},
-    durability::{DurableLLM, ExtendedGuest},
+    batch::send_batch,
+    chat_stream::{request_deadline, LlmChatStream, LlmChatStreamState},
+    conversions::{check_messages_not_empty, non_empty_tool_call_id, normalize_tool_call_ids},
+    dry_run::{send_or_dry_run, stream_or_dry_run},
+    durability::{retry_continuation_prompt, retry_system_prompt, DurableLLM, ExtendedGuest},
+    error::unsupported,
     event_source::EventSource,
+    extra_body::extra_body,
     golem::llm::llm::{
-        ChatEvent, ChatStream, Config, ContentPart, Error, FinishReason, Guest, Message,
-        ResponseMetadata, Role, StreamDelta, StreamEvent, ToolCall, ToolResult, Usage,
+        ChatEvent, ChatStream, Config, ContentPart, EmbeddingConfig, EmbeddingResult, Error, Guest,
+        ImageGenConfig, ImageGenResult, Message, ProviderCapabilities, ProviderHealth,
+        RerankConfig, RerankResult, ResponseMetadata, Role, StreamDelta, StreamEvent, ToolCall,
+        ToolResult, Usage,
     },
+    headers::extra_headers,
+    rate_limit::with_rate_limit,
+    usage::with_total_tokens,
     LOGGING_STATE,
 };
 use golem_rust::wasm_rpc::Pollable;
 use log::trace;
+use reqwest::header::HeaderMap;
 
 mod client;
 mod conversions;
@@ -22,23 +36,44 @@ struct OllamaChatStream {
     stream: RefCell<Option<EventSource>>,
     failure: Option<Error>,
     finished: RefCell<bool>,
+    dropped_images: u32,
+    /// The `created_at` of the latest chunk having one, as some Ollama versions omit it from
+    /// the final chunk
+    last_created_at: RefCell<Option<String>>,
 }
 
 impl OllamaChatStream {
-    pub fn new(stream: EventSource) -> LlmChatStream<Self> {
-        LlmChatStream::new(OllamaChatStream {
-            stream: RefCell::new(Some(stream)),
-            failure: None,
+    fn state(stream: Option<EventSource>, failure: Option<Error>, dropped_images: u32) -> Self {
+        OllamaChatStream {
+            stream: RefCell::new(stream),
+            failure,
             finished: RefCell::new(false),
-        })
+            dropped_images,
+            last_created_at: RefCell::new(None),
+        }
+    }
+
+    pub fn new(stream: EventSource, dropped_images: u32) -> LlmChatStream<Self> {
+        LlmChatStream::new(Self::state(Some(stream), None, dropped_images))
     }
 
     pub fn failed(error: Error) -> LlmChatStream<Self> {
-        LlmChatStream::new(OllamaChatStream {
-            stream: RefCell::new(None),
-            failure: Some(error),
-            finished: RefCell::new(false),
-        })
+        LlmChatStream::new(Self::state(None, Some(error), 0))
+    }
+
+    pub fn dry_run(events: Vec<StreamEvent>) -> LlmChatStream<Self> {
+        LlmChatStream::from_events(Self::state(None, None, 0), events)
+    }
+
+    /// The `created_at` of the chunk, or of the latest chunk having one when it is missing
+    fn created_at(&self, json: &serde_json::Value) -> Option<String> {
+        match json.get("created_at").and_then(|v| v.as_str()) {
+            Some(created_at) => {
+                *self.last_created_at.borrow_mut() = Some(created_at.to_string());
+                Some(created_at.to_string())
+            }
+            None => self.last_created_at.borrow().clone(),
+        }
     }
 }
 
@@ -62,10 +97,11 @@ impl LlmChatStreamState for OllamaChatStream {
         self.stream.borrow_mut()
     }
 
-    fn decode_message(&self, raw: &str) -> Result<Option<StreamEvent>, String> {
+    fn decode_message(&self, _event: &str, raw: &str) -> Result<Option<StreamEvent>, String> {
         trace!("Parsing NDJSON line: {raw}");
         let json: serde_json::Value =
             serde_json::from_str(raw.trim()).map_err(|e| format!("JSON parse error: {e}"))?;
+        let created_at = self.created_at(&json);
 
         if json.get("done").and_then(|v| v.as_bool()).unwrap_or(false) {
             let input_tokens = json
@@ -73,15 +109,11 @@ impl LlmChatStreamState for OllamaChatStream {
                 .and_then(|v| v.as_u64())
                 .unwrap_or(0) as u32;
             let output_tokens = json.get("eval_count").and_then(|v| v.as_u64()).unwrap_or(0) as u32;
-            let timestamp = json
-                .get("created_at")
-                .and_then(|v| v.as_str())
-                .map(|s| s.to_string());
-            let usage = Usage {
+            let usage = with_total_tokens(Usage {
                 input_tokens: Some(input_tokens),
-                output_tokens: Some(input_tokens),
-                total_tokens: Some(input_tokens + output_tokens),
-            };
+                output_tokens: Some(output_tokens),
+                total_tokens: None,
+            });
 
             let total_duration = json
                 .get("total_duration")
@@ -112,13 +144,23 @@ impl LlmChatStreamState for OllamaChatStream {
                 "context": context
             })
             .to_string();
+            let provider_metadata = with_dropped_images(provider_metadata, self.dropped_images);
 
             return Ok(Some(StreamEvent::Finish(ResponseMetadata {
-                finish_reason: Some(FinishReason::Stop),
+                finish_reason: convert_finish_reason(
+                    true,
+                    json.get("done_reason").and_then(|v| v.as_str()),
+                ),
                 usage: Some(usage),
                 provider_id: Some("ollama".to_string()),
-                timestamp,
+                model: json
+                    .get("model")
+                    .and_then(|v| v.as_str())
+                    .map(|s| s.to_string()),
+                timestamp: created_at,
                 provider_metadata_json: Some(provider_metadata),
+                tokens_per_second: None,
+                stop_sequence: None,
             })));
         }
 
@@ -133,7 +175,7 @@ impl LlmChatStreamState for OllamaChatStream {
             }
 
             if let Some(calls) = message.get("tool_calls").and_then(|tc| tc.as_array()) {
-                for call in calls {
+                for (index, call) in calls.iter().enumerate() {
                     if let Some(function) = call.get("function") {
                         let name = function
                             .get("name")
@@ -144,13 +186,7 @@ impl LlmChatStreamState for OllamaChatStream {
                             .get("arguments")
                             .cloned()
                             .unwrap_or(serde_json::json!({}));
-                        let id = format!(
-                            "ollama-{}",
-                            json.get("created_at")
-                                .and_then(|v| v.as_str())
-                                .map(|s| s.to_string())
-                                .unwrap_or_default()
-                        );
+                        let id = tool_call_id(created_at.as_deref().unwrap_or_default(), index);
                         tool_calls.push(ToolCall {
                             id,
                             name,
@@ -171,6 +207,9 @@ impl LlmChatStreamState for OllamaChatStream {
                 } else {
                     Some(tool_calls)
                 },
+                annotations: None,
+                tool_call_fragments: None,
+                running_usage: None,
             })));
         }
         Ok(None)
@@ -181,21 +220,39 @@ struct OllamaComponent;
 
 impl OllamaComponent {
     fn request(client: &OllamaApi, request: CompletionsRequest) -> ChatEvent {
-        match client.send_chat(request) {
-            Ok(response) => process_response(response),
+        let dropped_images = request.dropped_images;
+        send_or_dry_run(request, |request| match client.send_chat(request) {
+            Ok(response) => {
+                match with_rate_limit(process_response(response.body), response.rate_limit) {
+                    ChatEvent::Message(mut response) => {
+                        response.metadata.provider_metadata_json = response
+                            .metadata
+                            .provider_metadata_json
+                            .map(|json| with_dropped_images(json, dropped_images));
+                        ChatEvent::Message(response)
+                    }
+                    event => event,
+                }
+            }
             Err(err) => ChatEvent::Error(err),
-        }
+        })
     }
 
     fn streaming_request(
         client: &OllamaApi,
         mut request: CompletionsRequest,
     ) -> LlmChatStream<OllamaChatStream> {
-        request.stream = Some(true);
-        match client.send_chat_stream(request) {
-            Ok(stream) => OllamaChatStream::new(stream),
-            Err(err) => OllamaChatStream::failed(err),
+        if let Some(Err(err)) = request.model.as_deref().map(check_streaming) {
+            return OllamaChatStream::failed(err);
         }
+        request.stream = Some(true);
+        let dropped_images = request.dropped_images;
+        stream_or_dry_run(request, OllamaChatStream::dry_run, |request| {
+            match client.send_chat_stream(request) {
+                Ok(stream) => OllamaChatStream::new(stream, dropped_images),
+                Err(err) => OllamaChatStream::failed(err),
+            }
+        })
     }
 }
 
@@ -205,7 +262,25 @@ impl Guest for OllamaComponent {
     fn send(messages: Vec<Message>, config: Config) -> ChatEvent {
         LOGGING_STATE.with_borrow_mut(|state| state.init());
 
-        let client = OllamaApi::new(config.model.clone());
+        if let Err(err) = check_messages_not_empty(&messages, &[]) {
+            return ChatEvent::Error(err);
+        }
+
+        let extra_headers = match extra_headers(&config) {
+            Ok(extra_headers) => extra_headers,
+            Err(err) => return ChatEvent::Error(err),
+        };
+        let extra_body = match extra_body(&config) {
+            Ok(extra_body) => extra_body,
+            Err(err) => return ChatEvent::Error(err),
+        };
+
+        let client = match OllamaApi::new(config.model.clone(), extra_headers)
+            .map(|client| client.with_extra_body(extra_body))
+        {
+            Ok(client) => client,
+            Err(err) => return ChatEvent::Error(err),
+        };
         match messages_to_request(messages, config.clone(), None) {
             Ok(request) => Self::request(&client, request),
             Err(err) => ChatEvent::Error(err),
@@ -219,7 +294,30 @@ impl Guest for OllamaComponent {
     ) -> ChatEvent {
         LOGGING_STATE.with_borrow_mut(|state| state.init());
 
-        let client = OllamaApi::new(config.model.clone());
+        if let Err(err) = check_messages_not_empty(&messages, &tool_results) {
+            return ChatEvent::Error(err);
+        }
+
+        let extra_headers = match extra_headers(&config) {
+            Ok(extra_headers) => extra_headers,
+            Err(err) => return ChatEvent::Error(err),
+        };
+        let extra_body = match extra_body(&config) {
+            Ok(extra_body) => extra_body,
+            Err(err) => return ChatEvent::Error(err),
+        };
+
+        let tool_results = match normalize_tool_call_ids(tool_results, non_empty_tool_call_id) {
+            Ok(tool_results) => tool_results,
+            Err(err) => return ChatEvent::Error(err),
+        };
+
+        let client = match OllamaApi::new(config.model.clone(), extra_headers)
+            .map(|client| client.with_extra_body(extra_body))
+        {
+            Ok(client) => client,
+            Err(err) => return ChatEvent::Error(err),
+        };
 
         match messages_to_request(messages, config.clone(), Some(tool_results)) {
             Ok(request) => Self::request(&client, request),
@@ -227,18 +325,123 @@ impl Guest for OllamaComponent {
         }
     }
 
+    fn send_batch(requests: Vec<(Vec<Message>, Config)>, abort_on_error: bool) -> Vec<ChatEvent> {
+        send_batch(requests, abort_on_error, Self::send)
+    }
+
     fn stream(messages: Vec<Message>, config: Config) -> ChatStream {
         ChatStream::new(Self::unwrapped_stream(messages, config.clone()))
     }
+
+    fn continue_stream(
+        messages: Vec<Message>,
+        tool_results: Vec<(ToolCall, ToolResult)>,
+        config: Config,
+    ) -> ChatStream {
+        ChatStream::new(Self::unwrapped_continue_stream(
+            messages,
+            tool_results,
+            config,
+        ))
+    }
+
+    fn send_raw(body_json: String, config: Config) -> Result<String, Error> {
+        LOGGING_STATE.with_borrow_mut(|state| state.init());
+
+        let extra_headers = extra_headers(&config)?;
+        OllamaApi::new(config.model, extra_headers)?.send_raw(body_json)
+    }
+
+    fn rerank(
+        _query: String,
+        _documents: Vec<String>,
+        _config: RerankConfig,
+    ) -> Result<RerankResult, Error> {
+        Err(unsupported("rerank"))
+    }
+
+    fn embed(
+        _inputs: Vec<ContentPart>,
+        _config: EmbeddingConfig,
+    ) -> Result<EmbeddingResult, Error> {
+        Err(unsupported("embed"))
+    }
+
+    fn ping() -> Result<ProviderHealth, Error> {
+        LOGGING_STATE.with_borrow_mut(|state| state.init());
+
+        OllamaApi::new(String::new(), HeaderMap::new())?.ping()
+    }
+
+    fn capabilities() -> ProviderCapabilities {
+        ProviderCapabilities {
+            provider: "ollama".to_string(),
+            streaming: true,
+            tools: true,
+            vision: true,
+            embeddings: false,
+            // With the `format` provider option
+            json_schema: true,
+        }
+    }
+
+    fn warm(config: Config) -> Result<(), Error> {
+        LOGGING_STATE.with_borrow_mut(|state| state.init());
+
+        let extra_headers = extra_headers(&config)?;
+        let request = warm_request(config.clone())?;
+        OllamaApi::new(config.model, extra_headers)?.warm(request)
+    }
+
+    fn generate_image(_prompt: String, _config: ImageGenConfig) -> Result<ImageGenResult, Error> {
+        Err(unsupported("generate-image"))
+    }
 }
 
 impl ExtendedGuest for OllamaComponent {
     fn unwrapped_stream(messages: Vec<Message>, config: Config) -> LlmChatStream<OllamaChatStream> {
+        Self::unwrapped_continue_stream(messages, vec![], config)
+    }
+
+    fn unwrapped_continue_stream(
+        messages: Vec<Message>,
+        tool_results: Vec<(ToolCall, ToolResult)>,
+        config: Config,
+    ) -> LlmChatStream<OllamaChatStream> {
         LOGGING_STATE.with_borrow_mut(|state| state.init());
 
-        let client = OllamaApi::new(config.model.clone());
-        match messages_to_request(messages, config.clone(), None) {
-            Ok(request) => Self::streaming_request(&client, request),
+        if let Err(err) = check_messages_not_empty(&messages, &tool_results) {
+            return OllamaChatStream::failed(err);
+        }
+
+        let extra_headers = match extra_headers(&config) {
+            Ok(extra_headers) => extra_headers,
+            Err(err) => return OllamaChatStream::failed(err),
+        };
+        let extra_body = match extra_body(&config) {
+            Ok(extra_body) => extra_body,
+            Err(err) => return OllamaChatStream::failed(err),
+        };
+        let deadline = request_deadline(&config);
+
+        let tool_results = match normalize_tool_call_ids(tool_results, non_empty_tool_call_id) {
+            Ok(tool_results) => tool_results,
+            Err(err) => return OllamaChatStream::failed(err),
+        };
+
+        let client = match OllamaApi::new(config.model.clone(), extra_headers)
+            .map(|client| client.with_extra_body(extra_body))
+        {
+            Ok(client) => client,
+            Err(err) => return OllamaChatStream::failed(err),
+        };
+        let tool_results = if tool_results.is_empty() {
+            None
+        } else {
+            Some(tool_results)
+        };
+        match messages_to_request(messages, config.clone(), tool_results) {
+            Ok(request) => Self::streaming_request(&client, request).with_deadline(deadline),
             Err(err) => OllamaChatStream::failed(err),
         }
     }
@@ -249,12 +452,7 @@ impl ExtendedGuest for OllamaComponent {
         extended_messages.push(Message {
             role: Role::System,
             name: None,
-            content: vec![ContentPart::Text(
-                "You were asked the same question previously, but the response was interrupted before completion. \
-                 Please continue your response from where you left off. \
-                 Do not include the part of the response that was already seen."
-                    .to_string(),
-            )],
+            content: vec![ContentPart::Text(retry_system_prompt())],
         });
 
         extended_messages.push(Message {
@@ -285,12 +483,10 @@ impl ExtendedGuest for OllamaComponent {
         extended_messages.push(Message {
             role: Role::User,
             name: None,
-            content: vec![ContentPart::Text(
-                "Here is the partial response that was successfully received:".to_string(),
-            )]
-            .into_iter()
-            .chain(partial_result_as_content)
-            .collect(),
+            content: vec![ContentPart::Text(retry_continuation_prompt())]
+                .into_iter()
+                .chain(partial_result_as_content)
+                .collect(),
         });
 
         extended_messages
@@ -299,8 +495,198 @@ impl ExtendedGuest for OllamaComponent {
     fn subscribe(stream: &Self::ChatStream) -> Pollable {
         stream.subscribe()
     }
+
+    fn subscribe_deadline(stream: &Self::ChatStream) -> Option<Pollable> {
+        stream.subscribe_deadline()
+    }
+
+    fn usage_so_far(stream: &Self::ChatStream) -> Option<Usage> {
+        stream.usage_so_far()
+    }
 }
 
 type DurableOllamaComponent = DurableLLM<OllamaComponent>;
 
 golem_llm::export_llm!(DurableOllamaComponent with_types_in golem_llm);
+
+#[cfg(test)]
+mod tests {
+    use crate::{OllamaChatStream, OllamaComponent};
+    use golem_llm::chat_stream::{append_delta_text, LlmChatStream, LlmChatStreamState};
+    use golem_llm::event_source::StreamFormat;
+    use golem_llm::golem::llm::llm::{
+        FinishReason, Guest, ProviderCapabilities, StreamEvent, ToolCall, Usage,
+    };
+
+    fn decode(transcript: &[&str]) -> Vec<StreamEvent> {
+        let stream = OllamaChatStream::state(None, None, 0);
+        transcript
+            .iter()
+            .filter_map(|raw| stream.decode_message("", raw).unwrap())
+            .collect()
+    }
+
+    /// Decodes a recorded NDJSON transcript from the `fixtures` directory the way a live stream
+    /// receiving it would
+    fn decode_transcript(transcript: &str) -> Vec<StreamEvent> {
+        LlmChatStream::new(OllamaChatStream::state(None, None, 0))
+            .decode_transcript(transcript, StreamFormat::NdJson)
+    }
+
+    #[test]
+    fn content_transcript_decodes_to_text_deltas_and_a_finish() {
+        let events = decode_transcript(include_str!("../fixtures/content.ndjson"));
+
+        let (finish, deltas) = events.split_last().unwrap();
+        let mut text = String::new();
+        for event in deltas {
+            match event {
+                StreamEvent::Delta(delta) => {
+                    assert_eq!(delta.tool_calls, None);
+                    append_delta_text(&mut text, delta);
+                }
+                event => panic!("Expected a delta event, got {event:?}"),
+            }
+        }
+        assert_eq!(deltas.len(), 3);
+        assert_eq!(text, "The capital of France is Paris.");
+
+        match finish {
+            StreamEvent::Finish(metadata) => {
+                assert_eq!(metadata.finish_reason, Some(FinishReason::Stop));
+                assert_eq!(
+                    metadata.usage,
+                    Some(Usage {
+                        input_tokens: Some(26),
+                        output_tokens: Some(8),
+                        total_tokens: Some(34),
+                    })
+                );
+                assert_eq!(metadata.model, Some("llama3.2".to_string()));
+                assert_eq!(
+                    metadata.timestamp,
+                    Some("2025-01-01T00:00:00.4Z".to_string())
+                );
+            }
+            event => panic!("Expected a finish event, got {event:?}"),
+        }
+    }
+
+    #[test]
+    fn tool_call_transcript_decodes_to_tool_calls_and_a_tool_calls_finish() {
+        let events = decode_transcript(include_str!("../fixtures/tool_call.ndjson"));
+
+        match events.as_slice() {
+            [StreamEvent::Delta(delta), StreamEvent::Finish(metadata)] => {
+                assert_eq!(delta.content, None);
+                assert_eq!(
+                    delta.tool_calls,
+                    Some(vec![
+                        ToolCall {
+                            id: "ollama-2025-01-01T00:00:01.1Z-0".to_string(),
+                            name: "get_weather".to_string(),
+                            arguments_json: r#"{"city":"Paris","unit":"celsius"}"#.to_string(),
+                        },
+                        ToolCall {
+                            id: "ollama-2025-01-01T00:00:01.1Z-1".to_string(),
+                            name: "get_time".to_string(),
+                            arguments_json: r#"{"timezone":"Europe/Paris"}"#.to_string(),
+                        },
+                    ])
+                );
+                // Ollama reports `stop` after tool calls
+                assert_eq!(metadata.finish_reason, Some(FinishReason::ToolCalls));
+            }
+            events => panic!("Expected a tool call delta and a finish, got {events:?}"),
+        }
+    }
+
+    #[test]
+    fn length_transcript_finishes_with_the_length_reason() {
+        let events = decode_transcript(include_str!("../fixtures/length.ndjson"));
+
+        match events.last() {
+            Some(StreamEvent::Finish(metadata)) => {
+                assert_eq!(metadata.finish_reason, Some(FinishReason::Length));
+                assert_eq!(
+                    metadata
+                        .usage
+                        .as_ref()
+                        .and_then(|usage| usage.output_tokens),
+                    Some(4)
+                );
+            }
+            event => panic!("Expected a finish event, got {event:?}"),
+        }
+    }
+
+    #[test]
+    fn transcript_with_two_done_lines_finishes_once() {
+        let events = decode_transcript(include_str!("../fixtures/double_done.ndjson"));
+
+        match events.as_slice() {
+            [StreamEvent::Delta(delta), StreamEvent::Finish(metadata)] => {
+                let mut text = String::new();
+                append_delta_text(&mut text, delta);
+                assert_eq!(text, "Bonjour");
+                assert_eq!(
+                    metadata.timestamp,
+                    Some("2025-01-01T00:00:03.2Z".to_string())
+                );
+            }
+            events => panic!("Expected a delta and a single finish, got {events:?}"),
+        }
+    }
+
+    #[test]
+    fn finish_falls_back_to_the_last_seen_creation_time() {
+        let events = decode(&[
+            r#"{"model":"llama3.2","created_at":"2025-01-01T00:00:00.1Z","message":{"role":"assistant","content":"Hel"},"done":false}"#,
+            r#"{"model":"llama3.2","created_at":"2025-01-01T00:00:00.2Z","message":{"role":"assistant","content":"lo"},"done":false}"#,
+            r#"{"model":"llama3.2","message":{"role":"assistant","content":""},"done":true,"done_reason":"stop","prompt_eval_count":10,"eval_count":2}"#,
+        ]);
+
+        match events.last() {
+            Some(StreamEvent::Finish(metadata)) => assert_eq!(
+                metadata.timestamp,
+                Some("2025-01-01T00:00:00.2Z".to_string())
+            ),
+            event => panic!("Expected a finish event, got {event:?}"),
+        }
+    }
+
+    #[test]
+    fn tool_call_ids_fall_back_to_the_last_seen_creation_time() {
+        let events = decode(&[
+            r#"{"model":"llama3.2","created_at":"2025-01-01T00:00:00.1Z","message":{"role":"assistant","content":""},"done":false}"#,
+            r#"{"model":"llama3.2","message":{"role":"assistant","content":"","tool_calls":[{"function":{"name":"get_weather","arguments":{"city":"Paris"}}}]},"done":false}"#,
+        ]);
+
+        match events.last() {
+            Some(StreamEvent::Delta(delta)) => assert_eq!(
+                delta.tool_calls,
+                Some(vec![ToolCall {
+                    id: "ollama-2025-01-01T00:00:00.1Z-0".to_string(),
+                    name: "get_weather".to_string(),
+                    arguments_json: r#"{"city":"Paris"}"#.to_string(),
+                }])
+            ),
+            event => panic!("Expected a delta event, got {event:?}"),
+        }
+    }
+
+    #[test]
+    fn capabilities_describe_the_provider() {
+        assert_eq!(
+            OllamaComponent::capabilities(),
+            ProviderCapabilities {
+                provider: "ollama".to_string(),
+                streaming: true,
+                tools: true,
+                vision: true,
+                embeddings: false,
+                json_schema: true,
+            }
+        );
+    }
+}
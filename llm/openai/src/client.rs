@@ -1,13 +1,27 @@
-use golem_llm::error::{error_code_from_status, from_event_source_error, from_reqwest_error};
-use golem_llm::event_source::EventSource;
-use golem_llm::golem::llm::llm::Error;
+use golem_llm::body::read_body;
+use golem_llm::error::{
+    context_length_exceeded, error_code_from_response, from_event_source_error, from_reqwest_error,
+    number_after,
+};
+use golem_llm::event_source::{EventSource, StreamFormat, StreamFormatHint};
+use golem_llm::extra_body::ExtraBody;
+use golem_llm::golem::llm::llm::{Error, ErrorCode, ProviderHealth};
+use golem_llm::health::probe;
+use golem_llm::proxy::http_client;
+use golem_llm::rate_limit::{rate_limit_info, RateLimited};
+use golem_llm::raw::send_raw;
+use golem_llm::retry::send_with_connection_retry;
 use log::trace;
-use reqwest::header::HeaderValue;
-use reqwest::{Client, Method, Response};
+use reqwest::header::{HeaderMap, HeaderValue};
+use reqwest::{Client, Method, Response, StatusCode};
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fmt::Debug;
 
+/// Error code of requests exceeding the model's context window
+pub const CONTEXT_LENGTH_EXCEEDED_CODE: &str = "context_length_exceeded";
+
 const BASE_URL: &str = "https://api.openai.com";
 
 /// The OpenAI API client for creating model responses.
@@ -15,59 +29,177 @@ const BASE_URL: &str = "https://api.openai.com";
 /// Based on https://platform.openai.com/docs/api-reference/responses/create
 pub struct ResponsesApi {
     openai_api_key: String,
+    extra_headers: HeaderMap,
+    /// Fields added to the chat request bodies
+    extra_body: ExtraBody,
     client: Client,
 }
 
 impl ResponsesApi {
-    pub fn new(openai_api_key: String) -> Self {
-        let client = Client::builder()
-            .build()
-            .expect("Failed to initialize HTTP client");
-        Self {
+    pub fn new(openai_api_key: String, extra_headers: HeaderMap) -> Result<Self, Error> {
+        let client = http_client(BASE_URL)?;
+        Ok(Self {
             openai_api_key,
+            extra_headers,
+            extra_body: ExtraBody::default(),
             client,
-        }
+        })
+    }
+
+    /// Adds the `extra_body` of the config to the chat requests
+    pub fn with_extra_body(mut self, extra_body: ExtraBody) -> Self {
+        self.extra_body = extra_body;
+        self
     }
 
     pub fn create_model_response(
         &self,
         request: CreateModelResponseRequest,
-    ) -> Result<CreateModelResponseResponse, Error> {
+    ) -> Result<RateLimited<CreateModelResponseResponse>, Error> {
+        trace!("Sending request to OpenAI API: {request:?}");
+
+        let body = self.extra_body.merge(&request)?;
+
+        let response: Response = send_with_connection_retry(|| {
+            self.client
+                .request(Method::POST, format!("{BASE_URL}/v1/responses"))
+                .bearer_auth(&self.openai_api_key)
+                .json(&body)
+                .headers(self.extra_headers.clone())
+                .send()
+        })
+        .map_err(|err| from_reqwest_error("Request failed", err))?;
+
+        let rate_limit = rate_limit_info(response.headers());
+        parse_response(response).map(|body| RateLimited { body, rate_limit })
+    }
+
+    pub fn stream_model_response(
+        &self,
+        request: CreateModelResponseRequest,
+    ) -> Result<EventSource, Error> {
         trace!("Sending request to OpenAI API: {request:?}");
 
+        let body = self.extra_body.merge(&request)?;
+
         let response: Response = self
             .client
             .request(Method::POST, format!("{BASE_URL}/v1/responses"))
             .bearer_auth(&self.openai_api_key)
-            .json(&request)
+            .header(
+                reqwest::header::ACCEPT,
+                HeaderValue::from_static("text/event-stream"),
+            )
+            .json(&body)
+            .headers(self.extra_headers.clone())
             .send()
             .map_err(|err| from_reqwest_error("Request failed", err))?;
 
-        parse_response(response)
+        trace!("Initializing SSE stream");
+
+        EventSource::new(
+            response,
+            StreamFormatHint::Detect(StreamFormat::EventStream),
+        )
+        .map_err(|err| from_event_source_error("Failed to create SSE stream", err))
     }
 
-    pub fn stream_model_response(
+    /// Sends the JSON body untouched to the Responses API, returning the raw response body
+    pub fn send_raw(&self, body_json: String) -> Result<String, Error> {
+        send_raw(
+            self.client
+                .request(Method::POST, format!("{BASE_URL}/v1/responses"))
+                .bearer_auth(&self.openai_api_key),
+            self.extra_headers.clone(),
+            body_json,
+        )
+    }
+
+    /// Creates a chat completion, used instead of the Responses API for audio output, which only
+    /// the Chat Completions API supports.
+    ///
+    /// Based on https://platform.openai.com/docs/api-reference/chat/create
+    pub fn create_chat_completion(
         &self,
-        request: CreateModelResponseRequest,
+        request: ChatCompletionRequest,
+    ) -> Result<RateLimited<ChatCompletionResponse>, Error> {
+        trace!("Sending request to OpenAI API: {request:?}");
+
+        let body = self.extra_body.merge(&request)?;
+
+        let response: Response = send_with_connection_retry(|| {
+            self.client
+                .request(Method::POST, format!("{BASE_URL}/v1/chat/completions"))
+                .bearer_auth(&self.openai_api_key)
+                .json(&body)
+                .headers(self.extra_headers.clone())
+                .send()
+        })
+        .map_err(|err| from_reqwest_error("Request failed", err))?;
+
+        let rate_limit = rate_limit_info(response.headers());
+        parse_response(response).map(|body| RateLimited { body, rate_limit })
+    }
+
+    pub fn stream_chat_completion(
+        &self,
+        request: ChatCompletionRequest,
     ) -> Result<EventSource, Error> {
         trace!("Sending request to OpenAI API: {request:?}");
 
+        let body = self.extra_body.merge(&request)?;
+
         let response: Response = self
             .client
-            .request(Method::POST, format!("{BASE_URL}/v1/responses"))
+            .request(Method::POST, format!("{BASE_URL}/v1/chat/completions"))
             .bearer_auth(&self.openai_api_key)
             .header(
                 reqwest::header::ACCEPT,
                 HeaderValue::from_static("text/event-stream"),
             )
-            .json(&request)
+            .json(&body)
+            .headers(self.extra_headers.clone())
             .send()
             .map_err(|err| from_reqwest_error("Request failed", err))?;
 
         trace!("Initializing SSE stream");
 
-        EventSource::new(response)
-            .map_err(|err| from_event_source_error("Failed to create SSE stream", err))
+        EventSource::new(
+            response,
+            StreamFormatHint::Detect(StreamFormat::EventStream),
+        )
+        .map_err(|err| from_event_source_error("Failed to create SSE stream", err))
+    }
+
+    /// Generates images from a prompt.
+    ///
+    /// Based on https://platform.openai.com/docs/api-reference/images/create
+    pub fn create_image(&self, request: CreateImageRequest) -> Result<CreateImageResponse, Error> {
+        trace!("Sending request to OpenAI API: {request:?}");
+
+        let response: Response = self
+            .client
+            .request(Method::POST, format!("{BASE_URL}/v1/images/generations"))
+            .bearer_auth(&self.openai_api_key)
+            .json(&request)
+            .headers(self.extra_headers.clone())
+            .send()
+            .map_err(|err| from_reqwest_error("Request failed", err))?;
+
+        parse_response(response)
+    }
+
+    /// Checks the connectivity by listing the available models
+    pub fn ping(&self) -> Result<ProviderHealth, Error> {
+        trace!("Checking the connectivity of the OpenAI API");
+
+        probe(|| {
+            self.client
+                .request(Method::GET, format!("{BASE_URL}/v1/models"))
+                .bearer_auth(&self.openai_api_key)
+                .headers(self.extra_headers.clone())
+                .send()
+        })
     }
 }
 
@@ -88,18 +220,67 @@ pub struct CreateModelResponseRequest {
     pub top_p: Option<f32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub user: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub service_tier: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<HashMap<String, String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reasoning: Option<Reasoning>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub store: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prompt_cache_key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream_options: Option<ResponseStreamOptions>,
+
+    /// Whether the events are obfuscated when the request is streamed, see
+    /// `to_streaming_request`
+    #[serde(skip)]
+    pub include_stream_obfuscation: Option<bool>,
+    /// Output modalities requested with the `modalities` provider option. Requests with audio
+    /// output are sent to the Chat Completions API, see `to_chat_completion_request`
+    #[serde(skip)]
+    pub modalities: Vec<String>,
+    /// Voice and format of the audio output, set when the modalities include audio
+    #[serde(skip)]
+    pub audio: Option<AudioOutput>,
+}
+
+/// Voice and format of the spoken audio generated by the audio models
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AudioOutput {
+    pub voice: String,
+    /// `wav`, `mp3`, `flac`, `opus`, `aac` or `pcm16`, only `pcm16` can be streamed
+    pub format: String,
+}
+
+/// Options of a streamed response. The Responses API always reports the usage in the completed
+/// response, so unlike Chat Completions there is no `include_usage` option.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResponseStreamOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub include_obfuscation: Option<bool>,
+}
+
+/// Reasoning configuration of reasoning models
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Reasoning {
+    /// `low`, `medium` or `high`
+    pub effort: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CreateModelResponseResponse {
     pub id: String,
     pub created_at: u64,
+    pub model: String,
     pub error: Option<ErrorObject>,
     pub incomplete_details: Option<IncompleteDetailsObject>,
     pub status: Status,
     pub output: Vec<OutputItem>,
     pub usage: Option<Usage>,
     pub metadata: Option<serde_json::Value>,
+    pub service_tier: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -120,17 +301,52 @@ pub enum OutputItem {
         id: String,
         status: Status,
     },
+    /// Call of the hosted web search tool, whose results are cited by the following message
+    #[serde(rename = "web_search_call")]
+    WebSearchCall {
+        id: String,
+        status: String,
+        action: Option<WebSearchAction>,
+    },
+    /// Output of the other hosted tools, which are not decoded
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebSearchAction {
+    pub query: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub enum OutputMessageContent {
     #[serde(rename = "output_text")]
-    Text { text: String },
+    Text {
+        text: String,
+        #[serde(default)]
+        annotations: Vec<OutputTextAnnotation>,
+    },
     #[serde(rename = "refusal")]
     Refusal { refusal: String },
 }
 
+/// Annotation of an output text. Only URL citations refer to a span of the text, the other kinds
+/// (such as file citations) are ignored
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum OutputTextAnnotation {
+    #[serde(rename = "url_citation")]
+    UrlCitation {
+        url: String,
+        title: Option<String>,
+        start_index: u32,
+        end_index: u32,
+    },
+    #[serde(other)]
+    Other,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ErrorObject {
     pub code: String,
@@ -185,8 +401,9 @@ pub enum InputItem {
     ToolResult {
         /// The unique ID of the function tool call generated by the model.
         call_id: String,
-        /// A JSON string of the output of the function tool call.
-        output: String,
+        /// A JSON string of the output of the function tool call, or the text and images it
+        /// returned.
+        output: InnerInput,
     },
 }
 
@@ -233,6 +450,9 @@ pub enum Tool {
         parameters: Option<serde_json::Value>,
         strict: bool,
     },
+    /// The hosted web search tool, run by OpenAI itself
+    #[serde(rename = "web_search")]
+    WebSearch {},
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -262,33 +482,364 @@ pub struct ResponseOutputTextDelta {
     pub output_index: u32,
 }
 
+/// Next part of the refusal of the model, streamed apart from its output text
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResponseRefusalDelta {
+    pub delta: String,
+}
+
+/// The complete refusal of the model, sent once all its parts have been streamed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResponseRefusalDone {
+    pub refusal: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResponseOutputTextAnnotationAdded {
+    pub annotation: OutputTextAnnotation,
+    pub content_index: u32,
+    pub item_id: String,
+    pub output_index: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateImageRequest {
+    pub prompt: String,
+    pub model: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub n: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<String>,
+    /// Either `url` or `b64_json`, the GPT image models only return `b64_json` and reject it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quality: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub style: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output_format: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub background: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateImageResponse {
+    pub created: u64,
+    pub data: Vec<GeneratedImage>,
+    /// Only reported by the GPT image models
+    pub usage: Option<ImageUsage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GeneratedImage {
+    pub url: Option<String>,
+    pub b64_json: Option<String>,
+    pub revised_prompt: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageUsage {
+    pub input_tokens: u32,
+    pub output_tokens: u32,
+    pub total_tokens: u32,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ResponseOutputItemDone {
     pub item: OutputItem,
     pub output_index: u32,
 }
 
+/// A request to the Chat Completions API, only used for audio output
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatCompletionRequest {
+    pub messages: Vec<ChatMessage>,
+    pub model: String,
+    pub modalities: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub audio: Option<AudioOutput>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_completion_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tools: Vec<ChatTool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_choice: Option<String>,
+    pub stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream_options: Option<ChatStreamOptions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub service_tier: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<HashMap<String, String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub store: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prompt_cache_key: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatStreamOptions {
+    pub include_usage: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum ChatTool {
+    #[serde(rename = "function")]
+    Function { function: ChatFunction },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatFunction {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parameters: Option<serde_json::Value>,
+    pub strict: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "role")]
+pub enum ChatMessage {
+    #[serde(rename = "system")]
+    System { content: String },
+    #[serde(rename = "user")]
+    User { content: Vec<ChatContentPart> },
+    #[serde(rename = "assistant")]
+    Assistant {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        content: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        tool_calls: Option<Vec<ChatToolCall>>,
+    },
+    #[serde(rename = "tool")]
+    Tool {
+        content: String,
+        tool_call_id: String,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum ChatContentPart {
+    #[serde(rename = "text")]
+    Text { text: String },
+    #[serde(rename = "image_url")]
+    ImageUrl { image_url: ChatImageUrl },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatImageUrl {
+    pub url: String,
+    pub detail: Detail,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum ChatToolCall {
+    #[serde(rename = "function")]
+    Function {
+        function: ChatFunctionCall,
+        id: String,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatFunctionCall {
+    pub arguments: String,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatCompletionResponse {
+    pub choices: Vec<ChatChoice>,
+    pub created: u64,
+    pub id: String,
+    pub model: String,
+    pub usage: Option<ChatUsage>,
+    pub service_tier: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatChoice {
+    pub finish_reason: Option<String>,
+    pub message: ChatResponseMessage,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatResponseMessage {
+    pub content: Option<String>,
+    pub refusal: Option<String>,
+    pub audio: Option<ChatAudio>,
+    pub tool_calls: Option<Vec<ChatToolCall>>,
+}
+
+/// Audio output of a chat completion, with its base64 encoded data
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatAudio {
+    pub id: String,
+    pub data: String,
+    pub expires_at: u64,
+    pub transcript: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatUsage {
+    pub completion_tokens: u32,
+    pub prompt_tokens: u32,
+    pub total_tokens: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatCompletionChunk {
+    pub id: String,
+    pub created: u64,
+    pub model: String,
+    pub choices: Vec<ChatChoiceChunk>,
+    pub usage: Option<ChatUsage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatChoiceChunk {
+    pub delta: ChatDelta,
+    pub finish_reason: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatDelta {
+    pub content: Option<String>,
+    pub refusal: Option<String>,
+    pub audio: Option<ChatAudioChunk>,
+    pub tool_calls: Option<Vec<ChatToolCallChunk>>,
+}
+
+/// A fragment of the streamed audio output, with the next part of its base64 encoded data or of
+/// its transcript
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatAudioChunk {
+    pub id: Option<String>,
+    pub data: Option<String>,
+    pub transcript: Option<String>,
+    pub expires_at: Option<u64>,
+}
+
+/// A fragment of a streamed tool call. The first fragment of a call has its id and name, the
+/// following ones only the next part of its arguments.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatToolCallChunk {
+    pub index: u32,
+    pub id: Option<String>,
+    pub function: Option<ChatFunctionCallChunk>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatFunctionCallChunk {
+    pub name: Option<String>,
+    pub arguments: Option<String>,
+}
+
 fn parse_response<T: DeserializeOwned + Debug>(response: Response) -> Result<T, Error> {
     let status = response.status();
     if status.is_success() {
-        let body = response
-            .json::<T>()
-            .map_err(|err| from_reqwest_error("Failed to decode response body", err))?;
+        let raw_body = read_body(response, "Failed to receive response body")?;
+        let body = serde_json::from_str::<T>(&raw_body).map_err(|err| Error {
+            code: ErrorCode::InternalError,
+            message: format!("Failed to decode response body: {err}"),
+            provider_error_json: None,
+        })?;
 
         trace!("Received response from OpenAI API: {body:?}");
 
         Ok(body)
     } else {
-        let body = response
-            .text()
-            .map_err(|err| from_reqwest_error("Failed to receive error response body", err))?;
+        let body = read_body(response, "Failed to receive error response body")?;
 
         trace!("Received {status} response from OpenAI API: {body:?}");
 
-        Err(Error {
-            code: error_code_from_status(status),
+        Err(error_from_body(status, body))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErrorResponse {
+    pub error: ErrorResponseDetails,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErrorResponseDetails {
+    pub message: String,
+    pub code: Option<String>,
+}
+
+/// Converts the body of a failed response, reporting errors with the `context_length_exceeded`
+/// code as `context-length-exceeded` with the model's context window parsed from the message
+fn error_from_body(status: StatusCode, body: String) -> Error {
+    match serde_json::from_str::<ErrorResponse>(&body) {
+        Ok(ErrorResponse { error })
+            if error.code.as_deref() == Some(CONTEXT_LENGTH_EXCEEDED_CODE) =>
+        {
+            let limit = number_after(&error.message, "maximum context length");
+            context_length_exceeded(&error.message, limit, Some(body))
+        }
+        _ => Error {
+            code: error_code_from_response(status, &body),
             message: format!("Request failed with {status}"),
             provider_error_json: Some(body),
-        })
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::error_from_body;
+    use golem_llm::golem::llm::llm::ErrorCode;
+    use reqwest::StatusCode;
+
+    #[test]
+    fn context_length_error_reports_the_context_window() {
+        let body = r#"{
+            "error": {
+                "message": "This model's maximum context length is 128000 tokens. However, your messages resulted in 130412 tokens. Please reduce the length of the messages.",
+                "type": "invalid_request_error",
+                "param": "input",
+                "code": "context_length_exceeded"
+            }
+        }"#;
+
+        let error = error_from_body(StatusCode::BAD_REQUEST, body.to_string());
+
+        assert_eq!(error.code, ErrorCode::ContextLengthExceeded);
+        assert!(
+            error.message.contains("context window of 128000 tokens"),
+            "{}",
+            error.message
+        );
+        assert_eq!(error.provider_error_json, Some(body.to_string()));
+    }
+
+    #[test]
+    fn other_invalid_requests_are_not_context_length_errors() {
+        let body = r#"{
+            "error": {
+                "message": "Unsupported parameter: 'temperature' is not supported with this model.",
+                "type": "invalid_request_error",
+                "param": "temperature",
+                "code": "unsupported_parameter"
+            }
+        }"#;
+
+        let error = error_from_body(StatusCode::BAD_REQUEST, body.to_string());
+
+        assert_eq!(error.code, ErrorCode::InvalidRequest);
     }
 }
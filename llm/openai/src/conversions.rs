@@ -1,62 +1,456 @@
 use crate::client::{
-    CreateModelResponseRequest, CreateModelResponseResponse, Detail, InnerInput, InnerInputItem,
-    Input, InputItem, OutputItem, OutputMessageContent, Tool,
+    AudioOutput, ChatCompletionRequest, ChatCompletionResponse, ChatContentPart, ChatFunction,
+    ChatFunctionCall, ChatImageUrl, ChatMessage, ChatStreamOptions, ChatTool, ChatToolCall,
+    ChatUsage, CreateImageRequest, CreateImageResponse, CreateModelResponseRequest,
+    CreateModelResponseResponse, Detail, InnerInput, InnerInputItem, Input, InputItem, OutputItem,
+    OutputMessageContent, OutputTextAnnotation, Reasoning, ResponseStreamOptions, Tool,
+    CONTEXT_LENGTH_EXCEEDED_CODE,
 };
 use base64::{engine::general_purpose, Engine as _};
-use golem_llm::error::error_code_from_status;
+use golem_llm::capabilities::{
+    check_request_capabilities, check_streaming_support, ModelCapabilities,
+};
+use golem_llm::config::model_or_default;
+use golem_llm::conversions::{
+    check_provider_options, non_empty_tool_call_id, split_tool_results, validate_temperature,
+};
+use golem_llm::error::{error_code_from_status, unsupported};
 use golem_llm::golem::llm::llm::{
-    ChatEvent, CompleteResponse, Config, ContentPart, Error, ErrorCode, ImageDetail,
-    ImageReference, Message, ResponseMetadata, Role, ToolCall, ToolDefinition, ToolResult, Usage,
+    Annotation, AudioSource, ChatEvent, CompleteResponse, Config, ContentPart, Error, ErrorCode,
+    FinishReason, ImageDetail, ImageGenConfig, ImageGenResult, ImageReference, ImageResponseFormat,
+    ImageSource, ImageUrl, Message, ReasoningEffort, ResponseMetadata, Role, ToolCall,
+    ToolDefinition, ToolResult, ToolResultContent, Usage,
 };
+use golem_llm::usage::with_total_tokens;
+use log::trace;
 use reqwest::StatusCode;
 use std::collections::HashMap;
+use std::ops::RangeInclusive;
 use std::str::FromStr;
 
+/// Range of temperatures accepted by OpenAI
+const TEMPERATURE_RANGE: RangeInclusive<f32> = 0.0..=2.0;
+
+/// Provider options read by the OpenAI conversions, any other key is reported as unknown
+const PROVIDER_OPTIONS: [&str; 5] = [
+    "top_p",
+    "user",
+    WEB_SEARCH_OPTION,
+    MODALITIES_OPTION,
+    AUDIO_OPTION,
+];
+
+/// Provider option enabling OpenAI's hosted web search tool when set to `true`
+const WEB_SEARCH_OPTION: &str = "web_search";
+
+/// Provider option listing the output modalities separated by commas, such as `text,audio` to
+/// get spoken audio from the audio models
+const MODALITIES_OPTION: &str = "modalities";
+
+/// Provider option with the voice and format of the audio output as JSON, such as
+/// `{"voice":"alloy","format":"wav"}`
+const AUDIO_OPTION: &str = "audio";
+
+/// Voice and format of the audio output when the `audio` provider option is not set
+const DEFAULT_AUDIO_VOICE: &str = "alloy";
+const DEFAULT_AUDIO_FORMAT: &str = "wav";
+
+/// Provider options read by the image generation conversion
+const IMAGE_PROVIDER_OPTIONS: [&str; 5] =
+    ["quality", "style", "output_format", "background", "user"];
+
+/// Model id prefixes of the reasoning models, which reject the `temperature` and `top_p` sampling
+/// parameters. Their output limit is set with `max_output_tokens` like for any other model, as the
+/// Responses API has no separate `max_completion_tokens` parameter.
+const REASONING_MODEL_PREFIXES: [&str; 4] = ["o1", "o3", "o4", "gpt-5"];
+
+/// Capabilities of the known OpenAI models, requests to other models are not validated
+const MODEL_CAPABILITIES: [(&str, ModelCapabilities); 18] = [
+    ("gpt-5", ModelCapabilities::ALL),
+    ("gpt-5-mini", ModelCapabilities::ALL),
+    ("gpt-5-nano", ModelCapabilities::ALL),
+    ("gpt-4.1", ModelCapabilities::ALL),
+    ("gpt-4.1-mini", ModelCapabilities::ALL),
+    ("gpt-4.1-nano", ModelCapabilities::ALL),
+    ("gpt-4o", ModelCapabilities::ALL),
+    ("gpt-4o-mini", ModelCapabilities::ALL),
+    ("gpt-4-turbo", ModelCapabilities::ALL),
+    ("gpt-4", ModelCapabilities::TEXT_ONLY),
+    ("gpt-3.5-turbo", ModelCapabilities::TEXT_ONLY),
+    ("o1", ModelCapabilities::ALL),
+    ("o1-mini", REASONING_PREVIEW_MODEL_CAPABILITIES),
+    ("o1-pro", PRO_MODEL_CAPABILITIES),
+    ("o3", ModelCapabilities::ALL),
+    ("o3-mini", ModelCapabilities::TEXT_ONLY),
+    ("o3-pro", PRO_MODEL_CAPABILITIES),
+    ("o4-mini", ModelCapabilities::ALL),
+];
+
+/// The preview reasoning models take neither images nor tools
+const REASONING_PREVIEW_MODEL_CAPABILITIES: ModelCapabilities = ModelCapabilities {
+    vision: false,
+    tools: false,
+    streaming: true,
+};
+
+/// The pro models cannot stream their responses
+const PRO_MODEL_CAPABILITIES: ModelCapabilities = ModelCapabilities {
+    vision: true,
+    tools: true,
+    streaming: false,
+};
+
+fn is_reasoning_model(model: &str) -> bool {
+    REASONING_MODEL_PREFIXES
+        .iter()
+        .any(|prefix| model.starts_with(prefix))
+}
+
 pub fn create_request(
     items: Vec<InputItem>,
     config: Config,
-    tools: Vec<Tool>,
-) -> CreateModelResponseRequest {
+    mut tools: Vec<Tool>,
+) -> Result<CreateModelResponseRequest, Error> {
     let options = config
         .provider_options
         .into_iter()
         .map(|kv| (kv.key, kv.value))
         .collect::<HashMap<_, _>>();
+    check_provider_options(&options, &PROVIDER_OPTIONS)?;
+    if options
+        .get(WEB_SEARCH_OPTION)
+        .is_some_and(|web_search| web_search == "true")
+    {
+        tools.push(Tool::WebSearch {});
+    }
+    let (modalities, audio) = output_modalities(&options)?;
 
-    CreateModelResponseRequest {
+    let model = model_or_default(config.model)?;
+    check_request_capabilities(
+        &MODEL_CAPABILITIES,
+        &model,
+        items.iter().any(has_image),
+        !tools.is_empty(),
+    )?;
+    let temperature = validate_temperature(config.temperature, TEMPERATURE_RANGE)?;
+    let top_p = options
+        .get("top_p")
+        .and_then(|top_p_s| top_p_s.parse::<f32>().ok());
+    let reasoning_model = is_reasoning_model(&model);
+    let (temperature, top_p) = if reasoning_model {
+        if temperature.is_some() || top_p.is_some() {
+            trace!("Dropping the sampling parameters not supported by the reasoning model {model}");
+        }
+        (None, None)
+    } else {
+        (temperature, top_p)
+    };
+    let reasoning = match config.reasoning_effort {
+        Some(effort) if reasoning_model => Some(Reasoning {
+            effort: to_openai_reasoning_effort(effort).to_string(),
+        }),
+        Some(_) => {
+            trace!("Dropping the reasoning effort not supported by the model {model}");
+            None
+        }
+        None => None,
+    };
+
+    Ok(CreateModelResponseRequest {
         input: Input::List(items),
-        model: config.model,
-        temperature: config.temperature,
+        model,
+        temperature,
         max_output_tokens: config.max_tokens,
         tools,
         tool_choice: config.tool_choice,
         stream: false,
-        top_p: options
-            .get("top_p")
-            .and_then(|top_p_s| top_p_s.parse::<f32>().ok()),
+        top_p,
         user: options
             .get("user")
             .and_then(|user_s| user_s.parse::<String>().ok()),
+        service_tier: config.service_tier,
+        metadata: if config.metadata.is_empty() {
+            None
+        } else {
+            Some(config.metadata.into_iter().collect())
+        },
+        reasoning,
+        store: config.store,
+        prompt_cache_key: config.prompt_cache_key,
+        stream_options: None,
+        include_stream_obfuscation: config
+            .stream_options
+            .and_then(|stream_options| stream_options.include_obfuscation),
+        modalities,
+        audio,
+    })
+}
+
+/// Reads the output modalities, and the voice and format of the audio when they include audio
+fn output_modalities(
+    options: &HashMap<String, String>,
+) -> Result<(Vec<String>, Option<AudioOutput>), Error> {
+    let modalities = options
+        .get(MODALITIES_OPTION)
+        .map(|modalities| {
+            modalities
+                .split(',')
+                .map(|modality| modality.trim().to_string())
+                .filter(|modality| !modality.is_empty())
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    if !modalities.iter().any(|modality| modality == "audio") {
+        return Ok((modalities, None));
+    }
+
+    let audio = match options.get(AUDIO_OPTION) {
+        Some(audio) => serde_json::from_str(audio).map_err(|err| Error {
+            code: ErrorCode::InvalidRequest,
+            message: format!("Invalid {AUDIO_OPTION} provider option: {err}"),
+            provider_error_json: None,
+        })?,
+        None => AudioOutput {
+            voice: DEFAULT_AUDIO_VOICE.to_string(),
+            format: DEFAULT_AUDIO_FORMAT.to_string(),
+        },
+    };
+    Ok((modalities, Some(audio)))
+}
+
+/// Converts a request with audio output to a Chat Completions request, as the Responses API
+/// cannot generate audio. The hosted web search tool is not available there.
+pub fn to_chat_completion_request(
+    request: CreateModelResponseRequest,
+) -> Result<ChatCompletionRequest, Error> {
+    let items = match request.input {
+        Input::List(items) => items,
+        Input::TextInput(text) => vec![InputItem::InputMessage {
+            content: InnerInput::TextInput(text),
+            role: "user".to_string(),
+        }],
+    };
+    let tools = request
+        .tools
+        .into_iter()
+        .map(|tool| match tool {
+            Tool::Function {
+                name,
+                description,
+                parameters,
+                strict,
+            } => Ok(ChatTool::Function {
+                function: ChatFunction {
+                    name,
+                    description,
+                    parameters,
+                    strict,
+                },
+            }),
+            Tool::WebSearch {} => Err(unsupported("web search with audio output")),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(ChatCompletionRequest {
+        messages: input_items_to_chat_messages(items),
+        model: request.model,
+        modalities: request.modalities,
+        audio: request.audio,
+        temperature: request.temperature,
+        max_completion_tokens: request.max_output_tokens,
+        tools,
+        tool_choice: request.tool_choice,
+        stream: false,
+        stream_options: None,
+        top_p: request.top_p,
+        user: request.user,
+        service_tier: request.service_tier,
+        metadata: request.metadata,
+        store: request.store,
+        prompt_cache_key: request.prompt_cache_key,
+    })
+}
+
+/// Turns a chat completion request into a streaming request, always asking for the usage which
+/// is reported by the last event of the stream
+pub fn to_streaming_chat_completion_request(
+    mut request: ChatCompletionRequest,
+) -> ChatCompletionRequest {
+    request.stream = true;
+    request.stream_options = Some(ChatStreamOptions {
+        include_usage: true,
+    });
+    request
+}
+
+/// Converts the input items to chat messages. The function calls following an assistant message
+/// are added to it, and images are only kept in user messages, as tool messages only accept text.
+fn input_items_to_chat_messages(items: Vec<InputItem>) -> Vec<ChatMessage> {
+    let mut messages = Vec::new();
+    for item in items {
+        match item {
+            InputItem::InputMessage { content, role } => {
+                let parts = match content {
+                    InnerInput::TextInput(text) => vec![ChatContentPart::Text { text }],
+                    InnerInput::List(items) => items
+                        .into_iter()
+                        .map(|item| match item {
+                            InnerInputItem::TextInput { text } => ChatContentPart::Text { text },
+                            InnerInputItem::ImageInput { image_url, detail } => {
+                                ChatContentPart::ImageUrl {
+                                    image_url: ChatImageUrl {
+                                        url: image_url,
+                                        detail,
+                                    },
+                                }
+                            }
+                        })
+                        .collect(),
+                };
+                messages.push(match role.as_str() {
+                    "system" => ChatMessage::System {
+                        content: chat_text(parts),
+                    },
+                    "assistant" => ChatMessage::Assistant {
+                        content: Some(chat_text(parts)),
+                        tool_calls: None,
+                    },
+                    _ => ChatMessage::User { content: parts },
+                });
+            }
+            InputItem::ToolCall {
+                arguments,
+                call_id,
+                name,
+            } => {
+                let tool_call = ChatToolCall::Function {
+                    function: ChatFunctionCall { arguments, name },
+                    id: call_id,
+                };
+                match messages.last_mut() {
+                    Some(ChatMessage::Assistant { tool_calls, .. }) => {
+                        tool_calls.get_or_insert_with(Vec::new).push(tool_call)
+                    }
+                    _ => messages.push(ChatMessage::Assistant {
+                        content: None,
+                        tool_calls: Some(vec![tool_call]),
+                    }),
+                }
+            }
+            InputItem::ToolResult { call_id, output } => messages.push(ChatMessage::Tool {
+                content: match output {
+                    InnerInput::TextInput(text) => text,
+                    InnerInput::List(items) => items
+                        .into_iter()
+                        .filter_map(|item| match item {
+                            InnerInputItem::TextInput { text } => Some(text),
+                            InnerInputItem::ImageInput { .. } => None,
+                        })
+                        .collect::<Vec<_>>()
+                        .join("\n"),
+                },
+                tool_call_id: call_id,
+            }),
+        }
     }
+    messages
 }
 
-pub fn messages_to_input_items(messages: Vec<Message>) -> Vec<InputItem> {
+fn chat_text(parts: Vec<ChatContentPart>) -> String {
+    parts
+        .into_iter()
+        .filter_map(|part| match part {
+            ChatContentPart::Text { text } => Some(text),
+            ChatContentPart::ImageUrl { .. } => None,
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Turns a request into a streaming request, sending the obfuscation setting of the
+/// `stream-options` of the config if any. Their `include_usage` is ignored, as the usage is always
+/// reported at the end of the stream.
+pub fn to_streaming_request(mut request: CreateModelResponseRequest) -> CreateModelResponseRequest {
+    request.stream = true;
+    request.stream_options = request
+        .include_stream_obfuscation
+        .map(|include_obfuscation| ResponseStreamOptions {
+            include_obfuscation: Some(include_obfuscation),
+        });
+    request
+}
+
+/// Fails if the model is known not to support streaming
+pub fn check_streaming(model: &str) -> Result<(), Error> {
+    check_streaming_support(&MODEL_CAPABILITIES, model)
+}
+
+fn has_image(item: &InputItem) -> bool {
+    match item {
+        InputItem::InputMessage {
+            content: InnerInput::List(parts),
+            ..
+        }
+        | InputItem::ToolResult {
+            output: InnerInput::List(parts),
+            ..
+        } => parts
+            .iter()
+            .any(|part| matches!(part, InnerInputItem::ImageInput { .. })),
+        _ => false,
+    }
+}
+
+fn to_openai_reasoning_effort(effort: ReasoningEffort) -> &'static str {
+    match effort {
+        ReasoningEffort::Low => "low",
+        ReasoningEffort::Medium => "medium",
+        ReasoningEffort::High => "high",
+    }
+}
+
+/// Converts the messages to input items. OpenAI expects a single system prompt, so all the system
+/// messages are merged into one leading system message, keeping their relative order. Tool
+/// results included in the messages become function call and output items in their place.
+pub fn messages_to_input_items(messages: Vec<Message>) -> Result<Vec<InputItem>, Error> {
+    let (system_messages, messages): (Vec<_>, Vec<_>) = messages
+        .into_iter()
+        .partition(|message| message.role == Role::System);
+
     let mut items = Vec::new();
+    if !system_messages.is_empty() {
+        items.push(message_to_input_item(
+            Role::System,
+            system_messages
+                .into_iter()
+                .flat_map(|message| message.content)
+                .collect(),
+        ));
+    }
     for message in messages {
-        let role = to_openai_role_name(message.role).to_string();
-        let mut input_items = Vec::new();
-        for content_part in message.content {
-            input_items.push(content_part_to_inner_input_item(content_part));
+        let (content, tool_results) = split_tool_results(message.content, non_empty_tool_call_id)?;
+        if !content.is_empty() || tool_results.is_empty() {
+            items.push(message_to_input_item(message.role, content));
         }
+        items.extend(tool_results_to_input_items(tool_results));
+    }
+    Ok(items)
+}
 
-        items.push(InputItem::InputMessage {
-            role,
-            content: InnerInput::List(input_items),
-        });
+fn message_to_input_item(role: Role, content: Vec<ContentPart>) -> InputItem {
+    let mut input_items = Vec::new();
+    for content_part in content {
+        input_items.extend(content_part_to_inner_input_item(content_part));
+    }
+
+    InputItem::InputMessage {
+        role: to_openai_role_name(role).to_string(),
+        content: InnerInput::List(input_items),
     }
-    items
 }
 
+/// Converts the tool results to function call and output items. The output of a successful call
+/// is its text and images when the tool returned any, and its result JSON otherwise.
 pub fn tool_results_to_input_items(tool_results: Vec<(ToolCall, ToolResult)>) -> Vec<InputItem> {
     let mut items = Vec::new();
     for (tool_call, tool_result) in tool_results {
@@ -66,17 +460,30 @@ pub fn tool_results_to_input_items(tool_results: Vec<(ToolCall, ToolResult)>) ->
             name: tool_call.name,
         };
         let tool_result = match tool_result {
+            ToolResult::Success(success) if !success.content.is_empty() => InputItem::ToolResult {
+                call_id: success.id,
+                output: InnerInput::List(
+                    success
+                        .content
+                        .into_iter()
+                        .map(tool_result_content_to_inner_input_item)
+                        .collect(),
+                ),
+            },
             ToolResult::Success(success) => InputItem::ToolResult {
                 call_id: success.id,
-                output: format!(r#"{{ "success": {} }}"#, success.result_json),
+                output: InnerInput::TextInput(format!(
+                    r#"{{ "success": {} }}"#,
+                    success.result_json
+                )),
             },
             ToolResult::Error(error) => InputItem::ToolResult {
                 call_id: error.id,
-                output: format!(
+                output: InnerInput::TextInput(format!(
                     r#"{{ "error": {{ "code": {}, "message": {} }} }}"#,
                     error.error_code.unwrap_or_default(),
                     error.error_message
-                ),
+                )),
             },
         };
         items.push(tool_call);
@@ -122,40 +529,65 @@ pub fn to_openai_role_name(role: Role) -> &'static str {
     }
 }
 
-pub fn content_part_to_inner_input_item(content_part: ContentPart) -> InnerInputItem {
+/// Converts a text or image part of a message. Tool results are not part of a message's content
+/// for OpenAI, they are converted to separate items by `messages_to_input_items`, the
+/// reasoning of earlier responses is not sent back, audio is sent back as its transcript and
+/// refusals as text.
+pub fn content_part_to_inner_input_item(content_part: ContentPart) -> Option<InnerInputItem> {
     match content_part {
-        ContentPart::Text(msg) => InnerInputItem::TextInput { text: msg },
-        ContentPart::Image(image_reference) => match image_reference {
-            ImageReference::Url(image_url) => InnerInputItem::ImageInput {
-                image_url: image_url.url,
-                detail: match image_url.detail {
-                    Some(ImageDetail::Auto) => Detail::Auto,
-                    Some(ImageDetail::Low) => Detail::Low,
-                    Some(ImageDetail::High) => Detail::High,
-                    None => Detail::default(),
-                },
-            },
-            ImageReference::Inline(image_source) => {
-                let base64_data = general_purpose::STANDARD.encode(&image_source.data);
-                let mime_type = &image_source.mime_type; // This is already a string
-                let data_url = format!("data:{};base64,{}", mime_type, base64_data);
-
-                InnerInputItem::ImageInput {
-                    image_url: data_url,
-                    detail: match image_source.detail {
-                        Some(ImageDetail::Auto) => Detail::Auto,
-                        Some(ImageDetail::Low) => Detail::Low,
-                        Some(ImageDetail::High) => Detail::High,
-                        None => Detail::default(),
-                    },
-                }
-            }
+        ContentPart::Text(msg) => Some(InnerInputItem::TextInput { text: msg }),
+        ContentPart::Image(image_reference) => {
+            Some(image_reference_to_inner_input_item(image_reference))
+        }
+        ContentPart::Audio(audio) => audio
+            .transcript
+            .map(|text| InnerInputItem::TextInput { text }),
+        ContentPart::Refusal(text) => Some(InnerInputItem::TextInput { text }),
+        ContentPart::ToolResult(_) | ContentPart::Reasoning(_) => None,
+    }
+}
+
+fn tool_result_content_to_inner_input_item(content: ToolResultContent) -> InnerInputItem {
+    match content {
+        ToolResultContent::Text(text) => InnerInputItem::TextInput { text },
+        ToolResultContent::Image(image_reference) => {
+            image_reference_to_inner_input_item(image_reference)
+        }
+    }
+}
+
+fn image_reference_to_inner_input_item(image_reference: ImageReference) -> InnerInputItem {
+    match image_reference {
+        ImageReference::Url(image_url) => InnerInputItem::ImageInput {
+            image_url: image_url.url,
+            detail: to_openai_detail(image_url.detail),
         },
+        ImageReference::Inline(image_source) => {
+            let base64_data = general_purpose::STANDARD.encode(&image_source.data);
+            let mime_type = &image_source.mime_type; // This is already a string
+            let data_url = format!("data:{};base64,{}", mime_type, base64_data);
+
+            InnerInputItem::ImageInput {
+                image_url: data_url,
+                detail: to_openai_detail(image_source.detail),
+            }
+        }
+    }
+}
+
+fn to_openai_detail(detail: Option<ImageDetail>) -> Detail {
+    match detail {
+        Some(ImageDetail::Auto) => Detail::Auto,
+        Some(ImageDetail::Low) => Detail::Low,
+        Some(ImageDetail::High) => Detail::High,
+        None => Detail::default(),
     }
 }
 
 pub fn parse_error_code(code: String) -> ErrorCode {
-    if let Some(code) = <u16 as FromStr>::from_str(&code)
+    if code == CONTEXT_LENGTH_EXCEEDED_CODE {
+        ErrorCode::ContextLengthExceeded
+    } else if let Some(code) = <u16 as FromStr>::from_str(&code)
         .ok()
         .and_then(|code| StatusCode::from_u16(code).ok())
     {
@@ -175,6 +607,10 @@ pub fn process_model_response(response: CreateModelResponseResponse) -> ChatEven
     } else {
         let mut contents = Vec::new();
         let mut tool_calls = Vec::new();
+        let mut annotations = Vec::new();
+        // Annotation spans are relative to their output text, so they are offset by the length of
+        // the text before it
+        let mut text_length = 0;
 
         let metadata = create_response_metadata(&response);
 
@@ -183,11 +619,19 @@ pub fn process_model_response(response: CreateModelResponseResponse) -> ChatEven
                 OutputItem::Message { content, .. } => {
                     for content in content {
                         match content {
-                            OutputMessageContent::Text { text, .. } => {
+                            OutputMessageContent::Text {
+                                text,
+                                annotations: text_annotations,
+                            } => {
+                                annotations.extend(text_annotations.into_iter().filter_map(
+                                    |annotation| convert_annotation(annotation, text_length),
+                                ));
+                                text_length += text.chars().count() as u32;
                                 contents.push(ContentPart::Text(text));
                             }
+                            // Not part of the text, so the annotation spans are not moved
                             OutputMessageContent::Refusal { refusal, .. } => {
-                                contents.push(ContentPart::Text(format!("Refusal: {refusal}")));
+                                contents.push(ContentPart::Refusal(refusal));
                             }
                         }
                     }
@@ -205,10 +649,14 @@ pub fn process_model_response(response: CreateModelResponseResponse) -> ChatEven
                     };
                     tool_calls.push(tool_call);
                 }
+                // Hosted tools are run by OpenAI, so their calls are not returned as tool calls.
+                // The web searches are reported in the provider metadata, and their results as
+                // the URL citations of the message.
+                OutputItem::WebSearchCall { .. } | OutputItem::Other => {}
             }
         }
 
-        if contents.is_empty() {
+        if contents.is_empty() && !tool_calls.is_empty() {
             ChatEvent::ToolRequest(tool_calls)
         } else {
             ChatEvent::Message(CompleteResponse {
@@ -216,21 +664,1118 @@ pub fn process_model_response(response: CreateModelResponseResponse) -> ChatEven
                 content: contents,
                 tool_calls,
                 metadata,
+                annotations,
             })
         }
     }
 }
 
+/// Converts a URL citation to an annotation, moving its span by `offset` characters
+pub fn convert_annotation(annotation: OutputTextAnnotation, offset: u32) -> Option<Annotation> {
+    match annotation {
+        OutputTextAnnotation::UrlCitation {
+            url,
+            title,
+            start_index,
+            end_index,
+        } => Some(Annotation {
+            url: Some(url),
+            title,
+            start_index: start_index + offset,
+            end_index: end_index + offset,
+        }),
+        OutputTextAnnotation::Other => None,
+    }
+}
+
+/// Converts a chat completion with audio output. The audio is decoded from base64, with the MIME
+/// type of the requested format, and its id and expiry, by which OpenAI can refer to it in the
+/// following requests, are reported in the provider metadata.
+pub fn process_chat_completion(response: ChatCompletionResponse, audio_format: &str) -> ChatEvent {
+    let Some(choice) = response.choices.into_iter().next() else {
+        return ChatEvent::Error(Error {
+            code: ErrorCode::InternalError,
+            message: "No choices in response".to_string(),
+            provider_error_json: None,
+        });
+    };
+
+    let mut content = Vec::new();
+    let mut provider_metadata = serde_json::Map::new();
+    if let Some(service_tier) = response.service_tier {
+        provider_metadata.insert(
+            "service_tier".to_string(),
+            serde_json::Value::String(service_tier),
+        );
+    }
+    if let Some(text) = choice.message.content.filter(|text| !text.is_empty()) {
+        content.push(ContentPart::Text(text));
+    }
+    if let Some(refusal) = choice.message.refusal {
+        content.push(ContentPart::Refusal(refusal));
+    }
+    if let Some(audio) = choice.message.audio {
+        let data = match decode_audio(&audio.data) {
+            Ok(data) => data,
+            Err(err) => return ChatEvent::Error(err),
+        };
+        content.push(ContentPart::Audio(AudioSource {
+            data,
+            mime_type: audio_mime_type(audio_format),
+            transcript: Some(audio.transcript),
+        }));
+        provider_metadata.insert("audio_id".to_string(), audio.id.into());
+        provider_metadata.insert("audio_expires_at".to_string(), audio.expires_at.into());
+    }
+    let tool_calls = choice
+        .message
+        .tool_calls
+        .unwrap_or_default()
+        .into_iter()
+        .map(|ChatToolCall::Function { function, id }| ToolCall {
+            id,
+            name: function.name,
+            arguments_json: function.arguments,
+        })
+        .collect::<Vec<_>>();
+
+    let metadata = ResponseMetadata {
+        finish_reason: choice
+            .finish_reason
+            .as_deref()
+            .map(convert_chat_finish_reason),
+        usage: response.usage.map(convert_chat_usage),
+        provider_id: Some(response.id.clone()),
+        model: Some(response.model),
+        timestamp: Some(response.created.to_string()),
+        provider_metadata_json: if provider_metadata.is_empty() {
+            None
+        } else {
+            Some(serde_json::Value::Object(provider_metadata).to_string())
+        },
+        tokens_per_second: None,
+        stop_sequence: None,
+    };
+
+    if content.is_empty() && !tool_calls.is_empty() {
+        ChatEvent::ToolRequest(tool_calls)
+    } else {
+        ChatEvent::Message(CompleteResponse {
+            id: response.id,
+            content,
+            tool_calls,
+            metadata,
+            annotations: vec![],
+        })
+    }
+}
+
+/// Decodes the base64 encoded data of an audio output
+pub fn decode_audio(data: &str) -> Result<Vec<u8>, Error> {
+    general_purpose::STANDARD.decode(data).map_err(|err| Error {
+        code: ErrorCode::InternalError,
+        message: format!("Failed to decode audio output: {err}"),
+        provider_error_json: None,
+    })
+}
+
+/// The MIME type of an audio output format. The `pcm16` format is raw 16-bit little-endian
+/// samples at 24kHz
+pub fn audio_mime_type(format: &str) -> String {
+    match format {
+        "mp3" => "audio/mpeg".to_string(),
+        "pcm16" => "audio/pcm".to_string(),
+        other => format!("audio/{other}"),
+    }
+}
+
+pub fn convert_chat_finish_reason(finish_reason: &str) -> FinishReason {
+    match finish_reason {
+        "stop" => FinishReason::Stop,
+        "length" => FinishReason::Length,
+        "tool_calls" => FinishReason::ToolCalls,
+        "content_filter" => FinishReason::ContentFilter,
+        _ => FinishReason::Other,
+    }
+}
+
+pub fn convert_chat_usage(usage: ChatUsage) -> Usage {
+    with_total_tokens(Usage {
+        input_tokens: Some(usage.prompt_tokens),
+        output_tokens: Some(usage.completion_tokens),
+        total_tokens: Some(usage.total_tokens),
+    })
+}
+
 pub fn create_response_metadata(response: &CreateModelResponseResponse) -> ResponseMetadata {
     ResponseMetadata {
         finish_reason: None,
-        usage: response.usage.as_ref().map(|usage| Usage {
-            input_tokens: Some(usage.input_tokens),
-            output_tokens: Some(usage.output_tokens),
-            total_tokens: Some(usage.total_tokens),
+        usage: response.usage.as_ref().map(|usage| {
+            with_total_tokens(Usage {
+                input_tokens: Some(usage.input_tokens),
+                output_tokens: Some(usage.output_tokens),
+                total_tokens: Some(usage.total_tokens),
+            })
         }),
         provider_id: Some(response.id.clone()),
+        model: Some(response.model.clone()),
         timestamp: Some(response.created_at.to_string()),
-        provider_metadata_json: response.metadata.as_ref().map(|m| m.to_string()),
+        provider_metadata_json: provider_metadata_json(response),
+        tokens_per_second: None,
+        stop_sequence: None,
+    }
+}
+
+/// Returns the response's metadata, extended with the service tier actually used for
+/// processing the request and the web searches made by the hosted web search tool
+fn provider_metadata_json(response: &CreateModelResponseResponse) -> Option<String> {
+    let web_search_calls = response
+        .output
+        .iter()
+        .filter_map(|item| match item {
+            OutputItem::WebSearchCall { id, status, action } => Some(serde_json::json!({
+                "id": id,
+                "status": status,
+                "query": action.as_ref().and_then(|action| action.query.clone()),
+            })),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    if response.service_tier.is_none() && web_search_calls.is_empty() {
+        return response.metadata.as_ref().map(|m| m.to_string());
+    }
+
+    let mut metadata = match &response.metadata {
+        Some(serde_json::Value::Object(metadata)) => metadata.clone(),
+        _ => serde_json::Map::new(),
+    };
+    if let Some(service_tier) = &response.service_tier {
+        metadata.insert(
+            "service_tier".to_string(),
+            serde_json::Value::String(service_tier.clone()),
+        );
+    }
+    if !web_search_calls.is_empty() {
+        metadata.insert(
+            "web_search_calls".to_string(),
+            serde_json::Value::Array(web_search_calls),
+        );
+    }
+    Some(serde_json::Value::Object(metadata).to_string())
+}
+
+pub fn image_generation_request(
+    prompt: String,
+    config: ImageGenConfig,
+) -> Result<CreateImageRequest, Error> {
+    let options = config
+        .provider_options
+        .into_iter()
+        .map(|kv| (kv.key, kv.value))
+        .collect::<HashMap<_, _>>();
+    check_provider_options(&options, &IMAGE_PROVIDER_OPTIONS)?;
+
+    Ok(CreateImageRequest {
+        prompt,
+        model: model_or_default(config.model)?,
+        n: config.n,
+        size: config.size,
+        response_format: config.response_format.map(|format| {
+            match format {
+                ImageResponseFormat::Url => "url",
+                ImageResponseFormat::Inline => "b64_json",
+            }
+            .to_string()
+        }),
+        quality: options.get("quality").cloned(),
+        style: options.get("style").cloned(),
+        output_format: options.get("output_format").cloned(),
+        background: options.get("background").cloned(),
+        user: options.get("user").cloned(),
+    })
+}
+
+/// Converts the generated images to image references. Inline images are decoded from base64,
+/// their MIME type following the requested `output_format`, PNG by default. The prompts revised
+/// by the model are reported as `revised_prompts` in the provider metadata.
+pub fn process_image_response(
+    response: CreateImageResponse,
+    model: String,
+    output_format: Option<String>,
+) -> Result<ImageGenResult, Error> {
+    let mime_type = format!("image/{}", output_format.as_deref().unwrap_or("png"));
+
+    let mut images = Vec::new();
+    let mut revised_prompts = Vec::new();
+    for image in response.data {
+        let image_reference = match (image.url, image.b64_json) {
+            (_, Some(b64_json)) => {
+                let data = general_purpose::STANDARD
+                    .decode(b64_json)
+                    .map_err(|err| Error {
+                        code: ErrorCode::InternalError,
+                        message: format!("Failed to decode generated image: {err}"),
+                        provider_error_json: None,
+                    })?;
+                ImageReference::Inline(ImageSource {
+                    data,
+                    mime_type: mime_type.clone(),
+                    detail: None,
+                })
+            }
+            (Some(url), None) => ImageReference::Url(ImageUrl { url, detail: None }),
+            (None, None) => {
+                return Err(Error {
+                    code: ErrorCode::InternalError,
+                    message: "Generated image has neither a URL nor data".to_string(),
+                    provider_error_json: None,
+                })
+            }
+        };
+        images.push(image_reference);
+        revised_prompts.extend(image.revised_prompt);
+    }
+
+    Ok(ImageGenResult {
+        images,
+        usage: response.usage.map(|usage| {
+            with_total_tokens(Usage {
+                input_tokens: Some(usage.input_tokens),
+                output_tokens: Some(usage.output_tokens),
+                total_tokens: Some(usage.total_tokens),
+            })
+        }),
+        model: Some(model),
+        provider_metadata_json: if revised_prompts.is_empty() {
+            None
+        } else {
+            Some(serde_json::json!({ "revised_prompts": revised_prompts }).to_string())
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::{
+        ChatCompletionResponse, CreateModelResponseResponse, OutputItem, ResponseOutputItemDone,
+        Status,
+    };
+    use crate::conversions::{
+        check_streaming, create_request, create_response_metadata, has_image,
+        image_generation_request, messages_to_input_items, process_chat_completion,
+        process_image_response, process_model_response, to_chat_completion_request,
+        to_streaming_request, tool_defs_to_tools, tool_results_to_input_items,
+    };
+    use golem_llm::golem::llm::llm::{
+        Annotation, AudioSource, ChatEvent, Config, ContentPart, ErrorCode, FinishReason,
+        ImageDetail, ImageGenConfig, ImageReference, ImageResponseFormat, ImageSource, ImageUrl,
+        Kv, Message, ReasoningEffort, Role, StreamOptions, ToolCall, ToolDefinition, ToolResult,
+        ToolResultContent, ToolSuccess,
+    };
+
+    fn config(model: &str) -> Config {
+        Config {
+            model: model.to_string(),
+            temperature: Some(0.5),
+            max_tokens: Some(1000),
+            stop_sequences: None,
+            tools: vec![],
+            tool_choice: None,
+            provider_options: vec![Kv {
+                key: "top_p".to_string(),
+                value: "0.9".to_string(),
+            }],
+            extra_headers: vec![],
+            service_tier: None,
+            metadata: vec![],
+            reasoning_effort: None,
+            store: None,
+            prompt_cache_key: None,
+            stream_options: None,
+            deadline_ms: None,
+            extra_body: None,
+        }
+    }
+
+    #[test]
+    fn reasoning_models_get_no_sampling_parameters() {
+        for model in ["o1", "o3-mini", "o4-mini-2025-04-16", "gpt-5-mini"] {
+            let request =
+                serde_json::to_value(create_request(vec![], config(model), vec![]).unwrap())
+                    .unwrap();
+
+            assert_eq!(request["max_output_tokens"], 1000, "{model}");
+            assert!(request.get("max_tokens").is_none(), "{model}");
+            assert!(request.get("temperature").is_none(), "{model}");
+            assert!(request.get("top_p").is_none(), "{model}");
+        }
+    }
+
+    #[test]
+    fn other_models_keep_sampling_parameters() {
+        for model in ["gpt-4o", "gpt-4.1-mini"] {
+            let request =
+                serde_json::to_value(create_request(vec![], config(model), vec![]).unwrap())
+                    .unwrap();
+
+            assert_eq!(request["max_output_tokens"], 1000, "{model}");
+            assert!(request.get("max_tokens").is_none(), "{model}");
+            assert_eq!(request["temperature"], 0.5, "{model}");
+            assert!(request.get("top_p").is_some(), "{model}");
+        }
+    }
+
+    #[test]
+    fn reasoning_effort_is_only_sent_to_reasoning_models() {
+        for (model, effort) in [
+            ("o3-mini", Some("high")),
+            ("gpt-5-mini", Some("high")),
+            ("gpt-4o", None),
+        ] {
+            let request = create_request(
+                vec![],
+                Config {
+                    reasoning_effort: Some(ReasoningEffort::High),
+                    ..config(model)
+                },
+                vec![],
+            )
+            .unwrap();
+
+            let json = serde_json::to_value(&request).unwrap();
+            assert_eq!(
+                json.get("reasoning").map(|reasoning| &reasoning["effort"]),
+                effort.map(serde_json::Value::from).as_ref(),
+                "{model}"
+            );
+        }
+    }
+
+    #[test]
+    fn images_are_rejected_for_text_only_models() {
+        let items = || {
+            messages_to_input_items(vec![Message {
+                role: Role::User,
+                name: None,
+                content: vec![ContentPart::Image(ImageReference::Url(ImageUrl {
+                    url: "https://example.com/image.png".to_string(),
+                    detail: None,
+                }))],
+            }])
+            .unwrap()
+        };
+
+        assert!(create_request(items(), config("gpt-4o"), vec![]).is_ok());
+
+        let error = create_request(items(), config("gpt-3.5-turbo"), vec![]).unwrap_err();
+        assert_eq!(error.code, ErrorCode::InvalidRequest);
+        assert_eq!(
+            error.message,
+            "Model gpt-3.5-turbo does not support image input"
+        );
+    }
+
+    #[test]
+    fn tools_are_rejected_for_models_without_tool_support() {
+        let tools = || {
+            tool_defs_to_tools(&[ToolDefinition {
+                name: "get_weather".to_string(),
+                description: None,
+                parameters_schema: r#"{"type":"object"}"#.to_string(),
+            }])
+            .unwrap()
+        };
+
+        assert!(create_request(vec![], config("o3-mini"), tools()).is_ok());
+
+        let error = create_request(vec![], config("o1-mini"), tools()).unwrap_err();
+        assert_eq!(error.code, ErrorCode::InvalidRequest);
+        assert_eq!(error.message, "Model o1-mini does not support tools");
+    }
+
+    #[test]
+    fn streaming_is_rejected_for_known_pro_models_only() {
+        assert!(check_streaming("o1-pro").is_err());
+        assert!(check_streaming("gpt-4o").is_ok());
+        assert!(check_streaming("o1-pro-2025-03-19").is_ok());
+    }
+
+    #[test]
+    fn request_carries_service_tier() {
+        let request = create_request(
+            vec![],
+            Config {
+                model: "gpt-4o-mini".to_string(),
+                temperature: None,
+                max_tokens: None,
+                stop_sequences: None,
+                tools: vec![],
+                tool_choice: None,
+                provider_options: vec![],
+                extra_headers: vec![],
+                service_tier: Some("flex".to_string()),
+                metadata: vec![],
+                reasoning_effort: None,
+                store: None,
+                prompt_cache_key: None,
+                stream_options: None,
+                deadline_ms: None,
+                extra_body: None,
+            },
+            vec![],
+        )
+        .unwrap();
+
+        assert_eq!(request.service_tier, Some("flex".to_string()));
+        assert!(serde_json::to_string(&request)
+            .unwrap()
+            .contains(r#""service_tier":"flex""#));
+    }
+
+    #[test]
+    fn request_carries_store_and_prompt_cache_key() {
+        let request = create_request(
+            vec![],
+            Config {
+                store: Some(false),
+                prompt_cache_key: Some("tenant-acme".to_string()),
+                ..config("gpt-4o-mini")
+            },
+            vec![],
+        )
+        .unwrap();
+
+        let json = serde_json::to_value(&request).unwrap();
+        assert_eq!(json["store"], serde_json::json!(false));
+        assert_eq!(json["prompt_cache_key"], serde_json::json!("tenant-acme"));
+
+        let json =
+            serde_json::to_value(create_request(vec![], config("gpt-4o-mini"), vec![]).unwrap())
+                .unwrap();
+        assert!(json.get("store").is_none());
+        assert!(json.get("prompt_cache_key").is_none());
+    }
+
+    #[test]
+    fn streamed_request_carries_the_obfuscation_setting() {
+        let streaming_request_json = |stream_options| {
+            let request = create_request(
+                vec![],
+                Config {
+                    stream_options,
+                    ..config("gpt-4o-mini")
+                },
+                vec![],
+            )
+            .unwrap();
+            serde_json::to_value(to_streaming_request(request)).unwrap()
+        };
+
+        let json = streaming_request_json(Some(StreamOptions {
+            include_usage: true,
+            include_obfuscation: Some(false),
+        }));
+        assert_eq!(json["stream"], true);
+        assert_eq!(
+            json["stream_options"],
+            serde_json::json!({"include_obfuscation": false})
+        );
+
+        let json = streaming_request_json(None);
+        assert_eq!(json["stream"], true);
+        assert!(json.get("stream_options").is_none());
+    }
+
+    #[test]
+    fn request_carries_metadata() {
+        let request = create_request(
+            vec![],
+            Config {
+                metadata: vec![("tenant".to_string(), "acme".to_string())],
+                ..config("gpt-4o-mini")
+            },
+            vec![],
+        )
+        .unwrap();
+
+        let json = serde_json::to_value(&request).unwrap();
+        assert_eq!(json["metadata"], serde_json::json!({"tenant": "acme"}));
+    }
+
+    #[test]
+    fn used_service_tier_is_in_provider_metadata() {
+        let response = CreateModelResponseResponse {
+            id: "resp_1".to_string(),
+            created_at: 1746000000,
+            model: "gpt-4o-mini".to_string(),
+            error: None,
+            incomplete_details: None,
+            status: Status::Completed,
+            output: vec![],
+            usage: None,
+            metadata: Some(serde_json::json!({"user_key": "value"})),
+            service_tier: Some("default".to_string()),
+        };
+
+        let metadata = create_response_metadata(&response);
+        let provider_metadata: serde_json::Value =
+            serde_json::from_str(&metadata.provider_metadata_json.unwrap()).unwrap();
+        assert_eq!(
+            provider_metadata,
+            serde_json::json!({"user_key": "value", "service_tier": "default"})
+        );
+    }
+
+    fn message(role: Role, text: &str) -> Message {
+        Message {
+            role,
+            name: None,
+            content: vec![ContentPart::Text(text.to_string())],
+        }
+    }
+
+    #[test]
+    fn system_messages_are_merged_into_a_leading_one() {
+        let items = messages_to_input_items(vec![
+            message(Role::System, "Be concise"),
+            message(Role::User, "Hi"),
+            message(Role::System, "Answer in French"),
+            message(Role::Assistant, "Bonjour"),
+        ])
+        .unwrap();
+
+        assert_eq!(
+            serde_json::to_value(items).unwrap(),
+            serde_json::json!([
+                {
+                    "type": "message",
+                    "role": "system",
+                    "content": [
+                        { "type": "input_text", "text": "Be concise" },
+                        { "type": "input_text", "text": "Answer in French" }
+                    ]
+                },
+                {
+                    "type": "message",
+                    "role": "user",
+                    "content": [{ "type": "input_text", "text": "Hi" }]
+                },
+                {
+                    "type": "message",
+                    "role": "assistant",
+                    "content": [{ "type": "input_text", "text": "Bonjour" }]
+                }
+            ])
+        );
+    }
+
+    #[test]
+    fn tool_results_in_the_history_become_function_call_items() {
+        let items = messages_to_input_items(vec![
+            message(Role::User, "What's the weather in Paris?"),
+            Message {
+                role: Role::Tool,
+                name: None,
+                content: vec![ContentPart::ToolResult((
+                    ToolCall {
+                        id: "call_1".to_string(),
+                        name: "get_weather".to_string(),
+                        arguments_json: r#"{"city":"Paris"}"#.to_string(),
+                    },
+                    ToolResult::Success(ToolSuccess {
+                        id: "call_1".to_string(),
+                        name: "get_weather".to_string(),
+                        result_json: r#"{"temperature":22}"#.to_string(),
+                        execution_time_ms: None,
+                        content: vec![],
+                    }),
+                ))],
+            },
+            message(Role::Assistant, "It is 22 degrees in Paris."),
+        ])
+        .unwrap();
+
+        assert_eq!(
+            serde_json::to_value(items).unwrap(),
+            serde_json::json!([
+                {
+                    "type": "message",
+                    "role": "user",
+                    "content": [{ "type": "input_text", "text": "What's the weather in Paris?" }]
+                },
+                {
+                    "type": "function_call",
+                    "arguments": r#"{"city":"Paris"}"#,
+                    "call_id": "call_1",
+                    "name": "get_weather"
+                },
+                {
+                    "type": "function_call_output",
+                    "call_id": "call_1",
+                    "output": r#"{ "success": {"temperature":22} }"#
+                },
+                {
+                    "type": "message",
+                    "role": "assistant",
+                    "content": [{ "type": "input_text", "text": "It is 22 degrees in Paris." }]
+                }
+            ])
+        );
+    }
+
+    #[test]
+    fn tool_results_with_content_are_sent_as_text_and_images() {
+        let tool_call = ToolCall {
+            id: "call_1".to_string(),
+            name: "render_chart".to_string(),
+            arguments_json: r#"{"city":"Paris"}"#.to_string(),
+        };
+        let tool_result = ToolResult::Success(ToolSuccess {
+            id: "call_1".to_string(),
+            name: "render_chart".to_string(),
+            result_json: r#"{"rendered":true}"#.to_string(),
+            execution_time_ms: None,
+            content: vec![
+                ToolResultContent::Text("Temperatures of the week".to_string()),
+                ToolResultContent::Image(ImageReference::Inline(ImageSource {
+                    data: vec![137, 80, 78, 71],
+                    mime_type: "image/png".to_string(),
+                    detail: Some(ImageDetail::Low),
+                })),
+            ],
+        });
+
+        let items = tool_results_to_input_items(vec![(tool_call, tool_result)]);
+
+        assert_eq!(
+            serde_json::to_value(&items[1]).unwrap(),
+            serde_json::json!({
+                "type": "function_call_output",
+                "call_id": "call_1",
+                "output": [
+                    { "type": "input_text", "text": "Temperatures of the week" },
+                    {
+                        "type": "input_image",
+                        "image_url": "data:image/png;base64,iVBORw==",
+                        "detail": "low"
+                    }
+                ]
+            })
+        );
+        assert!(items.iter().any(has_image));
+    }
+
+    #[test]
+    fn image_detail_is_forwarded() {
+        let items = messages_to_input_items(vec![Message {
+            role: Role::User,
+            name: None,
+            content: vec![
+                ContentPart::Image(ImageReference::Url(ImageUrl {
+                    url: "https://example.com/image.png".to_string(),
+                    detail: Some(ImageDetail::Low),
+                })),
+                ContentPart::Image(ImageReference::Inline(ImageSource {
+                    data: vec![1, 2, 3],
+                    mime_type: "image/png".to_string(),
+                    detail: Some(ImageDetail::High),
+                })),
+            ],
+        }])
+        .unwrap();
+
+        assert_eq!(
+            serde_json::to_value(items).unwrap(),
+            serde_json::json!([
+                {
+                    "type": "message",
+                    "role": "user",
+                    "content": [
+                        {
+                            "type": "input_image",
+                            "image_url": "https://example.com/image.png",
+                            "detail": "low"
+                        },
+                        {
+                            "type": "input_image",
+                            "image_url": "data:image/png;base64,AQID",
+                            "detail": "high"
+                        }
+                    ]
+                }
+            ])
+        );
+    }
+
+    #[test]
+    fn url_citations_become_annotations_of_the_response_text() {
+        let response: CreateModelResponseResponse = serde_json::from_value(serde_json::json!({
+            "id": "resp_1",
+            "created_at": 1746000000,
+            "model": "gpt-4o-mini",
+            "error": null,
+            "incomplete_details": null,
+            "status": "completed",
+            "output": [{
+                "type": "message",
+                "id": "msg_1",
+                "role": "assistant",
+                "status": "completed",
+                "content": [
+                    { "type": "output_text", "text": "Golem is durable.", "annotations": [] },
+                    {
+                        "type": "output_text",
+                        "text": " It is open source.",
+                        "annotations": [
+                            {
+                                "type": "url_citation",
+                                "url": "https://github.com/golemcloud",
+                                "title": "Golem Cloud",
+                                "start_index": 1,
+                                "end_index": 19
+                            },
+                            { "type": "file_citation", "index": 0, "file_id": "file_1" }
+                        ]
+                    }
+                ]
+            }],
+            "usage": null,
+            "metadata": null,
+            "service_tier": null
+        }))
+        .unwrap();
+
+        let ChatEvent::Message(message) = process_model_response(response) else {
+            panic!("Expected a message");
+        };
+        assert_eq!(
+            message.annotations,
+            vec![Annotation {
+                url: Some("https://github.com/golemcloud".to_string()),
+                title: Some("Golem Cloud".to_string()),
+                start_index: 18,
+                end_index: 36,
+            }]
+        );
+    }
+
+    #[test]
+    fn refusals_are_not_part_of_the_response_text() {
+        let response: CreateModelResponseResponse = serde_json::from_value(serde_json::json!({
+            "id": "resp_2",
+            "created_at": 1746000000,
+            "model": "gpt-4o-mini",
+            "error": null,
+            "incomplete_details": null,
+            "status": "completed",
+            "output": [{
+                "type": "message",
+                "id": "msg_2",
+                "role": "assistant",
+                "status": "completed",
+                "content": [
+                    { "type": "output_text", "text": "I can explain locks in general.", "annotations": [] },
+                    { "type": "refusal", "refusal": "I can't help with picking this lock." }
+                ]
+            }],
+            "usage": null,
+            "metadata": null,
+            "service_tier": null
+        }))
+        .unwrap();
+
+        let ChatEvent::Message(message) = process_model_response(response) else {
+            panic!("Expected a message");
+        };
+        assert_eq!(
+            message.content,
+            vec![
+                ContentPart::Text("I can explain locks in general.".to_string()),
+                ContentPart::Refusal("I can't help with picking this lock.".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn web_search_is_requested_as_a_hosted_tool() {
+        let request = create_request(
+            vec![],
+            Config {
+                provider_options: vec![Kv {
+                    key: "web_search".to_string(),
+                    value: "true".to_string(),
+                }],
+                ..config("gpt-4o-mini")
+            },
+            vec![],
+        )
+        .unwrap();
+
+        let json = serde_json::to_value(&request).unwrap();
+        assert_eq!(json["tools"], serde_json::json!([{ "type": "web_search" }]));
+    }
+
+    #[test]
+    fn web_search_results_are_decoded() {
+        // Output of a response to "What's new in Golem?" with the web search tool enabled
+        let output = serde_json::json!([
+            {
+                "type": "web_search_call",
+                "id": "ws_1",
+                "status": "completed",
+                "action": { "type": "search", "query": "golem cloud latest release" }
+            },
+            {
+                "type": "message",
+                "id": "msg_1",
+                "role": "assistant",
+                "status": "completed",
+                "content": [{
+                    "type": "output_text",
+                    "text": "Golem 1.2 added durable LLM calls.",
+                    "annotations": [{
+                        "type": "url_citation",
+                        "url": "https://golem.cloud/blog",
+                        "title": "Golem blog",
+                        "start_index": 0,
+                        "end_index": 34
+                    }]
+                }]
+            }
+        ]);
+
+        let item_done: ResponseOutputItemDone = serde_json::from_value(serde_json::json!({
+            "type": "response.output_item.done",
+            "output_index": 0,
+            "item": output[0]
+        }))
+        .unwrap();
+        assert!(matches!(item_done.item, OutputItem::WebSearchCall { .. }));
+
+        let response: CreateModelResponseResponse = serde_json::from_value(serde_json::json!({
+            "id": "resp_1",
+            "created_at": 1746000000,
+            "model": "gpt-4o-mini",
+            "error": null,
+            "incomplete_details": null,
+            "status": "completed",
+            "output": output,
+            "usage": null,
+            "metadata": null,
+            "service_tier": null
+        }))
+        .unwrap();
+
+        let ChatEvent::Message(message) = process_model_response(response) else {
+            panic!("Expected a message");
+        };
+        assert!(message.tool_calls.is_empty());
+        assert_eq!(
+            message.content,
+            vec![ContentPart::Text(
+                "Golem 1.2 added durable LLM calls.".to_string()
+            )]
+        );
+        assert_eq!(
+            message.annotations,
+            vec![Annotation {
+                url: Some("https://golem.cloud/blog".to_string()),
+                title: Some("Golem blog".to_string()),
+                start_index: 0,
+                end_index: 34,
+            }]
+        );
+        let provider_metadata: serde_json::Value =
+            serde_json::from_str(&message.metadata.provider_metadata_json.unwrap()).unwrap();
+        assert_eq!(
+            provider_metadata,
+            serde_json::json!({
+                "web_search_calls": [{
+                    "id": "ws_1",
+                    "status": "completed",
+                    "query": "golem cloud latest release"
+                }]
+            })
+        );
+    }
+
+    #[test]
+    fn other_hosted_tool_outputs_are_skipped() {
+        let item_done: ResponseOutputItemDone = serde_json::from_value(serde_json::json!({
+            "type": "response.output_item.done",
+            "output_index": 0,
+            "item": { "type": "file_search_call", "id": "fs_1", "status": "completed", "queries": [] }
+        }))
+        .unwrap();
+
+        assert!(matches!(item_done.item, OutputItem::Other));
+    }
+
+    #[test]
+    fn image_generation_request_carries_the_config() {
+        let request = image_generation_request(
+            "A red bicycle".to_string(),
+            ImageGenConfig {
+                model: "dall-e-3".to_string(),
+                n: Some(1),
+                size: Some("1024x1024".to_string()),
+                response_format: Some(ImageResponseFormat::Inline),
+                provider_options: vec![Kv {
+                    key: "quality".to_string(),
+                    value: "hd".to_string(),
+                }],
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            serde_json::to_value(&request).unwrap(),
+            serde_json::json!({
+                "prompt": "A red bicycle",
+                "model": "dall-e-3",
+                "n": 1,
+                "size": "1024x1024",
+                "response_format": "b64_json",
+                "quality": "hd"
+            })
+        );
+    }
+
+    #[test]
+    fn generated_images_become_image_references() {
+        let response = serde_json::from_value(serde_json::json!({
+            "created": 1746000000,
+            "data": [
+                {
+                    "url": "https://example.com/generated.png",
+                    "revised_prompt": "A red bicycle leaning on a wall"
+                },
+                { "b64_json": "iVBORw==" }
+            ]
+        }))
+        .unwrap();
+
+        let result = process_image_response(response, "dall-e-3".to_string(), None).unwrap();
+
+        assert_eq!(
+            result.images,
+            vec![
+                ImageReference::Url(ImageUrl {
+                    url: "https://example.com/generated.png".to_string(),
+                    detail: None,
+                }),
+                ImageReference::Inline(ImageSource {
+                    data: vec![137, 80, 78, 71],
+                    mime_type: "image/png".to_string(),
+                    detail: None,
+                }),
+            ]
+        );
+        assert_eq!(result.model, Some("dall-e-3".to_string()));
+        assert_eq!(
+            result.provider_metadata_json,
+            Some(r#"{"revised_prompts":["A red bicycle leaning on a wall"]}"#.to_string())
+        );
+    }
+
+    #[test]
+    fn audio_output_is_requested_from_chat_completions() {
+        let items = messages_to_input_items(vec![Message {
+            role: Role::User,
+            name: None,
+            content: vec![ContentPart::Text(
+                "Is a golden retriever a good family dog?".to_string(),
+            )],
+        }])
+        .unwrap();
+        let config = Config {
+            provider_options: vec![
+                Kv {
+                    key: "modalities".to_string(),
+                    value: "text,audio".to_string(),
+                },
+                Kv {
+                    key: "audio".to_string(),
+                    value: r#"{"voice":"alloy","format":"mp3"}"#.to_string(),
+                },
+            ],
+            ..config("gpt-4o-audio-preview")
+        };
+
+        let request = create_request(items, config, vec![]).unwrap();
+        let request = serde_json::to_value(to_chat_completion_request(request).unwrap()).unwrap();
+
+        assert_eq!(request["modalities"], serde_json::json!(["text", "audio"]));
+        assert_eq!(
+            request["audio"],
+            serde_json::json!({"voice": "alloy", "format": "mp3"})
+        );
+        assert_eq!(request["max_completion_tokens"], 1000);
+        assert_eq!(
+            request["messages"],
+            serde_json::json!([{
+                "role": "user",
+                "content": [{"type": "text", "text": "Is a golden retriever a good family dog?"}]
+            }])
+        );
+    }
+
+    #[test]
+    fn audio_output_is_decoded_with_the_mime_type_of_its_format() {
+        let response: ChatCompletionResponse = serde_json::from_value(serde_json::json!({
+            "id": "chatcmpl-AzRobY5i6ZNp1ISTyV2r7Wb6S6c5T",
+            "object": "chat.completion",
+            "created": 1739000000,
+            "model": "gpt-4o-audio-preview-2024-12-17",
+            "choices": [
+                {
+                    "index": 0,
+                    "message": {
+                        "role": "assistant",
+                        "content": null,
+                        "refusal": null,
+                        "audio": {
+                            "id": "audio_67a8b1d5a1a08190b8a0a6e2d3c4f5e6",
+                            "data": "UklGRg==",
+                            "expires_at": 1739003600,
+                            "transcript": "Yes, golden retrievers are known to be great family dogs."
+                        }
+                    },
+                    "finish_reason": "stop"
+                }
+            ],
+            "usage": {
+                "prompt_tokens": 17,
+                "completion_tokens": 86,
+                "total_tokens": 103
+            },
+            "service_tier": "default"
+        }))
+        .unwrap();
+
+        match process_chat_completion(response, "wav") {
+            ChatEvent::Message(response) => {
+                assert_eq!(
+                    response.content,
+                    vec![ContentPart::Audio(AudioSource {
+                        data: vec![82, 73, 70, 70],
+                        mime_type: "audio/wav".to_string(),
+                        transcript: Some(
+                            "Yes, golden retrievers are known to be great family dogs.".to_string()
+                        ),
+                    })]
+                );
+                assert_eq!(response.metadata.finish_reason, Some(FinishReason::Stop));
+                assert_eq!(
+                    response.metadata.provider_metadata_json,
+                    Some(
+                        r#"{"audio_expires_at":1739003600,"audio_id":"audio_67a8b1d5a1a08190b8a0a6e2d3c4f5e6","service_tier":"default"}"#
+                            .to_string()
+                    )
+                );
+            }
+            event => panic!("Unexpected event: {event:?}"),
+        }
     }
 }
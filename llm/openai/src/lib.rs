@@ -1,23 +1,43 @@
 use crate::client::{
+    ChatCompletionChunk, ChatCompletionRequest, CreateModelResponseRequest,
     CreateModelResponseResponse, InputItem, OutputItem, ResponseOutputItemDone,
-    ResponseOutputTextDelta, ResponsesApi,
+    ResponseOutputTextAnnotationAdded, ResponseOutputTextDelta, ResponseRefusalDelta,
+    ResponseRefusalDone, ResponsesApi,
 };
 use crate::conversions::{
-    create_request, create_response_metadata, messages_to_input_items, parse_error_code,
-    process_model_response, tool_defs_to_tools, tool_results_to_input_items,
+    audio_mime_type, check_streaming, convert_annotation, convert_chat_finish_reason,
+    convert_chat_usage, create_request, create_response_metadata, decode_audio,
+    image_generation_request, messages_to_input_items, parse_error_code, process_chat_completion,
+    process_image_response, process_model_response, to_chat_completion_request,
+    to_streaming_chat_completion_request, to_streaming_request, tool_defs_to_tools,
+    tool_results_to_input_items,
 };
-use golem_llm::chat_stream::{LlmChatStream, LlmChatStreamState};
+use golem_llm::batch::send_batch;
+use golem_llm::chat_stream::{request_deadline, LlmChatStream, LlmChatStreamState};
 use golem_llm::config::with_config_key;
+use golem_llm::conversions::{
+    check_messages_not_empty, non_empty_tool_call_id, normalize_tool_call_ids,
+};
+use golem_llm::dry_run::{send_or_dry_run, stream_or_dry_run};
 use golem_llm::durability::{DurableLLM, ExtendedGuest};
+use golem_llm::error::unsupported;
 use golem_llm::event_source::EventSource;
+use golem_llm::extra_body::extra_body;
 use golem_llm::golem::llm::llm::{
-    ChatEvent, ChatStream, Config, ContentPart, Error, ErrorCode, Guest, Message, StreamDelta,
-    StreamEvent, ToolCall, ToolResult,
+    AudioSource, ChatEvent, ChatStream, Config, ContentPart, EmbeddingConfig, EmbeddingResult,
+    Error, ErrorCode, FinishReason, Guest, ImageGenConfig, ImageGenResult, Message,
+    ProviderCapabilities, ProviderHealth, RerankConfig, RerankResult, ResponseMetadata,
+    StreamDelta, StreamEvent, StreamStart, ToolCall, ToolResult, Usage,
 };
+use golem_llm::headers::extra_headers;
+use golem_llm::rate_limit::with_rate_limit;
+use golem_llm::refusal::{is_reporting_refusals_as_errors, refusal_error, with_refusal_as_error};
 use golem_llm::LOGGING_STATE;
 use golem_rust::wasm_rpc::Pollable;
 use log::trace;
+use reqwest::header::HeaderMap;
 use std::cell::{Ref, RefCell, RefMut};
+use std::collections::{BTreeMap, HashMap};
 
 mod client;
 mod conversions;
@@ -26,23 +46,192 @@ struct OpenAIChatStream {
     stream: RefCell<Option<EventSource>>,
     failure: Option<Error>,
     finished: RefCell<bool>,
+    /// Number of characters of text streamed so far
+    streamed_chars: RefCell<u32>,
+    /// Offset of each output text in the streamed text, by item id and content index, to move
+    /// the spans of its annotations by
+    text_offsets: RefCell<HashMap<(String, u32), u32>>,
+    /// Set for the requests with audio output, which are streamed by the Chat Completions API
+    /// instead of the Responses API
+    chat_completion: RefCell<Option<ChatCompletionStreamState>>,
+    /// Whether a refusal of the model is reported as an error once complete, instead of being
+    /// streamed as refusal content parts
+    refusals_as_errors: bool,
+}
+
+/// What is accumulated while streaming a chat completion, as its audio and tool calls are only
+/// emitted once complete
+struct ChatCompletionStreamState {
+    /// MIME type of the audio, following the requested format
+    audio_mime_type: String,
+    /// Audio data received so far, decoded chunk by chunk as each is encoded in base64 on its own
+    audio_data: Vec<u8>,
+    audio_transcript: String,
+    /// Refusal received so far, when refusals are reported as errors
+    refusal: String,
+    tool_calls: BTreeMap<u32, ToolCall>,
+    finish_reason: Option<FinishReason>,
+}
+
+impl ChatCompletionStreamState {
+    fn new(audio_format: &str) -> Self {
+        Self {
+            audio_mime_type: audio_mime_type(audio_format),
+            audio_data: Vec::new(),
+            audio_transcript: String::new(),
+            refusal: String::new(),
+            tool_calls: BTreeMap::new(),
+            finish_reason: None,
+        }
+    }
+
+    /// Decodes a chunk of the chat completion. The audio and the tool calls are emitted when the
+    /// finish reason is received, and the usage reported by the last chunk finishes the stream.
+    /// With `refusals_as_errors`, a refusal is reported as an error when the finish reason is
+    /// received instead of being streamed.
+    fn decode_chunk(
+        &mut self,
+        raw: &str,
+        refusals_as_errors: bool,
+    ) -> Result<Option<StreamEvent>, String> {
+        let chunk = serde_json::from_str::<ChatCompletionChunk>(raw)
+            .map_err(|err| format!("Failed to deserialize stream event: {err}"))?;
+
+        let Some(choice) = chunk.choices.into_iter().next() else {
+            return Ok(Some(StreamEvent::Finish(ResponseMetadata {
+                finish_reason: self.finish_reason,
+                usage: chunk.usage.map(convert_chat_usage),
+                provider_id: Some(chunk.id),
+                model: Some(chunk.model),
+                timestamp: Some(chunk.created.to_string()),
+                provider_metadata_json: None,
+                tokens_per_second: None,
+                stop_sequence: None,
+            })));
+        };
+
+        let mut content = Vec::new();
+        if let Some(text) = choice.delta.content.filter(|text| !text.is_empty()) {
+            content.push(ContentPart::Text(text));
+        }
+        if let Some(refusal) = choice.delta.refusal.filter(|refusal| !refusal.is_empty()) {
+            if refusals_as_errors {
+                self.refusal.push_str(&refusal);
+            } else {
+                content.push(ContentPart::Refusal(refusal));
+            }
+        }
+        if let Some(audio) = choice.delta.audio {
+            if let Some(data) = audio.data {
+                self.audio_data
+                    .extend(decode_audio(&data).map_err(|err| err.message)?);
+            }
+            self.audio_transcript
+                .push_str(audio.transcript.as_deref().unwrap_or_default());
+        }
+        for chunk in choice.delta.tool_calls.unwrap_or_default() {
+            let tool_call = self
+                .tool_calls
+                .entry(chunk.index)
+                .or_insert_with(|| ToolCall {
+                    id: String::new(),
+                    name: String::new(),
+                    arguments_json: String::new(),
+                });
+            if let Some(id) = chunk.id {
+                tool_call.id = id;
+            }
+            if let Some(function) = chunk.function {
+                if let Some(name) = function.name {
+                    tool_call.name = name;
+                }
+                if let Some(arguments) = function.arguments {
+                    tool_call.arguments_json.push_str(&arguments);
+                }
+            }
+        }
+
+        let mut tool_calls = Vec::new();
+        if let Some(finish_reason) = choice.finish_reason {
+            if !self.refusal.is_empty() {
+                return Ok(Some(StreamEvent::Error(refusal_error(&self.refusal))));
+            }
+            self.finish_reason = Some(convert_chat_finish_reason(&finish_reason));
+            if !self.audio_data.is_empty() {
+                content.push(ContentPart::Audio(AudioSource {
+                    data: std::mem::take(&mut self.audio_data),
+                    mime_type: self.audio_mime_type.clone(),
+                    transcript: Some(std::mem::take(&mut self.audio_transcript)),
+                }));
+            }
+            tool_calls = std::mem::take(&mut self.tool_calls).into_values().collect();
+        }
+
+        if content.is_empty() && tool_calls.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(StreamEvent::Delta(StreamDelta {
+                content: if content.is_empty() {
+                    None
+                } else {
+                    Some(content)
+                },
+                tool_calls: if tool_calls.is_empty() {
+                    None
+                } else {
+                    Some(tool_calls)
+                },
+                annotations: None,
+                tool_call_fragments: None,
+                running_usage: None,
+            })))
+        }
+    }
 }
 
 impl OpenAIChatStream {
-    pub fn new(stream: EventSource) -> LlmChatStream<Self> {
-        LlmChatStream::new(OpenAIChatStream {
-            stream: RefCell::new(Some(stream)),
-            failure: None,
+    fn state(
+        stream: Option<EventSource>,
+        failure: Option<Error>,
+        chat_completion: Option<ChatCompletionStreamState>,
+        refusals_as_errors: bool,
+    ) -> Self {
+        OpenAIChatStream {
+            stream: RefCell::new(stream),
+            failure,
             finished: RefCell::new(false),
-        })
+            streamed_chars: RefCell::new(0),
+            text_offsets: RefCell::new(HashMap::new()),
+            chat_completion: RefCell::new(chat_completion),
+            refusals_as_errors,
+        }
+    }
+
+    pub fn new(stream: EventSource) -> LlmChatStream<Self> {
+        LlmChatStream::new(Self::state(
+            Some(stream),
+            None,
+            None,
+            is_reporting_refusals_as_errors(),
+        ))
+    }
+
+    /// Creates a stream of a chat completion with audio output in the given format
+    pub fn chat_completion(stream: EventSource, audio_format: &str) -> LlmChatStream<Self> {
+        LlmChatStream::new(Self::state(
+            Some(stream),
+            None,
+            Some(ChatCompletionStreamState::new(audio_format)),
+            is_reporting_refusals_as_errors(),
+        ))
     }
 
     pub fn failed(error: Error) -> LlmChatStream<Self> {
-        LlmChatStream::new(OpenAIChatStream {
-            stream: RefCell::new(None),
-            failure: Some(error),
-            finished: RefCell::new(false),
-        })
+        LlmChatStream::new(Self::state(None, Some(error), None, false))
+    }
+
+    pub fn dry_run(events: Vec<StreamEvent>) -> LlmChatStream<Self> {
+        LlmChatStream::from_events(Self::state(None, None, None, false), events)
     }
 }
 
@@ -67,8 +256,12 @@ impl LlmChatStreamState for OpenAIChatStream {
         self.stream.borrow_mut()
     }
 
-    fn decode_message(&self, raw: &str) -> Result<Option<StreamEvent>, String> {
+    fn decode_message(&self, _event: &str, raw: &str) -> Result<Option<StreamEvent>, String> {
         trace!("Received raw stream event: {raw}");
+        if let Some(chat_completion) = self.chat_completion.borrow_mut().as_mut() {
+            return chat_completion.decode_chunk(raw, self.refusals_as_errors);
+        }
+
         let json: serde_json::Value = serde_json::from_str(raw)
             .map_err(|err| format!("Failed to deserialize stream event: {err}"))?;
 
@@ -104,6 +297,24 @@ impl LlmChatStreamState for OpenAIChatStream {
                     })))
                 }
             }
+            Some("response.created") => {
+                let response = json
+                    .as_object()
+                    .and_then(|obj| obj.get("response"))
+                    .ok_or_else(|| {
+                        "Unexpected stream event format, does not have 'response' field".to_string()
+                    })?;
+                let decoded =
+                    serde_json::from_value::<CreateModelResponseResponse>(response.clone())
+                        .map_err(|err| {
+                            format!("Failed to deserialize stream event's response field: {err}")
+                        })?;
+                Ok(Some(StreamEvent::Start(StreamStart {
+                    id: decoded.id,
+                    model: Some(decoded.model),
+                    created: Some(decoded.created_at.to_string()),
+                })))
+            }
             Some("response.completed") => {
                 let response = json
                     .as_object()
@@ -123,11 +334,57 @@ impl LlmChatStreamState for OpenAIChatStream {
             Some("response.output_text.delta") => {
                 let decoded = serde_json::from_value::<ResponseOutputTextDelta>(json)
                     .map_err(|err| format!("Failed to deserialize stream event: {err}"))?;
+                let mut streamed_chars = self.streamed_chars.borrow_mut();
+                self.text_offsets
+                    .borrow_mut()
+                    .entry((decoded.item_id, decoded.content_index))
+                    .or_insert(*streamed_chars);
+                *streamed_chars += decoded.delta.chars().count() as u32;
                 Ok(Some(StreamEvent::Delta(StreamDelta {
                     content: Some(vec![ContentPart::Text(decoded.delta)]),
                     tool_calls: None,
+                    annotations: None,
+                    tool_call_fragments: None,
+                    running_usage: None,
+                })))
+            }
+            Some("response.refusal.delta") if !self.refusals_as_errors => {
+                let decoded = serde_json::from_value::<ResponseRefusalDelta>(json)
+                    .map_err(|err| format!("Failed to deserialize stream event: {err}"))?;
+                Ok(Some(StreamEvent::Delta(StreamDelta {
+                    content: Some(vec![ContentPart::Refusal(decoded.delta)]),
+                    tool_calls: None,
+                    annotations: None,
+                    tool_call_fragments: None,
+                    running_usage: None,
                 })))
             }
+            Some("response.refusal.done") if self.refusals_as_errors => {
+                let decoded = serde_json::from_value::<ResponseRefusalDone>(json)
+                    .map_err(|err| format!("Failed to deserialize stream event: {err}"))?;
+                Ok(Some(StreamEvent::Error(refusal_error(&decoded.refusal))))
+            }
+            Some("response.output_text.annotation.added") => {
+                let decoded = serde_json::from_value::<ResponseOutputTextAnnotationAdded>(json)
+                    .map_err(|err| format!("Failed to deserialize stream event: {err}"))?;
+                let offset = self
+                    .text_offsets
+                    .borrow()
+                    .get(&(decoded.item_id, decoded.content_index))
+                    .copied()
+                    .unwrap_or(*self.streamed_chars.borrow());
+                Ok(
+                    convert_annotation(decoded.annotation, offset).map(|annotation| {
+                        StreamEvent::Delta(StreamDelta {
+                            content: None,
+                            tool_calls: None,
+                            annotations: Some(vec![annotation]),
+                            tool_call_fragments: None,
+                            running_usage: None,
+                        })
+                    }),
+                )
+            }
             Some("response.output_item.done") => {
                 let decoded = serde_json::from_value::<ResponseOutputItemDone>(json)
                     .map_err(|err| format!("Failed to deserialize stream event: {err}"))?;
@@ -145,8 +402,12 @@ impl LlmChatStreamState for OpenAIChatStream {
                             name,
                             arguments_json: arguments,
                         }]),
+                        annotations: None,
+                        tool_call_fragments: None,
+                        running_usage: None,
                     })))
                 } else {
+                    // Hosted tool calls are reported in the metadata of the completed response
                     Ok(None)
                 }
             }
@@ -162,14 +423,22 @@ impl OpenAIComponent {
     const ENV_VAR_NAME: &'static str = "OPENAI_API_KEY";
 
     fn request(client: ResponsesApi, items: Vec<InputItem>, config: Config) -> ChatEvent {
-        match tool_defs_to_tools(&config.tools) {
-            Ok(tools) => {
-                let request = create_request(items, config, tools);
+        match tool_defs_to_tools(&config.tools)
+            .and_then(|tools| create_request(items, config, tools))
+        {
+            Ok(request) if request.audio.is_some() => Self::audio_request(client, request),
+            Ok(request) => send_or_dry_run(request, |request| {
                 match client.create_model_response(request) {
-                    Ok(response) => process_model_response(response),
+                    Ok(response) => with_rate_limit(
+                        with_refusal_as_error(
+                            process_model_response(response.body),
+                            is_reporting_refusals_as_errors(),
+                        ),
+                        response.rate_limit,
+                    ),
                     Err(error) => ChatEvent::Error(error),
                 }
-            }
+            }),
             Err(error) => ChatEvent::Error(error),
         }
     }
@@ -179,18 +448,79 @@ impl OpenAIComponent {
         items: Vec<InputItem>,
         config: Config,
     ) -> LlmChatStream<OpenAIChatStream> {
-        match tool_defs_to_tools(&config.tools) {
-            Ok(tools) => {
-                let mut request = create_request(items, config, tools);
-                request.stream = true;
-                match client.stream_model_response(request) {
-                    Ok(stream) => OpenAIChatStream::new(stream),
-                    Err(error) => OpenAIChatStream::failed(error),
+        match tool_defs_to_tools(&config.tools)
+            .and_then(|tools| create_request(items, config, tools))
+        {
+            Ok(request) if request.audio.is_some() => {
+                Self::streaming_audio_request(client, request)
+            }
+            Ok(request) => {
+                if let Err(error) = check_streaming(&request.model) {
+                    return OpenAIChatStream::failed(error);
                 }
+                stream_or_dry_run(
+                    to_streaming_request(request),
+                    OpenAIChatStream::dry_run,
+                    |request| match client.stream_model_response(request) {
+                        Ok(stream) => OpenAIChatStream::new(stream),
+                        Err(error) => OpenAIChatStream::failed(error),
+                    },
+                )
             }
             Err(error) => OpenAIChatStream::failed(error),
         }
     }
+
+    /// Sends a request with audio output to the Chat Completions API
+    fn audio_request(client: ResponsesApi, request: CreateModelResponseRequest) -> ChatEvent {
+        match to_chat_completion_request(request) {
+            Ok(request) => {
+                let audio_format = audio_format(&request);
+                send_or_dry_run(request, |request| {
+                    match client.create_chat_completion(request) {
+                        Ok(response) => with_rate_limit(
+                            with_refusal_as_error(
+                                process_chat_completion(response.body, &audio_format),
+                                is_reporting_refusals_as_errors(),
+                            ),
+                            response.rate_limit,
+                        ),
+                        Err(error) => ChatEvent::Error(error),
+                    }
+                })
+            }
+            Err(error) => ChatEvent::Error(error),
+        }
+    }
+
+    /// Streams a request with audio output from the Chat Completions API
+    fn streaming_audio_request(
+        client: ResponsesApi,
+        request: CreateModelResponseRequest,
+    ) -> LlmChatStream<OpenAIChatStream> {
+        match to_chat_completion_request(request) {
+            Ok(request) => {
+                let audio_format = audio_format(&request);
+                stream_or_dry_run(
+                    to_streaming_chat_completion_request(request),
+                    OpenAIChatStream::dry_run,
+                    |request| match client.stream_chat_completion(request) {
+                        Ok(stream) => OpenAIChatStream::chat_completion(stream, &audio_format),
+                        Err(error) => OpenAIChatStream::failed(error),
+                    },
+                )
+            }
+            Err(error) => OpenAIChatStream::failed(error),
+        }
+    }
+}
+
+fn audio_format(request: &ChatCompletionRequest) -> String {
+    request
+        .audio
+        .as_ref()
+        .map(|audio| audio.format.clone())
+        .unwrap_or_default()
 }
 
 impl Guest for OpenAIComponent {
@@ -199,10 +529,31 @@ impl Guest for OpenAIComponent {
     fn send(messages: Vec<Message>, config: Config) -> ChatEvent {
         LOGGING_STATE.with_borrow_mut(|state| state.init());
 
+        if let Err(err) = check_messages_not_empty(&messages, &[]) {
+            return ChatEvent::Error(err);
+        }
+
+        let extra_headers = match extra_headers(&config) {
+            Ok(extra_headers) => extra_headers,
+            Err(err) => return ChatEvent::Error(err),
+        };
+        let extra_body = match extra_body(&config) {
+            Ok(extra_body) => extra_body,
+            Err(err) => return ChatEvent::Error(err),
+        };
+
         with_config_key(Self::ENV_VAR_NAME, ChatEvent::Error, |openai_api_key| {
-            let client = ResponsesApi::new(openai_api_key);
+            let client = match ResponsesApi::new(openai_api_key, extra_headers)
+                .map(|client| client.with_extra_body(extra_body))
+            {
+                Ok(client) => client,
+                Err(err) => return ChatEvent::Error(err),
+            };
 
-            let items = messages_to_input_items(messages);
+            let items = match messages_to_input_items(messages) {
+                Ok(items) => items,
+                Err(err) => return ChatEvent::Error(err),
+            };
             Self::request(client, items, config)
         })
     }
@@ -214,32 +565,170 @@ impl Guest for OpenAIComponent {
     ) -> ChatEvent {
         LOGGING_STATE.with_borrow_mut(|state| state.init());
 
+        if let Err(err) = check_messages_not_empty(&messages, &tool_results) {
+            return ChatEvent::Error(err);
+        }
+
+        let extra_headers = match extra_headers(&config) {
+            Ok(extra_headers) => extra_headers,
+            Err(err) => return ChatEvent::Error(err),
+        };
+        let extra_body = match extra_body(&config) {
+            Ok(extra_body) => extra_body,
+            Err(err) => return ChatEvent::Error(err),
+        };
+
+        let tool_results = match normalize_tool_call_ids(tool_results, non_empty_tool_call_id) {
+            Ok(tool_results) => tool_results,
+            Err(err) => return ChatEvent::Error(err),
+        };
+
         with_config_key(Self::ENV_VAR_NAME, ChatEvent::Error, |openai_api_key| {
-            let client = ResponsesApi::new(openai_api_key);
+            let client = match ResponsesApi::new(openai_api_key, extra_headers)
+                .map(|client| client.with_extra_body(extra_body))
+            {
+                Ok(client) => client,
+                Err(err) => return ChatEvent::Error(err),
+            };
 
-            let mut items = messages_to_input_items(messages);
+            let mut items = match messages_to_input_items(messages) {
+                Ok(items) => items,
+                Err(err) => return ChatEvent::Error(err),
+            };
             items.extend(tool_results_to_input_items(tool_results));
             Self::request(client, items, config)
         })
     }
 
+    fn send_batch(requests: Vec<(Vec<Message>, Config)>, abort_on_error: bool) -> Vec<ChatEvent> {
+        send_batch(requests, abort_on_error, Self::send)
+    }
+
     fn stream(messages: Vec<Message>, config: Config) -> ChatStream {
         ChatStream::new(Self::unwrapped_stream(messages, config))
     }
+
+    fn continue_stream(
+        messages: Vec<Message>,
+        tool_results: Vec<(ToolCall, ToolResult)>,
+        config: Config,
+    ) -> ChatStream {
+        ChatStream::new(Self::unwrapped_continue_stream(
+            messages,
+            tool_results,
+            config,
+        ))
+    }
+
+    fn send_raw(body_json: String, config: Config) -> Result<String, Error> {
+        LOGGING_STATE.with_borrow_mut(|state| state.init());
+
+        let extra_headers = extra_headers(&config)?;
+        with_config_key(Self::ENV_VAR_NAME, Err, |openai_api_key| {
+            ResponsesApi::new(openai_api_key, extra_headers)?.send_raw(body_json)
+        })
+    }
+
+    fn rerank(
+        _query: String,
+        _documents: Vec<String>,
+        _config: RerankConfig,
+    ) -> Result<RerankResult, Error> {
+        Err(unsupported("rerank"))
+    }
+
+    fn embed(
+        _inputs: Vec<ContentPart>,
+        _config: EmbeddingConfig,
+    ) -> Result<EmbeddingResult, Error> {
+        Err(unsupported("embed"))
+    }
+
+    fn ping() -> Result<ProviderHealth, Error> {
+        LOGGING_STATE.with_borrow_mut(|state| state.init());
+
+        with_config_key(Self::ENV_VAR_NAME, Err, |openai_api_key| {
+            ResponsesApi::new(openai_api_key, HeaderMap::new())?.ping()
+        })
+    }
+
+    fn capabilities() -> ProviderCapabilities {
+        ProviderCapabilities {
+            provider: "openai".to_string(),
+            streaming: true,
+            tools: true,
+            vision: true,
+            embeddings: false,
+            json_schema: false,
+        }
+    }
+
+    fn warm(_config: Config) -> Result<(), Error> {
+        Err(unsupported("warm"))
+    }
+
+    fn generate_image(prompt: String, config: ImageGenConfig) -> Result<ImageGenResult, Error> {
+        LOGGING_STATE.with_borrow_mut(|state| state.init());
+
+        with_config_key(Self::ENV_VAR_NAME, Err, |openai_api_key| {
+            let request = image_generation_request(prompt, config)?;
+            let model = request.model.clone();
+            let output_format = request.output_format.clone();
+            ResponsesApi::new(openai_api_key, HeaderMap::new())?
+                .create_image(request)
+                .and_then(|response| process_image_response(response, model, output_format))
+        })
+    }
 }
 
 impl ExtendedGuest for OpenAIComponent {
     fn unwrapped_stream(messages: Vec<Message>, config: Config) -> Self::ChatStream {
+        Self::unwrapped_continue_stream(messages, vec![], config)
+    }
+
+    fn unwrapped_continue_stream(
+        messages: Vec<Message>,
+        tool_results: Vec<(ToolCall, ToolResult)>,
+        config: Config,
+    ) -> Self::ChatStream {
         LOGGING_STATE.with_borrow_mut(|state| state.init());
 
+        if let Err(err) = check_messages_not_empty(&messages, &tool_results) {
+            return OpenAIChatStream::failed(err);
+        }
+
+        let extra_headers = match extra_headers(&config) {
+            Ok(extra_headers) => extra_headers,
+            Err(err) => return OpenAIChatStream::failed(err),
+        };
+        let extra_body = match extra_body(&config) {
+            Ok(extra_body) => extra_body,
+            Err(err) => return OpenAIChatStream::failed(err),
+        };
+        let deadline = request_deadline(&config);
+
+        let tool_results = match normalize_tool_call_ids(tool_results, non_empty_tool_call_id) {
+            Ok(tool_results) => tool_results,
+            Err(err) => return OpenAIChatStream::failed(err),
+        };
+
         with_config_key(
             Self::ENV_VAR_NAME,
             OpenAIChatStream::failed,
             |openai_api_key| {
-                let client = ResponsesApi::new(openai_api_key);
+                let client = match ResponsesApi::new(openai_api_key, extra_headers)
+                    .map(|client| client.with_extra_body(extra_body))
+                {
+                    Ok(client) => client,
+                    Err(err) => return OpenAIChatStream::failed(err),
+                };
 
-                let items = messages_to_input_items(messages);
-                Self::streaming_request(client, items, config)
+                let mut items = match messages_to_input_items(messages) {
+                    Ok(items) => items,
+                    Err(err) => return OpenAIChatStream::failed(err),
+                };
+                items.extend(tool_results_to_input_items(tool_results));
+                Self::streaming_request(client, items, config).with_deadline(deadline)
             },
         )
     }
@@ -247,8 +736,141 @@ impl ExtendedGuest for OpenAIComponent {
     fn subscribe(stream: &Self::ChatStream) -> Pollable {
         stream.subscribe()
     }
+
+    fn subscribe_deadline(stream: &Self::ChatStream) -> Option<Pollable> {
+        stream.subscribe_deadline()
+    }
+
+    fn usage_so_far(stream: &Self::ChatStream) -> Option<Usage> {
+        stream.usage_so_far()
+    }
 }
 
 type DurableOpenAIComponent = DurableLLM<OpenAIComponent>;
 
 golem_llm::export_llm!(DurableOpenAIComponent with_types_in golem_llm);
+
+#[cfg(test)]
+mod tests {
+    use crate::{ChatCompletionStreamState, OpenAIChatStream, OpenAIComponent};
+    use golem_llm::chat_stream::LlmChatStreamState;
+    use golem_llm::golem::llm::llm::{
+        AudioSource, ContentPart, ErrorCode, FinishReason, Guest, ProviderCapabilities,
+        StreamDelta, StreamEvent, Usage,
+    };
+
+    const REFUSAL_EVENTS: [&str; 3] = [
+        r#"{"type":"response.refusal.delta","item_id":"msg_1","output_index":0,"content_index":0,"delta":"I can't help "}"#,
+        r#"{"type":"response.refusal.delta","item_id":"msg_1","output_index":0,"content_index":0,"delta":"with that."}"#,
+        r#"{"type":"response.refusal.done","item_id":"msg_1","output_index":0,"content_index":0,"refusal":"I can't help with that."}"#,
+    ];
+
+    #[test]
+    fn streamed_refusals_are_refusal_parts() {
+        let stream = OpenAIChatStream::state(None, None, None, false);
+        let events = REFUSAL_EVENTS
+            .iter()
+            .filter_map(|raw| stream.decode_message("", raw).unwrap())
+            .collect::<Vec<_>>();
+
+        let refusal = |text: &str| {
+            StreamEvent::Delta(StreamDelta {
+                content: Some(vec![ContentPart::Refusal(text.to_string())]),
+                tool_calls: None,
+                annotations: None,
+                tool_call_fragments: None,
+                running_usage: None,
+            })
+        };
+        assert_eq!(
+            events,
+            vec![refusal("I can't help "), refusal("with that.")]
+        );
+    }
+
+    #[test]
+    fn streamed_refusals_can_be_reported_as_errors() {
+        let stream = OpenAIChatStream::state(None, None, None, true);
+        let events = REFUSAL_EVENTS
+            .iter()
+            .filter_map(|raw| stream.decode_message("", raw).unwrap())
+            .collect::<Vec<_>>();
+
+        match events.as_slice() {
+            [StreamEvent::Error(error)] => {
+                assert_eq!(error.code, ErrorCode::ContentFilter);
+                assert_eq!(
+                    error.message,
+                    "The model refused to answer: I can't help with that."
+                );
+            }
+            events => panic!("Expected a single error, got {events:?}"),
+        }
+    }
+
+    #[test]
+    fn streamed_audio_is_assembled_when_finished() {
+        let stream = OpenAIChatStream::state(
+            None,
+            None,
+            Some(ChatCompletionStreamState::new("pcm16")),
+            false,
+        );
+        let events = [
+            r#"{"id":"chatcmpl-1","object":"chat.completion.chunk","created":1739000000,"model":"gpt-4o-audio-preview-2024-12-17","choices":[{"index":0,"delta":{"role":"assistant","content":null,"refusal":null},"finish_reason":null}],"usage":null}"#,
+            r#"{"id":"chatcmpl-1","object":"chat.completion.chunk","created":1739000000,"model":"gpt-4o-audio-preview-2024-12-17","choices":[{"index":0,"delta":{"audio":{"id":"audio_1","transcript":"Hello"}},"finish_reason":null}],"usage":null}"#,
+            r#"{"id":"chatcmpl-1","object":"chat.completion.chunk","created":1739000000,"model":"gpt-4o-audio-preview-2024-12-17","choices":[{"index":0,"delta":{"audio":{"data":"AAE="}},"finish_reason":null}],"usage":null}"#,
+            r#"{"id":"chatcmpl-1","object":"chat.completion.chunk","created":1739000000,"model":"gpt-4o-audio-preview-2024-12-17","choices":[{"index":0,"delta":{"audio":{"data":"AgM=","transcript":" there"}},"finish_reason":null}],"usage":null}"#,
+            r#"{"id":"chatcmpl-1","object":"chat.completion.chunk","created":1739000000,"model":"gpt-4o-audio-preview-2024-12-17","choices":[{"index":0,"delta":{"audio":{"expires_at":1739003600}},"finish_reason":"stop"}],"usage":null}"#,
+            r#"{"id":"chatcmpl-1","object":"chat.completion.chunk","created":1739000000,"model":"gpt-4o-audio-preview-2024-12-17","choices":[],"usage":{"prompt_tokens":17,"completion_tokens":25,"total_tokens":42}}"#,
+        ]
+        .iter()
+        .filter_map(|raw| stream.decode_message("", raw).unwrap())
+        .collect::<Vec<_>>();
+
+        assert_eq!(events.len(), 2);
+        assert_eq!(
+            events[0],
+            StreamEvent::Delta(StreamDelta {
+                content: Some(vec![ContentPart::Audio(AudioSource {
+                    data: vec![0, 1, 2, 3],
+                    mime_type: "audio/pcm".to_string(),
+                    transcript: Some("Hello there".to_string()),
+                })]),
+                tool_calls: None,
+                annotations: None,
+                tool_call_fragments: None,
+                running_usage: None,
+            })
+        );
+        match &events[1] {
+            StreamEvent::Finish(metadata) => {
+                assert_eq!(metadata.finish_reason, Some(FinishReason::Stop));
+                assert_eq!(
+                    metadata.usage,
+                    Some(Usage {
+                        input_tokens: Some(17),
+                        output_tokens: Some(25),
+                        total_tokens: Some(42),
+                    })
+                );
+            }
+            event => panic!("Expected a finish event, got {event:?}"),
+        }
+    }
+
+    #[test]
+    fn capabilities_describe_the_provider() {
+        assert_eq!(
+            OpenAIComponent::capabilities(),
+            ProviderCapabilities {
+                provider: "openai".to_string(),
+                streaming: true,
+                tools: true,
+                vision: true,
+                embeddings: false,
+                json_schema: false,
+            }
+        );
+    }
+}
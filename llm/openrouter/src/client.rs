@@ -1,8 +1,15 @@
-use golem_llm::error::{error_code_from_status, from_event_source_error, from_reqwest_error};
-use golem_llm::event_source::EventSource;
-use golem_llm::golem::llm::llm::{Error, ErrorCode};
+use golem_llm::body::read_body;
+use golem_llm::error::{error_code_from_response, from_event_source_error, from_reqwest_error};
+use golem_llm::event_source::{EventSource, StreamFormat, StreamFormatHint};
+use golem_llm::extra_body::ExtraBody;
+use golem_llm::golem::llm::llm::{Error, ErrorCode, ProviderHealth};
+use golem_llm::health::probe;
+use golem_llm::proxy::http_client;
+use golem_llm::rate_limit::{rate_limit_info, RateLimited};
+use golem_llm::raw::send_raw;
+use golem_llm::retry::send_with_connection_retry;
 use log::trace;
-use reqwest::header::HeaderValue;
+use reqwest::header::{HeaderMap, HeaderValue};
 use reqwest::{Client, Method, Response, StatusCode};
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
@@ -13,34 +20,56 @@ const BASE_URL: &str = "https://openrouter.ai";
 /// The Completions API client for creating model responses.
 pub struct CompletionsApi {
     api_key: String,
+    extra_headers: HeaderMap,
+    /// Fields added to the chat request bodies
+    extra_body: ExtraBody,
     client: Client,
 }
 
 impl CompletionsApi {
-    pub fn new(api_key: String) -> Self {
-        let client = Client::builder()
-            .build()
-            .expect("Failed to initialize HTTP client");
-        Self { api_key, client }
+    pub fn new(api_key: String, extra_headers: HeaderMap) -> Result<Self, Error> {
+        let client = http_client(BASE_URL)?;
+        Ok(Self {
+            api_key,
+            extra_headers,
+            extra_body: ExtraBody::default(),
+            client,
+        })
+    }
+
+    /// Adds the `extra_body` of the config to the chat requests
+    pub fn with_extra_body(mut self, extra_body: ExtraBody) -> Self {
+        self.extra_body = extra_body;
+        self
     }
 
-    pub fn send_messages(&self, request: CompletionsRequest) -> Result<CompletionsResponse, Error> {
+    pub fn send_messages(
+        &self,
+        request: CompletionsRequest,
+    ) -> Result<RateLimited<CompletionsResponse>, Error> {
         trace!("Sending request to OpenRouter API: {request:?}");
 
-        let response: Response = self
-            .client
-            .request(Method::POST, format!("{BASE_URL}/api/v1/chat/completions"))
-            .bearer_auth(self.api_key.clone())
-            .json(&request)
-            .send()
-            .map_err(|err| from_reqwest_error("Request failed", err))?;
+        let body = self.extra_body.merge(&request)?;
+
+        let response: Response = send_with_connection_retry(|| {
+            self.client
+                .request(Method::POST, format!("{BASE_URL}/api/v1/chat/completions"))
+                .bearer_auth(self.api_key.clone())
+                .json(&body)
+                .headers(self.extra_headers.clone())
+                .send()
+        })
+        .map_err(|err| from_reqwest_error("Request failed", err))?;
 
-        parse_response(response)
+        let rate_limit = rate_limit_info(response.headers());
+        parse_response(response).map(|body| RateLimited { body, rate_limit })
     }
 
     pub fn stream_send_messages(&self, request: CompletionsRequest) -> Result<EventSource, Error> {
         trace!("Sending request to OpenRouter API: {request:?}");
 
+        let body = self.extra_body.merge(&request)?;
+
         let response: Response = self
             .client
             .request(Method::POST, format!("{BASE_URL}/api/v1/chat/completions"))
@@ -49,14 +78,46 @@ impl CompletionsApi {
                 reqwest::header::ACCEPT,
                 HeaderValue::from_static("text/event-stream"),
             )
-            .json(&request)
+            .json(&body)
+            .headers(self.extra_headers.clone())
             .send()
             .map_err(|err| from_reqwest_error("Request failed", err))?;
 
+        if !response.status().is_success() {
+            return Err(parse_error_response(response));
+        }
+
         trace!("Initializing SSE stream");
 
-        EventSource::new(response)
-            .map_err(|err| from_event_source_error("Failed to create SSE stream", err))
+        EventSource::new(
+            response,
+            StreamFormatHint::Detect(StreamFormat::EventStream),
+        )
+        .map_err(|err| from_event_source_error("Failed to create SSE stream", err))
+    }
+
+    /// Sends the JSON body untouched to the Chat Completions API, returning the raw response body
+    pub fn send_raw(&self, body_json: String) -> Result<String, Error> {
+        send_raw(
+            self.client
+                .request(Method::POST, format!("{BASE_URL}/api/v1/chat/completions"))
+                .bearer_auth(self.api_key.clone()),
+            self.extra_headers.clone(),
+            body_json,
+        )
+    }
+
+    /// Checks the connectivity by querying the API key's details
+    pub fn ping(&self) -> Result<ProviderHealth, Error> {
+        trace!("Checking the connectivity of the OpenRouter API");
+
+        probe(|| {
+            self.client
+                .request(Method::GET, format!("{BASE_URL}/api/v1/auth/key"))
+                .bearer_auth(self.api_key.clone())
+                .headers(self.extra_headers.clone())
+                .send()
+        })
     }
 }
 
@@ -92,6 +153,17 @@ pub struct CompletionsRequest {
     pub min_p: Option<f32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub top_a: Option<f32>,
+    /// Provider routing preferences, forwarded as is
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub provider: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usage: Option<UsageAccounting>,
+}
+
+/// Enables OpenRouter's usage accounting, adding the cost of the generation to the reported usage
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageAccounting {
+    pub include: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -220,6 +292,9 @@ pub struct CompletionsResponse {
     pub choices: Vec<Choice>,
     pub created: u64,
     pub model: String,
+    /// The upstream provider which served the request
+    #[serde(default)]
+    pub provider: Option<String>,
     pub system_fingerprint: Option<String>,
     pub usage: Option<Usage>,
 }
@@ -270,6 +345,14 @@ pub struct Usage {
     pub completion_tokens: u32,
     pub prompt_tokens: u32,
     pub total_tokens: u32,
+    /// Cost of the generation in credits, reported when usage accounting is enabled
+    #[serde(default)]
+    pub cost: Option<f64>,
+    /// Token counts of the upstream model's own tokenizer
+    #[serde(default)]
+    pub native_tokens_prompt: Option<u32>,
+    #[serde(default)]
+    pub native_tokens_completion: Option<u32>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -277,6 +360,8 @@ pub struct ChatCompletionChunk {
     pub id: String,
     pub created: u64,
     pub model: String,
+    #[serde(default)]
+    pub provider: Option<String>,
     pub choices: Vec<ChoiceChunk>,
     pub usage: Option<Usage>,
     pub system_fingerprint: Option<String>,
@@ -300,9 +385,7 @@ pub struct ChoiceDelta {
 fn parse_response<T: DeserializeOwned + Debug>(response: Response) -> Result<T, Error> {
     let status = response.status();
     if status.is_success() {
-        let raw_body = response
-            .text()
-            .map_err(|err| from_reqwest_error("Failed to receive response body", err))?;
+        let raw_body = read_body(response, "Failed to receive response body")?;
         trace!("Received response from OpenRouter API: {raw_body:?}");
 
         if let Ok(body) = serde_json::from_str::<T>(&raw_body) {
@@ -321,7 +404,7 @@ fn parse_response<T: DeserializeOwned + Debug>(response: Response) -> Result<T,
                 .and_then(|code| StatusCode::from_u16(code).ok())
                 .unwrap_or(status);
             Err(Error {
-                code: error_code_from_status(status),
+                code: error_code_from_response(status, &error_body.error.message),
                 message: error_body.error.message,
                 provider_error_json: error_body
                     .error
@@ -330,25 +413,93 @@ fn parse_response<T: DeserializeOwned + Debug>(response: Response) -> Result<T,
             })
         }
     } else {
-        let raw_error_body = response
-            .text()
-            .map_err(|err| from_reqwest_error("Failed to receive error response body", err))?;
-        trace!("Received {status} response from OpenRouter API: {raw_error_body:?}");
-
-        let error_body: ErrorResponseBody =
-            serde_json::from_str(&raw_error_body).map_err(|err| Error {
-                code: ErrorCode::InternalError,
-                message: format!("Failed to parse error response body: {err}"),
-                provider_error_json: Some(raw_error_body),
-            })?;
-
-        Err(Error {
-            code: error_code_from_status(status),
+        Err(parse_error_response(response))
+    }
+}
+
+/// Converts a failed response to an error, adding OpenRouter's `X-RateLimit-*` headers to the
+/// provider error JSON so rate limit errors report the remaining quota
+fn parse_error_response(response: Response) -> Error {
+    let status = response.status();
+    let rate_limit = rate_limit_info(response.headers());
+    let raw_error_body = match read_body(response, "Failed to receive error response body") {
+        Ok(raw_error_body) => raw_error_body,
+        Err(error) => return error,
+    };
+    trace!("Received {status} response from OpenRouter API: {raw_error_body:?}");
+
+    match serde_json::from_str::<ErrorResponseBody>(&raw_error_body) {
+        Ok(error_body) => Error {
+            code: error_code_from_response(status, &error_body.error.message),
             message: error_body.error.message,
-            provider_error_json: error_body
-                .error
-                .metadata
-                .map(|value| serde_json::to_string(&value).unwrap()),
-        })
+            provider_error_json: provider_error_json(error_body.error.metadata, rate_limit),
+        },
+        Err(err) => Error {
+            code: ErrorCode::InternalError,
+            message: format!("Failed to parse error response body: {err}"),
+            provider_error_json: Some(raw_error_body),
+        },
+    }
+}
+
+/// Adds the rate limit info as `rate_limit` to the error's metadata. Metadata which is not a
+/// JSON object is reported as `metadata` next to it.
+pub fn provider_error_json(
+    metadata: Option<serde_json::Value>,
+    rate_limit: Option<serde_json::Value>,
+) -> Option<String> {
+    match (metadata, rate_limit) {
+        (metadata, None) => metadata.map(|value| serde_json::to_string(&value).unwrap()),
+        (Some(serde_json::Value::Object(mut metadata)), Some(rate_limit)) => {
+            metadata.insert("rate_limit".to_string(), rate_limit);
+            Some(serde_json::Value::Object(metadata).to_string())
+        }
+        (Some(metadata), Some(rate_limit)) => {
+            Some(serde_json::json!({ "metadata": metadata, "rate_limit": rate_limit }).to_string())
+        }
+        (None, Some(rate_limit)) => {
+            Some(serde_json::json!({ "rate_limit": rate_limit }).to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::provider_error_json;
+    use golem_llm::rate_limit::rate_limit_info;
+    use reqwest::header::{HeaderMap, HeaderValue};
+
+    #[test]
+    fn rate_limit_headers_are_added_to_the_error() {
+        let mut headers = HeaderMap::new();
+        headers.insert("X-RateLimit-Limit", HeaderValue::from_static("20"));
+        headers.insert("X-RateLimit-Remaining", HeaderValue::from_static("0"));
+        headers.insert(
+            "X-RateLimit-Reset",
+            HeaderValue::from_static("1741305600000"),
+        );
+
+        let json = provider_error_json(
+            Some(serde_json::json!({"provider_name": "OpenAI"})),
+            rate_limit_info(&headers),
+        )
+        .unwrap();
+
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&json).unwrap(),
+            serde_json::json!({
+                "provider_name": "OpenAI",
+                "rate_limit": {"limit": "20", "remaining": "0", "reset": "1741305600000"}
+            })
+        );
+    }
+
+    #[test]
+    fn error_metadata_is_kept_without_rate_limit_headers() {
+        assert_eq!(rate_limit_info(&HeaderMap::new()), None);
+        assert_eq!(
+            provider_error_json(Some(serde_json::json!({"raw": "x"})), None),
+            Some(r#"{"raw":"x"}"#.to_string())
+        );
     }
 }
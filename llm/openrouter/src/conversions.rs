@@ -1,12 +1,62 @@
 use crate::client::{
     CompletionsRequest, CompletionsResponse, Detail, FunctionName, ToolChoiceFunction,
+    UsageAccounting,
 };
 use base64::{engine::general_purpose, Engine as _};
+use golem_llm::capabilities::{
+    check_model_capabilities, check_streaming_support, ModelCapabilities,
+};
+use golem_llm::config::model_or_default;
+use golem_llm::conversions::{
+    check_provider_options, non_empty_tool_call_id, split_tool_results, validate_temperature,
+    TextJoin, TEXT_JOIN_OPTION,
+};
 use golem_llm::golem::llm::llm::{
     ChatEvent, CompleteResponse, Config, ContentPart, Error, ErrorCode, FinishReason, ImageDetail,
     ImageReference, Message, ResponseMetadata, Role, ToolCall, ToolDefinition, ToolResult, Usage,
 };
+use golem_llm::usage::with_total_tokens;
 use std::collections::HashMap;
+use std::ops::RangeInclusive;
+
+/// Range of temperatures accepted by OpenRouter
+const TEMPERATURE_RANGE: RangeInclusive<f32> = 0.0..=2.0;
+
+/// Provider options read by the OpenRouter conversions, any other key is reported as unknown
+const PROVIDER_OPTIONS: [&str; 10] = [
+    TEXT_JOIN_OPTION,
+    "routing",
+    "frequency_penalty",
+    "presence_penalty",
+    "repetition_penalty",
+    "seed",
+    "top_p",
+    "top_k",
+    "min_p",
+    "top_a",
+];
+
+/// Capabilities of the known OpenRouter models, requests to other models are not validated
+const MODEL_CAPABILITIES: [(&str, ModelCapabilities); 8] = [
+    ("openai/gpt-4o", ModelCapabilities::ALL),
+    ("openai/gpt-4o-mini", ModelCapabilities::ALL),
+    ("openai/gpt-3.5-turbo", ModelCapabilities::TEXT_ONLY),
+    ("openai/o1-mini", REASONING_PREVIEW_MODEL_CAPABILITIES),
+    ("anthropic/claude-sonnet-4", ModelCapabilities::ALL),
+    ("anthropic/claude-3.5-sonnet", ModelCapabilities::ALL),
+    ("google/gemini-2.5-flash", ModelCapabilities::ALL),
+    (
+        "meta-llama/llama-3.1-8b-instruct",
+        ModelCapabilities::TEXT_ONLY,
+    ),
+];
+
+/// The preview reasoning models of OpenAI take neither images nor tools
+const REASONING_PREVIEW_MODEL_CAPABILITIES: ModelCapabilities = ModelCapabilities {
+    vision: false,
+    tools: false,
+    streaming: true,
+};
 
 pub fn messages_to_request(
     messages: Vec<Message>,
@@ -17,29 +67,39 @@ pub fn messages_to_request(
         .into_iter()
         .map(|kv| (kv.key, kv.value))
         .collect::<HashMap<_, _>>();
+    check_provider_options(&options, &PROVIDER_OPTIONS)?;
 
+    let model = model_or_default(config.model)?;
+    check_model_capabilities(&MODEL_CAPABILITIES, &model, &messages, &config.tools)?;
+
+    let text_join = TextJoin::from_options(&options);
     let mut completion_messages = Vec::new();
     for message in messages {
-        match message.role {
-            Role::User => completion_messages.push(crate::client::Message::User {
-                name: message.name,
-                content: convert_content_parts(message.content),
-            }),
-            Role::Assistant => completion_messages.push(crate::client::Message::Assistant {
-                name: message.name,
-                content: Some(convert_content_parts(message.content)),
-                tool_calls: None,
-            }),
-            Role::System => completion_messages.push(crate::client::Message::System {
-                name: message.name,
-                content: convert_content_parts(message.content),
-            }),
-            Role::Tool => completion_messages.push(crate::client::Message::Tool {
-                name: message.name,
-                content: convert_content_parts_to_string(message.content),
-                tool_call_id: "unknown".to_string(),
-            }),
+        // Tool results included in the messages are sent as a tool call and its result
+        let (content, tool_results) = split_tool_results(message.content, non_empty_tool_call_id)?;
+        if !content.is_empty() || tool_results.is_empty() {
+            match message.role {
+                Role::User => completion_messages.push(crate::client::Message::User {
+                    name: message.name,
+                    content: convert_content_parts(content),
+                }),
+                Role::Assistant => completion_messages.push(crate::client::Message::Assistant {
+                    name: message.name,
+                    content: Some(convert_content_parts(content)),
+                    tool_calls: None,
+                }),
+                Role::System => completion_messages.push(crate::client::Message::System {
+                    name: message.name,
+                    content: convert_content_parts(content),
+                }),
+                Role::Tool => completion_messages.push(crate::client::Message::Tool {
+                    name: message.name,
+                    content: convert_content_parts_to_string(content, &text_join),
+                    tool_call_id: "unknown".to_string(),
+                }),
+            }
         }
+        completion_messages.extend(tool_results_to_messages(tool_results));
     }
 
     let mut tools = Vec::new();
@@ -47,9 +107,14 @@ pub fn messages_to_request(
         tools.push(tool_definition_to_tool(tool)?)
     }
 
+    let provider = options
+        .get("routing")
+        .map(|routing| parse_routing(routing))
+        .transpose()?;
+
     Ok(CompletionsRequest {
         messages: completion_messages,
-        model: config.model,
+        model,
         frequency_penalty: options
             .get("frequency_penalty")
             .and_then(|fp_s| fp_s.parse::<f32>().ok()),
@@ -65,7 +130,7 @@ pub fn messages_to_request(
             .and_then(|seed_s| seed_s.parse::<u32>().ok()),
         stop: config.stop_sequences,
         stream: Some(false),
-        temperature: config.temperature,
+        temperature: validate_temperature(config.temperature, TEMPERATURE_RANGE)?,
         tool_choice: config.tool_choice.map(convert_tool_choice),
         tools,
         top_p: options
@@ -80,9 +145,34 @@ pub fn messages_to_request(
         top_a: options
             .get("top_a")
             .and_then(|top_a_s| top_a_s.parse::<f32>().ok()),
+        provider,
+        usage: Some(UsageAccounting { include: true }),
     })
 }
 
+/// Fails if the model is known not to support streaming
+pub fn check_streaming(model: &str) -> Result<(), Error> {
+    check_streaming_support(&MODEL_CAPABILITIES, model)
+}
+
+/// Parses the `routing` provider option, which has to be a JSON object in the format of
+/// OpenRouter's `provider` routing preferences
+fn parse_routing(routing: &str) -> Result<serde_json::Value, Error> {
+    match serde_json::from_str::<serde_json::Value>(routing) {
+        Ok(value) if value.is_object() => Ok(value),
+        Ok(_) => Err(Error {
+            code: ErrorCode::InvalidRequest,
+            message: "The routing provider option must be a JSON object".to_string(),
+            provider_error_json: None,
+        }),
+        Err(error) => Err(Error {
+            code: ErrorCode::InvalidRequest,
+            message: format!("Failed to parse the routing provider option: {error}"),
+            provider_error_json: None,
+        }),
+    }
+}
+
 pub fn process_response(response: CompletionsResponse) -> ChatEvent {
     let choice = response.choices.first();
     if let Some(choice) = choice {
@@ -98,15 +188,18 @@ pub fn process_response(response: CompletionsResponse) -> ChatEvent {
             tool_calls.push(convert_tool_call(tool_call));
         }
 
-        if contents.is_empty() {
+        if contents.is_empty() && !tool_calls.is_empty() {
             ChatEvent::ToolRequest(tool_calls)
         } else {
             let metadata = ResponseMetadata {
                 finish_reason: choice.finish_reason.as_ref().map(convert_finish_reason),
                 usage: response.usage.as_ref().map(convert_usage),
-                provider_id: None,
+                provider_id: response.provider.clone(),
+                model: Some(response.model.clone()),
                 timestamp: Some(response.created.to_string()),
-                provider_metadata_json: None,
+                provider_metadata_json: response.usage.as_ref().and_then(generation_stats),
+                tokens_per_second: None,
+                stop_sequence: None,
             };
 
             ChatEvent::Message(CompleteResponse {
@@ -114,6 +207,7 @@ pub fn process_response(response: CompletionsResponse) -> ChatEvent {
                 content: contents,
                 tool_calls,
                 metadata,
+                annotations: vec![],
             })
         }
     } else {
@@ -190,20 +284,39 @@ fn convert_content_parts(contents: Vec<ContentPart>) -> crate::client::Content {
                     });
                 }
             },
+            // Tool results are sent in messages of their own by `messages_to_request`
+            ContentPart::ToolResult(_) => {}
+            // The reasoning of earlier responses is not sent back to the model
+            ContentPart::Reasoning(_) => {}
+            // Audio responses are sent back to the model as their transcript
+            ContentPart::Audio(audio) => result.extend(
+                audio
+                    .transcript
+                    .map(|text| crate::client::ContentPart::TextInput { text }),
+            ),
+            // Refusals of earlier responses are sent back to the model as text
+            ContentPart::Refusal(text) => {
+                result.push(crate::client::ContentPart::TextInput { text })
+            }
         }
     }
     crate::client::Content::List(result)
 }
 
-fn convert_content_parts_to_string(contents: Vec<ContentPart>) -> String {
-    let mut result = String::new();
+fn convert_content_parts_to_string(contents: Vec<ContentPart>, text_join: &TextJoin) -> String {
+    let mut texts = Vec::new();
     for content in contents {
         match content {
-            ContentPart::Text(text) => result.push_str(&text),
+            ContentPart::Text(text) => texts.push(text),
             ContentPart::Image(_) => {} // Correctly ignores any image content
+            ContentPart::ToolResult(_) => {}
+            ContentPart::Reasoning(_) => {}
+            // Audio responses are sent back to the model as their transcript
+            ContentPart::Audio(audio) => texts.extend(audio.transcript),
+            ContentPart::Refusal(text) => texts.push(text),
         }
     }
-    result
+    text_join.join(&texts)
 }
 
 impl From<ImageDetail> for Detail {
@@ -227,10 +340,35 @@ pub fn convert_finish_reason(value: &crate::client::FinishReason) -> FinishReaso
 }
 
 pub fn convert_usage(value: &crate::client::Usage) -> Usage {
-    Usage {
+    with_total_tokens(Usage {
         input_tokens: Some(value.prompt_tokens),
         output_tokens: Some(value.completion_tokens),
         total_tokens: Some(value.total_tokens),
+    })
+}
+
+/// Reports the generation stats of the usage, its `cost` and the native token counts, as
+/// structured fields of the provider metadata JSON. Returns `None` if none of them is reported.
+pub fn generation_stats(value: &crate::client::Usage) -> Option<String> {
+    let stats = [
+        ("cost", value.cost.map(serde_json::Value::from)),
+        (
+            "native_tokens_prompt",
+            value.native_tokens_prompt.map(serde_json::Value::from),
+        ),
+        (
+            "native_tokens_completion",
+            value.native_tokens_completion.map(serde_json::Value::from),
+        ),
+    ]
+    .into_iter()
+    .filter_map(|(key, value)| value.map(|value| (key.to_string(), value)))
+    .collect::<serde_json::Map<_, _>>();
+
+    if stats.is_empty() {
+        None
+    } else {
+        Some(serde_json::Value::Object(stats).to_string())
     }
 }
 
@@ -259,3 +397,139 @@ fn convert_tool_choice(tool_choice: String) -> crate::client::ToolChoice {
         }),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::client::CompletionsResponse;
+    use crate::conversions::{messages_to_request, process_response};
+    use golem_llm::golem::llm::llm::{ChatEvent, Config, ErrorCode, Kv};
+
+    fn config(provider_options: Vec<Kv>) -> Config {
+        Config {
+            model: "openai/gpt-4o".to_string(),
+            temperature: None,
+            max_tokens: None,
+            stop_sequences: None,
+            tools: vec![],
+            tool_choice: None,
+            provider_options,
+            extra_headers: vec![],
+            service_tier: None,
+            metadata: vec![],
+            reasoning_effort: None,
+            store: None,
+            prompt_cache_key: None,
+            stream_options: None,
+            deadline_ms: None,
+            extra_body: None,
+        }
+    }
+
+    fn routing(value: &str) -> Kv {
+        Kv {
+            key: "routing".to_string(),
+            value: value.to_string(),
+        }
+    }
+
+    #[test]
+    fn routing_is_forwarded_verbatim() {
+        let routing_json = r#"{"order":["Anthropic","OpenAI"],"allow_fallbacks":false,"ignore":["DeepInfra"],"sort":"throughput"}"#;
+        let request = messages_to_request(vec![], config(vec![routing(routing_json)])).unwrap();
+
+        let body = serde_json::to_value(&request).unwrap();
+        assert_eq!(
+            body["provider"],
+            serde_json::from_str::<serde_json::Value>(routing_json).unwrap()
+        );
+    }
+
+    #[test]
+    fn routing_is_omitted_without_the_option() {
+        let request = messages_to_request(vec![], config(vec![])).unwrap();
+
+        let body = serde_json::to_value(&request).unwrap();
+        assert!(body.get("provider").is_none());
+    }
+
+    #[test]
+    fn routing_must_be_an_object() {
+        let error =
+            messages_to_request(vec![], config(vec![routing(r#"["OpenAI"]"#)])).unwrap_err();
+        assert_eq!(error.code, ErrorCode::InvalidRequest);
+    }
+
+    #[test]
+    fn upstream_provider_is_the_provider_id() {
+        let response: CompletionsResponse = serde_json::from_str(
+            r#"{
+                "id": "gen-123",
+                "provider": "Anthropic",
+                "model": "anthropic/claude-3.5-sonnet",
+                "created": 1735689600,
+                "choices": [{
+                    "finish_reason": "stop",
+                    "native_finish_reason": "stop",
+                    "message": { "role": "assistant", "content": "Hello!" }
+                }]
+            }"#,
+        )
+        .unwrap();
+
+        let ChatEvent::Message(message) = process_response(response) else {
+            panic!("Expected a message");
+        };
+        assert_eq!(message.metadata.provider_id, Some("Anthropic".to_string()));
+    }
+
+    #[test]
+    fn generation_stats_are_reported_in_provider_metadata() {
+        let response: CompletionsResponse = serde_json::from_str(
+            r#"{
+                "id": "gen-1746100000-XfcLW7bFfNnyn2OiWyPu",
+                "provider": "OpenAI",
+                "model": "openai/gpt-4o",
+                "object": "chat.completion",
+                "created": 1746100000,
+                "choices": [{
+                    "logprobs": null,
+                    "finish_reason": "stop",
+                    "native_finish_reason": "stop",
+                    "index": 0,
+                    "message": {
+                        "role": "assistant",
+                        "content": "Hello! How can I help you today?",
+                        "refusal": null,
+                        "reasoning": null
+                    }
+                }],
+                "usage": {
+                    "prompt_tokens": 8,
+                    "completion_tokens": 10,
+                    "total_tokens": 18,
+                    "cost": 0.00012,
+                    "native_tokens_prompt": 9,
+                    "native_tokens_completion": 10,
+                    "is_byok": false,
+                    "prompt_tokens_details": {"cached_tokens": 0},
+                    "completion_tokens_details": {"reasoning_tokens": 0}
+                }
+            }"#,
+        )
+        .unwrap();
+
+        let ChatEvent::Message(message) = process_response(response) else {
+            panic!("Expected a message");
+        };
+        let provider_metadata: serde_json::Value =
+            serde_json::from_str(&message.metadata.provider_metadata_json.unwrap()).unwrap();
+        assert_eq!(
+            provider_metadata,
+            serde_json::json!({
+                "cost": 0.00012,
+                "native_tokens_prompt": 9,
+                "native_tokens_completion": 10
+            })
+        );
+    }
+}
@@ -3,21 +3,32 @@ mod conversions;
 
 use crate::client::{ChatCompletionChunk, CompletionsApi, CompletionsRequest, FunctionCall};
 use crate::conversions::{
-    convert_finish_reason, convert_usage, messages_to_request, process_response,
-    tool_results_to_messages,
+    check_streaming, convert_finish_reason, convert_usage, generation_stats, messages_to_request,
+    process_response, tool_results_to_messages,
 };
-use golem_llm::chat_stream::{LlmChatStream, LlmChatStreamState};
+use golem_llm::batch::send_batch;
+use golem_llm::chat_stream::{request_deadline, LlmChatStream, LlmChatStreamState};
 use golem_llm::config::with_config_key;
+use golem_llm::conversions::{
+    check_messages_not_empty, non_empty_tool_call_id, normalize_tool_call_ids,
+};
+use golem_llm::dry_run::{send_or_dry_run, stream_or_dry_run};
 use golem_llm::durability::{DurableLLM, ExtendedGuest};
-use golem_llm::error::error_code_from_status;
+use golem_llm::error::{error_code_from_status, unsupported};
 use golem_llm::event_source::EventSource;
+use golem_llm::extra_body::extra_body;
 use golem_llm::golem::llm::llm::{
-    ChatEvent, ChatStream, Config, ContentPart, Error, FinishReason, Guest, Message,
-    ResponseMetadata, Role, StreamDelta, StreamEvent, ToolCall, ToolResult,
+    ChatEvent, ChatStream, Config, ContentPart, EmbeddingConfig, EmbeddingResult, Error,
+    FinishReason, Guest, ImageGenConfig, ImageGenResult, Message, ProviderCapabilities,
+    ProviderHealth, RerankConfig, RerankResult, ResponseMetadata, Role, StreamDelta, StreamEvent,
+    ToolCall, ToolResult, Usage,
 };
+use golem_llm::headers::extra_headers;
+use golem_llm::rate_limit::with_rate_limit;
 use golem_llm::LOGGING_STATE;
 use golem_rust::wasm_rpc::Pollable;
 use log::trace;
+use reqwest::header::HeaderMap;
 use reqwest::StatusCode;
 use std::cell::{Ref, RefCell, RefMut};
 use std::collections::{HashMap, HashSet};
@@ -57,6 +68,19 @@ impl OpenRouterChatStream {
             json_fragments: RefCell::new(HashMap::new()),
         })
     }
+
+    pub fn dry_run(events: Vec<StreamEvent>) -> LlmChatStream<Self> {
+        LlmChatStream::from_events(
+            OpenRouterChatStream {
+                stream: RefCell::new(None),
+                failure: None,
+                finished: RefCell::new(false),
+                finish_reason: RefCell::new(None),
+                json_fragments: RefCell::new(HashMap::new()),
+            },
+            events,
+        )
+    }
 }
 
 impl LlmChatStreamState for OpenRouterChatStream {
@@ -80,7 +104,7 @@ impl LlmChatStreamState for OpenRouterChatStream {
         self.stream.borrow_mut()
     }
 
-    fn decode_message(&self, raw: &str) -> Result<Option<StreamEvent>, String> {
+    fn decode_message(&self, _event: &str, raw: &str) -> Result<Option<StreamEvent>, String> {
         trace!("Received raw stream event: {raw}");
         if raw.starts_with(": ") {
             Ok(None) // comment
@@ -101,9 +125,12 @@ impl LlmChatStreamState for OpenRouterChatStream {
                         Ok(Some(StreamEvent::Finish(ResponseMetadata {
                             finish_reason: *finish_reason,
                             usage: Some(convert_usage(&usage)),
-                            provider_id: None,
+                            provider_id: message.provider,
+                            model: Some(message.model),
                             timestamp: Some(message.created.to_string()),
-                            provider_metadata_json: None,
+                            provider_metadata_json: generation_stats(&usage),
+                            tokens_per_second: None,
+                            stop_sequence: None,
                         })))
                     } else if let Some(choice) = message.choices.into_iter().next() {
                         if let Some(finish_reason) = choice.finish_reason {
@@ -210,6 +237,9 @@ impl LlmChatStreamState for OpenRouterChatStream {
                                 } else {
                                     Some(tool_calls)
                                 },
+                                annotations: None,
+                                tool_call_fragments: None,
+                                running_usage: None,
                             })))
                         }
                     } else {
@@ -231,21 +261,28 @@ impl OpenRouterComponent {
     const ENV_VAR_NAME: &'static str = "OPENROUTER_API_KEY";
 
     fn request(client: CompletionsApi, request: CompletionsRequest) -> ChatEvent {
-        match client.send_messages(request) {
-            Ok(response) => process_response(response),
+        send_or_dry_run(request, |request| match client.send_messages(request) {
+            Ok(response) => with_rate_limit(process_response(response.body), response.rate_limit),
             Err(err) => ChatEvent::Error(err),
-        }
+        })
     }
 
     fn streaming_request(
         client: CompletionsApi,
         mut request: CompletionsRequest,
     ) -> LlmChatStream<OpenRouterChatStream> {
-        request.stream = Some(true);
-        match client.stream_send_messages(request) {
-            Ok(stream) => OpenRouterChatStream::new(stream),
-            Err(err) => OpenRouterChatStream::failed(err),
+        if let Err(err) = check_streaming(&request.model) {
+            return OpenRouterChatStream::failed(err);
         }
+        request.stream = Some(true);
+        stream_or_dry_run(
+            request,
+            OpenRouterChatStream::dry_run,
+            |request| match client.stream_send_messages(request) {
+                Ok(stream) => OpenRouterChatStream::new(stream),
+                Err(err) => OpenRouterChatStream::failed(err),
+            },
+        )
     }
 }
 
@@ -255,8 +292,26 @@ impl Guest for OpenRouterComponent {
     fn send(messages: Vec<Message>, config: Config) -> ChatEvent {
         LOGGING_STATE.with_borrow_mut(|state| state.init());
 
+        if let Err(err) = check_messages_not_empty(&messages, &[]) {
+            return ChatEvent::Error(err);
+        }
+
+        let extra_headers = match extra_headers(&config) {
+            Ok(extra_headers) => extra_headers,
+            Err(err) => return ChatEvent::Error(err),
+        };
+        let extra_body = match extra_body(&config) {
+            Ok(extra_body) => extra_body,
+            Err(err) => return ChatEvent::Error(err),
+        };
+
         with_config_key(Self::ENV_VAR_NAME, ChatEvent::Error, |openrouter_api_key| {
-            let client = CompletionsApi::new(openrouter_api_key);
+            let client = match CompletionsApi::new(openrouter_api_key, extra_headers)
+                .map(|client| client.with_extra_body(extra_body))
+            {
+                Ok(client) => client,
+                Err(err) => return ChatEvent::Error(err),
+            };
 
             match messages_to_request(messages, config) {
                 Ok(request) => Self::request(client, request),
@@ -272,8 +327,31 @@ impl Guest for OpenRouterComponent {
     ) -> ChatEvent {
         LOGGING_STATE.with_borrow_mut(|state| state.init());
 
+        if let Err(err) = check_messages_not_empty(&messages, &tool_results) {
+            return ChatEvent::Error(err);
+        }
+
+        let extra_headers = match extra_headers(&config) {
+            Ok(extra_headers) => extra_headers,
+            Err(err) => return ChatEvent::Error(err),
+        };
+        let extra_body = match extra_body(&config) {
+            Ok(extra_body) => extra_body,
+            Err(err) => return ChatEvent::Error(err),
+        };
+
+        let tool_results = match normalize_tool_call_ids(tool_results, non_empty_tool_call_id) {
+            Ok(tool_results) => tool_results,
+            Err(err) => return ChatEvent::Error(err),
+        };
+
         with_config_key(Self::ENV_VAR_NAME, ChatEvent::Error, |openrouter_api_key| {
-            let client = CompletionsApi::new(openrouter_api_key);
+            let client = match CompletionsApi::new(openrouter_api_key, extra_headers)
+                .map(|client| client.with_extra_body(extra_body))
+            {
+                Ok(client) => client,
+                Err(err) => return ChatEvent::Error(err),
+            };
 
             match messages_to_request(messages, config) {
                 Ok(mut request) => {
@@ -287,26 +365,130 @@ impl Guest for OpenRouterComponent {
         })
     }
 
+    fn send_batch(requests: Vec<(Vec<Message>, Config)>, abort_on_error: bool) -> Vec<ChatEvent> {
+        send_batch(requests, abort_on_error, Self::send)
+    }
+
     fn stream(messages: Vec<Message>, config: Config) -> ChatStream {
         ChatStream::new(Self::unwrapped_stream(messages, config))
     }
+
+    fn continue_stream(
+        messages: Vec<Message>,
+        tool_results: Vec<(ToolCall, ToolResult)>,
+        config: Config,
+    ) -> ChatStream {
+        ChatStream::new(Self::unwrapped_continue_stream(
+            messages,
+            tool_results,
+            config,
+        ))
+    }
+
+    fn send_raw(body_json: String, config: Config) -> Result<String, Error> {
+        LOGGING_STATE.with_borrow_mut(|state| state.init());
+
+        let extra_headers = extra_headers(&config)?;
+        with_config_key(Self::ENV_VAR_NAME, Err, |openrouter_api_key| {
+            CompletionsApi::new(openrouter_api_key, extra_headers)?.send_raw(body_json)
+        })
+    }
+
+    fn rerank(
+        _query: String,
+        _documents: Vec<String>,
+        _config: RerankConfig,
+    ) -> Result<RerankResult, Error> {
+        Err(unsupported("rerank"))
+    }
+
+    fn embed(
+        _inputs: Vec<ContentPart>,
+        _config: EmbeddingConfig,
+    ) -> Result<EmbeddingResult, Error> {
+        Err(unsupported("embed"))
+    }
+
+    fn ping() -> Result<ProviderHealth, Error> {
+        LOGGING_STATE.with_borrow_mut(|state| state.init());
+
+        with_config_key(Self::ENV_VAR_NAME, Err, |openrouter_api_key| {
+            CompletionsApi::new(openrouter_api_key, HeaderMap::new())?.ping()
+        })
+    }
+
+    fn capabilities() -> ProviderCapabilities {
+        ProviderCapabilities {
+            provider: "openrouter".to_string(),
+            streaming: true,
+            tools: true,
+            vision: true,
+            embeddings: false,
+            json_schema: false,
+        }
+    }
+
+    fn warm(_config: Config) -> Result<(), Error> {
+        Err(unsupported("warm"))
+    }
+
+    fn generate_image(_prompt: String, _config: ImageGenConfig) -> Result<ImageGenResult, Error> {
+        Err(unsupported("generate-image"))
+    }
 }
 
 impl ExtendedGuest for OpenRouterComponent {
     fn unwrapped_stream(
         messages: Vec<Message>,
         config: Config,
+    ) -> LlmChatStream<OpenRouterChatStream> {
+        Self::unwrapped_continue_stream(messages, vec![], config)
+    }
+
+    fn unwrapped_continue_stream(
+        messages: Vec<Message>,
+        tool_results: Vec<(ToolCall, ToolResult)>,
+        config: Config,
     ) -> LlmChatStream<OpenRouterChatStream> {
         LOGGING_STATE.with_borrow_mut(|state| state.init());
 
+        if let Err(err) = check_messages_not_empty(&messages, &tool_results) {
+            return OpenRouterChatStream::failed(err);
+        }
+
+        let extra_headers = match extra_headers(&config) {
+            Ok(extra_headers) => extra_headers,
+            Err(err) => return OpenRouterChatStream::failed(err),
+        };
+        let extra_body = match extra_body(&config) {
+            Ok(extra_body) => extra_body,
+            Err(err) => return OpenRouterChatStream::failed(err),
+        };
+        let deadline = request_deadline(&config);
+
+        let tool_results = match normalize_tool_call_ids(tool_results, non_empty_tool_call_id) {
+            Ok(tool_results) => tool_results,
+            Err(err) => return OpenRouterChatStream::failed(err),
+        };
+
         with_config_key(
             Self::ENV_VAR_NAME,
             OpenRouterChatStream::failed,
             |openrouter_api_key| {
-                let client = CompletionsApi::new(openrouter_api_key);
+                let client = match CompletionsApi::new(openrouter_api_key, extra_headers)
+                    .map(|client| client.with_extra_body(extra_body))
+                {
+                    Ok(client) => client,
+                    Err(err) => return OpenRouterChatStream::failed(err),
+                };
 
                 match messages_to_request(messages, config) {
-                    Ok(request) => Self::streaming_request(client, request),
+                    Ok(mut request) => {
+                        request
+                            .messages
+                            .extend(tool_results_to_messages(tool_results));
+                        Self::streaming_request(client, request).with_deadline(deadline)
+                    }
                     Err(err) => OpenRouterChatStream::failed(err),
                 }
             },
@@ -365,8 +547,37 @@ impl ExtendedGuest for OpenRouterComponent {
     fn subscribe(stream: &Self::ChatStream) -> Pollable {
         stream.subscribe()
     }
+
+    fn subscribe_deadline(stream: &Self::ChatStream) -> Option<Pollable> {
+        stream.subscribe_deadline()
+    }
+
+    fn usage_so_far(stream: &Self::ChatStream) -> Option<Usage> {
+        stream.usage_so_far()
+    }
 }
 
 type DurableOpenRouterComponent = DurableLLM<OpenRouterComponent>;
 
 golem_llm::export_llm!(DurableOpenRouterComponent with_types_in golem_llm);
+
+#[cfg(test)]
+mod tests {
+    use crate::OpenRouterComponent;
+    use golem_llm::golem::llm::llm::{Guest, ProviderCapabilities};
+
+    #[test]
+    fn capabilities_describe_the_provider() {
+        assert_eq!(
+            OpenRouterComponent::capabilities(),
+            ProviderCapabilities {
+                provider: "openrouter".to_string(),
+                streaming: true,
+                tools: true,
+                vision: true,
+                embeddings: false,
+                json_schema: false,
+            }
+        );
+    }
+}
@@ -13,8 +13,16 @@ struct Component;
 const MODEL: &'static str = "gpt-3.5-turbo";
 #[cfg(feature = "anthropic")]
 const MODEL: &'static str = "claude-3-7-sonnet-20250219";
+#[cfg(feature = "azure-openai")]
+const MODEL: &'static str = "gpt-4o-mini";
+#[cfg(feature = "cohere")]
+const MODEL: &'static str = "command-a-03-2025";
+#[cfg(feature = "deepseek")]
+const MODEL: &'static str = "deepseek-chat";
 #[cfg(feature = "grok")]
 const MODEL: &'static str = "grok-3-beta";
+#[cfg(feature = "mistral")]
+const MODEL: &'static str = "mistral-large-latest";
 #[cfg(feature = "openrouter")]
 const MODEL: &'static str = "openrouter/auto";
 #[cfg(feature = "ollama")]  
@@ -24,24 +32,47 @@ const MODEL: &'static str = "qwen3:1.7b";
 const IMAGE_MODEL: &'static str = "gpt-4o-mini";
 #[cfg(feature = "anthropic")]
 const IMAGE_MODEL: &'static str = "claude-3-7-sonnet-20250219";
+#[cfg(feature = "azure-openai")]
+const IMAGE_MODEL: &'static str = "gpt-4o-mini";
+#[cfg(feature = "cohere")]
+const IMAGE_MODEL: &'static str = "command-a-vision-07-2025";
+#[cfg(feature = "deepseek")]
+const IMAGE_MODEL: &'static str = "deepseek-chat";
 #[cfg(feature = "grok")]
 const IMAGE_MODEL: &'static str = "grok-2-vision-latest";
+#[cfg(feature = "mistral")]
+const IMAGE_MODEL: &'static str = "pixtral-large-latest";
 #[cfg(feature = "openrouter")]
 const IMAGE_MODEL: &'static str = "openrouter/auto";
 #[cfg(feature = "ollama")]  
 const IMAGE_MODEL: &'static str = "gemma3:4b";
 
+// Anthropic requires max_tokens to be set explicitly
+#[cfg(feature = "anthropic")]
+const MAX_TOKENS: Option<u32> = Some(4096);
+#[cfg(not(feature = "anthropic"))]
+const MAX_TOKENS: Option<u32> = None;
+
 impl Guest for Component {
     /// test1 demonstrates a simple, non-streaming text question-answer interaction with the LLM.
     fn test1() -> String {
         let config = llm::Config {
             model: MODEL.to_string(),
             temperature: Some(0.2),
-            max_tokens: None,
+            max_tokens: MAX_TOKENS,
             stop_sequences: None,
             tools: vec![],
             tool_choice: None,
             provider_options: vec![],
+            extra_headers: vec![],
+            service_tier: None,
+            metadata: vec![],
+            reasoning_effort: None,
+            store: None,
+            prompt_cache_key: None,
+            stream_options: None,
+            deadline_ms: None,
+            extra_body: None,
         };
 
         println!("Sending request to LLM...");
@@ -61,7 +92,7 @@ impl Guest for Component {
         match response {
             llm::ChatEvent::Message(msg) => {
                 format!(
-                    "{}",
+                    "{}\nMODEL: {}",
                     msg.content
                         .into_iter()
                         .map(|content| match content {
@@ -70,9 +101,14 @@ impl Guest for Component {
                                 llm::ImageReference::Url(url_data) => format!("[IMAGE URL: {}]", url_data.url),
                                 llm::ImageReference::Inline(inline_data) => format!("[INLINE IMAGE: {} bytes, mime: {}]", inline_data.data.len(), inline_data.mime_type),
                             }
+                            llm::ContentPart::ToolResult((tool_call, _)) => format!("[TOOL RESULT: {}]", tool_call.id),
+                            llm::ContentPart::Reasoning(reasoning) => format!("[REASONING: {}]", reasoning),
+                            llm::ContentPart::Audio(audio) => format!("[AUDIO: {} bytes of {}]", audio.data.len(), audio.mime_type),
+                            llm::ContentPart::Refusal(refusal) => format!("[REFUSAL: {}]", refusal),
                         })
                         .collect::<Vec<_>>()
-                        .join(", ")
+                        .join(", "),
+                    msg.metadata.model.unwrap_or_default()
                 )
             }
             llm::ChatEvent::ToolRequest(request) => {
@@ -95,7 +131,7 @@ impl Guest for Component {
         let config = llm::Config {
             model: MODEL.to_string(),
             temperature: Some(0.2),
-            max_tokens: None,
+            max_tokens: MAX_TOKENS,
             stop_sequences: None,
             tools: vec![llm::ToolDefinition {
                 name: "test-tool".to_string(),
@@ -117,6 +153,15 @@ impl Guest for Component {
             }],
             tool_choice: Some("auto".to_string()),
             provider_options: vec![],
+            extra_headers: vec![],
+            service_tier: None,
+            metadata: vec![],
+            reasoning_effort: None,
+            store: None,
+            prompt_cache_key: None,
+            stream_options: None,
+            deadline_ms: None,
+            extra_body: None,
         };
 
         let input = vec![
@@ -165,6 +210,7 @@ impl Guest for Component {
                         name: call.name,
                         result_json: r#"{ "value": 6 }"#.to_string(),
                         execution_time_ms: None,
+                        content: vec![],
                     }),
                 ));
             }
@@ -205,11 +251,20 @@ impl Guest for Component {
         let config = llm::Config {
             model: MODEL.to_string(),
             temperature: Some(0.2),
-            max_tokens: None,
+            max_tokens: MAX_TOKENS,
             stop_sequences: None,
             tools: vec![],
             tool_choice: None,
             provider_options: vec![],
+            extra_headers: vec![],
+            service_tier: None,
+            metadata: vec![],
+            reasoning_effort: None,
+            store: None,
+            prompt_cache_key: None,
+            stream_options: None,
+            deadline_ms: None,
+            extra_body: None,
         };
 
         println!("Starting streaming request to LLM...");
@@ -226,44 +281,18 @@ impl Guest for Component {
         );
 
         let mut result = String::new();
-
-        loop {
-            let events = stream.blocking_get_next();
-            if events.is_empty() {
-                break;
-            }
-
-            for event in events {
-                println!("Received {event:?}");
-
-                match event {
-                    StreamEvent::Delta(delta) => {
-                        result.push_str(&format!("DELTA: {:?}\n", delta,));
-                    }
-                    StreamEvent::Finish(finish) => {
-                        result.push_str(&format!("FINISH: {:?}\n", finish,));
-                    }
-                    StreamEvent::Error(error) => {
-                        result.push_str(&format!(
-                            "ERROR: {:?} {} ({})\n",
-                            error.code,
-                            error.message,
-                            error.provider_error_json.unwrap_or_default()
-                        ));
-                    }
-                }
-            }
-        }
+        drain_stream(&stream, &mut result);
 
         result
     }
 
-    /// test4 shows how streaming works together with using tools
+    /// test4 shows how streaming works together with using tools, continuing the stream with
+    /// the results of the requested tool calls
     fn test4() -> String {
         let config = llm::Config {
             model: MODEL.to_string(),
             temperature: Some(0.2),
-            max_tokens: None,
+            max_tokens: MAX_TOKENS,
             stop_sequences: None,
             tools: vec![llm::ToolDefinition {
                 name: "test-tool".to_string(),
@@ -285,6 +314,15 @@ impl Guest for Component {
             }],
             tool_choice: Some("auto".to_string()),
             provider_options: vec![],
+            extra_headers: vec![],
+            service_tier: None,
+            metadata: vec![],
+            reasoning_effort: None,
+            store: None,
+            prompt_cache_key: None,
+            stream_options: None,
+            deadline_ms: None,
+            extra_body: None,
         };
 
         let input = vec![
@@ -295,43 +333,36 @@ impl Guest for Component {
         ];
 
         println!("Starting streaming request to LLM...");
-        let stream = llm::stream(
-            &[llm::Message {
-                role: llm::Role::User,
-                name: Some("vigoo".to_string()),
-                content: input,
-            }],
-            &config,
-        );
+        let messages = vec![llm::Message {
+            role: llm::Role::User,
+            name: Some("vigoo".to_string()),
+            content: input,
+        }];
+        let stream = llm::stream(&messages, &config);
 
         let mut result = String::new();
+        let tool_calls = drain_stream(&stream, &mut result);
+
+        if !tool_calls.is_empty() {
+            let tool_results = tool_calls
+                .into_iter()
+                .map(|call| {
+                    (
+                        call.clone(),
+                        llm::ToolResult::Success(llm::ToolSuccess {
+                            id: call.id,
+                            name: call.name,
+                            result_json: r#"{ "value": 6 }"#.to_string(),
+                            execution_time_ms: None,
+                            content: vec![],
+                        }),
+                    )
+                })
+                .collect::<Vec<_>>();
 
-        loop {
-            let events = stream.blocking_get_next();
-            if events.is_empty() {
-                break;
-            }
-
-            for event in events {
-                println!("Received {event:?}");
-
-                match event {
-                    StreamEvent::Delta(delta) => {
-                        result.push_str(&format!("DELTA: {:?}\n", delta,));
-                    }
-                    StreamEvent::Finish(finish) => {
-                        result.push_str(&format!("FINISH: {:?}\n", finish,));
-                    }
-                    StreamEvent::Error(error) => {
-                        result.push_str(&format!(
-                            "ERROR: {:?} {} ({})\n",
-                            error.code,
-                            error.message,
-                            error.provider_error_json.unwrap_or_default()
-                        ));
-                    }
-                }
-            }
+            println!("Continuing the streaming request with the tool results...");
+            let stream = llm::continue_stream(&messages, &tool_results, &config);
+            drain_stream(&stream, &mut result);
         }
 
         result
@@ -342,11 +373,20 @@ impl Guest for Component {
         let config = llm::Config {
             model: IMAGE_MODEL.to_string(),
             temperature: None,
-            max_tokens: None,
+            max_tokens: MAX_TOKENS,
             stop_sequences: None,
             tools: vec![],
             tool_choice: None,
             provider_options: vec![],
+            extra_headers: vec![],
+            service_tier: None,
+            metadata: vec![],
+            reasoning_effort: None,
+            store: None,
+            prompt_cache_key: None,
+            stream_options: None,
+            deadline_ms: None,
+            extra_body: None,
         };
 
         println!("Sending request to LLM...");
@@ -378,8 +418,21 @@ impl Guest for Component {
 
         match response {
             llm::ChatEvent::Message(msg) => {
+                let citations = msg
+                    .annotations
+                    .iter()
+                    .map(|annotation| {
+                        format!(
+                            "\n[CITATION {}..{}: {} {}]",
+                            annotation.start_index,
+                            annotation.end_index,
+                            annotation.title.as_deref().unwrap_or_default(),
+                            annotation.url.as_deref().unwrap_or_default()
+                        )
+                    })
+                    .collect::<String>();
                 format!(
-                    "{}",
+                    "{}{}",
                     msg.content
                         .into_iter()
                         .map(|content| match content {
@@ -388,9 +441,14 @@ impl Guest for Component {
                                 llm::ImageReference::Url(url_data) => format!("[IMAGE URL: {}]", url_data.url),
                                 llm::ImageReference::Inline(inline_data) => format!("[INLINE IMAGE: {} bytes, mime: {}]", inline_data.data.len(), inline_data.mime_type),
                             }
+                            llm::ContentPart::ToolResult((tool_call, _)) => format!("[TOOL RESULT: {}]", tool_call.id),
+                            llm::ContentPart::Reasoning(reasoning) => format!("[REASONING: {}]", reasoning),
+                            llm::ContentPart::Audio(audio) => format!("[AUDIO: {} bytes of {}]", audio.data.len(), audio.mime_type),
+                            llm::ContentPart::Refusal(refusal) => format!("[REFUSAL: {}]", refusal),
                         })
                         .collect::<Vec<_>>()
-                        .join(", ")
+                        .join(", "),
+                    citations
                 )
             }
             llm::ChatEvent::ToolRequest(request) => {
@@ -413,11 +471,20 @@ impl Guest for Component {
         let config = llm::Config {
             model: MODEL.to_string(),
             temperature: Some(0.2),
-            max_tokens: None,
+            max_tokens: MAX_TOKENS,
             stop_sequences: None,
             tools: vec![],
             tool_choice: None,
             provider_options: vec![],
+            extra_headers: vec![],
+            service_tier: None,
+            metadata: vec![],
+            reasoning_effort: None,
+            store: None,
+            prompt_cache_key: None,
+            stream_options: None,
+            deadline_ms: None,
+            extra_body: None,
         };
 
         println!("Starting streaming request to LLM...");
@@ -448,6 +515,9 @@ impl Guest for Component {
                 println!("Received {event:?}");
 
                 match event {
+                    StreamEvent::Start(start) => {
+                        result.push_str(&format!("START: {:?}\n", start,));
+                    }
                     StreamEvent::Delta(delta) => {
                         for content in delta.content.unwrap_or_default() {
                             match content {
@@ -462,6 +532,18 @@ impl Guest for Component {
                                         result.push_str(&format!("INLINE IMAGE: {} bytes, mime: {}, detail: {:?}\n", inline_data.data.len(), inline_data.mime_type, inline_data.detail));
                                     }
                                 }
+                                llm::ContentPart::ToolResult((tool_call, _)) => {
+                                    result.push_str(&format!("TOOL RESULT: {}\n", tool_call.id));
+                                }
+                                llm::ContentPart::Reasoning(reasoning) => {
+                                    result.push_str(&format!("REASONING: {}\n", reasoning));
+                                }
+                                llm::ContentPart::Audio(audio) => {
+                                    result.push_str(&format!("AUDIO: {} bytes of {}\n", audio.data.len(), audio.mime_type));
+                                }
+                                llm::ContentPart::Refusal(refusal) => {
+                                    result.push_str(&format!("REFUSAL: {}\n", refusal));
+                                }
                             }
                         }
                     }
@@ -503,11 +585,20 @@ impl Guest for Component {
         let config = llm::Config {
             model: IMAGE_MODEL.to_string(),
             temperature: None,
-            max_tokens: None,
+            max_tokens: MAX_TOKENS,
             stop_sequences: None,
             tools: vec![],
             tool_choice: None,
             provider_options: vec![],
+            extra_headers: vec![],
+            service_tier: None,
+            metadata: vec![],
+            reasoning_effort: None,
+            store: None,
+            prompt_cache_key: None,
+            stream_options: None,
+            deadline_ms: None,
+            extra_body: None,
         };
 
         println!("Reading image from Initial File System...");
@@ -552,6 +643,10 @@ impl Guest for Component {
                                 llm::ImageReference::Url(url_data) => format!("[IMAGE URL: {}]", url_data.url),
                                 llm::ImageReference::Inline(inline_data) => format!("[INLINE IMAGE: {} bytes, mime: {}]", inline_data.data.len(), inline_data.mime_type),
                             }
+                            llm::ContentPart::ToolResult((tool_call, _)) => format!("[TOOL RESULT: {}]", tool_call.id),
+                            llm::ContentPart::Reasoning(reasoning) => format!("[REASONING: {}]", reasoning),
+                            llm::ContentPart::Audio(audio) => format!("[AUDIO: {} bytes of {}]", audio.data.len(), audio.mime_type),
+                            llm::ContentPart::Refusal(refusal) => format!("[REFUSAL: {}]", refusal),
                         })
                         .collect::<Vec<_>>()
                         .join(", ")
@@ -572,4 +667,45 @@ impl Guest for Component {
     }
 }
 
+/// Reads all the events of the stream, appending them to `result`, and returns the requested
+/// tool calls
+fn drain_stream(stream: &llm::ChatStream, result: &mut String) -> Vec<llm::ToolCall> {
+    let mut tool_calls = Vec::new();
+
+    loop {
+        let events = stream.blocking_get_next();
+        if events.is_empty() {
+            break;
+        }
+
+        for event in events {
+            println!("Received {event:?}");
+
+            match event {
+                StreamEvent::Start(start) => {
+                    result.push_str(&format!("START: {:?}\n", start,));
+                }
+                StreamEvent::Delta(delta) => {
+                    result.push_str(&format!("DELTA: {:?}\n", delta,));
+                    tool_calls.extend(delta.tool_calls.unwrap_or_default());
+                }
+                StreamEvent::Finish(finish) => {
+                    result.push_str(&format!("FINISH: {:?}\n", finish,));
+                }
+                StreamEvent::Error(error) => {
+                    result.push_str(&format!(
+                        "ERROR: {:?} {} ({})\n",
+                        error.code,
+                        error.message,
+                        error.provider_error_json.unwrap_or_default()
+                    ));
+                }
+            }
+        }
+    }
+
+    tool_calls
+}
+
 bindings::export!(Component with_types_in bindings);
+